@@ -0,0 +1,12 @@
+// Textured quad tinted by a color, drawn with additive blending by `GlowMaterial`
+
+#import bevy_sprite::mesh2d_vertex_output::VertexOutput
+
+@group(1) @binding(0) var<uniform> color: vec4<f32>;
+@group(1) @binding(1) var glow_texture: texture_2d<f32>;
+@group(1) @binding(2) var glow_sampler: sampler;
+
+@fragment
+fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(glow_texture, glow_sampler, mesh.uv) * color;
+}
@@ -0,0 +1,536 @@
+//! The player's settings: how the movement keys repeat, whether the screen moves on its own, the
+//! overlay colors, the language, the interface scale, the mouse buttons, whether the overlays
+//! hide when idle and how the game runs in the background.
+//!
+//! The motion settings live in the `MotionPreferences` resource, read by the systems they affect.
+//! They are written to `PREFERENCES_FILE` with the language, the `UiScaling`, the
+//! `MouseInputMap`, the `AutoHideSettings`, the `BackgroundThrottle` and the
+//! `CameraPresetSettings` whenever one of them changes. The settings window opened with
+//! `SETTINGS_KEY` or `SETTINGS_BUTTON` edits them, but for the camera preset picked in the
+//! inspector, and picks the `OverlayPalette` and the `Difficulty`, which the saves keep instead.
+
+use std::path::Path;
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::EguiContext;
+use bevy_window::PrimaryWindow;
+use serde::{Deserialize, Serialize};
+
+use crate::auto_hide::AutoHideSettings;
+use crate::background::{BackgroundAudio, BackgroundThrottle};
+use crate::camera::{MainCamera, PanCam};
+use crate::camera_preset::CameraPresetSettings;
+use crate::game::GameSet;
+use crate::input_map::{button_key, MouseAction, MouseInputMap};
+use crate::localization::{tr, Localization, FALLBACK_LANGUAGE, LANG_DIR};
+use crate::menu_focus::{cycle, MenuFocus};
+use crate::palette::{OverlayPalette, PaletteKind};
+use crate::spawn_conditions::Difficulty;
+use crate::ui_scale::{UiScaling, MIN_UI_SCALE};
+
+/// Key that opens and closes the settings window
+pub const SETTINGS_KEY: KeyCode = KeyCode::Escape;
+/// Gamepad button that opens the settings window
+pub const SETTINGS_BUTTON: GamepadButtonType = GamepadButtonType::Start;
+/// File of the preferences, relative to the working directory
+pub const PREFERENCES_FILE: &str = "preferences.ron";
+/// Buttons the mouse actions can be bound to
+const MOUSE_BUTTONS: [MouseButton; 3] =
+    [MouseButton::Left, MouseButton::Right, MouseButton::Middle];
+/// Shortest time between two repeated steps of a held movement key, in seconds
+pub const MIN_MOVE_REPEAT_INTERVAL: f32 = 0.08;
+
+/// Plugin that loads, edits and saves the `MotionPreferences`
+#[derive(Default)]
+pub struct AccessibilityPlugin;
+
+impl Plugin for AccessibilityPlugin {
+    fn build(&self, app: &mut App) {
+        let preferences = Preferences::load_saved();
+        app.insert_resource(preferences.motion.clone())
+            .insert_resource(preferences)
+            .register_type::<MotionPreferences>()
+            .init_resource::<SettingsMenu>()
+            .add_systems(Update, toggle_settings_menu.in_set(GameSet::Input))
+            .add_systems(Update, show_settings_menu.in_set(GameSet::RenderPrep))
+            .add_systems(Last, persist_preferences);
+    }
+}
+
+/// What holding a movement key does
+#[derive(Reflect, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MoveInputMode {
+    /// One step per key press
+    #[default]
+    Tap,
+    /// Keep stepping while the key is held, every `MotionPreferences::repeat_interval`
+    Hold,
+}
+
+#[derive(Resource, Reflect, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[reflect(Resource)]
+#[serde(default)]
+pub struct MotionPreferences {
+    pub move_input: MoveInputMode,
+    /// Seconds between the steps of a held movement key, at least `MIN_MOVE_REPEAT_INTERVAL`
+    pub repeat_interval: f32,
+    /// Snap instead of animating: no camera drift and no marker pulse
+    pub reduce_motion: bool,
+}
+
+impl Default for MotionPreferences {
+    fn default() -> Self {
+        Self {
+            move_input: MoveInputMode::Tap,
+            repeat_interval: 0.2,
+            reduce_motion: false,
+        }
+    }
+}
+
+impl MotionPreferences {
+    /// The interval held movement keys repeat at, raised to the lower bound
+    pub fn move_repeat_interval(&self) -> f32 {
+        self.repeat_interval.max(MIN_MOVE_REPEAT_INTERVAL)
+    }
+}
+
+/// What `PREFERENCES_FILE` holds
+///
+/// As a resource, the preferences read at startup by the `AccessibilityPlugin`, which the other
+/// plugins take their settings from. The settings then live in their own resources.
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Preferences {
+    pub motion: MotionPreferences,
+    /// Code of the language, the name of its table in `LANG_DIR`
+    pub language: String,
+    /// Scale of the interface, guessed from the window when `None`
+    pub ui_scale: Option<f32>,
+    pub mouse: MouseInputMap,
+    pub auto_hide: AutoHideSettings,
+    pub background: BackgroundThrottle,
+    pub camera_preset: CameraPresetSettings,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            motion: MotionPreferences::default(),
+            language: FALLBACK_LANGUAGE.to_string(),
+            ui_scale: None,
+            mouse: MouseInputMap::default(),
+            auto_hide: AutoHideSettings::default(),
+            background: BackgroundThrottle::default(),
+            camera_preset: CameraPresetSettings::default(),
+        }
+    }
+}
+
+impl Preferences {
+    /// The preferences saved at `path`, the defaults when it can't be read
+    pub fn load(path: &Path) -> Self {
+        match std::fs::read_to_string(path) {
+            Ok(text) => ron::from_str(&text).unwrap_or_else(|err| {
+                warn!("can't read the preferences in {path:?}, using the defaults: {err}");
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// The preferences saved at `PREFERENCES_FILE`
+    pub fn load_saved() -> Self {
+        Self::load(Path::new(PREFERENCES_FILE))
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        std::fs::write(path, text)
+    }
+}
+
+/// Repeats an action while its key is held, once per interval
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct KeyRepeat {
+    /// Seconds until the next repeat
+    remaining: f32,
+}
+
+impl KeyRepeat {
+    /// A key was just pressed: the first repeat comes a whole `interval` later
+    pub fn restart(&mut self, interval: f32) {
+        self.remaining = interval;
+    }
+
+    /// Advance by `delta` seconds, whether the action repeats this frame. The repeat waits while
+    /// the action is `blocked` and fires as soon as it no longer is.
+    pub fn tick(&mut self, delta: f32, interval: f32, blocked: bool) -> bool {
+        self.remaining -= delta;
+        if self.remaining > 0. || blocked {
+            return false;
+        }
+        self.remaining = interval;
+        true
+    }
+}
+
+/// Whether the motion preferences ask for no animations, as a run condition
+pub fn motion_allowed(preferences: Res<MotionPreferences>) -> bool {
+    !preferences.reduce_motion
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct SettingsMenu {
+    pub open: bool,
+    /// Codes and names of the languages, listed when the window opens
+    languages: Vec<(String, String)>,
+    /// Scale on the slider, applied once it's let go so the slider doesn't move under the pointer
+    ui_scale_draft: f32,
+}
+
+fn toggle_settings_menu(
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    scaling: Res<UiScaling>,
+    focus: Res<MenuFocus>,
+    mut menu: ResMut<SettingsMenu>,
+) {
+    // an open menu takes Escape to back out, the settings window included
+    if menu.open || focus.is_open() {
+        return;
+    }
+    let start = gamepads
+        .iter()
+        .any(|gamepad| buttons.just_pressed(GamepadButton::new(gamepad, SETTINGS_BUTTON)));
+    if keys.just_pressed(SETTINGS_KEY) || start {
+        menu.open = true;
+        menu.languages = Localization::languages(Path::new(LANG_DIR));
+        menu.ui_scale_draft = scaling.scale();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn show_settings_menu(
+    mut menu: ResMut<SettingsMenu>,
+    mut preferences: ResMut<MotionPreferences>,
+    mut palette: ResMut<OverlayPalette>,
+    mut localization: ResMut<Localization>,
+    mut scaling: ResMut<UiScaling>,
+    mut mouse_map: ResMut<MouseInputMap>,
+    mut auto_hide: ResMut<AutoHideSettings>,
+    mut background: ResMut<BackgroundThrottle>,
+    mut difficulty: ResMut<Difficulty>,
+    cameras: Query<&PanCam, With<MainCamera>>,
+    mut focus: ResMut<MenuFocus>,
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+) {
+    if !menu.open {
+        return;
+    }
+    let Some(mut ctx) = contexts.iter_mut().next() else {
+        return;
+    };
+
+    if focus.begin("settings") {
+        menu.open = false;
+    }
+    let mut edited = preferences.clone();
+    let mut palette_kind = palette.kind;
+    let mut language = localization.language().to_string();
+    let mut custom_scale = scaling.custom;
+    let mut edited_mouse = *mouse_map;
+    let mut edited_auto_hide = *auto_hide;
+    let mut edited_background = *background;
+    let mut edited_difficulty = *difficulty;
+    let drag_threshold = cameras.get_single().map_or(0., |cam| cam.drag_threshold);
+    let menu = &mut *menu;
+    egui::Window::new(tr!("settings.title"))
+        .id(egui::Id::new("settings"))
+        .open(&mut menu.open)
+        .resizable(false)
+        .show(ctx.get_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label(tr!("settings.movement_keys"));
+                let tap = ui.radio_value(
+                    &mut edited.move_input,
+                    MoveInputMode::Tap,
+                    tr!("settings.tap"),
+                );
+                if focus.item(ui, "tap", &tap) {
+                    edited.move_input = MoveInputMode::Tap;
+                }
+                let hold = ui.radio_value(
+                    &mut edited.move_input,
+                    MoveInputMode::Hold,
+                    tr!("settings.hold"),
+                );
+                if focus.item(ui, "hold", &hold) {
+                    edited.move_input = MoveInputMode::Hold;
+                }
+            });
+            let repeat = ui.add_enabled(
+                edited.move_input == MoveInputMode::Hold,
+                egui::Slider::new(&mut edited.repeat_interval, MIN_MOVE_REPEAT_INTERVAL..=0.6)
+                    .text(tr!("settings.repeat_every"))
+                    .suffix(" s"),
+            );
+            focus.item(ui, "repeat_interval", &repeat);
+            let step = focus.adjust("repeat_interval");
+            if step != 0 {
+                edited.repeat_interval = (edited.repeat_interval + step as f32 * 0.02)
+                    .clamp(MIN_MOVE_REPEAT_INTERVAL, 0.6);
+            }
+            let reduce_motion =
+                ui.checkbox(&mut edited.reduce_motion, tr!("settings.reduce_motion"));
+            if focus.item(ui, "reduce_motion", &reduce_motion) {
+                edited.reduce_motion = !edited.reduce_motion;
+            }
+            let hide_overlays =
+                ui.checkbox(&mut edited_auto_hide.enabled, tr!("settings.auto_hide"));
+            if focus.item(ui, "auto_hide", &hide_overlays) {
+                edited_auto_hide.enabled = !edited_auto_hide.enabled;
+            }
+            let throttle = ui.checkbox(
+                &mut edited_background.enabled,
+                tr!("settings.background_throttle"),
+            );
+            if focus.item(ui, "background_throttle", &throttle) {
+                edited_background.enabled = !edited_background.enabled;
+            }
+            let background_audio = egui::ComboBox::from_label(tr!("settings.background_audio"))
+                .selected_text(tr!(edited_background.audio.key()))
+                .show_ui(ui, |ui| {
+                    for audio in BackgroundAudio::ALL {
+                        ui.selectable_value(&mut edited_background.audio, audio, tr!(audio.key()));
+                    }
+                });
+            focus.item(ui, "background_audio", &background_audio.response);
+            edited_background.audio = cycle(
+                &BackgroundAudio::ALL,
+                &edited_background.audio,
+                focus.adjust("background_audio"),
+            );
+            let palettes = egui::ComboBox::from_label(tr!("settings.overlay_colors"))
+                .selected_text(tr!(palette_kind.key()))
+                .show_ui(ui, |ui| {
+                    for kind in PaletteKind::ALL {
+                        ui.selectable_value(&mut palette_kind, kind, tr!(kind.key()));
+                    }
+                });
+            focus.item(ui, "palette", &palettes.response);
+            palette_kind = cycle(&PaletteKind::ALL, &palette_kind, focus.adjust("palette"));
+            let language_name = menu
+                .languages
+                .iter()
+                .find(|(code, _)| *code == language)
+                .map_or(language.clone(), |(_, name)| name.clone());
+            let languages = egui::ComboBox::from_label(tr!("settings.language"))
+                .selected_text(language_name)
+                .show_ui(ui, |ui| {
+                    for (code, name) in &menu.languages {
+                        ui.selectable_value(&mut language, code.clone(), name);
+                    }
+                });
+            focus.item(ui, "language", &languages.response);
+            let codes: Vec<_> = menu
+                .languages
+                .iter()
+                .map(|(code, _)| code.clone())
+                .collect();
+            language = cycle(&codes, &language, focus.adjust("language"));
+            let difficulties = egui::ComboBox::from_label(tr!("settings.difficulty"))
+                .selected_text(tr!(edited_difficulty.key()))
+                .show_ui(ui, |ui| {
+                    for option in Difficulty::ALL {
+                        ui.selectable_value(&mut edited_difficulty, option, tr!(option.key()));
+                    }
+                });
+            focus.item(ui, "difficulty", &difficulties.response);
+            edited_difficulty = cycle(
+                &Difficulty::ALL,
+                &edited_difficulty,
+                focus.adjust("difficulty"),
+            );
+            ui.horizontal(|ui| {
+                let mut auto = custom_scale.is_none();
+                let auto_box = ui.checkbox(&mut auto, tr!("settings.ui_scale_auto"));
+                if focus.item(ui, "ui_scale_auto", &auto_box) {
+                    auto = !auto;
+                }
+                if auto != custom_scale.is_none() {
+                    custom_scale = (!auto).then_some(menu.ui_scale_draft);
+                }
+                let slider = ui.add_enabled(
+                    !auto,
+                    egui::Slider::new(
+                        &mut menu.ui_scale_draft,
+                        MIN_UI_SCALE..=scaling.max.max(MIN_UI_SCALE),
+                    )
+                    .text(tr!("settings.ui_scale")),
+                );
+                focus.item(ui, "ui_scale", &slider);
+                let step = focus.adjust("ui_scale");
+                if step != 0 {
+                    menu.ui_scale_draft = (menu.ui_scale_draft + step as f32 * 0.25)
+                        .clamp(MIN_UI_SCALE, scaling.max.max(MIN_UI_SCALE));
+                }
+                if slider.drag_released() || (slider.changed() && !slider.dragged()) || step != 0 {
+                    custom_scale = Some(menu.ui_scale_draft);
+                }
+            });
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label(tr!("settings.mouse"));
+                let swap = ui.button(tr!("settings.swap_buttons"));
+                if focus.item(ui, "swap_buttons", &swap) || swap.clicked() {
+                    edited_mouse = edited_mouse.swapped();
+                }
+            });
+            for action in MouseAction::ALL {
+                let button = edited_mouse.button_mut(action);
+                let combo = egui::ComboBox::from_label(tr!(action.key()))
+                    .selected_text(tr!(button_key(*button)))
+                    .show_ui(ui, |ui| {
+                        for option in MOUSE_BUTTONS {
+                            ui.selectable_value(button, option, tr!(button_key(option)));
+                        }
+                    });
+                focus.item(ui, action.key(), &combo.response);
+                *button = cycle(&MOUSE_BUTTONS, button, focus.adjust(action.key()));
+            }
+            for (first, second) in edited_mouse.conflicts(drag_threshold) {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    tr!(
+                        "settings.mouse_conflict",
+                        first = tr!(first.key()),
+                        second = tr!(second.key())
+                    ),
+                );
+            }
+        });
+    focus.end();
+    // only touch the resource on an actual edit, a change is what gets it saved
+    if edited != *preferences {
+        *preferences = edited;
+    }
+    if palette_kind != palette.kind {
+        palette.kind = palette_kind;
+    }
+    if language != localization.language() {
+        *localization = Localization::load(Path::new(LANG_DIR), &language);
+    }
+    if custom_scale != scaling.custom {
+        scaling.custom = custom_scale;
+        menu.ui_scale_draft = scaling.scale();
+    }
+    if edited_mouse != *mouse_map {
+        *mouse_map = edited_mouse;
+    }
+    if edited_auto_hide != *auto_hide {
+        *auto_hide = edited_auto_hide;
+    }
+    if edited_background != *background {
+        *background = edited_background;
+    }
+    if edited_difficulty != *difficulty {
+        *difficulty = edited_difficulty;
+    }
+}
+
+/// Write the preferences whenever they differ from the ones last saved or loaded
+#[allow(clippy::too_many_arguments)]
+fn persist_preferences(
+    motion: Res<MotionPreferences>,
+    localization: Res<Localization>,
+    scaling: Res<UiScaling>,
+    mouse_map: Res<MouseInputMap>,
+    auto_hide: Res<AutoHideSettings>,
+    background: Res<BackgroundThrottle>,
+    camera_preset: Res<CameraPresetSettings>,
+    mut saved: Local<Option<Preferences>>,
+) {
+    let preferences = Preferences {
+        motion: motion.clone(),
+        language: localization.language().to_string(),
+        ui_scale: scaling.custom,
+        mouse: *mouse_map,
+        auto_hide: *auto_hide,
+        background: *background,
+        camera_preset: camera_preset.clone(),
+    };
+    let Some(last) = saved.as_ref() else {
+        *saved = Some(preferences);
+        return;
+    };
+    if *last == preferences {
+        return;
+    }
+    if let Err(err) = preferences.save(Path::new(PREFERENCES_FILE)) {
+        warn!("can't write the preferences to {PREFERENCES_FILE}: {err}");
+    }
+    *saved = Some(preferences);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn held_keys_repeat_once_per_interval_and_wait_while_blocked() {
+        let mut repeat = KeyRepeat::default();
+        repeat.restart(0.2);
+        assert!(!repeat.tick(0.1, 0.2, false));
+        assert!(repeat.tick(0.1, 0.2, false));
+        assert!(!repeat.tick(0.15, 0.2, false));
+        // due, but still walking onto the last tile
+        assert!(!repeat.tick(0.1, 0.2, true));
+        assert!(repeat.tick(0.01, 0.2, false));
+    }
+
+    #[test]
+    fn the_repeat_interval_has_a_lower_bound() {
+        let preferences = MotionPreferences {
+            repeat_interval: 0.,
+            ..default()
+        };
+        assert_eq!(preferences.move_repeat_interval(), MIN_MOVE_REPEAT_INTERVAL);
+    }
+
+    #[test]
+    fn preferences_are_saved_and_loaded() {
+        let path =
+            std::env::temp_dir().join(format!("bevy_test_preferences_{}.ron", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(Preferences::load(&path), Preferences::default());
+
+        let preferences = Preferences {
+            motion: MotionPreferences {
+                move_input: MoveInputMode::Hold,
+                repeat_interval: 0.1,
+                reduce_motion: true,
+            },
+            language: "fr".to_string(),
+            ui_scale: Some(1.5),
+            mouse: MouseInputMap::default().swapped(),
+            auto_hide: AutoHideSettings { enabled: false },
+            background: BackgroundThrottle {
+                enabled: false,
+                max_fps: 5.,
+                audio: BackgroundAudio::Pause,
+            },
+            camera_preset: CameraPresetSettings {
+                startup_preset: Some("presets/close.ron".to_string()),
+            },
+        };
+        preferences.save(&path).unwrap();
+        assert_eq!(Preferences::load(&path), preferences);
+
+        std::fs::write(&path, "(language: ").unwrap();
+        assert_eq!(Preferences::load(&path), Preferences::default());
+        let _ = std::fs::remove_file(&path);
+    }
+}
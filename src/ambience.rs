@@ -0,0 +1,215 @@
+//! The map's music, and the ambience of the region the player stands in.
+//!
+//! Properties read from the map:
+//! - `music`: audio asset looped while the level plays
+//!
+//! Properties read from the rectangle objects of the "regions" layer:
+//! - `ambience`: audio asset looped while the player is in the region, crossfaded on the way in
+//!   and out
+
+use bevy::prelude::*;
+
+use crate::audio::{AudioChannel, PlayAudio, StopChannel};
+use crate::coords::{self, MapFrame};
+use crate::game::{GameSet, LevelLifecycleSet};
+use crate::helpers;
+use crate::map::GameInfoAlt;
+use crate::player::MainPlayer;
+use crate::regions::REGIONS_LAYER_NAME;
+use crate::state::{level_ready, AppState};
+
+/// Plugin that starts the map's music and switches the ambience with the player's region
+#[derive(Default)]
+pub struct AmbiencePlugin;
+
+impl Plugin for AmbiencePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AmbienceRegions>()
+            .add_systems(
+                OnEnter(AppState::Level),
+                start_map_audio.in_set(LevelLifecycleSet),
+            )
+            .add_systems(
+                OnExit(AppState::Level),
+                stop_map_audio.in_set(LevelLifecycleSet),
+            )
+            .add_systems(
+                Update,
+                update_region_ambience
+                    .in_set(GameSet::Gameplay)
+                    .run_if(level_ready),
+            );
+    }
+}
+
+/// A region of the map with an ambience track
+#[derive(Debug, Clone, PartialEq)]
+pub struct AmbienceRegion {
+    /// World-space extent of the region
+    pub rect: Rect,
+    pub ambience: String,
+}
+
+/// The regions with an ambience, and the ambience now playing
+#[derive(Resource, Default, Debug)]
+pub struct AmbienceRegions {
+    pub regions: Vec<AmbienceRegion>,
+    current: Option<String>,
+}
+
+impl AmbienceRegions {
+    /// Ambience of the first region containing `pos`
+    pub fn ambience_at(&self, pos: Vec2) -> Option<&str> {
+        self.regions
+            .iter()
+            .find(|region| region.rect.contains(pos))
+            .map(|region| region.ambience.as_str())
+    }
+}
+
+fn start_map_audio(
+    game_info: Res<GameInfoAlt>,
+    tile_maps: Res<Assets<helpers::tiled::TiledMap>>,
+    map_frame: Res<MapFrame>,
+    mut ambience: ResMut<AmbienceRegions>,
+    mut play: EventWriter<PlayAudio>,
+) {
+    *ambience = AmbienceRegions::default();
+    let Some(map) = tile_maps.get(&game_info.tile_map) else {
+        return;
+    };
+    if let Some(tiled::PropertyValue::StringValue(music)) = map.map.properties.get("music") {
+        play.send(PlayAudio {
+            channel: AudioChannel::Music,
+            path: music.clone(),
+        });
+    }
+
+    let regions = helpers::tiled::all_layers(&map.map)
+        .filter(|layer| layer.name.eq_ignore_ascii_case(REGIONS_LAYER_NAME))
+        .filter_map(|layer| match layer.layer_type() {
+            tiled::LayerType::Objects(layer) => Some(layer),
+            _ => None,
+        });
+    for layer in regions {
+        for object in layer.objects() {
+            let Some(tiled::PropertyValue::StringValue(track)) = object.properties.get("ambience")
+            else {
+                continue;
+            };
+            let tiled::ObjectShape::Rect { width, height } = object.shape else {
+                warn!(
+                    "region {} has an ambience but isn't a rectangle",
+                    object.name
+                );
+                continue;
+            };
+            let corners = (
+                coords::tiled_px_to_world(&map_frame, Vec2::new(object.x, object.y)),
+                coords::tiled_px_to_world(
+                    &map_frame,
+                    Vec2::new(object.x + width, object.y + height),
+                ),
+            );
+            let (Some(a), Some(b)) = corners else {
+                warn!("region {} is outside the map", object.name);
+                continue;
+            };
+            ambience.regions.push(AmbienceRegion {
+                rect: Rect::from_corners(a, b),
+                ambience: track.clone(),
+            });
+        }
+    }
+}
+
+fn stop_map_audio(mut ambience: ResMut<AmbienceRegions>, mut stop: EventWriter<StopChannel>) {
+    ambience.current = None;
+    stop.send(StopChannel(AudioChannel::Music));
+    stop.send(StopChannel(AudioChannel::Ambience));
+}
+
+fn update_region_ambience(
+    map_frame: Res<MapFrame>,
+    players: Query<&Transform, (With<MainPlayer>, Changed<Transform>)>,
+    mut ambience: ResMut<AmbienceRegions>,
+    mut play: EventWriter<PlayAudio>,
+    mut stop: EventWriter<StopChannel>,
+) {
+    let Ok(transform) = players.get_single() else {
+        return;
+    };
+    // the region of the player's tile, not of where its sprite is mid-step
+    let Some(center) = coords::world_to_tile(&map_frame, transform.translation.truncate())
+        .and_then(|tile| coords::tile_to_world_center(&map_frame, &tile))
+    else {
+        return;
+    };
+    let track = ambience.ambience_at(center).map(str::to_string);
+    if track == ambience.current {
+        return;
+    }
+    match &track {
+        Some(path) => play.send(PlayAudio {
+            channel: AudioChannel::Ambience,
+            path: path.clone(),
+        }),
+        None => stop.send(StopChannel(AudioChannel::Ambience)),
+    }
+    ambience.current = track;
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs_tilemap::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn ambience_follows_the_players_tile() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(MapFrame {
+                transform: Transform::IDENTITY,
+                grid_size: TilemapGridSize { x: 10., y: 10. },
+                map_type: TilemapType::Square,
+                map_size: TilemapSize { x: 8, y: 8 },
+            })
+            .insert_resource(AmbienceRegions {
+                regions: vec![AmbienceRegion {
+                    rect: Rect::new(0., 0., 30., 30.),
+                    ambience: "cave.ogg".to_string(),
+                }],
+                current: None,
+            })
+            .add_event::<PlayAudio>()
+            .add_event::<StopChannel>()
+            .add_systems(Update, update_region_ambience);
+        let player = app
+            .world
+            .spawn((MainPlayer, Transform::from_xyz(15., 15., 2.)))
+            .id();
+
+        app.update();
+        let events = app.world.resource::<Events<PlayAudio>>();
+        let played: Vec<_> = events.get_reader().read(events).cloned().collect();
+        assert_eq!(
+            played,
+            [PlayAudio {
+                channel: AudioChannel::Ambience,
+                path: "cave.ogg".to_string(),
+            }]
+        );
+
+        app.world
+            .get_mut::<Transform>(player)
+            .unwrap()
+            .translation
+            .x = 55.;
+        app.update();
+        let events = app.world.resource::<Events<StopChannel>>();
+        let stopped: Vec<_> = events.get_reader().read(events).copied().collect();
+        assert_eq!(stopped, [StopChannel(AudioChannel::Ambience)]);
+        assert_eq!(app.world.resource::<AmbienceRegions>().current, None);
+    }
+}
@@ -0,0 +1,519 @@
+//! Butterflies and birds drifting over the regions of the map, for a bit of life.
+//!
+//! Properties read from the rectangle objects of the "regions" layer:
+//! - `ambient`: `<kind>:<density>`, e.g. `butterfly:0.2`, the kind of creature and how many
+//!   live in the region per tile, 0.1 when left out; the kinds are `butterfly` and `bird`
+//!
+//! Each such region gets an `AmbientSpawner` entity. While the region is within `view_margin` of
+//! the camera view, its creatures are spawned a few per frame, up to the region's density scaled
+//! by `AmbientSettings::density_scale` and to `max_creatures` over the whole map. Once the region
+//! is out of view they're despawned, to come back when it's in view again. The creatures aren't
+//! on the grid: they wander smoothly and turn back before they leave their region.
+
+use bevy::prelude::*;
+use thiserror::Error;
+
+use crate::camera::CameraViewChanged;
+use crate::coords::{self, MapFrame};
+use crate::game::{GameSet, LevelLifecycleSet};
+use crate::helpers;
+use crate::map::GameInfoAlt;
+use crate::regions::REGIONS_LAYER_NAME;
+use crate::state::{level_ready, AppState};
+
+/// Under the fog of war, over the canopies
+const AMBIENT_Z: f32 = 4.9;
+
+/// Plugin that spawns the ambient creatures of the regions in view and moves them
+#[derive(Default)]
+pub struct AmbientLifePlugin;
+
+impl Plugin for AmbientLifePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AmbientSettings>()
+            .register_type::<AmbientSettings>()
+            .register_type::<AmbientSpawner>()
+            .add_systems(
+                OnEnter(AppState::Level),
+                spawn_ambient_spawners.in_set(LevelLifecycleSet),
+            )
+            .add_systems(
+                OnExit(AppState::Level),
+                despawn_ambient_life.in_set(LevelLifecycleSet),
+            )
+            .add_systems(
+                Update,
+                (
+                    update_spawner_activity,
+                    spawn_ambient_creatures,
+                    wander_ambient_creatures,
+                )
+                    .chain()
+                    .in_set(GameSet::RenderPrep)
+                    .run_if(level_ready)
+                    .run_if(crate::background::not_throttled),
+            );
+    }
+}
+
+#[derive(Resource, Reflect, Debug, Clone, PartialEq)]
+#[reflect(Resource)]
+pub struct AmbientSettings {
+    /// Multiplies the density of every region
+    pub density_scale: f32,
+    /// Most ambient creatures alive at once, over the whole map
+    pub max_creatures: usize,
+    /// Most ambient creatures spawned in a frame
+    pub spawns_per_frame: usize,
+    /// How far out of the camera view a region keeps its creatures, in world units
+    pub view_margin: f32,
+}
+
+impl Default for AmbientSettings {
+    fn default() -> Self {
+        Self {
+            density_scale: 1.,
+            max_creatures: 60,
+            spawns_per_frame: 4,
+            view_margin: 128.,
+        }
+    }
+}
+
+#[derive(Reflect, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AmbientKind {
+    Butterfly,
+    Bird,
+}
+
+impl AmbientKind {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.trim() {
+            "butterfly" => Some(Self::Butterfly),
+            "bird" => Some(Self::Bird),
+            _ => None,
+        }
+    }
+
+    /// World units per second
+    fn speed(self) -> f32 {
+        match self {
+            Self::Butterfly => 18.,
+            Self::Bird => 60.,
+        }
+    }
+
+    /// Fastest turn while wandering, in radians per second
+    fn turn_rate(self) -> f32 {
+        match self {
+            Self::Butterfly => 5.,
+            Self::Bird => 1.5,
+        }
+    }
+
+    fn sprite(self) -> Sprite {
+        let (color, size) = match self {
+            Self::Butterfly => (Color::rgb(1., 0.85, 0.3), Vec2::splat(3.)),
+            Self::Bird => (Color::rgb(0.2, 0.2, 0.25), Vec2::new(6., 2.)),
+        };
+        Sprite {
+            color,
+            custom_size: Some(size),
+            ..default()
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum AmbientParseError {
+    #[error("unknown ambient creature in `{0}`, expected butterfly or bird")]
+    UnknownKind(String),
+    #[error("bad density in `{0}`, expected `<kind>:<creatures per tile>`")]
+    BadDensity(String),
+}
+
+/// The kind and density of an `ambient` property
+pub fn parse_ambient(value: &str) -> Result<(AmbientKind, f32), AmbientParseError> {
+    let (kind, density) = value.split_once(':').unwrap_or((value, "0.1"));
+    let kind = AmbientKind::from_name(kind)
+        .ok_or_else(|| AmbientParseError::UnknownKind(value.to_string()))?;
+    let density = density
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|density| density.is_finite() && *density >= 0.)
+        .ok_or_else(|| AmbientParseError::BadDensity(value.to_string()))?;
+    Ok((kind, density))
+}
+
+/// A region with ambient creatures
+#[derive(Component, Reflect, Debug, Clone)]
+pub struct AmbientSpawner {
+    pub kind: AmbientKind,
+    /// Creatures per tile of the region
+    pub density: f32,
+    /// World-space extent of the region
+    pub rect: Rect,
+    /// Tiles the region covers
+    pub tiles: f32,
+    /// Whether the region is near enough the camera view to have its creatures
+    pub active: bool,
+}
+
+impl AmbientSpawner {
+    /// How many creatures the region has when active
+    pub fn wanted(&self, density_scale: f32) -> usize {
+        (self.density * self.tiles * density_scale.max(0.)).round() as usize
+    }
+}
+
+/// A creature wandering in the region of its `spawner`
+#[derive(Component, Debug)]
+pub struct AmbientCreature {
+    spawner: Entity,
+    /// Direction it flies in, in radians
+    heading: f32,
+    /// State of its random turns
+    rng: u32,
+}
+
+/// A xorshift step of `state`, which must not be zero, and the next value in `0..1`
+pub fn next_random(state: &mut u32) -> f32 {
+    let mut x = *state;
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    *state = x;
+    (x >> 8) as f32 / (1 << 24) as f32
+}
+
+/// Move a creature at `pos` flying along `heading` for `delta` seconds, turning by `turn`
+/// radians per second, and back towards the middle of `rect` near its edges
+pub fn wander_step(
+    pos: Vec2,
+    heading: f32,
+    rect: Rect,
+    speed: f32,
+    turn: f32,
+    delta: f32,
+) -> (Vec2, f32) {
+    let mut heading = heading + turn * delta;
+    let margin = rect.size().min_element() * 0.1;
+    let inner = coords::inflate_rect(rect, -margin);
+    if !inner.contains(pos) {
+        let to_center = rect.center() - pos;
+        let wanted = to_center.y.atan2(to_center.x);
+        // the shortest way around to the wanted heading
+        let mut diff = (wanted - heading).rem_euclid(std::f32::consts::TAU);
+        if diff > std::f32::consts::PI {
+            diff -= std::f32::consts::TAU;
+        }
+        heading += diff * (4. * delta).min(1.);
+    }
+    let pos = pos + Vec2::from_angle(heading) * speed * delta;
+    (pos.clamp(rect.min, rect.max), heading)
+}
+
+fn spawn_ambient_spawners(
+    mut commands: Commands,
+    game_info: Res<GameInfoAlt>,
+    tile_maps: Res<Assets<helpers::tiled::TiledMap>>,
+    map_frame: Res<MapFrame>,
+) {
+    let Some(map) = tile_maps.get(&game_info.tile_map) else {
+        return;
+    };
+    let regions = helpers::tiled::all_layers(&map.map)
+        .filter(|layer| layer.name.eq_ignore_ascii_case(REGIONS_LAYER_NAME))
+        .filter_map(|layer| match layer.layer_type() {
+            tiled::LayerType::Objects(layer) => Some(layer),
+            _ => None,
+        });
+    let tile_area = map_frame.grid_size.x * map_frame.grid_size.y;
+    for layer in regions {
+        for object in layer.objects() {
+            let Some(tiled::PropertyValue::StringValue(ambient)) = object.properties.get("ambient")
+            else {
+                continue;
+            };
+            let (kind, density) = match parse_ambient(ambient) {
+                Ok(parsed) => parsed,
+                Err(err) => {
+                    warn!("region {} has a bad ambient property: {err}", object.name);
+                    continue;
+                }
+            };
+            let tiled::ObjectShape::Rect { width, height } = object.shape else {
+                warn!(
+                    "region {} has ambient creatures but isn't a rectangle",
+                    object.name
+                );
+                continue;
+            };
+            let corners = (
+                coords::tiled_px_to_world(&map_frame, Vec2::new(object.x, object.y)),
+                coords::tiled_px_to_world(
+                    &map_frame,
+                    Vec2::new(object.x + width, object.y + height),
+                ),
+            );
+            let (Some(a), Some(b)) = corners else {
+                warn!("region {} is outside the map", object.name);
+                continue;
+            };
+            let rect = Rect::from_corners(a, b);
+            commands.spawn((
+                AmbientSpawner {
+                    kind,
+                    density,
+                    rect,
+                    tiles: rect.width() * rect.height() / tile_area.max(1.),
+                    active: false,
+                },
+                Name::new(format!("ambient life of {}", object.name)),
+            ));
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn despawn_ambient_life(
+    mut commands: Commands,
+    spawned: Query<Entity, Or<(With<AmbientSpawner>, With<AmbientCreature>)>>,
+) {
+    for entity in &spawned {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Activate the regions near the camera view, and despawn the creatures of the others
+fn update_spawner_activity(
+    mut commands: Commands,
+    mut view_changed: EventReader<CameraViewChanged>,
+    settings: Res<AmbientSettings>,
+    mut spawners: Query<(Entity, &mut AmbientSpawner)>,
+    creatures: Query<(Entity, &AmbientCreature)>,
+    mut view: Local<Option<Rect>>,
+) {
+    if let Some(changed) = view_changed.read().last() {
+        *view = Some(changed.view);
+    } else if !settings.is_changed() {
+        return;
+    }
+    let Some(view) = *view else {
+        return;
+    };
+    let near = coords::inflate_rect(view, settings.view_margin);
+    for (entity, mut spawner) in &mut spawners {
+        let active = !near.intersect(spawner.rect).is_empty();
+        if active == spawner.active {
+            continue;
+        }
+        spawner.active = active;
+        if !active {
+            for (creature, _) in creatures.iter().filter(|(_, c)| c.spawner == entity) {
+                commands.entity(creature).despawn_recursive();
+            }
+        }
+    }
+}
+
+/// Spawn the missing creatures of the active regions, `spawns_per_frame` at most
+fn spawn_ambient_creatures(
+    mut commands: Commands,
+    settings: Res<AmbientSettings>,
+    spawners: Query<(Entity, &AmbientSpawner)>,
+    creatures: Query<&AmbientCreature>,
+    mut seed: Local<u32>,
+) {
+    let mut alive = creatures.iter().count();
+    let mut budget = settings.spawns_per_frame;
+    for (entity, spawner) in &spawners {
+        if !spawner.active {
+            continue;
+        }
+        let have = creatures.iter().filter(|c| c.spawner == entity).count();
+        let wanted = spawner.wanted(settings.density_scale);
+        for _ in have..wanted {
+            if budget == 0 || alive >= settings.max_creatures {
+                return;
+            }
+            budget -= 1;
+            alive += 1;
+            *seed = seed.wrapping_add(1);
+            let mut rng = seed.wrapping_mul(0x9e37_79b9) | 1;
+            let pos = spawner.rect.min
+                + Vec2::new(next_random(&mut rng), next_random(&mut rng)) * spawner.rect.size();
+            commands.spawn((
+                SpriteBundle {
+                    sprite: spawner.kind.sprite(),
+                    transform: Transform::from_translation(pos.extend(AMBIENT_Z)),
+                    ..default()
+                },
+                AmbientCreature {
+                    spawner: entity,
+                    heading: next_random(&mut rng) * std::f32::consts::TAU,
+                    rng,
+                },
+                Name::new("ambient creature"),
+            ));
+        }
+    }
+}
+
+fn wander_ambient_creatures(
+    time: Res<Time>,
+    spawners: Query<&AmbientSpawner>,
+    mut creatures: Query<(&mut AmbientCreature, &mut Transform)>,
+) {
+    let delta = time.delta_seconds();
+    if delta == 0. {
+        return;
+    }
+    for (mut creature, mut transform) in &mut creatures {
+        let Ok(spawner) = spawners.get(creature.spawner) else {
+            continue;
+        };
+        let rate = spawner.kind.turn_rate();
+        let turn = (next_random(&mut creature.rng) * 2. - 1.) * rate;
+        let (pos, heading) = wander_step(
+            transform.translation.truncate(),
+            creature.heading,
+            spawner.rect,
+            spawner.kind.speed(),
+            turn,
+            delta,
+        );
+        creature.heading = heading;
+        transform.translation = pos.extend(AMBIENT_Z);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+
+    #[test]
+    fn ambient_properties_parse() {
+        assert_eq!(
+            parse_ambient("butterfly:0.2"),
+            Ok((AmbientKind::Butterfly, 0.2))
+        );
+        assert_eq!(parse_ambient("bird: 1"), Ok((AmbientKind::Bird, 1.)));
+        assert_eq!(
+            parse_ambient("dragon:1"),
+            Err(AmbientParseError::UnknownKind("dragon:1".to_string()))
+        );
+        assert_eq!(
+            parse_ambient("bird:-2"),
+            Err(AmbientParseError::BadDensity("bird:-2".to_string()))
+        );
+    }
+
+    #[test]
+    fn wandering_stays_in_the_region() {
+        let rect = Rect::new(0., 0., 100., 50.);
+        let mut pos = Vec2::new(95., 25.);
+        let mut heading = 0.;
+        let mut rng = 7;
+        for _ in 0..1000 {
+            let turn = (next_random(&mut rng) * 2. - 1.) * 5.;
+            (pos, heading) = wander_step(pos, heading, rect, 60., turn, 0.1);
+            assert!(rect.contains(pos), "{pos}");
+        }
+    }
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                100,
+            )))
+            .insert_resource(AmbientSettings {
+                spawns_per_frame: 3,
+                max_creatures: 12,
+                ..default()
+            })
+            .add_event::<CameraViewChanged>()
+            .add_systems(
+                Update,
+                (
+                    update_spawner_activity,
+                    spawn_ambient_creatures,
+                    apply_deferred,
+                    wander_ambient_creatures,
+                )
+                    .chain(),
+            );
+        app
+    }
+
+    fn spawner(app: &mut App, rect: Rect) -> Entity {
+        app.world
+            .spawn(AmbientSpawner {
+                kind: AmbientKind::Butterfly,
+                density: 1.,
+                rect,
+                tiles: 10.,
+                active: false,
+            })
+            .id()
+    }
+
+    fn creatures_of(app: &mut App, spawner: Entity) -> usize {
+        app.world
+            .query::<&AmbientCreature>()
+            .iter(&app.world)
+            .filter(|creature| creature.spawner == spawner)
+            .count()
+    }
+
+    fn look_at(app: &mut App, view: Rect) {
+        app.world.send_event(CameraViewChanged { view, scale: 1. });
+        app.update();
+    }
+
+    #[test]
+    fn creatures_spawn_within_the_budget_and_cap() {
+        let mut app = test_app();
+        let meadow = spawner(&mut app, Rect::new(0., 0., 100., 100.));
+        let garden = spawner(&mut app, Rect::new(150., 0., 250., 100.));
+
+        look_at(&mut app, Rect::new(0., 0., 200., 100.));
+        assert_eq!(
+            creatures_of(&mut app, meadow) + creatures_of(&mut app, garden),
+            3
+        );
+        for _ in 0..10 {
+            app.update();
+        }
+        // ten each, but no more than twelve in all
+        assert_eq!(creatures_of(&mut app, meadow), 10);
+        assert_eq!(creatures_of(&mut app, garden), 2);
+    }
+
+    #[test]
+    fn creatures_leave_with_their_region_and_come_back() {
+        let mut app = test_app();
+        let meadow = spawner(&mut app, Rect::new(0., 0., 100., 100.));
+        look_at(&mut app, Rect::new(0., 0., 100., 100.));
+        for _ in 0..5 {
+            app.update();
+        }
+        assert_eq!(creatures_of(&mut app, meadow), 10);
+
+        // still within the margin
+        look_at(&mut app, Rect::new(200., 0., 300., 100.));
+        assert_eq!(creatures_of(&mut app, meadow), 10);
+
+        look_at(&mut app, Rect::new(1000., 0., 1100., 100.));
+        assert_eq!(creatures_of(&mut app, meadow), 0);
+
+        look_at(&mut app, Rect::new(0., 0., 100., 100.));
+        assert_eq!(creatures_of(&mut app, meadow), 3);
+    }
+}
@@ -0,0 +1,263 @@
+//! Sprite animations of the creatures.
+//!
+//! A new animation starts part way into its first frame, by a phase from the `AnimationSeed` and
+//! where the creature spawned, so a crowd doesn't flip its frames all at once. The phases are the
+//! same on every run with the same seed. A `synchronized` clip starts on the beat instead.
+//!
+//! A sprite index past the end of the atlas would panic when the sprites are drawn, so the
+//! systems setting indices go through `set_sprite_index_checked`, which clamps it to the last
+//! sprite and warns, once per entity.
+
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+
+use crate::game::GameSet;
+use crate::perf::timed;
+use crate::state::level_ready;
+
+/// Plugin that steps the creature sprites through their animation frames
+#[derive(Default)]
+pub struct AnimationPlugin;
+
+impl Plugin for AnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AnimationSeed>()
+            .init_resource::<SpriteIndexWarnings>()
+            .register_type::<AnimationSeed>()
+            // in the set before the animation's, which can't be named once timed
+            .add_systems(
+                Update,
+                offset_animation_phases
+                    .in_set(GameSet::Gameplay)
+                    .run_if(level_ready),
+            )
+            .add_systems(
+                Update,
+                timed!(animate_sprite)
+                    .in_set(GameSet::RenderPrep)
+                    .run_if(level_ready)
+                    .run_if(crate::debug_step::gameplay_running)
+                    .run_if(crate::background::not_throttled),
+            );
+    }
+}
+
+/// Index into the `AnimationClip` of the frame a creature shows
+#[derive(Component)]
+pub struct AnimationFrame(pub i32);
+
+/// Frames of a creature's animation, the first ones of its row in the creature atlas
+pub const CREATURE_FRAME_COUNT: usize = 2;
+
+/// The frames `animate_sprite` cycles a creature through
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct AnimationClip {
+    /// Atlas indices of the frames
+    pub frames: Vec<usize>,
+    /// Start on the beat rather than at a phase of its own, for what has to blink together
+    pub synchronized: bool,
+}
+
+impl AnimationClip {
+    pub fn new(frames: Vec<usize>) -> Self {
+        Self {
+            frames,
+            synchronized: false,
+        }
+    }
+
+    /// The animation of the creature on `row` of an atlas `columns` cells wide
+    pub fn creature_row(row: usize, columns: usize) -> Self {
+        let base = row * columns;
+        Self::new((base..base + CREATURE_FRAME_COUNT).collect())
+    }
+}
+
+/// Seed of the animation phases, the game has no shared random generator yet
+#[derive(Resource, Reflect, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[reflect(Resource)]
+pub struct AnimationSeed(pub u64);
+
+/// How far into its first frame an animation spawned at `pos` starts, from 0 to 1
+pub fn phase_offset(seed: u64, pos: Vec2) -> f32 {
+    // splitmix64 of the seed and the whole world position, the spawn tile's center
+    let x = pos.x.round() as i32 as u32 as u64;
+    let y = pos.y.round() as i32 as u32 as u64;
+    let mut z = (seed ^ (x << 32 | y)).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 40) as f32 / (1u64 << 24) as f32
+}
+
+/// Columns and rows of an atlas cut from a grid, from the width of its first cell
+pub fn atlas_grid(atlas: &TextureAtlas) -> Option<(usize, usize)> {
+    let cell = atlas.textures.first()?;
+    let columns = (atlas.size.x / cell.width()).floor() as usize;
+    if columns == 0 {
+        return None;
+    }
+    Some((columns, atlas.textures.len() / columns))
+}
+
+#[derive(Component, Deref, DerefMut)]
+pub struct AnimationTimer(pub Timer);
+
+/// Set the sprite to `index`, or to the last sprite of an atlas of `atlas_len` sprites when it's
+/// past the end, returns whether it had to be clamped
+pub fn set_sprite_index_checked(
+    sprite: &mut TextureAtlasSprite,
+    index: usize,
+    atlas_len: usize,
+) -> bool {
+    let clamped = index.min(atlas_len.saturating_sub(1));
+    if sprite.index != clamped {
+        sprite.index = clamped;
+    }
+    clamped != index
+}
+
+/// A sprite of `atlas` showing `index`, clamped and warned about when it's past the end, for
+/// `name` spawning
+pub fn spawn_sprite(name: &str, index: usize, atlas: Option<&TextureAtlas>) -> TextureAtlasSprite {
+    let mut sprite = TextureAtlasSprite::new(index);
+    if let Some(atlas) = atlas {
+        if set_sprite_index_checked(&mut sprite, index, atlas.len()) {
+            warn!(
+                "{name} shows sprite {index}, the atlas has {}, showing the last one",
+                atlas.len()
+            );
+        }
+    }
+    sprite
+}
+
+/// The entities already warned about for a sprite index outside their atlas
+#[derive(Resource, Debug, Default)]
+pub struct SpriteIndexWarnings(HashSet<Entity>);
+
+impl SpriteIndexWarnings {
+    /// Warn that `entity` wanted the sprite `index` of an atlas of `atlas_len`, the first time
+    /// only, returns whether it warned
+    pub fn warn(&mut self, entity: Entity, index: usize, atlas_len: usize) -> bool {
+        if !self.0.insert(entity) {
+            return false;
+        }
+        warn!("{entity:?} shows sprite {index}, the atlas has {atlas_len}, showing the last one");
+        true
+    }
+}
+
+/// Start the new animations at their phase
+pub fn offset_animation_phases(
+    seed: Res<AnimationSeed>,
+    mut added: Query<(&AnimationClip, &Transform, &mut AnimationTimer), Added<AnimationTimer>>,
+) {
+    for (clip, transform, mut timer) in &mut added {
+        if clip.synchronized {
+            continue;
+        }
+        let phase = phase_offset(seed.0, transform.translation.truncate());
+        let elapsed = timer.duration().mul_f32(phase);
+        timer.set_elapsed(elapsed);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn animate_sprite(
+    time: Res<Time>,
+    atlases: Res<Assets<TextureAtlas>>,
+    mut warnings: ResMut<SpriteIndexWarnings>,
+    mut query: Query<(
+        Entity,
+        &mut AnimationFrame,
+        &AnimationClip,
+        &mut AnimationTimer,
+        &mut TextureAtlasSprite,
+        Option<&Handle<TextureAtlas>>,
+    )>,
+) {
+    for (entity, mut frame, clip, mut timer, mut sprite, atlas) in &mut query {
+        timer.tick(time.delta());
+        if timer.just_finished() && !clip.frames.is_empty() {
+            frame.0 = (frame.0 + 1).rem_euclid(clip.frames.len() as i32);
+            let index = clip.frames[frame.0 as usize];
+            // without its atlas the sprite isn't drawn, whatever its index
+            let Some(atlas_len) = atlas
+                .and_then(|atlas| atlases.get(atlas))
+                .map(TextureAtlas::len)
+            else {
+                sprite.index = index;
+                continue;
+            };
+            if set_sprite_index_checked(&mut sprite, index, atlas_len) {
+                warnings.warn(entity, index, atlas_len);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+
+    /// An atlas of two sprites
+    fn small_atlas() -> TextureAtlas {
+        TextureAtlas::from_grid(Handle::default(), Vec2::splat(16.), 2, 1, None, None)
+    }
+
+    #[test]
+    fn indices_past_the_atlas_are_clamped() {
+        let mut sprite = TextureAtlasSprite::new(0);
+        assert!(!set_sprite_index_checked(&mut sprite, 1, 2));
+        assert_eq!(sprite.index, 1);
+        assert!(set_sprite_index_checked(&mut sprite, 5, 2));
+        assert_eq!(sprite.index, 1);
+
+        let sprite = spawn_sprite("troll", 22, Some(&small_atlas()));
+        assert_eq!(sprite.index, 1);
+    }
+
+    #[test]
+    fn animations_past_the_atlas_warn_once() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<Assets<TextureAtlas>>()
+            .init_resource::<SpriteIndexWarnings>()
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                100,
+            )))
+            .add_systems(Update, animate_sprite);
+        let atlas = app
+            .world
+            .resource_mut::<Assets<TextureAtlas>>()
+            .add(small_atlas());
+        let creature = app
+            .world
+            .spawn((
+                SpriteSheetBundle {
+                    texture_atlas: atlas,
+                    ..default()
+                },
+                AnimationFrame(0),
+                AnimationClip::new(vec![0, 7]),
+                AnimationTimer(Timer::new(Duration::from_millis(100), TimerMode::Repeating)),
+            ))
+            .id();
+
+        let mut indices = Vec::new();
+        for _ in 0..5 {
+            app.update();
+            indices.push(app.world.get::<TextureAtlasSprite>(creature).unwrap().index);
+        }
+        // the first update has no time delta
+        assert_eq!(indices, [0, 1, 0, 1, 0]);
+        let mut warnings = app.world.resource_mut::<SpriteIndexWarnings>();
+        assert_eq!(warnings.0.len(), 1);
+        assert!(!warnings.warn(creature, 7, 2));
+    }
+}
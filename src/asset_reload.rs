@@ -0,0 +1,441 @@
+//! Reloading the dynamic assets file without a restart, with Ctrl+Shift+R or the
+//! `reload_assets` console command.
+//!
+//! The loading state reads `ASSETS_FILE` once, so the definitions it had then are kept in
+//! `AssetDefinitions`. A reload reads the file again and compares the keys: the changed ones are
+//! loaded, and once their assets are in, built like the loading state does, registered in
+//! `DynamicAssets` and put in their field of `GameInfoAlt` or `UiAssets`, from where they take the
+//! paths of a hot-reloaded asset.
+//! A new main map is switched to with `LoadMap`, a new creature sheet is selected again, which
+//! checks the catalog and the templates against it. A key gone from the file keeps its asset,
+//! with a warning, since live entities may still use it. There are no per-level collections, and
+//! the UI already built keeps the textures it was built with.
+
+use std::any::{type_name, TypeId};
+use std::path::{Path, PathBuf};
+
+use bevy::asset::LoadState;
+use bevy::ecs::system::RunSystemOnce;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_asset_loader::dynamic_asset::{DynamicAsset, DynamicAssetType, DynamicAssets};
+use bevy_asset_loader::standard_dynamic_asset::{
+    StandardDynamicAsset, StandardDynamicAssetCollection,
+};
+use thiserror::Error;
+
+use crate::console::{console_closed, ConsoleCommand, DebugConsole};
+use crate::game::GameSet;
+use crate::game_ui::UiAssets;
+use crate::map::GameInfoAlt;
+use crate::map_switch::LoadMap;
+use crate::sprite_atlas;
+use crate::state::{level_ready, AppState};
+use crate::toast::Toast;
+
+/// The dynamic assets file, relative to the assets folder
+pub const ASSETS_FILE: &str = "main.assets.ron";
+/// Key that reloads the dynamic assets file, with Ctrl+Shift
+pub const RELOAD_ASSETS_KEY: KeyCode = KeyCode::R;
+
+/// Plugin that reloads the dynamic assets file on `ReloadAssetDefinitions`
+#[derive(Default)]
+pub struct AssetReloadPlugin;
+
+impl Plugin for AssetReloadPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AssetReloadSettings>()
+            .init_resource::<AssetDefinitions>()
+            .init_resource::<PendingReloads>()
+            .add_event::<ReloadAssetDefinitions>()
+            .add_systems(OnExit(AppState::Loading), remember_asset_definitions)
+            .add_systems(
+                Update,
+                (reload_shortcut.run_if(console_closed), reload_command).in_set(GameSet::Input),
+            )
+            .add_systems(
+                Update,
+                (
+                    reload_asset_definitions.run_if(on_event::<ReloadAssetDefinitions>()),
+                    build_reloaded_assets
+                        .run_if(|pending: Res<PendingReloads>| !pending.0.is_empty()),
+                )
+                    .chain()
+                    .in_set(GameSet::Gameplay)
+                    .run_if(level_ready),
+            );
+    }
+}
+
+/// Where the dynamic assets file is read from on a reload
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct AssetReloadSettings {
+    pub path: PathBuf,
+}
+
+impl Default for AssetReloadSettings {
+    fn default() -> Self {
+        Self {
+            path: Path::new("assets").join(ASSETS_FILE),
+        }
+    }
+}
+
+/// Send to read the dynamic assets file again and reload the keys that changed
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReloadAssetDefinitions;
+
+#[derive(Debug, Error)]
+pub enum AssetsFileError {
+    #[error("could not read the assets file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("unreadable assets file: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+}
+
+fn read_assets_file(path: &Path) -> Result<StandardDynamicAssetCollection, AssetsFileError> {
+    let text = std::fs::read_to_string(path)?;
+    Ok(ron::from_str(&text)?)
+}
+
+/// The definition of each key of the dynamic assets file as loaded, written out for comparing
+#[derive(Resource, Default, Debug, PartialEq)]
+pub struct AssetDefinitions(HashMap<String, String>);
+
+impl AssetDefinitions {
+    fn of(collection: &StandardDynamicAssetCollection) -> Self {
+        Self(
+            collection
+                .0
+                .iter()
+                .map(|(key, asset)| (key.clone(), format!("{asset:?}")))
+                .collect(),
+        )
+    }
+
+    /// The keys `new` defines differently, new keys included, and the keys it no longer has
+    pub fn diff(&self, new: &Self) -> (Vec<String>, Vec<String>) {
+        let mut changed: Vec<_> = new
+            .0
+            .iter()
+            .filter(|(key, definition)| self.0.get(*key) != Some(definition))
+            .map(|(key, _)| key.clone())
+            .collect();
+        let mut removed: Vec<_> = self
+            .0
+            .keys()
+            .filter(|key| !new.0.contains_key(*key))
+            .cloned()
+            .collect();
+        changed.sort();
+        removed.sort();
+        (changed, removed)
+    }
+}
+
+fn remember_asset_definitions(
+    settings: Res<AssetReloadSettings>,
+    mut definitions: ResMut<AssetDefinitions>,
+) {
+    match read_assets_file(&settings.path) {
+        Ok(collection) => *definitions = AssetDefinitions::of(&collection),
+        Err(err) => warn!("{err}, a reload will load every key again"),
+    }
+}
+
+fn reload_shortcut(keys: Res<Input<KeyCode>>, mut reload: EventWriter<ReloadAssetDefinitions>) {
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if ctrl && shift && keys.just_pressed(RELOAD_ASSETS_KEY) {
+        reload.send(ReloadAssetDefinitions);
+    }
+}
+
+/// `reload_assets` reads the dynamic assets file again
+fn reload_command(
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<DebugConsole>,
+    mut reload: EventWriter<ReloadAssetDefinitions>,
+) {
+    for command in console_commands.read() {
+        if command.name == "reload_assets" {
+            console.print("reloading the assets file");
+            reload.send(ReloadAssetDefinitions);
+        }
+    }
+}
+
+/// A changed key of the assets file, loading before it's built
+#[derive(Debug)]
+struct PendingReload {
+    key: String,
+    /// The key's definition in the file, remembered once it's built
+    definition: String,
+    asset: StandardDynamicAsset,
+    handles: Vec<UntypedHandle>,
+}
+
+/// The changed keys still loading
+#[derive(Resource, Default, Debug)]
+struct PendingReloads(Vec<PendingReload>);
+
+fn reload_asset_definitions(world: &mut World) {
+    let path = world.resource::<AssetReloadSettings>().path.clone();
+    let mut collection = match read_assets_file(&path) {
+        Ok(collection) => collection,
+        Err(err) => {
+            error!("{err}");
+            world.send_event(Toast::error(format!("Can't reload the assets: {err}")));
+            return;
+        }
+    };
+    let definitions = AssetDefinitions::of(&collection);
+    let (changed, removed) = world.resource::<AssetDefinitions>().diff(&definitions);
+    for key in &removed {
+        warn!("{key} is gone from {}, keeping its asset", path.display());
+        world.send_event(Toast::warn(format!(
+            "{key} is gone from the assets file, keeping its asset"
+        )));
+    }
+    for key in changed {
+        let Some(asset) = collection.0.remove(&key) else {
+            continue;
+        };
+        let handles = asset.load(world.resource::<AssetServer>());
+        let definition = definitions.0[&key].clone();
+        let mut pending = world.resource_mut::<PendingReloads>();
+        // a key reloaded again before it's built only keeps its newest definition
+        pending.0.retain(|reload| reload.key != key);
+        pending.0.push(PendingReload {
+            key,
+            definition,
+            asset,
+            handles,
+        });
+    }
+}
+
+/// Build the reloaded keys whose assets are loaded, like the loading state does
+fn build_reloaded_assets(world: &mut World) {
+    let asset_server = world.resource::<AssetServer>().clone();
+    let pending = std::mem::take(&mut world.resource_mut::<PendingReloads>().0);
+    let mut reloaded = Vec::new();
+    for reload in pending {
+        let key = reload.key.clone();
+        let states: Vec<_> = reload
+            .handles
+            .iter()
+            .map(|handle| asset_server.get_load_state(handle.id()))
+            .collect();
+        // a key that failed keeps its old definition, to be tried again
+        if states.contains(&Some(LoadState::Failed)) {
+            error!("can't reload {key}: its asset failed to load");
+            world.send_event(Toast::error(format!("Can't reload {key}")));
+            continue;
+        }
+        if !states.iter().all(|state| *state == Some(LoadState::Loaded)) {
+            world.resource_mut::<PendingReloads>().0.push(reload);
+            continue;
+        }
+        let built = match reload.asset.build(world) {
+            Ok(built) => built,
+            Err(err) => {
+                error!("can't reload {key}: {err}");
+                world.send_event(Toast::error(format!("Can't reload {key}")));
+                continue;
+            }
+        };
+        info!("reloaded {key}");
+        use_reloaded_asset(world, &key, built);
+        world
+            .resource_mut::<DynamicAssets>()
+            .register_asset(key.clone(), Box::new(reload.asset));
+        world
+            .resource_mut::<AssetDefinitions>()
+            .0
+            .insert(key.clone(), reload.definition);
+        reloaded.push(key);
+    }
+    if !reloaded.is_empty() {
+        world.send_event(Toast::info(format!("Reloaded {}", reloaded.join(", "))));
+    }
+}
+
+/// The reloaded `handle` as an `A`, `None` with a warning when `key` isn't one
+fn typed<A: Asset>(key: &str, handle: UntypedHandle) -> Option<Handle<A>> {
+    if handle.type_id() != TypeId::of::<A>() {
+        warn!(
+            "{key} is the wrong kind of asset, not a {}",
+            type_name::<A>()
+        );
+        return None;
+    }
+    Some(handle.typed::<A>())
+}
+
+/// Put the asset reloaded for `key` where the game reads it
+fn use_reloaded_asset(world: &mut World, key: &str, built: DynamicAssetType) {
+    let DynamicAssetType::Single(handle) = built else {
+        // the creature folder is only packed when loading ends
+        warn!("{key} is a collection, it's used from the next start");
+        world.send_event(Toast::warn(format!("Restart to use the new {key}")));
+        return;
+    };
+    match key {
+        "map.main" => {
+            if let Some(map) = typed::<crate::helpers::tiled::TiledMap>(key, handle) {
+                let path = map.path().map_or_else(String::new, ToString::to_string);
+                world.send_event(LoadMap { path });
+            }
+        }
+        "atlas.creatures" => {
+            let Some(sheet) = typed::<TextureAtlas>(key, handle) else {
+                return;
+            };
+            {
+                let mut game_info = world.resource_mut::<GameInfoAlt>();
+                game_info.creature_sheet = Some(sheet.clone());
+                game_info.creature_atlas = sheet;
+            }
+            world.run_system_once(sprite_atlas::select_creature_atlas);
+        }
+        "templates" => {
+            if let Some(templates) = typed(key, handle) {
+                world.resource_mut::<GameInfoAlt>().templates = templates;
+            }
+        }
+        "ui.panel" => {
+            if let (Some(panel), Some(mut ui)) =
+                (typed(key, handle), world.get_resource_mut::<UiAssets>())
+            {
+                ui.panel = panel;
+            }
+        }
+        "ui.icons" => {
+            if let (Some(icons), Some(mut ui)) =
+                (typed(key, handle), world.get_resource_mut::<UiAssets>())
+            {
+                ui.icons = icons;
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flags::GameFlags;
+    use crate::game::{configure_game_sets, KeepLevel};
+    use crate::helpers::tiled::{TiledLoader, TiledMap};
+    use crate::inventory::ItemIcons;
+    use crate::layers::LayerZConfig;
+    use crate::map;
+    use crate::map_switch::MapSwitchPlugin;
+    use crate::spawn_conditions::{DeferredSpawns, Difficulty};
+    use crate::sprite_atlas::SpriteAtlasPlugin;
+    use crate::templates::EntityTemplates;
+
+    fn assets_file(map: &str, templates: bool) -> String {
+        let templates = if templates {
+            r#""templates": File(path: "templates/creatures.templates.ron"),"#
+        } else {
+            ""
+        };
+        format!(r#"({{ "map.main": File(path: "{map}"), {templates} }})"#)
+    }
+
+    /// Update until `done`, the assets load on other threads
+    fn update_until(app: &mut App, done: impl Fn(&App) -> bool) {
+        for _ in 0..500 {
+            if done(app) {
+                return;
+            }
+            app.update();
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        panic!("gave up waiting");
+    }
+
+    fn is_in(app: &App, state: AppState) -> bool {
+        *app.world.resource::<State<AppState>>().get() == state
+    }
+
+    #[test]
+    fn changed_keys_are_reloaded_and_gone_ones_kept() {
+        let path = std::env::temp_dir().join(format!("reload_{}.assets.ron", std::process::id()));
+        std::fs::write(&path, assets_file("maps/TMX/map_test_portal_a.tmx", true)).unwrap();
+
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .add_state::<AppState>()
+            .init_resource::<KeepLevel>()
+            .init_resource::<GameFlags>()
+            .init_resource::<ItemIcons>()
+            .init_resource::<LayerZConfig>()
+            .init_resource::<Difficulty>()
+            .init_resource::<DeferredSpawns>()
+            .init_resource::<Input<KeyCode>>()
+            .init_resource::<DebugConsole>()
+            .init_resource::<DynamicAssets>()
+            .init_asset::<TiledMap>()
+            .init_asset::<Image>()
+            .init_asset::<TextureAtlas>()
+            .init_asset::<EntityTemplates>()
+            .register_asset_loader(TiledLoader)
+            .add_event::<Toast>()
+            .add_event::<ConsoleCommand>()
+            .insert_resource(AssetReloadSettings { path: path.clone() })
+            .add_plugins((MapSwitchPlugin, SpriteAtlasPlugin, AssetReloadPlugin));
+        configure_game_sets(&mut app);
+        map::add_level_systems(&mut app);
+        let asset_server = app.world.resource::<AssetServer>().clone();
+        let creature_sheet =
+            app.world
+                .resource_mut::<Assets<TextureAtlas>>()
+                .add(TextureAtlas::from_grid(
+                    Handle::default(),
+                    Vec2::splat(24.),
+                    20,
+                    27,
+                    None,
+                    None,
+                ));
+        app.world.insert_resource(GameInfoAlt {
+            creature_atlas: Handle::default(),
+            creature_sheet: Some(creature_sheet),
+            creature_folder: None,
+            tile_map: asset_server.load("maps/TMX/map_test_portal_a.tmx"),
+            templates: Handle::default(),
+        });
+
+        update_until(&mut app, |app| {
+            let game_info = app.world.resource::<GameInfoAlt>();
+            app.world
+                .resource::<Assets<TiledMap>>()
+                .contains(&game_info.tile_map)
+        });
+        app.world
+            .resource_mut::<NextState<AppState>>()
+            .set(AppState::Level);
+        app.update();
+        app.update();
+
+        std::fs::write(&path, assets_file("maps/TMX/map_test_portal_b.tmx", false)).unwrap();
+        app.world.send_event(ReloadAssetDefinitions);
+        update_until(&mut app, |app| is_in(app, AppState::SwitchingMap));
+        update_until(&mut app, |app| is_in(app, AppState::Level));
+        std::fs::remove_file(&path).unwrap();
+
+        let game_info = app.world.resource::<GameInfoAlt>();
+        assert_eq!(
+            game_info.tile_map.path().unwrap().to_string(),
+            "maps/TMX/map_test_portal_b.tmx"
+        );
+        // gone from the file, but kept
+        assert_eq!(game_info.templates, Handle::default());
+        let definitions = app.world.resource::<AssetDefinitions>();
+        assert!(definitions.0.contains_key("templates"));
+        assert!(definitions.0["map.main"].contains("map_test_portal_b"));
+    }
+}
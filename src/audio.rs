@@ -0,0 +1,442 @@
+//! Audio channels: music, ambience and sound effects, each with its own volume.
+//!
+//! Music and ambience play one looping track at a time, a new track crossfades with the one
+//! playing. Sound effects play once and may overlap. Audio files that fail to load are warned
+//! about once and not requested again.
+//!
+//! Sound effects happening somewhere in the world are played with `SpatialAudio`: they pan to
+//! the side of the view they're on and fade past its edges, following the camera while they
+//! play, see `spatial_mix`. The panning is rodio's spatial sink, with the emitter placed between
+//! the ears of a fixed listener by its pan rather than by its world position.
+
+use std::collections::HashSet;
+
+use bevy::asset::LoadState;
+use bevy::audio::{AudioSinkPlayback, SpatialListener, Volume};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use crate::camera::CurrentCameraView;
+use crate::game::GameSet;
+
+/// Screen pixels past the edge of the view over which a sound fades out, farther ones aren't
+/// played
+const SFX_FALLOFF: f32 = 480.;
+/// Quietest a sound in view gets as the camera zooms out
+const MIN_ZOOM_GAIN: f32 = 0.4;
+/// Distance between the ears of the listener the positional sounds are placed between, close
+/// enough that rodio doesn't attenuate them by distance
+const EAR_GAP: f32 = 1.;
+
+/// Plugin that plays `PlayAudio` requests on their channels and fades the tracks
+#[derive(Default)]
+pub struct AudioChannelsPlugin;
+
+impl Plugin for AudioChannelsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AudioChannels>()
+            .init_resource::<MissingAudio>()
+            .register_type::<AudioChannels>()
+            .add_event::<PlayAudio>()
+            .add_event::<StopChannel>()
+            .init_resource::<CurrentCameraView>()
+            .add_systems(Startup, spawn_sfx_listener)
+            .add_systems(
+                Update,
+                (
+                    play_audio,
+                    mix_spatial_sfx,
+                    fade_tracks,
+                    forget_missing_audio,
+                )
+                    .chain()
+                    .in_set(GameSet::RenderPrep),
+            );
+    }
+}
+
+#[derive(Reflect, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AudioChannel {
+    /// One looping track, the map's music
+    Music,
+    /// One looping track, the sound of the region the player is in
+    Ambience,
+    /// Any number of sounds played once
+    Sfx,
+}
+
+#[derive(Resource, Reflect, Debug, Clone, PartialEq)]
+#[reflect(Resource)]
+pub struct AudioChannels {
+    pub music: f32,
+    pub ambience: f32,
+    pub sfx: f32,
+    /// Seconds a music or ambience track takes to fade in or out
+    pub crossfade_secs: f32,
+    /// Part of their volume all the channels play at, lowered in the background, see
+    /// `background`
+    pub ducking: f32,
+}
+
+impl Default for AudioChannels {
+    fn default() -> Self {
+        Self {
+            music: 0.6,
+            ambience: 0.8,
+            sfx: 1.,
+            crossfade_secs: 2.,
+            ducking: 1.,
+        }
+    }
+}
+
+impl AudioChannels {
+    pub fn volume(&self, channel: AudioChannel) -> f32 {
+        let volume = match channel {
+            AudioChannel::Music => self.music,
+            AudioChannel::Ambience => self.ambience,
+            AudioChannel::Sfx => self.sfx,
+        };
+        volume * self.ducking
+    }
+}
+
+/// Send to play the audio asset at `path` on `channel`
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct PlayAudio {
+    pub channel: AudioChannel,
+    pub path: String,
+}
+
+/// Send to fade out what plays on the channel
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StopChannel(pub AudioChannel);
+
+/// A sound playing on a channel
+#[derive(Component, Debug)]
+pub struct ChannelTrack {
+    pub channel: AudioChannel,
+    pub path: String,
+    /// Part of the channel volume the track plays at, rising to 1 or falling to 0
+    fade: f32,
+    fading_out: bool,
+}
+
+/// Paths of the audio files that failed to load
+#[derive(Resource, Default, Debug)]
+pub struct MissingAudio(HashSet<String>);
+
+/// A sound effect heard from `world_pos`, panned and attenuated as the camera moves
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct SpatialSfx {
+    pub world_pos: Vec2,
+    /// Volume before the channel volume and the attenuation
+    pub volume: f32,
+}
+
+/// How a positional sound is heard
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpatialMix {
+    /// From -1, all left, to 1, all right
+    pub pan: f32,
+    /// Part of its volume the sound plays at
+    pub gain: f32,
+}
+
+/// How a sound at `world_pos` is heard with the camera showing `view` at `scale`, `None` when
+/// it's too far out of view to be heard
+///
+/// The pan follows the horizontal offset from the center of the view, all to one side at its
+/// edge. Out of view the sound fades over `SFX_FALLOFF` screen pixels, so the same spot sounds
+/// farther zoomed in, and in view it gets quieter as the camera zooms out.
+pub fn spatial_mix(view: Rect, scale: f32, world_pos: Vec2) -> Option<SpatialMix> {
+    if view.is_empty() || scale <= 0. {
+        // no camera yet
+        return Some(SpatialMix { pan: 0., gain: 1. });
+    }
+    let pan = ((world_pos.x - view.center().x) / view.half_size().x).clamp(-1., 1.);
+    let outside = world_pos.distance(world_pos.clamp(view.min, view.max)) / scale;
+    if outside >= SFX_FALLOFF {
+        return None;
+    }
+    let zoom_gain = (1. / scale).clamp(MIN_ZOOM_GAIN, 1.);
+    Some(SpatialMix {
+        pan,
+        gain: (1. - outside / SFX_FALLOFF) * zoom_gain,
+    })
+}
+
+/// Where the emitter of a sound panned `pan` goes, between the ears of the listener
+fn emitter_position(pan: f32) -> Vec3 {
+    Vec3::new(pan * EAR_GAP / 2., 0., 0.)
+}
+
+/// Plays sound effects from places in the world
+#[derive(SystemParam)]
+pub struct SpatialAudio<'w, 's> {
+    commands: Commands<'w, 's>,
+    view: Res<'w, CurrentCameraView>,
+    channels: Res<'w, AudioChannels>,
+    missing: Res<'w, MissingAudio>,
+}
+
+impl SpatialAudio<'_, '_> {
+    /// Play the sound effect `handle` at `volume` as heard from `world_pos`, not at all when
+    /// it's too far out of view
+    pub fn play_spatial_sfx(&mut self, world_pos: Vec2, handle: Handle<AudioSource>, volume: f32) {
+        let path = handle
+            .path()
+            .map_or_else(String::new, |path| path.to_string());
+        if self.missing.0.contains(&path) {
+            return;
+        }
+        let Some(mix) = spatial_mix(self.view.view, self.view.scale, world_pos) else {
+            return;
+        };
+        self.commands.spawn((
+            AudioBundle {
+                source: handle,
+                settings: PlaybackSettings::DESPAWN
+                    .with_volume(Volume::new_relative(
+                        self.channels.volume(AudioChannel::Sfx) * volume * mix.gain,
+                    ))
+                    .with_spatial(true),
+            },
+            TransformBundle::from_transform(Transform::from_translation(emitter_position(mix.pan))),
+            SpatialSfx { world_pos, volume },
+            ChannelTrack {
+                channel: AudioChannel::Sfx,
+                path,
+                fade: 1.,
+                fading_out: false,
+            },
+        ));
+    }
+}
+
+fn spawn_sfx_listener(mut commands: Commands) {
+    commands.spawn((
+        SpatialListener::new(EAR_GAP),
+        TransformBundle::default(),
+        Name::new("Sfx listener"),
+    ));
+}
+
+fn play_audio(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    channels: Res<AudioChannels>,
+    missing: Res<MissingAudio>,
+    mut play: EventReader<PlayAudio>,
+    mut stop: EventReader<StopChannel>,
+    mut tracks: Query<&mut ChannelTrack>,
+) {
+    for StopChannel(channel) in stop.read() {
+        for mut track in &mut tracks {
+            if track.channel == *channel {
+                track.fading_out = true;
+            }
+        }
+    }
+
+    for request in play.read() {
+        if request.path.is_empty() || missing.0.contains(&request.path) {
+            continue;
+        }
+        let source = asset_server.load(request.path.clone());
+        if request.channel == AudioChannel::Sfx {
+            commands.spawn((
+                AudioBundle {
+                    source,
+                    settings: PlaybackSettings::DESPAWN
+                        .with_volume(Volume::new_relative(channels.volume(AudioChannel::Sfx))),
+                },
+                ChannelTrack {
+                    channel: request.channel,
+                    path: request.path.clone(),
+                    fade: 1.,
+                    fading_out: false,
+                },
+            ));
+            continue;
+        }
+
+        let mut already_playing = false;
+        for mut track in &mut tracks {
+            if track.channel != request.channel {
+                continue;
+            }
+            if track.path == request.path && !track.fading_out {
+                already_playing = true;
+            } else {
+                track.fading_out = true;
+            }
+        }
+        if already_playing {
+            continue;
+        }
+        commands.spawn((
+            AudioBundle {
+                source,
+                settings: PlaybackSettings::LOOP.with_volume(Volume::new_relative(0.)),
+            },
+            ChannelTrack {
+                channel: request.channel,
+                path: request.path.clone(),
+                fade: 0.,
+                fading_out: false,
+            },
+            Name::new(format!("{:?} {}", request.channel, request.path)),
+        ));
+    }
+}
+
+/// Pan and attenuate the positional sounds still playing as the camera moves
+fn mix_spatial_sfx(
+    view: Res<CurrentCameraView>,
+    channels: Res<AudioChannels>,
+    mut sounds: Query<(&SpatialSfx, &mut Transform, Option<&SpatialAudioSink>)>,
+) {
+    if !view.is_changed() && !channels.is_changed() {
+        return;
+    }
+    for (sfx, mut transform, sink) in &mut sounds {
+        // gone out of hearing, but left to finish
+        let mix = spatial_mix(view.view, view.scale, sfx.world_pos)
+            .unwrap_or(SpatialMix { pan: 0., gain: 0. });
+        let emitter = emitter_position(mix.pan);
+        if transform.translation != emitter {
+            transform.translation = emitter;
+        }
+        if let Some(sink) = sink {
+            sink.set_volume(channels.volume(AudioChannel::Sfx) * sfx.volume * mix.gain);
+        }
+    }
+}
+
+fn fade_tracks(
+    mut commands: Commands,
+    time: Res<Time>,
+    channels: Res<AudioChannels>,
+    mut tracks: Query<(Entity, &mut ChannelTrack, Option<&AudioSink>)>,
+) {
+    let step = if channels.crossfade_secs > 0. {
+        time.delta_seconds() / channels.crossfade_secs
+    } else {
+        1.
+    };
+    for (entity, mut track, sink) in &mut tracks {
+        if track.fading_out {
+            track.fade = (track.fade - step).max(0.);
+            if track.fade <= 0. {
+                commands.entity(entity).despawn_recursive();
+                continue;
+            }
+        } else {
+            track.fade = (track.fade + step).min(1.);
+        }
+        if let Some(sink) = sink {
+            sink.set_volume(channels.volume(track.channel) * track.fade);
+        }
+    }
+}
+
+fn forget_missing_audio(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    tracks: Query<(Entity, &ChannelTrack, &Handle<AudioSource>)>,
+    mut missing: ResMut<MissingAudio>,
+) {
+    for (entity, track, handle) in &tracks {
+        if asset_server.get_load_state(handle) != Some(LoadState::Failed) {
+            continue;
+        }
+        if missing.0.insert(track.path.clone()) {
+            warn!("can't load audio {}", track.path);
+        }
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+
+    fn track(channel: AudioChannel, path: &str, fade: f32, fading_out: bool) -> ChannelTrack {
+        ChannelTrack {
+            channel,
+            path: path.to_string(),
+            fade,
+            fading_out,
+        }
+    }
+
+    #[test]
+    fn sounds_pan_and_fade_with_the_view() {
+        let view = Rect::new(0., 0., 800., 600.);
+        let mix = |scale, x, y| spatial_mix(view, scale, Vec2::new(x, y));
+        assert_eq!(mix(1., 400., 300.), Some(SpatialMix { pan: 0., gain: 1. }));
+        assert_eq!(mix(1., 0., 300.), Some(SpatialMix { pan: -1., gain: 1. }));
+        assert_eq!(mix(1., 600., 0.), Some(SpatialMix { pan: 0.5, gain: 1. }));
+        // past the right edge, all right and fading
+        assert_eq!(
+            mix(1., 800. + SFX_FALLOFF / 2., 300.),
+            Some(SpatialMix { pan: 1., gain: 0.5 })
+        );
+        assert_eq!(mix(1., 400., 600. + SFX_FALLOFF), None);
+
+        // zoomed in, the same spot past the edge is farther
+        assert_eq!(mix(0.5, 800. + SFX_FALLOFF / 2., 300.), None);
+        assert_eq!(
+            mix(0.5, 800. + SFX_FALLOFF / 4., 300.),
+            Some(SpatialMix { pan: 1., gain: 0.5 })
+        );
+        // zoomed out, quieter in view
+        assert_eq!(mix(2., 400., 300.), Some(SpatialMix { pan: 0., gain: 0.5 }));
+        assert_eq!(mix(10., 400., 300.).unwrap().gain, MIN_ZOOM_GAIN);
+
+        // no camera yet
+        assert_eq!(
+            spatial_mix(Rect::default(), 0., Vec2::new(5000., 0.)),
+            Some(SpatialMix { pan: 0., gain: 1. })
+        );
+    }
+
+    #[test]
+    fn tracks_crossfade_and_the_old_one_goes() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            // the virtual time clock caps its steps at a quarter second
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                250,
+            )))
+            .insert_resource(AudioChannels {
+                crossfade_secs: 0.5,
+                ..default()
+            })
+            .add_systems(Update, fade_tracks);
+        let old = app
+            .world
+            .spawn(track(AudioChannel::Music, "old.ogg", 1., true))
+            .id();
+        let new = app
+            .world
+            .spawn(track(AudioChannel::Music, "new.ogg", 0., false))
+            .id();
+
+        // the first update has no time delta
+        app.update();
+        app.update();
+        assert_eq!(app.world.get::<ChannelTrack>(old).unwrap().fade, 0.5);
+        assert_eq!(app.world.get::<ChannelTrack>(new).unwrap().fade, 0.5);
+
+        app.update();
+        app.update();
+        assert!(app.world.get_entity(old).is_none());
+        assert_eq!(app.world.get::<ChannelTrack>(new).unwrap().fade, 1.);
+    }
+}
@@ -0,0 +1,256 @@
+//! Fading the overlays out once the player leaves the game alone, for clean screenshots and
+//! streams.
+//!
+//! `InputActivity` counts the seconds since the last key, mouse or gamepad input. After
+//! `IDLE_SECS` of it, each overlay with an `AutoHide` fades out over its own `fade_secs`, at once
+//! when the motion preferences ask for no animations. Any input brings them back at once. The
+//! overlays are drawn with egui, so the `AutoHide` entities stand in for them: the drawing
+//! systems look their alpha up with `overlay_alpha`. The inspector has none and always shows.
+
+use bevy::input::gamepad::GamepadEvent;
+use bevy::input::keyboard::KeyboardInput;
+use bevy::input::mouse::{MouseButtonInput, MouseMotion, MouseWheel};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::accessibility::{MotionPreferences, Preferences};
+use crate::game::GameSet;
+
+/// Seconds without input before the overlays fade out
+pub const IDLE_SECS: f32 = 10.;
+
+/// Plugin that tracks the `InputActivity` and fades the `AutoHide` overlays
+#[derive(Default)]
+pub struct AutoHidePlugin;
+
+impl Plugin for AutoHidePlugin {
+    fn build(&self, app: &mut App) {
+        let settings = app.world.resource::<Preferences>().auto_hide;
+        app.insert_resource(settings)
+            .init_resource::<InputActivity>()
+            .register_type::<AutoHide>()
+            .register_type::<AutoHideSettings>()
+            .add_systems(Startup, spawn_overlay_roots)
+            .add_systems(
+                Update,
+                (track_input_activity, fade_overlays)
+                    .chain()
+                    .in_set(GameSet::Input),
+            );
+    }
+}
+
+/// Whether the overlays hide while the game is left alone
+#[derive(Resource, Reflect, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[reflect(Resource)]
+#[serde(default)]
+pub struct AutoHideSettings {
+    pub enabled: bool,
+}
+
+impl Default for AutoHideSettings {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+/// Seconds since the last input of any kind
+#[derive(Resource, Debug, Default)]
+pub struct InputActivity {
+    pub idle_secs: f32,
+}
+
+impl InputActivity {
+    pub fn is_idle(&self) -> bool {
+        self.idle_secs >= IDLE_SECS
+    }
+}
+
+/// The overlays that hide when idle
+#[derive(Reflect, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overlay {
+    StatusBar,
+    Toasts,
+    Perf,
+}
+
+/// Fades an overlay out while the game is idle
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq)]
+pub struct AutoHide {
+    pub overlay: Overlay,
+    /// Seconds the fade out takes
+    pub fade_secs: f32,
+    /// Opacity the overlay is drawn with, from 0 to 1
+    pub alpha: f32,
+}
+
+impl AutoHide {
+    pub fn new(overlay: Overlay, fade_secs: f32) -> Self {
+        Self {
+            overlay,
+            fade_secs,
+            alpha: 1.,
+        }
+    }
+}
+
+/// Opacity to draw `overlay` with, fully opaque when it doesn't hide
+pub fn overlay_alpha(overlays: &Query<&AutoHide>, overlay: Overlay) -> f32 {
+    overlays
+        .iter()
+        .find(|auto_hide| auto_hide.overlay == overlay)
+        .map_or(1., |auto_hide| auto_hide.alpha)
+}
+
+/// Multiply the colors of `visuals` by `alpha`, for everything drawn with them to fade
+pub fn fade_visuals(visuals: &mut egui::Visuals, alpha: f32) {
+    visuals.override_text_color = Some(visuals.text_color().gamma_multiply(alpha));
+    let widgets = &mut visuals.widgets;
+    for widget in [
+        &mut widgets.noninteractive,
+        &mut widgets.inactive,
+        &mut widgets.hovered,
+        &mut widgets.active,
+        &mut widgets.open,
+    ] {
+        widget.bg_fill = widget.bg_fill.gamma_multiply(alpha);
+        widget.weak_bg_fill = widget.weak_bg_fill.gamma_multiply(alpha);
+        widget.bg_stroke.color = widget.bg_stroke.color.gamma_multiply(alpha);
+        widget.fg_stroke.color = widget.fg_stroke.color.gamma_multiply(alpha);
+    }
+    visuals.window_fill = visuals.window_fill.gamma_multiply(alpha);
+    visuals.window_stroke.color = visuals.window_stroke.color.gamma_multiply(alpha);
+    visuals.window_shadow.color = visuals.window_shadow.color.gamma_multiply(alpha);
+    visuals.popup_shadow.color = visuals.popup_shadow.color.gamma_multiply(alpha);
+    visuals.panel_fill = visuals.panel_fill.gamma_multiply(alpha);
+    visuals.extreme_bg_color = visuals.extreme_bg_color.gamma_multiply(alpha);
+    visuals.faint_bg_color = visuals.faint_bg_color.gamma_multiply(alpha);
+}
+
+fn spawn_overlay_roots(mut commands: Commands) {
+    commands.spawn((
+        AutoHide::new(Overlay::StatusBar, 1.),
+        Name::new("status bar overlay"),
+    ));
+    commands.spawn((
+        AutoHide::new(Overlay::Toasts, 0.5),
+        Name::new("toasts overlay"),
+    ));
+    commands.spawn((AutoHide::new(Overlay::Perf, 1.), Name::new("perf overlay")));
+}
+
+#[allow(clippy::too_many_arguments)]
+fn track_input_activity(
+    time: Res<Time>,
+    mut activity: ResMut<InputActivity>,
+    mut keys: EventReader<KeyboardInput>,
+    mut buttons: EventReader<MouseButtonInput>,
+    mut motion: EventReader<MouseMotion>,
+    mut wheel: EventReader<MouseWheel>,
+    mut gamepad: EventReader<GamepadEvent>,
+) {
+    // read them all, so none is left over for the next frame
+    let input = keys.read().count()
+        + buttons.read().count()
+        + motion.read().count()
+        + wheel.read().count()
+        + gamepad.read().count();
+    if input > 0 {
+        activity.idle_secs = 0.;
+    } else {
+        activity.idle_secs += time.delta_seconds();
+    }
+}
+
+fn fade_overlays(
+    time: Res<Time>,
+    activity: Res<InputActivity>,
+    settings: Res<AutoHideSettings>,
+    motion: Res<MotionPreferences>,
+    mut overlays: Query<&mut AutoHide>,
+) {
+    let hidden = settings.enabled && activity.is_idle();
+    for mut auto_hide in &mut overlays {
+        let alpha = if !hidden {
+            1.
+        } else if motion.reduce_motion || auto_hide.fade_secs <= 0. {
+            0.
+        } else {
+            (auto_hide.alpha - time.delta_seconds() / auto_hide.fade_secs).max(0.)
+        };
+        // only touch the component when it changes
+        if auto_hide.alpha != alpha {
+            auto_hide.alpha = alpha;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+
+    fn test_app() -> (App, Entity) {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                100,
+            )))
+            .init_resource::<InputActivity>()
+            .init_resource::<AutoHideSettings>()
+            .init_resource::<MotionPreferences>()
+            .add_event::<KeyboardInput>()
+            .add_event::<MouseButtonInput>()
+            .add_event::<MouseMotion>()
+            .add_event::<MouseWheel>()
+            .add_event::<GamepadEvent>()
+            .add_systems(Update, (track_input_activity, fade_overlays).chain());
+        let overlay = app.world.spawn(AutoHide::new(Overlay::StatusBar, 0.5)).id();
+        // the first frame has no delta
+        app.update();
+        (app, overlay)
+    }
+
+    fn alpha(app: &App, overlay: Entity) -> f32 {
+        app.world.get::<AutoHide>(overlay).unwrap().alpha
+    }
+
+    #[test]
+    fn overlays_fade_when_idle_and_come_back_on_input() {
+        let (mut app, overlay) = test_app();
+        app.update();
+        assert_eq!(alpha(&app, overlay), 1.);
+
+        app.world.resource_mut::<InputActivity>().idle_secs = IDLE_SECS;
+        app.update();
+        let fading = alpha(&app, overlay);
+        assert!(fading > 0. && fading < 1., "{fading}");
+        for _ in 0..5 {
+            app.update();
+        }
+        assert_eq!(alpha(&app, overlay), 0.);
+
+        app.world.send_event(MouseMotion {
+            delta: Vec2::new(3., 0.),
+        });
+        app.update();
+        assert_eq!(app.world.resource::<InputActivity>().idle_secs, 0.);
+        assert_eq!(alpha(&app, overlay), 1.);
+    }
+
+    #[test]
+    fn reduced_motion_snaps_and_the_setting_keeps_them_shown() {
+        let (mut app, overlay) = test_app();
+        app.world.resource_mut::<MotionPreferences>().reduce_motion = true;
+        app.world.resource_mut::<InputActivity>().idle_secs = IDLE_SECS;
+        app.update();
+        assert_eq!(alpha(&app, overlay), 0.);
+
+        app.world.resource_mut::<AutoHideSettings>().enabled = false;
+        app.update();
+        assert_eq!(alpha(&app, overlay), 1.);
+    }
+}
@@ -0,0 +1,329 @@
+//! Sparing the CPU and GPU while the window is in the background.
+//!
+//! When the primary window loses focus, which it does when minimized, winit's unfocused update
+//! mode wakes the app at most `BackgroundThrottle::max_fps` times a second, and the systems with
+//! the `not_throttled` run condition (the animations, the ambient life and the weather) stop
+//! until it's back. The loading states are exempt: the app keeps running at full speed so the
+//! assets finish loading behind other windows. The audio keeps playing, ducks or pauses, per
+//! `BackgroundThrottle::audio`.
+//!
+//! Virtual time never advances by more than `MAX_FRAME_DELTA` in a frame, so a frame after a
+//! long stall, like coming back to the window, doesn't fire every timer at once.
+
+use std::time::Duration;
+
+use bevy::audio::AudioSinkPlayback;
+use bevy::prelude::*;
+use bevy::winit::{UpdateMode, WinitSettings};
+use bevy_window::{PrimaryWindow, WindowFocused};
+use serde::{Deserialize, Serialize};
+
+use crate::accessibility::Preferences;
+use crate::audio::AudioChannels;
+use crate::game::GameSet;
+use crate::state::AppState;
+
+/// Longest virtual time step a frame can take
+pub const MAX_FRAME_DELTA: Duration = Duration::from_millis(100);
+/// Part of their volume the channels play at while ducked
+const DUCKED_VOLUME: f32 = 0.25;
+
+/// Plugin that throttles the app while its window is in the background and clamps the frame delta
+#[derive(Default)]
+pub struct BackgroundThrottlePlugin;
+
+impl Plugin for BackgroundThrottlePlugin {
+    fn build(&self, app: &mut App) {
+        let throttle = app.world.resource::<Preferences>().background;
+        app.insert_resource(throttle)
+            .init_resource::<InBackground>()
+            .register_type::<BackgroundThrottle>()
+            .add_systems(Startup, clamp_frame_delta)
+            .add_systems(
+                Update,
+                (
+                    track_window_focus,
+                    apply_update_mode,
+                    apply_background_audio,
+                )
+                    .chain()
+                    .in_set(GameSet::Input),
+            );
+    }
+}
+
+/// What the background audio does
+#[derive(Reflect, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackgroundAudio {
+    /// Keep playing as is
+    Keep,
+    /// Play quieter
+    #[default]
+    Duck,
+    /// Pause until the window is back
+    Pause,
+}
+
+impl BackgroundAudio {
+    pub const ALL: [BackgroundAudio; 3] = [
+        BackgroundAudio::Keep,
+        BackgroundAudio::Duck,
+        BackgroundAudio::Pause,
+    ];
+
+    /// Localization key of the option's name
+    pub fn key(self) -> &'static str {
+        match self {
+            BackgroundAudio::Keep => "background_audio.keep",
+            BackgroundAudio::Duck => "background_audio.duck",
+            BackgroundAudio::Pause => "background_audio.pause",
+        }
+    }
+}
+
+/// How the app runs while its window is in the background
+#[derive(Resource, Reflect, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[reflect(Resource)]
+#[serde(default)]
+pub struct BackgroundThrottle {
+    pub enabled: bool,
+    /// Most updates a second in the background
+    pub max_fps: f32,
+    pub audio: BackgroundAudio,
+}
+
+impl Default for BackgroundThrottle {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_fps: 10.,
+            audio: BackgroundAudio::Duck,
+        }
+    }
+}
+
+impl BackgroundThrottle {
+    /// The update mode of an app throttled to `max_fps`
+    pub fn update_mode(&self) -> UpdateMode {
+        UpdateMode::ReactiveLowPower {
+            wait: Duration::from_secs_f32(1. / self.max_fps.max(1.)),
+        }
+    }
+}
+
+/// Whether the primary window has lost focus
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct InBackground(pub bool);
+
+/// Run condition for the systems that stop while the window is in the background
+pub fn not_throttled(throttle: Res<BackgroundThrottle>, background: Res<InBackground>) -> bool {
+    !(throttle.enabled && background.0)
+}
+
+fn clamp_frame_delta(mut virtual_time: ResMut<Time<Virtual>>) {
+    virtual_time.set_max_delta(MAX_FRAME_DELTA);
+}
+
+fn track_window_focus(
+    mut focus_events: EventReader<WindowFocused>,
+    primary: Query<(), With<PrimaryWindow>>,
+    mut background: ResMut<InBackground>,
+) {
+    for event in focus_events.read() {
+        if primary.contains(event.window) {
+            background.set_if_neq(InBackground(!event.focused));
+        }
+    }
+}
+
+/// Throttle the unfocused updates, unless assets are loading
+fn apply_update_mode(
+    throttle: Res<BackgroundThrottle>,
+    state: Res<State<AppState>>,
+    winit_settings: Option<ResMut<WinitSettings>>,
+) {
+    let Some(mut winit_settings) = winit_settings else {
+        return;
+    };
+    let loading = matches!(state.get(), AppState::Loading | AppState::SwitchingMap);
+    let unfocused_mode = if throttle.enabled && !loading {
+        throttle.update_mode()
+    } else {
+        UpdateMode::Continuous
+    };
+    if !same_update_mode(&winit_settings.unfocused_mode, &unfocused_mode) {
+        winit_settings.unfocused_mode = unfocused_mode;
+    }
+}
+
+/// Whether `a` and `b` are the same mode, `UpdateMode` isn't `PartialEq`
+fn same_update_mode(a: &UpdateMode, b: &UpdateMode) -> bool {
+    match (a, b) {
+        (UpdateMode::Continuous, UpdateMode::Continuous) => true,
+        (UpdateMode::Reactive { wait: a }, UpdateMode::Reactive { wait: b })
+        | (UpdateMode::ReactiveLowPower { wait: a }, UpdateMode::ReactiveLowPower { wait: b }) => {
+            a == b
+        }
+        _ => false,
+    }
+}
+
+/// Pauses the playing sinks while `BackgroundAudio::Pause` applies, and plays again only the ones
+/// it paused, so the sinks other code paused stay paused.
+fn apply_background_audio(
+    throttle: Res<BackgroundThrottle>,
+    background: Res<InBackground>,
+    mut channels: ResMut<AudioChannels>,
+    mut paused_here: Local<Vec<Entity>>,
+    sinks: Query<(Entity, &AudioSink)>,
+    spatial_sinks: Query<(Entity, &SpatialAudioSink)>,
+) {
+    if !throttle.is_changed() && !background.is_changed() {
+        return;
+    }
+    let audio = if throttle.enabled && background.0 {
+        throttle.audio
+    } else {
+        BackgroundAudio::Keep
+    };
+    let ducking = if audio == BackgroundAudio::Duck {
+        DUCKED_VOLUME
+    } else {
+        1.
+    };
+    if channels.ducking != ducking {
+        channels.ducking = ducking;
+    }
+    if audio == BackgroundAudio::Pause {
+        pause_playing(&sinks, &mut paused_here);
+        pause_playing(&spatial_sinks, &mut paused_here);
+    } else {
+        for entity in paused_here.drain(..) {
+            if let Ok((_, sink)) = sinks.get(entity) {
+                sink.play();
+            } else if let Ok((_, sink)) = spatial_sinks.get(entity) {
+                sink.play();
+            }
+        }
+    }
+}
+
+/// Pauses the sinks still playing and records them in `paused`
+fn pause_playing<S: Component + AudioSinkPlayback>(
+    sinks: &Query<(Entity, &S)>,
+    paused: &mut Vec<Entity>,
+) {
+    for (entity, sink) in sinks {
+        if !sink.is_paused() {
+            sink.pause();
+            paused.push(entity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+
+    /// Deltas seen by a gameplay system, one per frame it ran
+    #[derive(Resource, Default)]
+    struct Deltas(Vec<Duration>);
+
+    fn record_delta(time: Res<Time>, mut deltas: ResMut<Deltas>) {
+        deltas.0.push(time.delta());
+    }
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_state::<AppState>()
+            .init_resource::<AudioChannels>()
+            .init_resource::<Deltas>()
+            .insert_resource(BackgroundThrottle::default())
+            .init_resource::<InBackground>()
+            .add_event::<WindowFocused>()
+            .init_resource::<WinitSettings>()
+            .add_systems(Startup, clamp_frame_delta)
+            .add_systems(
+                Update,
+                (
+                    (
+                        track_window_focus,
+                        apply_update_mode,
+                        apply_background_audio,
+                    )
+                        .chain(),
+                    record_delta.run_if(not_throttled),
+                )
+                    .chain(),
+            );
+        app
+    }
+
+    #[test]
+    fn a_long_stall_advances_by_the_max_delta() {
+        let mut app = test_app();
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            20,
+        )));
+        // the first frame has no delta
+        app.update();
+        app.update();
+        // coming back after half a minute away
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs(30)));
+        app.update();
+        assert_eq!(
+            app.world.resource::<Deltas>().0[1..],
+            [Duration::from_millis(20), MAX_FRAME_DELTA]
+        );
+        assert_eq!(
+            app.world.resource::<Time<Real>>().delta(),
+            Duration::from_secs(30)
+        );
+    }
+
+    #[test]
+    fn losing_focus_throttles_and_ducks_until_it_comes_back() {
+        let mut app = test_app();
+        let window = app.world.spawn((Window::default(), PrimaryWindow)).id();
+        app.world.insert_resource(NextState(Some(AppState::Level)));
+        app.update();
+        assert!(same_update_mode(
+            &app.world.resource::<WinitSettings>().unfocused_mode,
+            &BackgroundThrottle::default().update_mode()
+        ));
+        let ran = app.world.resource::<Deltas>().0.len();
+
+        app.world.send_event(WindowFocused {
+            window,
+            focused: false,
+        });
+        app.update();
+        assert_eq!(app.world.resource::<Deltas>().0.len(), ran);
+        assert_eq!(app.world.resource::<AudioChannels>().ducking, DUCKED_VOLUME);
+
+        app.world.send_event(WindowFocused {
+            window,
+            focused: true,
+        });
+        app.update();
+        assert_eq!(app.world.resource::<Deltas>().0.len(), ran + 1);
+        assert_eq!(app.world.resource::<AudioChannels>().ducking, 1.);
+    }
+
+    #[test]
+    fn loading_runs_at_full_speed_in_the_background() {
+        let mut app = test_app();
+        app.world.insert_resource(NextState(Some(AppState::Level)));
+        app.update();
+        app.world
+            .insert_resource(NextState(Some(AppState::SwitchingMap)));
+        app.update();
+        assert!(same_update_mode(
+            &app.world.resource::<WinitSettings>().unfocused_mode,
+            &UpdateMode::Continuous
+        ));
+    }
+}
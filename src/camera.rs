@@ -1,21 +1,23 @@
-use crate::state::AppState;
+use crate::state::{level_ready, AppState};
 use bevy::{
-    input::mouse::{MouseScrollUnit, MouseWheel},
+    input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
     math::vec2,
     prelude::*,
     render::camera::CameraProjection,
-    window::PrimaryWindow,
+    window::{CursorGrabMode, PrimaryWindow},
 };
-use bevy_ecs_tilemap::prelude::*;
 // use bevy_ecs_tilemap::tiles::TilePos;
 // use bevy_ecs_tilemap::map::TilemapTileSize;
-use bevy::core_pipeline::tonemapping::{DebandDither, Tonemapping};
-use bevy::render::camera::CameraRenderGraph;
+use bevy::render::camera::CameraUpdateSystem;
 use bevy::render::primitives::Frustum;
-use bevy::render::view::VisibleEntities;
-use bevy_ecs_tilemap::map::TilemapType;
 use bevy_inspector_egui::*;
-use crate::{GameInfoAlt, helpers};
+use crate::accessibility::MotionPreferences;
+use crate::camera_rig::CameraRig;
+use crate::coords::{self, MapFrame};
+use crate::cursor::CursorWorldCache;
+use crate::game::{GameSet, LevelLifecycleSet};
+use crate::helpers::tiled::TiledMap;
+use serde::{Deserialize, Serialize};
 
 /// Plugin that adds the necessary systems for `PanCam` components to work
 #[derive(Default)]
@@ -31,66 +33,351 @@ pub struct MainCamera;
 
 impl Plugin for PanCamPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(AppState::Level), camera_spawn)
+        app.init_resource::<CameraMode>()
+            .init_resource::<CurrentCameraView>()
+            .add_systems(
+                OnEnter(AppState::Level),
+                camera_spawn.in_set(LevelLifecycleSet),
+            )
+            .add_systems(OnEnter(AppState::Level), set_camera_mode(CameraMode::Interactive))
+            .add_systems(
+                Update,
+                camera_menu_drift
+                    .in_set(GameSet::Camera)
+                    .run_if(resource_equals(CameraMode::MenuDrift))
+                    .run_if(crate::accessibility::motion_allowed),
+            )
+            .add_systems(
+                Update,
+                update_camera_bounds
+                    .in_set(GameSet::Camera)
+                    .before(PanCamSystemSet)
+                    .run_if(level_ready),
+            )
             .add_systems(
                 Update,
                 (camera_movement, camera_zoom)
                     .in_set(PanCamSystemSet)
-                    .run_if(in_state(AppState::Level)),
+                    .run_if(level_ready),
+            )
+            // outside `PanCamSystemSet`, egui taking the pointer mid-zoom mustn't leave the view
+            // stretched
+            .add_systems(
+                Update,
+                settle_elastic_zoom
+                    .in_set(GameSet::Camera)
+                    .after(PanCamSystemSet)
+                    .run_if(level_ready),
             )
+            .init_resource::<LastZoomInput>()
+            .add_systems(
+                PostUpdate,
+                send_view_changed
+                    .after(bevy::transform::TransformSystem::TransformPropagate)
+                    .after(CameraUpdateSystem),
+            )
+            .init_resource::<Panning>()
+            .add_event::<WorldClick>()
+            .add_event::<CameraViewChanged>()
             .register_type::<PanCam>();
 
         //#[cfg(feature = "bevy_egui")]
         {
             app.init_resource::<EguiWantsFocus>()
-                .add_systems(PostUpdate, check_egui_wants_focus)
+                // egui has the pointer of this frame and the areas of the last one once its
+                // frame began, deciding then leaves no stale frame for `PanCamSystemSet`
+                .add_systems(
+                    PreUpdate,
+                    check_egui_wants_focus.after(bevy_egui::EguiSet::BeginFrame),
+                )
                 .configure_sets(
                     Update,
                     PanCamSystemSet
                         .run_if(resource_equals(EguiWantsFocus(false)))
-                        .run_if(in_state(AppState::Level)),
+                        .run_if(resource_equals(CameraMode::Interactive))
+                        .run_if(level_ready),
                 );
         }
     }
 }
 
+/// Sent when the main camera moved or zoomed, with what it now shows
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct CameraViewChanged {
+    /// World-space rectangle the camera shows
+    pub view: Rect,
+    /// Scale of the orthographic projection, larger is zoomed out
+    pub scale: f32,
+}
+
+/// What the main camera shows now, kept up to date with `CameraViewChanged`, empty before the
+/// camera is spawned
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Default)]
+pub struct CurrentCameraView {
+    /// World-space rectangle the camera shows
+    pub view: Rect,
+    /// Scale of the orthographic projection, larger is zoomed out
+    pub scale: f32,
+}
+
+#[allow(clippy::type_complexity)]
+fn send_view_changed(
+    cameras: Query<
+        (&OrthographicProjection, &GlobalTransform),
+        (
+            With<MainCamera>,
+            Or<(Changed<OrthographicProjection>, Changed<GlobalTransform>)>,
+        ),
+    >,
+    mut changed: EventWriter<CameraViewChanged>,
+    mut current: ResMut<CurrentCameraView>,
+) {
+    for (projection, transform) in &cameras {
+        let area = projection.area;
+        let center = transform.translation().truncate();
+        let view = Rect::from_corners(center + area.min, center + area.max);
+        changed.send(CameraViewChanged {
+            view,
+            scale: projection.scale,
+        });
+        *current = CurrentCameraView {
+            view,
+            scale: projection.scale,
+        };
+    }
+}
+
+/// How the camera behaves in the current app state
+///
+/// Every mode leaves the camera transform where it is, so switching modes never snaps the view.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CameraMode {
+    /// Panned and zoomed by the player
+    #[default]
+    Interactive,
+    /// Slowly drifts across the map on its own, for menus shown over the map
+    MenuDrift,
+    /// Doesn't move at all
+    Frozen,
+}
+
+/// System for `OnEnter` schedules that switches the camera to `mode`
+pub fn set_camera_mode(mode: CameraMode) -> impl Fn(ResMut<CameraMode>) {
+    move |mut camera_mode| *camera_mode = mode
+}
+
+/// Drift speed in world units per second, at scale 1
+const DRIFT_SPEED: Vec2 = Vec2::new(12., 7.);
+
+/// Current drift direction of each axis, flipped at the bounds
+#[derive(Deref, DerefMut)]
+struct DriftDirection(Vec2);
+
+impl Default for DriftDirection {
+    fn default() -> Self {
+        Self(Vec2::ONE)
+    }
+}
+
+/// Move `pos` along `direction`, reversing each axis that reaches the edge of `min..=max`
+fn drift_step(pos: Vec2, direction: Vec2, delta: Vec2, min: Vec2, max: Vec2) -> (Vec2, Vec2) {
+    let mut pos = pos + direction * delta;
+    let mut direction = direction;
+    for axis in 0..2 {
+        // a view wider than the bounds has nowhere to drift on that axis
+        if min[axis] >= max[axis] {
+            pos[axis] = (min[axis] + max[axis]) / 2.;
+        } else if pos[axis] <= min[axis] {
+            pos[axis] = min[axis];
+            direction[axis] = direction[axis].abs();
+        } else if pos[axis] >= max[axis] {
+            pos[axis] = max[axis];
+            direction[axis] = -direction[axis].abs();
+        }
+    }
+    (pos, direction)
+}
+
+fn camera_menu_drift(
+    time: Res<Time>,
+    mut query: Query<(&PanCam, &mut Transform, &OrthographicProjection)>,
+    mut direction: Local<DriftDirection>,
+) {
+    for (cam, mut transform, projection) in &mut query {
+        let half_view = projection.area.size() / 2.;
+        let min = Vec2::new(
+            cam.min_x.unwrap_or(f32::MIN) + half_view.x,
+            cam.min_y.unwrap_or(f32::MIN) + half_view.y,
+        );
+        let max = Vec2::new(
+            cam.max_x.unwrap_or(f32::MAX) - half_view.x,
+            cam.max_y.unwrap_or(f32::MAX) - half_view.y,
+        );
+        let delta = DRIFT_SPEED * projection.scale * time.delta_seconds();
+        let (pos, new_direction) =
+            drift_step(transform.translation.truncate(), **direction, delta, min, max);
+        transform.translation = pos.extend(transform.translation.z);
+        **direction = new_direction;
+    }
+}
+
+/// Whether egui has the pointer or the keyboard of the game window, which the world then leaves
+/// alone
 #[derive(Resource, Deref, DerefMut, PartialEq, Eq, Default)]
 //#[cfg(feature = "bevy_egui")]
-struct EguiWantsFocus(bool);
+pub struct EguiWantsFocus(pub bool);
 
 // todo: make run condition when Bevy supports mutable resources in them
 //#[cfg(feature = "bevy_egui")]
 fn check_egui_wants_focus(
-    mut contexts: Query<&mut bevy_egui::EguiContext>,
+    mut contexts: Query<(&mut bevy_egui::EguiContext, &Window)>,
     mut wants_focus: ResMut<EguiWantsFocus>,
 ) {
-    let ctx = contexts.iter_mut().next();
-    let new_wants_focus = if let Some(ctx) = ctx {
+    // only the window under the cursor matters, a detached inspector window must not block
+    // camera input in the game window
+    let ctx = contexts
+        .iter_mut()
+        .find(|(_, window)| window.cursor_position().is_some());
+    // the pointer over an area rather than `wants_pointer_input`, so the non-interactable areas
+    // like the status bar let the pointer through
+    let new_wants_focus = if let Some((ctx, _)) = ctx {
         let ctx = ctx.into_inner().get_mut();
-        ctx.wants_pointer_input() || ctx.wants_keyboard_input()
+        ctx.is_pointer_over_area() || ctx.is_using_pointer() || ctx.wants_keyboard_input()
     } else {
         false
     };
     wants_focus.set_if_neq(EguiWantsFocus(new_wants_focus));
 }
 
+/// World units panned per pixel of wheel scrolling, at scale 1
+const WHEEL_PAN_SPEED: f32 = 1.;
+
+/// What scrolling sideways, or with Shift held, does; plain vertical scrolling always zooms
+#[derive(Reflect, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WheelPanMode {
+    /// Only vertical scrolling is used, to zoom
+    None,
+    /// Horizontal scrolling pans sideways
+    #[default]
+    HorizontalPans,
+    /// Horizontal scrolling pans sideways, and vertical scrolling pans up and down while Shift
+    /// is held
+    ShiftScrollPans,
+}
+
+/// The scrolling of a frame, split into zooming and panning
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct WheelInput {
+    zoom: f32,
+    pan: Vec2,
+}
+
+/// Split the wheel deltas of a frame, in pixels, into zooming and panning
+///
+/// Each delta goes to the axis it mostly scrolls along, so the slightly diagonal deltas of a
+/// trackpad swipe don't both zoom and pan.
+fn split_wheel(deltas: &[Vec2], mode: WheelPanMode, shift: bool) -> WheelInput {
+    let mut input = WheelInput::default();
+    for delta in deltas {
+        let horizontal = delta.x.abs() > delta.y.abs();
+        match mode {
+            WheelPanMode::None => input.zoom += delta.y,
+            _ if horizontal => input.pan.x += delta.x,
+            WheelPanMode::ShiftScrollPans if shift => input.pan.y += delta.y,
+            _ => input.zoom += delta.y,
+        }
+    }
+    input
+}
+
+/// Holds back the zoom scrolling that stays under a deadband, like the resting fingers of a
+/// trackpad
+///
+/// Scrolling adds up over a window of `debounce` seconds and only goes through once it reaches
+/// the deadband, then what's left of the window is dropped. Past the deadband the scrolling goes
+/// through freely until it pauses for `debounce` seconds, so the start and the tail of a flick
+/// aren't cut.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct ScrollDeadband {
+    /// Scrolling held back in the current window, in pixels
+    pending: f32,
+    /// When the current window started
+    window_start: f32,
+    /// Scrolling goes through until then
+    open_until: f32,
+}
+
+impl ScrollDeadband {
+    /// The part of `scroll`, scrolled at `now` seconds, that goes through
+    fn filter(&mut self, scroll: f32, now: f32, deadband: f32, debounce: f32) -> f32 {
+        if scroll == 0. {
+            return 0.;
+        }
+        if now < self.open_until {
+            self.open_until = now + debounce;
+            return scroll;
+        }
+        if now - self.window_start > debounce {
+            self.pending = 0.;
+            self.window_start = now;
+        }
+        self.pending += scroll;
+        if self.pending.abs() < deadband {
+            return 0.;
+        }
+        self.open_until = now + debounce;
+        std::mem::take(&mut self.pending)
+    }
+}
+
+/// Keys that zoom in by one line of scrolling
+pub const ZOOM_IN_KEYS: [KeyCode; 2] = [KeyCode::Equals, KeyCode::NumpadAdd];
+/// Keys that zoom out by one line of scrolling
+pub const ZOOM_OUT_KEYS: [KeyCode; 2] = [KeyCode::Minus, KeyCode::NumpadSubtract];
+
+/// How far past its scale limits an elastic zoom stretches at most, as a factor of the limit
+const ELASTIC_ZOOM_STRETCH: f32 = 1.25;
+/// Seconds a stretched elastic zoom takes to settle back to its limit
+const ELASTIC_ZOOM_SETTLE_SECS: f32 = 0.2;
+
+/// When the zoom input last changed the scale, in seconds since startup
+#[derive(Resource, Debug, Default)]
+struct LastZoomInput(f32);
+
+#[allow(clippy::too_many_arguments)]
 fn camera_zoom(
-    mut query: Query<(&PanCam, &mut OrthographicProjection, &mut Transform)>,
+    mut query: Query<(&PanCam, &mut OrthographicProjection, &mut Transform), Without<CameraRig>>,
     mut scroll_events: EventReader<MouseWheel>,
+    keys: Res<Input<KeyCode>>,
     primary_window: Query<&Window, With<PrimaryWindow>>,
+    time: Res<Time>,
+    motion: Res<MotionPreferences>,
+    mut last_zoom: ResMut<LastZoomInput>,
+    mut step_scroll: Local<f32>,
+    mut deadband: Local<ScrollDeadband>,
 ) {
     let pixels_per_line = 100.; // Maybe make configurable?
-    let scroll = scroll_events
+    let deltas: Vec<Vec2> = scroll_events
         .read()
         .map(|ev| match ev.unit {
-            MouseScrollUnit::Pixel => ev.y,
-            MouseScrollUnit::Line => ev.y * pixels_per_line,
+            MouseScrollUnit::Pixel => vec2(ev.x, ev.y),
+            MouseScrollUnit::Line => vec2(ev.x, ev.y) * pixels_per_line,
         })
-        .sum::<f32>();
+        .collect();
+    // a key press zooms like one line of scrolling
+    let mut key_scroll = 0.;
+    for key in keys.get_just_pressed() {
+        if ZOOM_IN_KEYS.contains(key) {
+            key_scroll += pixels_per_line;
+        } else if ZOOM_OUT_KEYS.contains(key) {
+            key_scroll -= pixels_per_line;
+        }
+    }
 
-    if scroll == 0. {
+    if deltas.is_empty() && key_scroll == 0. {
         return;
     }
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
 
     let window = primary_window.single();
     let window_size = Vec2::new(window.width(), window.height());
@@ -100,155 +387,480 @@ fn camera_zoom(
         .map(|p| Vec2::new(p.x, -p.y));
 
     for (cam, mut proj, mut pos) in &mut query {
-        if cam.enabled {
-            let old_scale = proj.scale;
-            proj.scale = (proj.scale * (1. + -scroll * 0.001)).max(cam.min_scale);
+        if !cam.enabled {
+            continue;
+        }
+        let wheel = split_wheel(&deltas, cam.wheel_pan, shift);
+        if wheel.pan != Vec2::ZERO {
+            // content follows the scroll, like a scrolled page
+            let delta_world = vec2(-wheel.pan.x, wheel.pan.y) * WHEEL_PAN_SPEED * proj.scale;
+            let center = pos.translation.truncate() + delta_world;
+            let center = clamp_view_center(center, proj.area.size(), cam);
+            pos.translation = center.extend(pos.translation.z);
+        }
 
-            // Apply max scale constraint
-            if let Some(max_scale) = cam.max_scale {
-                proj.scale = proj.scale.min(max_scale);
+        let wheel_zoom = deadband.filter(
+            wheel.zoom,
+            time.elapsed_seconds(),
+            cam.scroll_deadband,
+            cam.scroll_debounce_secs,
+        );
+        let scroll = wheel_zoom + key_scroll;
+        if scroll == 0. {
+            continue;
+        }
+        // zoom steps move one step per line worth of scrolling, small pixel scrolls add up
+        *step_scroll += scroll;
+        let notches = (*step_scroll / pixels_per_line).trunc();
+        *step_scroll -= notches * pixels_per_line;
+
+        // without a cursor over the window, zoom around the middle of the screen
+        let anchor_world = pos.translation.truncate()
+            + mouse_normalized_screen_pos.unwrap_or(Vec2::ZERO) * proj.area.size() / 2.;
+        let new_scale = match &cam.zoom_steps {
+            Some(steps) => {
+                if notches == 0. {
+                    continue;
+                }
+                let max_scale = max_zoom_scale(cam, &proj);
+                zoom_step(steps, proj.scale, notches as i32, cam.min_scale, max_scale)
             }
+            None if cam.elastic_zoom_limits && !motion.reduce_motion => {
+                let max_scale = max_zoom_scale(cam, &proj);
+                let raw = raw_scale(proj.scale, cam.min_scale, max_scale);
+                let scale = elastic_scale(raw * (1. + -scroll * 0.001), cam.min_scale, max_scale);
+                // stretching past the bounds would show what's outside them
+                let scale = scale.min(max_scale_in_bounds(cam, &proj));
+                zoom_camera_to(&mut proj, &mut pos, anchor_world, scale, cam);
+                last_zoom.0 = time.elapsed_seconds();
+                continue;
+            }
+            None => proj.scale * (1. + -scroll * 0.001),
+        };
+        zoom_camera_around(&mut proj, &mut pos, anchor_world, new_scale, cam);
+    }
+}
 
-            // If there is both a min and max boundary, that limits how far we can zoom. Make sure we don't exceed that
-            let scale_constrained = BVec2::new(
-                cam.min_x.is_some() && cam.max_x.is_some(),
-                cam.min_y.is_some() && cam.max_y.is_some(),
-            );
+/// Zoom to `new_scale` keeping the world point `anchor_world` where it is on screen
+///
+/// The scale is clamped to the camera's scale limits and to what fits in its bounds, then the
+/// view is moved back inside the bounds. When `zoom_to_cursor` is off the anchor is ignored and
+/// the camera zooms around its center. Every way of zooming goes through here, but for the
+/// stretch of an elastic zoom past the scale limits.
+pub fn zoom_camera_around(
+    proj: &mut OrthographicProjection,
+    transform: &mut Transform,
+    anchor_world: Vec2,
+    new_scale: f32,
+    cam: &PanCam,
+) {
+    let scale = new_scale.max(cam.min_scale).min(max_zoom_scale(cam, proj));
+    zoom_camera_to(proj, transform, anchor_world, scale, cam);
+}
 
-            if scale_constrained.x || scale_constrained.y {
-                let bounds_width = if let (Some(min_x), Some(max_x)) = (cam.min_x, cam.max_x) {
-                    max_x - min_x
-                } else {
-                    f32::INFINITY
-                };
+/// Zoom to `scale` as it is around `anchor_world`, only moving the view back inside the bounds
+fn zoom_camera_to(
+    proj: &mut OrthographicProjection,
+    transform: &mut Transform,
+    anchor_world: Vec2,
+    scale: f32,
+    cam: &PanCam,
+) {
+    let old_scale = proj.scale;
+    // the projection area is only recomputed from the scale later in the frame
+    let view_size = proj.area.size() / old_scale * scale;
 
-                let bounds_height = if let (Some(min_y), Some(max_y)) = (cam.min_y, cam.max_y) {
-                    max_y - min_y
-                } else {
-                    f32::INFINITY
-                };
+    let center = transform.translation.truncate();
+    let mut new_center = if cam.zoom_to_cursor {
+        anchor_world - (anchor_world - center) * (scale / old_scale)
+    } else {
+        center
+    };
 
-                let bounds_size = vec2(bounds_width, bounds_height);
-                let max_safe_scale = max_scale_within_bounds(bounds_size, &proj, window_size);
+    // As we zoom out, we don't want the viewport to move beyond the provided boundary
+    new_center = clamp_view_center(new_center, view_size, cam);
 
-                if scale_constrained.x {
-                    proj.scale = proj.scale.min(max_safe_scale.x);
-                }
+    proj.scale = scale;
+    proj.area = Rect::from_center_size(proj.area.center() / old_scale * scale, view_size);
+    transform.translation = new_center.extend(transform.translation.z);
+}
 
-                if scale_constrained.y {
-                    proj.scale = proj.scale.min(max_safe_scale.y);
-                }
-            }
+/// Move the camera's scale and position back inside its limits, after they changed
+pub fn clamp_camera(proj: &mut OrthographicProjection, transform: &mut Transform, cam: &PanCam) {
+    let center = transform.translation.truncate();
+    zoom_camera_around(proj, transform, center, proj.scale, cam);
+}
 
-            // Move the camera position to normalize the projection window
-            if let (Some(mouse_normalized_screen_pos), true) =
-                (mouse_normalized_screen_pos, cam.zoom_to_cursor)
-            {
-                let proj_size = proj.area.max / old_scale;
-                let mouse_world_pos = pos.translation.truncate()
-                    + mouse_normalized_screen_pos * proj_size * old_scale;
-                pos.translation = (mouse_world_pos
-                    - mouse_normalized_screen_pos * proj_size * proj.scale)
-                    .extend(pos.translation.z);
-
-                // As we zoom out, we don't want the viewport to move beyond the provided boundary. If the most recent
-                // change to the camera zoom would move cause parts of the window beyond the boundary to be shown, we
-                // need to change the camera position to keep the viewport within bounds. The four if statements below
-                // provide this behavior for the min and max x and y boundaries.
-                let proj_size = proj.area.size();
-
-                let half_of_viewport = proj_size / 2.;
-
-                if let Some(min_x_bound) = cam.min_x {
-                    let min_safe_cam_x = min_x_bound + half_of_viewport.x;
-                    pos.translation.x = pos.translation.x.max(min_safe_cam_x);
-                }
-                if let Some(max_x_bound) = cam.max_x {
-                    let max_safe_cam_x = max_x_bound - half_of_viewport.x;
-                    pos.translation.x = pos.translation.x.min(max_safe_cam_x);
-                }
-                if let Some(min_y_bound) = cam.min_y {
-                    let min_safe_cam_y = min_y_bound + half_of_viewport.y;
-                    pos.translation.y = pos.translation.y.max(min_safe_cam_y);
-                }
-                if let Some(max_y_bound) = cam.max_y {
-                    let max_safe_cam_y = max_y_bound - half_of_viewport.y;
-                    pos.translation.y = pos.translation.y.min(max_safe_cam_y);
-                }
-            }
+/// Center the camera on `target` at once, as far as its bounds allow
+pub fn snap_camera_to(
+    target: Vec2,
+    proj: &OrthographicProjection,
+    transform: &mut Transform,
+    cam: &PanCam,
+) {
+    let center = clamp_view_center(target, proj.area.size(), cam);
+    transform.translation = center.extend(transform.translation.z);
+}
+
+/// Center of a `view_size` view moved back inside the camera's bounds
+///
+/// A view larger than the bounds on an axis is centered on them instead: clamping it against
+/// both edges would snap it to whichever edge is checked last.
+fn clamp_view_center(center: Vec2, view_size: Vec2, cam: &PanCam) -> Vec2 {
+    let half_of_viewport = view_size / 2.;
+    let min_safe = vec2(
+        cam.min_x.map_or(f32::MIN, |min_x| min_x + half_of_viewport.x),
+        cam.min_y.map_or(f32::MIN, |min_y| min_y + half_of_viewport.y),
+    );
+    let max_safe = vec2(
+        cam.max_x.map_or(f32::MAX, |max_x| max_x - half_of_viewport.x),
+        cam.max_y.map_or(f32::MAX, |max_y| max_y - half_of_viewport.y),
+    );
+    let mut center = center;
+    for axis in 0..2 {
+        center[axis] = if min_safe[axis] > max_safe[axis] {
+            (min_safe[axis] + max_safe[axis]) / 2.
+        } else {
+            center[axis].clamp(min_safe[axis], max_safe[axis])
+        };
+    }
+    center
+}
+
+/// Largest scale allowed by `max_scale` and, when there are both a min and max boundary on an
+/// axis, by keeping the view within them
+fn max_zoom_scale(cam: &PanCam, proj: &OrthographicProjection) -> f32 {
+    cam.max_scale
+        .unwrap_or(f32::INFINITY)
+        .min(max_scale_in_bounds(cam, proj))
+}
+
+/// Largest scale keeping the view within the bounds, on the axes with both a min and a max
+fn max_scale_in_bounds(cam: &PanCam, proj: &OrthographicProjection) -> f32 {
+    let mut max_scale = f32::INFINITY;
+
+    let bounds_width = if let (Some(min_x), Some(max_x)) = (cam.min_x, cam.max_x) {
+        max_x - min_x
+    } else {
+        f32::INFINITY
+    };
+    let bounds_height = if let (Some(min_y), Some(max_y)) = (cam.min_y, cam.max_y) {
+        max_y - min_y
+    } else {
+        f32::INFINITY
+    };
+
+    let max_safe_scale = max_scale_within_bounds(vec2(bounds_width, bounds_height), proj);
+    if bounds_width.is_finite() {
+        max_scale = max_scale.min(max_safe_scale.x);
+    }
+    if bounds_height.is_finite() {
+        max_scale = max_scale.min(max_safe_scale.y);
+    }
+    max_scale
+}
+
+/// Scale shown for the `raw` scale asked for by the zoom input, with elastic limits
+///
+/// Inside `min..=max` it's the raw scale. Past a limit the scale stretches less and less the
+/// further the input goes, never past `ELASTIC_ZOOM_STRETCH` times the limit.
+fn elastic_scale(raw: f32, min: f32, max: f32) -> f32 {
+    // a flick faster than the zoom factor goes could ask for no scale at all
+    let raw = raw.max(f32::MIN_POSITIVE);
+    let limit = raw.max(min).min(max);
+    let overscroll = (raw / limit).ln();
+    let reach = ELASTIC_ZOOM_STRETCH.ln();
+    let stretch = overscroll.signum() * reach * (1. - (-overscroll.abs() / reach).exp());
+    limit * stretch.exp()
+}
+
+/// The raw scale `elastic_scale` shows as `scale`, for the zoom input to go on from
+fn raw_scale(scale: f32, min: f32, max: f32) -> f32 {
+    let limit = scale.max(min).min(max);
+    let stretch = (scale / limit).ln();
+    let reach = ELASTIC_ZOOM_STRETCH.ln();
+    // a stretch all the way to the asymptote would take endless input
+    let stretched = (stretch.abs() / reach).min(0.999);
+    limit * (-stretch.signum() * reach * (1. - stretched).ln()).exp()
+}
+
+/// Scale a stretched elastic zoom eases to over `delta` seconds, on its way back to `limit`
+///
+/// The stretch decays exponentially, so the ease doesn't depend on the frame rate, and snaps to
+/// the limit once it's too small to see: within `ELASTIC_ZOOM_SETTLE_SECS` of the largest one.
+fn settle_step(scale: f32, limit: f32, delta: f32) -> f32 {
+    let stretch = (scale / limit).ln() * (-5. * delta / ELASTIC_ZOOM_SETTLE_SECS).exp();
+    if stretch.abs() < 2e-3 {
+        limit
+    } else {
+        limit * stretch.exp()
+    }
+}
+
+/// Ease the scale of a stretched elastic zoom back to its limit once the zoom input paused for
+/// `scroll_debounce_secs`, or at once when the motion preferences ask for no animations
+fn settle_elastic_zoom(
+    mut query: Query<(&PanCam, &mut OrthographicProjection, &mut Transform), Without<CameraRig>>,
+    time: Res<Time>,
+    motion: Res<MotionPreferences>,
+    last_zoom: Res<LastZoomInput>,
+) {
+    for (cam, mut proj, mut transform) in &mut query {
+        if !cam.elastic_zoom_limits {
+            continue;
+        }
+        let limit = proj
+            .scale
+            .max(cam.min_scale)
+            .min(max_zoom_scale(cam, &proj));
+        if proj.scale == limit {
+            continue;
+        }
+        if motion.reduce_motion {
+            clamp_camera(&mut proj, &mut transform, cam);
+            continue;
         }
+        if time.elapsed_seconds() - last_zoom.0 < cam.scroll_debounce_secs {
+            continue;
+        }
+        let scale = settle_step(proj.scale, limit, time.delta_seconds());
+        let center = transform.translation.truncate();
+        zoom_camera_to(&mut proj, &mut transform, center, scale, cam);
     }
 }
 
+/// Scale reached by scrolling `notches` steps through `steps`, positive notches zoom in
+///
+/// Only the steps between `min_scale` and `max_scale` are used. A scale between two steps moves
+/// to the neighbouring step in the scroll direction first. When no step is usable the scale is
+/// just clamped.
+fn zoom_step(steps: &[f32], scale: f32, notches: i32, min_scale: f32, max_scale: f32) -> f32 {
+    // scales this close to a step count as being on it
+    const EPSILON: f32 = 1e-4;
+
+    let mut allowed: Vec<f32> = steps
+        .iter()
+        .copied()
+        .filter(|step| *step >= min_scale && *step <= max_scale)
+        .collect();
+    allowed.sort_by(f32::total_cmp);
+    let (Some(smallest), Some(largest)) = (allowed.first().copied(), allowed.last().copied())
+    else {
+        return scale.min(max_scale).max(min_scale);
+    };
+
+    let mut scale = scale;
+    for _ in 0..notches.unsigned_abs() {
+        scale = if notches > 0 {
+            allowed
+                .iter()
+                .rev()
+                .find(|step| **step < scale - EPSILON)
+                .copied()
+                .unwrap_or(smallest)
+        } else {
+            allowed
+                .iter()
+                .find(|step| **step > scale + EPSILON)
+                .copied()
+                .unwrap_or(largest)
+        };
+    }
+    scale
+}
+
 /// max_scale_within_bounds is used to find the maximum safe zoom out/projection
 /// scale when we have been provided with minimum and maximum x boundaries for
 /// the camera.
-fn max_scale_within_bounds(
-    bounds_size: Vec2,
-    proj: &OrthographicProjection,
-    window_size: Vec2, //viewport?
-) -> Vec2 {
-    let mut p = proj.clone();
-    p.scale = 1.;
-    p.update(window_size.x, window_size.y);
-    let base_world_size = p.area.size();
+fn max_scale_within_bounds(bounds_size: Vec2, proj: &OrthographicProjection) -> Vec2 {
+    // size of the view at scale 1
+    let base_world_size = proj.area.size() / proj.scale;
     bounds_size / base_world_size
 }
 
-fn camera_movement(
-    primary_window: Query<&Window, With<PrimaryWindow>>,
+/// Sent when a grab button is pressed and released without moving further than
+/// `PanCam::drag_threshold`, i.e. the gesture was a click rather than a pan
+#[derive(Event, Debug, Clone, Copy)]
+pub struct WorldClick {
+    pub button: MouseButton,
+    /// Position of the click in world coordinates
+    pub world_pos: Vec2,
+}
+
+/// Whether a grab button is dragging the camera right now
+#[derive(Resource, Default, Debug, PartialEq, Eq)]
+pub struct Panning(pub bool);
+
+/// The grab gesture of a camera that is in progress
+#[derive(Component, Default, Debug)]
+pub struct DragState {
+    button: Option<MouseButton>,
+    /// Cursor position (y flipped) when the button was pressed
+    press_pos: Vec2,
+    /// World point under the cursor when the button was pressed, kept under it while panning
+    anchor: Vec2,
+    /// The press position moved on by each frame's step of the `DragCursor`, so the cursor
+    /// jumping back into the window doesn't jump the map
+    cursor: Vec2,
+    /// Whether the modifiers required to pan were held when the button was pressed
+    can_pan: bool,
+    /// Set once the cursor moved past the drag threshold and the gesture became a pan
+    panning: bool,
+}
+
+/// Where the drag is, from the cursor inside the window and from the raw mouse motion outside
+#[derive(Default)]
+pub struct DragCursor {
+    last: Option<Vec2>,
+    /// Whether `last` was followed from the mouse motion, the cursor being outside the window
+    outside: bool,
+}
+
+impl DragCursor {
+    /// Where the cursor was last frame and where it is now (y flipped), `None` when it's outside
+    /// the window and no drag is held
+    ///
+    /// Outside the window the position moves on by `motion`, the raw mouse motion of the frame.
+    /// Coming back in, the cursor jumps to where the OS shows it, so the previous position is
+    /// taken back by this frame's motion from there instead of the followed one.
+    fn advance(
+        &mut self,
+        cursor: Option<Vec2>,
+        motion: Vec2,
+        dragging: bool,
+    ) -> Option<(Vec2, Vec2)> {
+        let (from, to) = match (cursor, self.last) {
+            (Some(cursor), Some(last)) if !self.outside => (last, cursor),
+            (Some(cursor), _) => (cursor - motion, cursor),
+            (None, Some(last)) if dragging => (last, last + motion),
+            (None, _) => {
+                self.last = None;
+                return None;
+            }
+        };
+        self.last = Some(to);
+        self.outside = cursor.is_none();
+        Some((from, to))
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn camera_movement(
+    mut primary_window: Query<&mut Window, With<PrimaryWindow>>,
     mouse_buttons: Res<Input<MouseButton>>,
-    mut query: Query<(&PanCam, &mut Transform, &OrthographicProjection)>,
-    mut last_pos: Local<Option<Vec2>>,
+    keys: Res<Input<KeyCode>>,
+    mut motion_events: EventReader<MouseMotion>,
+    // a camera rig takes over from the player's input until it's done
+    mut query: Query<
+        (
+            Entity,
+            &PanCam,
+            &mut DragState,
+            &mut Transform,
+            &OrthographicProjection,
+        ),
+        Without<CameraRig>,
+    >,
+    cursor_cache: Res<CursorWorldCache>,
+    mut clicks: EventWriter<WorldClick>,
+    mut panning: ResMut<Panning>,
+    mut drag: Local<DragCursor>,
 ) {
-    let window = primary_window.single();
+    let mut window = primary_window.single_mut();
     let window_size = Vec2::new(window.width(), window.height());
+    // the window center in the same y flipped pixels as the cursor
+    let window_center = Vec2::new(window_size.x, -window_size.y) / 2.;
 
-    // Use position instead of MouseMotion, otherwise we don't get acceleration movement
-    let current_pos = match window.cursor_position() {
-        Some(c) => Vec2::new(c.x, -c.y),
-        None => return,
+    // Use position instead of MouseMotion, otherwise we don't get acceleration movement. The
+    // motion only keeps a drag going while the cursor is outside the window.
+    let motion: Vec2 = motion_events
+        .read()
+        .map(|motion| Vec2::new(motion.delta.x, -motion.delta.y))
+        .sum();
+    let dragging = query.iter().any(|(_, _, state, _, _)| {
+        state
+            .button
+            .is_some_and(|btn| mouse_buttons.pressed(btn) || mouse_buttons.just_released(btn))
+    });
+    let cursor = window.cursor_position().map(|c| Vec2::new(c.x, -c.y));
+    let Some((previous_pos, current_pos)) = drag.advance(cursor, motion, dragging) else {
+        return;
     };
-    let delta_device_pixels = current_pos - last_pos.unwrap_or(current_pos);
 
-    for (cam, mut transform, projection) in &mut query {
-        if cam.enabled
-            && cam
-                .grab_buttons
-                .iter()
-                .any(|btn| mouse_buttons.pressed(*btn) && !mouse_buttons.just_pressed(*btn))
-        {
-            let proj_size = projection.area.size();
+    let mut any_panning = false;
+    for (camera, cam, mut state, mut transform, projection) in &mut query {
+        if !cam.enabled {
+            continue;
+        }
+        let proj_size = projection.area.size();
+        let world_units_per_device_pixel = proj_size / window_size;
 
-            let world_units_per_device_pixel = proj_size / window_size;
+        // anchor the gesture where it was pressed, the first frame of motion already pans from it
+        if let Some(btn) = cam
+            .grab_buttons
+            .iter()
+            .find(|btn| cam.grabs(**btn) && mouse_buttons.just_pressed(**btn))
+        {
+            *state = DragState {
+                button: Some(*btn),
+                press_pos: current_pos,
+                cursor: current_pos,
+                anchor: transform.translation.truncate()
+                    + (current_pos - window_center) * world_units_per_device_pixel,
+                can_pan: cam.pan_buttons.contains(btn)
+                    && (cam.grab_modifiers.is_empty()
+                        || cam.grab_modifiers.iter().any(|key| keys.pressed(*key))),
+                panning: false,
+            };
+            continue;
+        }
 
-            // The proposed new camera position
-            let delta_world = delta_device_pixels * world_units_per_device_pixel;
-            let mut proposed_cam_transform = transform.translation - delta_world.extend(0.);
+        let Some(btn) = state.button else {
+            continue;
+        };
+        state.cursor += current_pos - previous_pos;
 
-            // Check whether the proposed camera movement would be within the provided boundaries, override it if we
-            // need to do so to stay within bounds.
-            if let Some(min_x_boundary) = cam.min_x {
-                let min_safe_cam_x = min_x_boundary + proj_size.x / 2.;
-                proposed_cam_transform.x = proposed_cam_transform.x.max(min_safe_cam_x);
+        if mouse_buttons.just_released(btn) {
+            if !state.panning && state.cursor.distance(state.press_pos) <= cam.drag_threshold {
+                if let Some(cursor) = cursor_cache.get(camera) {
+                    clicks.send(WorldClick {
+                        button: btn,
+                        world_pos: cursor.world_pos,
+                    });
+                }
             }
-            if let Some(max_x_boundary) = cam.max_x {
-                let max_safe_cam_x = max_x_boundary - proj_size.x / 2.;
-                proposed_cam_transform.x = proposed_cam_transform.x.min(max_safe_cam_x);
+            *state = DragState::default();
+            if cam.confine_cursor_while_dragging {
+                window.cursor.grab_mode = CursorGrabMode::None;
             }
-            if let Some(min_y_boundary) = cam.min_y {
-                let min_safe_cam_y = min_y_boundary + proj_size.y / 2.;
-                proposed_cam_transform.y = proposed_cam_transform.y.max(min_safe_cam_y);
+            continue;
+        }
+
+        if !state.can_pan || !mouse_buttons.pressed(btn) {
+            continue;
+        }
+
+        if !state.panning {
+            if state.cursor.distance(state.press_pos) <= cam.drag_threshold {
+                continue;
             }
-            if let Some(max_y_boundary) = cam.max_y {
-                let max_safe_cam_y = max_y_boundary - proj_size.y / 2.;
-                proposed_cam_transform.y = proposed_cam_transform.y.min(max_safe_cam_y);
+            state.panning = true;
+            if cam.confine_cursor_while_dragging {
+                // not every platform can confine, there the drag goes on from the mouse motion
+                window.cursor.grab_mode = CursorGrabMode::Confined;
             }
-
-            transform.translation = proposed_cam_transform;
         }
+        any_panning = true;
+
+        // the whole movement since the press, so the map stays under the cursor
+        let proposed_center =
+            state.anchor - (state.cursor - window_center) * world_units_per_device_pixel;
+
+        // Keep the proposed camera movement within the provided boundaries
+        let center = clamp_view_center(proposed_center, proj_size, cam);
+        transform.translation = center.extend(transform.translation.z);
     }
-    *last_pos = Some(current_pos);
+    panning.set_if_neq(Panning(any_panning));
 }
 
 // fn camera_setup(
@@ -300,8 +912,29 @@ fn camera_movement(
 #[derive(Component, Reflect)]
 #[reflect(Component)]
 pub struct PanCam {
-    /// The mouse buttons that will be used to drag and pan the camera
+    /// The mouse buttons whose presses are told apart as clicks or drags
+    ///
+    /// Each press is sent as a `WorldClick` when it doesn't move past `drag_threshold`.
     pub grab_buttons: Vec<MouseButton>,
+    /// The grab buttons that pan when dragged, the others only click
+    pub pan_buttons: Vec<MouseButton>,
+    /// Whether the middle button grabs when it's one of the `grab_buttons`
+    ///
+    /// Off for setups where a middle click also starts the system's autoscroll.
+    pub middle_button_grabs: bool,
+    /// Keys of which at least one has to be held when pressing a grab button to pan
+    ///
+    /// When empty, the grab buttons pan on their own.
+    pub grab_modifiers: Vec<KeyCode>,
+    /// Distance in device pixels the cursor has to move with a grab button held before the
+    /// gesture turns into a pan
+    ///
+    /// Gestures that stay below it are sent as a `WorldClick` instead.
+    pub drag_threshold: f32,
+    /// Whether to keep the cursor inside the window while panning
+    ///
+    /// Without it, a pan goes on from the mouse motion while the cursor is outside the window.
+    pub confine_cursor_while_dragging: bool,
     /// Whether camera currently responds to user input
     pub enabled: bool,
     /// When true, zooming the camera will center on the mouse cursor
@@ -318,6 +951,27 @@ pub struct PanCam {
     /// If present, the orthographic projection's scale will be clamped at
     /// this value when zooming out.
     pub max_scale: Option<f32>,
+    /// Whether smooth zooming stretches past `min_scale` and `max_scale` instead of stopping
+    ///
+    /// The stretch answers less and less to the input, like the overscroll of a phone, and
+    /// eases back to the limit once the zoom input pauses. The view still stays inside the
+    /// bounds, and reduce motion turns the stretch off.
+    pub elastic_zoom_limits: bool,
+    /// Scales zooming snaps to, e.g. `[0.25, 0.5, 1., 2., 3., 4.]` for crisp pixel art
+    ///
+    /// When present, each line of scrolling moves to the next or previous step instead of
+    /// zooming smoothly. Steps outside `min_scale`, `max_scale` and the bounds are skipped.
+    pub zoom_steps: Option<Vec<f32>>,
+    /// What horizontal scrolling, and scrolling with Shift held, does
+    pub wheel_pan: WheelPanMode,
+    /// Zoom scrolling, in pixels, that has to add up before the camera zooms
+    ///
+    /// Keeps the sub-pixel deltas of fingers resting on a trackpad from creeping the zoom. A
+    /// mouse wheel scrolls whole lines, far past it.
+    pub scroll_deadband: f32,
+    /// Seconds the zoom scrolling adds up over to reach `scroll_deadband`, and that it goes
+    /// through freely for after a pause shorter than that
+    pub scroll_debounce_secs: f32,
     /// The minimum x position of the camera window
     ///
     /// If present, the orthographic projection will be clamped to this boundary both
@@ -368,20 +1022,8 @@ pub fn new_camera2d_with_constraints(pancam: &PanCam, pos: &Vec3) -> Camera2dBun
     let mut proposed_cam_transform = transform;
 
     // clamp to the given boundaries
-    {
-        let min_x_boundary = pancam.min_x.unwrap_or_else(|| f32::MIN);
-        let max_x_boundary = pancam.max_x.unwrap_or_else(|| f32::MAX);
-        let min_y_boundary = pancam.min_y.unwrap_or_else(|| f32::MIN);
-        let max_y_boundary = pancam.max_y.unwrap_or_else(|| f32::MAX);
-
-        let min_safe_cam_x = min_x_boundary + proj_size.x / 2.;
-        let max_safe_cam_x = max_x_boundary - proj_size.x / 2.;
-        proposed_cam_transform.translation.x = proposed_cam_transform.translation.x.clamp(min_safe_cam_x, max_safe_cam_x);
-
-        let min_safe_cam_y = min_y_boundary + proj_size.y / 2.;
-        let max_safe_cam_y = max_y_boundary - proj_size.y / 2.;
-        proposed_cam_transform.translation.y = proposed_cam_transform.translation.y.clamp(min_safe_cam_y, max_safe_cam_y);
-    }
+    let center = clamp_view_center(transform.translation.truncate(), proj_size, pancam);
+    proposed_cam_transform.translation = center.extend(transform.translation.z);
 
     Camera2dBundle {
         projection,
@@ -393,60 +1035,95 @@ pub fn new_camera2d_with_constraints(pancam: &PanCam, pos: &Vec3) -> Camera2dBun
 
 fn camera_spawn(
     mut commands: Commands,
-    game_info: Res<GameInfoAlt>,
-    tile_maps: Res<Assets<helpers::tiled::TiledMap>>,
+    map_frame: Option<Res<MapFrame>>,
+    mut cameras: Query<(&mut PanCam, &mut Transform), With<MainCamera>>,
 ) {
     info!("camera_spawn");
 
-    let mut camera_pos = Vec3::default();
-    let mut map_size = Vec2::default();
-    if let Some(map) = tile_maps.get(&game_info.tile_map) {
-        map_size = Vec2::new(
-            ((map.map.width - 1) * map.map.tile_width) as f32,
-            ((map.map.height - 1) * map.map.tile_height) as f32,
-        );
-
-        let tile_size = TilemapTileSize { x: map.map.tile_width as f32, y: map.map.tile_height as f32 };
-        let grid_size = tile_size.into();
-
-        let map_type = TilemapType::Square;
-
-        let low = TilePos::new(0, 0).center_in_world(&grid_size, &map_type);
-        let high = TilePos::new(map.map.width - 1, map.map.height - 1).center_in_world(&grid_size, &map_type);
-        let diff = high - low;
-
-        let xform = Transform::from_xyz(diff.x / 2., diff.y / 2., 0.);
-        camera_pos = xform.translation;
-    }
-    else {
-        warn!("can't find tile map for camera setup!")
+    // the camera and the ui on it stay when the map is switched, only moving to the new map
+    if let Ok((mut pancam, mut transform)) = cameras.get_single_mut() {
+        if let Some(map_frame) = map_frame {
+            let map_transform = GlobalTransform::from(map_frame.transform);
+            let bounds = coords::map_world_rect(&map_frame, &map_transform);
+            pancam.set_bounds(bounds);
+            transform.translation = bounds.center().extend(transform.translation.z);
+        }
+        return;
     }
 
-    let pancam = PanCam {
+    let mut pancam = PanCam {
         min_scale: 0.25,
         max_scale: Some(30.),
-        //max_x: None,
-        //max_y: None,
-        min_x: Some(0.),
-        min_y: Some(0.),
-        max_x: Some(map_size.x),
-        max_y: Some(map_size.y),
+        elastic_zoom_limits: true,
         ..default()
     };
+    let mut camera_pos = Vec3::default();
+    if let Some(map_frame) = map_frame {
+        // the map entity is spawned with the frame's transform, `update_camera_bounds` follows
+        // it when it changes later
+        let map_transform = GlobalTransform::from(map_frame.transform);
+        let bounds = coords::map_world_rect(&map_frame, &map_transform);
+        pancam.set_bounds(bounds);
+        camera_pos = bounds.center().extend(0.);
+    } else {
+        warn!("can't find tile map for camera setup!")
+    }
+
     let cam2d = new_camera2d_with_constraints(&pancam, &camera_pos);
 
     // spawn the camera system
-    commands.spawn((cam2d, pancam, MainCamera));
+    commands.spawn((cam2d, pancam, DragState::default(), MainCamera));
+}
+
+/// Keep the camera bounds on the map's world-space extent when the map entity moves or scales
+fn update_camera_bounds(
+    map_q: Query<&GlobalTransform, (With<Handle<TiledMap>>, Changed<GlobalTransform>)>,
+    mut cameras: Query<&mut PanCam>,
+    map_frame: Option<Res<MapFrame>>,
+) {
+    let (Ok(map_transform), Some(map_frame)) = (map_q.get_single(), map_frame) else {
+        return;
+    };
+    let bounds = coords::map_world_rect(&map_frame, map_transform);
+    for mut pancam in &mut cameras {
+        pancam.set_bounds(bounds);
+    }
+}
+
+impl PanCam {
+    /// Whether presses of `button` are told apart as clicks or drags
+    pub fn grabs(&self, button: MouseButton) -> bool {
+        self.grab_buttons.contains(&button)
+            && (self.middle_button_grabs || button != MouseButton::Middle)
+    }
+
+    /// Keep the camera view inside `bounds`
+    pub fn set_bounds(&mut self, bounds: Rect) {
+        self.min_x = Some(bounds.min.x);
+        self.min_y = Some(bounds.min.y);
+        self.max_x = Some(bounds.max.x);
+        self.max_y = Some(bounds.max.y);
+    }
 }
 
 impl Default for PanCam {
     fn default() -> Self {
         Self {
             grab_buttons: vec![MouseButton::Left, MouseButton::Right, MouseButton::Middle],
+            pan_buttons: vec![MouseButton::Left, MouseButton::Right, MouseButton::Middle],
+            middle_button_grabs: true,
+            grab_modifiers: Vec::new(),
+            drag_threshold: 4.,
+            confine_cursor_while_dragging: false,
             enabled: true,
             zoom_to_cursor: true,
             min_scale: 0.00001,
             max_scale: None,
+            elastic_zoom_limits: false,
+            zoom_steps: None,
+            wheel_pan: WheelPanMode::default(),
+            scroll_deadband: 4.,
+            scroll_debounce_secs: 0.15,
             min_x: None,
             max_x: None,
             min_y: None,
@@ -457,9 +1134,9 @@ impl Default for PanCam {
 
 #[cfg(test)]
 mod tests {
-    use std::f32::INFINITY;
-
+    use bevy::math::vec3;
     use bevy::prelude::OrthographicProjection;
+    use bevy_ecs_tilemap::prelude::{TilemapGridSize, TilemapSize, TilemapType};
 
     use super::*;
 
@@ -475,7 +1152,7 @@ mod tests {
         let window_size = vec2(100., 100.);
         let proj = mock_proj(window_size);
         assert_eq!(
-            max_scale_within_bounds(vec2(100., INFINITY), &proj, window_size).x,
+            max_scale_within_bounds(vec2(100., f32::INFINITY), &proj).x,
             1.
         );
     }
@@ -486,7 +1163,7 @@ mod tests {
         let window_size = vec2(100., 100.);
         let proj = mock_proj(window_size);
         assert_eq!(
-            max_scale_within_bounds(vec2(50., INFINITY), &proj, window_size).x,
+            max_scale_within_bounds(vec2(50., f32::INFINITY), &proj).x,
             0.5
         );
     }
@@ -497,7 +1174,7 @@ mod tests {
         let window_size = vec2(100., 100.);
         let proj = mock_proj(window_size);
         assert_eq!(
-            max_scale_within_bounds(vec2(200., INFINITY), &proj, window_size).x,
+            max_scale_within_bounds(vec2(200., f32::INFINITY), &proj).x,
             2.
         );
     }
@@ -507,7 +1184,7 @@ mod tests {
         let window_size = vec2(100., 100.);
         let proj = mock_proj(window_size);
         assert_eq!(
-            max_scale_within_bounds(vec2(INFINITY, 100.), &proj, window_size).y,
+            max_scale_within_bounds(vec2(f32::INFINITY, 100.), &proj).y,
             1.
         );
     }
@@ -518,7 +1195,7 @@ mod tests {
         let window_size = vec2(100., 100.);
         let proj = mock_proj(window_size);
         assert_eq!(
-            max_scale_within_bounds(vec2(INFINITY, 50.), &proj, window_size).y,
+            max_scale_within_bounds(vec2(f32::INFINITY, 50.), &proj).y,
             0.5
         );
     }
@@ -529,8 +1206,577 @@ mod tests {
         let window_size = vec2(100., 100.);
         let proj = mock_proj(window_size);
         assert_eq!(
-            max_scale_within_bounds(vec2(INFINITY, 200.), &proj, window_size).y,
+            max_scale_within_bounds(vec2(f32::INFINITY, 200.), &proj).y,
             2.
         );
     }
+
+    #[test]
+    fn drift_reverses_at_the_bounds() {
+        let (min, max) = (Vec2::ZERO, Vec2::new(100., 50.));
+        let (pos, direction) = drift_step(vec2(95., 10.), Vec2::ONE, vec2(10., 10.), min, max);
+        assert_eq!(pos, vec2(100., 20.));
+        assert_eq!(direction, vec2(-1., 1.));
+
+        let (pos, direction) = drift_step(pos, direction, vec2(10., 40.), min, max);
+        assert_eq!(pos, vec2(90., 50.));
+        assert_eq!(direction, vec2(-1., -1.));
+    }
+
+    #[test]
+    fn drift_centers_axes_wider_than_the_bounds() {
+        let (pos, _) = drift_step(
+            vec2(30., 10.),
+            Vec2::ONE,
+            vec2(5., 5.),
+            vec2(60., 0.),
+            vec2(40., 50.),
+        );
+        assert_eq!(pos, vec2(50., 15.));
+    }
+
+    const STEPS: [f32; 6] = [0.25, 0.5, 1., 2., 3., 4.];
+
+    #[test]
+    fn wheel_deltas_go_to_their_main_axis() {
+        let deltas = [vec2(30., 4.), vec2(-2., 100.), vec2(0., -20.)];
+
+        let zoom_only = split_wheel(&deltas, WheelPanMode::None, false);
+        assert_eq!(zoom_only.zoom, 84.);
+        assert_eq!(zoom_only.pan, Vec2::ZERO);
+
+        let horizontal = split_wheel(&deltas, WheelPanMode::HorizontalPans, true);
+        assert_eq!(horizontal.zoom, 80.);
+        assert_eq!(horizontal.pan, vec2(30., 0.));
+
+        let shifted = split_wheel(&deltas, WheelPanMode::ShiftScrollPans, true);
+        assert_eq!(shifted.zoom, 0.);
+        assert_eq!(shifted.pan, vec2(30., 80.));
+        let unshifted = split_wheel(&deltas, WheelPanMode::ShiftScrollPans, false);
+        assert_eq!(unshifted, horizontal);
+    }
+
+    #[test]
+    fn the_deadband_holds_back_resting_fingers_but_not_flicks() {
+        let mut deadband = ScrollDeadband::default();
+        // resting fingers, never adding up to the deadband within a window
+        let noise: f32 = (0..60)
+            .map(|frame| deadband.filter(0.3, frame as f32 / 60., 4., 0.15))
+            .sum();
+        assert_eq!(noise, 0.);
+
+        // a flick starting small goes through whole once past the deadband, its tail too
+        let mut deadband = ScrollDeadband::default();
+        let flick = [1.5, 3., 12., 6., 0.5];
+        let through: Vec<f32> = flick
+            .iter()
+            .enumerate()
+            .map(|(frame, scroll)| deadband.filter(*scroll, 2. + frame as f32 / 60., 4., 0.15))
+            .collect();
+        assert_eq!(through, [0., 4.5, 12., 6., 0.5]);
+
+        // a mouse wheel line goes through at once
+        let mut deadband = ScrollDeadband::default();
+        assert_eq!(deadband.filter(100., 5., 4., 0.15), 100.);
+    }
+
+    /// An app zooming `pancam` in a 1920x1080 window without a cursor, at 16 ms per frame
+    fn zoom_app(pancam: PanCam) -> (App, Entity, Entity) {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(bevy::time::TimeUpdateStrategy::ManualDuration(
+                std::time::Duration::from_millis(16),
+            ))
+            .init_resource::<Input<KeyCode>>()
+            .init_resource::<MotionPreferences>()
+            .init_resource::<LastZoomInput>()
+            .add_event::<MouseWheel>()
+            .add_systems(Update, (camera_zoom, settle_elastic_zoom).chain());
+        let window = Window {
+            resolution: (1920., 1080.).into(),
+            ..default()
+        };
+        let window = app.world.spawn((window, PrimaryWindow)).id();
+        let camera = app
+            .world
+            .spawn((pancam, Transform::default(), mock_proj(vec2(1920., 1080.))))
+            .id();
+        (app, window, camera)
+    }
+
+    /// Scroll `y` pixels in a frame, none when it's zero, and return the scale of the camera
+    fn scroll(app: &mut App, window: Entity, camera: Entity, y: f32) -> f32 {
+        if y != 0. {
+            app.world.send_event(MouseWheel {
+                unit: MouseScrollUnit::Pixel,
+                x: 0.,
+                y,
+                window,
+            });
+        }
+        app.update();
+        app.world
+            .get::<OrthographicProjection>(camera)
+            .unwrap()
+            .scale
+    }
+
+    #[test]
+    fn trackpad_noise_does_not_creep_the_zoom() {
+        let (mut app, window, camera) = zoom_app(PanCam::default());
+        for _ in 0..100 {
+            assert_eq!(scroll(&mut app, window, camera, 0.2), 1.);
+        }
+        // a deliberate flick zooms in
+        let mut scale = 1.;
+        for y in [2., 8., 20., 10.] {
+            scale = scroll(&mut app, window, camera, y);
+        }
+        assert!(scale < 0.97, "{scale}");
+    }
+
+    #[test]
+    fn drags_follow_the_motion_outside_the_window_without_jumping_back_in() {
+        let mut drag = DragCursor::default();
+        assert_eq!(
+            drag.advance(Some(vec2(90., -50.)), Vec2::ZERO, true),
+            Some((vec2(90., -50.), vec2(90., -50.)))
+        );
+        assert_eq!(
+            drag.advance(Some(vec2(99., -50.)), vec2(9., 0.), true),
+            Some((vec2(90., -50.), vec2(99., -50.)))
+        );
+
+        // past the right edge, the motion carries on
+        assert_eq!(
+            drag.advance(None, vec2(20., 0.), true),
+            Some((vec2(99., -50.), vec2(119., -50.)))
+        );
+        assert_eq!(
+            drag.advance(None, vec2(5., -3.), true),
+            Some((vec2(119., -50.), vec2(124., -53.)))
+        );
+
+        // back in at the edge, only this frame's motion counts, not the distance to 124
+        assert_eq!(
+            drag.advance(Some(vec2(98., -60.)), vec2(-2., 0.), true),
+            Some((vec2(100., -60.), vec2(98., -60.)))
+        );
+        assert_eq!(
+            drag.advance(Some(vec2(95., -60.)), vec2(-3., 0.), true),
+            Some((vec2(98., -60.), vec2(95., -60.)))
+        );
+
+        // outside without a drag there is nothing to follow
+        assert_eq!(drag.advance(None, vec2(40., 0.), false), None);
+    }
+
+    #[test]
+    fn zoom_steps_move_one_step_per_notch() {
+        assert_eq!(zoom_step(&STEPS, 1., 1, 0., f32::INFINITY), 0.5);
+        assert_eq!(zoom_step(&STEPS, 1., -2, 0., f32::INFINITY), 3.);
+        // off-step scales move to the neighbouring step first
+        assert_eq!(zoom_step(&STEPS, 1.337, 1, 0., f32::INFINITY), 1.);
+        assert_eq!(zoom_step(&STEPS, 1.337, -1, 0., f32::INFINITY), 2.);
+    }
+
+    #[test]
+    fn zoom_steps_stop_at_the_ends_of_the_list() {
+        assert_eq!(zoom_step(&STEPS, 0.25, 1, 0., f32::INFINITY), 0.25);
+        assert_eq!(zoom_step(&STEPS, 0.5, 5, 0., f32::INFINITY), 0.25);
+        assert_eq!(zoom_step(&STEPS, 4., -1, 0., f32::INFINITY), 4.);
+        // unsorted lists work too
+        assert_eq!(zoom_step(&[2., 0.5, 1.], 2., -1, 0., f32::INFINITY), 2.);
+    }
+
+    #[test]
+    fn zoom_steps_respect_scale_limits_and_bounds() {
+        // min_scale skips the smallest step
+        assert_eq!(zoom_step(&STEPS, 0.5, 1, 0.3, f32::INFINITY), 0.5);
+        // bounds only allow up to 2.5, so 3 and 4 are skipped
+        let bounds_max =
+            max_scale_within_bounds(vec2(250., 250.), &mock_proj(vec2(100., 100.))).min_element();
+        assert_eq!(zoom_step(&STEPS, 2., -1, 0., bounds_max), 2.);
+        // bounds shrinking below the current scale zoom out to the largest step that fits
+        assert_eq!(zoom_step(&STEPS, 4., -1, 0., bounds_max), 2.);
+        // no usable step falls back to clamping
+        assert_eq!(zoom_step(&STEPS, 0.2, 1, 0.1, 0.2), 0.2);
+    }
+
+    /// Camera at the origin showing 100x100 world units at scale 1
+    fn zoom_camera(scale: f32) -> (OrthographicProjection, Transform) {
+        let mut proj = mock_proj(vec2(100., 100.));
+        proj.scale = scale;
+        proj.update(100., 100.);
+        (proj, Transform::from_xyz(0., 0., 999.9))
+    }
+
+    #[test]
+    fn zoom_keeps_the_anchor_in_place() {
+        let cam = PanCam::default();
+        for anchor in [vec2(0., 0.), vec2(40., -25.), vec2(-50., 50.), vec2(12.5, 3.)] {
+            for (from, to) in [(1., 0.5), (1., 2.), (0.5, 0.2)] {
+                let (mut proj, mut transform) = zoom_camera(from);
+                // position of the anchor relative to the view, from -1 to 1
+                let screen_before =
+                    (anchor - transform.translation.truncate()) / (proj.area.size() / 2.);
+
+                zoom_camera_around(&mut proj, &mut transform, anchor, to, &cam);
+
+                let screen_after =
+                    (anchor - transform.translation.truncate()) / (proj.area.size() / 2.);
+                assert_eq!(proj.scale, to);
+                assert!(
+                    screen_before.distance(screen_after) < 1e-5,
+                    "{anchor} moved from {screen_before} to {screen_after} zooming {from} -> {to}"
+                );
+                assert_eq!(transform.translation.z, 999.9);
+            }
+        }
+    }
+
+    #[test]
+    fn zoom_without_zoom_to_cursor_keeps_the_center() {
+        let cam = PanCam {
+            zoom_to_cursor: false,
+            ..default()
+        };
+        let (mut proj, mut transform) = zoom_camera(1.);
+        zoom_camera_around(&mut proj, &mut transform, vec2(40., 40.), 0.5, &cam);
+        assert_eq!(transform.translation.truncate(), Vec2::ZERO);
+        assert_eq!(proj.area.size(), vec2(50., 50.));
+    }
+
+    #[test]
+    fn zoom_clamps_scale_and_view_to_the_bounds() {
+        let mut cam = PanCam {
+            min_scale: 0.25,
+            ..default()
+        };
+        cam.set_bounds(Rect::new(-100., -100., 100., 100.));
+
+        // zooming out next to an edge pushes the view back inside
+        let (mut proj, mut transform) = zoom_camera(1.);
+        transform.translation = vec3(-50., 0., 0.);
+        zoom_camera_around(&mut proj, &mut transform, vec2(-90., 0.), 1.5, &cam);
+        assert_eq!(proj.scale, 1.5);
+        assert_eq!(transform.translation.truncate(), vec2(-25., 0.));
+
+        // the scale can't show more than the bounds, nor go under min_scale
+        zoom_camera_around(&mut proj, &mut transform, vec2(-90., 0.), 10., &cam);
+        assert_eq!(proj.scale, 2.);
+        assert_eq!(transform.translation.truncate(), Vec2::ZERO);
+        zoom_camera_around(&mut proj, &mut transform, Vec2::ZERO, 0.01, &cam);
+        assert_eq!(proj.scale, 0.25);
+
+        // bounds narrower than min_scale allows still win, the view just fills them
+        let mut narrow = PanCam {
+            min_scale: 1.,
+            ..default()
+        };
+        narrow.min_x = Some(10.);
+        narrow.max_x = Some(60.);
+        let (mut proj, mut transform) = zoom_camera(1.);
+        zoom_camera_around(&mut proj, &mut transform, vec2(20., 30.), 1., &narrow);
+        assert_eq!(proj.scale, 0.5);
+        assert_eq!(transform.translation.truncate(), vec2(35., 15.));
+    }
+
+    #[test]
+    fn elastic_zoom_answers_less_and_less_past_the_limits() {
+        let (min, max) = (0.5, 2.);
+        // inside the limits the input goes through as it is
+        assert_eq!(elastic_scale(1.3, min, max), 1.3);
+        assert_eq!(raw_scale(1.3, min, max), 1.3);
+
+        let (mut last, mut last_gain) = (max, f32::INFINITY);
+        for notch in 1..25 {
+            let raw = max * 1.05_f32.powi(notch);
+            let scale = elastic_scale(raw, min, max);
+            assert!(scale > last, "{raw} -> {scale}");
+            assert!(scale < max * ELASTIC_ZOOM_STRETCH, "{raw} -> {scale}");
+            assert!(scale - last < last_gain, "{raw} -> {scale}");
+            (last, last_gain) = (scale, scale - last);
+            // the input goes on from where the stretch shows it
+            assert!(
+                (raw_scale(scale, min, max) / raw - 1.).abs() < 1e-3,
+                "{raw}"
+            );
+        }
+
+        // zooming in past min_scale stretches the same way
+        let scale = elastic_scale(min / 4., min, max);
+        assert!(scale < min && scale > min / ELASTIC_ZOOM_STRETCH, "{scale}");
+    }
+
+    #[test]
+    fn stretched_zoom_settles_back_within_the_settle_time() {
+        let limit = 2.;
+        let settle = |from: f32, fps: f32| {
+            let frames = (ELASTIC_ZOOM_SETTLE_SECS * fps).round() as usize;
+            let mut scale = from;
+            (0..frames)
+                .map(|_| {
+                    scale = settle_step(scale, limit, 1. / fps);
+                    scale
+                })
+                .collect::<Vec<f32>>()
+        };
+
+        let trajectory = settle(limit * 1.24, 60.);
+        assert!(trajectory.windows(2).all(|w| w[1] <= w[0] && w[1] >= limit));
+        // eased rather than snapped: still stretched halfway, if mostly back
+        assert!(
+            trajectory[5] > limit && trajectory[5] < limit * 1.05,
+            "{trajectory:?}"
+        );
+        assert_eq!(trajectory.last(), Some(&limit));
+
+        // the same at a lower frame rate, and from under min_scale
+        assert_eq!(settle(limit * 1.24, 30.).last(), Some(&limit));
+        let trajectory = settle(limit / 1.24, 60.);
+        assert!(trajectory.windows(2).all(|w| w[1] >= w[0] && w[1] <= limit));
+        assert_eq!(trajectory.last(), Some(&limit));
+    }
+
+    #[test]
+    fn elastic_zoom_stays_in_the_bounds_and_settles_back() {
+        let mut pancam = PanCam {
+            max_scale: Some(1.5),
+            elastic_zoom_limits: true,
+            ..default()
+        };
+        // fits a scale of 2
+        pancam.set_bounds(Rect::new(-1920., -1080., 1920., 1080.));
+        let (mut app, window, camera) = zoom_app(pancam);
+        app.world.get_mut::<Transform>(camera).unwrap().translation = vec3(900., 500., 0.);
+        let view_in_bounds = |app: &App| {
+            let center = app.world.get::<Transform>(camera).unwrap().translation;
+            let area = app
+                .world
+                .get::<OrthographicProjection>(camera)
+                .unwrap()
+                .area;
+            let view = Rect::from_center_size(center.truncate(), area.size());
+            view.min.cmpge(vec2(-1920., -1080.) - 1e-3).all()
+                && view.max.cmple(vec2(1920., 1080.) + 1e-3).all()
+        };
+
+        // zooming out keeps answering past max_scale, less and less
+        let mut scales = Vec::new();
+        for _ in 0..15 {
+            scales.push(scroll(&mut app, window, camera, -100.));
+            assert!(view_in_bounds(&app));
+        }
+        let last = *scales.last().unwrap();
+        assert!(
+            last > 1.5 && last < 1.5 * ELASTIC_ZOOM_STRETCH,
+            "{scales:?}"
+        );
+        assert!(scales.windows(2).all(|w| w[1] > w[0]), "{scales:?}");
+
+        // once the scrolling stops it eases back to the limit
+        let mut settled = last;
+        for _ in 0..30 {
+            settled = scroll(&mut app, window, camera, 0.);
+            assert!(view_in_bounds(&app));
+        }
+        assert_eq!(settled, 1.5);
+
+        // never stretched past what the bounds fit
+        app.world.get_mut::<PanCam>(camera).unwrap().max_scale = Some(1.9);
+        for _ in 0..40 {
+            assert!(scroll(&mut app, window, camera, -100.) <= 2.);
+            assert!(view_in_bounds(&app));
+        }
+
+        // reduce motion turns the stretch off
+        for _ in 0..30 {
+            scroll(&mut app, window, camera, 0.);
+        }
+        app.world.resource_mut::<MotionPreferences>().reduce_motion = true;
+        app.world.get_mut::<PanCam>(camera).unwrap().max_scale = Some(1.5);
+        assert_eq!(scroll(&mut app, window, camera, 0.), 1.5);
+        assert_eq!(scroll(&mut app, window, camera, -100.), 1.5);
+    }
+
+    /// An app panning a camera at `translation` in a 1920x1080 window with the cursor centered
+    fn drag_app(pancam: PanCam, translation: Vec3) -> (App, Entity, Entity) {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<Input<MouseButton>>()
+            .init_resource::<Input<KeyCode>>()
+            .init_resource::<CursorWorldCache>()
+            .init_resource::<Panning>()
+            .add_event::<MouseMotion>()
+            .add_event::<WorldClick>()
+            .add_systems(Update, camera_movement);
+        let mut window = Window {
+            resolution: (1920., 1080.).into(),
+            ..default()
+        };
+        window.set_cursor_position(Some(vec2(960., 540.)));
+        let window = app.world.spawn((window, PrimaryWindow)).id();
+        let camera = app
+            .world
+            .spawn((
+                pancam,
+                DragState::default(),
+                Transform::from_translation(translation),
+                mock_proj(vec2(1920., 1080.)),
+            ))
+            .id();
+        (app, window, camera)
+    }
+
+    fn move_cursor(app: &mut App, window: Entity, cursor: Vec2) {
+        app.world.resource_mut::<Input<MouseButton>>().clear();
+        app.world
+            .get_mut::<Window>(window)
+            .unwrap()
+            .set_cursor_position(Some(cursor));
+        app.update();
+    }
+
+    #[test]
+    fn dragging_a_map_smaller_than_the_view_keeps_the_camera_still() {
+        // a 5x5 map of 24 pixel tiles, the tiles span -12..108 on both axes
+        let frame = MapFrame {
+            transform: Transform::IDENTITY,
+            grid_size: TilemapGridSize { x: 24., y: 24. },
+            map_type: TilemapType::Square,
+            map_size: TilemapSize { x: 5, y: 5 },
+        };
+        let mut pancam = PanCam::default();
+        pancam.set_bounds(coords::map_world_rect(&frame, &GlobalTransform::IDENTITY));
+        let (mut app, window, camera) = drag_app(pancam, vec3(10., -5., 0.));
+
+        app.world
+            .resource_mut::<Input<MouseButton>>()
+            .press(MouseButton::Left);
+        app.update();
+        for frame in 1..6 {
+            let cursor = vec2(960., 540.) + Vec2::splat(30. * frame as f32);
+            move_cursor(&mut app, window, cursor);
+
+            let translation = app.world.get::<Transform>(camera).unwrap().translation;
+            assert_eq!(translation, vec3(48., 48., 0.), "frame {frame}");
+        }
+    }
+
+    #[test]
+    fn the_first_frame_of_a_drag_pans_the_whole_way() {
+        let (mut app, window, camera) = drag_app(PanCam::default(), Vec3::ZERO);
+        app.world
+            .resource_mut::<Input<MouseButton>>()
+            .press(MouseButton::Middle);
+        app.update();
+        move_cursor(&mut app, window, vec2(1000., 560.));
+        let translation = app.world.get::<Transform>(camera).unwrap().translation;
+        assert_eq!(translation, vec3(-40., 20., 0.));
+        assert_eq!(*app.world.resource::<Panning>(), Panning(true));
+
+        move_cursor(&mut app, window, vec2(1010., 560.));
+        let translation = app.world.get::<Transform>(camera).unwrap().translation;
+        assert_eq!(translation, vec3(-50., 20., 0.));
+    }
+
+    #[test]
+    fn the_middle_button_can_be_left_out_of_grabbing() {
+        let pancam = PanCam {
+            middle_button_grabs: false,
+            ..default()
+        };
+        let (mut app, window, camera) = drag_app(pancam, Vec3::ZERO);
+        app.world
+            .resource_mut::<Input<MouseButton>>()
+            .press(MouseButton::Middle);
+        app.update();
+        move_cursor(&mut app, window, vec2(1000., 560.));
+        assert_eq!(
+            app.world.get::<Transform>(camera).unwrap().translation,
+            Vec3::ZERO
+        );
+    }
+
+    #[test]
+    fn bounds_follow_offset_and_scaled_map() {
+        let frame = MapFrame {
+            transform: Transform::IDENTITY,
+            grid_size: TilemapGridSize { x: 24., y: 24. },
+            map_type: TilemapType::Square,
+            map_size: TilemapSize { x: 10, y: 5 },
+        };
+        let map_transform = Transform::from_xyz(-40., 30., 0.1).with_scale(Vec3::splat(2.));
+
+        let mut pancam = PanCam::default();
+        pancam.set_bounds(coords::map_world_rect(
+            &frame,
+            &GlobalTransform::from(map_transform),
+        ));
+
+        // tiles span -12..228 by -12..108 in map space, then doubled and offset
+        assert_eq!(pancam.min_x, Some(-64.));
+        assert_eq!(pancam.max_x, Some(416.));
+        assert_eq!(pancam.min_y, Some(6.));
+        assert_eq!(pancam.max_y, Some(246.));
+    }
+
+    /// The focus `PanCamSystemSet` saw in each frame
+    #[derive(Resource, Default)]
+    struct SeenFocus(Vec<bool>);
+
+    fn record_focus(focus: Res<EguiWantsFocus>, mut seen: ResMut<SeenFocus>) {
+        seen.0.push(focus.0);
+    }
+
+    /// Run an egui pass with the pointer at `pointer`, showing a window under it when `open`
+    fn egui_pass(ctx: &mut bevy_egui::EguiContext, pointer: egui::Pos2, open: bool) {
+        let input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(
+                egui::Pos2::ZERO,
+                egui::vec2(800., 600.),
+            )),
+            events: vec![egui::Event::PointerMoved(pointer)],
+            ..default()
+        };
+        let _ = ctx.get_mut().run(input, |ctx| {
+            if open {
+                egui::Window::new("Inventory")
+                    .fixed_pos(egui::pos2(10., 10.))
+                    .show(ctx, |ui| ui.label("a sword"));
+            }
+        });
+    }
+
+    #[test]
+    fn egui_focus_is_current_in_the_frame_it_changes() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<EguiWantsFocus>()
+            .init_resource::<SeenFocus>()
+            .add_systems(PreUpdate, check_egui_wants_focus)
+            .add_systems(Update, record_focus);
+        let mut window = Window::default();
+        window.set_cursor_position(Some(vec2(20., 20.)));
+        let window = app
+            .world
+            .spawn((window, bevy_egui::EguiContext::default()))
+            .id();
+        let pointer = egui::pos2(20., 20.);
+        let pass = |app: &mut App, open: bool| {
+            let mut ctx = app.world.get_mut::<bevy_egui::EguiContext>(window).unwrap();
+            egui_pass(&mut ctx, pointer, open);
+        };
+
+        app.update();
+        // a window opened under the cursor takes the very next click
+        pass(&mut app, true);
+        app.update();
+        // and closing it gives the clicks back to the camera right away
+        pass(&mut app, false);
+        app.update();
+
+        assert_eq!(app.world.resource::<SeenFocus>().0, [false, true, false]);
+    }
 }
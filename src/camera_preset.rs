@@ -0,0 +1,331 @@
+//! Sharing the tuning of the `PanCam` between projects as RON presets.
+//!
+//! A `PanCamPreset` holds every field of the `PanCam` that is tuned by hand: the buttons come
+//! from the `MouseInputMap` and the bounds from the map, so they stay out of it. The inspector
+//! saves the camera's tuning to a preset file and loads one back, and the preset named in the
+//! `CameraPresetSettings` is loaded on every camera spawned. Loading one clamps the camera to its
+//! new limits at once.
+
+use std::path::Path;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::accessibility::Preferences;
+use crate::camera::{self, PanCam, WheelPanMode};
+use crate::game::GameSet;
+use crate::toast::Toast;
+
+/// File the inspector saves the preset to until another one is picked
+pub const DEFAULT_PRESET_FILE: &str = "camera_preset.ron";
+
+/// Plugin that loads the startup `PanCamPreset` on the cameras spawned
+#[derive(Default)]
+pub struct CameraPresetPlugin;
+
+impl Plugin for CameraPresetPlugin {
+    fn build(&self, app: &mut App) {
+        let settings = app.world.resource::<Preferences>().camera_preset.clone();
+        app.insert_resource(PresetPathField(
+            settings
+                .startup_preset
+                .clone()
+                .unwrap_or_else(|| DEFAULT_PRESET_FILE.to_string()),
+        ))
+        .insert_resource(settings)
+        .register_type::<CameraPresetSettings>()
+        .add_systems(Update, apply_startup_preset.in_set(GameSet::Input));
+    }
+}
+
+/// The preset loaded on the cameras when they're spawned
+#[derive(Resource, Reflect, Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[reflect(Resource)]
+#[serde(default)]
+pub struct CameraPresetSettings {
+    /// Path of the preset file, none loaded when `None`
+    pub startup_preset: Option<String>,
+}
+
+/// Path typed in the inspector's preset section
+#[derive(Resource, Debug)]
+struct PresetPathField(String);
+
+#[derive(Debug, Error)]
+pub enum PresetError {
+    #[error("could not read or write the preset: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("unreadable preset: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("could not write the preset: {0}")]
+    Serialize(#[from] ron::Error),
+}
+
+/// The tunable fields of a `PanCam`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct PanCamPreset {
+    pub middle_button_grabs: bool,
+    pub grab_modifiers: Vec<KeyCode>,
+    pub drag_threshold: f32,
+    pub confine_cursor_while_dragging: bool,
+    pub zoom_to_cursor: bool,
+    pub min_scale: f32,
+    pub max_scale: Option<f32>,
+    pub elastic_zoom_limits: bool,
+    pub zoom_steps: Option<Vec<f32>>,
+    pub wheel_pan: WheelPanMode,
+    pub scroll_deadband: f32,
+    pub scroll_debounce_secs: f32,
+}
+
+impl Default for PanCamPreset {
+    fn default() -> Self {
+        Self::from_cam(&PanCam::default())
+    }
+}
+
+impl PanCamPreset {
+    /// The tuning of `cam`
+    pub fn from_cam(cam: &PanCam) -> Self {
+        Self {
+            middle_button_grabs: cam.middle_button_grabs,
+            grab_modifiers: cam.grab_modifiers.clone(),
+            drag_threshold: cam.drag_threshold,
+            confine_cursor_while_dragging: cam.confine_cursor_while_dragging,
+            zoom_to_cursor: cam.zoom_to_cursor,
+            min_scale: cam.min_scale,
+            max_scale: cam.max_scale,
+            elastic_zoom_limits: cam.elastic_zoom_limits,
+            zoom_steps: cam.zoom_steps.clone(),
+            wheel_pan: cam.wheel_pan,
+            scroll_deadband: cam.scroll_deadband,
+            scroll_debounce_secs: cam.scroll_debounce_secs,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, PresetError> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(ron::from_str(&text)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), PresetError> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+}
+
+impl PanCam {
+    /// Take the tuning of `preset`, leaving the buttons, the bounds and whether it's enabled
+    ///
+    /// The camera has to be clamped with `camera::clamp_camera` afterwards, for its scale and
+    /// position to follow the new limits.
+    pub fn apply_preset(&mut self, preset: &PanCamPreset) {
+        self.middle_button_grabs = preset.middle_button_grabs;
+        self.grab_modifiers = preset.grab_modifiers.clone();
+        self.drag_threshold = preset.drag_threshold.max(0.);
+        self.confine_cursor_while_dragging = preset.confine_cursor_while_dragging;
+        self.zoom_to_cursor = preset.zoom_to_cursor;
+        // a scale of zero or less would flip or collapse the view
+        self.min_scale = preset.min_scale.max(PanCam::default().min_scale);
+        self.max_scale = preset.max_scale.map(|max| max.max(self.min_scale));
+        self.elastic_zoom_limits = preset.elastic_zoom_limits;
+        self.zoom_steps = preset.zoom_steps.clone();
+        self.wheel_pan = preset.wheel_pan;
+        self.scroll_deadband = preset.scroll_deadband.max(0.);
+        self.scroll_debounce_secs = preset.scroll_debounce_secs.max(0.);
+    }
+}
+
+/// Apply `preset` to every camera and clamp them to their new limits
+fn apply_to_cameras(world: &mut World, preset: &PanCamPreset) {
+    let mut cameras = world.query::<(&mut PanCam, &mut OrthographicProjection, &mut Transform)>();
+    for (mut cam, mut proj, mut transform) in cameras.iter_mut(world) {
+        cam.apply_preset(preset);
+        camera::clamp_camera(&mut proj, &mut transform, &cam);
+    }
+}
+
+fn apply_startup_preset(
+    settings: Res<CameraPresetSettings>,
+    mut cameras: Query<(&mut PanCam, &mut OrthographicProjection, &mut Transform), Added<PanCam>>,
+) {
+    let Some(path) = settings.startup_preset.as_deref() else {
+        return;
+    };
+    if cameras.is_empty() {
+        return;
+    }
+    let preset = match PanCamPreset::load(Path::new(path)) {
+        Ok(preset) => preset,
+        Err(err) => {
+            warn!("can't load the camera preset {path}: {err}");
+            return;
+        }
+    };
+    for (mut cam, mut proj, mut transform) in &mut cameras {
+        cam.apply_preset(&preset);
+        camera::clamp_camera(&mut proj, &mut transform, &cam);
+    }
+}
+
+/// Inspector section saving the camera's tuning to a preset file and loading one back
+pub fn camera_preset_ui(world: &mut World, ui: &mut egui::Ui) {
+    egui::CollapsingHeader::new("Camera preset").show(ui, |ui| {
+        let mut path = world.resource::<PresetPathField>().0.clone();
+        ui.text_edit_singleline(&mut path);
+        let startup = world
+            .resource::<CameraPresetSettings>()
+            .startup_preset
+            .clone();
+        let mut load_at_startup = startup.as_deref() == Some(path.as_str());
+        if ui
+            .checkbox(&mut load_at_startup, "Load at startup")
+            .changed()
+        {
+            world.resource_mut::<CameraPresetSettings>().startup_preset =
+                load_at_startup.then(|| path.clone());
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Save preset").clicked() {
+                save_preset(world, &path);
+            }
+            if ui.button("Load preset").clicked() {
+                load_preset(world, &path);
+            }
+        });
+        if world.resource::<PresetPathField>().0 != path {
+            world.resource_mut::<PresetPathField>().0 = path;
+        }
+    });
+}
+
+/// Save the tuning of the first camera to `path` and report the result in a toast
+fn save_preset(world: &mut World, path: &str) {
+    let cam = world.query::<&PanCam>().iter(world).next();
+    let Some(preset) = cam.map(PanCamPreset::from_cam) else {
+        world.send_event(Toast::error("Saving the preset failed: no camera"));
+        return;
+    };
+    let toast = match preset.save(Path::new(path)) {
+        Ok(()) => Toast::info(format!("Saved the camera preset to {path}")),
+        Err(err) => Toast::error(format!("Saving the preset failed: {err}")),
+    };
+    world.send_event(toast);
+}
+
+/// Load the preset at `path` on every camera and report the result in a toast
+fn load_preset(world: &mut World, path: &str) {
+    let toast = match PanCamPreset::load(Path::new(path)) {
+        Ok(preset) => {
+            apply_to_cameras(world, &preset);
+            Toast::info(format!("Loaded the camera preset {path}"))
+        }
+        Err(err) => Toast::error(format!("Loading the preset failed: {err}")),
+    };
+    world.send_event(toast);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::vec2;
+
+    use super::*;
+
+    fn tuned_preset() -> PanCamPreset {
+        PanCamPreset {
+            middle_button_grabs: false,
+            grab_modifiers: vec![KeyCode::Space],
+            drag_threshold: 8.,
+            confine_cursor_while_dragging: true,
+            zoom_to_cursor: false,
+            min_scale: 0.5,
+            max_scale: Some(2.),
+            elastic_zoom_limits: true,
+            zoom_steps: Some(vec![0.5, 1., 2.]),
+            wheel_pan: WheelPanMode::ShiftScrollPans,
+            scroll_deadband: 2.,
+            scroll_debounce_secs: 0.3,
+        }
+    }
+
+    #[test]
+    fn presets_round_trip_through_ron() {
+        let preset = tuned_preset();
+        let text = ron::ser::to_string_pretty(&preset, ron::ser::PrettyConfig::default()).unwrap();
+        assert_eq!(ron::from_str::<PanCamPreset>(&text).unwrap(), preset);
+        // fields left out keep their defaults
+        let partial: PanCamPreset = ron::from_str("(drag_threshold: 6.)").unwrap();
+        assert_eq!(partial.drag_threshold, 6.);
+        assert_eq!(partial.min_scale, PanCamPreset::default().min_scale);
+
+        let path = std::env::temp_dir().join(format!(
+            "bevy_test_camera_preset_{}.ron",
+            std::process::id()
+        ));
+        preset.save(&path).unwrap();
+        assert_eq!(PanCamPreset::load(&path).unwrap(), preset);
+        std::fs::write(&path, "(min_scale: ").unwrap();
+        assert!(matches!(
+            PanCamPreset::load(&path),
+            Err(PresetError::Parse(_))
+        ));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn applying_a_preset_keeps_the_buttons_and_bounds() {
+        let mut cam = PanCam {
+            grab_buttons: vec![MouseButton::Left],
+            enabled: false,
+            ..default()
+        };
+        cam.set_bounds(Rect::new(0., 0., 100., 100.));
+        cam.apply_preset(&tuned_preset());
+        assert_eq!(PanCamPreset::from_cam(&cam), tuned_preset());
+        assert_eq!(cam.grab_buttons, [MouseButton::Left]);
+        assert!(!cam.enabled);
+        assert_eq!((cam.min_x, cam.max_y), (Some(0.), Some(100.)));
+
+        // impossible limits are straightened out
+        cam.apply_preset(&PanCamPreset {
+            min_scale: -1.,
+            max_scale: Some(-2.),
+            ..tuned_preset()
+        });
+        assert!(cam.min_scale > 0.);
+        assert_eq!(cam.max_scale, Some(cam.min_scale));
+    }
+
+    #[test]
+    fn a_loaded_preset_clamps_the_camera_at_once() {
+        let mut cam = PanCam::default();
+        cam.set_bounds(Rect::new(-500., -500., 500., 500.));
+        // a 100x100 window zoomed out to 4
+        let mut proj = OrthographicProjection {
+            scale: 4.,
+            area: Rect::from_center_size(Vec2::ZERO, vec2(400., 400.)),
+            ..default()
+        };
+        let mut transform = Transform::from_xyz(450., -450., 10.);
+
+        cam.apply_preset(&tuned_preset());
+        camera::clamp_camera(&mut proj, &mut transform, &cam);
+        assert_eq!(proj.scale, 2.);
+        assert_eq!(proj.area.size(), vec2(200., 200.));
+        // back inside the bounds with the smaller view, on the same z
+        assert_eq!(transform.translation, Vec3::new(400., -400., 10.));
+
+        cam.apply_preset(&PanCamPreset {
+            max_scale: Some(20.),
+            min_scale: 15.,
+            ..tuned_preset()
+        });
+        camera::clamp_camera(&mut proj, &mut transform, &cam);
+        // the bounds only fit 1000 / 100
+        assert_eq!(proj.scale, 10.);
+        assert_eq!(transform.translation, Vec3::new(0., 0., 10.));
+    }
+}
@@ -0,0 +1,400 @@
+//! Scripted camera moves, for cutscenes started by triggers or the console.
+//!
+//! A `CameraRig` on a camera plays its keyframes one after the other, easing the camera's center
+//! and zoom to each target over the keyframe's duration. While the rig is there the camera
+//! ignores the player's panning and zooming; the moves go through the same bounds clamping as
+//! theirs. The rig removes itself when its last keyframe is done, or when the player presses a
+//! key or a mouse button and the rig is `skippable`, and sends `CameraRigFinished`.
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::TilePos;
+
+use crate::camera::{self, MainCamera, PanCam, PanCamSystemSet};
+use crate::console::{console_closed, ConsoleCommand, DebugConsole};
+use crate::coords::{self, MapFrame};
+use crate::game::GameSet;
+use crate::state::level_ready;
+use crate::tween::Ease;
+
+/// Plugin that plays the `CameraRig`s and starts them from `MoveCamera` events
+#[derive(Default)]
+pub struct CameraRigPlugin;
+
+impl Plugin for CameraRigPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<MoveCamera>()
+            .add_event::<CameraRigFinished>()
+            .register_type::<CameraRig>()
+            .add_systems(
+                Update,
+                (cam_command, skip_camera_rigs.run_if(console_closed))
+                    .in_set(GameSet::Input)
+                    .run_if(level_ready),
+            )
+            .add_systems(
+                Update,
+                (queue_camera_moves, run_camera_rigs)
+                    .chain()
+                    .in_set(GameSet::Camera)
+                    .before(PanCamSystemSet)
+                    .run_if(level_ready),
+            );
+    }
+}
+
+/// A camera move of a `CameraRig`
+#[derive(Reflect, Debug, Clone, Copy, PartialEq)]
+pub struct CameraKeyframe {
+    /// World position the camera centers on
+    pub target_pos: Vec2,
+    /// Scale of the orthographic projection at the end, larger is zoomed out
+    pub target_scale: f32,
+    /// Seconds the move takes
+    pub duration: f32,
+    pub easing: Ease,
+}
+
+/// Moves the camera through `keyframes` instead of the player's input
+#[derive(Component, Reflect, Debug, Clone, Default)]
+#[reflect(Component)]
+pub struct CameraRig {
+    /// Moves still to play, the first one playing
+    pub keyframes: Vec<CameraKeyframe>,
+    /// Whether a key or mouse button press ends the rig
+    pub skippable: bool,
+    /// Center and scale the playing move started from
+    from: Option<(Vec2, f32)>,
+    /// Seconds into the playing move
+    elapsed: f32,
+}
+
+impl CameraRig {
+    pub fn new(keyframes: Vec<CameraKeyframe>, skippable: bool) -> Self {
+        Self {
+            keyframes,
+            skippable,
+            ..default()
+        }
+    }
+}
+
+/// Sent when the `CameraRig` of `camera` is done, or was skipped
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CameraRigFinished {
+    pub camera: Entity,
+    pub skipped: bool,
+}
+
+/// Ask for the main camera to make `keyframe`'s move, after the moves it's already making
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct MoveCamera {
+    pub keyframe: CameraKeyframe,
+    pub skippable: bool,
+}
+
+/// Give the main camera a rig, or queue the moves on the one it has
+fn queue_camera_moves(
+    mut commands: Commands,
+    mut moves: EventReader<MoveCamera>,
+    mut cameras: Query<(Entity, Option<&mut CameraRig>), With<MainCamera>>,
+) {
+    let Ok((camera, mut rig)) = cameras.get_single_mut() else {
+        moves.clear();
+        return;
+    };
+    let mut new_rig: Option<CameraRig> = None;
+    for event in moves.read() {
+        let rig = match rig.as_deref_mut() {
+            Some(rig) => rig,
+            None => new_rig.get_or_insert_with(|| CameraRig::new(Vec::new(), true)),
+        };
+        rig.keyframes.push(event.keyframe);
+        // a move that has to be watched keeps the whole rig from being skipped
+        rig.skippable &= event.skippable;
+    }
+    if let Some(rig) = new_rig {
+        commands.entity(camera).insert(rig);
+    }
+}
+
+/// Ease the cameras through the moves of their rigs
+pub fn run_camera_rigs(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut rigs: Query<(
+        Entity,
+        &PanCam,
+        &mut CameraRig,
+        &mut Transform,
+        &mut OrthographicProjection,
+    )>,
+    mut finished: EventWriter<CameraRigFinished>,
+) {
+    for (camera, cam, mut rig, mut transform, mut proj) in &mut rigs {
+        let rig = &mut *rig;
+        let mut delta = time.delta_seconds();
+        // a move ending during the frame leaves the rest of the frame to the next one
+        while let Some(keyframe) = rig.keyframes.first().copied() {
+            let (from_pos, from_scale) = *rig
+                .from
+                .get_or_insert((transform.translation.truncate(), proj.scale));
+            let remaining = keyframe.duration - rig.elapsed;
+            let done = delta >= remaining;
+            let step = if done { remaining.max(0.) } else { delta };
+            rig.elapsed += step;
+            delta -= step;
+            let t = if done {
+                1.
+            } else {
+                rig.elapsed / keyframe.duration
+            };
+            let eased = keyframe.easing.sample(t);
+
+            let scale = from_scale + (keyframe.target_scale - from_scale) * eased;
+            let center = transform.translation.truncate();
+            camera::zoom_camera_around(&mut proj, &mut transform, center, scale, cam);
+            let pos = from_pos.lerp(keyframe.target_pos, eased);
+            camera::snap_camera_to(pos, &proj, &mut transform, cam);
+
+            if !done {
+                break;
+            }
+            rig.keyframes.remove(0);
+            rig.from = None;
+            rig.elapsed = 0.;
+        }
+        if rig.keyframes.is_empty() {
+            commands.entity(camera).remove::<CameraRig>();
+            finished.send(CameraRigFinished {
+                camera,
+                skipped: false,
+            });
+        }
+    }
+}
+
+/// End the skippable rigs on a key or mouse button press, leaving the camera where it got to
+fn skip_camera_rigs(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    rigs: Query<(Entity, &CameraRig)>,
+    mut finished: EventWriter<CameraRigFinished>,
+) {
+    let pressed = keys.get_just_pressed().next().is_some()
+        || mouse_buttons.get_just_pressed().next().is_some();
+    if !pressed {
+        return;
+    }
+    for (camera, rig) in &rigs {
+        if rig.skippable {
+            commands.entity(camera).remove::<CameraRig>();
+            finished.send(CameraRigFinished {
+                camera,
+                skipped: true,
+            });
+        }
+    }
+}
+
+/// `cam moveto <x> <y> <scale> <secs> [skippable]` eases the camera to the center of a tile
+fn cam_command(
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<DebugConsole>,
+    map_frame: Option<Res<MapFrame>>,
+    mut moves: EventWriter<MoveCamera>,
+) {
+    for command in console_commands.read() {
+        if command.name != "cam" {
+            continue;
+        }
+        let args: Vec<_> = command.args.iter().map(String::as_str).collect();
+        let (keyframe, skippable) = match args.as_slice() {
+            ["moveto", x, y, scale, secs, rest @ ..]
+                if rest.is_empty() || rest == ["skippable"] =>
+            {
+                (
+                    (x.parse(), y.parse(), scale.parse(), secs.parse()),
+                    !rest.is_empty(),
+                )
+            }
+            _ => {
+                console.print("usage: cam moveto <x> <y> <scale> <secs> [skippable]");
+                continue;
+            }
+        };
+        let (Ok(x), Ok(y), Ok(scale), Ok(secs)) = keyframe else {
+            console.print("x and y must be tile numbers, scale and secs numbers");
+            continue;
+        };
+        let target = map_frame
+            .as_deref()
+            .and_then(|map_frame| coords::tile_to_world_center(map_frame, &TilePos::new(x, y)));
+        let Some(target_pos) = target else {
+            console.print(format!("({x}, {y}) is outside the map"));
+            continue;
+        };
+        moves.send(MoveCamera {
+            keyframe: CameraKeyframe {
+                target_pos,
+                target_scale: scale,
+                duration: secs,
+                easing: Ease::QuadOut,
+            },
+            skippable,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+
+    fn keyframe(x: f32, scale: f32, duration: f32) -> CameraKeyframe {
+        CameraKeyframe {
+            target_pos: Vec2::new(x, 0.),
+            target_scale: scale,
+            duration,
+            easing: Ease::Linear,
+        }
+    }
+
+    fn rig_app() -> (App, Entity) {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                100,
+            )))
+            .add_event::<MoveCamera>()
+            .add_event::<CameraRigFinished>()
+            .add_systems(Update, (queue_camera_moves, run_camera_rigs).chain());
+        let projection = OrthographicProjection {
+            area: Rect::from_center_size(Vec2::ZERO, Vec2::new(100., 100.)),
+            ..default()
+        };
+        let camera = app
+            .world
+            .spawn((
+                MainCamera,
+                PanCam {
+                    min_x: Some(-1000.),
+                    max_x: Some(200.),
+                    ..default()
+                },
+                Transform::default(),
+                projection,
+            ))
+            .id();
+        // the first frame has no delta
+        app.update();
+        (app, camera)
+    }
+
+    fn finished(app: &App) -> Vec<CameraRigFinished> {
+        let events = app.world.resource::<Events<CameraRigFinished>>();
+        events.get_reader().read(events).copied().collect()
+    }
+
+    #[test]
+    fn queued_moves_play_in_turn_and_finish() {
+        let (mut app, camera) = rig_app();
+        for keyframe in [keyframe(-20., 2., 0.2), keyframe(-40., 1., 0.4)] {
+            app.world.send_event(MoveCamera {
+                keyframe,
+                skippable: true,
+            });
+        }
+        app.update();
+        let rig = app.world.get::<CameraRig>(camera).unwrap();
+        assert_eq!(rig.keyframes.len(), 2);
+
+        app.update();
+        let x = app.world.get::<Transform>(camera).unwrap().translation.x;
+        assert!(
+            (x + 10.).abs() < 1e-3,
+            "halfway through the first move, at {x}"
+        );
+        assert!(
+            (app.world
+                .get::<OrthographicProjection>(camera)
+                .unwrap()
+                .scale
+                - 1.5)
+                .abs()
+                < 1e-3
+        );
+
+        for _ in 0..6 {
+            app.update();
+        }
+        assert!(app.world.get::<CameraRig>(camera).is_none());
+        let x = app.world.get::<Transform>(camera).unwrap().translation.x;
+        assert!((x + 40.).abs() < 1e-3);
+        assert_eq!(
+            app.world
+                .get::<OrthographicProjection>(camera)
+                .unwrap()
+                .scale,
+            1.
+        );
+        assert_eq!(
+            finished(&app),
+            [CameraRigFinished {
+                camera,
+                skipped: false
+            }]
+        );
+    }
+
+    #[test]
+    fn moves_stay_in_the_camera_bounds() {
+        let (mut app, camera) = rig_app();
+        app.world.send_event(MoveCamera {
+            keyframe: keyframe(500., 1., 0.1),
+            skippable: false,
+        });
+        app.update();
+        app.update();
+        // the right edge of the 100 wide view stops at 200
+        let x = app.world.get::<Transform>(camera).unwrap().translation.x;
+        assert_eq!(x, 150.);
+    }
+
+    #[test]
+    fn a_press_skips_a_skippable_rig_only() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<Input<KeyCode>>()
+            .init_resource::<Input<MouseButton>>()
+            .add_event::<CameraRigFinished>()
+            .add_systems(Update, skip_camera_rigs);
+        let watched = app
+            .world
+            .spawn(CameraRig::new(vec![keyframe(1., 1., 1.)], false))
+            .id();
+        let skippable = app
+            .world
+            .spawn(CameraRig::new(vec![keyframe(1., 1., 1.)], true))
+            .id();
+        app.update();
+        assert!(app.world.get::<CameraRig>(skippable).is_some());
+
+        app.world
+            .resource_mut::<Input<MouseButton>>()
+            .press(MouseButton::Left);
+        app.update();
+        assert!(app.world.get::<CameraRig>(skippable).is_none());
+        assert!(app.world.get::<CameraRig>(watched).is_some());
+        assert_eq!(
+            finished(&app),
+            [CameraRigFinished {
+                camera: skippable,
+                skipped: true
+            }]
+        );
+    }
+}
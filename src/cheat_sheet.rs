@@ -0,0 +1,284 @@
+//! An overlay listing the key bindings, opened with F1 or `?`.
+//!
+//! The list is the `KeyBindings` resource, built from the key constants of the modules reading
+//! the keys. Keys bound to more than one action are shown in red. While the overlay is open the
+//! keyboard is swallowed, so pressing a key to read about it doesn't also trigger it.
+
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use bevy_inspector_egui::bevy_egui::EguiContext;
+use bevy_window::PrimaryWindow;
+
+use crate::accessibility::SETTINGS_KEY;
+use crate::asset_reload::RELOAD_ASSETS_KEY;
+use crate::camera::{ZOOM_IN_KEYS, ZOOM_OUT_KEYS};
+use crate::collision::COST_OVERLAY_KEY;
+use crate::console::{DebugConsole, CONSOLE_KEY};
+use crate::debug_info::COPY_KEY;
+use crate::debug_step::{PAUSE_KEY, STEP_KEY};
+use crate::editor::{EDITOR_KEY, UNDO_KEY};
+use crate::event_log::EVENT_LOG_KEY;
+use crate::game::GameSet;
+use crate::interact::INTERACT_KEYS;
+use crate::inventory::INVENTORY_KEY;
+use crate::minimap::MINIMAP_KEY;
+use crate::path_debug::{COLLISION_KEY, PATHS_KEY};
+use crate::perf::PERF_KEY;
+use crate::player::MOVE_KEYS;
+use crate::region_editor::DELETE_REGION_KEY;
+use crate::save_slots::SAVES_KEY;
+use crate::selection::CYCLE_KEY;
+use crate::targeting::TARGETING_KEY;
+
+/// Key that opens and closes the cheat sheet, typing `?` does too
+pub const CHEAT_SHEET_KEY: KeyCode = KeyCode::F1;
+/// Color of the keys bound to more than one action
+const CONFLICT_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 100, 90);
+
+/// Plugin that shows the key bindings overlay and blocks the keyboard while it's open
+#[derive(Default)]
+pub struct CheatSheetPlugin;
+
+impl Plugin for CheatSheetPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CheatSheet>()
+            .init_resource::<KeyBindings>()
+            .add_systems(PreUpdate, toggle_cheat_sheet.after(InputSystem))
+            .add_systems(
+                Update,
+                show_cheat_sheet
+                    .in_set(GameSet::RenderPrep)
+                    .run_if(resource_equals(CheatSheet { open: true })),
+            );
+    }
+}
+
+#[derive(Resource, Default, Debug, PartialEq, Eq)]
+pub struct CheatSheet {
+    pub open: bool,
+}
+
+/// A key binding as the cheat sheet lists it
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyBinding {
+    pub category: &'static str,
+    pub action: String,
+    /// Held with the keys, like "Ctrl+Shift", empty for none
+    pub modifiers: &'static str,
+    /// Each of them triggers the action
+    pub keys: Vec<KeyCode>,
+}
+
+impl KeyBinding {
+    fn new(category: &'static str, action: impl Into<String>, keys: &[KeyCode]) -> Self {
+        Self {
+            category,
+            action: action.into(),
+            modifiers: "",
+            keys: keys.to_vec(),
+        }
+    }
+
+    fn with_modifiers(mut self, modifiers: &'static str) -> Self {
+        self.modifiers = modifiers;
+        self
+    }
+
+    /// The keys as written on the sheet, like "Ctrl+Shift+C" or "Return / Space"
+    pub fn keys_text(&self) -> String {
+        self.keys
+            .iter()
+            .map(|key| {
+                if self.modifiers.is_empty() {
+                    format!("{key:?}")
+                } else {
+                    format!("{}+{key:?}", self.modifiers)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" / ")
+    }
+}
+
+/// The key bindings of the game, grouped by category
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct KeyBindings(pub Vec<KeyBinding>);
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let copy_debug_info = KeyBinding::new("Debug", "Copy debug info", &[COPY_KEY]);
+        let reload_assets = KeyBinding::new("Debug", "Reload assets file", &[RELOAD_ASSETS_KEY]);
+        let mut bindings: Vec<_> = MOVE_KEYS
+            .iter()
+            .map(|(dir, keys)| KeyBinding::new("Movement", direction_name(*dir), keys))
+            .collect();
+        bindings.extend([
+            KeyBinding::new("Actions", "Interact", &INTERACT_KEYS),
+            KeyBinding::new(
+                "Actions",
+                "Aim (hold), release to confirm",
+                &[TARGETING_KEY],
+            ),
+            KeyBinding::new("Actions", "Inventory", &[INVENTORY_KEY]),
+            KeyBinding::new("Actions", "Select next creature", &[CYCLE_KEY]),
+            KeyBinding::new("Actions", "Select previous creature", &[CYCLE_KEY])
+                .with_modifiers("Shift"),
+            KeyBinding::new("Game", "Saves", &[SAVES_KEY]),
+            KeyBinding::new("Game", "Minimap", &[MINIMAP_KEY]),
+            KeyBinding::new("Game", "Settings", &[SETTINGS_KEY]),
+            KeyBinding::new("Camera", "Zoom in", &ZOOM_IN_KEYS),
+            KeyBinding::new("Camera", "Zoom out", &ZOOM_OUT_KEYS),
+            KeyBinding::new("Debug", "Key bindings", &[CHEAT_SHEET_KEY]),
+            KeyBinding::new("Debug", "Console", &[CONSOLE_KEY]),
+            KeyBinding::new("Debug", "Movement cost overlay", &[COST_OVERLAY_KEY]),
+            KeyBinding::new("Debug", "Path overlay", &[PATHS_KEY]),
+            KeyBinding::new("Debug", "Collision overlay", &[COLLISION_KEY]),
+            KeyBinding::new("Debug", "Pause gameplay", &[PAUSE_KEY]),
+            KeyBinding::new("Debug", "Step one frame", &[STEP_KEY]),
+            KeyBinding::new("Debug", "Event log", &[EVENT_LOG_KEY]),
+            KeyBinding::new("Debug", "Perf overlay", &[PERF_KEY]),
+            copy_debug_info.with_modifiers("Ctrl+Shift"),
+            reload_assets.with_modifiers("Ctrl+Shift"),
+            KeyBinding::new("Editor", "Editor mode", &[EDITOR_KEY]),
+            KeyBinding::new("Editor", "Undo edit", &[UNDO_KEY]).with_modifiers("Ctrl"),
+            KeyBinding::new("Editor", "Delete region", &[DELETE_REGION_KEY]),
+        ]);
+        Self(bindings)
+    }
+}
+
+impl KeyBindings {
+    /// Keys, with their modifiers, bound to more than one action
+    pub fn conflicts(&self) -> HashSet<(&'static str, KeyCode)> {
+        let mut uses: HashMap<(&'static str, KeyCode), usize> = HashMap::default();
+        for binding in &self.0 {
+            let keys: HashSet<_> = binding.keys.iter().copied().collect();
+            for key in keys {
+                *uses.entry((binding.modifiers, key)).or_default() += 1;
+            }
+        }
+        uses.into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|(key, _)| key)
+            .collect()
+    }
+}
+
+fn direction_name(dir: IVec2) -> String {
+    let vertical = match dir.y {
+        1 => "north",
+        -1 => "south",
+        _ => "",
+    };
+    let horizontal = match dir.x {
+        1 => "east",
+        -1 => "west",
+        _ => "",
+    };
+    let separator = if vertical.is_empty() || horizontal.is_empty() {
+        ""
+    } else {
+        "-"
+    };
+    format!("Move {vertical}{separator}{horizontal}")
+}
+
+fn toggle_cheat_sheet(
+    mut keys: ResMut<Input<KeyCode>>,
+    mut typed: EventReader<ReceivedCharacter>,
+    console: Res<DebugConsole>,
+    mut sheet: ResMut<CheatSheet>,
+) {
+    // a `?` typed into the console is part of the command
+    let question_mark = typed.read().any(|typed| typed.char == '?') && !console.open;
+    if keys.just_pressed(CHEAT_SHEET_KEY) || question_mark {
+        sheet.open = !sheet.open;
+    }
+    if sheet.open {
+        keys.reset_all();
+    }
+}
+
+fn show_cheat_sheet(
+    mut sheet: ResMut<CheatSheet>,
+    bindings: Res<KeyBindings>,
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+) {
+    let Some(mut ctx) = contexts.iter_mut().next() else {
+        return;
+    };
+    let conflicts = bindings.conflicts();
+
+    egui::Window::new("Key bindings")
+        .open(&mut sheet.open)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx.get_mut(), |ui| {
+            egui::Grid::new("cheat_sheet").striped(true).show(ui, |ui| {
+                let mut category = "";
+                for binding in &bindings.0 {
+                    if binding.category != category {
+                        category = binding.category;
+                        ui.strong(category);
+                        ui.end_row();
+                    }
+                    let conflicting = binding
+                        .keys
+                        .iter()
+                        .any(|key| conflicts.contains(&(binding.modifiers, *key)));
+                    ui.label(&binding.action);
+                    if conflicting {
+                        ui.colored_label(CONFLICT_COLOR, binding.keys_text());
+                    } else {
+                        ui.label(binding.keys_text());
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_bound_twice_conflict() {
+        let mut bindings = KeyBindings::default();
+        assert!(bindings.conflicts().is_empty());
+
+        bindings
+            .0
+            .push(KeyBinding::new("Debug", "Screenshot", &[KeyCode::C]));
+        assert_eq!(bindings.conflicts(), HashSet::from_iter([("", KeyCode::C)]));
+    }
+
+    #[test]
+    fn the_open_sheet_swallows_the_keys() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<Input<KeyCode>>()
+            .init_resource::<DebugConsole>()
+            .init_resource::<CheatSheet>()
+            .add_event::<ReceivedCharacter>()
+            .add_systems(Update, toggle_cheat_sheet);
+
+        app.world
+            .resource_mut::<Input<KeyCode>>()
+            .press(CHEAT_SHEET_KEY);
+        app.update();
+        assert!(app.world.resource::<CheatSheet>().open);
+
+        app.world.resource_mut::<Input<KeyCode>>().press(KeyCode::W);
+        app.update();
+        assert!(!app.world.resource::<Input<KeyCode>>().pressed(KeyCode::W));
+
+        let mut keys = app.world.resource_mut::<Input<KeyCode>>();
+        keys.clear();
+        keys.press(CHEAT_SHEET_KEY);
+        app.update();
+        assert!(!app.world.resource::<CheatSheet>().open);
+    }
+}
@@ -0,0 +1,671 @@
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use bevy_inspector_egui::bevy_egui::EguiContext;
+use bevy_window::PrimaryWindow;
+
+use crate::game::GameSet;
+use crate::helpers;
+use crate::helpers::tiled::{map_layers, texture_tile_id, TileColliders, TiledLayer, TiledMap};
+use crate::map::GameInfoAlt;
+use crate::palette::{mix, OverlayPalette, OverlayRole, PaletteChanged};
+use crate::perf::timed;
+use crate::state::{level_ready, AppState};
+use crate::tile_writer::TileChanged;
+
+/// Key that toggles tinting the tiles by their movement cost
+pub const COST_OVERLAY_KEY: KeyCode = KeyCode::F2;
+
+/// Name of the Tiled layer whose tiles all block movement
+pub const COLLISION_LAYER_NAME: &str = "collision";
+
+/// Plugin that builds the `CollisionMap` for the current map
+#[derive(Default)]
+pub struct CollisionPlugin;
+
+impl Plugin for CollisionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CollisionMap>()
+            .init_resource::<TileColliders>()
+            .init_resource::<CostOverlay>()
+            .init_resource::<CollisionRebuildBudget>()
+            .add_event::<MoveBlocked>()
+            .add_systems(OnExit(AppState::Loading), build_collision_map)
+            .add_systems(OnExit(AppState::SwitchingMap), build_collision_map)
+            .add_systems(
+                Update,
+                (toggle_cost_overlay, tint_tiles_by_cost)
+                    .chain()
+                    .in_set(GameSet::RenderPrep)
+                    .run_if(level_ready),
+            )
+            .add_systems(
+                Update,
+                (
+                    rebuild_collision_on_reload,
+                    timed!(rebuild_collision_map),
+                    show_rebuild_progress,
+                )
+                    .chain()
+                    .in_set(GameSet::Gameplay)
+                    .run_if(level_ready),
+            )
+            .add_systems(PostUpdate, follow_tile_changes.run_if(level_ready));
+    }
+}
+
+/// Sent when `entity` tried to move onto `tile` but the collision map or another creature
+/// blocks it
+#[derive(Event, Debug, Clone, Copy)]
+pub struct MoveBlocked {
+    pub entity: Entity,
+    pub tile: TilePos,
+}
+
+/// Movement cost of a tile from its custom properties, `None` when the tile is impassable
+///
+/// The cost comes from the `movement_cost` property and defaults to 1. A cost of 0, or a missing
+/// cost on a tile with `blocked = true`, makes the tile impassable.
+pub fn tile_movement_cost(properties: &tiled::Properties) -> Option<u32> {
+    let cost = match properties.get("movement_cost") {
+        Some(tiled::PropertyValue::IntValue(cost)) => Some((*cost).max(0) as u32),
+        Some(tiled::PropertyValue::FloatValue(cost)) => Some(cost.max(0.).ceil() as u32),
+        _ => None,
+    };
+    match cost {
+        Some(0) => None,
+        Some(cost) => Some(cost),
+        None if matches!(
+            properties.get("blocked"),
+            Some(tiled::PropertyValue::BoolValue(true))
+        ) =>
+        {
+            None
+        }
+        None => Some(1),
+    }
+}
+
+/// Debug overlay tinting every tile by its movement cost, for checking the map data
+#[derive(Resource, Default, PartialEq, Eq)]
+pub struct CostOverlay(pub bool);
+
+/// Which tiles of the map block movement, and how expensive the others are to enter
+///
+/// A tile is blocked when the collision layer has a tile there, or when a tile on any layer is
+/// impassable according to `tile_movement_cost`. Otherwise its cost is the highest cost of its
+/// tiles on all layers.
+#[derive(Resource, Default, Clone, Debug)]
+pub struct CollisionMap {
+    size: TilemapSize,
+    blocked: Vec<bool>,
+    costs: Vec<u32>,
+}
+
+// `TilemapSize` isn't `PartialEq`
+impl PartialEq for CollisionMap {
+    fn eq(&self, other: &Self) -> bool {
+        (self.size.x, self.size.y) == (other.size.x, other.size.y)
+            && self.blocked == other.blocked
+            && self.costs == other.costs
+    }
+}
+
+impl CollisionMap {
+    /// An empty (nothing blocked, every tile costs 1) collision map of the given size
+    pub fn new(size: TilemapSize) -> Self {
+        Self {
+            size,
+            blocked: vec![false; size.count()],
+            costs: vec![1; size.count()],
+        }
+    }
+
+    pub fn from_tiled_map(map: &tiled::Map) -> Self {
+        let mut builder = CollisionBuilder::new(map);
+        builder.step(map, usize::MAX);
+        builder.finish()
+    }
+
+    pub fn size(&self) -> TilemapSize {
+        self.size
+    }
+
+    /// Whether `pos` blocks movement, positions outside the map always do
+    pub fn is_blocked(&self, pos: &TilePos) -> bool {
+        if !pos.within_map_bounds(&self.size) {
+            return true;
+        }
+        self.blocked[pos.to_index(&self.size)]
+    }
+
+    /// Cost of entering `pos`, `None` when it is blocked
+    pub fn movement_cost(&self, pos: &TilePos) -> Option<u32> {
+        if self.is_blocked(pos) {
+            return None;
+        }
+        Some(self.costs[pos.to_index(&self.size)])
+    }
+
+    pub fn set_movement_cost(&mut self, pos: &TilePos, cost: u32) {
+        if pos.within_map_bounds(&self.size) {
+            let index = pos.to_index(&self.size);
+            self.costs[index] = cost;
+        }
+    }
+
+    pub fn set_blocked(&mut self, pos: &TilePos, blocked: bool) {
+        if pos.within_map_bounds(&self.size) {
+            let index = pos.to_index(&self.size);
+            self.blocked[index] = blocked;
+        }
+    }
+
+    /// The blocked tiles merged into runs along each row, so a wall takes one collider instead
+    /// of one per tile
+    #[cfg_attr(not(feature = "physics"), allow(dead_code))]
+    pub fn blocked_strips(&self) -> Vec<TileStrip> {
+        let mut strips = Vec::new();
+        for y in 0..self.size.y {
+            let mut run: Option<TileStrip> = None;
+            for x in 0..self.size.x {
+                if self.is_blocked(&TilePos::new(x, y)) {
+                    run.get_or_insert(TileStrip { x, y, len: 0 }).len += 1;
+                } else if let Some(strip) = run.take() {
+                    strips.push(strip);
+                }
+            }
+            strips.extend(run);
+        }
+        strips
+    }
+}
+
+/// `len` tiles of a row, from `(x, y)` to the right
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(not(feature = "physics"), allow(dead_code))]
+pub struct TileStrip {
+    pub x: u32,
+    pub y: u32,
+    pub len: u32,
+}
+
+/// A collision map being built from a Tiled map a few tiles at a time
+#[derive(Debug, Clone)]
+pub struct CollisionBuilder {
+    map: CollisionMap,
+    /// Next tile to look at, counting through every tile of every layer
+    next: usize,
+    total: usize,
+}
+
+impl CollisionBuilder {
+    pub fn new(map: &tiled::Map) -> Self {
+        let size = TilemapSize {
+            x: map.width,
+            y: map.height,
+        };
+        Self {
+            map: CollisionMap::new(size),
+            next: 0,
+            total: map_layers(map).len() * size.count(),
+        }
+    }
+
+    /// Look at up to `budget` more tiles of `map`, returns whether the collision map is complete
+    ///
+    /// `map` has to be the map the builder was created with.
+    pub fn step(&mut self, map: &tiled::Map, budget: usize) -> bool {
+        let tiles_per_layer = self.map.size.count();
+        let mut budget = budget;
+        let layers = map_layers(map);
+        while budget > 0 && self.next < self.total {
+            let layer_index = self.next / tiles_per_layer;
+            let Some(layer) = layers.get(layer_index).map(|map_layer| map_layer.layer) else {
+                self.next = self.total;
+                break;
+            };
+            let layer_end = (layer_index + 1) * tiles_per_layer;
+            let tiled::LayerType::Tiles(tiled::TileLayer::Finite(layer_data)) = layer.layer_type()
+            else {
+                // nothing to look at, skipping the layer costs a single tile
+                self.next = layer_end;
+                budget -= 1;
+                continue;
+            };
+            let is_collision_layer = layer.name.eq_ignore_ascii_case(COLLISION_LAYER_NAME);
+
+            let end = self.next.saturating_add(budget).min(layer_end);
+            for index in self.next % tiles_per_layer..end - layer_index * tiles_per_layer {
+                let tile_pos = TilePos {
+                    x: index as u32 % map.width,
+                    y: index as u32 / map.width,
+                };
+                // Transform TMX coords into bevy coords.
+                let mapped_y = map.height - 1 - tile_pos.y;
+                let Some(layer_tile) = layer_data.get_tile(tile_pos.x as i32, mapped_y as i32)
+                else {
+                    continue;
+                };
+                let cost = match layer_tile.get_tile() {
+                    Some(tile) => tile_movement_cost(&tile.properties),
+                    None => Some(1),
+                };
+                match cost {
+                    Some(cost) if !is_collision_layer => {
+                        let index = tile_pos.to_index(&self.map.size);
+                        self.map.costs[index] = self.map.costs[index].max(cost);
+                    }
+                    _ => self.map.set_blocked(&tile_pos, true),
+                }
+            }
+            budget -= end - self.next;
+            self.next = end;
+        }
+        self.is_complete()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.next >= self.total
+    }
+
+    /// Share of the tiles looked at so far, from 0 to 1
+    pub fn progress(&self) -> f32 {
+        if self.total == 0 {
+            return 1.;
+        }
+        self.next.min(self.total) as f32 / self.total as f32
+    }
+
+    pub fn finish(self) -> CollisionMap {
+        self.map
+    }
+}
+
+/// Tiles the collision map rebuild looks at per frame
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CollisionRebuildBudget(pub usize);
+
+impl Default for CollisionRebuildBudget {
+    fn default() -> Self {
+        Self(16 * 1024)
+    }
+}
+
+/// Asks for the collision map to be rebuilt from `map` over the next frames
+///
+/// The current `CollisionMap` stays in use until the new one is complete, then it is swapped in
+/// at once and this resource is removed. Inserting it again restarts the rebuild.
+#[derive(Resource, Debug, Clone)]
+pub struct RebuildCollision {
+    pub map: Handle<TiledMap>,
+    builder: Option<CollisionBuilder>,
+}
+
+impl RebuildCollision {
+    pub fn new(map: Handle<TiledMap>) -> Self {
+        Self { map, builder: None }
+    }
+
+    /// Share of the rebuild done, from 0 to 1
+    pub fn progress(&self) -> f32 {
+        self.builder.as_ref().map_or(0., CollisionBuilder::progress)
+    }
+}
+
+fn rebuild_collision_on_reload(
+    mut commands: Commands,
+    mut map_events: EventReader<AssetEvent<TiledMap>>,
+    game_info: Res<GameInfoAlt>,
+) {
+    let reloaded = map_events.read().any(
+        |event| matches!(event, AssetEvent::Modified { id } if *id == game_info.tile_map.id()),
+    );
+    if reloaded {
+        commands.insert_resource(RebuildCollision::new(game_info.tile_map.clone()));
+    }
+}
+
+fn rebuild_collision_map(
+    mut commands: Commands,
+    rebuild: Option<ResMut<RebuildCollision>>,
+    tile_maps: Res<Assets<TiledMap>>,
+    budget: Res<CollisionRebuildBudget>,
+    mut collision_map: ResMut<CollisionMap>,
+    mut tile_colliders: ResMut<TileColliders>,
+) {
+    let Some(mut rebuild) = rebuild else {
+        return;
+    };
+    let Some(map) = tile_maps.get(&rebuild.map) else {
+        return;
+    };
+    let builder = rebuild
+        .builder
+        .get_or_insert_with(|| CollisionBuilder::new(&map.map));
+    if builder.step(&map.map, budget.0.max(1)) {
+        if let Some(builder) = rebuild.builder.take() {
+            *collision_map = builder.finish();
+        }
+        *tile_colliders = TileColliders::from_map(&map.map);
+        commands.remove_resource::<RebuildCollision>();
+    }
+}
+
+/// Work out again whether the tiles changed through `TileWriter` block movement, and their cost,
+/// from the tiles now on every layer
+pub fn follow_tile_changes(
+    mut changes: EventReader<TileChanged>,
+    game_info: Res<GameInfoAlt>,
+    tile_maps: Res<Assets<TiledMap>>,
+    layers: Query<(&Name, &TiledLayer, &TileStorage)>,
+    textures: Query<&TileTextureIndex>,
+    mut collision_map: ResMut<CollisionMap>,
+) {
+    // a tint doesn't change what the tile is
+    let mut changed: Vec<_> = changes
+        .read()
+        .filter(|change| change.old.map(|tile| tile.texture) != change.new.map(|tile| tile.texture))
+        .map(|change| change.pos)
+        .collect();
+    let Some(map) = tile_maps.get(&game_info.tile_map) else {
+        return;
+    };
+    changed.sort_by_key(|pos| (pos.y, pos.x));
+    changed.dedup();
+    for pos in changed {
+        let mut blocked = false;
+        let mut cost = 1;
+        for (name, layer, storage) in &layers {
+            let Some(texture) = storage
+                .checked_get(&pos)
+                .and_then(|tile| textures.get(tile).ok())
+            else {
+                continue;
+            };
+            let tile_id = texture_tile_id(map, layer.tileset_index, texture.0);
+            let tile_cost = match map
+                .map
+                .tilesets()
+                .get(layer.tileset_index)
+                .and_then(|tileset| tileset.get_tile(tile_id))
+            {
+                Some(tile) => tile_movement_cost(&tile.properties),
+                None => Some(1),
+            };
+            match tile_cost {
+                Some(tile_cost) if !name.eq_ignore_ascii_case(COLLISION_LAYER_NAME) => {
+                    cost = cost.max(tile_cost);
+                }
+                _ => blocked = true,
+            }
+        }
+        collision_map.set_blocked(&pos, blocked);
+        collision_map.set_movement_cost(&pos, cost);
+    }
+}
+
+fn show_rebuild_progress(
+    rebuild: Option<Res<RebuildCollision>>,
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+) {
+    let Some(rebuild) = rebuild else {
+        return;
+    };
+    let Some(mut ctx) = contexts.iter_mut().next() else {
+        return;
+    };
+    egui::Area::new(egui::Id::new("collision_rebuild"))
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0., 28.))
+        .interactable(false)
+        .show(ctx.get_mut(), |ui| {
+            ui.add(
+                egui::ProgressBar::new(rebuild.progress())
+                    .desired_width(200.)
+                    .text("Rebuilding collision"),
+            );
+        });
+}
+
+fn build_collision_map(
+    mut commands: Commands,
+    game_info: Res<GameInfoAlt>,
+    tile_maps: Res<Assets<helpers::tiled::TiledMap>>,
+) {
+    let Some(map) = tile_maps.get(&game_info.tile_map) else {
+        warn!("can't find tile map for collision setup!");
+        return;
+    };
+    commands.insert_resource(CollisionMap::from_tiled_map(&map.map));
+    commands.insert_resource(TileColliders::from_map(&map.map));
+}
+
+fn toggle_cost_overlay(keys: Res<Input<KeyCode>>, mut overlay: ResMut<CostOverlay>) {
+    if keys.just_pressed(COST_OVERLAY_KEY) {
+        overlay.0 = !overlay.0;
+    }
+}
+
+/// Overlay tint for a tile with the given movement cost, whiter for cheaper tiles
+fn cost_color(palette: &OverlayPalette, cost: Option<u32>) -> Color {
+    let blocked = palette.color(OverlayRole::Blocked);
+    match cost {
+        None => blocked,
+        Some(cost) => mix(
+            Color::WHITE,
+            blocked,
+            cost.saturating_sub(1).min(3) as f32 / 4.,
+        ),
+    }
+}
+
+fn tint_tiles_by_cost(
+    overlay: Res<CostOverlay>,
+    collision_map: Res<CollisionMap>,
+    palette: Res<OverlayPalette>,
+    mut palette_changes: EventReader<PaletteChanged>,
+    mut tiles: Query<(&TilePos, &mut TileColor)>,
+    added_tiles: Query<(), Added<TilePos>>,
+) {
+    let restyled = palette_changes.read().count() > 0;
+    // only repaint when something changed, tiles are respawned when the map is reloaded
+    if !overlay.is_changed() && !collision_map.is_changed() && added_tiles.is_empty() && !restyled {
+        return;
+    }
+    for (tile_pos, mut color) in &mut tiles {
+        color.0 = if overlay.0 {
+            cost_color(&palette, collision_map.movement_cost(tile_pos))
+        } else {
+            Color::WHITE
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::helpers::tiled::{load_test_map, parse_tmx, process_loaded_maps, TiledMapBundle};
+    use crate::layers::LayerZConfig;
+    use crate::tile_writer::{TileWriter, TileWriterPlugin};
+    use crate::toast::Toast;
+
+    const MAP_PATH: &str = "assets/maps/TMX/map_test_collision.tmx";
+
+    fn load_map() -> tiled::Map {
+        let source = std::fs::read(MAP_PATH).unwrap();
+        parse_tmx(&source, Path::new(MAP_PATH)).unwrap()
+    }
+
+    fn properties(values: &[(&str, tiled::PropertyValue)]) -> tiled::Properties {
+        values
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn movement_cost_from_properties() {
+        use tiled::PropertyValue::*;
+
+        let cases = [
+            (properties(&[]), Some(1)),
+            (properties(&[("movement_cost", IntValue(2))]), Some(2)),
+            (properties(&[("movement_cost", FloatValue(1.5))]), Some(2)),
+            (properties(&[("movement_cost", IntValue(0))]), None),
+            (properties(&[("movement_cost", IntValue(-3))]), None),
+            (properties(&[("blocked", BoolValue(true))]), None),
+            (properties(&[("blocked", BoolValue(false))]), Some(1)),
+            (
+                properties(&[("blocked", BoolValue(true)), ("movement_cost", IntValue(3))]),
+                Some(3),
+            ),
+        ];
+        for (properties, expected) in cases {
+            assert_eq!(tile_movement_cost(&properties), expected, "{properties:?}");
+        }
+    }
+
+    #[test]
+    fn blocked_tiles_have_no_cost() {
+        let mut map = CollisionMap::new(TilemapSize { x: 2, y: 2 });
+        map.set_movement_cost(&TilePos::new(1, 0), 4);
+        map.set_blocked(&TilePos::new(1, 1), true);
+
+        assert_eq!(map.movement_cost(&TilePos::new(0, 0)), Some(1));
+        assert_eq!(map.movement_cost(&TilePos::new(1, 0)), Some(4));
+        assert_eq!(map.movement_cost(&TilePos::new(1, 1)), None);
+        assert_eq!(map.movement_cost(&TilePos::new(2, 0)), None);
+    }
+
+    #[test]
+    fn blocked_tiles_merge_into_row_strips() {
+        let mut map = CollisionMap::new(TilemapSize { x: 5, y: 3 });
+        for (x, y) in [(0, 0), (1, 0), (2, 0), (4, 0), (3, 1), (4, 1), (2, 2)] {
+            map.set_blocked(&TilePos::new(x, y), true);
+        }
+        assert_eq!(
+            map.blocked_strips(),
+            [
+                TileStrip { x: 0, y: 0, len: 3 },
+                TileStrip { x: 4, y: 0, len: 1 },
+                TileStrip { x: 3, y: 1, len: 2 },
+                TileStrip { x: 2, y: 2, len: 1 },
+            ]
+        );
+        assert!(CollisionMap::new(TilemapSize { x: 3, y: 3 })
+            .blocked_strips()
+            .is_empty());
+    }
+
+    #[test]
+    fn rebuild_in_steps_matches_a_full_build() {
+        let map = load_map();
+        let expected = CollisionMap::from_tiled_map(&map);
+        // spot checks that the fixture exercises costs, blocking tiles and the collision layer
+        assert_eq!(expected.movement_cost(&TilePos::new(1, 2)), Some(3));
+        assert_eq!(expected.movement_cost(&TilePos::new(4, 2)), None);
+        assert_eq!(expected.movement_cost(&TilePos::new(5, 4)), None);
+        assert_eq!(expected.movement_cost(&TilePos::new(0, 0)), None);
+        assert_eq!(expected.movement_cost(&TilePos::new(3, 2)), Some(1));
+        assert_eq!(expected.movement_cost(&TilePos::new(3, 1)), Some(3));
+        for budget in [1, 7, 500, 100_000] {
+            let mut builder = CollisionBuilder::new(&map);
+            let mut steps = 0;
+            while !builder.step(&map, budget) {
+                steps += 1;
+                assert!(builder.progress() < 1.);
+            }
+            assert_eq!(builder.progress(), 1.);
+            assert_eq!(
+                builder.finish(),
+                expected,
+                "budget {budget} after {steps} steps"
+            );
+        }
+    }
+
+    #[test]
+    fn old_map_stays_in_use_until_the_rebuild_is_done() {
+        let map = load_map();
+        let expected = CollisionMap::from_tiled_map(&map);
+        let old = CollisionMap::new(expected.size());
+        assert_ne!(old, expected);
+
+        let mut tile_maps = Assets::<TiledMap>::default();
+        let handle = tile_maps.add(TiledMap {
+            map,
+            tilemap_textures: Default::default(),
+            #[cfg(not(feature = "atlas"))]
+            tile_image_offsets: Default::default(),
+        });
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(tile_maps)
+            .insert_resource(old.clone())
+            .init_resource::<TileColliders>()
+            .insert_resource(CollisionRebuildBudget(8))
+            .insert_resource(RebuildCollision::new(handle))
+            .add_systems(Update, rebuild_collision_map);
+
+        let mut frames = 0;
+        while app.world.contains_resource::<RebuildCollision>() {
+            assert_eq!(*app.world.resource::<CollisionMap>(), old);
+            app.update();
+            frames += 1;
+            assert!(frames < 1000);
+        }
+        assert!(frames > 1);
+        assert_eq!(*app.world.resource::<CollisionMap>(), expected);
+    }
+
+    #[test]
+    fn tile_changes_update_the_collision_map() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugins(TileWriterPlugin)
+            .init_resource::<Assets<TiledMap>>()
+            .init_resource::<LayerZConfig>()
+            .insert_resource(CollisionMap::from_tiled_map(&load_map()))
+            .add_event::<AssetEvent<TiledMap>>()
+            .add_event::<Toast>()
+            .add_systems(Update, process_loaded_maps)
+            .add_systems(PostUpdate, follow_tile_changes);
+        let tile_map = app
+            .world
+            .resource_mut::<Assets<TiledMap>>()
+            .add(load_test_map(MAP_PATH));
+        app.world.insert_resource(GameInfoAlt {
+            creature_atlas: Handle::default(),
+            creature_sheet: None,
+            creature_folder: None,
+            tile_map: tile_map.clone(),
+            templates: Handle::default(),
+        });
+        app.world.spawn(TiledMapBundle {
+            tiled_map: tile_map,
+            ..default()
+        });
+        app.update();
+
+        let pos = TilePos::new(3, 2);
+        let mut cost_after = |layer: &'static str, texture: Option<u32>| {
+            app.world.run_system_once(move |mut writer: TileWriter| {
+                match texture {
+                    Some(texture) => writer.set_tile(layer, pos, texture),
+                    None => writer.remove_tile(layer, pos),
+                }
+                .unwrap();
+            });
+            app.update();
+            app.world.resource::<CollisionMap>().movement_cost(&pos)
+        };
+        assert_eq!(cost_after("ground", Some(1)), Some(3));
+        assert_eq!(cost_after("ground", Some(2)), None);
+        assert_eq!(cost_after("ground", Some(0)), Some(1));
+        assert_eq!(cost_after("collision", Some(0)), None);
+        assert_eq!(cost_after("collision", None), Some(1));
+    }
+}
@@ -0,0 +1,223 @@
+//! Melee attacks: the player bumping into a creature with health hits it instead of moving, and so
+//! does a hostile creature bumping into the player.
+
+use bevy::prelude::*;
+
+use crate::audio::SpatialAudio;
+use crate::coords::MapFrame;
+use crate::game::GameSet;
+use crate::health::DamageEvent;
+use crate::movement::{Facing, StepCooldown};
+use crate::player::MainPlayer;
+use crate::state::level_ready;
+use crate::tween::{Ease, Tween, TweenValue};
+
+/// Part of a tile the attacker lunges toward its target
+const LUNGE_REACH: f32 = 0.35;
+
+/// Plugin that turns `BumpAttack`s into damage, a lunge and an `AttackLanded`
+#[derive(Default)]
+pub struct CombatPlugin;
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CombatSettings>()
+            .register_type::<CombatSettings>()
+            .add_event::<BumpAttack>()
+            .add_event::<AttackLanded>()
+            .add_systems(
+                Update,
+                (melee_attack, play_attack_sound)
+                    .chain()
+                    .in_set(GameSet::Movement)
+                    .after(crate::movement::resolve_moves)
+                    .run_if(level_ready)
+                    .run_if(crate::debug_step::gameplay_running),
+            );
+    }
+}
+
+#[derive(Resource, Reflect, Debug, Clone, PartialEq)]
+#[reflect(Resource)]
+pub struct CombatSettings {
+    /// Damage of one of the player's melee attacks
+    pub player_damage: i32,
+    /// Damage of one of the hostile creatures' melee attacks
+    pub creature_damage: i32,
+    /// Seconds the lunge lasts, the attacker can't act meanwhile
+    pub lunge_secs: f32,
+    /// Audio asset played when an attack lands, none when empty
+    pub attack_sound: String,
+}
+
+impl Default for CombatSettings {
+    fn default() -> Self {
+        Self {
+            player_damage: 3,
+            creature_damage: 1,
+            lunge_secs: 0.2,
+            attack_sound: String::new(),
+        }
+    }
+}
+
+/// Sent by the move resolution when `attacker` stepped by `dir` into `target`
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BumpAttack {
+    pub attacker: Entity,
+    pub target: Entity,
+    pub dir: IVec2,
+}
+
+/// Sent when a melee attack hit, for the feedback like sounds and camera shake
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttackLanded {
+    pub attacker: Entity,
+    pub target: Entity,
+}
+
+fn melee_attack(
+    mut commands: Commands,
+    settings: Res<CombatSettings>,
+    map_frame: Res<MapFrame>,
+    mut attacks: EventReader<BumpAttack>,
+    attackers: Query<(&Transform, Has<MainPlayer>)>,
+    mut damage: EventWriter<DamageEvent>,
+    mut landed: EventWriter<AttackLanded>,
+) {
+    let tile_size = Vec2::new(map_frame.grid_size.x, map_frame.grid_size.y);
+    for attack in attacks.read() {
+        let Ok((transform, is_player)) = attackers.get(attack.attacker) else {
+            continue;
+        };
+        let start = transform.translation.truncate();
+        let lunge = Tween::new(
+            TweenValue::Position {
+                from: start,
+                to: start + attack.dir.as_vec2() * tile_size * LUNGE_REACH,
+            },
+            Ease::ThereAndBack,
+            settings.lunge_secs,
+        );
+        // the attack takes the attacker's turn like a step would
+        commands.entity(attack.attacker).insert((
+            lunge,
+            Facing(attack.dir),
+            StepCooldown(settings.lunge_secs),
+        ));
+        damage.send(DamageEvent {
+            target: attack.target,
+            amount: if is_player {
+                settings.player_damage
+            } else {
+                settings.creature_damage
+            },
+            source: Some(attack.attacker),
+        });
+        landed.send(AttackLanded {
+            attacker: attack.attacker,
+            target: attack.target,
+        });
+    }
+}
+
+fn play_attack_sound(
+    settings: Res<CombatSettings>,
+    asset_server: Res<AssetServer>,
+    mut landed: EventReader<AttackLanded>,
+    targets: Query<&GlobalTransform>,
+    mut sounds: SpatialAudio,
+) {
+    for attack in landed.read() {
+        if settings.attack_sound.is_empty() {
+            continue;
+        }
+        let Ok(target) = targets.get(attack.target) else {
+            continue;
+        };
+        sounds.play_spatial_sfx(
+            target.translation().truncate(),
+            asset_server.load(settings.attack_sound.clone()),
+            1.,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs_tilemap::prelude::*;
+
+    use super::*;
+    use crate::collision::{CollisionMap, MoveBlocked};
+    use crate::coords;
+    use crate::health::{self, DamageNumberPool, Died, Health};
+    use crate::map::Npc;
+    use crate::movement::{self, MoveRequested, MoveResolved, MovementSettings};
+    use crate::player::MainPlayer;
+
+    fn map_frame() -> MapFrame {
+        MapFrame {
+            transform: Transform::IDENTITY,
+            grid_size: TilemapGridSize { x: 10., y: 10. },
+            map_type: TilemapType::Square,
+            map_size: TilemapSize { x: 4, y: 4 },
+        }
+    }
+
+    fn spawn_at(app: &mut App, tile: TilePos, bundle: impl Bundle) -> Entity {
+        let pos = coords::tile_to_world_center(&map_frame(), &tile).unwrap();
+        app.world
+            .spawn((Transform::from_translation(pos.extend(2.)), bundle))
+            .id()
+    }
+
+    #[test]
+    fn walking_into_a_creature_attacks_it() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(map_frame())
+            .insert_resource(CollisionMap::new(TilemapSize { x: 4, y: 4 }))
+            .init_resource::<MovementSettings>()
+            .init_resource::<CombatSettings>()
+            .init_resource::<DamageNumberPool>()
+            .init_resource::<crate::ui_scale::UiScaling>()
+            .add_event::<MoveRequested>()
+            .add_event::<MoveResolved>()
+            .add_event::<MoveBlocked>()
+            .add_event::<BumpAttack>()
+            .add_event::<AttackLanded>()
+            .add_event::<DamageEvent>()
+            .add_event::<Died>()
+            .add_systems(
+                Update,
+                (
+                    movement::resolve_moves,
+                    movement::apply_moves,
+                    melee_attack,
+                    health::apply_damage,
+                )
+                    .chain(),
+            );
+        let player = spawn_at(&mut app, TilePos::new(1, 1), MainPlayer);
+        let npc = spawn_at(&mut app, TilePos::new(2, 1), (Npc, Health::new(10)));
+        let player_start = app.world.get::<Transform>(player).unwrap().translation;
+
+        app.world.send_event(MoveRequested {
+            entity: player,
+            dir: IVec2::X,
+        });
+        app.update();
+
+        assert_eq!(app.world.get::<Health>(npc).unwrap().current, 7);
+        let transform = app.world.get::<Transform>(player).unwrap();
+        assert_eq!(
+            coords::world_to_tile(&map_frame(), transform.translation.truncate()),
+            Some(TilePos::new(1, 1))
+        );
+        assert_eq!(transform.translation, player_start);
+        assert_eq!(app.world.get::<Facing>(player), Some(&Facing(IVec2::X)));
+        assert!(app.world.get::<Tween>(player).is_some());
+        let events = app.world.resource::<Events<MoveResolved>>();
+        assert_eq!(events.get_reader().read(events).count(), 0);
+    }
+}
@@ -0,0 +1,140 @@
+//! A debug console for commands that exercise systems before the game has a way to reach them.
+//!
+//! A line typed into the console is split on whitespace and sent as a `ConsoleCommand`, the
+//! modules handling the command read the event and answer with `DebugConsole::print`.
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::EguiContext;
+use bevy_window::PrimaryWindow;
+
+use crate::game::GameSet;
+
+/// Key that opens and closes the console
+pub const CONSOLE_KEY: KeyCode = KeyCode::Grave;
+/// Lines of output kept in the console
+const MAX_LINES: usize = 100;
+
+/// Plugin that draws the console and turns the typed lines into `ConsoleCommand` events
+#[derive(Default)]
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DebugConsole>()
+            .add_event::<ConsoleCommand>()
+            .add_systems(
+                Update,
+                (toggle_console, show_console)
+                    .chain()
+                    .in_set(GameSet::Input),
+            );
+    }
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct DebugConsole {
+    pub open: bool,
+    input: String,
+    lines: Vec<String>,
+}
+
+impl DebugConsole {
+    /// Add a line to the console output
+    pub fn print(&mut self, line: impl Into<String>) {
+        self.lines.push(line.into());
+        if self.lines.len() > MAX_LINES {
+            self.lines.remove(0);
+        }
+    }
+}
+
+/// Run condition for keyboard-driven gameplay, which must not react to what is typed
+pub fn console_closed(console: Res<DebugConsole>) -> bool {
+    !console.open
+}
+
+/// A command typed into the console, `name` being its first word
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct ConsoleCommand {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+impl ConsoleCommand {
+    /// Split a console line into a command, `None` for a blank line
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split_whitespace().map(str::to_string);
+        let name = words.next()?;
+        Some(Self {
+            name,
+            args: words.collect(),
+        })
+    }
+}
+
+fn toggle_console(keys: Res<Input<KeyCode>>, mut console: ResMut<DebugConsole>) {
+    if keys.just_pressed(CONSOLE_KEY) {
+        console.open = !console.open;
+    }
+}
+
+fn show_console(
+    mut console: ResMut<DebugConsole>,
+    mut commands: EventWriter<ConsoleCommand>,
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+) {
+    if !console.open {
+        return;
+    }
+    let Some(mut ctx) = contexts.iter_mut().next() else {
+        return;
+    };
+
+    let console = &mut *console;
+    let mut submitted = None;
+    egui::Window::new("Console")
+        .default_width(420.)
+        .show(ctx.get_mut(), |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(200.)
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for line in &console.lines {
+                        ui.monospace(line);
+                    }
+                });
+            let response = ui.text_edit_singleline(&mut console.input);
+            if response.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter)) {
+                submitted = Some(std::mem::take(&mut console.input));
+                response.request_focus();
+            } else if !response.has_focus() {
+                response.request_focus();
+            }
+        });
+
+    if let Some(line) = submitted {
+        // the key that opens the console also ends up in the text field
+        let line = line.replace('`', "");
+        console.print(format!("> {line}"));
+        if let Some(command) = ConsoleCommand::parse(&line) {
+            commands.send(command);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_command_and_arguments() {
+        assert_eq!(
+            ConsoleCommand::parse("  damage  5 "),
+            Some(ConsoleCommand {
+                name: "damage".to_string(),
+                args: vec!["5".to_string()],
+            })
+        );
+        assert_eq!(ConsoleCommand::parse("   "), None);
+    }
+}
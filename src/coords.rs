@@ -0,0 +1,414 @@
+//! Conversions between the coordinate spaces used by the game: screen (window cursor), world,
+//! tile (`TilePos`) and Tiled pixel space (y down, origin at the top-left of the map).
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::helpers;
+
+/// Describes where the current map lives in the world, captured when the level is set up
+#[derive(Resource, Clone, Debug)]
+pub struct MapFrame {
+    /// Transform of the map entity
+    pub transform: Transform,
+    pub grid_size: TilemapGridSize,
+    pub map_type: TilemapType,
+    pub map_size: TilemapSize,
+}
+
+impl MapFrame {
+    pub fn from_tiled_map(map: &tiled::Map, transform: Transform) -> Self {
+        Self {
+            transform,
+            grid_size: TilemapGridSize {
+                x: map.tile_width as f32,
+                y: map.tile_height as f32,
+            },
+            map_type: helpers::tiled::tilemap_type(map.orientation),
+            map_size: TilemapSize {
+                x: map.width,
+                y: map.height,
+            },
+        }
+    }
+
+    /// Size of the map in Tiled pixels
+    fn size_in_px(&self) -> Vec2 {
+        Vec2::new(
+            self.map_size.x as f32 * self.grid_size.x,
+            self.map_size.y as f32 * self.grid_size.y,
+        )
+    }
+
+    /// Half a tile, from the corner of the map in Tiled pixel space to the center of its first
+    /// tile, which the tiles are laid out around in map space
+    fn half_tile(&self) -> Vec2 {
+        Vec2::new(self.grid_size.x, self.grid_size.y) / 2.
+    }
+}
+
+/// Convert a world position into the tile it falls on, `None` when outside the map
+pub fn world_to_tile(frame: &MapFrame, world_pos: Vec2) -> Option<TilePos> {
+    // We need to make sure that the world position is correct relative to the map
+    // due to any map transformation.
+    let pos_in_map = frame
+        .transform
+        .compute_matrix()
+        .inverse()
+        .transform_point3(world_pos.extend(0.))
+        .truncate();
+    TilePos::from_world_pos(
+        &pos_in_map,
+        &frame.map_size,
+        &frame.grid_size,
+        &frame.map_type,
+    )
+}
+
+/// World position of the center of a tile, `None` when the tile is outside the map
+pub fn tile_to_world_center(frame: &MapFrame, tile_pos: &TilePos) -> Option<Vec2> {
+    if !tile_pos.within_map_bounds(&frame.map_size) {
+        return None;
+    }
+    let center = tile_pos.center_in_world(&frame.grid_size, &frame.map_type);
+    Some(
+        frame
+            .transform
+            .transform_point(center.extend(0.))
+            .truncate(),
+    )
+}
+
+/// Convert a cursor position in window coordinates into a world position
+pub fn screen_to_world(
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    cursor: Vec2,
+) -> Option<Vec2> {
+    camera
+        .viewport_to_world(camera_transform, cursor)
+        .map(|ray| ray.origin.truncate())
+}
+
+/// Convert a position in Tiled pixel space (e.g. an object position) into a world position,
+/// `None` when it lies outside the map
+///
+/// The corner of the map in Tiled is the corner of its first tile, half a tile from the tile's
+/// center in map space.
+pub fn tiled_px_to_world(frame: &MapFrame, tiled_px: Vec2) -> Option<Vec2> {
+    let size_in_px = frame.size_in_px();
+    if tiled_px.cmplt(Vec2::ZERO).any() || tiled_px.cmpgt(size_in_px).any() {
+        return None;
+    }
+    let pos_in_map = Vec2::new(tiled_px.x, size_in_px.y - tiled_px.y) - frame.half_tile();
+    Some(
+        frame
+            .transform
+            .transform_point(pos_in_map.extend(0.))
+            .truncate(),
+    )
+}
+
+/// The tile under the center of a Tiled object, `None` when it lies outside the map
+///
+/// Tile objects are anchored at their bottom-left corner, rectangles at their top-left one and
+/// the other shapes at their position.
+pub fn object_tile(frame: &MapFrame, object: &tiled::Object) -> Option<TilePos> {
+    let center = match object.shape {
+        tiled::ObjectShape::Rect { width, height } if object.get_tile().is_some() => {
+            Vec2::new(object.x + width / 2., object.y - height / 2.)
+        }
+        tiled::ObjectShape::Rect { width, height } => {
+            Vec2::new(object.x + width / 2., object.y + height / 2.)
+        }
+        _ => Vec2::new(object.x, object.y),
+    };
+    tiled_px_to_world(frame, center).and_then(|pos| world_to_tile(frame, pos))
+}
+
+/// Convert a world position back into Tiled pixel space, the inverse of `tiled_px_to_world`
+pub fn world_to_tiled_px(frame: &MapFrame, world_pos: Vec2) -> Vec2 {
+    let pos_in_map = frame
+        .transform
+        .compute_matrix()
+        .inverse()
+        .transform_point3(world_pos.extend(0.))
+        .truncate()
+        + frame.half_tile();
+    Vec2::new(pos_in_map.x, frame.size_in_px().y - pos_in_map.y)
+}
+
+/// The world rectangle covered by a Tiled rectangle object, `None` for the other shapes and when
+/// it sticks out of the map
+pub fn object_rect(frame: &MapFrame, object: &tiled::Object) -> Option<Rect> {
+    let tiled::ObjectShape::Rect { width, height } = object.shape else {
+        return None;
+    };
+    let top_left = tiled_px_to_world(frame, Vec2::new(object.x, object.y))?;
+    let bottom_right = tiled_px_to_world(frame, Vec2::new(object.x + width, object.y + height))?;
+    Some(Rect::from_corners(top_left, bottom_right))
+}
+
+/// A world rectangle in Tiled pixel space, the inverse of `object_rect`
+pub fn world_rect_to_tiled_px(frame: &MapFrame, rect: Rect) -> Rect {
+    Rect::from_corners(
+        world_to_tiled_px(frame, rect.min),
+        world_to_tiled_px(frame, rect.max),
+    )
+}
+
+/// World-space bounding box of the map's tiles, when the map entity has `map_transform`
+pub fn map_world_rect(frame: &MapFrame, map_transform: &GlobalTransform) -> Rect {
+    let last_x = frame.map_size.x.saturating_sub(1);
+    let last_y = frame.map_size.y.saturating_sub(1);
+    let half_tile = Vec2::new(frame.grid_size.x, frame.grid_size.y) / 2.;
+    // `Rect::new` would reorder the corners, this is the empty box `union` starts from
+    let empty = Rect {
+        min: Vec2::splat(f32::MAX),
+        max: Vec2::splat(f32::MIN),
+    };
+
+    // box around the corner tiles in map space, then around that box in world space
+    let corner_tiles = [(0, 0), (last_x, 0), (0, last_y), (last_x, last_y)];
+    let local = corner_tiles
+        .iter()
+        .map(|&(x, y)| TilePos::new(x, y).center_in_world(&frame.grid_size, &frame.map_type))
+        .fold(empty, |rect, center| {
+            rect.union(Rect::from_center_half_size(center, half_tile))
+        });
+    [
+        local.min,
+        Vec2::new(local.max.x, local.min.y),
+        Vec2::new(local.min.x, local.max.y),
+        local.max,
+    ]
+    .iter()
+    .map(|corner| map_transform.transform_point(corner.extend(0.)).truncate())
+    .fold(empty, |rect, corner| rect.union_point(corner))
+}
+
+/// `rect` grown by `by` on every side, or shrunk for a negative `by`, down to its center
+pub fn inflate_rect(rect: Rect, by: f32) -> Rect {
+    let center = rect.center();
+    let half_size = (rect.half_size() + by).max(Vec2::ZERO);
+    Rect::from_center_half_size(center, half_size)
+}
+
+/// Number of king moves (8-way steps) between two tiles
+pub fn tile_distance(a: &TilePos, b: &TilePos) -> u32 {
+    a.x.abs_diff(b.x).max(a.y.abs_diff(b.y))
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::vec2;
+
+    use super::*;
+
+    /// A 4x3 square map of 16px tiles
+    fn mock_frame(transform: Transform) -> MapFrame {
+        MapFrame {
+            transform,
+            grid_size: TilemapGridSize { x: 16., y: 16. },
+            map_type: TilemapType::Square,
+            map_size: TilemapSize { x: 4, y: 3 },
+        }
+    }
+
+    #[test]
+    fn world_to_tile_square() {
+        let cases = [
+            (Transform::IDENTITY, vec2(0., 0.), Some(TilePos::new(0, 0))),
+            (
+                Transform::IDENTITY,
+                vec2(-7.9, -7.9),
+                Some(TilePos::new(0, 0)),
+            ),
+            (
+                Transform::IDENTITY,
+                vec2(48., 32.),
+                Some(TilePos::new(3, 2)),
+            ),
+            (
+                Transform::IDENTITY,
+                vec2(55.9, 39.9),
+                Some(TilePos::new(3, 2)),
+            ),
+            (Transform::IDENTITY, vec2(48., 0.), Some(TilePos::new(3, 0))),
+            (Transform::IDENTITY, vec2(-8.1, 0.), None),
+            (Transform::IDENTITY, vec2(56.1, 0.), None),
+            (Transform::IDENTITY, vec2(0., 40.1), None),
+            (
+                Transform::from_xyz(100., 50., 0.1),
+                vec2(116., 66.),
+                Some(TilePos::new(1, 1)),
+            ),
+            (Transform::from_xyz(100., 50., 0.1), vec2(0., 0.), None),
+            (
+                Transform::from_scale(Vec3::splat(2.)),
+                vec2(32., 32.),
+                Some(TilePos::new(1, 1)),
+            ),
+        ];
+        for (transform, world_pos, expected) in cases {
+            assert_eq!(
+                world_to_tile(&mock_frame(transform), world_pos),
+                expected,
+                "{world_pos} with {transform:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn tile_to_world_center_square() {
+        let cases = [
+            (Transform::IDENTITY, TilePos::new(0, 0), Some(vec2(0., 0.))),
+            (
+                Transform::IDENTITY,
+                TilePos::new(3, 2),
+                Some(vec2(48., 32.)),
+            ),
+            (Transform::IDENTITY, TilePos::new(4, 0), None),
+            (Transform::IDENTITY, TilePos::new(0, 3), None),
+            (
+                Transform::from_xyz(100., 50., 0.1),
+                TilePos::new(1, 1),
+                Some(vec2(116., 66.)),
+            ),
+            (
+                Transform::from_scale(Vec3::splat(2.)),
+                TilePos::new(3, 2),
+                Some(vec2(96., 64.)),
+            ),
+        ];
+        for (transform, tile_pos, expected) in cases {
+            assert_eq!(
+                tile_to_world_center(&mock_frame(transform), &tile_pos),
+                expected,
+                "{tile_pos:?} with {transform:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn tiled_px_to_world_square() {
+        let cases = [
+            (Transform::IDENTITY, vec2(8., 40.), Some(vec2(0., 0.))),
+            (Transform::IDENTITY, vec2(0., 48.), Some(vec2(-8., -8.))),
+            (Transform::IDENTITY, vec2(0., 0.), Some(vec2(-8., 40.))),
+            (Transform::IDENTITY, vec2(64., 0.), Some(vec2(56., 40.))),
+            (Transform::IDENTITY, vec2(-1., 0.), None),
+            (Transform::IDENTITY, vec2(0., 48.1), None),
+            (Transform::IDENTITY, vec2(64.1, 10.), None),
+            (
+                Transform::from_xyz(100., 50., 0.1),
+                vec2(24., 24.),
+                Some(vec2(116., 66.)),
+            ),
+        ];
+        for (transform, tiled_px, expected) in cases {
+            assert_eq!(
+                tiled_px_to_world(&mock_frame(transform), tiled_px),
+                expected,
+                "{tiled_px} with {transform:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn world_to_tiled_px_round_trips() {
+        let frame = mock_frame(Transform::from_xyz(100., 50., 0.1));
+        for tiled_px in [vec2(16., 32.), vec2(0., 0.), vec2(59.5, 47.25)] {
+            let world = tiled_px_to_world(&frame, tiled_px).unwrap();
+            assert_eq!(world_to_tiled_px(&frame, world), tiled_px);
+        }
+    }
+
+    #[test]
+    fn tiled_positions_land_on_their_tile() {
+        let frame = mock_frame(Transform::from_xyz(-20., 12., 0.1));
+        for x in 0..frame.map_size.x {
+            for y in 0..frame.map_size.y {
+                // Tiled counts the rows from the top
+                let row = frame.map_size.y - 1 - y;
+                let corner = vec2(x as f32, row as f32) * 16.;
+                for offset in [vec2(8., 8.), vec2(0.5, 0.5), vec2(15.5, 15.5)] {
+                    let world = tiled_px_to_world(&frame, corner + offset).unwrap();
+                    assert_eq!(
+                        world_to_tile(&frame, world),
+                        Some(TilePos::new(x, y)),
+                        "{offset} into Tiled tile ({x}, {row})"
+                    );
+                }
+            }
+        }
+        // the corner of the map in Tiled is the corner of its tiles in the world
+        let frame = mock_frame(Transform::IDENTITY);
+        let map_rect = map_world_rect(&frame, &GlobalTransform::IDENTITY);
+        assert_eq!(tiled_px_to_world(&frame, vec2(0., 48.)), Some(map_rect.min));
+        assert_eq!(tiled_px_to_world(&frame, vec2(64., 0.)), Some(map_rect.max));
+    }
+
+    #[test]
+    fn object_tile_finds_the_tile_under_the_object() {
+        let map = crate::helpers::tiled::load_test_map("assets/maps/TMX/map_test_object_tiles.tmx");
+        let frame = MapFrame::from_tiled_map(&map.map, Transform::IDENTITY);
+        let objects: Vec<_> = map
+            .map
+            .layers()
+            .filter_map(|layer| layer.as_object_layer())
+            .flat_map(|layer| layer.objects())
+            .collect();
+        assert_eq!(objects.len(), 2);
+        // a tile object anchored at its bottom left corner and a rectangle at its top left,
+        // both covering tile (2, 3)
+        for object in objects {
+            assert_eq!(
+                object_tile(&frame, &object),
+                Some(TilePos::new(2, 3)),
+                "{}",
+                object.name
+            );
+        }
+    }
+
+    #[test]
+    fn world_to_tile_round_trips_tile_centers() {
+        let frame = mock_frame(Transform::from_xyz(-20., 12., 0.1));
+        for x in 0..frame.map_size.x {
+            for y in 0..frame.map_size.y {
+                let tile_pos = TilePos::new(x, y);
+                let center = tile_to_world_center(&frame, &tile_pos).unwrap();
+                assert_eq!(world_to_tile(&frame, center), Some(tile_pos));
+            }
+        }
+    }
+
+    #[test]
+    fn map_world_rect_follows_map_transform() {
+        let frame = mock_frame(Transform::IDENTITY);
+        let cases = [
+            (Transform::IDENTITY, Rect::new(-8., -8., 56., 40.)),
+            (
+                Transform::from_xyz(100., -50., 0.1),
+                Rect::new(92., -58., 156., -10.),
+            ),
+            (
+                Transform::from_xyz(100., -50., 0.1).with_scale(Vec3::splat(2.)),
+                Rect::new(84., -66., 212., 30.),
+            ),
+        ];
+        for (transform, expected) in cases {
+            let rect = map_world_rect(&frame, &GlobalTransform::from(transform));
+            assert_eq!(rect, expected, "{transform:?}");
+        }
+    }
+
+    #[test]
+    fn inflate_rect_grows_and_shrinks() {
+        let rect = Rect::new(0., 0., 10., 4.);
+        assert_eq!(inflate_rect(rect, 1.), Rect::new(-1., -1., 11., 5.));
+        assert_eq!(inflate_rect(rect, -1.), Rect::new(1., 1., 9., 3.));
+        assert_eq!(inflate_rect(rect, -3.), Rect::new(3., 2., 7., 2.));
+    }
+}
@@ -0,0 +1,111 @@
+use bevy::prelude::*;
+use bevy::render::camera::RenderTarget;
+use bevy::utils::HashMap;
+use bevy::window::{PrimaryWindow, WindowRef};
+
+use crate::coords;
+
+/// Plugin that computes the cursor's world position once per frame for every camera
+#[derive(Default)]
+pub struct CursorCachePlugin;
+
+/// System set containing the cursor cache update, which runs in `PreUpdate`
+#[derive(Debug, Clone, Copy, SystemSet, PartialEq, Eq, Hash)]
+pub struct CursorCacheSet;
+
+impl Plugin for CursorCachePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CursorWorldCache>()
+            .add_systems(PreUpdate, update_cursor_cache.in_set(CursorCacheSet));
+    }
+}
+
+/// The cursor as seen through one camera
+#[derive(Clone, Copy, Debug)]
+pub struct CameraCursor {
+    /// Cursor position in window coordinates
+    pub screen_pos: Vec2,
+    /// Cursor position in world coordinates
+    pub world_pos: Vec2,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct CacheEntry {
+    cursor: CameraCursor,
+    /// False once the cursor left the camera's window; `cursor` then holds the last known value
+    valid: bool,
+}
+
+/// The cursor position for each camera, keyed by camera entity
+///
+/// Computed in `PreUpdate` so every consumer sees the same value during the frame. The camera
+/// transforms used are the ones propagated at the end of the previous frame.
+#[derive(Resource, Default)]
+pub struct CursorWorldCache {
+    entries: HashMap<Entity, CacheEntry>,
+}
+
+impl CursorWorldCache {
+    /// The cursor for `camera`, `None` when the cursor is not over the camera's window
+    pub fn get(&self, camera: Entity) -> Option<CameraCursor> {
+        self.entries
+            .get(&camera)
+            .filter(|entry| entry.valid)
+            .map(|entry| entry.cursor)
+    }
+
+    /// The last known cursor for `camera`, even if the cursor has since left the window
+    pub fn last_known(&self, camera: Entity) -> Option<CameraCursor> {
+        self.entries.get(&camera).map(|entry| entry.cursor)
+    }
+
+    /// Record the cursor seen through `camera` this frame
+    pub fn insert(&mut self, camera: Entity, cursor: CameraCursor) {
+        self.entries.insert(
+            camera,
+            CacheEntry {
+                cursor,
+                valid: true,
+            },
+        );
+    }
+}
+
+fn update_cursor_cache(
+    mut cache: ResMut<CursorWorldCache>,
+    q_camera: Query<(Entity, &Camera, &GlobalTransform)>,
+    q_primary_window: Query<Entity, With<PrimaryWindow>>,
+    q_window: Query<&Window>,
+) {
+    // forget cameras that no longer exist
+    cache
+        .entries
+        .retain(|camera_entity, _| q_camera.contains(*camera_entity));
+
+    for (camera_entity, camera, camera_transform) in q_camera.iter() {
+        let window_entity = match &camera.target {
+            RenderTarget::Window(WindowRef::Primary) => q_primary_window.get_single().ok(),
+            RenderTarget::Window(WindowRef::Entity(entity)) => Some(*entity),
+            _ => None,
+        };
+        let cursor = window_entity
+            .and_then(|entity| q_window.get(entity).ok())
+            .and_then(|window| window.cursor_position())
+            .and_then(|screen_pos| {
+                let world_pos = coords::screen_to_world(camera, camera_transform, screen_pos)?;
+                Some(CameraCursor {
+                    screen_pos,
+                    world_pos,
+                })
+            });
+
+        match cursor {
+            Some(cursor) => cache.insert(camera_entity, cursor),
+            None => {
+                if let Some(entry) = cache.entries.get_mut(&camera_entity) {
+                    entry.valid = false;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! The OS cursor shows what a click would do: grab while panning, a crosshair while aiming, a
+//! hand over something to interact with, the arrow elsewhere. Over an egui window egui picks the
+//! cursor itself.
+
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy_inspector_egui::bevy_egui::EguiContext;
+
+use crate::camera::Panning;
+use crate::coords::{self, MapFrame};
+use crate::game::GameSet;
+use crate::interact::Interactable;
+use crate::state::level_ready;
+use crate::targeting::TARGETING_KEY;
+use crate::ui::HoveredTile;
+
+/// Plugin that picks the cursor icon of the primary window
+#[derive(Default)]
+pub struct CursorIconPlugin;
+
+impl Plugin for CursorIconPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CursorIconState>().add_systems(
+            Update,
+            update_cursor_icon
+                .in_set(GameSet::RenderPrep)
+                .run_if(level_ready),
+        );
+    }
+}
+
+/// What the cursor shows, from the highest priority down
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CursorIconState {
+    /// Over an egui window, which sets the cursor itself
+    Egui,
+    /// Dragging the camera
+    Grab,
+    /// Aiming with the targeting key
+    Crosshair,
+    /// Hovering an interactable creature or object
+    Pointer,
+    #[default]
+    Arrow,
+}
+
+impl CursorIconState {
+    /// The state for what's going on, the highest priority that applies
+    pub fn pick(
+        egui_wants_pointer: bool,
+        panning: bool,
+        aiming: bool,
+        over_interactable: bool,
+    ) -> Self {
+        if egui_wants_pointer {
+            Self::Egui
+        } else if panning {
+            Self::Grab
+        } else if aiming {
+            Self::Crosshair
+        } else if over_interactable {
+            Self::Pointer
+        } else {
+            Self::Arrow
+        }
+    }
+
+    /// The icon to show, `None` to leave the cursor to egui
+    pub fn icon(self) -> Option<CursorIcon> {
+        match self {
+            Self::Egui => None,
+            Self::Grab => Some(CursorIcon::Grabbing),
+            Self::Crosshair => Some(CursorIcon::Crosshair),
+            Self::Pointer => Some(CursorIcon::Hand),
+            Self::Arrow => Some(CursorIcon::Default),
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_cursor_icon(
+    mut state: ResMut<CursorIconState>,
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+    panning: Res<Panning>,
+    keys: Res<Input<KeyCode>>,
+    hovered_tile: Res<HoveredTile>,
+    map_frame: Option<Res<MapFrame>>,
+    interactables: Query<&GlobalTransform, With<Interactable>>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let egui_wants_pointer = contexts
+        .iter_mut()
+        .next()
+        .map(|mut ctx| ctx.get_mut().wants_pointer_input())
+        .unwrap_or(false);
+    let over_interactable = hovered_tile
+        .0
+        .zip(map_frame)
+        .is_some_and(|(tile, map_frame)| {
+            interactables.iter().any(|transform| {
+                coords::world_to_tile(&map_frame, transform.translation().truncate()) == Some(tile)
+            })
+        });
+    let new_state = CursorIconState::pick(
+        egui_wants_pointer,
+        panning.0,
+        keys.pressed(TARGETING_KEY),
+        over_interactable,
+    );
+    state.set_if_neq(new_state);
+
+    let (Some(icon), Ok(mut window)) = (new_state.icon(), windows.get_single_mut()) else {
+        return;
+    };
+    // only touch the window when the icon changes, every write goes to the window backend
+    if window.cursor.icon != icon {
+        window.cursor.icon = icon;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs_tilemap::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn drag_beats_aim_beats_hover() {
+        use CursorIconState::*;
+        assert_eq!(CursorIconState::pick(true, true, true, true), Egui);
+        assert_eq!(CursorIconState::pick(false, true, true, true), Grab);
+        assert_eq!(CursorIconState::pick(false, false, true, true), Crosshair);
+        assert_eq!(CursorIconState::pick(false, false, false, true), Pointer);
+        assert_eq!(CursorIconState::pick(false, false, false, false), Arrow);
+    }
+
+    #[derive(Resource, Default)]
+    struct WindowWrites(usize);
+
+    fn count_window_writes(windows: Query<(), Changed<Window>>, mut writes: ResMut<WindowWrites>) {
+        writes.0 += windows.iter().count();
+    }
+
+    #[test]
+    fn the_window_is_only_written_when_the_icon_changes() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<CursorIconState>()
+            .init_resource::<Panning>()
+            .init_resource::<Input<KeyCode>>()
+            .init_resource::<HoveredTile>()
+            .init_resource::<WindowWrites>()
+            .insert_resource(MapFrame {
+                transform: Transform::IDENTITY,
+                grid_size: TilemapGridSize { x: 10., y: 10. },
+                map_type: TilemapType::Square,
+                map_size: TilemapSize { x: 8, y: 8 },
+            })
+            .add_systems(Update, (update_cursor_icon, count_window_writes).chain());
+        let window = app.world.spawn((Window::default(), PrimaryWindow)).id();
+        app.world.spawn((
+            Interactable,
+            GlobalTransform::from_translation(Vec3::new(30., 20., 2.)),
+        ));
+        app.update();
+        let icon = |app: &App| app.world.get::<Window>(window).unwrap().cursor.icon;
+        let writes = |app: &App| app.world.resource::<WindowWrites>().0;
+        assert_eq!(icon(&app), CursorIcon::Default);
+        // the window was only added, nothing written
+        assert_eq!(writes(&app), 1);
+
+        app.world.resource_mut::<HoveredTile>().0 = Some(TilePos::new(3, 2));
+        app.update();
+        app.update();
+        assert_eq!(icon(&app), CursorIcon::Hand);
+        assert_eq!(writes(&app), 2);
+
+        app.world.resource_mut::<Panning>().0 = true;
+        app.update();
+        assert_eq!(icon(&app), CursorIcon::Grabbing);
+        assert_eq!(
+            *app.world.resource::<CursorIconState>(),
+            CursorIconState::Grab
+        );
+        assert_eq!(writes(&app), 3);
+    }
+}
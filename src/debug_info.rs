@@ -0,0 +1,172 @@
+//! A snapshot of where the game is, copied to the clipboard for bug reports.
+
+use std::fmt::Write;
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use bevy_inspector_egui::bevy_egui::EguiContext;
+use bevy_window::PrimaryWindow;
+
+use crate::camera::MainCamera;
+use crate::coords::{self, MapFrame};
+use crate::game::GameSet;
+use crate::localization::tr;
+use crate::log_capture::CapturedLog;
+use crate::map::GameInfoAlt;
+use crate::player::MainPlayer;
+use crate::state::AppState;
+use crate::toast::Toasts;
+use crate::ui::HoveredTile;
+
+/// Key copying the snapshot, together with Ctrl and Shift
+pub const COPY_KEY: KeyCode = KeyCode::C;
+
+/// Plugin that copies a `DebugInfo` snapshot to the clipboard on Ctrl+Shift+C or `CopyDebugInfo`
+#[derive(Default)]
+pub struct DebugInfoPlugin;
+
+impl Plugin for DebugInfoPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<CopyDebugInfo>().add_systems(
+            Update,
+            (copy_shortcut, copy_debug_info)
+                .chain()
+                .in_set(GameSet::RenderPrep),
+        );
+    }
+}
+
+/// Send to copy the debug snapshot to the clipboard
+#[derive(Event, Debug, Clone, Copy, Default)]
+pub struct CopyDebugInfo;
+
+/// What a bug report needs to know about the game's state
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DebugInfo {
+    pub map: Option<String>,
+    pub app_state: Option<AppState>,
+    pub player_tile: Option<TilePos>,
+    pub camera_translation: Option<Vec3>,
+    pub camera_scale: Option<f32>,
+    pub hovered_tile: Option<TilePos>,
+    /// Seed of the random generation, the game has none yet
+    pub seed: Option<u64>,
+    pub log: Vec<String>,
+}
+
+impl DebugInfo {
+    /// The snapshot as plain text
+    pub fn to_text(&self) -> String {
+        fn or_none<T>(value: Option<T>, show: impl FnOnce(T) -> String) -> String {
+            value.map(show).unwrap_or_else(|| "none".to_string())
+        }
+        let tile = |tile: TilePos| format!("{},{}", tile.x, tile.y);
+
+        let mut text = String::new();
+        let _ = writeln!(text, "map: {}", or_none(self.map.as_ref(), Clone::clone));
+        let _ = writeln!(
+            text,
+            "state: {}",
+            or_none(self.app_state, |state| format!("{state:?}"))
+        );
+        let _ = writeln!(text, "player tile: {}", or_none(self.player_tile, tile));
+        let _ = writeln!(
+            text,
+            "camera: {} scale {}",
+            or_none(self.camera_translation, |pos| format!(
+                "{:.1},{:.1}",
+                pos.x, pos.y
+            )),
+            or_none(self.camera_scale, |scale| format!("{scale:.2}"))
+        );
+        let _ = writeln!(text, "hovered tile: {}", or_none(self.hovered_tile, tile));
+        let _ = writeln!(
+            text,
+            "seed: {}",
+            or_none(self.seed, |seed| seed.to_string())
+        );
+        let _ = writeln!(text, "last log lines:");
+        for line in &self.log {
+            let _ = writeln!(text, "  {line}");
+        }
+        text
+    }
+}
+
+fn copy_shortcut(keys: Res<Input<KeyCode>>, mut copy: EventWriter<CopyDebugInfo>) {
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if ctrl && shift && keys.just_pressed(COPY_KEY) {
+        copy.send(CopyDebugInfo);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn copy_debug_info(
+    mut requests: EventReader<CopyDebugInfo>,
+    game_info: Option<Res<GameInfoAlt>>,
+    state: Res<State<AppState>>,
+    map_frame: Option<Res<MapFrame>>,
+    players: Query<&Transform, With<MainPlayer>>,
+    cameras: Query<(&Transform, &OrthographicProjection), With<MainCamera>>,
+    hovered: Res<HoveredTile>,
+    log: Res<CapturedLog>,
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+    mut toasts: Toasts,
+) {
+    if requests.read().count() == 0 {
+        return;
+    }
+    let camera = cameras.get_single().ok();
+    let info = DebugInfo {
+        map: game_info.and_then(|info| info.tile_map.path().map(|path| path.to_string())),
+        app_state: Some(*state.get()),
+        player_tile: map_frame.as_deref().and_then(|map_frame| {
+            let player = players.get_single().ok()?;
+            coords::world_to_tile(map_frame, player.translation.truncate())
+        }),
+        camera_translation: camera.map(|(transform, _)| transform.translation),
+        camera_scale: camera.map(|(_, projection)| projection.scale),
+        hovered_tile: hovered.0,
+        seed: None,
+        log: log.lines(),
+    };
+
+    let Some(mut ctx) = contexts.iter_mut().next() else {
+        return;
+    };
+    let text = info.to_text();
+    ctx.get_mut().output_mut(|output| output.copied_text = text);
+    toasts.info(tr!("toast.debug_info_copied"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_text_lists_everything() {
+        let info = DebugInfo {
+            map: Some("maps/main.tmx".to_string()),
+            app_state: Some(AppState::Level),
+            player_tile: Some(TilePos::new(3, 4)),
+            camera_translation: Some(Vec3::new(10., 20.5, 0.)),
+            camera_scale: Some(0.5),
+            hovered_tile: None,
+            seed: None,
+            log: vec!["INFO bevy_test: spawn_level".to_string()],
+        };
+
+        assert_eq!(
+            info.to_text(),
+            "map: maps/main.tmx\n\
+             state: Level\n\
+             player tile: 3,4\n\
+             camera: 10.0,20.5 scale 0.50\n\
+             hovered tile: none\n\
+             seed: none\n\
+             last log lines:\n  \
+             INFO bevy_test: spawn_level\n"
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Debug pause that freezes gameplay while the camera and the inspector stay usable, and steps
+//! it one frame at a time with a fixed delta so animation and movement timing are reproducible.
+
+use std::time::Duration;
+
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::EguiContext;
+use bevy_window::PrimaryWindow;
+
+use crate::game::GameSet;
+
+/// Key that pauses and resumes gameplay
+pub const PAUSE_KEY: KeyCode = KeyCode::F9;
+/// Key that advances paused gameplay by one frame
+pub const STEP_KEY: KeyCode = KeyCode::F10;
+/// Virtual time a single step advances by
+pub const STEP_DELTA: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// Plugin that handles the pause and step keys
+#[derive(Default)]
+pub struct DebugStepPlugin;
+
+impl Plugin for DebugStepPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DebugStep>()
+            .add_systems(PreUpdate, handle_step_keys.after(InputSystem))
+            .add_systems(Update, show_step_state.in_set(GameSet::RenderPrep));
+    }
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct DebugStep {
+    pub paused: bool,
+    /// True during the frame a step was requested
+    stepping: bool,
+}
+
+impl DebugStep {
+    /// Whether gameplay systems run this frame
+    pub fn gameplay_runs(&self) -> bool {
+        !self.paused || self.stepping
+    }
+}
+
+/// Run condition for gameplay systems that freeze while paused
+pub fn gameplay_running(step: Res<DebugStep>) -> bool {
+    step.gameplay_runs()
+}
+
+fn handle_step_keys(
+    keys: Res<Input<KeyCode>>,
+    mut step: ResMut<DebugStep>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    mut time: ResMut<Time>,
+) {
+    step.stepping = false;
+    if keys.just_pressed(PAUSE_KEY) {
+        step.paused = !step.paused;
+        // pausing virtual time stops every timer ticked from `Time`
+        if step.paused {
+            virtual_time.pause();
+        } else {
+            virtual_time.unpause();
+        }
+    }
+    if step.paused && keys.just_pressed(STEP_KEY) {
+        step.stepping = true;
+        virtual_time.advance_by(STEP_DELTA);
+        // `Time` was already copied from the virtual clock this frame
+        *time = virtual_time.as_generic();
+    }
+}
+
+fn show_step_state(
+    step: Res<DebugStep>,
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+) {
+    if !step.paused {
+        return;
+    }
+    let Some(mut ctx) = contexts.iter_mut().next() else {
+        return;
+    };
+    egui::Area::new(egui::Id::new("debug_step"))
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0., 8.))
+        .interactable(false)
+        .show(ctx.get_mut(), |ui| {
+            let state = if step.stepping { "STEP" } else { "PAUSED" };
+            ui.label(
+                egui::RichText::new(format!(
+                    "{state}  ({PAUSE_KEY:?} resume, {STEP_KEY:?} step)"
+                ))
+                .color(egui::Color32::YELLOW)
+                .strong(),
+            );
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::input::keyboard::KeyboardInput;
+    use bevy::input::{ButtonState, InputPlugin};
+
+    use super::*;
+
+    /// Deltas seen by a gameplay system, one per frame it ran
+    #[derive(Resource, Default)]
+    struct Deltas(Vec<Duration>);
+
+    fn record_delta(time: Res<Time>, mut deltas: ResMut<Deltas>) {
+        deltas.0.push(time.delta());
+    }
+
+    fn press(app: &mut App, key_code: KeyCode) {
+        app.world.send_event(KeyboardInput {
+            scan_code: 0,
+            key_code: Some(key_code),
+            state: ButtonState::Pressed,
+            window: Entity::PLACEHOLDER,
+        });
+        app.update();
+        app.world.send_event(KeyboardInput {
+            scan_code: 0,
+            key_code: Some(key_code),
+            state: ButtonState::Released,
+            window: Entity::PLACEHOLDER,
+        });
+    }
+
+    #[test]
+    fn step_runs_gameplay_once_with_fixed_delta() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, InputPlugin))
+            .init_resource::<DebugStep>()
+            .init_resource::<Deltas>()
+            .add_systems(PreUpdate, handle_step_keys.after(InputSystem))
+            .add_systems(Update, record_delta.run_if(gameplay_running));
+
+        app.update();
+        assert_eq!(app.world.resource::<Deltas>().0.len(), 1);
+
+        press(&mut app, PAUSE_KEY);
+        app.update();
+        app.update();
+        assert_eq!(app.world.resource::<Deltas>().0.len(), 1);
+
+        press(&mut app, STEP_KEY);
+        app.update();
+        let deltas = &app.world.resource::<Deltas>().0;
+        assert_eq!(deltas.len(), 2);
+        assert_eq!(deltas[1], STEP_DELTA);
+    }
+}
@@ -0,0 +1,207 @@
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::EguiContext;
+use bevy_window::PrimaryWindow;
+
+use crate::camera::MainCamera;
+use crate::coords::{self, MapFrame};
+use crate::game::GameSet;
+use crate::interact::Interact;
+use crate::localization::localized;
+use crate::state::level_ready;
+use crate::world_label;
+
+/// Distance in logical pixels the bubble keeps from the window edges
+const BUBBLE_EDGE_MARGIN: f32 = 8.;
+
+/// Plugin that shows speech bubbles above NPCs the player interacts with
+#[derive(Default)]
+pub struct DialoguePlugin;
+
+impl Plugin for DialoguePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ActiveDialogue>()
+            .add_systems(
+                Update,
+                (
+                    advance_dialogue.run_if(crate::debug_step::gameplay_running),
+                    dismiss_distant_dialogue,
+                )
+                    .chain()
+                    .in_set(GameSet::Gameplay)
+                    .run_if(level_ready),
+            )
+            .add_systems(
+                Update,
+                show_dialogue_bubble
+                    .in_set(GameSet::RenderPrep)
+                    .run_if(level_ready),
+            );
+    }
+}
+
+/// Lines an NPC says, one bubble page each
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct Dialogue {
+    pub pages: Vec<String>,
+}
+
+impl Dialogue {
+    /// Read the `dialogue` property of a Tiled object, pages are separated by `|`. A page
+    /// starting with `@` is a localization key.
+    pub fn from_properties(properties: &tiled::Properties) -> Option<Self> {
+        let Some(tiled::PropertyValue::StringValue(text)) = properties.get("dialogue") else {
+            return None;
+        };
+        let pages: Vec<String> = text
+            .split('|')
+            .map(str::trim)
+            .filter(|page| !page.is_empty())
+            .map(str::to_string)
+            .collect();
+        (!pages.is_empty()).then_some(Self { pages })
+    }
+}
+
+/// The bubble currently open, only one can be open at a time
+#[derive(Resource, Default)]
+pub struct ActiveDialogue(pub Option<OpenBubble>);
+
+#[derive(Debug, Clone, Copy)]
+pub struct OpenBubble {
+    /// Entity with the `Dialogue` the bubble is shown above
+    pub speaker: Entity,
+    /// Entity that started the conversation
+    pub listener: Entity,
+    pub page: usize,
+}
+
+fn advance_dialogue(
+    mut interact: EventReader<Interact>,
+    dialogues: Query<&Dialogue>,
+    mut active: ResMut<ActiveDialogue>,
+) {
+    for event in interact.read() {
+        let Ok(dialogue) = dialogues.get(event.target) else {
+            continue;
+        };
+        active.0 = match active.0 {
+            // interacting again with the same speaker turns the page, closing after the last one
+            Some(bubble) if bubble.speaker == event.target => {
+                let page = bubble.page + 1;
+                (page < dialogue.pages.len()).then_some(OpenBubble { page, ..bubble })
+            }
+            _ => Some(OpenBubble {
+                speaker: event.target,
+                listener: event.actor,
+                page: 0,
+            }),
+        };
+    }
+}
+
+fn dismiss_distant_dialogue(
+    mut active: ResMut<ActiveDialogue>,
+    transforms: Query<&Transform>,
+    map_frame: Res<MapFrame>,
+) {
+    let Some(bubble) = active.0 else {
+        return;
+    };
+    let tile_of = |entity| {
+        transforms.get(entity).ok().and_then(|transform| {
+            coords::world_to_tile(&map_frame, transform.translation.truncate())
+        })
+    };
+    let close_enough = match (tile_of(bubble.speaker), tile_of(bubble.listener)) {
+        (Some(speaker), Some(listener)) => coords::tile_distance(&speaker, &listener) <= 1,
+        _ => false,
+    };
+    if !close_enough {
+        active.0 = None;
+    }
+}
+
+fn show_dialogue_bubble(
+    active: Res<ActiveDialogue>,
+    dialogues: Query<(&Dialogue, &GlobalTransform)>,
+    q_camera: Query<(&GlobalTransform, &OrthographicProjection), With<MainCamera>>,
+    q_window: Query<&Window, With<PrimaryWindow>>,
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+    map_frame: Res<MapFrame>,
+) {
+    let Some(bubble) = active.0 else {
+        return;
+    };
+    let Ok((dialogue, speaker_transform)) = dialogues.get(bubble.speaker) else {
+        return;
+    };
+    let Some(text) = dialogue.pages.get(bubble.page) else {
+        return;
+    };
+    let text = localized(text);
+    let (Ok((camera_transform, projection)), Ok(window)) =
+        (q_camera.get_single(), q_window.get_single())
+    else {
+        return;
+    };
+    let Some(mut ctx) = contexts.iter_mut().next() else {
+        return;
+    };
+
+    // anchor the bubble just above the top of the speaker's tile, reprojected every frame so it
+    // follows the camera
+    let anchor =
+        speaker_transform.translation().truncate() + Vec2::Y * (map_frame.grid_size.y * 0.5 + 2.);
+    let view = world_label::camera_view(camera_transform, projection);
+    let window_size = Vec2::new(window.width(), window.height());
+    let screen_pos = world_label::world_to_screen(view, window_size, anchor).clamp(
+        Vec2::splat(BUBBLE_EDGE_MARGIN),
+        (window_size - BUBBLE_EDGE_MARGIN).max(Vec2::splat(BUBBLE_EDGE_MARGIN)),
+    );
+
+    egui::Area::new(egui::Id::new("dialogue_bubble"))
+        .order(egui::Order::Foreground)
+        .pivot(egui::Align2::CENTER_BOTTOM)
+        .fixed_pos(egui::pos2(screen_pos.x, screen_pos.y))
+        .constrain(true)
+        .interactable(false)
+        .show(ctx.get_mut(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.set_max_width(200.);
+                ui.label(text.as_ref());
+                if dialogue.pages.len() > 1 {
+                    ui.small(format!("{}/{}", bubble.page + 1, dialogue.pages.len()));
+                }
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties(dialogue: &str) -> tiled::Properties {
+        let mut properties = tiled::Properties::new();
+        properties.insert(
+            "dialogue".to_string(),
+            tiled::PropertyValue::StringValue(dialogue.to_string()),
+        );
+        properties
+    }
+
+    #[test]
+    fn dialogue_pages_split_on_pipe() {
+        assert_eq!(
+            Dialogue::from_properties(&properties("Hello! | Lovely weather.||Bye")),
+            Some(Dialogue {
+                pages: vec![
+                    "Hello!".to_string(),
+                    "Lovely weather.".to_string(),
+                    "Bye".to_string()
+                ]
+            })
+        );
+        assert_eq!(Dialogue::from_properties(&properties(" | ")), None);
+        assert_eq!(Dialogue::from_properties(&tiled::Properties::new()), None);
+    }
+}
@@ -0,0 +1,406 @@
+//! Locked doors, placed in Tiled either as objects of type "door" or as tiles whose type is
+//! "door".
+//!
+//! Doors read these properties:
+//!
+//! * `key_id` the item id that opens the door
+//! * `keep_key` when true the key stays in the inventory, otherwise it is used up
+//! * `open_tile` texture index the door's tile switches to once open
+//! * `flag` the game flag recording that the door is open, `door_<x>_<y>_open` by default
+//!
+//! The flag is the source of truth: the collision map and the tile visuals follow it, so a door
+//! restored from saved flags opens again on load. The tile is switched through `TileWriter`, and
+//! a closed door blocks its tile again whenever the collision map follows a tile change there.
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::collision::{CollisionMap, MoveBlocked};
+use crate::coords::{self, MapFrame};
+use crate::flags::GameFlags;
+use crate::game::{GameSet, LevelLifecycleSet};
+use crate::helpers;
+use crate::inventory::Inventory;
+use crate::localization::tr;
+use crate::map::GameInfoAlt;
+use crate::player::MainPlayer;
+use crate::replication::Replicated;
+use crate::state::{level_ready, AppState};
+use crate::tile_writer::TileWriter;
+use crate::toast::Toasts;
+
+/// Plugin that spawns doors from the map and opens them with keys from the inventory
+#[derive(Default)]
+pub struct DoorPlugin;
+
+impl Plugin for DoorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnEnter(AppState::Level),
+            spawn_doors.in_set(LevelLifecycleSet),
+        )
+        .add_systems(
+            OnExit(AppState::Level),
+            despawn_doors.in_set(LevelLifecycleSet),
+        )
+        .add_systems(
+            Update,
+            (try_open_doors, block_closed_doors, show_door_tiles)
+                .chain()
+                .in_set(GameSet::Gameplay)
+                .run_if(level_ready)
+                .run_if(crate::debug_step::gameplay_running),
+        )
+        .add_systems(
+            PostUpdate,
+            block_closed_doors
+                .after(crate::collision::follow_tile_changes)
+                .run_if(level_ready),
+        );
+    }
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct Door {
+    pub tile: TilePos,
+    /// Item id that opens the door
+    pub key_id: String,
+    /// Whether opening the door leaves the key in the inventory
+    pub keep_key: bool,
+    /// Game flag set once the door is open
+    pub flag: String,
+    /// Texture index of the door's tile when open, `None` keeps the tile as is
+    pub open_texture: Option<u32>,
+    /// Texture index of the tile before it was opened, captured the first time it changes
+    closed_texture: Option<u32>,
+    /// Open state the tile shows, `None` until the tile entities exist
+    shown: Option<bool>,
+}
+
+impl Door {
+    pub fn new(tile: TilePos, key_id: impl Into<String>) -> Self {
+        Self {
+            tile,
+            key_id: key_id.into(),
+            keep_key: false,
+            flag: format!("door_{}_{}_open", tile.x, tile.y),
+            open_texture: None,
+            closed_texture: None,
+            shown: None,
+        }
+    }
+
+    /// Build a door at `tile` from the properties of its Tiled object or tile
+    pub fn from_properties(tile: TilePos, properties: &tiled::Properties) -> Option<Self> {
+        let Some(tiled::PropertyValue::StringValue(key_id)) = properties.get("key_id") else {
+            return None;
+        };
+        let mut door = Self::new(tile, key_id.clone());
+        door.keep_key = matches!(
+            properties.get("keep_key"),
+            Some(tiled::PropertyValue::BoolValue(true))
+        );
+        if let Some(tiled::PropertyValue::StringValue(flag)) = properties.get("flag") {
+            if !flag.is_empty() {
+                door.flag = flag.clone();
+            }
+        }
+        if let Some(tiled::PropertyValue::IntValue(index)) = properties.get("open_tile") {
+            door.open_texture = u32::try_from(*index).ok();
+        }
+        Some(door)
+    }
+}
+
+/// The door on `tile`, from a door object on it or a door tile of a tile layer
+pub fn door_at(map: &tiled::Map, map_frame: &MapFrame, tile: TilePos) -> Option<Door> {
+    for layer in helpers::tiled::all_layers(map) {
+        let door = match layer.layer_type() {
+            tiled::LayerType::Objects(object_layer) => object_layer
+                .objects()
+                .filter(|object| object.user_type.eq_ignore_ascii_case("door"))
+                .filter(|object| coords::object_tile(map_frame, object) == Some(tile))
+                .find_map(|object| Door::from_properties(tile, &object.properties)),
+            tiled::LayerType::Tiles(tiled::TileLayer::Finite(layer_data)) => {
+                // TMX rows go down
+                let mapped_y = map.height.checked_sub(tile.y + 1)?;
+                layer_data
+                    .get_tile(tile.x as i32, mapped_y as i32)
+                    .and_then(|layer_tile| layer_tile.get_tile())
+                    .filter(|tile_data| {
+                        tile_data
+                            .user_type
+                            .as_deref()
+                            .is_some_and(|user_type| user_type.eq_ignore_ascii_case("door"))
+                    })
+                    .and_then(|tile_data| Door::from_properties(tile, &tile_data.properties))
+            }
+            _ => None,
+        };
+        if door.is_some() {
+            return door;
+        }
+    }
+    None
+}
+
+fn despawn_doors(mut commands: Commands, doors: Query<Entity, With<Door>>) {
+    for entity in &doors {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn spawn_doors(
+    mut commands: Commands,
+    game_info: Res<GameInfoAlt>,
+    tile_maps: Res<Assets<helpers::tiled::TiledMap>>,
+    map_frame: Res<MapFrame>,
+) {
+    let Some(map) = tile_maps.get(&game_info.tile_map) else {
+        return;
+    };
+    let map = &map.map;
+
+    for layer in helpers::tiled::all_layers(map) {
+        match layer.layer_type() {
+            tiled::LayerType::Objects(object_layer) => {
+                for object in object_layer.objects() {
+                    if !object.user_type.eq_ignore_ascii_case("door") {
+                        continue;
+                    }
+                    let tile = coords::object_tile(&map_frame, &object);
+                    let Some(tile) = tile else {
+                        warn!("door {} is outside the map, skipping it", object.name);
+                        continue;
+                    };
+                    let Some(door) = Door::from_properties(tile, &object.properties) else {
+                        warn!("door {} has no key_id property, skipping it", object.name);
+                        continue;
+                    };
+                    commands.spawn((door, Name::new(object.name.clone()), Replicated));
+                }
+            }
+            tiled::LayerType::Tiles(tiled::TileLayer::Finite(layer_data)) => {
+                for x in 0..map.width {
+                    for y in 0..map.height {
+                        // Transform TMX coords into bevy coords.
+                        let mapped_y = map.height - 1 - y;
+                        let Some(tile) = layer_data
+                            .get_tile(x as i32, mapped_y as i32)
+                            .and_then(|layer_tile| layer_tile.get_tile())
+                        else {
+                            continue;
+                        };
+                        if !tile
+                            .user_type
+                            .as_deref()
+                            .is_some_and(|user_type| user_type.eq_ignore_ascii_case("door"))
+                        {
+                            continue;
+                        }
+                        let tile_pos = TilePos { x, y };
+                        let Some(door) = Door::from_properties(tile_pos, &tile.properties) else {
+                            warn!("door tile at {x},{y} has no key_id property, skipping it");
+                            continue;
+                        };
+                        commands.spawn((door, Name::new(format!("door {x},{y}")), Replicated));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn try_open_doors(
+    mut blocked: EventReader<MoveBlocked>,
+    player_q: Query<(), With<MainPlayer>>,
+    doors: Query<&Door>,
+    mut inventory: ResMut<Inventory>,
+    mut flags: ResMut<GameFlags>,
+    mut toasts: Toasts,
+) {
+    for event in blocked.read() {
+        if !player_q.contains(event.entity) {
+            continue;
+        }
+        let Some(door) = doors.iter().find(|door| door.tile == event.tile) else {
+            continue;
+        };
+        if flags.is_set(&door.flag) {
+            continue;
+        }
+        if !inventory.contains(&door.key_id) {
+            toasts.warn(tr!("toast.door_locked", key = door.key_id));
+            continue;
+        }
+        if !door.keep_key {
+            inventory.remove(&door.key_id);
+        }
+        flags.set_bool(door.flag.clone(), true);
+    }
+}
+
+/// Block the tiles of the closed doors and free those of the open ones
+pub fn block_closed_doors(
+    doors: Query<&Door>,
+    flags: Res<GameFlags>,
+    mut collision_map: ResMut<CollisionMap>,
+) {
+    for door in &doors {
+        let blocked = !flags.is_set(&door.flag);
+        // only write actual changes, the cost overlay repaints on any
+        if collision_map.is_blocked(&door.tile) != blocked {
+            collision_map.set_blocked(&door.tile, blocked);
+        }
+    }
+}
+
+/// Switch the door tiles to their open or closed texture to match the door flags
+fn show_door_tiles(mut doors: Query<&mut Door>, flags: Res<GameFlags>, mut writer: TileWriter) {
+    for mut door in &mut doors {
+        let Some(open_texture) = door.open_texture else {
+            continue;
+        };
+        let open = flags.is_set(&door.flag);
+        if door.shown == Some(open) {
+            continue;
+        }
+        // the door is drawn by the topmost layer with a tile there
+        let Some((layer, tile)) = writer.topmost_tile(door.tile) else {
+            continue;
+        };
+        let closed_texture = *door.closed_texture.get_or_insert(tile.texture);
+        let wanted = if open { open_texture } else { closed_texture };
+        if tile.texture != wanted {
+            if let Err(error) = writer.set_tile(&layer, door.tile, wanted) {
+                warn!("can't switch the tile of door {}: {error}", door.flag);
+                continue;
+            }
+        }
+        door.shown = Some(open);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::tiled::TiledLayer;
+    use crate::tile_writer::{TileChanged, TileWriterPlugin};
+    use crate::toast::Toast;
+
+    const KEY: &str = "key_red";
+
+    fn test_app(flags: GameFlags) -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, TileWriterPlugin))
+            .insert_resource(flags)
+            .insert_resource(CollisionMap::new(TilemapSize { x: 4, y: 3 }))
+            .init_resource::<Inventory>()
+            .add_event::<Toast>()
+            .add_event::<MoveBlocked>()
+            .add_systems(
+                Update,
+                (try_open_doors, block_closed_doors, show_door_tiles).chain(),
+            );
+        app
+    }
+
+    /// Spawn the door, a one layer tilemap drawing it with texture 5 and the player
+    fn spawn_level(app: &mut App) -> (Entity, Entity) {
+        let door_tile = TilePos::new(2, 1);
+        let mut door = Door::new(door_tile, KEY);
+        door.open_texture = Some(6);
+        app.world.spawn(door);
+
+        let tile = app
+            .world
+            .spawn((TileTextureIndex(5), TileColor::default()))
+            .id();
+        let mut storage = TileStorage::empty(TilemapSize { x: 4, y: 3 });
+        storage.set(&door_tile, tile);
+        app.world.spawn((
+            storage,
+            Transform::default(),
+            Name::new("walls"),
+            TiledLayer {
+                layer_index: 0,
+                tileset_index: 0,
+                above_entities: false,
+                tile_color: Color::WHITE,
+            },
+        ));
+
+        let player = app.world.spawn(MainPlayer).id();
+        (player, tile)
+    }
+
+    fn bump_door(app: &mut App, player: Entity) {
+        app.world.send_event(MoveBlocked {
+            entity: player,
+            tile: TilePos::new(2, 1),
+        });
+        app.update();
+    }
+
+    #[test]
+    fn locked_without_key() {
+        let mut app = test_app(GameFlags::default());
+        let (player, _) = spawn_level(&mut app);
+        app.update();
+        assert!(app
+            .world
+            .resource::<CollisionMap>()
+            .is_blocked(&TilePos::new(2, 1)));
+
+        bump_door(&mut app, player);
+        assert!(app
+            .world
+            .resource::<CollisionMap>()
+            .is_blocked(&TilePos::new(2, 1)));
+        assert_eq!(app.world.resource::<Events<Toast>>().len(), 1);
+    }
+
+    #[test]
+    fn open_door_stays_open_after_save_and_load() {
+        let mut app = test_app(GameFlags::default());
+        let (player, tile) = spawn_level(&mut app);
+        app.world.resource_mut::<Inventory>().add(KEY);
+        app.update();
+
+        bump_door(&mut app, player);
+        assert!(!app
+            .world
+            .resource::<CollisionMap>()
+            .is_blocked(&TilePos::new(2, 1)));
+        assert_eq!(
+            app.world.get::<TileTextureIndex>(tile).map(|index| index.0),
+            Some(6)
+        );
+        let changes = app.world.resource::<Events<TileChanged>>();
+        let mut reader = changes.get_reader();
+        let switched: Vec<_> = reader
+            .read(changes)
+            .map(|change| (change.layer.as_str(), change.new.map(|tile| tile.texture)))
+            .collect();
+        assert_eq!(switched, [("walls", Some(6))]);
+        assert!(!app.world.resource::<Inventory>().contains(KEY));
+
+        let saved = ron::to_string(app.world.resource::<GameFlags>()).unwrap();
+        let mut loaded = test_app(ron::from_str(&saved).unwrap());
+        let (_, tile) = spawn_level(&mut loaded);
+        loaded.update();
+
+        assert!(!loaded
+            .world
+            .resource::<CollisionMap>()
+            .is_blocked(&TilePos::new(2, 1)));
+        assert_eq!(
+            loaded
+                .world
+                .get::<TileTextureIndex>(tile)
+                .map(|index| index.0),
+            Some(6)
+        );
+    }
+}
@@ -0,0 +1,340 @@
+//! Creatures far from the camera go dormant: their entity is despawned and only a small record
+//! is kept until the camera comes close again, so NPC-heavy maps only pay for what is around
+//! the view.
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::animation::AnimationFrame;
+use crate::camera::CameraViewChanged;
+use crate::coords::{self, MapFrame};
+use crate::game::{GameSet, LevelLifecycleSet};
+use crate::groups::GroupId;
+use crate::helpers;
+use crate::helpers::tiled::TiledObject;
+use crate::layers::LayerZConfig;
+use crate::map::{GameInfoAlt, Npc};
+use crate::perf::timed;
+use crate::sprite_atlas::CurrentCreatureAtlas;
+use crate::state::{level_ready, AppState};
+use crate::templates::EntityTemplates;
+
+/// Side of the square chunks dormant creatures are grouped in, in world units
+const CHUNK_SIZE: f32 = 512.;
+
+/// Plugin that puts the NPCs far from the camera to sleep and wakes them up when it comes back
+#[derive(Default)]
+pub struct DormancyPlugin;
+
+impl Plugin for DormancyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DormancySettings>()
+            .init_resource::<DormantCreatures>()
+            .add_systems(
+                Update,
+                (timed!(wake_creatures), timed!(put_creatures_to_sleep))
+                    .chain()
+                    .in_set(GameSet::Gameplay)
+                    .run_if(level_ready),
+            )
+            .add_systems(
+                OnExit(AppState::Level),
+                clear_dormant_creatures.in_set(LevelLifecycleSet),
+            );
+    }
+}
+
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct DormancySettings {
+    /// Dormant creatures this far outside the camera view come back, in world units
+    pub radius: f32,
+    /// Extra distance past `radius` a creature has to be to go dormant, so creatures near the
+    /// edge don't keep going back and forth
+    pub hysteresis: f32,
+}
+
+impl Default for DormancySettings {
+    fn default() -> Self {
+        Self {
+            radius: 480.,
+            hysteresis: 96.,
+        }
+    }
+}
+
+/// What is left of a dormant creature
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DormantCreature {
+    /// Tiled object the creature was spawned from, which tells what to spawn again
+    pub object_id: u32,
+    pub tile: TilePos,
+    pub animation_frame: i32,
+}
+
+/// The dormant creatures, grouped by chunk so waking only looks near the view
+#[derive(Resource, Default, Debug)]
+pub struct DormantCreatures {
+    chunks: HashMap<IVec2, Vec<DormantCreature>>,
+    len: usize,
+}
+
+fn chunk_of(pos: Vec2) -> IVec2 {
+    (pos / CHUNK_SIZE).floor().as_ivec2()
+}
+
+impl DormantCreatures {
+    pub fn insert(&mut self, frame: &MapFrame, creature: DormantCreature) {
+        let pos = coords::tile_to_world_center(frame, &creature.tile).unwrap_or_default();
+        self.chunks.entry(chunk_of(pos)).or_default().push(creature);
+        self.len += 1;
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.len = 0;
+    }
+
+    /// Remove and return the creatures whose tile center is inside `rect`
+    pub fn take_in_rect(&mut self, frame: &MapFrame, rect: Rect) -> Vec<DormantCreature> {
+        let (min, max) = (chunk_of(rect.min), chunk_of(rect.max));
+        let mut taken = Vec::new();
+        self.chunks.retain(|chunk, creatures| {
+            if chunk.cmplt(min).any() || chunk.cmpgt(max).any() {
+                return true;
+            }
+            creatures.retain(|creature| {
+                let inside = coords::tile_to_world_center(frame, &creature.tile)
+                    .is_some_and(|pos| rect.contains(pos));
+                if inside {
+                    taken.push(*creature);
+                }
+                !inside
+            });
+            !creatures.is_empty()
+        });
+        self.len -= taken.len();
+        taken
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn put_creatures_to_sleep(
+    mut commands: Commands,
+    settings: Res<DormancySettings>,
+    map_frame: Res<MapFrame>,
+    mut dormant: ResMut<DormantCreatures>,
+    mut view_changed: EventReader<CameraViewChanged>,
+    mut view: Local<Option<Rect>>,
+    creatures: Query<
+        (Entity, &Transform, &TiledObject, &AnimationFrame),
+        (With<Npc>, Without<GroupId>),
+    >,
+) {
+    if let Some(changed) = view_changed.read().last() {
+        *view = Some(changed.view);
+    }
+    let Some(view) = *view else {
+        return;
+    };
+    let awake = coords::inflate_rect(view, settings.radius + settings.hysteresis);
+    for (entity, transform, object, animation_frame) in &creatures {
+        let pos = transform.translation.truncate();
+        if awake.contains(pos) {
+            continue;
+        }
+        let Some(tile) = coords::world_to_tile(&map_frame, pos) else {
+            continue;
+        };
+        commands.entity(entity).despawn_recursive();
+        dormant.insert(
+            &map_frame,
+            DormantCreature {
+                object_id: object.id,
+                tile,
+                animation_frame: animation_frame.0,
+            },
+        );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn wake_creatures(
+    mut commands: Commands,
+    settings: Res<DormancySettings>,
+    map_frame: Res<MapFrame>,
+    z_config: Res<LayerZConfig>,
+    game_info: Res<GameInfoAlt>,
+    creature_atlas: Res<CurrentCreatureAtlas>,
+    tile_maps: Res<Assets<helpers::tiled::TiledMap>>,
+    atlases: Res<Assets<TextureAtlas>>,
+    entity_templates: Res<Assets<EntityTemplates>>,
+    mut dormant: ResMut<DormantCreatures>,
+    mut view_changed: EventReader<CameraViewChanged>,
+) {
+    let Some(changed) = view_changed.read().last() else {
+        return;
+    };
+    if dormant.is_empty() {
+        return;
+    }
+    let Some(map) = tile_maps.get(&game_info.tile_map) else {
+        return;
+    };
+    for creature in dormant.take_in_rect(&map_frame, coords::inflate_rect(changed.view, settings.radius)) {
+        let Some(object) = helpers::tiled::object_by_id(&map.map, creature.object_id) else {
+            warn!("dormant creature {} has no object", creature.object_id);
+            continue;
+        };
+        let Some(pos) = coords::tile_to_world_center(&map_frame, &creature.tile) else {
+            continue;
+        };
+        let z = z_config.creature_z(&map_frame, pos);
+        crate::map::spawn_creature(
+            &mut commands,
+            &creature_atlas.0,
+            &atlases,
+            entity_templates.get(&game_info.templates),
+            &object,
+            pos.extend(z),
+            creature.animation_frame,
+        );
+    }
+}
+
+fn clear_dormant_creatures(mut dormant: ResMut<DormantCreatures>) {
+    dormant.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::animation::{AnimationClip, AnimationTimer};
+
+    fn map_frame() -> MapFrame {
+        MapFrame {
+            transform: Transform::IDENTITY,
+            grid_size: TilemapGridSize { x: 24., y: 24. },
+            map_type: TilemapType::Square,
+            map_size: TilemapSize { x: 200, y: 200 },
+        }
+    }
+
+    fn test_app(settings: DormancySettings) -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_event::<CameraViewChanged>()
+            .insert_resource(map_frame())
+            .insert_resource(settings)
+            .init_resource::<DormantCreatures>()
+            .init_resource::<Assets<TextureAtlas>>()
+            .init_resource::<crate::animation::SpriteIndexWarnings>()
+            .add_systems(Update, (put_creatures_to_sleep, crate::animation::animate_sprite));
+        app.world.send_event(CameraViewChanged {
+            view: Rect::new(0., 0., 480., 320.),
+            scale: 1.,
+        });
+        app
+    }
+
+    fn spawn_npc(app: &mut App, id: u32, tile: TilePos, animation_frame: i32) -> Entity {
+        let pos = coords::tile_to_world_center(&map_frame(), &tile).unwrap();
+        app.world
+            .spawn((
+                Transform::from_translation(pos.extend(2.)),
+                TiledObject { id },
+                AnimationFrame(animation_frame),
+                AnimationClip::creature_row(1, 20),
+                AnimationTimer(Timer::from_seconds(0.2, TimerMode::Repeating)),
+                TextureAtlasSprite::new(22),
+                Npc,
+            ))
+            .id()
+    }
+
+    #[test]
+    fn dormant_creatures_keep_their_tile_and_frame() {
+        let mut app = test_app(DormancySettings {
+            radius: 48.,
+            hysteresis: 24.,
+        });
+        let near = spawn_npc(&mut app, 1, TilePos::new(5, 5), 0);
+        let far = spawn_npc(&mut app, 2, TilePos::new(60, 40), 1);
+        app.update();
+
+        assert!(app.world.get_entity(near).is_some());
+        assert!(app.world.get_entity(far).is_none());
+        let frame = map_frame();
+        let mut dormant = app.world.resource_mut::<DormantCreatures>();
+        assert_eq!(dormant.len(), 1);
+
+        // looking elsewhere wakes nobody
+        assert!(dormant
+            .take_in_rect(&frame, Rect::new(0., 0., 480., 320.))
+            .is_empty());
+        let around = coords::tile_to_world_center(&frame, &TilePos::new(60, 40)).unwrap();
+        assert_eq!(
+            dormant.take_in_rect(&frame, Rect::from_center_size(around, Vec2::splat(100.))),
+            vec![DormantCreature {
+                object_id: 2,
+                tile: TilePos::new(60, 40),
+                animation_frame: 1,
+            }]
+        );
+        assert!(dormant.is_empty());
+    }
+
+    #[test]
+    fn dormant_creatures_cost_less_per_frame() {
+        const FRAMES: u32 = 20;
+        let mut app = test_app(DormancySettings {
+            radius: f32::INFINITY,
+            hysteresis: 0.,
+        });
+        for id in 0..10_000 {
+            let tile = TilePos::new(id % 100 * 2, id / 100 * 2);
+            spawn_npc(&mut app, id, tile, 0);
+        }
+        app.update();
+
+        let frame_time = |app: &mut App| {
+            let start = Instant::now();
+            for _ in 0..FRAMES {
+                app.update();
+            }
+            start.elapsed() / FRAMES
+        };
+        let awake_time = frame_time(&mut app);
+
+        app.world.resource_mut::<DormancySettings>().radius = 240.;
+        app.update();
+        let awake_count = app
+            .world
+            .query_filtered::<(), With<Npc>>()
+            .iter(&app.world)
+            .count();
+        assert_eq!(
+            app.world.resource::<DormantCreatures>().len() + awake_count,
+            10_000
+        );
+        assert!(awake_count < 500);
+
+        let dormant_time = frame_time(&mut app);
+        assert!(
+            dormant_time < awake_time,
+            "{dormant_time:?} per frame with dormant creatures, {awake_time:?} awake"
+        );
+        assert!(dormant_time < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,677 @@
+//! Editor mode: select the entities spawned from map objects and move them around as a group.
+//!
+//! In editor mode a click selects the object on the clicked tile, shift-click adds or removes it,
+//! and dragging from a selected object moves the whole selection by whole tiles, with ghosts
+//! showing where it lands. A move is one `GroupMove`, undone at once with Ctrl+Z. The moved
+//! objects keep their `TiledObject`, so an export writes their new positions. The player can only
+//! be picked with "Include player" checked, and tiles never are. With "Edit regions" checked the
+//! clicks go to the trigger zones and regions instead, see `region_editor`, whose edits share the
+//! undo history with the moves. With "Paint terrain" checked they paint tiles instead, see
+//! `terrain`. The group layers of the map can be shown and hidden from the "Layers" section,
+//! hiding a group hides every layer in it.
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use bevy_inspector_egui::bevy_egui::EguiContext;
+use bevy_window::PrimaryWindow;
+
+use crate::camera::{CameraMode, MainCamera, PanCamSystemSet, WorldClick};
+use crate::coords::{self, MapFrame};
+use crate::cursor::CursorWorldCache;
+use crate::game::GameSet;
+use crate::helpers::tiled::{TiledGroupLayer, TiledObject};
+use crate::input_map::{MouseAction, MouseInputMap};
+use crate::player::MainPlayer;
+use crate::region_editor::MapRegion;
+use crate::state::level_ready;
+
+/// Key that turns editor mode on and off
+pub const EDITOR_KEY: KeyCode = KeyCode::F6;
+/// Key that undoes the last edit, with Ctrl held
+pub const UNDO_KEY: KeyCode = KeyCode::Z;
+const GHOST_ALPHA: f32 = 0.4;
+/// Over the object it's the ghost of
+const GHOST_Z: f32 = 0.5;
+
+/// Plugin that selects and moves map objects in editor mode
+#[derive(Default)]
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EditorState>()
+            .init_resource::<EditorSelection>()
+            .init_resource::<EditorHistory>()
+            .init_resource::<EditorDrag>()
+            .add_event::<GroupMove>()
+            .add_event::<EditRegion>()
+            .add_event::<UndoEdit>()
+            .add_systems(
+                Update,
+                (toggle_editor, undo_key)
+                    .chain()
+                    .in_set(GameSet::Input)
+                    .run_if(level_ready)
+                    .run_if(crate::console::console_closed),
+            )
+            .add_systems(
+                Update,
+                drag_selection
+                    .in_set(GameSet::Cursor)
+                    .run_if(level_ready)
+                    .run_if(editing_objects),
+            )
+            .add_systems(
+                Update,
+                // clicks come out of the camera's drag handling
+                select_objects
+                    .in_set(GameSet::Camera)
+                    .after(PanCamSystemSet)
+                    .run_if(level_ready)
+                    .run_if(editing_objects),
+            )
+            .add_systems(
+                Update,
+                apply_edits.in_set(GameSet::Gameplay).run_if(level_ready),
+            )
+            .add_systems(
+                Update,
+                editor_ui
+                    .in_set(GameSet::RenderPrep)
+                    .run_if(level_ready)
+                    .run_if(editing),
+            );
+    }
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct EditorState {
+    pub active: bool,
+    /// Whether the player can be selected and moved like the objects
+    pub include_player: bool,
+    /// Whether the clicks edit the regions rather than select the objects
+    pub regions: bool,
+    /// Whether the clicks paint terrain rather than select the objects
+    pub terrain: bool,
+}
+
+/// Run condition for the editor, and for the player input it replaces
+pub fn editing(state: Res<EditorState>) -> bool {
+    state.active
+}
+
+fn editing_objects(state: Res<EditorState>) -> bool {
+    state.active && !state.regions && !state.terrain
+}
+
+/// Run condition for the region tools of the editor
+pub fn editing_regions(state: Res<EditorState>) -> bool {
+    state.active && state.regions
+}
+
+/// Run condition for the terrain painter of the editor
+pub fn editing_terrain(state: Res<EditorState>) -> bool {
+    state.active && state.terrain
+}
+
+/// The selected entities, in the order they were picked
+#[derive(Resource, Debug, Default)]
+pub struct EditorSelection(pub Vec<Entity>);
+
+impl EditorSelection {
+    /// Select `entity` alone, or add or remove it from the selection when `extend`ing
+    pub fn pick(&mut self, entity: Option<Entity>, extend: bool) {
+        match (entity, extend) {
+            (Some(entity), true) => {
+                if let Some(index) = self.0.iter().position(|selected| *selected == entity) {
+                    self.0.remove(index);
+                } else {
+                    self.0.push(entity);
+                }
+            }
+            (Some(entity), false) => self.0 = vec![entity],
+            (None, true) => {}
+            (None, false) => self.0.clear(),
+        }
+    }
+}
+
+/// Move `entities` by `delta` tiles, all of them or none
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct GroupMove {
+    pub entities: Vec<Entity>,
+    pub delta: IVec2,
+}
+
+/// Replace the `MapRegion` of `entity` with `region`
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct EditRegion {
+    pub entity: Entity,
+    pub region: MapRegion,
+}
+
+/// Undo the last `GroupMove` or `EditRegion`
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UndoEdit;
+
+/// A carried out group move, each entity with the tile it left and the tile it landed on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppliedMove(pub Vec<(Entity, TilePos, TilePos)>);
+
+/// A carried out edit
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit {
+    Move(AppliedMove),
+    Region {
+        entity: Entity,
+        before: MapRegion,
+        after: MapRegion,
+    },
+}
+
+/// The edits that can be undone, the last one on top
+#[derive(Resource, Debug, Default)]
+pub struct EditorHistory {
+    pub undo: Vec<Edit>,
+}
+
+/// A drag of the selection from the tile `from` to the tile `to`
+#[derive(Debug, Clone, Copy)]
+struct GroupDrag {
+    from: TilePos,
+    to: TilePos,
+    /// The camera mode to go back to, the camera is frozen while dragging
+    camera_mode: CameraMode,
+}
+
+#[derive(Resource, Debug, Default)]
+struct EditorDrag(Option<GroupDrag>);
+
+/// Shows where a dragged entity would land
+#[derive(Component, Debug)]
+pub struct Ghost {
+    of: Entity,
+}
+
+type Pickable = Or<(With<TiledObject>, With<MainPlayer>)>;
+
+fn shift_held(keys: &Input<KeyCode>) -> bool {
+    keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight])
+}
+
+fn ctrl_held(keys: &Input<KeyCode>) -> bool {
+    keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight])
+}
+
+fn offset(tile: TilePos, delta: IVec2) -> Option<TilePos> {
+    let x = u32::try_from(tile.x as i32 + delta.x).ok()?;
+    let y = u32::try_from(tile.y as i32 + delta.y).ok()?;
+    Some(TilePos::new(x, y))
+}
+
+fn toggle_editor(
+    keys: Res<Input<KeyCode>>,
+    mut state: ResMut<EditorState>,
+    mut selection: ResMut<EditorSelection>,
+) {
+    if keys.just_pressed(EDITOR_KEY) {
+        state.active = !state.active;
+        if !state.active {
+            selection.0.clear();
+        }
+        info!("editor mode {}", if state.active { "on" } else { "off" });
+    }
+}
+
+fn undo_key(keys: Res<Input<KeyCode>>, state: Res<EditorState>, mut undo: EventWriter<UndoEdit>) {
+    if state.active && ctrl_held(&keys) && keys.just_pressed(UNDO_KEY) {
+        undo.send(UndoEdit);
+    }
+}
+
+/// The pickable entity on `tile`, if any
+fn entity_on(
+    map_frame: &MapFrame,
+    tile: TilePos,
+    include_player: bool,
+    pickable: &Query<(Entity, &Transform, Has<MainPlayer>), Pickable>,
+) -> Option<Entity> {
+    pickable
+        .iter()
+        .filter(|(_, _, is_player)| include_player || !is_player)
+        .find(|(_, transform, _)| {
+            coords::world_to_tile(map_frame, transform.translation.truncate()) == Some(tile)
+        })
+        .map(|(entity, _, _)| entity)
+}
+
+fn select_objects(
+    mut clicks: EventReader<WorldClick>,
+    keys: Res<Input<KeyCode>>,
+    mouse_map: Res<MouseInputMap>,
+    state: Res<EditorState>,
+    map_frame: Res<MapFrame>,
+    pickable: Query<(Entity, &Transform, Has<MainPlayer>), Pickable>,
+    mut selection: ResMut<EditorSelection>,
+) {
+    for click in clicks.read() {
+        if !mouse_map.is(MouseAction::Select, click.button) {
+            continue;
+        }
+        let Some(tile) = coords::world_to_tile(&map_frame, click.world_pos) else {
+            continue;
+        };
+        let entity = entity_on(&map_frame, tile, state.include_player, &pickable);
+        selection.pick(entity, shift_held(&keys));
+    }
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn drag_selection(
+    mut commands: Commands,
+    buttons: Res<Input<MouseButton>>,
+    mouse_map: Res<MouseInputMap>,
+    keys: Res<Input<KeyCode>>,
+    cursor_cache: Res<CursorWorldCache>,
+    cameras: Query<Entity, With<MainCamera>>,
+    map_frame: Res<MapFrame>,
+    mut selection: ResMut<EditorSelection>,
+    mut drag: ResMut<EditorDrag>,
+    mut camera_mode: ResMut<CameraMode>,
+    selected: Query<
+        (
+            &Transform,
+            Option<&TextureAtlasSprite>,
+            Option<&Handle<TextureAtlas>>,
+        ),
+        Without<Ghost>,
+    >,
+    mut ghosts: Query<(Entity, &Ghost, &mut Transform)>,
+    mut moves: EventWriter<GroupMove>,
+) {
+    let cursor_tile = cameras
+        .get_single()
+        .ok()
+        .and_then(|camera| cursor_cache.get(camera))
+        .and_then(|cursor| coords::world_to_tile(&map_frame, cursor.world_pos));
+    let tile_of = |entity: Entity| {
+        let (transform, _, _) = selected.get(entity).ok()?;
+        coords::world_to_tile(&map_frame, transform.translation.truncate())
+    };
+
+    let Some(mut current) = drag.0 else {
+        // a press on a selected entity picks the selection up instead of panning
+        let Some(tile) = cursor_tile.filter(|_| buttons.just_pressed(mouse_map.select)) else {
+            return;
+        };
+        if !selection
+            .0
+            .iter()
+            .any(|entity| tile_of(*entity) == Some(tile))
+        {
+            return;
+        }
+        drag.0 = Some(GroupDrag {
+            from: tile,
+            to: tile,
+            camera_mode: *camera_mode,
+        });
+        *camera_mode = CameraMode::Frozen;
+        let tile_size = Vec2::new(map_frame.grid_size.x, map_frame.grid_size.y);
+        for entity in &selection.0 {
+            let Ok((transform, sprite, atlas)) = selected.get(*entity) else {
+                continue;
+            };
+            let ghost_transform =
+                transform.with_translation(transform.translation + Vec3::Z * GHOST_Z);
+            let mut ghost = commands.spawn((
+                Ghost { of: *entity },
+                SpatialBundle::from_transform(ghost_transform),
+                Name::new("ghost"),
+            ));
+            match (sprite, atlas) {
+                (Some(sprite), Some(atlas)) => {
+                    let color = sprite.color.with_a(sprite.color.a() * GHOST_ALPHA);
+                    ghost.insert((
+                        TextureAtlasSprite {
+                            color,
+                            ..sprite.clone()
+                        },
+                        atlas.clone(),
+                    ));
+                }
+                _ => {
+                    ghost.insert((
+                        Sprite {
+                            color: Color::rgba(1., 1., 1., GHOST_ALPHA),
+                            custom_size: Some(tile_size),
+                            ..default()
+                        },
+                        Handle::<Image>::default(),
+                    ));
+                }
+            }
+        }
+        return;
+    };
+
+    if let Some(tile) = cursor_tile {
+        current.to = tile;
+    }
+    let delta = IVec2::new(current.to.x as i32, current.to.y as i32)
+        - IVec2::new(current.from.x as i32, current.from.y as i32);
+
+    if buttons.pressed(mouse_map.select) {
+        drag.0 = Some(current);
+        for (_, ghost, mut transform) in &mut ghosts {
+            let target = tile_of(ghost.of)
+                .and_then(|tile| offset(tile, delta))
+                .and_then(|tile| coords::tile_to_world_center(&map_frame, &tile));
+            if let Some(target) = target {
+                transform.translation.x = target.x;
+                transform.translation.y = target.y;
+            }
+        }
+        return;
+    }
+
+    // dropped
+    drag.0 = None;
+    *camera_mode = current.camera_mode;
+    for (ghost, _, _) in &ghosts {
+        commands.entity(ghost).despawn();
+    }
+    if delta != IVec2::ZERO {
+        moves.send(GroupMove {
+            entities: selection.0.clone(),
+            delta,
+        });
+    } else {
+        // the camera was frozen, so this click is no `WorldClick`: pick the entity here
+        let clicked = selection
+            .0
+            .iter()
+            .copied()
+            .find(|entity| tile_of(*entity) == Some(current.from));
+        selection.pick(clicked, shift_held(&keys));
+    }
+}
+
+/// Put each entity of `moves` on the center of its tile, `to` or `from` when undoing
+fn place(
+    moves: &AppliedMove,
+    undo: bool,
+    map_frame: &MapFrame,
+    transforms: &mut Query<&mut Transform, Without<Ghost>>,
+) {
+    for (entity, from, to) in &moves.0 {
+        let tile = if undo { from } else { to };
+        let (Ok(mut transform), Some(center)) = (
+            transforms.get_mut(*entity),
+            coords::tile_to_world_center(map_frame, tile),
+        ) else {
+            continue;
+        };
+        transform.translation.x = center.x;
+        transform.translation.y = center.y;
+    }
+}
+
+/// Carry out the group moves, region edits and undos
+pub fn apply_edits(
+    mut moves: EventReader<GroupMove>,
+    mut region_edits: EventReader<EditRegion>,
+    mut undos: EventReader<UndoEdit>,
+    map_frame: Res<MapFrame>,
+    mut history: ResMut<EditorHistory>,
+    mut transforms: Query<&mut Transform, Without<Ghost>>,
+    mut regions: Query<&mut MapRegion>,
+) {
+    for group_move in moves.read() {
+        let applied: Option<Vec<_>> = group_move
+            .entities
+            .iter()
+            .filter_map(|entity| {
+                let transform = transforms.get(*entity).ok()?;
+                coords::world_to_tile(&map_frame, transform.translation.truncate())
+                    .map(|from| (*entity, from))
+            })
+            .map(|(entity, from)| {
+                let to = offset(from, group_move.delta)
+                    .filter(|to| to.within_map_bounds(&map_frame.map_size))?;
+                Some((entity, from, to))
+            })
+            .collect();
+        // a move pushing anything off the map moves nothing
+        let Some(applied) = applied.filter(|applied| !applied.is_empty()) else {
+            warn!("can't move the selection by {} tiles", group_move.delta);
+            continue;
+        };
+        let applied = AppliedMove(applied);
+        place(&applied, false, &map_frame, &mut transforms);
+        history.undo.push(Edit::Move(applied));
+    }
+    for edit in region_edits.read() {
+        let Ok(mut region) = regions.get_mut(edit.entity) else {
+            continue;
+        };
+        if *region == edit.region {
+            continue;
+        }
+        history.undo.push(Edit::Region {
+            entity: edit.entity,
+            before: region.clone(),
+            after: edit.region.clone(),
+        });
+        *region = edit.region.clone();
+    }
+    for _ in undos.read() {
+        match history.undo.pop() {
+            Some(Edit::Move(last)) => place(&last, true, &map_frame, &mut transforms),
+            Some(Edit::Region { entity, before, .. }) => {
+                if let Ok(mut region) = regions.get_mut(entity) {
+                    *region = before;
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+fn editor_ui(
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+    mut state: ResMut<EditorState>,
+    mut selection: ResMut<EditorSelection>,
+    history: Res<EditorHistory>,
+    players: Query<(), With<MainPlayer>>,
+    mut group_layers: Query<(&Name, &mut Visibility), With<TiledGroupLayer>>,
+    mut undo: EventWriter<UndoEdit>,
+) {
+    let Some(mut ctx) = contexts.iter_mut().next() else {
+        return;
+    };
+    egui::Window::new("Editor")
+        .default_pos(egui::pos2(8., 80.))
+        .resizable(false)
+        .show(ctx.get_mut(), |ui| {
+            ui.label(format!("{} selected", selection.0.len()));
+            if ui
+                .checkbox(&mut state.include_player, "Include player")
+                .changed()
+                && !state.include_player
+            {
+                selection.0.retain(|entity| !players.contains(*entity));
+            }
+            if ui.checkbox(&mut state.regions, "Edit regions").changed() && state.regions {
+                state.terrain = false;
+            }
+            if ui.checkbox(&mut state.terrain, "Paint terrain").changed() && state.terrain {
+                state.regions = false;
+            }
+            ui.add_enabled_ui(!history.undo.is_empty(), |ui| {
+                if ui.button("Undo").clicked() {
+                    undo.send(UndoEdit);
+                }
+            });
+            if !group_layers.is_empty() {
+                ui.collapsing("Layers", |ui| {
+                    for (name, mut visibility) in &mut group_layers {
+                        let mut shown = *visibility != Visibility::Hidden;
+                        if ui.checkbox(&mut shown, name.as_str()).changed() {
+                            *visibility = if shown {
+                                Visibility::Inherited
+                            } else {
+                                Visibility::Hidden
+                            };
+                        }
+                    }
+                });
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Npc;
+    use crate::region_editor::RegionKind;
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<EditorHistory>()
+            .insert_resource(MapFrame {
+                transform: Transform::IDENTITY,
+                grid_size: TilemapGridSize { x: 10., y: 10. },
+                map_type: TilemapType::Square,
+                map_size: TilemapSize { x: 8, y: 8 },
+            })
+            .add_event::<GroupMove>()
+            .add_event::<EditRegion>()
+            .add_event::<UndoEdit>()
+            .add_systems(Update, apply_edits);
+        app
+    }
+
+    fn spawn_npc(app: &mut App, id: u32, tile: TilePos) -> Entity {
+        let pos = coords::tile_to_world_center(app.world.resource::<MapFrame>(), &tile).unwrap();
+        app.world
+            .spawn((
+                Npc,
+                TiledObject { id },
+                Transform::from_translation(pos.extend(2.)),
+            ))
+            .id()
+    }
+
+    fn tile(app: &App, entity: Entity) -> Option<TilePos> {
+        let transform = app.world.get::<Transform>(entity).unwrap();
+        coords::world_to_tile(
+            app.world.resource::<MapFrame>(),
+            transform.translation.truncate(),
+        )
+    }
+
+    #[test]
+    fn group_moves_carry_every_selected_entity_and_undo_at_once() {
+        let mut app = test_app();
+        let bat = spawn_npc(&mut app, 1, TilePos::new(1, 1));
+        let slime = spawn_npc(&mut app, 2, TilePos::new(2, 4));
+        let ghost = spawn_npc(&mut app, 3, TilePos::new(6, 6));
+        let mut selection = EditorSelection::default();
+        selection.pick(Some(bat), false);
+        selection.pick(Some(slime), true);
+
+        app.world.send_event(GroupMove {
+            entities: selection.0.clone(),
+            delta: IVec2::new(3, -1),
+        });
+        app.update();
+        assert_eq!(tile(&app, bat), Some(TilePos::new(4, 0)));
+        assert_eq!(tile(&app, slime), Some(TilePos::new(5, 3)));
+        assert_eq!(tile(&app, ghost), Some(TilePos::new(6, 6)));
+        assert_eq!(app.world.get::<Transform>(bat).unwrap().translation.z, 2.);
+
+        // off the map for the bat, so nobody moves
+        app.world.send_event(GroupMove {
+            entities: selection.0.clone(),
+            delta: IVec2::new(0, -1),
+        });
+        app.update();
+        assert_eq!(tile(&app, bat), Some(TilePos::new(4, 0)));
+        assert_eq!(app.world.resource::<EditorHistory>().undo.len(), 1);
+
+        app.world.send_event(UndoEdit);
+        app.update();
+        assert_eq!(tile(&app, bat), Some(TilePos::new(1, 1)));
+        assert_eq!(tile(&app, slime), Some(TilePos::new(2, 4)));
+        assert!(app.world.resource::<EditorHistory>().undo.is_empty());
+    }
+
+    #[test]
+    fn region_edits_are_undone_with_the_moves() {
+        let mut app = test_app();
+        let bat = spawn_npc(&mut app, 1, TilePos::new(1, 1));
+        let original = MapRegion {
+            name: "gate".to_string(),
+            kind: RegionKind::Trigger,
+            rect: Rect::new(0., 0., 20., 10.),
+            object_id: Some(2),
+            actions: "gate".to_string(),
+            deleted: false,
+        };
+        let region = app.world.spawn(original.clone()).id();
+        let resized = MapRegion {
+            rect: Rect::new(0., 0., 40., 10.),
+            ..original.clone()
+        };
+
+        app.world.send_event(EditRegion {
+            entity: region,
+            region: resized.clone(),
+        });
+        app.update();
+        app.world.send_event(GroupMove {
+            entities: vec![bat],
+            delta: IVec2::new(1, 0),
+        });
+        app.world.send_event(EditRegion {
+            entity: region,
+            region: MapRegion {
+                deleted: true,
+                ..resized.clone()
+            },
+        });
+        app.update();
+        assert!(app.world.get::<MapRegion>(region).unwrap().deleted);
+        assert_eq!(app.world.resource::<EditorHistory>().undo.len(), 3);
+
+        app.world.send_event(UndoEdit);
+        app.update();
+        assert_eq!(app.world.get::<MapRegion>(region), Some(&resized));
+        assert_eq!(tile(&app, bat), Some(TilePos::new(2, 1)));
+        app.world.send_event(UndoEdit);
+        app.world.send_event(UndoEdit);
+        app.update();
+        assert_eq!(tile(&app, bat), Some(TilePos::new(1, 1)));
+        assert_eq!(app.world.get::<MapRegion>(region), Some(&original));
+    }
+
+    #[test]
+    fn shift_picks_add_and_remove() {
+        let mut world = World::new();
+        let [a, b] = [world.spawn_empty().id(), world.spawn_empty().id()];
+        let mut selection = EditorSelection::default();
+        selection.pick(Some(a), false);
+        selection.pick(Some(b), true);
+        assert_eq!(selection.0, [a, b]);
+        selection.pick(Some(a), true);
+        assert_eq!(selection.0, [b]);
+        selection.pick(None, true);
+        assert_eq!(selection.0, [b]);
+        selection.pick(Some(a), false);
+        assert_eq!(selection.0, [a]);
+        selection.pick(None, false);
+        assert!(selection.0.is_empty());
+    }
+}
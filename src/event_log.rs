@@ -0,0 +1,297 @@
+//! A log of the gameplay events, to answer "why did that happen".
+//!
+//! `App::log_event::<E>()` adds a `log_events::<E>` system appending every `E` to the `EventLog`
+//! with the frame it was read on, so logging one more event type is one line in
+//! `EventLogPlugin`. The log keeps the last `CAPACITY` events. It's shown in a window opened with
+//! `EVENT_LOG_KEY`, and `events tail [type]` prints the end of it in the console.
+
+use std::collections::VecDeque;
+use std::fmt::Debug;
+
+use bevy::core::FrameCount;
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use bevy_inspector_egui::bevy_egui::EguiContext;
+use bevy_window::PrimaryWindow;
+
+use crate::console::{ConsoleCommand, DebugConsole};
+use crate::game::GameSet;
+
+/// Key that opens and closes the event log window
+pub const EVENT_LOG_KEY: KeyCode = KeyCode::F7;
+/// Events kept in the log, older ones are dropped
+const CAPACITY: usize = 500;
+/// Events printed by `events tail`
+const TAIL_LEN: usize = 10;
+
+/// Plugin that logs the gameplay events and shows the log
+#[derive(Default)]
+pub struct EventLogPlugin;
+
+impl Plugin for EventLogPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EventLog>()
+            .log_event::<crate::movement::MoveResolved>()
+            .log_event::<crate::collision::MoveBlocked>()
+            .log_event::<crate::camera::WorldClick>()
+            .log_event::<crate::triggers::ZoneEntered>()
+            .log_event::<crate::health::DamageEvent>()
+            .log_event::<crate::health::Died>()
+            .log_event::<crate::combat::BumpAttack>()
+            .log_event::<crate::hostile::AggroGained>()
+            .log_event::<crate::hostile::AggroLost>()
+            .log_event::<crate::flags::FlagChanged>()
+            .log_event::<crate::interact::Interact>()
+            .log_event::<crate::map_switch::LoadMap>()
+            .log_event::<crate::groups::MoveGroup>()
+            .log_event::<crate::save_slots::SlotRequest>()
+            .log_event::<crate::statistics::MilestoneReached>()
+            .log_event::<crate::tile_writer::TileChanged>()
+            .add_systems(
+                Update,
+                (toggle_event_log, events_command).in_set(GameSet::Input),
+            )
+            .add_systems(Update, show_event_log.in_set(GameSet::RenderPrep));
+    }
+}
+
+/// Adding event types to the `EventLog`
+pub trait LogEventsExt {
+    /// Log every `E` sent from now on
+    fn log_event<E: Event + Debug>(&mut self) -> &mut Self;
+}
+
+impl LogEventsExt for App {
+    fn log_event<E: Event + Debug>(&mut self) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(EventLog::default)
+            .types
+            .push(event_name::<E>());
+        // after `Update`, where the gameplay events are sent
+        self.add_systems(PostUpdate, log_events::<E>)
+    }
+}
+
+/// Short name of the event type, without its module path
+fn event_name<E>() -> &'static str {
+    let name = std::any::type_name::<E>();
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+/// An event in the log
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogEntry {
+    pub name: &'static str,
+    pub frame: u32,
+    /// The event's `Debug` output
+    pub payload: String,
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct EventLog {
+    entries: VecDeque<LogEntry>,
+    /// The logged event types, in the order they were added
+    types: Vec<&'static str>,
+    /// Event types left out of the window
+    hidden: HashSet<&'static str>,
+    /// While paused, events go by without being logged
+    pub paused: bool,
+    pub open: bool,
+}
+
+impl EventLog {
+    pub fn push(&mut self, entry: LogEntry) {
+        if self.entries.len() == CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// The logged events, oldest first
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &LogEntry> {
+        self.entries.iter()
+    }
+
+    /// The last `count` events named `name`, or of any type, oldest first
+    pub fn tail(&self, name: Option<&str>, count: usize) -> Vec<&LogEntry> {
+        let mut tail: Vec<_> = self
+            .entries
+            .iter()
+            .rev()
+            .filter(|entry| name.is_none_or(|name| entry.name.eq_ignore_ascii_case(name)))
+            .take(count)
+            .collect();
+        tail.reverse();
+        tail
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// Append the `E` events of this frame to the `EventLog`
+pub fn log_events<E: Event + Debug>(
+    mut events: EventReader<E>,
+    frame: Res<FrameCount>,
+    mut log: ResMut<EventLog>,
+) {
+    if log.paused {
+        events.clear();
+        return;
+    }
+    for event in events.read() {
+        log.push(LogEntry {
+            name: event_name::<E>(),
+            frame: frame.0,
+            payload: format!("{event:?}"),
+        });
+    }
+}
+
+fn toggle_event_log(keys: Res<Input<KeyCode>>, mut log: ResMut<EventLog>) {
+    if keys.just_pressed(EVENT_LOG_KEY) {
+        log.open = !log.open;
+    }
+}
+
+/// `events tail [type]` prints the last events, of one type or all
+fn events_command(
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<DebugConsole>,
+    log: Res<EventLog>,
+) {
+    for command in console_commands.read() {
+        if command.name != "events" {
+            continue;
+        }
+        if command.args.first().map(String::as_str) != Some("tail") {
+            console.print("usage: events tail [type]");
+            continue;
+        }
+        let name = command.args.get(1).map(String::as_str);
+        let tail = log.tail(name, TAIL_LEN);
+        if tail.is_empty() {
+            console.print("no events logged");
+        }
+        for entry in tail {
+            console.print(format!("[{}] {}", entry.frame, entry.payload));
+        }
+    }
+}
+
+fn show_event_log(
+    mut log: ResMut<EventLog>,
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+) {
+    if !log.open {
+        return;
+    }
+    let Some(mut ctx) = contexts.iter_mut().next() else {
+        return;
+    };
+
+    let log = &mut *log;
+    let mut open = true;
+    egui::Window::new("Event Log")
+        .open(&mut open)
+        .default_width(460.)
+        .show(ctx.get_mut(), |ui| {
+            ui.horizontal(|ui| {
+                let pause = if log.paused { "Resume" } else { "Pause" };
+                if ui.button(pause).clicked() {
+                    log.paused = !log.paused;
+                }
+                if ui.button("Clear").clicked() {
+                    log.clear();
+                }
+            });
+            ui.horizontal_wrapped(|ui| {
+                for name in &log.types {
+                    let mut shown = !log.hidden.contains(name);
+                    if ui.checkbox(&mut shown, *name).changed() {
+                        if shown {
+                            log.hidden.remove(name);
+                        } else {
+                            log.hidden.insert(name);
+                        }
+                    }
+                }
+            });
+            ui.separator();
+            egui::ScrollArea::vertical()
+                .max_height(300.)
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for entry in log.entries() {
+                        if !log.hidden.contains(entry.name) {
+                            ui.monospace(format!("[{}] {}", entry.frame, entry.payload));
+                        }
+                    }
+                });
+        });
+    log.open = open;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Event, Debug)]
+    struct Ping(#[allow(dead_code)] u32);
+
+    #[derive(Event, Debug)]
+    struct Pong;
+
+    #[test]
+    fn events_are_logged_in_order_with_their_frame() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_event::<Ping>()
+            .add_event::<Pong>()
+            .log_event::<Ping>()
+            .log_event::<Pong>();
+
+        app.world.send_event(Ping(1));
+        app.world.send_event(Ping(2));
+        app.update();
+        app.update();
+        app.world.send_event(Pong);
+        app.update();
+
+        let log = app.world.resource::<EventLog>();
+        let logged: Vec<_> = log
+            .entries()
+            .map(|entry| (entry.name, entry.frame, entry.payload.as_str()))
+            .collect();
+        assert_eq!(
+            logged,
+            [
+                ("Ping", 0, "Ping(1)"),
+                ("Ping", 0, "Ping(2)"),
+                ("Pong", 2, "Pong"),
+            ]
+        );
+        assert_eq!(log.types, ["Ping", "Pong"]);
+        let tail: Vec<_> = log
+            .tail(Some("ping"), 1)
+            .iter()
+            .map(|e| e.payload.as_str())
+            .collect();
+        assert_eq!(tail, ["Ping(2)"]);
+    }
+
+    #[test]
+    fn the_log_keeps_the_latest_events() {
+        let mut log = EventLog::default();
+        for frame in 0..CAPACITY as u32 + 5 {
+            log.push(LogEntry {
+                name: "Ping",
+                frame,
+                payload: String::new(),
+            });
+        }
+        assert_eq!(log.entries().count(), CAPACITY);
+        assert_eq!(log.entries().next().unwrap().frame, 5);
+    }
+}
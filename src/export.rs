@@ -0,0 +1,718 @@
+//! Writes the live map back to a TMX file Tiled can open.
+//!
+//! The export starts from the text of the map file it was loaded from. Tilesets, tile layers that
+//! weren't edited and objects that didn't move are copied byte for byte, so a diff against the
+//! original only shows the edits. Edited tile layers are rewritten as CSV data and moved objects
+//! get new `x`/`y` attributes. The regions edited in the editor are resized, removed or added to
+//! the object layer of their kind, which is created when the map has none.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use thiserror::Error;
+
+use crate::coords::{self, MapFrame};
+use crate::groups::GroupId;
+use crate::helpers::tiled::{all_layers, TiledLayer, TiledMap, TiledObject};
+use crate::map::GameInfoAlt;
+use crate::region_editor::{MapRegion, RegionKind};
+
+/// Where the asset server reads the map files from
+const ASSETS_DIR: &str = "assets";
+/// Objects closer than this to their original position, in Tiled pixels, haven't moved
+const MOVE_EPSILON: f32 = 0.01;
+
+const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+const FLIP_BITS: u32 = FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY;
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("the map is not loaded")]
+    MapNotLoaded,
+    #[error("the map was not loaded from a file")]
+    NoSourceFile,
+    #[error("malformed map file: {0}")]
+    Malformed(&'static str),
+    #[error("could not read or write the map file: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Changes to apply to the original map text
+#[derive(Debug, Default)]
+pub struct TmxEdits {
+    /// New global tile ids of edited tile layers by layer id, rows top to bottom
+    pub layers: HashMap<u32, Vec<u32>>,
+    /// New positions of moved objects by object id, in Tiled pixels
+    pub objects: HashMap<u32, Vec2>,
+    /// New bounds of resized rectangle objects by object id, in Tiled pixels
+    pub resized: HashMap<u32, Rect>,
+    /// Ids of the deleted objects
+    pub removed: HashSet<u32>,
+    /// Objects to add, numbered from the map's `nextobjectid` on
+    pub added: Vec<NewObject>,
+}
+
+impl TmxEdits {
+    /// Number of objects moved, resized, removed or added
+    pub fn object_count(&self) -> usize {
+        self.objects.len() + self.resized.len() + self.removed.len() + self.added.len()
+    }
+}
+
+/// A rectangle object to add to the object layer `layer`
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewObject {
+    pub layer: String,
+    pub object_type: String,
+    pub name: String,
+    /// Bounds in Tiled pixels
+    pub rect: Rect,
+    pub properties: Vec<(String, String)>,
+}
+
+/// Default export path: next to the source map, with `.export.tmx` as extension
+pub fn default_export_path(world: &World) -> Option<PathBuf> {
+    let handle = &world.get_resource::<GameInfoAlt>()?.tile_map;
+    let source = world.resource::<AssetServer>().get_path(handle)?;
+    let source = Path::new(ASSETS_DIR).join(source.path());
+    Some(source.with_extension("export.tmx"))
+}
+
+/// Write the current map, with the live tiles, object positions and regions, as TMX to `path`
+///
+/// Returns the number of edited layers and changed objects.
+pub fn export_map(world: &mut World, path: &Path) -> Result<(usize, usize), ExportError> {
+    let mut layers_q = world.query::<(&TiledLayer, &TileStorage)>();
+    let mut tiles_q = world.query::<(&TileTextureIndex, &TileFlip)>();
+    // the members of a group stand around its object, which stays where it is
+    let mut objects_q =
+        world.query_filtered::<(&TiledObject, &GlobalTransform), Without<GroupId>>();
+    let mut regions_q = world.query::<&MapRegion>();
+
+    let handle = world
+        .get_resource::<GameInfoAlt>()
+        .ok_or(ExportError::MapNotLoaded)?
+        .tile_map
+        .clone();
+    let source_path = world
+        .resource::<AssetServer>()
+        .get_path(&handle)
+        .ok_or(ExportError::NoSourceFile)?;
+    let source = std::fs::read_to_string(Path::new(ASSETS_DIR).join(source_path.path()))?;
+    let map = &world
+        .resource::<Assets<TiledMap>>()
+        .get(&handle)
+        .ok_or(ExportError::MapNotLoaded)?
+        .map;
+    let map_frame = world
+        .get_resource::<MapFrame>()
+        .ok_or(ExportError::MapNotLoaded)?;
+    let first_gids = first_gids(&source)?;
+
+    let mut edits = TmxEdits::default();
+
+    // tiles of each layer as they are now, starting from the file so tiles of tilesets that
+    // weren't rendered are kept
+    for (layer_index, layer) in all_layers(map).enumerate() {
+        let tiled::LayerType::Tiles(tiled::TileLayer::Finite(layer_data)) = layer.layer_type()
+        else {
+            continue;
+        };
+        let original = layer_gids(map, &layer_data, &first_gids);
+        let mut live = original.clone();
+        for (tiled_layer, storage) in layers_q.iter(world) {
+            if tiled_layer.layer_index != layer_index {
+                continue;
+            }
+            let Some(first_gid) = first_gids.get(tiled_layer.tileset_index) else {
+                continue;
+            };
+            let tileset_gids = *first_gid..*first_gid + tileset_len(map, tiled_layer.tileset_index);
+            for gid in &mut live {
+                if tileset_gids.contains(&(*gid & !FLIP_BITS)) {
+                    *gid = 0;
+                }
+            }
+            for (pos, tile) in storage.iter().enumerate() {
+                let Some((texture, flip)) = tile.and_then(|tile| tiles_q.get(world, tile).ok())
+                else {
+                    continue;
+                };
+                // storage rows go up, TMX rows go down
+                let (x, y) = (pos as u32 % map.width, pos as u32 / map.width);
+                let index = ((map.height - 1 - y) * map.width + x) as usize;
+                live[index] = encode_gid(first_gid + texture.0, flip.x, flip.y, flip.d);
+            }
+        }
+        if live != original {
+            edits.layers.insert(layer.id(), live);
+        }
+    }
+
+    let positions: HashMap<u32, Vec2> = objects_q
+        .iter(world)
+        .map(|(object, transform)| {
+            let tiled_px = coords::world_to_tiled_px(map_frame, transform.translation().truncate());
+            (object.id, tiled_px)
+        })
+        .collect();
+    let mut region_rects = HashMap::new();
+    for region in regions_q.iter(world) {
+        let rect = coords::world_rect_to_tiled_px(map_frame, region.rect);
+        match (region.object_id, region.deleted) {
+            (Some(id), true) => {
+                edits.removed.insert(id);
+            }
+            (Some(id), false) => {
+                region_rects.insert(id, rect);
+            }
+            (None, true) => {}
+            (None, false) => edits.added.push(NewObject {
+                layer: region.kind.layer_name().to_string(),
+                object_type: region.kind.object_type().to_string(),
+                name: region.name.clone(),
+                rect,
+                properties: match region.kind {
+                    RegionKind::Trigger => vec![("actions".to_string(), region.actions.clone())],
+                    _ => Vec::new(),
+                },
+            }),
+        }
+    }
+    for layer in all_layers(map) {
+        let tiled::LayerType::Objects(object_layer) = layer.layer_type() else {
+            continue;
+        };
+        for object in object_layer.objects() {
+            if let Some(pos) = positions.get(&object.id()) {
+                if pos.distance(Vec2::new(object.x, object.y)) > MOVE_EPSILON {
+                    edits.objects.insert(object.id(), *pos);
+                }
+            }
+            if let (Some(rect), tiled::ObjectShape::Rect { width, height }) =
+                (region_rects.get(&object.id()), &object.shape)
+            {
+                let original = Rect::new(object.x, object.y, object.x + width, object.y + height);
+                if original.min.distance(rect.min) > MOVE_EPSILON
+                    || original.max.distance(rect.max) > MOVE_EPSILON
+                {
+                    edits.resized.insert(object.id(), *rect);
+                }
+            }
+        }
+    }
+
+    let exported = rewrite_tmx(&source, &edits, map.width)?;
+    std::fs::write(path, exported)?;
+    Ok((edits.layers.len(), edits.object_count()))
+}
+
+/// Global tile id as stored in TMX files, with the flip flags in the high bits
+pub fn encode_gid(gid: u32, flip_h: bool, flip_v: bool, flip_d: bool) -> u32 {
+    let mut encoded = gid;
+    if flip_h {
+        encoded |= FLIPPED_HORIZONTALLY;
+    }
+    if flip_v {
+        encoded |= FLIPPED_VERTICALLY;
+    }
+    if flip_d {
+        encoded |= FLIPPED_DIAGONALLY;
+    }
+    encoded
+}
+
+/// Number of ids the tileset at `tileset_index` spans
+fn tileset_len(map: &tiled::Map, tileset_index: usize) -> u32 {
+    map.tilesets()
+        .get(tileset_index)
+        .map_or(0, |tileset| tileset.tilecount)
+}
+
+/// Global tile ids of a layer, rows top to bottom, 0 for empty cells
+pub fn layer_gids(
+    map: &tiled::Map,
+    layer: &tiled::FiniteTileLayer,
+    first_gids: &[u32],
+) -> Vec<u32> {
+    let mut gids = Vec::with_capacity((map.width * map.height) as usize);
+    for y in 0..map.height as i32 {
+        for x in 0..map.width as i32 {
+            let gid = layer.get_tile_data(x, y).map_or(0, |tile| {
+                let first_gid = first_gids.get(tile.tileset_index()).copied().unwrap_or(1);
+                encode_gid(first_gid + tile.id(), tile.flip_h, tile.flip_v, tile.flip_d)
+            });
+            gids.push(gid);
+        }
+    }
+    gids
+}
+
+/// The `firstgid` of every tileset of the map, in order
+pub fn first_gids(source: &str) -> Result<Vec<u32>, ExportError> {
+    tags(source)
+        .filter(|(_, tag)| tag_name(tag) == "tileset")
+        .map(|(_, tag)| {
+            attribute(tag, "firstgid")
+                .and_then(|gid| gid.parse().ok())
+                .ok_or(ExportError::Malformed("tileset without firstgid"))
+        })
+        .collect()
+}
+
+/// Apply `edits` to the TMX text `source`, everything else is copied unchanged
+pub fn rewrite_tmx(source: &str, edits: &TmxEdits, width: u32) -> Result<String, ExportError> {
+    let mut out = String::with_capacity(source.len());
+    // end of the source text already copied to `out`
+    let mut copied = 0;
+    let mut skip_until = 0;
+    let mut in_tileset = false;
+    // the added objects by layer, in the order of `edits.added`, each with its new id
+    let mut added: Vec<(&str, Vec<(u32, &NewObject)>)> = Vec::new();
+    let mut group: Option<&str> = None;
+    // id of the first object layer created for the added objects
+    let mut new_layer_id = 0;
+    let group_names: HashSet<&str> = tags(source)
+        .filter(|(_, tag)| tag_name(tag) == "objectgroup")
+        .filter_map(|(_, tag)| attribute(tag, "name"))
+        .collect();
+
+    for (start, tag) in tags(source) {
+        if start < skip_until {
+            continue;
+        }
+        let end = start + tag.len();
+        match tag_name(tag) {
+            "map" if !edits.added.is_empty() => {
+                let next_object_id: u32 = attribute(tag, "nextobjectid")
+                    .and_then(|id| id.parse().ok())
+                    .ok_or(ExportError::Malformed("map without nextobjectid"))?;
+                let next_layer_id: u32 = attribute(tag, "nextlayerid")
+                    .and_then(|id| id.parse().ok())
+                    .ok_or(ExportError::Malformed("map without nextlayerid"))?;
+                for (object, id) in edits.added.iter().zip(next_object_id..) {
+                    match added.iter_mut().find(|(layer, _)| *layer == object.layer) {
+                        Some((_, objects)) => objects.push((id, object)),
+                        None => added.push((object.layer.as_str(), vec![(id, object)])),
+                    }
+                }
+                let new_groups = added
+                    .iter()
+                    .filter(|(layer, _)| !group_names.contains(layer))
+                    .count() as u32;
+                new_layer_id = next_layer_id;
+                let next_object_id = next_object_id + edits.added.len() as u32;
+                let tag = set_attribute(
+                    tag,
+                    "nextlayerid",
+                    &(next_layer_id + new_groups).to_string(),
+                );
+                out.push_str(&source[copied..start]);
+                out.push_str(&set_attribute(
+                    &tag,
+                    "nextobjectid",
+                    &next_object_id.to_string(),
+                ));
+                copied = end;
+            }
+            "tileset" => in_tileset = !tag.ends_with("/>"),
+            "/tileset" => in_tileset = false,
+            // collision shapes inside tilesets have their own object ids
+            _ if in_tileset => {}
+            "objectgroup" => {
+                group = attribute(tag, "name");
+                let new_objects = added.iter().find(|(layer, _)| Some(*layer) == group);
+                if let (true, Some((_, objects))) = (tag.ends_with("/>"), new_objects) {
+                    // an empty layer has to be opened to take the objects
+                    let indent = line_indent(source, start);
+                    out.push_str(&source[copied..start]);
+                    out.push_str(&tag[..tag.len() - 2]);
+                    out.push_str(">\n");
+                    out.push_str(&objects_xml(objects, &format!("{indent} ")));
+                    out.push_str(&format!("{indent}</objectgroup>"));
+                    copied = end;
+                }
+            }
+            "/objectgroup" => {
+                let new_objects = added.iter().find(|(layer, _)| Some(*layer) == group);
+                if let Some((_, objects)) = new_objects {
+                    // on the lines before the closing tag
+                    let indent = line_indent(source, start);
+                    let line_start = start - indent.len();
+                    out.push_str(&source[copied..line_start]);
+                    out.push_str(&objects_xml(objects, &format!("{indent} ")));
+                    copied = line_start;
+                }
+                group = None;
+            }
+            "/map" => {
+                let missing = added
+                    .iter()
+                    .filter(|(layer, _)| !group_names.contains(layer));
+                out.push_str(&source[copied..start]);
+                for ((layer, objects), id) in missing.zip(new_layer_id..) {
+                    out.push_str(&format!(
+                        " <objectgroup id=\"{id}\" name=\"{}\">\n",
+                        escape(layer)
+                    ));
+                    out.push_str(&objects_xml(objects, "  "));
+                    out.push_str(" </objectgroup>\n");
+                }
+                copied = start;
+            }
+            "layer" => {
+                let Some(gids) = tag_id(tag).and_then(|id| edits.layers.get(&id)) else {
+                    continue;
+                };
+                let close = source[end..]
+                    .find("</layer>")
+                    .ok_or(ExportError::Malformed("unclosed layer"))?
+                    + end;
+                out.push_str(&source[copied..start]);
+                let indent = line_indent(source, start);
+                out.push_str(&layer_xml(tag, &source[end..close], indent, gids, width));
+                copied = close + "</layer>".len();
+                skip_until = copied;
+            }
+            "object" => {
+                let Some(id) = tag_id(tag) else {
+                    continue;
+                };
+                if edits.removed.contains(&id) {
+                    // the whole element, with its line
+                    let close = if tag.ends_with("/>") {
+                        end
+                    } else {
+                        source[end..]
+                            .find("</object>")
+                            .ok_or(ExportError::Malformed("unclosed object"))?
+                            + end
+                            + "</object>".len()
+                    };
+                    let line_start = start - line_indent(source, start).len();
+                    out.push_str(&source[copied..line_start]);
+                    copied = close + usize::from(source[close..].starts_with('\n'));
+                    skip_until = copied;
+                    continue;
+                }
+                let mut edited = tag.to_string();
+                if let Some(pos) = edits.objects.get(&id) {
+                    edited = set_attribute(&edited, "x", &format_number(pos.x));
+                    edited = set_attribute(&edited, "y", &format_number(pos.y));
+                }
+                if let Some(rect) = edits.resized.get(&id) {
+                    edited = set_attribute(&edited, "x", &format_number(rect.min.x));
+                    edited = set_attribute(&edited, "y", &format_number(rect.min.y));
+                    edited = set_attribute(&edited, "width", &format_number(rect.width()));
+                    edited = set_attribute(&edited, "height", &format_number(rect.height()));
+                }
+                if edited != tag {
+                    out.push_str(&source[copied..start]);
+                    out.push_str(&edited);
+                    copied = end;
+                }
+            }
+            _ => {}
+        }
+    }
+    out.push_str(&source[copied..]);
+    Ok(out)
+}
+
+/// Every tag of `source` with its byte offset
+fn tags(source: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut pos = 0;
+    std::iter::from_fn(move || {
+        let start = pos + source[pos..].find('<')?;
+        let end = start + source[start..].find('>')? + 1;
+        pos = end;
+        Some((start, &source[start..end]))
+    })
+}
+
+/// `layer` for `<layer id="1">`, `/layer` for `</layer>`
+fn tag_name(tag: &str) -> &str {
+    let name = &tag[1..];
+    let len = name
+        .find(|c: char| c.is_whitespace() || c == '>' || (c == '/' && !name.starts_with(c)))
+        .unwrap_or(name.len());
+    &name[..len]
+}
+
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let key = format!(" {name}=\"");
+    let start = tag.find(&key)? + key.len();
+    let len = tag[start..].find('"')?;
+    Some(&tag[start..start + len])
+}
+
+fn tag_id(tag: &str) -> Option<u32> {
+    attribute(tag, "id")?.parse().ok()
+}
+
+/// `tag` with the attribute `name` set to `value`, added at the end when missing
+fn set_attribute(tag: &str, name: &str, value: &str) -> String {
+    let key = format!(" {name}=\"");
+    match tag.find(&key) {
+        Some(found) => {
+            let start = found + key.len();
+            let len = tag[start..].find('"').unwrap_or(0);
+            format!("{}{value}{}", &tag[..start], &tag[start + len..])
+        }
+        None => {
+            let close = if tag.ends_with("/>") {
+                tag.len() - 2
+            } else {
+                tag.len() - 1
+            };
+            format!("{}{key}{value}\"{}", &tag[..close], &tag[close..])
+        }
+    }
+}
+
+/// Whitespace between the start of the line and `pos`
+fn line_indent(source: &str, pos: usize) -> &str {
+    let line_start = source[..pos].rfind('\n').map_or(0, |newline| newline + 1);
+    let indent = &source[line_start..pos];
+    if indent.trim().is_empty() {
+        indent
+    } else {
+        ""
+    }
+}
+
+/// `text` with the XML special characters escaped, for attribute values
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The elements of the new `objects`, each on its own line starting with `indent`
+fn objects_xml(objects: &[(u32, &NewObject)], indent: &str) -> String {
+    let mut xml = String::new();
+    for (id, object) in objects {
+        xml.push_str(&format!(
+            "{indent}<object id=\"{id}\" name=\"{}\" type=\"{}\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"",
+            escape(&object.name),
+            escape(&object.object_type),
+            format_number(object.rect.min.x),
+            format_number(object.rect.min.y),
+            format_number(object.rect.width()),
+            format_number(object.rect.height()),
+        ));
+        if object.properties.is_empty() {
+            xml.push_str("/>\n");
+            continue;
+        }
+        xml.push_str(&format!(">\n{indent} <properties>\n"));
+        for (name, value) in &object.properties {
+            xml.push_str(&format!(
+                "{indent}  <property name=\"{}\" value=\"{}\"/>\n",
+                escape(name),
+                escape(value)
+            ));
+        }
+        xml.push_str(&format!("{indent} </properties>\n{indent}</object>\n"));
+    }
+    xml
+}
+
+/// Numbers the way Tiled writes them, whole numbers without a fraction
+fn format_number(value: f32) -> String {
+    if value.fract() == 0. {
+        format!("{}", value as i64)
+    } else {
+        format!("{value}")
+    }
+}
+
+/// A tile layer element with CSV data, keeping the start tag and properties of the original
+fn layer_xml(start_tag: &str, inner: &str, indent: &str, gids: &[u32], width: u32) -> String {
+    let mut xml = format!("{start_tag}\n");
+    if let (Some(start), Some(end)) = (inner.find("<properties"), inner.find("</properties>")) {
+        xml.push_str(&format!(
+            "{indent} {}\n",
+            &inner[start..end + "</properties>".len()]
+        ));
+    }
+    xml.push_str(&format!("{indent} <data encoding=\"csv\">\n"));
+    let rows: Vec<String> = gids
+        .chunks(width.max(1) as usize)
+        .map(|row| {
+            row.iter()
+                .map(|gid| gid.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .collect();
+    xml.push_str(&rows.join(",\n"));
+    xml.push_str(&format!("\n</data>\n{indent}</layer>"));
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::tiled::parse_tmx;
+
+    const MAP_PATH: &str = "assets/maps/TMX/map_test_1.tmx";
+
+    fn load(source: &str) -> tiled::Map {
+        parse_tmx(source.as_bytes(), Path::new(MAP_PATH)).unwrap()
+    }
+
+    fn tile_layers(map: &tiled::Map, first_gids: &[u32]) -> Vec<(u32, Vec<u32>)> {
+        map.layers()
+            .filter_map(|layer| match layer.layer_type() {
+                tiled::LayerType::Tiles(tiled::TileLayer::Finite(data)) => {
+                    Some((layer.id(), layer_gids(map, &data, first_gids)))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn no_edits_copy_the_map_unchanged() {
+        let source = std::fs::read_to_string(MAP_PATH).unwrap();
+        let exported = rewrite_tmx(&source, &TmxEdits::default(), 28).unwrap();
+        assert_eq!(exported, source);
+    }
+
+    #[test]
+    fn edited_map_loads_back_with_the_same_tiles() {
+        let source = std::fs::read_to_string(MAP_PATH).unwrap();
+        let map = load(&source);
+        let first_gids = first_gids(&source).unwrap();
+        assert_eq!(first_gids, vec![1, 1765, 2251, 2427]);
+
+        let layers = tile_layers(&map, &first_gids);
+        let (edited_id, mut gids) = layers[0].clone();
+        gids[0] = encode_gid(2, true, false, false);
+        gids[map.width as usize + 3] = 0;
+        let mut edits = TmxEdits::default();
+        edits.layers.insert(edited_id, gids.clone());
+        edits.objects.insert(1, Vec2::new(84., 96.5));
+
+        let exported = rewrite_tmx(&source, &edits, map.width).unwrap();
+        let reloaded = load(&exported);
+
+        for (id, layer_gids) in tile_layers(&reloaded, &first_gids) {
+            if id == edited_id {
+                assert_eq!(layer_gids, gids);
+            } else {
+                let (_, original) = layers.iter().find(|(layer_id, _)| *layer_id == id).unwrap();
+                assert_eq!(&layer_gids, original, "layer {id} changed");
+            }
+        }
+
+        // only the spawn object moved, not the collision shape with the same id in a tileset
+        let object = reloaded
+            .layers()
+            .find_map(|layer| match layer.layer_type() {
+                tiled::LayerType::Objects(objects) => objects.objects().find(|o| o.id() == 1),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!((object.x, object.y), (84., 96.5));
+        assert!(exported.contains(r#"<object id="1" x="2" y="1" width="22" height="23"/>"#));
+
+        // unedited layers are copied byte for byte
+        let untouched = &source[source.find("<layer id=\"11\"").unwrap()..];
+        let untouched = &untouched[..untouched.find("</layer>").unwrap()];
+        assert!(exported.contains(untouched));
+    }
+
+    fn object<'map>(map: &'map tiled::Map, id: u32) -> Option<(String, tiled::Object<'map>)> {
+        map.layers().find_map(|layer| match layer.layer_type() {
+            tiled::LayerType::Objects(objects) => objects
+                .objects()
+                .find(|object| object.id() == id)
+                .map(|object| (layer.name.clone(), object)),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn regions_are_added_resized_and_removed() {
+        let source = std::fs::read_to_string(MAP_PATH).unwrap();
+        let mut edits = TmxEdits {
+            added: vec![
+                NewObject {
+                    layer: "triggers".to_string(),
+                    object_type: "trigger".to_string(),
+                    name: "Gate & door".to_string(),
+                    rect: Rect::new(24., 48., 72., 96.),
+                    properties: vec![("actions".to_string(), "message:Hi;gate".to_string())],
+                },
+                NewObject {
+                    layer: "Spawner Layer".to_string(),
+                    object_type: "camera_poi".to_string(),
+                    name: "lookout".to_string(),
+                    rect: Rect::new(0., 0., 24., 24.),
+                    properties: Vec::new(),
+                },
+            ],
+            ..default()
+        };
+        edits.resized.insert(1, Rect::new(48., 72., 96., 120.));
+
+        let exported = rewrite_tmx(&source, &edits, 28).unwrap();
+        assert!(exported.contains(r#"nextlayerid="13" nextobjectid="4""#));
+        let reloaded = load(&exported);
+        let (layer, trigger) = object(&reloaded, 2).unwrap();
+        assert_eq!(layer, "triggers");
+        assert_eq!(
+            (trigger.name.as_str(), trigger.user_type.as_str()),
+            ("Gate & door", "trigger")
+        );
+        assert_eq!(
+            trigger.properties.get("actions"),
+            Some(&tiled::PropertyValue::StringValue(
+                "message:Hi;gate".to_string()
+            ))
+        );
+        let (layer, poi) = object(&reloaded, 3).unwrap();
+        assert_eq!(layer, "Spawner Layer");
+        assert_eq!((poi.x, poi.y), (0., 0.));
+        let (_, resized) = object(&reloaded, 1).unwrap();
+        assert_eq!((resized.x, resized.y), (48., 72.));
+        let tiled::ObjectShape::Rect { width, height } = resized.shape else {
+            panic!("the spawn is no longer a rectangle");
+        };
+        assert_eq!((width, height), (48., 48.));
+
+        // the removed object goes with its line
+        let mut edits = TmxEdits::default();
+        edits.removed.insert(1);
+        let exported = rewrite_tmx(&source, &edits, 28).unwrap();
+        let line = source
+            .lines()
+            .find(|line| line.contains("player_start_0"))
+            .unwrap();
+        assert_eq!(exported, source.replace(&format!("{line}\n"), ""));
+        assert!(object(&load(&exported), 1).is_none());
+    }
+
+    #[test]
+    fn attributes_are_replaced_or_added() {
+        let tag = r#"<object id="4" x="1" y="2"/>"#;
+        assert_eq!(
+            set_attribute(tag, "x", "10.5"),
+            r#"<object id="4" x="10.5" y="2"/>"#
+        );
+        assert_eq!(
+            set_attribute(r#"<object id="4">"#, "y", "3"),
+            r#"<object id="4" y="3">"#
+        );
+        assert_eq!(tag_name(tag), "object");
+        assert_eq!(tag_name("</layer>"), "/layer");
+        assert_eq!(tag_name("<data encoding=\"csv\">"), "data");
+    }
+}
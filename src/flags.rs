@@ -0,0 +1,309 @@
+//! Shared game state used by triggers, dialogue and level logic, e.g. `door_3_open = true`.
+
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::EguiContext;
+use bevy_window::PrimaryWindow;
+use serde::{Deserialize, Serialize};
+
+use crate::game::GameSet;
+use crate::state::level_ready;
+
+/// Plugin that sends `FlagChanged` events and shows the flags panel
+#[derive(Default)]
+pub struct GameFlagsPlugin;
+
+impl Plugin for GameFlagsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameFlags>()
+            .add_event::<FlagChanged>()
+            .add_systems(
+                Update,
+                flags_ui.in_set(GameSet::RenderPrep).run_if(level_ready),
+            )
+            .add_systems(PostUpdate, send_flag_changes);
+    }
+}
+
+/// Saved plain, `true` rather than `Bool(true)`, see `migrations`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum FlagValue {
+    Bool(bool),
+    Int(i32),
+    String(String),
+}
+
+impl FlagValue {
+    /// Whether the value counts as set for `requires_flag` conditions
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            FlagValue::Bool(value) => *value,
+            FlagValue::Int(value) => *value != 0,
+            FlagValue::String(value) => !value.is_empty(),
+        }
+    }
+}
+
+/// Sent when a flag is set to a different value, `old` is `None` for new flags
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct FlagChanged {
+    pub key: String,
+    pub old: Option<FlagValue>,
+    pub new: FlagValue,
+}
+
+/// Named flags shared by triggers and level logic
+#[derive(Resource, Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameFlags {
+    values: HashMap<String, FlagValue>,
+    /// Flags kept when the player respawns, see `clear_transient`
+    #[serde(default)]
+    persistent: HashSet<String>,
+    /// Changes not yet sent as `FlagChanged` events
+    #[serde(skip)]
+    changes: Vec<FlagChanged>,
+}
+
+impl GameFlags {
+    pub fn get(&self, key: &str) -> Option<&FlagValue> {
+        self.values.get(key)
+    }
+
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.get(key)? {
+            FlagValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn get_int(&self, key: &str) -> Option<i32> {
+        match self.get(key)? {
+            FlagValue::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn get_string(&self, key: &str) -> Option<&str> {
+        match self.get(key)? {
+            FlagValue::String(value) => Some(value.as_str()),
+            _ => None,
+        }
+    }
+
+    /// Whether the flag exists and is truthy
+    pub fn is_set(&self, key: &str) -> bool {
+        self.get(key).is_some_and(FlagValue::is_truthy)
+    }
+
+    pub fn set(&mut self, key: impl Into<String>, value: FlagValue) {
+        let key = key.into();
+        let old = self.values.insert(key.clone(), value.clone());
+        if old.as_ref() != Some(&value) {
+            self.changes.push(FlagChanged {
+                key,
+                old,
+                new: value,
+            });
+        }
+    }
+
+    pub fn set_bool(&mut self, key: impl Into<String>, value: bool) {
+        self.set(key, FlagValue::Bool(value));
+    }
+
+    pub fn set_int(&mut self, key: impl Into<String>, value: i32) {
+        self.set(key, FlagValue::Int(value));
+    }
+
+    pub fn set_string(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.set(key, FlagValue::String(value.into()));
+    }
+
+    /// Keep the flag when the player respawns
+    pub fn mark_persistent(&mut self, key: impl Into<String>) {
+        self.persistent.insert(key.into());
+    }
+
+    pub fn is_persistent(&self, key: &str) -> bool {
+        self.persistent.contains(key)
+    }
+
+    /// Drop every flag not marked persistent, without sending `FlagChanged` for them
+    pub fn clear_transient(&mut self) {
+        let persistent = &self.persistent;
+        self.values.retain(|key, _| persistent.contains(key));
+        self.changes
+            .retain(|change| persistent.contains(&change.key));
+    }
+
+    /// Evaluate a flag requirement: `name` needs the flag to be set, `!name` needs it unset
+    pub fn requirement_met(&self, requirement: &str) -> bool {
+        let requirement = requirement.trim();
+        match requirement.strip_prefix('!') {
+            Some(key) => !self.is_set(key.trim()),
+            None => self.is_set(requirement),
+        }
+    }
+
+    /// Whether a Tiled object's `requires_flag` and `forbids_flag` properties (if any) are met
+    pub fn object_condition_met(&self, properties: &tiled::Properties) -> bool {
+        let required = match properties.get("requires_flag") {
+            Some(tiled::PropertyValue::StringValue(requirement)) if !requirement.is_empty() => {
+                self.requirement_met(requirement)
+            }
+            _ => true,
+        };
+        let forbidden = match properties.get("forbids_flag") {
+            Some(tiled::PropertyValue::StringValue(key)) if !key.is_empty() => {
+                self.is_set(key.trim())
+            }
+            _ => false,
+        };
+        required && !forbidden
+    }
+}
+
+fn send_flag_changes(mut flags: ResMut<GameFlags>, mut changed: EventWriter<FlagChanged>) {
+    // avoid triggering change detection every frame
+    if flags.changes.is_empty() {
+        return;
+    }
+    changed.send_batch(flags.changes.drain(..));
+}
+
+fn flags_ui(
+    mut flags: ResMut<GameFlags>,
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+) {
+    let Some(mut ctx) = contexts.iter_mut().next() else {
+        return;
+    };
+
+    let mut keys: Vec<String> = flags.values.keys().cloned().collect();
+    keys.sort();
+
+    egui::Window::new("Game Flags")
+        .default_open(false)
+        .show(ctx.get_mut(), |ui| {
+            if keys.is_empty() {
+                ui.label("No flags set");
+            }
+            egui::Grid::new("game_flags").show(ui, |ui| {
+                for key in &keys {
+                    ui.label(key);
+                    if let Some(mut value) = flags.get_bool(key) {
+                        if ui.checkbox(&mut value, "").changed() {
+                            flags.set_bool(key, value);
+                        }
+                    } else if let Some(mut value) = flags.get_int(key) {
+                        if ui.add(egui::DragValue::new(&mut value)).changed() {
+                            flags.set_int(key, value);
+                        }
+                    } else if let Some(value) = flags.get_string(key) {
+                        let mut value = value.to_string();
+                        if ui.text_edit_singleline(&mut value).changed() {
+                            flags.set_string(key, value);
+                        }
+                    }
+                    if flags.is_persistent(key) {
+                        ui.label("persistent")
+                            .on_hover_text("kept when the player respawns");
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties(requires_flag: &str) -> tiled::Properties {
+        let mut properties = tiled::Properties::new();
+        properties.insert(
+            "requires_flag".to_string(),
+            tiled::PropertyValue::StringValue(requires_flag.to_string()),
+        );
+        properties
+    }
+
+    #[test]
+    fn spawn_without_requirement_always_happens() {
+        let flags = GameFlags::default();
+        assert!(flags.object_condition_met(&tiled::Properties::new()));
+        assert!(flags.object_condition_met(&properties("")));
+    }
+
+    #[test]
+    fn spawn_requires_truthy_flag() {
+        let mut flags = GameFlags::default();
+        assert!(!flags.object_condition_met(&properties("door_open")));
+
+        flags.set_bool("door_open", false);
+        assert!(!flags.object_condition_met(&properties("door_open")));
+
+        flags.set_bool("door_open", true);
+        assert!(flags.object_condition_met(&properties("door_open")));
+
+        flags.set_int("coins", 0);
+        assert!(!flags.object_condition_met(&properties("coins")));
+        flags.set_int("coins", 3);
+        assert!(flags.object_condition_met(&properties("coins")));
+
+        flags.set_string("name", "");
+        assert!(!flags.object_condition_met(&properties("name")));
+        flags.set_string("name", "bob");
+        assert!(flags.object_condition_met(&properties("name")));
+    }
+
+    #[test]
+    fn negated_requirement_skips_spawn_when_flag_set() {
+        let mut flags = GameFlags::default();
+        assert!(flags.object_condition_met(&properties("!npc_left")));
+
+        flags.set_bool("npc_left", true);
+        assert!(!flags.object_condition_met(&properties("!npc_left")));
+        assert!(!flags.object_condition_met(&properties(" ! npc_left ")));
+    }
+
+    #[test]
+    fn only_actual_changes_are_recorded() {
+        let mut flags = GameFlags::default();
+        flags.set_bool("a", true);
+        flags.set_bool("a", true);
+        flags.set_int("a", 2);
+        assert_eq!(
+            flags.changes,
+            vec![
+                FlagChanged {
+                    key: "a".to_string(),
+                    old: None,
+                    new: FlagValue::Bool(true),
+                },
+                FlagChanged {
+                    key: "a".to_string(),
+                    old: Some(FlagValue::Bool(true)),
+                    new: FlagValue::Int(2),
+                },
+            ]
+        );
+        assert_eq!(flags.get_int("a"), Some(2));
+        assert_eq!(flags.get_bool("a"), None);
+    }
+
+    #[test]
+    fn clearing_keeps_persistent_flags() {
+        let mut flags = GameFlags::default();
+        flags.mark_persistent("boss_dead");
+        flags.set_bool("boss_dead", true);
+        flags.set_int("coins", 3);
+        flags.clear_transient();
+
+        assert!(flags.is_set("boss_dead"));
+        assert_eq!(flags.get("coins"), None);
+        assert!(flags.changes.iter().all(|change| change.key == "boss_dead"));
+    }
+}
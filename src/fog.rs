@@ -0,0 +1,358 @@
+//! Fog of war: tiles never seen are black, tiles seen before but out of sight are dimmed.
+//!
+//! The fog is a single sprite covering the map whose image has one pixel per tile, only the
+//! pixels of tiles whose state changed are rewritten when the player moves. `FogChanged` lists
+//! those tiles, for the minimap to follow.
+//!
+//! The explored tiles of every map visited are kept in `DiscoveredAreas`, by map asset path, and
+//! go in the saves. When the level spawns the map's explored tiles are restored and painted in
+//! the fog image before it's first drawn.
+
+use std::collections::{BTreeMap, HashSet};
+
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy_ecs_tilemap::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::collision::CollisionMap;
+use crate::coords::{self, MapFrame};
+use crate::fov;
+use crate::game::{GameSet, LevelLifecycleSet};
+use crate::map::GameInfoAlt;
+use crate::player::MainPlayer;
+use crate::state::{level_ready, AppState};
+
+/// How far the player sees, in tiles
+pub const SIGHT_RADIUS: u32 = 12;
+
+/// Above the map and the creatures
+const FOG_Z: f32 = 5.;
+const UNEXPLORED_ALPHA: u8 = 255;
+const EXPLORED_ALPHA: u8 = 140;
+
+/// Plugin that tracks which tiles the player has seen and draws the fog over the others
+#[derive(Default)]
+pub struct FogPlugin;
+
+impl Plugin for FogPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ExplorationMap>()
+            .init_resource::<DiscoveredAreas>()
+            .add_event::<FogChanged>()
+            .add_systems(
+                OnEnter(AppState::Level),
+                spawn_fog.in_set(LevelLifecycleSet),
+            )
+            .add_systems(
+                OnExit(AppState::Level),
+                despawn_fog.in_set(LevelLifecycleSet),
+            )
+            .add_systems(
+                Update,
+                (update_exploration, update_fog_image)
+                    .chain()
+                    .in_set(GameSet::RenderPrep)
+                    .run_if(level_ready),
+            );
+    }
+}
+
+/// Sent with the tiles whose fog changed: explored, or entering or leaving sight
+#[derive(Event, Debug, Clone)]
+pub struct FogChanged {
+    pub tiles: Vec<TilePos>,
+}
+
+/// How a tile shows through the fog
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FogState {
+    Unexplored,
+    /// Seen before, out of sight now
+    Explored,
+    Visible,
+}
+
+/// The explored tiles of a map as saved, one bit per tile in `TilePos::to_index` order
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExploredTiles {
+    /// Width and height of the map
+    pub size: (u32, u32),
+    /// 32 tiles a word, `ron::Value` reads the saves and has no integers past `i64`
+    pub bits: Vec<u32>,
+}
+
+/// The explored tiles of the maps visited, by map asset path
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct DiscoveredAreas(pub BTreeMap<String, ExploredTiles>);
+
+impl DiscoveredAreas {
+    /// These areas with the explored tiles of `map` replaced by the ones of `exploration`
+    pub fn with_current(&self, map: &str, exploration: &ExplorationMap) -> Self {
+        let mut areas = self.clone();
+        areas
+            .0
+            .insert(map.to_string(), exploration.explored_tiles());
+        areas
+    }
+}
+
+/// Which tiles have been seen, and which are in sight right now
+#[derive(Resource, Default, Debug)]
+pub struct ExplorationMap {
+    size: TilemapSize,
+    explored: Vec<bool>,
+    visible: HashSet<TilePos>,
+    /// Tiles whose fog needs redrawing
+    dirty: Vec<TilePos>,
+}
+
+impl ExplorationMap {
+    pub fn new(size: TilemapSize) -> Self {
+        Self {
+            size,
+            explored: vec![false; size.count()],
+            ..default()
+        }
+    }
+
+    pub fn is_explored(&self, pos: &TilePos) -> bool {
+        pos.within_map_bounds(&self.size) && self.explored[pos.to_index(&self.size)]
+    }
+
+    pub fn is_visible(&self, pos: &TilePos) -> bool {
+        self.visible.contains(pos)
+    }
+
+    pub fn size(&self) -> TilemapSize {
+        self.size
+    }
+
+    pub fn state(&self, pos: &TilePos) -> FogState {
+        if self.is_visible(pos) {
+            FogState::Visible
+        } else if self.is_explored(pos) {
+            FogState::Explored
+        } else {
+            FogState::Unexplored
+        }
+    }
+
+    /// A map of `size` with the tiles of `saved` explored, none when it was saved for another size
+    pub fn restored(size: TilemapSize, saved: Option<&ExploredTiles>) -> Self {
+        let mut map = Self::new(size);
+        let Some(saved) = saved.filter(|saved| saved.size == (size.x, size.y)) else {
+            return map;
+        };
+        for (index, explored) in map.explored.iter_mut().enumerate() {
+            *explored = saved
+                .bits
+                .get(index / 32)
+                .is_some_and(|word| word & (1 << (index % 32)) != 0);
+        }
+        map
+    }
+
+    pub fn explored_tiles(&self) -> ExploredTiles {
+        let mut bits = vec![0; self.explored.len().div_ceil(32)];
+        for (index, _) in self.explored.iter().enumerate().filter(|(_, seen)| **seen) {
+            bits[index / 32] |= 1 << (index % 32);
+        }
+        ExploredTiles {
+            size: (self.size.x, self.size.y),
+            bits,
+        }
+    }
+
+    /// Replace the tiles in sight, returns the tiles explored for the first time
+    pub fn update_visible(&mut self, visible: &mut HashSet<TilePos>) -> Vec<TilePos> {
+        visible.retain(|pos| pos.within_map_bounds(&self.size));
+        // tiles leaving or entering sight both change their fog
+        self.dirty
+            .extend(self.visible.symmetric_difference(visible).copied());
+        std::mem::swap(&mut self.visible, visible);
+
+        let mut newly_explored = Vec::new();
+        for pos in &self.visible {
+            let index = pos.to_index(&self.size);
+            if !self.explored[index] {
+                self.explored[index] = true;
+                newly_explored.push(*pos);
+            }
+        }
+        newly_explored
+    }
+}
+
+/// Marks the fog sprite
+#[derive(Component)]
+pub struct FogOverlay;
+
+/// Image row major index of the first byte of the pixel of `pos`, in an image of one RGBA
+/// pixel per tile
+pub fn pixel_index(size: TilemapSize, pos: &TilePos) -> usize {
+    // image rows go down, tile rows go up
+    let row = (size.y - 1 - pos.y) as usize;
+    (row * size.x as usize + pos.x as usize) * 4
+}
+
+/// Every tile of a map of `size`
+pub fn all_tiles(size: TilemapSize) -> impl Iterator<Item = TilePos> {
+    (0..size.y).flat_map(move |y| (0..size.x).map(move |x| TilePos::new(x, y)))
+}
+
+fn fog_alpha(state: FogState) -> u8 {
+    match state {
+        FogState::Visible => 0,
+        FogState::Explored => EXPLORED_ALPHA,
+        FogState::Unexplored => UNEXPLORED_ALPHA,
+    }
+}
+
+/// Keep the explored tiles of the map being left
+fn despawn_fog(
+    mut commands: Commands,
+    overlays: Query<Entity, With<FogOverlay>>,
+    game_info: Res<GameInfoAlt>,
+    exploration: Res<ExplorationMap>,
+    mut discovered: ResMut<DiscoveredAreas>,
+) {
+    for entity in &overlays {
+        commands.entity(entity).despawn_recursive();
+    }
+    if let Some(map) = game_info.map_path() {
+        discovered.0.insert(map, exploration.explored_tiles());
+    }
+}
+
+/// Restore the map's explored tiles and spawn the fog with them already painted
+pub fn spawn_fog(
+    mut commands: Commands,
+    map_frame: Res<MapFrame>,
+    game_info: Res<GameInfoAlt>,
+    discovered: Res<DiscoveredAreas>,
+    mut exploration: ResMut<ExplorationMap>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let size = map_frame.map_size;
+    let saved = game_info.map_path().and_then(|map| discovered.0.get(&map));
+    *exploration = ExplorationMap::restored(size, saved);
+
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, UNEXPLORED_ALPHA],
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    for pos in all_tiles(size) {
+        image.data[pixel_index(size, &pos) + 3] = fog_alpha(exploration.state(&pos));
+    }
+
+    let last_tile = TilePos::new(size.x - 1, size.y - 1);
+    let (Some(low), Some(high)) = (
+        coords::tile_to_world_center(&map_frame, &TilePos::new(0, 0)),
+        coords::tile_to_world_center(&map_frame, &last_tile),
+    ) else {
+        return;
+    };
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                custom_size: Some(Vec2::new(
+                    size.x as f32 * map_frame.grid_size.x,
+                    size.y as f32 * map_frame.grid_size.y,
+                )),
+                ..default()
+            },
+            texture: images.add(image),
+            transform: Transform::from_translation(((low + high) / 2.).extend(FOG_Z)),
+            ..default()
+        },
+        FogOverlay,
+        Name::new("fog of war"),
+    ));
+}
+
+pub fn update_exploration(
+    player_q: Query<&Transform, With<MainPlayer>>,
+    map_frame: Res<MapFrame>,
+    collision_map: Res<CollisionMap>,
+    mut exploration: ResMut<ExplorationMap>,
+    mut fog_changed: EventWriter<FogChanged>,
+    mut last_tile: Local<Option<TilePos>>,
+    mut visible: Local<HashSet<TilePos>>,
+) {
+    let Some(player_tile) = player_q
+        .get_single()
+        .ok()
+        .and_then(|transform| coords::world_to_tile(&map_frame, transform.translation.truncate()))
+    else {
+        return;
+    };
+    // doors opening change what can be seen too
+    if *last_tile == Some(player_tile) && !collision_map.is_changed() {
+        return;
+    }
+    *last_tile = Some(player_tile);
+
+    fov::compute_fov_into(
+        player_tile,
+        SIGHT_RADIUS,
+        |pos| collision_map.is_blocked(&pos),
+        &mut visible,
+    );
+    exploration.update_visible(&mut visible);
+    let changed = std::mem::take(&mut exploration.dirty);
+    if !changed.is_empty() {
+        fog_changed.send(FogChanged { tiles: changed });
+    }
+}
+
+pub fn update_fog_image(
+    mut fog_changed: EventReader<FogChanged>,
+    exploration: Res<ExplorationMap>,
+    fog_q: Query<&Handle<Image>, With<FogOverlay>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let Some(image) = fog_q
+        .get_single()
+        .ok()
+        .and_then(|handle| images.get_mut(handle))
+    else {
+        fog_changed.clear();
+        return;
+    };
+    let size = exploration.size;
+    for pos in fog_changed.read().flat_map(|changed| &changed.tiles) {
+        image.data[pixel_index(size, pos) + 3] = fog_alpha(exploration.state(pos));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exploration_keeps_seen_tiles() {
+        let mut map = ExplorationMap::new(TilemapSize { x: 4, y: 1 });
+        let a = TilePos::new(0, 0);
+        let b = TilePos::new(1, 0);
+        let c = TilePos::new(2, 0);
+
+        let mut visible = HashSet::from([a, b, TilePos::new(9, 0)]);
+        let mut newly = map.update_visible(&mut visible);
+        newly.sort_by_key(|pos| pos.x);
+        assert_eq!(newly, vec![a, b]);
+
+        let mut visible = HashSet::from([b, c]);
+        assert_eq!(map.update_visible(&mut visible), vec![c]);
+        assert!(map.is_explored(&a) && !map.is_visible(&a));
+        assert!(map.is_visible(&b) && map.is_visible(&c));
+        assert!(!map.is_explored(&TilePos::new(3, 0)));
+    }
+}
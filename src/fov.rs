@@ -0,0 +1,326 @@
+//! Field of view using recursive shadowcasting.
+
+use std::collections::HashSet;
+
+use bevy_ecs_tilemap::prelude::*;
+
+/// Row/column multipliers mapping octant 0 onto each of the eight octants
+const OCTANTS: [[i64; 4]; 8] = [
+    [1, 0, 0, -1],
+    [0, 1, -1, 0],
+    [0, -1, -1, 0],
+    [-1, 0, 0, -1],
+    [-1, 0, 0, 1],
+    [0, -1, 1, 0],
+    [0, 1, 1, 0],
+    [1, 0, 0, 1],
+];
+
+/// Tiles visible from `origin` within `radius`, `blocks` tells whether a tile blocks sight
+///
+/// Blocking tiles are themselves visible when lit, so walls around a room show up. The origin is
+/// always visible.
+pub fn compute_fov(
+    origin: TilePos,
+    radius: u32,
+    blocks: impl Fn(TilePos) -> bool,
+) -> HashSet<TilePos> {
+    let mut visible = HashSet::new();
+    compute_fov_into(origin, radius, blocks, &mut visible);
+    visible
+}
+
+/// Like `compute_fov`, but fills `visible` (cleared first) so its allocation can be reused
+pub fn compute_fov_into(
+    origin: TilePos,
+    radius: u32,
+    blocks: impl Fn(TilePos) -> bool,
+    visible: &mut HashSet<TilePos>,
+) {
+    visible.clear();
+    visible.insert(origin);
+    let caster = Caster {
+        origin: (origin.x as i64, origin.y as i64),
+        radius: radius as i64,
+        blocks: &blocks,
+    };
+    for octant in &OCTANTS {
+        caster.cast_light(1, 1., 0., octant, visible);
+    }
+}
+
+struct Caster<'a, F: Fn(TilePos) -> bool> {
+    origin: (i64, i64),
+    radius: i64,
+    blocks: &'a F,
+}
+
+impl<F: Fn(TilePos) -> bool> Caster<'_, F> {
+    /// Light the rows of one octant starting at `row`, between the slopes `start` and `end`
+    fn cast_light(
+        &self,
+        row: i64,
+        mut start: f32,
+        end: f32,
+        octant: &[i64; 4],
+        visible: &mut HashSet<TilePos>,
+    ) {
+        if start < end {
+            return;
+        }
+        let [xx, xy, yx, yy] = *octant;
+        // slightly more than radius² gives rounder circles
+        let radius_sq = self.radius * self.radius + self.radius;
+
+        let mut next_start = start;
+        for distance in row..=self.radius {
+            let dy = -distance;
+            let mut blocked = false;
+            for dx in -distance..=0 {
+                let left_slope = (dx as f32 - 0.5) / (dy as f32 + 0.5);
+                let right_slope = (dx as f32 + 0.5) / (dy as f32 - 0.5);
+                if start < right_slope {
+                    continue;
+                }
+                if end > left_slope {
+                    break;
+                }
+
+                let x = self.origin.0 + dx * xx + dy * xy;
+                let y = self.origin.1 + dx * yx + dy * yy;
+                // tiles left of or below the map can't be represented and block sight
+                let tile = (x >= 0 && y >= 0).then(|| TilePos::new(x as u32, y as u32));
+                if let Some(tile) = tile {
+                    if dx * dx + dy * dy <= radius_sq {
+                        visible.insert(tile);
+                    }
+                }
+                let wall = tile.is_none_or(|tile| (self.blocks)(tile));
+
+                if blocked {
+                    if wall {
+                        next_start = right_slope;
+                    } else {
+                        blocked = false;
+                        start = next_start;
+                    }
+                } else if wall && distance < self.radius {
+                    blocked = true;
+                    self.cast_light(distance + 1, start, left_slope, octant, visible);
+                    next_start = right_slope;
+                }
+            }
+            if blocked {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A hand-drawn grid, rows top to bottom: `#` blocks sight and `@` is the origin
+    struct Grid {
+        walls: HashSet<TilePos>,
+        origin: TilePos,
+        size: TilemapSize,
+    }
+
+    impl Grid {
+        fn parse(rows: &[&str]) -> Self {
+            let size = TilemapSize {
+                x: rows[0].len() as u32,
+                y: rows.len() as u32,
+            };
+            let mut walls = HashSet::new();
+            let mut origin = TilePos::new(0, 0);
+            for (row, line) in rows.iter().enumerate() {
+                let y = size.y - 1 - row as u32;
+                for (x, c) in line.chars().enumerate() {
+                    let pos = TilePos::new(x as u32, y);
+                    match c {
+                        '#' => {
+                            walls.insert(pos);
+                        }
+                        '@' => origin = pos,
+                        _ => {}
+                    }
+                }
+            }
+            Self {
+                walls,
+                origin,
+                size,
+            }
+        }
+
+        fn fov(&self, radius: u32) -> HashSet<TilePos> {
+            compute_fov(self.origin, radius, |pos| {
+                !pos.within_map_bounds(&self.size) || self.walls.contains(&pos)
+            })
+        }
+    }
+
+    fn tile(x: u32, y: u32) -> TilePos {
+        TilePos::new(x, y)
+    }
+
+    #[test]
+    fn open_ground_is_a_disc() {
+        let grid = Grid::parse(&[
+            "...........", //
+            "...........", //
+            "...........", //
+            "...........", //
+            "...........", //
+            ".....@.....", //
+            "...........", //
+            "...........", //
+            "...........", //
+            "...........", //
+            "...........",
+        ]);
+        let visible = grid.fov(3);
+        assert!(visible.contains(&tile(5, 5)));
+        assert!(visible.contains(&tile(8, 5)));
+        assert!(visible.contains(&tile(5, 2)));
+        assert!(visible.contains(&tile(7, 7)));
+        assert!(!visible.contains(&tile(9, 5)));
+        assert!(!visible.contains(&tile(8, 8)));
+        // symmetric in all four directions
+        for (dx, dy) in [(2, 1), (1, 2), (3, 0), (2, 2)] {
+            let quadrants = [
+                (5 + dx, 5 + dy),
+                (5 - dx, 5 + dy),
+                (5 + dx, 5 - dy),
+                (5 - dx, 5 - dy),
+            ];
+            let seen = quadrants
+                .iter()
+                .filter(|(x, y)| visible.contains(&tile(*x, *y)))
+                .count();
+            assert!(
+                seen == 0 || seen == 4,
+                "({dx}, {dy}) seen in {seen} quadrants"
+            );
+        }
+    }
+
+    #[test]
+    fn zero_radius_sees_only_origin() {
+        let grid = Grid::parse(&["...", ".@.", "..."]);
+        assert_eq!(grid.fov(0), HashSet::from([tile(1, 1)]));
+    }
+
+    #[test]
+    fn pillar_casts_a_shadow() {
+        let grid = Grid::parse(&[
+            ".......", //
+            ".......", //
+            "@.#....", //
+            ".......", //
+            ".......",
+        ]);
+        let visible = grid.fov(10);
+        assert!(visible.contains(&tile(1, 2)));
+        assert!(visible.contains(&tile(2, 2)), "the pillar itself is lit");
+        for x in 3..7 {
+            assert!(
+                !visible.contains(&tile(x, 2)),
+                "({x}, 2) is behind the pillar"
+            );
+        }
+        assert!(visible.contains(&tile(4, 0)));
+        assert!(visible.contains(&tile(4, 4)));
+    }
+
+    #[test]
+    fn corridor_walls_hide_neighbouring_rooms() {
+        let grid = Grid::parse(&[
+            ".......", //
+            "#######", //
+            "@.....#", //
+            "#######", //
+            ".......",
+        ]);
+        let visible = grid.fov(12);
+        for x in 0..7 {
+            assert!(
+                visible.contains(&tile(x, 2)),
+                "({x}, 2) is along the corridor"
+            );
+        }
+        assert!(visible.contains(&tile(3, 1)));
+        assert!(visible.contains(&tile(3, 3)));
+        for x in 0..7 {
+            assert!(!visible.contains(&tile(x, 0)));
+            assert!(!visible.contains(&tile(x, 4)));
+        }
+    }
+
+    #[test]
+    fn room_corner_sees_the_room_but_not_outside() {
+        let grid = Grid::parse(&[
+            ".......", //
+            ".#####.", //
+            ".#...#.", //
+            ".#...#.", //
+            ".#@..#.", //
+            ".#####.", //
+            ".......",
+        ]);
+        let visible = grid.fov(12);
+        for x in 2..5 {
+            for y in 2..5 {
+                assert!(visible.contains(&tile(x, y)), "({x}, {y}) is in the room");
+            }
+        }
+        assert!(visible.contains(&tile(1, 1)));
+        assert!(visible.contains(&tile(5, 5)));
+        for i in 0..7 {
+            for pos in [tile(i, 0), tile(i, 6), tile(0, i), tile(6, i)] {
+                assert!(!visible.contains(&pos), "{pos:?} is outside the room");
+            }
+        }
+    }
+
+    #[test]
+    fn origin_at_map_edge() {
+        let grid = Grid::parse(&["@..", "...", "..."]);
+        let visible = grid.fov(5);
+        // past the far edges the blocking tiles are lit like walls, past x or y 0 there are none
+        let on_map = visible
+            .iter()
+            .filter(|pos| pos.within_map_bounds(&grid.size))
+            .count();
+        assert_eq!(on_map, 9);
+        assert!(visible.contains(&tile(3, 0)));
+    }
+
+    /// Run with `cargo test --release fov_benchmark -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn fov_benchmark() {
+        let size = TilemapSize { x: 64, y: 64 };
+        // scattered pillars
+        let blocks = |pos: TilePos| {
+            !pos.within_map_bounds(&size) || (pos.x.is_multiple_of(5) && pos.y.is_multiple_of(3))
+        };
+
+        let iterations = 10_000;
+        let mut visible = HashSet::new();
+        let started = std::time::Instant::now();
+        for i in 0..iterations {
+            let origin = TilePos::new(20 + i % 20, 20 + (i / 20) % 20);
+            compute_fov_into(origin, 12, blocks, &mut visible);
+        }
+        let elapsed = started.elapsed();
+        println!(
+            "compute_fov radius 12: {:?} per call ({} tiles visible)",
+            elapsed / iterations,
+            visible.len()
+        );
+    }
+}
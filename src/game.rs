@@ -0,0 +1,209 @@
+//! The app states, the frame's system sets and the plugin group of the whole game.
+
+use bevy::app::PluginGroupBuilder;
+use bevy::prelude::*;
+use bevy_asset_loader::prelude::*;
+
+use crate::camera::PanCamSystemSet;
+use crate::state::AppState;
+
+/// Every plugin of the game, to add next to `DefaultPlugins`
+pub struct GamePlugins;
+
+impl PluginGroup for GamePlugins {
+    fn build(self) -> PluginGroupBuilder {
+        let group = PluginGroupBuilder::start::<Self>()
+            .add(GamePlugin)
+            .add(crate::cursor::CursorCachePlugin)
+            .add(crate::camera::PanCamPlugin)
+            .add(crate::camera_rig::CameraRigPlugin)
+            .add(crate::map::MapPlugin)
+            .add(crate::sprite_atlas::SpriteAtlasPlugin)
+            .add(crate::sprite_scale::SpriteScalePlugin)
+            .add(crate::map_switch::MapSwitchPlugin)
+            .add(crate::asset_reload::AssetReloadPlugin)
+            .add(crate::map_validation::MapValidationPlugin)
+            .add(crate::tile_writer::TileWriterPlugin)
+            .add(crate::editor::EditorPlugin)
+            .add(crate::region_editor::RegionEditorPlugin)
+            .add(crate::templates::TemplatePlugin)
+            .add(crate::player::PlayerPlugin)
+            .add(crate::movement::MovementPlugin)
+            .add(crate::animation::AnimationPlugin)
+            .add(crate::tween::TweenPlugin)
+            .add(crate::lifetime::LifetimePlugin)
+            .add(crate::audio::AudioChannelsPlugin)
+            .add(crate::spawn_effects::SpawnEffectPlugin)
+            .add(crate::ui::UiPlugin)
+            .add(crate::console::ConsolePlugin)
+            .add(crate::cheat_sheet::CheatSheetPlugin)
+            .add(crate::health::HealthPlugin)
+            .add(crate::health_bar::HealthBarPlugin)
+            .add(crate::world_label::WorldLabelPlugin)
+            .add(crate::combat::CombatPlugin)
+            .add(crate::debug_step::DebugStepPlugin)
+            .add(crate::log_capture::LogCapturePlugin)
+            .add(crate::event_log::EventLogPlugin)
+            .add(crate::perf::PerfPlugin)
+            // loads the `Preferences` the plugins below take their settings from
+            .add(crate::accessibility::AccessibilityPlugin)
+            .add(crate::auto_hide::AutoHidePlugin)
+            .add(crate::background::BackgroundThrottlePlugin)
+            .add(crate::debug_info::DebugInfoPlugin)
+            .add(crate::save_slots::SaveSlotsPlugin)
+            .add(crate::statistics::StatisticsPlugin)
+            .add(crate::localization::LocalizationPlugin)
+            .add(crate::ui_scale::UiScalePlugin)
+            .add(crate::input_map::InputMapPlugin)
+            .add(crate::camera_preset::CameraPresetPlugin)
+            .add(crate::menu_focus::MenuFocusPlugin)
+            .add(crate::palette::PalettePlugin)
+            .add(crate::triggers::TriggerPlugin)
+            .add(crate::flags::GameFlagsPlugin)
+            .add(crate::spawn_conditions::SpawnConditionsPlugin)
+            .add(crate::interact::InteractPlugin)
+            .add(crate::dialogue::DialoguePlugin)
+            .add(crate::inventory::InventoryPlugin)
+            .add(crate::doors::DoorPlugin)
+            .add(crate::portals::PortalPlugin)
+            .add(crate::targeting::TargetingPlugin)
+            .add(crate::selection::SelectionCyclePlugin)
+            .add(crate::fog::FogPlugin)
+            .add(crate::minimap::MinimapPlugin)
+            .add(crate::terrain::TerrainPlugin)
+            .add(crate::lod::LodPlugin)
+            .add(crate::regions::RegionLabelPlugin)
+            .add(crate::ambience::AmbiencePlugin)
+            .add(crate::ambient_life::AmbientLifePlugin)
+            .add(crate::weather::WeatherPlugin)
+            .add(crate::lights::LightPlugin)
+            .add(crate::dormancy::DormancyPlugin)
+            .add(crate::replication::ReplicationPlugin)
+            .add(crate::groups::GroupPlugin)
+            .add(crate::hostile::HostilePlugin)
+            .add(crate::game_over::GameOverPlugin);
+        #[cfg(feature = "physics")]
+        let group = group.add(crate::physics::PhysicsPlugin);
+        group
+    }
+}
+
+/// The stages of a frame in `Update`, run in this order
+///
+/// Every `Update` system of the game belongs to one of them. The cursor cache itself is filled
+/// in `PreUpdate`, so everything from `Input` on sees the same cursor during the frame.
+#[derive(Debug, Clone, Copy, SystemSet, PartialEq, Eq, Hash)]
+pub enum GameSet {
+    /// Turn the keyboard and the typed console lines into requests, like `MoveRequested`
+    Input,
+    /// Turn the cached cursor into the hovered tile
+    Cursor,
+    /// Move the creatures
+    Movement,
+    /// React to what moved: damage, doors, triggers, dialogue and the like
+    Gameplay,
+    /// Pan, zoom and follow with the camera
+    Camera,
+    /// Update what is drawn and heard: animations, overlays, egui windows and audio
+    RenderPrep,
+}
+
+/// The systems that spawn the level on `OnEnter(AppState::Level)` and tear it down on `OnExit`
+///
+/// They are skipped while `KeepLevel` is set, so a trip through the game over screen leaves the
+/// level as it was.
+#[derive(Debug, Clone, Copy, SystemSet, PartialEq, Eq, Hash)]
+pub struct LevelLifecycleSet;
+
+/// Set while the level is left for `AppState::GameOver` and should be resumed, not rebuilt
+#[derive(Resource, Default, Debug, PartialEq, Eq)]
+pub struct KeepLevel(pub bool);
+
+/// Plugin that sets up the app states, the asset loading and the order of the `GameSet`s
+#[derive(Default)]
+pub struct GamePlugin;
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        configure_game_sets(app);
+        app.init_resource::<KeepLevel>()
+            .add_state::<AppState>()
+            .add_loading_state(
+                LoadingState::new(AppState::Loading)
+                    .continue_to_state(AppState::Level)
+                    .with_dynamic_assets_file::<StandardDynamicAssetCollection>(
+                        crate::asset_reload::ASSETS_FILE,
+                    )
+                    .load_collection::<crate::map::GameInfoAlt>()
+                    .load_collection::<crate::game_ui::UiAssets>(),
+            );
+    }
+}
+
+/// Order the `GameSet`s and the sets nested in them, also used by tests needing the frame order
+pub fn configure_game_sets(app: &mut App) {
+    app.configure_sets(
+        Update,
+        (
+            GameSet::Input,
+            GameSet::Cursor,
+            GameSet::Movement,
+            GameSet::Gameplay,
+            GameSet::Camera,
+            GameSet::RenderPrep,
+        )
+            .chain(),
+    )
+    .configure_sets(Update, PanCamSystemSet.in_set(GameSet::Camera))
+    .configure_sets(
+        OnEnter(AppState::Level),
+        LevelLifecycleSet.run_if(resource_equals(KeepLevel(false))),
+    )
+    .configure_sets(
+        OnExit(AppState::Level),
+        LevelLifecycleSet.run_if(resource_equals(KeepLevel(false))),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct Ran(Vec<&'static str>);
+
+    fn record(name: &'static str) -> impl Fn(ResMut<Ran>) {
+        move |mut ran: ResMut<Ran>| ran.0.push(name)
+    }
+
+    #[test]
+    fn sets_run_in_order() {
+        let mut app = App::new();
+        configure_game_sets(&mut app);
+        // added back to front, so only the set order puts them right
+        app.init_resource::<Ran>().add_systems(
+            Update,
+            (
+                record("render prep").in_set(GameSet::RenderPrep),
+                record("pan cam").in_set(PanCamSystemSet),
+                record("gameplay").in_set(GameSet::Gameplay),
+                record("movement").in_set(GameSet::Movement),
+                record("cursor").in_set(GameSet::Cursor),
+                record("input").in_set(GameSet::Input),
+            ),
+        );
+        app.update();
+
+        assert_eq!(
+            app.world.resource::<Ran>().0,
+            [
+                "input",
+                "cursor",
+                "movement",
+                "gameplay",
+                "pan cam",
+                "render prep"
+            ]
+        );
+    }
+}
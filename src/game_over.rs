@@ -0,0 +1,243 @@
+//! Game over when the player dies, and respawning from the game over screen.
+//!
+//! The level stays spawned behind the screen (see `KeepLevel`): respawning puts the same player
+//! entity back on its spawn point with full health and clears what was going on when it died.
+//! Game flags are cleared too, except the ones marked persistent by the map's
+//! `persistent_flags` property.
+
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::EguiContext;
+use bevy_window::PrimaryWindow;
+
+use crate::dialogue::ActiveDialogue;
+use crate::flags::GameFlags;
+use crate::game::{GameSet, KeepLevel, LevelLifecycleSet};
+use crate::health::{Died, Health, HitFlash};
+use crate::localization::tr;
+use crate::menu_focus::MenuFocus;
+use crate::movement::{Facing, StepCooldown};
+use crate::pathfinding::MovePath;
+use crate::player::{MainPlayer, SpawnPoint};
+use crate::state::{level_ready, AppState};
+use crate::targeting::TargetingPreview;
+use crate::tween::Tween;
+use crate::ui::SelectedTile;
+
+/// Plugin that shows the game over screen when the player dies and respawns it from there
+#[derive(Default)]
+pub struct GameOverPlugin;
+
+impl Plugin for GameOverPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            enter_game_over
+                .in_set(GameSet::Gameplay)
+                .after(crate::health::apply_damage)
+                .run_if(level_ready),
+        )
+        .add_systems(
+            Update,
+            game_over_screen
+                .in_set(GameSet::RenderPrep)
+                .run_if(in_state(AppState::GameOver)),
+        )
+        .add_systems(
+            OnEnter(AppState::Level),
+            respawn_player
+                .after(LevelLifecycleSet)
+                .run_if(resource_equals(KeepLevel(true))),
+        );
+    }
+}
+
+fn enter_game_over(
+    mut died: EventReader<Died>,
+    players: Query<(), With<MainPlayer>>,
+    mut keep_level: ResMut<KeepLevel>,
+    mut state: ResMut<NextState<AppState>>,
+) {
+    if died.read().any(|Died(entity)| players.contains(*entity)) {
+        keep_level.0 = true;
+        state.set(AppState::GameOver);
+    }
+}
+
+fn game_over_screen(
+    mut state: ResMut<NextState<AppState>>,
+    mut exit: EventWriter<AppExit>,
+    mut focus: ResMut<MenuFocus>,
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+) {
+    let Some(mut ctx) = contexts.iter_mut().next() else {
+        return;
+    };
+
+    // there is nothing to back out to
+    focus.begin("game_over");
+
+    egui::Window::new(tr!("game_over.title"))
+        .id(egui::Id::new("game_over"))
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx.get_mut(), |ui| {
+            ui.vertical_centered(|ui| {
+                ui.heading(tr!("game_over.you_died"));
+                ui.add_space(8.);
+                let respawn = ui.button(tr!("game_over.respawn"));
+                if focus.item(ui, "respawn", &respawn) || respawn.clicked() {
+                    state.set(AppState::Level);
+                }
+                let quit = ui.button(tr!("game_over.quit"));
+                if focus.item(ui, "quit", &quit) || quit.clicked() {
+                    exit.send(AppExit);
+                }
+            });
+        });
+    focus.end();
+}
+
+/// Put the player back on its spawn point and reset what was going on when it died
+#[allow(clippy::type_complexity)]
+fn respawn_player(
+    mut commands: Commands,
+    mut players: Query<
+        (
+            Entity,
+            &SpawnPoint,
+            &mut Transform,
+            Option<&mut Health>,
+            Option<&mut TextureAtlasSprite>,
+        ),
+        With<MainPlayer>,
+    >,
+    mut flags: ResMut<GameFlags>,
+    mut selected: ResMut<SelectedTile>,
+    mut preview: ResMut<TargetingPreview>,
+    mut dialogue: ResMut<ActiveDialogue>,
+    mut keep_level: ResMut<KeepLevel>,
+) {
+    for (entity, spawn_point, mut transform, health, sprite) in &mut players {
+        *transform = Transform::from_translation(spawn_point.0);
+        if let Some(mut health) = health {
+            health.current = health.max;
+        }
+        if let Some(mut sprite) = sprite {
+            sprite.color = Color::WHITE;
+        }
+        commands
+            .entity(entity)
+            .remove::<(MovePath, StepCooldown, Tween, HitFlash)>()
+            .insert(Facing::default());
+    }
+
+    flags.clear_transient();
+    selected.0 = None;
+    preview.path.clear();
+    dialogue.0 = None;
+    keep_level.0 = false;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::{self, DamageEvent, DamageNumberPool};
+    use crate::spawn_effects::DespawnAfterEffect;
+    use crate::state::LevelReady;
+
+    #[derive(Resource, Default)]
+    struct LevelSpawns(u32);
+
+    fn count_level_spawn(mut spawns: ResMut<LevelSpawns>) {
+        spawns.0 += 1;
+    }
+
+    #[test]
+    fn dying_and_respawning_keeps_the_level() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugins(GameOverPlugin)
+            .add_state::<AppState>()
+            .init_resource::<KeepLevel>()
+            .init_resource::<GameFlags>()
+            .init_resource::<SelectedTile>()
+            .init_resource::<TargetingPreview>()
+            .init_resource::<ActiveDialogue>()
+            .init_resource::<DamageNumberPool>()
+            .init_resource::<crate::ui_scale::UiScaling>()
+            .init_resource::<MenuFocus>()
+            .init_resource::<LevelSpawns>()
+            .insert_resource(LevelReady(true))
+            .add_event::<DamageEvent>()
+            .add_event::<Died>()
+            .add_event::<DespawnAfterEffect>()
+            .add_event::<AppExit>()
+            .configure_sets(
+                OnEnter(AppState::Level),
+                LevelLifecycleSet.run_if(resource_equals(KeepLevel(false))),
+            )
+            .add_systems(
+                OnEnter(AppState::Level),
+                count_level_spawn.in_set(LevelLifecycleSet),
+            )
+            .add_systems(Update, health::apply_damage.run_if(level_ready));
+        app.world
+            .resource_mut::<NextState<AppState>>()
+            .set(AppState::Level);
+        app.update();
+        assert_eq!(app.world.resource::<LevelSpawns>().0, 1);
+
+        let spawn = Vec3::new(12., 12., 2.);
+        let player = app
+            .world
+            .spawn((
+                MainPlayer,
+                SpawnPoint(spawn),
+                Transform::from_xyz(60., 36., 2.),
+                Health::new(5),
+                MovePath::default(),
+            ))
+            .id();
+        {
+            let mut flags = app.world.resource_mut::<GameFlags>();
+            flags.mark_persistent("met_king");
+            flags.set_bool("met_king", true);
+            flags.set_bool("lever_pulled", true);
+        }
+
+        app.world.send_event(DamageEvent {
+            target: player,
+            amount: 9,
+            source: None,
+        });
+        app.update();
+        app.update();
+        assert_eq!(
+            *app.world.resource::<State<AppState>>().get(),
+            AppState::GameOver
+        );
+
+        app.world
+            .resource_mut::<NextState<AppState>>()
+            .set(AppState::Level);
+        app.update();
+
+        assert_eq!(
+            *app.world.resource::<State<AppState>>().get(),
+            AppState::Level
+        );
+        assert_eq!(app.world.resource::<LevelSpawns>().0, 1);
+        assert_eq!(app.world.get::<Health>(player), Some(&Health::new(5)));
+        assert_eq!(
+            app.world.get::<Transform>(player).unwrap().translation,
+            spawn
+        );
+        assert!(app.world.get::<MovePath>(player).is_none());
+        let flags = app.world.resource::<GameFlags>();
+        assert!(flags.is_set("met_king"));
+        assert!(!flags.is_set("lever_pulled"));
+        assert_eq!(*app.world.resource::<KeepLevel>(), KeepLevel(false));
+    }
+}
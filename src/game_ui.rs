@@ -0,0 +1,384 @@
+//! Sprite-based UI for the player: 9-slice panels and icon grids drawn in camera space.
+//!
+//! The egui windows stay for debugging, game-facing UI is built from these. Everything under the
+//! `UiRoot` is laid out in screen pixels, with the origin at the center of the screen and y up.
+
+use bevy::prelude::*;
+use bevy_asset_loader::prelude::*;
+
+use crate::camera::MainCamera;
+use crate::game::GameSet;
+
+/// In front of the whole world z budget, relative to the camera
+const UI_Z: f32 = -900.;
+/// Width of the border of the panel texture, in texture pixels
+const PANEL_MARGIN: f32 = 8.;
+
+/// Plugin that keeps a camera-space UI root under the main camera
+#[derive(Default)]
+pub struct GameUiPlugin;
+
+impl Plugin for GameUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, spawn_ui_root.in_set(GameSet::RenderPrep))
+            .add_systems(
+                PostUpdate,
+                scale_ui_root.before(bevy::transform::TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+/// Textures of the game UI
+#[derive(AssetCollection, Resource)]
+pub struct UiAssets {
+    /// 9-slice panel background, see `UiAssets::panel_style`
+    #[asset(key = "ui.panel")]
+    pub panel: Handle<Image>,
+    /// Item icons, indexed like `ItemIcons`
+    #[asset(key = "ui.icons")]
+    pub icons: Handle<TextureAtlas>,
+}
+
+impl UiAssets {
+    /// Style of the default panel
+    pub fn panel_style(&self) -> PanelStyle {
+        PanelStyle {
+            texture: self.panel.clone(),
+            texture_size: Vec2::splat(24.),
+            margins: SliceMargins::all(PANEL_MARGIN),
+            border_scale: 2.,
+            color: Color::WHITE,
+        }
+    }
+}
+
+/// Parent of the game UI, follows the main camera so its children stay put on screen
+#[derive(Component, Debug)]
+pub struct UiRoot;
+
+/// Width of each border of a 9-slice texture, in texture pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SliceMargins {
+    pub left: f32,
+    pub right: f32,
+    pub top: f32,
+    pub bottom: f32,
+}
+
+impl SliceMargins {
+    pub fn all(margin: f32) -> Self {
+        Self {
+            left: margin,
+            right: margin,
+            top: margin,
+            bottom: margin,
+        }
+    }
+}
+
+/// How a 9-slice panel is drawn
+#[derive(Debug, Clone)]
+pub struct PanelStyle {
+    pub texture: Handle<Image>,
+    pub texture_size: Vec2,
+    pub margins: SliceMargins,
+    /// Screen pixels per texture pixel of the corners and edges
+    pub border_scale: f32,
+    pub color: Color,
+}
+
+/// One of the nine pieces of a panel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Slice {
+    /// Where the piece is drawn, y up
+    pub dest: Rect,
+    /// The part of the texture it shows, in texture pixels with y down
+    pub source: Rect,
+}
+
+/// Split `rect` into the pieces of a 9-slice panel
+///
+/// Corners keep their size, edges stretch along the panel and the center stretches both ways.
+/// A panel smaller than its borders shrinks the borders to fit, and pieces that end up empty
+/// are left out.
+pub fn nine_slice(
+    rect: Rect,
+    texture_size: Vec2,
+    margins: SliceMargins,
+    border_scale: f32,
+) -> Vec<Slice> {
+    // borders can't take more than the whole texture
+    let fit = |a: f32, b: f32, size: f32| {
+        let (a, b) = (a.max(0.), b.max(0.));
+        if a + b > size && a + b > 0. {
+            (a * size / (a + b), b * size / (a + b))
+        } else {
+            (a, b)
+        }
+    };
+    let (left, right) = fit(margins.left, margins.right, texture_size.x);
+    let (top, bottom) = fit(margins.top, margins.bottom, texture_size.y);
+    let (dest_left, dest_right) = fit(left * border_scale, right * border_scale, rect.width());
+    let (dest_top, dest_bottom) = fit(top * border_scale, bottom * border_scale, rect.height());
+
+    let source_x = [0., left, texture_size.x - right, texture_size.x];
+    // texture rows go down, so the top row of the panel is the first one
+    let source_y = [0., top, texture_size.y - bottom, texture_size.y];
+    let dest_x = [
+        rect.min.x,
+        rect.min.x + dest_left,
+        rect.max.x - dest_right,
+        rect.max.x,
+    ];
+    let dest_y = [
+        rect.max.y,
+        rect.max.y - dest_top,
+        rect.min.y + dest_bottom,
+        rect.min.y,
+    ];
+
+    let mut slices = Vec::with_capacity(9);
+    for row in 0..3 {
+        for column in 0..3 {
+            let slice = Slice {
+                dest: Rect::new(
+                    dest_x[column],
+                    dest_y[row + 1],
+                    dest_x[column + 1],
+                    dest_y[row],
+                ),
+                source: Rect::new(
+                    source_x[column],
+                    source_y[row],
+                    source_x[column + 1],
+                    source_y[row + 1],
+                ),
+            };
+            if !slice.dest.is_empty() && !slice.source.is_empty() {
+                slices.push(slice);
+            }
+        }
+    }
+    slices
+}
+
+/// Size of a panel fitting `columns` by `rows` icons
+pub fn icon_panel_size(
+    columns: usize,
+    rows: usize,
+    padding: f32,
+    icon_size: Vec2,
+    spacing: f32,
+) -> Vec2 {
+    let grid = |count: usize, size: f32| {
+        count as f32 * size + count.saturating_sub(1) as f32 * spacing + 2. * padding
+    };
+    Vec2::new(grid(columns, icon_size.x), grid(rows, icon_size.y))
+}
+
+/// Centers of `count` icons laid out in rows inside `rect`, left to right and top to bottom
+///
+/// As many icons as fit go on a row, at least one. Icons past the bottom of `rect` keep going
+/// down, size the panel with `icon_panel_size` to avoid that.
+pub fn layout_icons(
+    rect: Rect,
+    padding: f32,
+    icon_size: Vec2,
+    spacing: f32,
+    count: usize,
+) -> Vec<Vec2> {
+    let inner_width = rect.width() - 2. * padding;
+    let columns = (((inner_width + spacing) / (icon_size.x + spacing)).floor() as usize).max(1);
+    let top_left = Vec2::new(rect.min.x + padding, rect.max.y - padding);
+    (0..count)
+        .map(|index| {
+            let (column, row) = ((index % columns) as f32, (index / columns) as f32);
+            top_left
+                + Vec2::new(
+                    column * (icon_size.x + spacing) + icon_size.x / 2.,
+                    -(row * (icon_size.y + spacing) + icon_size.y / 2.),
+                )
+        })
+        .collect()
+}
+
+/// Spawn a 9-slice panel covering `rect`, as a child of the node `commands` builds
+///
+/// Children of the returned panel entity are drawn over it when they have a z above 0.
+pub fn spawn_nine_slice_panel(
+    commands: &mut ChildBuilder,
+    rect: Rect,
+    style: &PanelStyle,
+) -> Entity {
+    commands
+        .spawn((SpatialBundle::default(), Name::new("panel")))
+        .with_children(|panel| {
+            for slice in nine_slice(rect, style.texture_size, style.margins, style.border_scale) {
+                panel.spawn(SpriteBundle {
+                    sprite: Sprite {
+                        color: style.color,
+                        custom_size: Some(slice.dest.size()),
+                        rect: Some(slice.source),
+                        ..default()
+                    },
+                    texture: style.texture.clone(),
+                    transform: Transform::from_translation(slice.dest.center().extend(0.)),
+                    ..default()
+                });
+            }
+        })
+        .id()
+}
+
+fn spawn_ui_root(mut commands: Commands, cameras: Query<Entity, Added<MainCamera>>) {
+    for camera in &cameras {
+        commands.entity(camera).with_children(|camera| {
+            camera.spawn((
+                SpatialBundle::from_transform(Transform::from_xyz(0., 0., UI_Z)),
+                UiRoot,
+                Name::new("ui root"),
+            ));
+        });
+    }
+}
+
+/// Undo the camera zoom, so one unit under the root is one screen pixel
+fn scale_ui_root(
+    cameras: Query<&OrthographicProjection, With<MainCamera>>,
+    mut roots: Query<(&Parent, &mut Transform), With<UiRoot>>,
+) {
+    for (parent, mut transform) in &mut roots {
+        let Ok(projection) = cameras.get(parent.get()) else {
+            continue;
+        };
+        let scale = Vec3::splat(projection.scale);
+        if transform.scale != scale {
+            transform.scale = scale;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dests(slices: &[Slice]) -> Vec<Rect> {
+        slices.iter().map(|slice| slice.dest).collect()
+    }
+
+    #[test]
+    fn corners_keep_their_size_and_the_rest_stretches() {
+        let slices = nine_slice(
+            Rect::new(0., 0., 100., 60.),
+            Vec2::splat(24.),
+            SliceMargins::all(8.),
+            1.,
+        );
+        assert_eq!(slices.len(), 9);
+        assert_eq!(
+            dests(&slices),
+            vec![
+                // top row
+                Rect::new(0., 52., 8., 60.),
+                Rect::new(8., 52., 92., 60.),
+                Rect::new(92., 52., 100., 60.),
+                // middle row
+                Rect::new(0., 8., 8., 52.),
+                Rect::new(8., 8., 92., 52.),
+                Rect::new(92., 8., 100., 52.),
+                // bottom row
+                Rect::new(0., 0., 8., 8.),
+                Rect::new(8., 0., 92., 8.),
+                Rect::new(92., 0., 100., 8.),
+            ]
+        );
+        // the top left corner of the panel shows the top left corner of the texture
+        assert_eq!(slices[0].source, Rect::new(0., 0., 8., 8.));
+        assert_eq!(slices[4].source, Rect::new(8., 8., 16., 16.));
+        assert_eq!(slices[8].source, Rect::new(16., 16., 24., 24.));
+    }
+
+    #[test]
+    fn uneven_margins_and_border_scale() {
+        let margins = SliceMargins {
+            left: 4.,
+            right: 6.,
+            top: 2.,
+            bottom: 10.,
+        };
+        let slices = nine_slice(
+            Rect::new(10., 20., 110., 100.),
+            Vec2::new(20., 30.),
+            margins,
+            2.,
+        );
+        assert_eq!(slices[0].dest, Rect::new(10., 96., 18., 100.));
+        assert_eq!(slices[0].source, Rect::new(0., 0., 4., 2.));
+        assert_eq!(slices[8].dest, Rect::new(98., 20., 110., 40.));
+        assert_eq!(slices[8].source, Rect::new(14., 20., 20., 30.));
+    }
+
+    #[test]
+    fn small_panels_shrink_their_borders() {
+        // 10 wide can't fit two 8 pixel borders, they share it and the middle column goes away
+        let slices = nine_slice(
+            Rect::new(0., 0., 10., 40.),
+            Vec2::splat(24.),
+            SliceMargins::all(8.),
+            1.,
+        );
+        assert_eq!(slices.len(), 6);
+        assert_eq!(slices[0].dest, Rect::new(0., 32., 5., 40.));
+        assert_eq!(slices[1].dest, Rect::new(5., 32., 10., 40.));
+        // the texture is still sampled from the full corners
+        assert_eq!(slices[1].source, Rect::new(16., 0., 24., 8.));
+
+        // margins larger than the texture are shrunk to it
+        let slices = nine_slice(
+            Rect::new(0., 0., 100., 100.),
+            Vec2::splat(10.),
+            SliceMargins::all(8.),
+            1.,
+        );
+        assert_eq!(slices[0].source, Rect::new(0., 0., 5., 5.));
+        assert_eq!(slices.len(), 4);
+
+        // no margins only leave the stretched center
+        let slices = nine_slice(
+            Rect::new(0., 0., 50., 50.),
+            Vec2::splat(24.),
+            SliceMargins::all(0.),
+            1.,
+        );
+        assert_eq!(
+            slices,
+            vec![Slice {
+                dest: Rect::new(0., 0., 50., 50.),
+                source: Rect::new(0., 0., 24., 24.),
+            }]
+        );
+    }
+
+    #[test]
+    fn icons_fill_rows_from_the_top_left() {
+        let size = icon_panel_size(3, 2, 10., Vec2::splat(20.), 4.);
+        assert_eq!(size, Vec2::new(88., 64.));
+
+        let rect = Rect::from_corners(Vec2::ZERO, size);
+        let centers = layout_icons(rect, 10., Vec2::splat(20.), 4., 5);
+        assert_eq!(
+            centers,
+            vec![
+                Vec2::new(20., 44.),
+                Vec2::new(44., 44.),
+                Vec2::new(68., 44.),
+                Vec2::new(20., 20.),
+                Vec2::new(44., 20.),
+            ]
+        );
+
+        // a panel too narrow for one icon still gets a column
+        let centers = layout_icons(Rect::new(0., 0., 10., 100.), 10., Vec2::splat(20.), 4., 2);
+        assert_eq!(centers, vec![Vec2::new(20., 80.), Vec2::new(20., 56.)]);
+    }
+}
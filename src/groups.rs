@@ -0,0 +1,560 @@
+//! Groups of creatures spawned together from a Tiled object, moving in formation.
+//!
+//! An object of type `group_spawn` spawns `count` creatures of its `template` on the free tiles
+//! around its own tile, in a `block` (the default) or a `ring` `formation`, see
+//! `formation_tiles`. The first one is the group's leader, the others keep their offset from it.
+//! The group id is the id of the object, so the same group comes back when the map is reloaded.
+//!
+//! `MoveGroup`, sent by the console command `group move <id> <x> <y>`, walks the leader to a tile
+//! along a `MovePath`. Followers step towards their place next to the leader, and when it's
+//! blocked (in a corridor, say) towards the leader itself, waiting whenever the tile ahead is
+//! taken, so they queue up behind it. Group members never go dormant.
+
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::collision::CollisionMap;
+use crate::console::{ConsoleCommand, DebugConsole};
+use crate::coords::{self, MapFrame};
+use crate::flags::GameFlags;
+use crate::game::{GameSet, LevelLifecycleSet};
+use crate::helpers;
+use crate::layers::LayerZConfig;
+use crate::map::{GameInfoAlt, Npc};
+use crate::movement::{MoveRequested, MovementSettings, StepCooldown};
+use crate::pathfinding::{self, MovePath};
+use crate::player::MainPlayer;
+use crate::sprite_atlas::CurrentCreatureAtlas;
+use crate::state::{level_ready, AppState};
+use crate::templates::EntityTemplates;
+
+/// Rings searched around the spawn tile for free tiles
+const MAX_RING: i32 = 6;
+
+/// Plugin that spawns the creature groups and moves them in formation
+#[derive(Default)]
+pub struct GroupPlugin;
+
+impl Plugin for GroupPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<MoveGroup>()
+            .add_systems(
+                OnEnter(AppState::Level),
+                spawn_groups.in_set(LevelLifecycleSet),
+            )
+            .add_systems(
+                Update,
+                (
+                    group_command,
+                    start_group_moves,
+                    apply_deferred,
+                    steer_groups,
+                )
+                    .chain()
+                    .in_set(GameSet::Input)
+                    .run_if(level_ready)
+                    .run_if(crate::debug_step::gameplay_running)
+                    .run_if(not(crate::editor::editing)),
+            );
+    }
+}
+
+/// The group of a creature, the id of the `group_spawn` object it was spawned from
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GroupId(pub u32);
+
+/// The creature the rest of its group follows
+#[derive(Component, Debug, Default)]
+pub struct GroupLeader;
+
+/// Where the creature stands in the formation, relative to its leader's tile
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroupMember {
+    pub offset: IVec2,
+}
+
+/// Send to walk the leader of `group` to `to`, the rest of the group following
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MoveGroup {
+    pub group: GroupId,
+    pub to: TilePos,
+}
+
+/// How a group is laid out around its spawn tile
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Formation {
+    /// Packed around the spawn tile, starting on it
+    #[default]
+    Block,
+    /// Around the spawn tile, leaving it free
+    Ring,
+}
+
+impl Formation {
+    /// The formation named by a `formation` property
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "block" => Some(Formation::Block),
+            "ring" => Some(Formation::Ring),
+            _ => None,
+        }
+    }
+}
+
+/// Offsets of the tiles `ring` steps away from a tile, the orthogonal ones first, then going
+/// counterclockwise from the east
+fn ring_offsets(ring: i32) -> Vec<IVec2> {
+    let angle = |offset: &IVec2| {
+        (offset.y as f32)
+            .atan2(offset.x as f32)
+            .rem_euclid(std::f32::consts::TAU)
+    };
+    let mut offsets: Vec<_> = (-ring..=ring)
+        .flat_map(|y| (-ring..=ring).map(move |x| IVec2::new(x, y)))
+        .filter(|offset| offset.x.abs().max(offset.y.abs()) == ring)
+        .collect();
+    offsets.sort_by(|a, b| {
+        let (da, db) = (a.x.abs() + a.y.abs(), b.x.abs() + b.y.abs());
+        da.cmp(&db).then(angle(a).total_cmp(&angle(b)))
+    });
+    offsets
+}
+
+/// Up to `count` open tiles for a group spawned on `center`, searched ring by ring outwards so
+/// the group stays compact. Fewer when there's no room within `MAX_RING` rings.
+pub fn formation_tiles(
+    map: &CollisionMap,
+    center: TilePos,
+    count: usize,
+    formation: Formation,
+) -> Vec<TilePos> {
+    let size = map.size();
+    let center = IVec2::new(center.x as i32, center.y as i32);
+    let first_ring = match formation {
+        Formation::Block => 0,
+        Formation::Ring => 1,
+    };
+    (first_ring..=MAX_RING)
+        .flat_map(ring_offsets)
+        .filter_map(|offset| {
+            let tile = center + offset;
+            let tile = TilePos::new(u32::try_from(tile.x).ok()?, u32::try_from(tile.y).ok()?);
+            (tile.within_map_bounds(&size) && map.movement_cost(&tile).is_some()).then_some(tile)
+        })
+        .take(count)
+        .collect()
+}
+
+fn int_property(properties: &tiled::Properties, name: &str) -> Option<i32> {
+    match properties.get(name)? {
+        tiled::PropertyValue::IntValue(value) => Some(*value),
+        _ => None,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_groups(
+    mut commands: Commands,
+    game_info: Res<GameInfoAlt>,
+    creature_atlas: Res<CurrentCreatureAtlas>,
+    tile_maps: Res<Assets<helpers::tiled::TiledMap>>,
+    atlases: Res<Assets<TextureAtlas>>,
+    entity_templates: Res<Assets<EntityTemplates>>,
+    map_frame: Res<MapFrame>,
+    collision_map: Res<CollisionMap>,
+    z_config: Res<LayerZConfig>,
+    flags: Res<GameFlags>,
+) {
+    let Some(map) = tile_maps.get(&game_info.tile_map) else {
+        return;
+    };
+    let objects = helpers::tiled::all_layers(&map.map)
+        .filter_map(|layer| match layer.layer_type() {
+            tiled::LayerType::Objects(layer) => Some(layer),
+            _ => None,
+        })
+        .flat_map(|layer| layer.objects().collect::<Vec<_>>());
+    for object in objects {
+        if !object.visible
+            || !object.user_type.eq_ignore_ascii_case("group_spawn")
+            || !flags.object_condition_met(&object.properties)
+        {
+            continue;
+        }
+        let Some(center) = coords::object_tile(&map_frame, &object) else {
+            warn!("group {} is outside the map", object.name);
+            continue;
+        };
+        let count = int_property(&object.properties, "count")
+            .unwrap_or(1)
+            .max(0) as usize;
+        let formation = match object.properties.get("formation") {
+            Some(tiled::PropertyValue::StringValue(name)) => Formation::from_name(name)
+                .unwrap_or_else(|| {
+                    warn!("group {} has unknown formation {name}", object.name);
+                    Formation::default()
+                }),
+            _ => Formation::default(),
+        };
+        let tiles = formation_tiles(&collision_map, center, count, formation);
+        if tiles.len() < count {
+            warn!(
+                "group {} has room for {} of its {count} creatures",
+                object.name,
+                tiles.len()
+            );
+        }
+        let Some(leader_tile) = tiles.first().copied() else {
+            continue;
+        };
+        info!("spawning group {} of {}", object.name, tiles.len());
+        for (i, tile) in tiles.into_iter().enumerate() {
+            let Some(pos) = coords::tile_to_world_center(&map_frame, &tile) else {
+                continue;
+            };
+            let z = z_config.creature_z(&map_frame, pos);
+            let creature = crate::map::spawn_creature(
+                &mut commands,
+                &creature_atlas.0,
+                &atlases,
+                entity_templates.get(&game_info.templates),
+                &object,
+                pos.extend(z),
+                i as i32,
+            );
+            let offset = IVec2::new(tile.x as i32, tile.y as i32)
+                - IVec2::new(leader_tile.x as i32, leader_tile.y as i32);
+            let mut creature = commands.entity(creature);
+            creature.insert((
+                GroupId(object.id()),
+                GroupMember { offset },
+                Name::new(format!("{} {}", object.name, i + 1)),
+            ));
+            if i == 0 {
+                creature.insert(GroupLeader);
+            }
+        }
+    }
+}
+
+/// `group list` shows the groups, `group move <id> <x> <y>` walks one to a tile
+fn group_command(
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<DebugConsole>,
+    map_frame: Res<MapFrame>,
+    leaders: Query<(&GroupId, &Name, &Transform), With<GroupLeader>>,
+    mut moves: EventWriter<MoveGroup>,
+) {
+    for command in console_commands.read() {
+        if command.name != "group" {
+            continue;
+        }
+        let args: Vec<_> = command.args.iter().map(String::as_str).collect();
+        match args.as_slice() {
+            ["list"] => {
+                if leaders.is_empty() {
+                    console.print("no groups");
+                }
+                for (group, name, transform) in &leaders {
+                    let tile = coords::world_to_tile(&map_frame, transform.translation.truncate())
+                        .map_or("outside the map".to_string(), |tile| {
+                            format!("({}, {})", tile.x, tile.y)
+                        });
+                    console.print(format!("group {}: {name} leads, at {tile}", group.0));
+                }
+            }
+            ["move", group, x, y] => match (group.parse(), x.parse(), y.parse()) {
+                (Ok(group), Ok(x), Ok(y)) => moves.send(MoveGroup {
+                    group: GroupId(group),
+                    to: TilePos::new(x, y),
+                }),
+                _ => console.print("group, x and y must be numbers"),
+            },
+            _ => console.print("usage: group list | group move <id> <x> <y>"),
+        }
+    }
+}
+
+/// Give the leader of each moved group its path
+fn start_group_moves(
+    mut commands: Commands,
+    mut moves: EventReader<MoveGroup>,
+    settings: Res<MovementSettings>,
+    collision_map: Res<CollisionMap>,
+    map_frame: Res<MapFrame>,
+    leaders: Query<(Entity, &GroupId, &Transform), With<GroupLeader>>,
+) {
+    for event in moves.read() {
+        let Some((leader, _, transform)) = leaders.iter().find(|(_, id, _)| **id == event.group)
+        else {
+            warn!("there is no group {}", event.group.0);
+            continue;
+        };
+        let Some(from) = coords::world_to_tile(&map_frame, transform.translation.truncate()) else {
+            continue;
+        };
+        match pathfinding::find_path(&collision_map, from, event.to, settings.diagonal) {
+            Some(path) => {
+                commands.entity(leader).insert(MovePath {
+                    tiles: path.into_iter().skip(1).collect(),
+                });
+            }
+            None => warn!("group {} can't get to {:?}", event.group.0, event.to),
+        }
+    }
+}
+
+fn step_dir(from: TilePos, to: TilePos) -> IVec2 {
+    IVec2::new(to.x as i32, to.y as i32) - IVec2::new(from.x as i32, from.y as i32)
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn steer_groups(
+    mut commands: Commands,
+    settings: Res<MovementSettings>,
+    collision_map: Res<CollisionMap>,
+    map_frame: Res<MapFrame>,
+    mut leaders: Query<
+        (
+            Entity,
+            &GroupId,
+            &Transform,
+            Option<&StepCooldown>,
+            Option<&mut MovePath>,
+        ),
+        With<GroupLeader>,
+    >,
+    followers: Query<
+        (
+            Entity,
+            &GroupId,
+            &GroupMember,
+            &Transform,
+            Option<&StepCooldown>,
+        ),
+        Without<GroupLeader>,
+    >,
+    creatures: Query<&Transform, Or<(With<MainPlayer>, With<Npc>)>>,
+    mut requests: EventWriter<MoveRequested>,
+) {
+    let tile_of =
+        |transform: &Transform| coords::world_to_tile(&map_frame, transform.translation.truncate());
+    let walking = |cooldown: Option<&StepCooldown>| cooldown.is_some_and(StepCooldown::is_walking);
+    let mut occupied: HashSet<TilePos> = creatures.iter().filter_map(tile_of).collect();
+    // a step taken this frame frees the tile for whoever queues behind
+    let mut step = |occupied: &mut HashSet<TilePos>, entity: Entity, from: TilePos, to: TilePos| {
+        if occupied.contains(&to) {
+            return false;
+        }
+        occupied.remove(&from);
+        occupied.insert(to);
+        requests.send(MoveRequested {
+            entity,
+            dir: step_dir(from, to),
+        });
+        true
+    };
+
+    let mut leader_tiles = HashMap::default();
+    for (leader, group, transform, cooldown, path) in &mut leaders {
+        let Some(tile) = tile_of(transform) else {
+            continue;
+        };
+        leader_tiles.insert(*group, tile);
+        let Some(mut path) = path else {
+            continue;
+        };
+        if walking(cooldown) {
+            continue;
+        }
+        while path.tiles.first() == Some(&tile) {
+            path.tiles.remove(0);
+        }
+        let Some(next) = path.tiles.first().copied() else {
+            commands.entity(leader).remove::<MovePath>();
+            continue;
+        };
+        if coords::tile_distance(&tile, &next) > 1 {
+            // pushed off its path, plan it again
+            let destination = path.destination().unwrap_or(next);
+            match pathfinding::find_path(&collision_map, tile, destination, settings.diagonal) {
+                Some(replanned) => path.tiles = replanned.into_iter().skip(1).collect(),
+                None => {
+                    commands.entity(leader).remove::<MovePath>();
+                }
+            }
+            continue;
+        }
+        if step(&mut occupied, leader, tile, next) {
+            path.tiles.remove(0);
+        }
+    }
+
+    // the followers closest to their leader go first, the others queue up behind them
+    let mut followers: Vec<_> = followers
+        .iter()
+        .filter(|(.., cooldown)| !walking(*cooldown))
+        .collect();
+    followers.sort_by_key(|(entity, _, member, ..)| {
+        (member.offset.x.abs() + member.offset.y.abs(), *entity)
+    });
+    for (entity, group, member, transform, _) in followers {
+        let (Some(tile), Some(leader_tile)) = (tile_of(transform), leader_tiles.get(group)) else {
+            continue;
+        };
+        let place = IVec2::new(leader_tile.x as i32, leader_tile.y as i32) + member.offset;
+        let place = u32::try_from(place.x)
+            .ok()
+            .zip(u32::try_from(place.y).ok())
+            .map(|(x, y)| TilePos::new(x, y))
+            .filter(|place| collision_map.movement_cost(place).is_some());
+        // no room for its place, get in line behind the leader
+        let target = place.unwrap_or(*leader_tile);
+        if tile == target {
+            continue;
+        }
+        let Some(path) = pathfinding::find_path(&collision_map, tile, target, settings.diagonal)
+        else {
+            continue;
+        };
+        if let Some(next) = path.get(1) {
+            step(&mut occupied, entity, tile, *next);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collision::MoveBlocked;
+    use crate::combat::BumpAttack;
+    use crate::movement::{self, MoveResolved};
+
+    fn open_map(width: u32, height: u32) -> CollisionMap {
+        CollisionMap::new(TilemapSize {
+            x: width,
+            y: height,
+        })
+    }
+
+    #[test]
+    fn block_formations_pack_around_the_spawn_tile() {
+        let map = open_map(5, 5);
+        let tiles = formation_tiles(&map, TilePos::new(2, 2), 5, Formation::Block);
+        assert_eq!(
+            tiles,
+            [
+                TilePos::new(2, 2),
+                TilePos::new(3, 2),
+                TilePos::new(2, 3),
+                TilePos::new(1, 2),
+                TilePos::new(2, 1),
+            ]
+        );
+        let ring = formation_tiles(&map, TilePos::new(2, 2), 9, Formation::Ring);
+        assert_eq!(ring.len(), 9);
+        assert!(!ring.contains(&TilePos::new(2, 2)));
+        assert!(ring[..8]
+            .iter()
+            .all(|tile| { coords::tile_distance(tile, &TilePos::new(2, 2)) == 1 }));
+    }
+
+    #[test]
+    fn formations_skip_blocked_tiles_and_the_map_edge() {
+        let mut map = open_map(3, 3);
+        map.set_blocked(&TilePos::new(0, 0), true);
+        map.set_blocked(&TilePos::new(1, 0), true);
+        let tiles = formation_tiles(&map, TilePos::new(0, 0), 10, Formation::Block);
+        // the seven open tiles of the map, the nearest first
+        assert_eq!(tiles.len(), 7);
+        assert_eq!(tiles[..2], [TilePos::new(0, 1), TilePos::new(1, 1)]);
+        assert!(tiles.iter().all(|tile| map.movement_cost(tile).is_some()));
+    }
+
+    fn map_frame() -> MapFrame {
+        MapFrame {
+            transform: Transform::IDENTITY,
+            grid_size: TilemapGridSize { x: 10., y: 10. },
+            map_type: TilemapType::Square,
+            map_size: TilemapSize { x: 8, y: 3 },
+        }
+    }
+
+    fn tile_of(app: &App, entity: Entity) -> TilePos {
+        let pos = app.world.get::<Transform>(entity).unwrap().translation;
+        coords::world_to_tile(&map_frame(), pos.truncate()).unwrap()
+    }
+
+    #[test]
+    fn followers_queue_behind_the_leader_through_a_corridor() {
+        // a corridor along y = 1 from x = 2 to 5
+        let mut collision_map = open_map(8, 3);
+        for x in 2..6 {
+            collision_map.set_blocked(&TilePos::new(x, 0), true);
+            collision_map.set_blocked(&TilePos::new(x, 2), true);
+        }
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<MovementSettings>()
+            .insert_resource(map_frame())
+            .insert_resource(collision_map)
+            .add_event::<MoveGroup>()
+            .add_event::<MoveRequested>()
+            .add_event::<MoveResolved>()
+            .add_event::<MoveBlocked>()
+            .add_event::<BumpAttack>()
+            .add_systems(
+                Update,
+                (
+                    start_group_moves,
+                    apply_deferred,
+                    steer_groups,
+                    movement::resolve_moves,
+                    movement::apply_moves,
+                    apply_deferred,
+                )
+                    .chain(),
+            );
+        let mut spawn = |tile: TilePos, offset: IVec2| {
+            let pos = coords::tile_to_world_center(&map_frame(), &tile).unwrap();
+            app.world
+                .spawn((
+                    Transform::from_translation(pos.extend(2.)),
+                    Npc,
+                    GroupId(7),
+                    GroupMember { offset },
+                ))
+                .id()
+        };
+        let leader = spawn(TilePos::new(1, 1), IVec2::ZERO);
+        let follower = spawn(TilePos::new(1, 2), IVec2::Y);
+        app.world.entity_mut(leader).insert(GroupLeader);
+
+        app.world.send_event(MoveGroup {
+            group: GroupId(7),
+            to: TilePos::new(7, 1),
+        });
+        for _ in 0..20 {
+            app.update();
+            // done walking onto the tile
+            for mut cooldown in app
+                .world
+                .query::<&mut StepCooldown>()
+                .iter_mut(&mut app.world)
+            {
+                cooldown.0 = 0.;
+            }
+            let follower_tile = tile_of(&app, follower);
+            assert!(app
+                .world
+                .resource::<CollisionMap>()
+                .movement_cost(&follower_tile)
+                .is_some());
+        }
+
+        assert_eq!(tile_of(&app, leader), TilePos::new(7, 1));
+        assert_eq!(tile_of(&app, follower), TilePos::new(7, 2));
+        assert!(app.world.get::<MovePath>(leader).is_none());
+    }
+}
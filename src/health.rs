@@ -0,0 +1,417 @@
+//! Hit points, damage and death of the creatures.
+//!
+//! Properties read from the creature objects:
+//! - `health`: hit points the creature starts with, creatures without it can't be hurt
+//! - `max_health`: most hit points the creature can have, `health` when missing
+
+use std::fmt::Write;
+
+use bevy::prelude::*;
+
+use crate::console::{ConsoleCommand, DebugConsole};
+use crate::coords::{self, MapFrame};
+use crate::game::GameSet;
+use crate::lifetime::ExpireOnStateExit;
+use crate::player::MainPlayer;
+use crate::spawn_effects::DespawnAfterEffect;
+use crate::state::{level_ready, AppState};
+use crate::ui::SelectedTile;
+use crate::ui_scale::UiScaling;
+
+/// Seconds a creature stays tinted after a hit
+const HIT_FLASH_SECS: f32 = 0.15;
+/// Tint of a creature that was just hit
+const HIT_FLASH_COLOR: Color = Color::rgb(1., 0.3, 0.3);
+/// Seconds a damage number floats before it is gone
+const DAMAGE_NUMBER_SECS: f32 = 0.8;
+/// How far up a damage number floats, in world units
+const DAMAGE_NUMBER_RISE: f32 = 24.;
+/// Above everything else in the world, see `layers`
+const DAMAGE_NUMBER_Z: f32 = 6.5;
+/// Font size of the damage numbers at interface scale 1
+const DAMAGE_NUMBER_FONT_SIZE: f32 = 16.;
+
+/// Plugin that applies `DamageEvent`s, shows the hits and sends `Died`
+#[derive(Default)]
+pub struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DamageNumberPool>()
+            .register_type::<Health>()
+            .add_event::<DamageEvent>()
+            .add_event::<Died>()
+            .add_systems(
+                Update,
+                (damage_command, apply_damage, despawn_dead)
+                    .chain()
+                    .in_set(GameSet::Gameplay)
+                    .run_if(level_ready)
+                    .run_if(crate::debug_step::gameplay_running),
+            )
+            .add_systems(
+                Update,
+                (update_hit_flash, float_damage_numbers)
+                    .in_set(GameSet::RenderPrep)
+                    .run_if(level_ready),
+            );
+    }
+}
+
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Health {
+    pub current: i32,
+    pub max: i32,
+}
+
+impl Health {
+    #[cfg(test)]
+    pub fn new(max: i32) -> Self {
+        Self { current: max, max }
+    }
+
+    /// Health from the `health` and `max_health` properties of a Tiled object
+    pub fn from_properties(properties: &tiled::Properties) -> Option<Self> {
+        let Some(tiled::PropertyValue::IntValue(health)) = properties.get("health") else {
+            return None;
+        };
+        let max = match properties.get("max_health") {
+            Some(tiled::PropertyValue::IntValue(max)) => *max,
+            _ => *health,
+        };
+        Some(Self {
+            current: (*health).clamp(0, max),
+            max,
+        })
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0
+    }
+}
+
+/// Sent to take `amount` hit points from `target`, negative amounts heal
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DamageEvent {
+    pub target: Entity,
+    pub amount: i32,
+    /// Who dealt the damage, if anyone
+    pub source: Option<Entity>,
+}
+
+/// Sent when the health of the entity reaches zero
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Died(pub Entity);
+
+/// Tints the sprite of a creature that was just hit
+#[derive(Component, Debug)]
+pub struct HitFlash(Timer);
+
+/// A number floating up from a hit creature
+#[derive(Component, Debug)]
+pub struct DamageNumber {
+    timer: Timer,
+    start: Vec2,
+}
+
+/// Damage number entities that finished floating, reused for the next hits
+#[derive(Resource, Default, Debug)]
+pub struct DamageNumberPool {
+    free: Vec<Entity>,
+}
+
+pub fn apply_damage(
+    mut commands: Commands,
+    mut damage: EventReader<DamageEvent>,
+    mut targets: Query<(&mut Health, &Transform), Without<DamageNumber>>,
+    mut numbers: Query<(
+        &mut DamageNumber,
+        &mut Text,
+        &mut Transform,
+        &mut Visibility,
+    )>,
+    mut pool: ResMut<DamageNumberPool>,
+    mut died: EventWriter<Died>,
+    ui_scale: Res<UiScaling>,
+) {
+    let font_size = DAMAGE_NUMBER_FONT_SIZE * ui_scale.scale();
+    for event in damage.read() {
+        let Ok((mut health, transform)) = targets.get_mut(event.target) else {
+            continue;
+        };
+        if health.is_dead() {
+            continue;
+        }
+        health.current = (health.current - event.amount).clamp(0, health.max);
+        if health.is_dead() {
+            died.send(Died(event.target));
+        }
+
+        commands
+            .entity(event.target)
+            .insert(HitFlash(Timer::from_seconds(
+                HIT_FLASH_SECS,
+                TimerMode::Once,
+            )));
+
+        let start = transform.translation.truncate();
+        let reused = pool
+            .free
+            .pop()
+            .and_then(|entity| numbers.get_mut(entity).ok());
+        match reused {
+            Some((mut number, mut text, mut transform, mut visibility)) => {
+                *number = DamageNumber::new(start);
+                let value = &mut text.sections[0].value;
+                value.clear();
+                let _ = write!(value, "{}", event.amount);
+                text.sections[0].style.color = Color::WHITE;
+                text.sections[0].style.font_size = font_size;
+                *transform = Transform::from_translation(start.extend(DAMAGE_NUMBER_Z));
+                *visibility = Visibility::Inherited;
+            }
+            None => {
+                commands.spawn((
+                    Text2dBundle {
+                        text: Text::from_section(
+                            event.amount.to_string(),
+                            TextStyle {
+                                font_size,
+                                color: Color::WHITE,
+                                ..default()
+                            },
+                        )
+                        .with_alignment(TextAlignment::Center),
+                        transform: Transform::from_translation(start.extend(DAMAGE_NUMBER_Z)),
+                        ..default()
+                    },
+                    DamageNumber::new(start),
+                    ExpireOnStateExit(AppState::Level),
+                    Name::new("damage number"),
+                ));
+            }
+        }
+    }
+}
+
+impl DamageNumber {
+    fn new(start: Vec2) -> Self {
+        Self {
+            timer: Timer::from_seconds(DAMAGE_NUMBER_SECS, TimerMode::Once),
+            start,
+        }
+    }
+}
+
+fn despawn_dead(
+    mut died: EventReader<Died>,
+    players: Query<(), With<MainPlayer>>,
+    mut despawn: EventWriter<DespawnAfterEffect>,
+) {
+    for Died(entity) in died.read() {
+        // what happens when the player dies is up to the game flow
+        if !players.contains(*entity) {
+            despawn.send(DespawnAfterEffect { entity: *entity });
+        }
+    }
+}
+
+fn update_hit_flash(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut flashing: Query<(Entity, &mut HitFlash, &mut TextureAtlasSprite)>,
+) {
+    for (entity, mut flash, mut sprite) in &mut flashing {
+        flash.0.tick(time.delta());
+        // keep the alpha, which belongs to the spawn and despawn effects
+        let alpha = sprite.color.a();
+        if flash.0.finished() {
+            sprite.color = Color::WHITE.with_a(alpha);
+            commands.entity(entity).remove::<HitFlash>();
+        } else {
+            sprite.color = HIT_FLASH_COLOR.with_a(alpha);
+        }
+    }
+}
+
+fn float_damage_numbers(
+    time: Res<Time>,
+    mut numbers: Query<(
+        Entity,
+        &mut DamageNumber,
+        &mut Text,
+        &mut Transform,
+        &mut Visibility,
+    )>,
+    mut pool: ResMut<DamageNumberPool>,
+) {
+    for (entity, mut number, mut text, mut transform, mut visibility) in &mut numbers {
+        if *visibility == Visibility::Hidden {
+            continue;
+        }
+        number.timer.tick(time.delta());
+        let t = number.timer.percent();
+        transform.translation.y = number.start.y + DAMAGE_NUMBER_RISE * t;
+        text.sections[0].style.color.set_a(1. - t * t);
+        if number.timer.finished() {
+            *visibility = Visibility::Hidden;
+            pool.free.push(entity);
+        }
+    }
+}
+
+/// `damage <amount>` hurts the creature on the selected tile
+fn damage_command(
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<DebugConsole>,
+    selected: Res<SelectedTile>,
+    map_frame: Option<Res<MapFrame>>,
+    targets: Query<(Entity, &Transform), With<Health>>,
+    mut damage: EventWriter<DamageEvent>,
+) {
+    for command in console_commands.read() {
+        if command.name != "damage" {
+            continue;
+        }
+        let Some(amount) = command.args.first().and_then(|arg| arg.parse::<i32>().ok()) else {
+            console.print("usage: damage <amount>");
+            continue;
+        };
+        let (Some(tile), Some(map_frame)) = (selected.0, map_frame.as_deref()) else {
+            console.print("no tile selected");
+            continue;
+        };
+        let target = targets.iter().find(|(_, transform)| {
+            coords::world_to_tile(map_frame, transform.translation.truncate()) == Some(tile)
+        });
+        let Some((target, _)) = target else {
+            console.print(format!("nothing with health at {},{}", tile.x, tile.y));
+            continue;
+        };
+        damage.send(DamageEvent {
+            target,
+            amount,
+            source: None,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::ecs::event::ManualEventReader;
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                100,
+            )))
+            .init_resource::<DamageNumberPool>()
+            .init_resource::<UiScaling>()
+            .add_event::<DamageEvent>()
+            .add_event::<Died>()
+            .add_event::<DespawnAfterEffect>()
+            .add_systems(
+                Update,
+                (
+                    apply_damage,
+                    despawn_dead,
+                    apply_deferred,
+                    update_hit_flash,
+                    float_damage_numbers,
+                )
+                    .chain(),
+            );
+        app
+    }
+
+    fn hit(app: &mut App, target: Entity, amount: i32) {
+        app.world.send_event(DamageEvent {
+            target,
+            amount,
+            source: None,
+        });
+        app.update();
+    }
+
+    /// The `Died` events sent since `reader` last read them
+    fn died(app: &App, reader: &mut ManualEventReader<Died>) -> Vec<Died> {
+        let events = app.world.resource::<Events<Died>>();
+        reader.read(events).copied().collect()
+    }
+
+    #[test]
+    fn health_is_clamped_and_dies_once() {
+        let mut app = test_app();
+        let npc = app
+            .world
+            .spawn((
+                Health::new(10),
+                Transform::default(),
+                TextureAtlasSprite::default(),
+            ))
+            .id();
+
+        hit(&mut app, npc, 4);
+        assert_eq!(app.world.get::<Health>(npc).unwrap().current, 6);
+        assert!(app.world.get::<HitFlash>(npc).is_some());
+        assert_ne!(
+            app.world.get::<TextureAtlasSprite>(npc).unwrap().color,
+            Color::WHITE
+        );
+
+        hit(&mut app, npc, 20);
+        assert_eq!(app.world.get::<Health>(npc).unwrap().current, 0);
+        let mut reader = ManualEventReader::default();
+        assert_eq!(died(&app, &mut reader), [Died(npc)]);
+
+        app.update();
+        hit(&mut app, npc, 5);
+        assert!(died(&app, &mut reader).is_empty());
+    }
+
+    #[test]
+    fn damage_numbers_are_reused() {
+        let mut app = test_app();
+        let npc = app
+            .world
+            .spawn((Health::new(100), Transform::default()))
+            .id();
+        let count = |app: &mut App| {
+            app.world
+                .query_filtered::<(), With<DamageNumber>>()
+                .iter(&app.world)
+                .count()
+        };
+
+        for _ in 0..3 {
+            hit(&mut app, npc, 1);
+            for _ in 0..10 {
+                app.update();
+            }
+        }
+        assert_eq!(count(&mut app), 1);
+        assert_eq!(app.world.resource::<DamageNumberPool>().free.len(), 1);
+    }
+
+    #[test]
+    fn health_from_properties() {
+        let mut properties = tiled::Properties::new();
+        assert_eq!(Health::from_properties(&properties), None);
+        properties.insert("health".to_string(), tiled::PropertyValue::IntValue(7));
+        assert_eq!(Health::from_properties(&properties), Some(Health::new(7)));
+        properties.insert("max_health".to_string(), tiled::PropertyValue::IntValue(12));
+        assert_eq!(
+            Health::from_properties(&properties),
+            Some(Health {
+                current: 7,
+                max: 12
+            })
+        );
+    }
+}
@@ -0,0 +1,381 @@
+//! Health bars floating above the hurt creatures.
+//!
+//! A creature that loses health gets a bar from the `HealthBarPool` showing the fraction it has
+//! left. The bar follows the creature wherever it moves and goes back to the pool
+//! `HEALTH_BAR_SECS` after the last change of its health, so a creature healed to full shows its
+//! full bar for a moment before it goes.
+//!
+//! Properties read from the creature objects, usually set by their template:
+//! - `health_bar_width`: width of the bar in world units, wider for big creatures
+//! - `health_bar_offset`: how far above the creature's center the bar floats
+
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use bevy::utils::HashMap;
+
+use crate::game::GameSet;
+use crate::health::Health;
+use crate::lifetime::ExpireOnStateExit;
+use crate::state::{level_ready, AppState};
+
+/// Seconds a bar stays up after the last change of its creature's health
+const HEALTH_BAR_SECS: f32 = 3.;
+/// Height of the bar, border included, in world units
+const HEALTH_BAR_HEIGHT: f32 = 4.;
+/// Width of the border around the fill
+const HEALTH_BAR_BORDER: f32 = 1.;
+/// Above the weather and below the damage numbers, see `layers`
+const HEALTH_BAR_Z: f32 = 6.4;
+const HEALTH_BAR_BACKGROUND: Color = Color::rgba(0.1, 0.1, 0.1, 0.8);
+const HEALTH_BAR_FILL: Color = Color::rgb(0.3, 0.85, 0.3);
+
+/// Plugin that shows a `HealthBar` over the creatures while their health changes
+#[derive(Default)]
+pub struct HealthBarPlugin;
+
+impl Plugin for HealthBarPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HealthBarPool>()
+            .register_type::<HealthBarStyle>()
+            .add_systems(
+                Update,
+                (show_health_bars, hide_health_bars)
+                    .chain()
+                    .in_set(GameSet::RenderPrep)
+                    .run_if(level_ready),
+            )
+            .add_systems(
+                PostUpdate,
+                follow_owners
+                    .before(bevy::transform::TransformSystem::TransformPropagate)
+                    .run_if(level_ready),
+            );
+    }
+}
+
+/// Size and place of a creature's health bar
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq)]
+pub struct HealthBarStyle {
+    pub width: f32,
+    /// Height of the bar's center over the creature's center
+    pub offset: f32,
+}
+
+impl Default for HealthBarStyle {
+    fn default() -> Self {
+        Self {
+            width: 14.,
+            offset: 12.,
+        }
+    }
+}
+
+impl HealthBarStyle {
+    /// Style from the `health_bar_width` and `health_bar_offset` properties of a Tiled object, the
+    /// defaults for the ones missing
+    pub fn from_properties(properties: &tiled::Properties) -> Self {
+        let float = |name| match properties.get(name) {
+            Some(tiled::PropertyValue::FloatValue(value)) => Some(*value),
+            Some(tiled::PropertyValue::IntValue(value)) => Some(*value as f32),
+            _ => None,
+        };
+        let default = Self::default();
+        Self {
+            width: float("health_bar_width").unwrap_or(default.width),
+            offset: float("health_bar_offset").unwrap_or(default.offset),
+        }
+    }
+}
+
+/// A bar showing the health of `owner`, with a background and a fill sprite as children
+#[derive(Component, Debug)]
+pub struct HealthBar {
+    owner: Entity,
+    offset: f32,
+    background: Entity,
+    fill: Entity,
+    /// Time left before the bar goes back to the pool
+    timer: Timer,
+}
+
+/// The fill sprite of a `HealthBar`, scaled by the health fraction
+#[derive(Component, Debug)]
+pub struct HealthBarFill;
+
+/// Bars in use by owner, and hidden bars reused for the next hurt creatures
+#[derive(Resource, Default, Debug)]
+pub struct HealthBarPool {
+    shown: HashMap<Entity, Entity>,
+    free: Vec<Entity>,
+}
+
+/// Show the bars of the creatures whose health changed, and update their fill
+fn show_health_bars(
+    mut commands: Commands,
+    owners: Query<(Entity, Ref<Health>, Option<&HealthBarStyle>)>,
+    mut bars: Query<(&mut HealthBar, &mut Visibility)>,
+    mut sprites: Query<&mut Sprite>,
+    mut fills: Query<&mut Transform, With<HealthBarFill>>,
+    mut pool: ResMut<HealthBarPool>,
+) {
+    for (owner, health, style) in &owners {
+        // creatures spawned hurt get a bar on their first change
+        if !health.is_changed() || health.is_added() {
+            continue;
+        }
+        let fraction = health.current as f32 / health.max.max(1) as f32;
+        let shown = pool.shown.get(&owner).copied();
+        if shown.is_none() && (fraction >= 1. || health.is_dead()) {
+            continue;
+        }
+        let style = style.copied().unwrap_or_default();
+        let fill_width = (style.width - 2. * HEALTH_BAR_BORDER).max(0.);
+        let timer = Timer::from_seconds(HEALTH_BAR_SECS, TimerMode::Once);
+
+        let reused = shown
+            .or_else(|| pool.free.pop())
+            .filter(|bar| bars.contains(*bar));
+        let bar = match reused {
+            Some(bar) => {
+                let (mut bar_state, mut visibility) = bars.get_mut(bar).unwrap();
+                bar_state.owner = owner;
+                bar_state.offset = style.offset;
+                bar_state.timer = timer;
+                *visibility = Visibility::Inherited;
+                if let Ok(mut background) = sprites.get_mut(bar_state.background) {
+                    background.custom_size = Some(Vec2::new(style.width, HEALTH_BAR_HEIGHT));
+                }
+                if let Ok(mut fill) = sprites.get_mut(bar_state.fill) {
+                    fill.custom_size = Some(Vec2::new(
+                        fill_width,
+                        HEALTH_BAR_HEIGHT - 2. * HEALTH_BAR_BORDER,
+                    ));
+                }
+                if let Ok(mut transform) = fills.get_mut(bar_state.fill) {
+                    transform.translation.x = -fill_width / 2.;
+                    transform.scale.x = fraction.clamp(0., 1.);
+                }
+                bar
+            }
+            None => {
+                let background = commands
+                    .spawn(SpriteBundle {
+                        sprite: Sprite {
+                            color: HEALTH_BAR_BACKGROUND,
+                            custom_size: Some(Vec2::new(style.width, HEALTH_BAR_HEIGHT)),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .id();
+                let fill = commands
+                    .spawn((
+                        SpriteBundle {
+                            sprite: Sprite {
+                                color: HEALTH_BAR_FILL,
+                                custom_size: Some(Vec2::new(
+                                    fill_width,
+                                    HEALTH_BAR_HEIGHT - 2. * HEALTH_BAR_BORDER,
+                                )),
+                                anchor: Anchor::CenterLeft,
+                                ..default()
+                            },
+                            // just over the background
+                            transform: Transform::from_xyz(-fill_width / 2., 0., 0.01)
+                                .with_scale(Vec3::new(fraction.clamp(0., 1.), 1., 1.)),
+                            ..default()
+                        },
+                        HealthBarFill,
+                    ))
+                    .id();
+                commands
+                    .spawn((
+                        SpatialBundle::from_transform(Transform::from_xyz(0., 0., HEALTH_BAR_Z)),
+                        HealthBar {
+                            owner,
+                            offset: style.offset,
+                            background,
+                            fill,
+                            timer,
+                        },
+                        ExpireOnStateExit(AppState::Level),
+                        Name::new("health bar"),
+                    ))
+                    .push_children(&[background, fill])
+                    .id()
+            }
+        };
+        pool.shown.insert(owner, bar);
+    }
+}
+
+/// Put the bars back in the pool once their time is up, or their creature is dead or gone
+fn hide_health_bars(
+    time: Res<Time>,
+    owners: Query<&Health>,
+    mut bars: Query<(Entity, &mut HealthBar, &mut Visibility)>,
+    mut pool: ResMut<HealthBarPool>,
+) {
+    for (entity, mut bar, mut visibility) in &mut bars {
+        if *visibility == Visibility::Hidden {
+            continue;
+        }
+        bar.timer.tick(time.delta());
+        let alive = owners.get(bar.owner).is_ok_and(|health| !health.is_dead());
+        if bar.timer.finished() || !alive {
+            *visibility = Visibility::Hidden;
+            pool.shown.remove(&bar.owner);
+            pool.free.push(entity);
+        }
+    }
+}
+
+/// Keep the bars over their creatures, once the tweens moved them this frame
+fn follow_owners(
+    owners: Query<&Transform, Without<HealthBar>>,
+    mut bars: Query<(&HealthBar, &Visibility, &mut Transform)>,
+) {
+    for (bar, visibility, mut transform) in &mut bars {
+        if *visibility == Visibility::Hidden {
+            continue;
+        }
+        let Ok(owner) = owners.get(bar.owner) else {
+            continue;
+        };
+        let position = owner.translation.truncate() + Vec2::new(0., bar.offset);
+        transform.translation = position.extend(HEALTH_BAR_Z);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+    use crate::health::{self, DamageEvent, DamageNumberPool, Died};
+    use crate::spawn_effects::DespawnAfterEffect;
+    use crate::ui_scale::UiScaling;
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                100,
+            )))
+            .init_resource::<HealthBarPool>()
+            .init_resource::<DamageNumberPool>()
+            .init_resource::<UiScaling>()
+            .add_event::<DamageEvent>()
+            .add_event::<Died>()
+            .add_event::<DespawnAfterEffect>()
+            .add_systems(
+                Update,
+                (
+                    health::apply_damage,
+                    show_health_bars,
+                    apply_deferred,
+                    hide_health_bars,
+                )
+                    .chain(),
+            )
+            .add_systems(PostUpdate, follow_owners);
+        app
+    }
+
+    fn hit(app: &mut App, target: Entity, amount: i32) {
+        app.world.send_event(DamageEvent {
+            target,
+            amount,
+            source: None,
+        });
+        app.update();
+    }
+
+    /// The visibility, the position and the fill scale of the bars
+    fn bars(app: &mut App) -> Vec<(Visibility, Vec2, f32)> {
+        let mut bars = app.world.query::<(&HealthBar, &Visibility, &Transform)>();
+        let bars: Vec<_> = bars
+            .iter(&app.world)
+            .map(|(bar, visibility, transform)| {
+                (*visibility, transform.translation.truncate(), bar.fill)
+            })
+            .collect();
+        bars.into_iter()
+            .map(|(visibility, position, fill)| {
+                let scale = app.world.get::<Transform>(fill).unwrap().scale.x;
+                (visibility, position, scale)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn hurt_creatures_show_their_health_until_it_settles() {
+        let mut app = test_app();
+        let style = HealthBarStyle {
+            width: 30.,
+            offset: 20.,
+        };
+        let npc = app
+            .world
+            .spawn((Health::new(10), Transform::from_xyz(40., 8., 2.5), style))
+            .id();
+        app.update();
+        assert!(bars(&mut app).is_empty());
+
+        hit(&mut app, npc, 4);
+        assert_eq!(
+            bars(&mut app),
+            [(Visibility::Inherited, Vec2::new(40., 28.), 0.6)]
+        );
+
+        // the bar follows its creature
+        app.world.get_mut::<Transform>(npc).unwrap().translation = Vec3::new(56., 8., 2.4);
+        hit(&mut app, npc, -4);
+        assert_eq!(
+            bars(&mut app),
+            [(Visibility::Inherited, Vec2::new(56., 28.), 1.)]
+        );
+
+        // back to full health, the bar goes after the delay, counted from the heal
+        for _ in 0..28 {
+            app.update();
+        }
+        assert_eq!(bars(&mut app)[0].0, Visibility::Inherited);
+        app.update();
+        assert_eq!(bars(&mut app)[0].0, Visibility::Hidden);
+
+        // and is reused for the next hit
+        hit(&mut app, npc, 5);
+        assert_eq!(
+            bars(&mut app),
+            [(Visibility::Inherited, Vec2::new(56., 28.), 0.5)]
+        );
+        assert!(app.world.resource::<HealthBarPool>().free.is_empty());
+    }
+
+    #[test]
+    fn style_from_properties() {
+        let mut properties = tiled::Properties::new();
+        assert_eq!(
+            HealthBarStyle::from_properties(&properties),
+            HealthBarStyle::default()
+        );
+        properties.insert(
+            "health_bar_width".to_string(),
+            tiled::PropertyValue::FloatValue(40.),
+        );
+        properties.insert(
+            "health_bar_offset".to_string(),
+            tiled::PropertyValue::IntValue(30),
+        );
+        assert_eq!(
+            HealthBarStyle::from_properties(&properties),
+            HealthBarStyle {
+                width: 40.,
+                offset: 30.
+            }
+        );
+    }
+}
@@ -0,0 +1,437 @@
+//! Spawning the tiles of big maps a chunk at a time, around the camera.
+//!
+//! A map entity with `MapStreaming::Streaming` gets its layer tilemaps spawned empty. Its tiles
+//! are read from the parsed map in square chunks of `CHUNK_SIZE` tiles when the camera view comes
+//! near them, and despawned again once more than `max_chunks` are loaded, least recently used
+//! first. Reading a tile through `ChunkCache::tile` loads its chunk too, so gameplay asking about
+//! a tile far from the camera still gets an answer.
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use bevy_ecs_tilemap::prelude::*;
+
+use super::tiled::{map_layers, texture_index, MapStreaming, TiledLayer, TiledMap};
+use crate::camera::CameraViewChanged;
+use crate::coords::MapFrame;
+
+/// Width and height of a chunk, in tiles
+pub const CHUNK_SIZE: u32 = 64;
+
+/// Position of a chunk, in chunks from the bottom left one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChunkPos {
+    pub x: u32,
+    pub y: u32,
+}
+
+impl ChunkPos {
+    /// The chunk holding `tile`
+    pub fn of(tile: &TilePos) -> Self {
+        Self {
+            x: tile.x / CHUNK_SIZE,
+            y: tile.y / CHUNK_SIZE,
+        }
+    }
+
+    /// The first tile of the chunk
+    pub fn origin(self) -> TilePos {
+        TilePos::new(self.x * CHUNK_SIZE, self.y * CHUNK_SIZE)
+    }
+
+    /// Index of `tile`, which must be in this chunk, in the chunk's tiles
+    #[allow(dead_code)]
+    fn index(self, tile: &TilePos) -> usize {
+        let origin = self.origin();
+        ((tile.y - origin.y) * CHUNK_SIZE + tile.x - origin.x) as usize
+    }
+}
+
+/// The chunks a map of `map_size` is cut into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkDirectory {
+    /// Number of chunks across and up, the last ones may be partly outside the map
+    pub size: UVec2,
+}
+
+impl ChunkDirectory {
+    pub fn new(map_size: &TilemapSize) -> Self {
+        Self {
+            size: UVec2::new(
+                map_size.x.div_ceil(CHUNK_SIZE),
+                map_size.y.div_ceil(CHUNK_SIZE),
+            ),
+        }
+    }
+
+    /// The chunks from `min` to `max` included, grown by `margin` chunks on each side
+    pub fn around(&self, min: ChunkPos, max: ChunkPos, margin: u32) -> HashSet<ChunkPos> {
+        let mut chunks = HashSet::default();
+        if self.size.x == 0 || self.size.y == 0 {
+            return chunks;
+        }
+        let last = self.size - 1;
+        for x in min.x.saturating_sub(margin)..=(max.x + margin).min(last.x) {
+            for y in min.y.saturating_sub(margin)..=(max.y + margin).min(last.y) {
+                chunks.insert(ChunkPos { x, y });
+            }
+        }
+        chunks
+    }
+
+    /// The chunks showing in the world-space `view` of the map of `frame`, grown by `margin`.
+    /// The view is only worked out on square grids, every chunk is wanted on the other ones.
+    pub fn in_view(&self, frame: &MapFrame, view: Rect, margin: u32) -> HashSet<ChunkPos> {
+        let last = ChunkPos {
+            x: self.size.x.saturating_sub(1),
+            y: self.size.y.saturating_sub(1),
+        };
+        if frame.map_type != TilemapType::Square {
+            return self.around(ChunkPos { x: 0, y: 0 }, last, 0);
+        }
+        let to_map = frame.transform.compute_matrix().inverse();
+        let grid = Vec2::new(frame.grid_size.x, frame.grid_size.y);
+        let corners = [
+            view.min,
+            view.max,
+            Vec2::new(view.min.x, view.max.y),
+            Vec2::new(view.max.x, view.min.y),
+        ]
+        // tile centers are at multiples of the grid size in map space
+        .map(|corner| (to_map.transform_point3(corner.extend(0.)).truncate() / grid + 0.5).floor());
+        let min = corners.into_iter().reduce(Vec2::min).unwrap();
+        let max = corners.into_iter().reduce(Vec2::max).unwrap();
+        let map_max = Vec2::new(frame.map_size.x as f32, frame.map_size.y as f32) - 1.;
+        if max.x < 0. || max.y < 0. || min.x > map_max.x || min.y > map_max.y {
+            return HashSet::default();
+        }
+        let chunk = |tile: Vec2| {
+            let tile = tile.clamp(Vec2::ZERO, map_max);
+            ChunkPos::of(&TilePos::new(tile.x as u32, tile.y as u32))
+        };
+        self.around(chunk(min), chunk(max), margin)
+    }
+}
+
+/// A tile read from the map, before it's spawned
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkTile {
+    pub tileset_index: usize,
+    pub id: u32,
+    pub flip: TileFlip,
+}
+
+/// The tiles of every tile layer in a chunk
+#[derive(Debug, Clone)]
+pub struct TileChunk {
+    #[allow(dead_code)]
+    pub pos: ChunkPos,
+    /// Layer index and tiles of each finite tile layer, row by row from the chunk's origin
+    pub layers: Vec<(usize, Vec<Option<ChunkTile>>)>,
+}
+
+impl TileChunk {
+    /// Read the chunk at `pos` out of `map`
+    pub fn read(map: &tiled::Map, pos: ChunkPos) -> Self {
+        let origin = pos.origin();
+        let mut layers = Vec::new();
+        for (layer_index, map_layer) in map_layers(map).into_iter().enumerate() {
+            let tiled::LayerType::Tiles(tiled::TileLayer::Finite(data)) =
+                map_layer.layer.layer_type()
+            else {
+                continue;
+            };
+            let mut tiles = Vec::with_capacity((CHUNK_SIZE * CHUNK_SIZE) as usize);
+            for y in origin.y..origin.y + CHUNK_SIZE {
+                for x in origin.x..origin.x + CHUNK_SIZE {
+                    if x >= map.width || y >= map.height {
+                        tiles.push(None);
+                        continue;
+                    }
+                    // Transform bevy coords into TMX coords.
+                    let (tmx_x, tmx_y) = (x as i32, (map.height - 1 - y) as i32);
+                    let tile = data
+                        .get_tile(tmx_x, tmx_y)
+                        .zip(data.get_tile_data(tmx_x, tmx_y));
+                    tiles.push(tile.map(|(tile, tile_data)| ChunkTile {
+                        tileset_index: tile.tileset_index(),
+                        id: tile.id(),
+                        flip: TileFlip {
+                            x: tile_data.flip_h,
+                            y: tile_data.flip_v,
+                            d: tile_data.flip_d,
+                        },
+                    }));
+                }
+            }
+            layers.push((layer_index, tiles));
+        }
+        Self { pos, layers }
+    }
+
+    /// The tile at `tile` of the layer `layer_index`, which must be in this chunk
+    #[allow(dead_code)]
+    pub fn tile(&self, layer_index: usize, tile: &TilePos) -> Option<ChunkTile> {
+        let (_, tiles) = self
+            .layers
+            .iter()
+            .find(|(index, _)| *index == layer_index)?;
+        tiles[self.pos.index(tile)]
+    }
+}
+
+/// The chunks read from a map, at most `capacity` of them
+#[derive(Debug)]
+pub struct ChunkCache {
+    capacity: usize,
+    chunks: HashMap<ChunkPos, TileChunk>,
+    /// Loaded chunks, least recently used first
+    recent: VecDeque<ChunkPos>,
+}
+
+impl ChunkCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            chunks: HashMap::default(),
+            recent: VecDeque::new(),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn is_loaded(&self, pos: ChunkPos) -> bool {
+        self.chunks.contains_key(&pos)
+    }
+
+    /// The chunk at `pos`, read out of `map` when it isn't loaded
+    pub fn chunk(&mut self, map: &tiled::Map, pos: ChunkPos) -> &TileChunk {
+        if let Some(index) = self.recent.iter().position(|recent| *recent == pos) {
+            self.recent.remove(index);
+        }
+        self.recent.push_back(pos);
+        self.chunks
+            .entry(pos)
+            .or_insert_with(|| TileChunk::read(map, pos))
+    }
+
+    /// The tile at `tile` of the layer `layer_index`, loading its chunk when needed
+    // no system queries tiles this way yet, only the tests
+    #[allow(dead_code)]
+    pub fn tile(
+        &mut self,
+        map: &tiled::Map,
+        layer_index: usize,
+        tile: &TilePos,
+    ) -> Option<ChunkTile> {
+        if tile.x >= map.width || tile.y >= map.height {
+            return None;
+        }
+        self.chunk(map, ChunkPos::of(tile)).tile(layer_index, tile)
+    }
+
+    /// Drop the least recently used chunks over the capacity, but the ones in `keep`. The
+    /// positions of the dropped chunks.
+    pub fn evict(&mut self, keep: &HashSet<ChunkPos>) -> Vec<ChunkPos> {
+        let mut evicted = Vec::new();
+        let mut index = 0;
+        while self.chunks.len() > self.capacity && index < self.recent.len() {
+            if keep.contains(&self.recent[index]) {
+                index += 1;
+                continue;
+            }
+            let pos = self.recent.remove(index).unwrap();
+            self.chunks.remove(&pos);
+            evicted.push(pos);
+        }
+        evicted
+    }
+}
+
+/// The streaming state of a map entity with `MapStreaming::Streaming`
+#[derive(Component, Debug)]
+pub struct StreamedChunks {
+    pub cache: ChunkCache,
+    /// Tilemap entity of each layer and tileset
+    tilemaps: HashMap<(usize, usize), Entity>,
+    /// Tiles spawned for each chunk, with their tilemap
+    spawned: HashMap<ChunkPos, Vec<(Entity, TilePos, Entity)>>,
+}
+
+impl StreamedChunks {
+    pub fn new(max_chunks: usize, tilemaps: HashMap<(usize, usize), Entity>) -> Self {
+        Self {
+            cache: ChunkCache::new(max_chunks),
+            tilemaps,
+            spawned: HashMap::default(),
+        }
+    }
+}
+
+/// Spawn the chunks around the camera view of the streamed maps and despawn the ones evicted
+pub fn stream_chunks(
+    mut commands: Commands,
+    mut views: EventReader<CameraViewChanged>,
+    maps: Res<Assets<TiledMap>>,
+    map_frame: Option<Res<MapFrame>>,
+    mut streamed: Query<(&Handle<TiledMap>, &MapStreaming, &mut StreamedChunks)>,
+    mut tilemaps: Query<(&mut TileStorage, &TilemapTexture, &TiledLayer)>,
+    mut last_view: Local<Option<Rect>>,
+) {
+    let changed = views.read().last().map(|changed| changed.view);
+    if changed.is_some() {
+        *last_view = changed;
+    }
+    let (Some(view), Some(map_frame)) = (*last_view, map_frame) else {
+        return;
+    };
+    for (handle, streaming, chunks) in &mut streamed {
+        // new maps are streamed in right away, the others when the view moves
+        if changed.is_none() && !chunks.is_added() {
+            continue;
+        }
+        let MapStreaming::Streaming { margin, .. } = *streaming else {
+            continue;
+        };
+        let Some(tiled_map) = maps.get(handle) else {
+            continue;
+        };
+        let chunks = chunks.into_inner();
+        let directory = ChunkDirectory::new(&map_frame.map_size);
+        let wanted = directory.in_view(&map_frame, view, margin);
+        for &pos in &wanted {
+            let chunk = chunks.cache.chunk(&tiled_map.map, pos);
+            if chunks.spawned.contains_key(&pos) {
+                continue;
+            }
+            let mut spawned = Vec::new();
+            for (layer_index, tiles) in &chunk.layers {
+                let origin = pos.origin();
+                for (index, tile) in tiles.iter().enumerate() {
+                    let Some(tile) = tile else {
+                        continue;
+                    };
+                    let Some(&tilemap) = chunks.tilemaps.get(&(*layer_index, tile.tileset_index))
+                    else {
+                        continue;
+                    };
+                    let Ok((mut storage, texture, layer)) = tilemaps.get_mut(tilemap) else {
+                        continue;
+                    };
+                    let index = index as u32;
+                    let tile_pos =
+                        TilePos::new(origin.x + index % CHUNK_SIZE, origin.y + index / CHUNK_SIZE);
+                    let texture_index =
+                        texture_index(tiled_map, texture, tile.tileset_index, tile.id);
+                    let entity = commands
+                        .spawn(TileBundle {
+                            position: tile_pos,
+                            tilemap_id: TilemapId(tilemap),
+                            texture_index: TileTextureIndex(texture_index),
+                            flip: tile.flip,
+                            color: TileColor(layer.tile_color),
+                            ..default()
+                        })
+                        .id();
+                    storage.set(&tile_pos, entity);
+                    spawned.push((tilemap, tile_pos, entity));
+                }
+            }
+            chunks.spawned.insert(pos, spawned);
+        }
+        for pos in chunks.cache.evict(&wanted) {
+            for (tilemap, tile_pos, entity) in chunks.spawned.remove(&pos).unwrap_or_default() {
+                if let Ok((mut storage, ..)) = tilemaps.get_mut(tilemap) {
+                    storage.remove(&tile_pos);
+                }
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::helpers::tiled::parse_tmx;
+
+    /// A map of `width` by `height` tiles, each tile's id is its TMX index
+    fn map(width: u32, height: u32) -> tiled::Map {
+        let data: Vec<String> = (0..width * height).map(|i| (i + 1).to_string()).collect();
+        let tmx = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" renderorder="right-down" width="{width}" height="{height}" tilewidth="16" tileheight="16" infinite="0" nextlayerid="2" nextobjectid="1">
+ <tileset firstgid="1" name="tiles" tilewidth="16" tileheight="16" tilecount="{count}" columns="1">
+  <image source="tiles.png" width="16" height="{image_height}"/>
+ </tileset>
+ <layer id="1" name="ground" width="{width}" height="{height}">
+  <data encoding="csv">{data}</data>
+ </layer>
+</map>"#,
+            count = width * height,
+            image_height = 16 * width * height,
+            data = data.join(","),
+        );
+        parse_tmx(tmx.as_bytes(), Path::new("streamed.tmx")).unwrap()
+    }
+
+    #[test]
+    fn a_far_tile_loads_its_chunk() {
+        let map = map(200, 150);
+        let mut cache = ChunkCache::new(2);
+
+        // bevy row 0 is the last TMX row
+        let tile = TilePos::new(130, 70);
+        let read = cache.tile(&map, 0, &tile).unwrap();
+        assert_eq!(read.id, (150 - 1 - 70) * 200 + 130);
+        assert_eq!(read.tileset_index, 0);
+        assert!(cache.is_loaded(ChunkPos { x: 2, y: 1 }));
+        assert!(!cache.is_loaded(ChunkPos { x: 0, y: 0 }));
+
+        assert!(cache.tile(&map, 0, &TilePos::new(200, 0)).is_none());
+        assert!(cache.tile(&map, 1, &TilePos::new(0, 0)).is_none());
+        // the corner chunk is cut by the map's edges
+        assert!(cache.tile(&map, 0, &TilePos::new(199, 149)).is_some());
+    }
+
+    #[test]
+    fn the_least_recently_used_chunks_go_first() {
+        let map = map(200, 150);
+        let mut cache = ChunkCache::new(2);
+        let [a, b, c] = [(0, 0), (1, 0), (2, 2)].map(|(x, y)| ChunkPos { x, y });
+        for pos in [a, b, a, c] {
+            cache.chunk(&map, pos);
+        }
+        assert_eq!(cache.evict(&HashSet::default()), [b]);
+        assert!(cache.is_loaded(a) && cache.is_loaded(c));
+
+        // the chunks in view stay even over the capacity
+        cache.chunk(&map, b);
+        let keep = HashSet::from_iter([a, b, c]);
+        assert!(cache.evict(&keep).is_empty());
+        assert!(keep.iter().all(|&pos| cache.is_loaded(pos)));
+    }
+
+    #[test]
+    fn the_view_picks_the_chunks_around_it() {
+        let frame = MapFrame {
+            transform: Transform::IDENTITY,
+            grid_size: TilemapGridSize { x: 16., y: 16. },
+            map_type: TilemapType::Square,
+            map_size: TilemapSize { x: 200, y: 150 },
+        };
+        let directory = ChunkDirectory::new(&frame.map_size);
+        assert_eq!(directory.size, UVec2::new(4, 3));
+
+        // tiles 70 to 90 across, 10 to 20 up
+        let view = Rect::new(70. * 16., 10. * 16., 90. * 16., 20. * 16.);
+        let wanted = directory.in_view(&frame, view, 0);
+        assert_eq!(wanted, HashSet::from_iter([ChunkPos { x: 1, y: 0 }]));
+        assert_eq!(directory.in_view(&frame, view, 1).len(), 6);
+
+        let outside = Rect::new(-500., -500., -100., -100.);
+        assert!(directory.in_view(&frame, outside, 1).is_empty());
+    }
+}
@@ -1 +1,2 @@
-pub mod tiled;
\ No newline at end of file
+pub mod chunks;
+pub mod tiled;
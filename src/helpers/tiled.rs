@@ -11,18 +11,21 @@
 // Functional limitations:
 //   * When the 'atlas' feature is enabled tilesets using a collection of images will be skipped.
 //   * Only finite tile layers are loaded. Infinite tile layers and object layers will be skipped.
+//   * Group layers become parent entities of the layers in them, their opacity, tint, offset and
+//     visibility are composed down to the tiles, see `map_layers`.
 
-use std::io::{Cursor, ErrorKind};
+use std::io::Cursor;
 use std::path::Path;
 use std::sync::Arc;
 
 use bevy::{
-    asset::{io::Reader, AssetLoader, AssetPath, AsyncReadExt},
+    asset::{io::Reader, AssetLoader, AsyncReadExt},
     log,
     prelude::{
-        Added, Asset, AssetApp, AssetEvent, AssetId, Assets, Bundle, Commands, Component,
-        DespawnRecursiveExt, Entity, EventReader, GlobalTransform, Handle, Image, Plugin, Query,
-        Res, Transform, Update,
+        Added, Asset, AssetApp, AssetEvent, AssetId, Assets, BuildChildren, Bundle, Color,
+        Commands, Component, DespawnRecursiveExt, Entity, EventReader, GlobalTransform, Handle,
+        Image, IntoSystemConfigs, Name, Parent, Plugin, Query, Rect, Res, Resource, SpatialBundle,
+        Transform, Update, Vec2, Vec4, Visibility, With, Without,
     },
     reflect::TypePath,
     utils::{BoxedFuture, HashMap},
@@ -31,14 +34,26 @@ use bevy_ecs_tilemap::prelude::*;
 
 use thiserror::Error;
 
+use crate::coords::{self, MapFrame};
+use crate::game::GameSet;
+use crate::helpers::chunks::{self, StreamedChunks};
+use crate::layers::{self, LayerZConfig};
+use crate::toast::Toasts;
+
 #[derive(Default)]
 pub struct TiledMapPlugin;
 
 impl Plugin for TiledMapPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app.init_asset::<TiledMap>()
+            .init_resource::<LayerZConfig>()
             .register_asset_loader(TiledLoader)
-            .add_systems(Update, process_loaded_maps);
+            .add_systems(
+                Update,
+                (process_loaded_maps, chunks::stream_chunks)
+                    .chain()
+                    .in_set(GameSet::RenderPrep),
+            );
     }
 }
 
@@ -59,10 +74,61 @@ pub struct TiledLayersStorage {
     pub storage: HashMap<u32, Entity>,
 }
 
+/// The Tiled layer and tileset the tiles of a tilemap entity come from.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct TiledLayer {
+    /// Index of the layer in `map_layers`
+    pub layer_index: usize,
+    pub tileset_index: usize,
+    /// Whether the layer is drawn over the creatures
+    pub above_entities: bool,
+    /// Color its tiles are drawn with, from the tint and opacity of the layer and its groups
+    pub tile_color: Color,
+}
+
+/// A Tiled group layer, the parent of the tilemaps of the layers in it
+#[derive(Component, Debug, Clone, Copy)]
+pub struct TiledGroupLayer;
+
+/// Id of the Tiled object an entity was spawned from.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct TiledObject {
+    pub id: u32,
+}
+
+/// How the tiles of a map entity are spawned
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MapStreaming {
+    /// All of them when the map loads
+    #[default]
+    Eager,
+    /// The chunks around the camera view, for maps too big to spawn at once
+    Streaming {
+        /// Chunks kept around the view, so walking doesn't outrun the loading
+        margin: u32,
+        /// Most chunks kept loaded, the least recently used ones go first
+        max_chunks: usize,
+    },
+}
+
+impl MapStreaming {
+    /// Streaming for maps with a true `streaming` property, eager for the others
+    pub fn from_properties(properties: &tiled::Properties) -> Self {
+        match properties.get("streaming") {
+            Some(tiled::PropertyValue::BoolValue(true)) => MapStreaming::Streaming {
+                margin: 1,
+                max_chunks: 16,
+            },
+            _ => MapStreaming::Eager,
+        }
+    }
+}
+
 #[derive(Default, Bundle)]
 pub struct TiledMapBundle {
     pub tiled_map: Handle<TiledMap>,
     pub storage: TiledLayersStorage,
+    pub streaming: MapStreaming,
     pub transform: Transform,
     pub global_transform: GlobalTransform,
 }
@@ -89,6 +155,15 @@ impl tiled::ResourceReader for BytesResourceReader {
     }
 }
 
+/// Parse the TMX map in `bytes`, `path` is only used to resolve the paths inside it.
+pub fn parse_tmx(bytes: &[u8], path: &Path) -> Result<tiled::Map, tiled::Error> {
+    let mut loader = tiled::Loader::with_cache_and_reader(
+        tiled::DefaultResourceCache::new(),
+        BytesResourceReader::new(bytes),
+    );
+    loader.load_tmx_map(path)
+}
+
 pub struct TiledLoader;
 
 #[derive(Debug, Error)]
@@ -113,13 +188,8 @@ impl AssetLoader for TiledLoader {
             let mut bytes = Vec::new();
             reader.read_to_end(&mut bytes).await?;
 
-            let mut loader = tiled::Loader::with_cache_and_reader(
-                tiled::DefaultResourceCache::new(),
-                BytesResourceReader::new(&bytes),
-            );
-            let map = loader.load_tmx_map(load_context.path()).map_err(|e| {
-                std::io::Error::new(ErrorKind::Other, format!("Could not load TMX map: {e}"))
-            })?;
+            let map = parse_tmx(&bytes, load_context.path())
+                .map_err(|e| std::io::Error::other(format!("Could not load TMX map: {e}")))?;
 
             let mut tilemap_textures = HashMap::default();
             #[cfg(not(feature = "atlas"))]
@@ -195,13 +265,59 @@ impl AssetLoader for TiledLoader {
     }
 }
 
+/// Index in `texture` of the tile `tile_id` of the tileset `tileset_index`
+#[cfg_attr(feature = "atlas", allow(unused_variables))]
+pub fn texture_index(
+    tiled_map: &TiledMap,
+    texture: &TilemapTexture,
+    tileset_index: usize,
+    tile_id: tiled::TileId,
+) -> u32 {
+    match texture {
+        TilemapTexture::Single(_) => tile_id,
+        #[cfg(not(feature = "atlas"))]
+        TilemapTexture::Vector(_) => *tiled_map
+            .tile_image_offsets
+            .get(&(tileset_index, tile_id))
+            .expect(
+                "The offset into to image vector should have been saved during the initial load.",
+            ),
+        #[cfg(not(feature = "atlas"))]
+        _ => unreachable!(),
+    }
+}
+
+/// The tile of the tileset `tileset_index` drawn with the texture index `texture`, the inverse of
+/// `texture_index`
+#[cfg_attr(feature = "atlas", allow(unused_variables))]
+pub fn texture_tile_id(tiled_map: &TiledMap, tileset_index: usize, texture: u32) -> tiled::TileId {
+    #[cfg(not(feature = "atlas"))]
+    if let Some(((_, tile_id), _)) = tiled_map
+        .tile_image_offsets
+        .iter()
+        .find(|((tileset, _), offset)| *tileset == tileset_index && **offset == texture)
+    {
+        return *tile_id;
+    }
+    texture
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn process_loaded_maps(
     mut commands: Commands,
     mut map_events: EventReader<AssetEvent<TiledMap>>,
     maps: Res<Assets<TiledMap>>,
     tile_storage_query: Query<(Entity, &TileStorage)>,
-    mut map_query: Query<(&Handle<TiledMap>, &mut TiledLayersStorage)>,
+    mut map_query: Query<(
+        Entity,
+        &Handle<TiledMap>,
+        &mut TiledLayersStorage,
+        &MapStreaming,
+    )>,
     new_maps: Query<&Handle<TiledMap>, Added<Handle<TiledMap>>>,
+    group_layers: Query<(), (With<TiledGroupLayer>, Without<Parent>)>,
+    z_config: Res<LayerZConfig>,
+    mut toasts: Toasts,
 ) {
     let mut changed_maps = Vec::<AssetId<TiledMap>>::default();
     for event in map_events.read() {
@@ -212,6 +328,7 @@ pub fn process_loaded_maps(
             }
             AssetEvent::Modified { id } => {
                 log::info!("Map changed!");
+                toasts.info("Map reloaded");
                 changed_maps.push(*id);
             }
             AssetEvent::Removed { id } => {
@@ -230,7 +347,7 @@ pub fn process_loaded_maps(
     }
 
     for changed_map in changed_maps.iter() {
-        for (map_handle, mut layer_storage) in map_query.iter_mut() {
+        for (map_entity, map_handle, mut layer_storage, streaming) in map_query.iter_mut() {
             // only deal with currently changed map
             if map_handle.id() != *changed_map {
                 continue;
@@ -243,14 +360,43 @@ pub fn process_loaded_maps(
                             commands.entity(*tile).despawn_recursive()
                         }
                     }
+                    // the nested groups go with the outer ones
+                    if group_layers.contains(*layer_entity) {
+                        commands.entity(*layer_entity).despawn_recursive();
+                    }
                     // commands.entity(*layer_entity).despawn_recursive();
                 }
 
+                let map_layers = map_layers(&tiled_map.map);
+                // hiding a group entity hides the tilemaps of all the layers in it
+                let mut groups = HashMap::default();
+                for (layer_index, map_layer) in map_layers.iter().enumerate() {
+                    if !matches!(map_layer.layer.layer_type(), tiled::LayerType::Group(_)) {
+                        continue;
+                    }
+                    let group = commands
+                        .spawn((
+                            SpatialBundle {
+                                visibility: layer_visibility(&map_layer.layer),
+                                ..Default::default()
+                            },
+                            TiledGroupLayer,
+                            Name::new(map_layer.layer.name.clone()),
+                        ))
+                        .id();
+                    if let Some(parent) = map_layer.parent.and_then(|parent| groups.get(&parent)) {
+                        commands.entity(group).set_parent(*parent);
+                    }
+                    groups.insert(layer_index, group);
+                    layer_storage.storage.insert(layer_index as u32, group);
+                }
+
                 // The TilemapBundle requires that all tile images come exclusively from a single
                 // tiled texture or from a Vec of independent per-tile images. Furthermore, all of
                 // the per-tile images must be the same size. Since Tiled allows tiles of mixed
                 // tilesets on each layer and allows differently-sized tile images in each tileset,
                 // this means we need to load each combination of tileset and layer separately.
+                let mut tilemaps = HashMap::default();
                 for (tileset_index, tileset) in tiled_map.map.tilesets().iter().enumerate() {
                     let Some(tilemap_texture) = tiled_map.tilemap_textures.get(&tileset_index)
                     else {
@@ -269,9 +415,9 @@ pub fn process_loaded_maps(
                     };
 
                     // Once materials have been created/added we need to then create the layers.
-                    for (layer_index, layer) in tiled_map.map.layers().enumerate() {
-                        let offset_x = layer.offset_x;
-                        let offset_y = layer.offset_y;
+                    for (layer_index, map_layer) in map_layers.iter().enumerate() {
+                        let layer = map_layer.layer;
+                        let style = map_layer.style;
 
                         let tiled::LayerType::Tiles(tile_layer) = layer.layer_type() else {
                             log::info!(
@@ -299,23 +445,19 @@ pub fn process_loaded_maps(
                             y: tiled_map.map.tile_height as f32,
                         };
 
-                        let map_type = match tiled_map.map.orientation {
-                            tiled::Orientation::Hexagonal => {
-                                TilemapType::Hexagon(HexCoordSystem::Row)
-                            }
-                            tiled::Orientation::Isometric => {
-                                TilemapType::Isometric(IsoCoordSystem::Diamond)
-                            }
-                            tiled::Orientation::Staggered => {
-                                TilemapType::Isometric(IsoCoordSystem::Staggered)
-                            }
-                            tiled::Orientation::Orthogonal => TilemapType::Square,
-                        };
+                        let map_type = tilemap_type(tiled_map.map.orientation);
+                        let above_entities = layers::above_entities(&layer);
+                        let layer_z = z_config.layer_z(layer_index, above_entities);
 
                         let mut tile_storage = TileStorage::empty(map_size);
                         let layer_entity = commands.spawn_empty().id();
 
-                        for x in 0..map_size.x {
+                        // streamed maps spawn their tiles a chunk at a time, see `chunks`
+                        let eager_tiles = match streaming {
+                            MapStreaming::Eager => map_size.x,
+                            MapStreaming::Streaming { .. } => 0,
+                        };
+                        for x in 0..eager_tiles {
                             for y in 0..map_size.y {
                                 // Transform TMX coords into bevy coords.
                                 let mapped_y = tiled_map.map.height - 1 - y;
@@ -340,15 +482,12 @@ pub fn process_loaded_maps(
                                         }
                                     };
 
-                                let texture_index = match tilemap_texture {
-                                    TilemapTexture::Single(_) => layer_tile.id(),
-                                    #[cfg(not(feature = "atlas"))]
-                                    TilemapTexture::Vector(_) =>
-                                        *tiled_map.tile_image_offsets.get(&(tileset_index, layer_tile.id()))
-                                        .expect("The offset into to image vector should have been saved during the initial load."),
-                                    #[cfg(not(feature = "atlas"))]
-                                    _ => unreachable!()
-                                };
+                                let texture_index = texture_index(
+                                    tiled_map,
+                                    tilemap_texture,
+                                    tileset_index,
+                                    layer_tile.id(),
+                                );
 
                                 let tile_pos = TilePos { x, y };
                                 let tile_entity = commands
@@ -361,6 +500,7 @@ pub fn process_loaded_maps(
                                             y: layer_tile_data.flip_v,
                                             d: layer_tile_data.flip_d,
                                         },
+                                        color: TileColor(style.tile_color()),
                                         ..Default::default()
                                     })
                                     .id();
@@ -368,24 +508,718 @@ pub fn process_loaded_maps(
                             }
                         }
 
-                        commands.entity(layer_entity).insert(TilemapBundle {
-                            grid_size,
-                            size: map_size,
-                            storage: tile_storage,
-                            texture: tilemap_texture.clone(),
-                            tile_size,
-                            spacing: tile_spacing,
-                            transform: Transform::from_xyz(offset_x, offset_y, (layer_index as f32) * 0.1),
-                            map_type,
-                            ..Default::default()
-                        });
+                        commands.entity(layer_entity).insert((
+                            TilemapBundle {
+                                grid_size,
+                                size: map_size,
+                                storage: tile_storage,
+                                texture: tilemap_texture.clone(),
+                                tile_size,
+                                spacing: tile_spacing,
+                                transform: Transform::from_xyz(
+                                    style.offset.x,
+                                    style.offset.y,
+                                    layer_z,
+                                ),
+                                map_type,
+                                visibility: layer_visibility(&layer),
+                                ..Default::default()
+                            },
+                            TiledLayer {
+                                layer_index,
+                                tileset_index,
+                                above_entities,
+                                tile_color: style.tile_color(),
+                            },
+                            Name::new(layer.name.clone()),
+                        ));
+                        let group = map_layer.parent.and_then(|parent| groups.get(&parent));
+                        if let Some(group) = group {
+                            commands.entity(layer_entity).set_parent(*group);
+                        }
 
                         layer_storage
                             .storage
                             .insert(layer_index as u32, layer_entity);
+                        tilemaps.insert((layer_index, tileset_index), layer_entity);
                     }
                 }
+                if let MapStreaming::Streaming { max_chunks, .. } = *streaming {
+                    commands
+                        .entity(map_entity)
+                        .insert(StreamedChunks::new(max_chunks, tilemaps));
+                }
             }
         }
     }
 }
+
+/// The bevy_ecs_tilemap map type matching a Tiled map orientation.
+pub fn tilemap_type(orientation: tiled::Orientation) -> TilemapType {
+    match orientation {
+        tiled::Orientation::Hexagonal => TilemapType::Hexagon(HexCoordSystem::Row),
+        tiled::Orientation::Isometric => TilemapType::Isometric(IsoCoordSystem::Diamond),
+        tiled::Orientation::Staggered => TilemapType::Isometric(IsoCoordSystem::Staggered),
+        tiled::Orientation::Orthogonal => TilemapType::Square,
+    }
+}
+
+/// How a layer is drawn, composed down the group layers it's in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayerStyle {
+    pub opacity: f32,
+    /// Color the tiles are multiplied with, white leaves them as they are
+    pub tint: Color,
+    /// Offset of the layer, in pixels
+    pub offset: Vec2,
+    pub visible: bool,
+}
+
+impl Default for LayerStyle {
+    fn default() -> Self {
+        Self {
+            opacity: 1.,
+            tint: Color::WHITE,
+            offset: Vec2::ZERO,
+            visible: true,
+        }
+    }
+}
+
+impl LayerStyle {
+    /// The settings of `layer` itself
+    pub fn of(layer: &tiled::Layer) -> Self {
+        Self {
+            opacity: layer.opacity,
+            tint: layer.tint_color.map_or(Color::WHITE, |tint| {
+                Color::rgba_u8(tint.red, tint.green, tint.blue, tint.alpha)
+            }),
+            offset: Vec2::new(layer.offset_x, layer.offset_y),
+            visible: layer.visible,
+        }
+    }
+
+    /// The style of a layer drawn with `self` inside a group drawn with `group`
+    pub fn within(self, group: LayerStyle) -> Self {
+        let tint = Vec4::from(self.tint.as_rgba_f32()) * Vec4::from(group.tint.as_rgba_f32());
+        Self {
+            opacity: self.opacity * group.opacity,
+            tint: Color::rgba(tint.x, tint.y, tint.z, tint.w),
+            offset: self.offset + group.offset,
+            visible: self.visible && group.visible,
+        }
+    }
+
+    /// Color the tiles of the layer are drawn with
+    pub fn tile_color(&self) -> Color {
+        self.tint.with_a(self.tint.a() * self.opacity)
+    }
+}
+
+/// A layer of a map and where it sits in the group layers
+#[derive(Debug, Clone, Copy)]
+pub struct MapLayer<'map> {
+    pub layer: tiled::Layer<'map>,
+    /// Its own style composed with the ones of the groups it's in
+    pub style: LayerStyle,
+    /// Layer index of the group it's directly in
+    pub parent: Option<usize>,
+}
+
+/// Every layer of `map`, the ones in group layers included, depth first in drawing order
+///
+/// The position of a layer in the list is its layer index. A map without group layers lists
+/// the same layers as `map.layers()`.
+pub fn map_layers(map: &tiled::Map) -> Vec<MapLayer<'_>> {
+    let mut layers = Vec::new();
+    for layer in map.layers() {
+        push_layer(&mut layers, layer, LayerStyle::default(), None);
+    }
+    layers
+}
+
+fn push_layer<'map>(
+    layers: &mut Vec<MapLayer<'map>>,
+    layer: tiled::Layer<'map>,
+    group: LayerStyle,
+    parent: Option<usize>,
+) {
+    let style = LayerStyle::of(&layer).within(group);
+    let index = layers.len();
+    layers.push(MapLayer {
+        layer,
+        style,
+        parent,
+    });
+    if let tiled::LayerType::Group(group_layer) = layer.layer_type() {
+        for child in group_layer.layers() {
+            push_layer(layers, child, style, Some(index));
+        }
+    }
+}
+
+/// Every layer of `map`, the ones in group layers included, in the order of `map_layers`
+pub fn all_layers(map: &tiled::Map) -> impl Iterator<Item = tiled::Layer<'_>> {
+    map_layers(map).into_iter().map(|map_layer| map_layer.layer)
+}
+
+/// The visibility of the entity of `layer`, hidden or following its group
+fn layer_visibility(layer: &tiled::Layer) -> Visibility {
+    if layer.visible {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    }
+}
+
+/// The object with the given id, from any object layer of `map`.
+pub fn object_by_id(map: &tiled::Map, id: u32) -> Option<tiled::Object<'_>> {
+    all_layers(map)
+        .filter_map(|layer| match layer.layer_type() {
+            tiled::LayerType::Objects(layer) => Some(layer),
+            _ => None,
+        })
+        .find_map(|layer| layer.objects().find(|object| object.id() == id))
+}
+
+/// The Wang sets of every tileset of `map`, with their tiles sorted by id.
+pub fn wang_sets(map: &tiled::Map) -> crate::terrain::WangSets {
+    let mut sets = Vec::new();
+    for (tileset_index, tileset) in map.tilesets().iter().enumerate() {
+        for wang_set in &tileset.wang_sets {
+            let mut tiles: Vec<(u32, [u8; 8])> = wang_set
+                .wang_tiles
+                .iter()
+                .map(|(tile_id, wang_tile)| (*tile_id, wang_tile.wang_id.0))
+                .collect();
+            tiles.sort_by_key(|(tile_id, _)| *tile_id);
+            sets.push(crate::terrain::WangSet {
+                name: wang_set.name.clone(),
+                tileset: tileset_index,
+                kind: match wang_set.wang_set_type {
+                    tiled::WangSetType::Corner => crate::terrain::WangKind::Corner,
+                    tiled::WangSetType::Edge => crate::terrain::WangKind::Edge,
+                    tiled::WangSetType::Mixed => crate::terrain::WangKind::Mixed,
+                },
+                tiles,
+            });
+        }
+    }
+    crate::terrain::WangSets(sets)
+}
+
+/// The tile found on a single Tiled layer at some map position.
+pub struct LayerTileInfo {
+    pub layer_index: usize,
+    pub layer_name: String,
+    pub texture_index: u32,
+    pub properties: tiled::Properties,
+}
+
+/// Look up the tile on every visible, finite tile layer at `tile_pos`.
+///
+/// `tile_pos` is in bevy coordinates (y up), the same as the spawned `TilePos` components. The
+/// texture index matches what `process_loaded_maps` assigns with the `atlas` feature enabled.
+pub fn tiles_at(map: &tiled::Map, tile_pos: &TilePos) -> Vec<LayerTileInfo> {
+    if tile_pos.x >= map.width || tile_pos.y >= map.height {
+        return Vec::new();
+    }
+
+    // Transform bevy coords into TMX coords.
+    let mapped_x = tile_pos.x as i32;
+    let mapped_y = (map.height - 1 - tile_pos.y) as i32;
+
+    map_layers(map)
+        .into_iter()
+        .enumerate()
+        .filter(|(_, map_layer)| map_layer.style.visible)
+        .filter_map(|(layer_index, MapLayer { layer, .. })| {
+            let tiled::LayerType::Tiles(tiled::TileLayer::Finite(layer_data)) = layer.layer_type()
+            else {
+                return None;
+            };
+            let layer_tile = layer_data.get_tile(mapped_x, mapped_y)?;
+            let properties = layer_tile
+                .get_tile()
+                .map(|tile| tile.properties.clone())
+                .unwrap_or_default();
+            Some(LayerTileInfo {
+                layer_index,
+                layer_name: layer.name.clone(),
+                texture_index: layer_tile.id(),
+                properties,
+            })
+        })
+        .collect()
+}
+
+/// A collision shape of a tileset tile, in pixels from the top left corner of the tile (y down)
+#[derive(Debug, Clone, PartialEq)]
+pub enum LocalShape {
+    Rect { min: Vec2, max: Vec2 },
+    Polygon(Vec<Vec2>),
+}
+
+/// A tile collision shape in world space, polygons wound counter-clockwise
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorldShape {
+    Rect(Rect),
+    Polygon(Vec<Vec2>),
+}
+
+/// The collision shapes of a tileset tile, from the object group Tiled's collision editor saves on
+/// it. Ellipses are kept as their bounds; polylines and points enclose nothing and are skipped,
+/// and so is the rotation of an object.
+pub fn tile_shapes(tile: &tiled::TileData) -> Vec<LocalShape> {
+    let Some(collision) = &tile.collision else {
+        return Vec::new();
+    };
+    collision
+        .object_data()
+        .iter()
+        .filter_map(|object| {
+            let origin = Vec2::new(object.x, object.y);
+            match &object.shape {
+                tiled::ObjectShape::Rect { width, height }
+                | tiled::ObjectShape::Ellipse { width, height } => Some(LocalShape::Rect {
+                    min: origin,
+                    max: origin + Vec2::new(*width, *height),
+                }),
+                tiled::ObjectShape::Polygon { points } => Some(LocalShape::Polygon(
+                    points
+                        .iter()
+                        .map(|(x, y)| origin + Vec2::new(*x, *y))
+                        .collect(),
+                )),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Flip a point of a `size` tile the way Tiled flips the tile: the diagonal flip swaps the axes
+/// first, then the horizontal and the vertical flips mirror them
+pub fn flip_point(point: Vec2, size: Vec2, flip: TileFlip) -> Vec2 {
+    let (mut point, size) = if flip.d {
+        (Vec2::new(point.y, point.x), Vec2::new(size.y, size.x))
+    } else {
+        (point, size)
+    };
+    if flip.x {
+        point.x = size.x - point.x;
+    }
+    if flip.y {
+        point.y = size.y - point.y;
+    }
+    point
+}
+
+/// Twice the signed area of `points`, positive when they go counter-clockwise with y up
+fn signed_area(points: &[Vec2]) -> f32 {
+    points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(a, b)| a.perp_dot(*b))
+        .sum()
+}
+
+impl LocalShape {
+    /// The shape on a flipped tile of `size` pixels, centered on `center` in world space with
+    /// `scale` world units per pixel
+    pub fn to_world(&self, size: Vec2, flip: TileFlip, center: Vec2, scale: Vec2) -> WorldShape {
+        // the tile is flipped as Tiled draws it, y down, then turned y up around its center
+        let place = |point: Vec2| {
+            let flipped = flip_point(point, size, flip);
+            center + Vec2::new(flipped.x - size.x / 2., size.y / 2. - flipped.y) * scale
+        };
+        match self {
+            LocalShape::Rect { min, max } => {
+                WorldShape::Rect(Rect::from_corners(place(*min), place(*max)))
+            }
+            LocalShape::Polygon(points) => {
+                let mut points: Vec<_> = points.iter().map(|point| place(*point)).collect();
+                // every mirror, including y down to y up, reverses the winding
+                if signed_area(&points) < 0. {
+                    points.reverse();
+                }
+                WorldShape::Polygon(points)
+            }
+        }
+    }
+}
+
+/// One tile with collision shapes placed on a map cell
+#[derive(Debug, Clone, Copy)]
+struct PlacedCollider {
+    layer_index: usize,
+    tileset_index: usize,
+    texture_index: u32,
+    flip: TileFlip,
+}
+
+/// The collision shapes of the tiles of the current map, for finer collision than the
+/// `CollisionMap`'s whole blocked tiles
+#[derive(Resource, Debug, Default)]
+pub struct TileColliders {
+    /// Shapes of the tiles that have some, by tileset index and texture index
+    shapes: HashMap<(usize, u32), Vec<LocalShape>>,
+    /// The tiles with shapes on each cell, over the visible finite tile layers
+    cells: HashMap<TilePos, Vec<PlacedCollider>>,
+    /// Tile size of each tileset, in pixels
+    tile_sizes: Vec<Vec2>,
+}
+
+impl TileColliders {
+    pub fn from_map(map: &tiled::Map) -> Self {
+        let mut shapes = HashMap::default();
+        for (tileset_index, tileset) in map.tilesets().iter().enumerate() {
+            for (texture_index, tile) in tileset.tiles() {
+                let tile_shapes = tile_shapes(&tile);
+                if !tile_shapes.is_empty() {
+                    shapes.insert((tileset_index, texture_index), tile_shapes);
+                }
+            }
+        }
+        let tile_sizes = map
+            .tilesets()
+            .iter()
+            .map(|tileset| Vec2::new(tileset.tile_width as f32, tileset.tile_height as f32))
+            .collect();
+
+        let mut cells: HashMap<TilePos, Vec<PlacedCollider>> = HashMap::default();
+        for (layer_index, map_layer) in map_layers(map).into_iter().enumerate() {
+            let MapLayer { layer, style, .. } = map_layer;
+            if !style.visible {
+                continue;
+            }
+            let tiled::LayerType::Tiles(tiled::TileLayer::Finite(layer_data)) = layer.layer_type()
+            else {
+                continue;
+            };
+            for x in 0..map.width {
+                for y in 0..map.height {
+                    let Some(layer_tile) = layer_data.get_tile(x as i32, y as i32) else {
+                        continue;
+                    };
+                    let key = (layer_tile.tileset_index(), layer_tile.id());
+                    if !shapes.contains_key(&key) {
+                        continue;
+                    }
+                    // TMX rows go down, bevy's go up
+                    cells
+                        .entry(TilePos::new(x, map.height - 1 - y))
+                        .or_default()
+                        .push(PlacedCollider {
+                            layer_index,
+                            tileset_index: key.0,
+                            texture_index: key.1,
+                            flip: TileFlip {
+                                x: layer_tile.flip_h,
+                                y: layer_tile.flip_v,
+                                d: layer_tile.flip_d,
+                            },
+                        });
+                }
+            }
+        }
+
+        Self {
+            shapes,
+            cells,
+            tile_sizes,
+        }
+    }
+
+    /// The local shapes of a tileset tile
+    pub fn shapes(&self, tileset_index: usize, texture_index: u32) -> &[LocalShape] {
+        self.shapes
+            .get(&(tileset_index, texture_index))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// The world shapes of the tiles on `tile_pos`, over all the tile layers, flipped like the
+    /// tiles are
+    pub fn colliders_for_tile<'a>(
+        &'a self,
+        frame: &'a MapFrame,
+        tile_pos: TilePos,
+    ) -> impl Iterator<Item = WorldShape> + 'a {
+        let center = coords::tile_to_world_center(frame, &tile_pos);
+        let scale = frame.transform.scale.truncate();
+        let placed = center
+            .and_then(|_| self.cells.get(&tile_pos))
+            .map_or(&[][..], Vec::as_slice);
+        placed.iter().flat_map(move |placed| {
+            let size = self.tile_sizes[placed.tileset_index];
+            let center = center.unwrap_or_default();
+            self.shapes(placed.tileset_index, placed.texture_index)
+                .iter()
+                .map(move |shape| shape.to_world(size, placed.flip, center, scale))
+        })
+    }
+
+    /// The layers with a collider on `tile_pos`
+    pub fn layers_at(&self, tile_pos: TilePos) -> impl Iterator<Item = usize> + '_ {
+        self.cells
+            .get(&tile_pos)
+            .into_iter()
+            .flatten()
+            .map(|placed| placed.layer_index)
+    }
+}
+
+/// Load a map from `path` like `TiledLoader` does, with placeholder tileset images
+#[cfg(test)]
+pub fn load_test_map(path: &str) -> TiledMap {
+    let source = std::fs::read(path).unwrap();
+    let map = parse_tmx(&source, Path::new(path)).unwrap();
+    let mut tilemap_textures = HashMap::default();
+    for (tileset_index, tileset) in map.tilesets().iter().enumerate() {
+        if tileset.image.is_some() {
+            tilemap_textures.insert(tileset_index, TilemapTexture::Single(Handle::default()));
+        }
+    }
+    TiledMap {
+        map,
+        tilemap_textures,
+        #[cfg(not(feature = "atlas"))]
+        tile_image_offsets: HashMap::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::Transform;
+
+    use super::*;
+
+    const TILE: Vec2 = Vec2::splat(24.);
+
+    fn colliders() -> (TileColliders, MapFrame) {
+        let map = load_test_map("assets/maps/TMX/map_test_tile_colliders.tmx").map;
+        let frame = MapFrame {
+            transform: Transform::IDENTITY,
+            grid_size: TilemapGridSize { x: 24., y: 24. },
+            map_type: TilemapType::Square,
+            map_size: TilemapSize { x: 3, y: 2 },
+        };
+        (TileColliders::from_map(&map), frame)
+    }
+
+    /// Check `shape` is a counter-clockwise polygon through `expected`, from any start
+    fn assert_polygon(shape: &WorldShape, expected: &[Vec2]) {
+        let WorldShape::Polygon(points) = shape else {
+            panic!("{shape:?} isn't a polygon");
+        };
+        assert!(
+            signed_area(points) > 0.,
+            "{points:?} isn't counter-clockwise"
+        );
+        assert_eq!(points.len(), expected.len());
+        for point in expected {
+            assert!(points.contains(point), "{point} missing from {points:?}");
+        }
+    }
+
+    #[test]
+    fn tile_shapes_are_parsed_from_the_tileset() {
+        let (colliders, _) = colliders();
+        assert_eq!(
+            colliders.shapes(0, 4),
+            [LocalShape::Rect {
+                min: Vec2::ZERO,
+                max: Vec2::new(24., 12.)
+            }]
+        );
+        assert_eq!(
+            colliders.shapes(0, 5),
+            [LocalShape::Polygon(vec![
+                Vec2::ZERO,
+                Vec2::new(24., 0.),
+                Vec2::new(0., 24.)
+            ])]
+        );
+        assert!(colliders.shapes(0, 0).is_empty());
+    }
+
+    #[test]
+    fn flips_follow_tiled_order() {
+        let point = Vec2::new(3., 10.);
+        let flip = |x, y, d| flip_point(point, TILE, TileFlip { x, y, d });
+        assert_eq!(flip(false, false, false), point);
+        assert_eq!(flip(true, false, false), Vec2::new(21., 10.));
+        assert_eq!(flip(false, true, false), Vec2::new(3., 14.));
+        // the axes are swapped before mirroring
+        assert_eq!(flip(false, false, true), Vec2::new(10., 3.));
+        assert_eq!(flip(true, false, true), Vec2::new(14., 3.));
+        assert_eq!(flip(true, true, true), Vec2::new(14., 21.));
+    }
+
+    #[test]
+    fn polygons_stay_counter_clockwise_under_every_flip() {
+        let triangle =
+            LocalShape::Polygon(vec![Vec2::ZERO, Vec2::new(24., 0.), Vec2::new(0., 24.)]);
+        for bits in 0..8 {
+            let flip = TileFlip {
+                x: bits & 1 != 0,
+                y: bits & 2 != 0,
+                d: bits & 4 != 0,
+            };
+            let WorldShape::Polygon(points) = triangle.to_world(TILE, flip, Vec2::ZERO, Vec2::ONE)
+            else {
+                panic!("not a polygon");
+            };
+            assert!(
+                signed_area(&points) > 0.,
+                "{flip:?} winds {points:?} clockwise"
+            );
+        }
+    }
+
+    #[test]
+    fn colliders_are_placed_on_their_tiles() {
+        let (colliders, frame) = colliders();
+
+        let shapes: Vec<_> = colliders
+            .colliders_for_tile(&frame, TilePos::new(0, 1))
+            .collect();
+        assert_eq!(shapes, [WorldShape::Rect(Rect::new(-12., 24., 12., 36.))]);
+
+        let shapes: Vec<_> = colliders
+            .colliders_for_tile(&frame, TilePos::new(1, 1))
+            .collect();
+        assert_polygon(
+            &shapes[0],
+            &[
+                Vec2::new(12., 36.),
+                Vec2::new(36., 36.),
+                Vec2::new(12., 12.),
+            ],
+        );
+
+        // flipped horizontally, the corner moves to the right
+        let shapes: Vec<_> = colliders
+            .colliders_for_tile(&frame, TilePos::new(2, 1))
+            .collect();
+        assert_polygon(
+            &shapes[0],
+            &[
+                Vec2::new(60., 36.),
+                Vec2::new(36., 36.),
+                Vec2::new(60., 12.),
+            ],
+        );
+
+        // flipped vertically on the ground, with the decor's rect over it
+        let shapes: Vec<_> = colliders
+            .colliders_for_tile(&frame, TilePos::new(1, 0))
+            .collect();
+        assert_eq!(shapes.len(), 2);
+        assert_polygon(
+            &shapes[0],
+            &[
+                Vec2::new(12., -12.),
+                Vec2::new(36., -12.),
+                Vec2::new(12., 12.),
+            ],
+        );
+        assert_eq!(shapes[1], WorldShape::Rect(Rect::new(12., 0., 36., 12.)));
+        assert_eq!(
+            colliders.layers_at(TilePos::new(1, 0)).collect::<Vec<_>>(),
+            [0, 1]
+        );
+
+        assert_eq!(
+            colliders
+                .colliders_for_tile(&frame, TilePos::new(2, 0))
+                .count(),
+            0
+        );
+        assert_eq!(
+            colliders
+                .colliders_for_tile(&frame, TilePos::new(5, 5))
+                .count(),
+            0
+        );
+    }
+
+    const GROUPS_MAP: &str = "assets/maps/TMX/map_test_groups.tmx";
+
+    fn assert_color(color: Color, expected: [f32; 4]) {
+        let rgba = color.as_rgba_f32();
+        for (channel, expected) in rgba.iter().zip(expected) {
+            assert!(
+                (channel - expected).abs() < 0.01,
+                "{rgba:?} isn't {expected:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn group_layers_are_walked_depth_first() {
+        let map = load_test_map(GROUPS_MAP).map;
+        let layers = map_layers(&map);
+        let names: Vec<_> = layers
+            .iter()
+            .map(|map_layer| map_layer.layer.name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "ground",
+                "forest",
+                "trees",
+                "grove",
+                "mushrooms",
+                "grove objects",
+                "roof"
+            ]
+        );
+        let parents: Vec<_> = layers.iter().map(|map_layer| map_layer.parent).collect();
+        assert_eq!(
+            parents,
+            [None, None, Some(1), Some(1), Some(3), Some(3), None]
+        );
+
+        // the objects of nested object layers are found too
+        let hermit = object_by_id(&map, 1).unwrap();
+        assert_eq!(hermit.name, "hermit");
+    }
+
+    #[test]
+    fn group_settings_compose_down_the_tree() {
+        let map = load_test_map(GROUPS_MAP).map;
+        let layers = map_layers(&map);
+        let half = 128. / 255.;
+
+        assert_eq!(layers[0].style, LayerStyle::default());
+
+        let trees = layers[2].style;
+        assert_eq!(trees.opacity, 0.25);
+        assert_eq!(trees.offset, Vec2::new(5., 3.));
+        assert!(trees.visible);
+        assert_color(trees.tint, [1., half, half, 1.]);
+        assert_color(trees.tile_color(), [1., half, half, 0.25]);
+
+        // hidden with its group, the tints multiply
+        let mushrooms = layers[4].style;
+        assert_eq!(mushrooms.opacity, 0.5);
+        assert_eq!(mushrooms.offset, Vec2::new(4., 5.));
+        assert!(!mushrooms.visible);
+        assert!(!layers[5].style.visible);
+        assert_color(mushrooms.tint, [half, half * half, half * half, 1.]);
+
+        assert_eq!(layers[6].style, LayerStyle::default());
+    }
+
+    #[test]
+    fn hidden_groups_hide_their_tiles_from_lookups() {
+        let map = load_test_map(GROUPS_MAP).map;
+        // the trees show, the mushrooms under them are in the hidden grove
+        let names: Vec<_> = tiles_at(&map, &TilePos::new(1, 2))
+            .into_iter()
+            .map(|info| (info.layer_name, info.layer_index))
+            .collect();
+        assert_eq!(names, [("ground".to_string(), 0), ("trees".to_string(), 2)]);
+    }
+}
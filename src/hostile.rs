@@ -0,0 +1,507 @@
+//! Creatures that chase and attack the player, see `Hostile`.
+//!
+//! The `aggro_radius` property of a creature, on its object or its template, makes it hostile.
+//! Every `HostileSettings::turn_secs` the hostile creatures take a turn. One that sees the player
+//! within its radius, through the field of view of `fov`, steps toward it with `chase_step`, or
+//! attacks it once next to it; the others now and then wander to a random open neighbour. Steps
+//! and attacks are `MoveRequested`s like the player's, so collisions, the other creatures and the
+//! diagonal rule hold for them too.
+//!
+//! A creature starting a chase sends `AggroGained`, one losing sight of the player `AggroLost`.
+
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::ambient_life::next_random;
+use crate::audio::SpatialAudio;
+use crate::collision::CollisionMap;
+use crate::coords::{self, MapFrame};
+use crate::fov;
+use crate::game::GameSet;
+use crate::groups::GroupId;
+use crate::map::Npc;
+use crate::movement::{MoveRequested, MovementSettings, StepCooldown};
+use crate::pathfinding::DiagonalRule;
+use crate::player::MainPlayer;
+use crate::state::level_ready;
+
+/// Name of the creature property making it hostile
+pub const AGGRO_RADIUS_PROPERTY: &str = "aggro_radius";
+
+/// The eight steps a creature can take
+const DIRS: [IVec2; 8] = [
+    IVec2::new(1, 0),
+    IVec2::new(0, 1),
+    IVec2::new(-1, 0),
+    IVec2::new(0, -1),
+    IVec2::new(1, 1),
+    IVec2::new(-1, 1),
+    IVec2::new(-1, -1),
+    IVec2::new(1, -1),
+];
+
+/// Plugin that has the hostile creatures chase the player
+#[derive(Default)]
+pub struct HostilePlugin;
+
+impl Plugin for HostilePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HostileSettings>()
+            .register_type::<HostileSettings>()
+            .add_event::<AggroGained>()
+            .add_event::<AggroLost>()
+            .add_systems(
+                Update,
+                (take_hostile_turns, play_aggro_sound)
+                    .chain()
+                    .in_set(GameSet::Input)
+                    .run_if(level_ready)
+                    .run_if(crate::debug_step::gameplay_running)
+                    .run_if(not(crate::editor::editing)),
+            );
+    }
+}
+
+#[derive(Resource, Reflect, Debug, Clone, PartialEq)]
+#[reflect(Resource)]
+pub struct HostileSettings {
+    /// Seconds between the turns of the hostile creatures
+    pub turn_secs: f32,
+    /// Chance that a creature not chasing the player steps somewhere on its turn
+    pub wander_chance: f32,
+    /// Audio asset played when a creature spots the player, none when empty
+    pub aggro_sound: String,
+}
+
+impl Default for HostileSettings {
+    fn default() -> Self {
+        Self {
+            turn_secs: 0.4,
+            wander_chance: 0.3,
+            aggro_sound: String::new(),
+        }
+    }
+}
+
+/// A creature chasing the player once it sees it within `aggro_radius` tiles
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hostile {
+    pub aggro_radius: u32,
+}
+
+impl Hostile {
+    /// The hostility of a creature with an `aggro_radius` property
+    pub fn from_properties(properties: &tiled::Properties) -> Option<Self> {
+        match properties.get(AGGRO_RADIUS_PROPERTY) {
+            Some(tiled::PropertyValue::IntValue(radius)) if *radius >= 0 => Some(Self {
+                aggro_radius: *radius as u32,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The hostile creature is chasing the player
+#[derive(Component, Debug, Default)]
+pub struct Aggroed;
+
+/// Sent when `npc` spots the player and starts chasing it
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AggroGained {
+    pub npc: Entity,
+}
+
+/// Sent when `npc` lost sight of the player and gives up the chase
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AggroLost {
+    pub npc: Entity,
+}
+
+/// What a creature chasing the player does on its turn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChaseStep {
+    /// Step by the direction, toward the player
+    Step(IVec2),
+    /// Attack the player next to it, in the direction
+    Attack(IVec2),
+    /// No step brings it closer
+    Stuck,
+}
+
+/// The neighbour of `tile` by `dir`, none off the low edges of the map
+fn neighbour(tile: TilePos, dir: IVec2) -> Option<TilePos> {
+    let pos = IVec2::new(tile.x as i32, tile.y as i32) + dir;
+    Some(TilePos::new(
+        u32::try_from(pos.x).ok()?,
+        u32::try_from(pos.y).ok()?,
+    ))
+}
+
+/// Steps from `a` to `b` as far as `diagonal` allows, ignoring what's in the way
+fn steps_between(a: TilePos, b: TilePos, diagonal: DiagonalRule) -> u32 {
+    match diagonal {
+        DiagonalRule::Disabled => a.x.abs_diff(b.x) + a.y.abs_diff(b.y),
+        _ => coords::tile_distance(&a, &b),
+    }
+}
+
+/// The turn of a creature on `npc` chasing the player on `player`, `open` tells whether a tile can
+/// be walked on
+///
+/// The creature attacks when the player is a step away, otherwise it takes the step that leaves
+/// the fewest steps to go, the straightest one between equals.
+pub fn chase_step(
+    npc: TilePos,
+    player: TilePos,
+    diagonal: DiagonalRule,
+    open: impl Fn(TilePos) -> bool,
+) -> ChaseStep {
+    let dir = IVec2::new(player.x as i32, player.y as i32) - IVec2::new(npc.x as i32, npc.y as i32);
+    if steps_between(npc, player, diagonal) == 1 && diagonal.allows_with(&npc, &player, &open) {
+        return ChaseStep::Attack(dir);
+    }
+    // steps left, then squared straight-line distance
+    let score = |tile: TilePos| {
+        let offset =
+            IVec2::new(player.x as i32, player.y as i32) - IVec2::new(tile.x as i32, tile.y as i32);
+        (
+            steps_between(tile, player, diagonal),
+            offset.length_squared(),
+        )
+    };
+    DIRS.iter()
+        .filter_map(|dir| Some((*dir, neighbour(npc, *dir)?)))
+        .filter(|(_, to)| open(*to) && diagonal.allows_with(&npc, to, &open))
+        .map(|(dir, to)| (score(to), dir))
+        .filter(|(to_go, _)| *to_go < score(npc))
+        .min_by_key(|(to_go, _)| *to_go)
+        .map_or(ChaseStep::Stuck, |(_, dir)| ChaseStep::Step(dir))
+}
+
+/// Whether a creature on `npc` sees `player` within `radius` tiles, `blocks` tells whether a
+/// tile blocks sight
+pub fn sees_player(
+    npc: TilePos,
+    player: TilePos,
+    radius: u32,
+    blocks: impl Fn(TilePos) -> bool,
+) -> bool {
+    coords::tile_distance(&npc, &player) <= radius
+        && fov::compute_fov(npc, radius, blocks).contains(&player)
+}
+
+/// A step to an open neighbour of `tile`, picked by `roll` in `0..1`
+fn wander_step(
+    tile: TilePos,
+    diagonal: DiagonalRule,
+    open: impl Fn(TilePos) -> bool,
+    roll: f32,
+) -> Option<IVec2> {
+    let dirs: Vec<IVec2> = DIRS
+        .iter()
+        .copied()
+        .filter(|dir| {
+            neighbour(tile, *dir)
+                .is_some_and(|to| open(to) && diagonal.allows_with(&tile, &to, &open))
+        })
+        .collect();
+    let index = (roll * dirs.len() as f32) as usize;
+    dirs.get(index.min(dirs.len().saturating_sub(1))).copied()
+}
+
+/// Have the hostile creatures that aren't walking chase the player or wander, once a turn
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn take_hostile_turns(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<HostileSettings>,
+    movement: Res<MovementSettings>,
+    collision_map: Res<CollisionMap>,
+    map_frame: Res<MapFrame>,
+    hostiles: Query<(
+        Entity,
+        &Hostile,
+        &Transform,
+        Option<&StepCooldown>,
+        Has<Aggroed>,
+        Has<GroupId>,
+    )>,
+    players: Query<&Transform, With<MainPlayer>>,
+    creatures: Query<&Transform, Or<(With<MainPlayer>, With<Npc>)>>,
+    mut requests: EventWriter<MoveRequested>,
+    mut gained: EventWriter<AggroGained>,
+    mut lost: EventWriter<AggroLost>,
+    mut elapsed: Local<f32>,
+    mut rng: Local<u32>,
+) {
+    *elapsed += time.delta_seconds();
+    if *elapsed < settings.turn_secs {
+        return;
+    }
+    *elapsed = 0.;
+    if *rng == 0 {
+        *rng = 0x9e37_79b9;
+    }
+    let tile_of =
+        |transform: &Transform| coords::world_to_tile(&map_frame, transform.translation.truncate());
+    let player_tile = players.get_single().ok().and_then(tile_of);
+    let mut occupied: HashSet<TilePos> = creatures.iter().filter_map(tile_of).collect();
+
+    // by entity, for the same turns whatever the query order
+    let mut hostiles: Vec<_> = hostiles.iter().collect();
+    hostiles.sort_by_key(|(entity, ..)| *entity);
+    for (entity, hostile, transform, cooldown, aggroed, in_group) in hostiles {
+        let Some(tile) = tile_of(transform) else {
+            continue;
+        };
+        let seen = player_tile.filter(|player| {
+            sees_player(tile, *player, hostile.aggro_radius, |pos| {
+                collision_map.is_blocked(&pos)
+            })
+        });
+        match (seen, aggroed) {
+            (Some(_), false) => {
+                commands.entity(entity).insert(Aggroed);
+                gained.send(AggroGained { npc: entity });
+            }
+            (None, true) => {
+                commands.entity(entity).remove::<Aggroed>();
+                lost.send(AggroLost { npc: entity });
+            }
+            _ => {}
+        }
+        if cooldown.is_some_and(StepCooldown::is_walking) {
+            continue;
+        }
+
+        let open =
+            |pos: TilePos| collision_map.movement_cost(&pos).is_some() && !occupied.contains(&pos);
+        let step = match seen {
+            Some(player) => chase_step(tile, player, movement.diagonal, open),
+            // groups walk where their leader takes them
+            None if !in_group && next_random(&mut rng) < settings.wander_chance => {
+                let roll = next_random(&mut rng);
+                wander_step(tile, movement.diagonal, open, roll)
+                    .map_or(ChaseStep::Stuck, ChaseStep::Step)
+            }
+            None => ChaseStep::Stuck,
+        };
+        let dir = match step {
+            ChaseStep::Step(dir) => {
+                // taken at once, for the next creatures not to head there too
+                if let Some(to) = neighbour(tile, dir) {
+                    occupied.remove(&tile);
+                    occupied.insert(to);
+                }
+                dir
+            }
+            ChaseStep::Attack(dir) => dir,
+            ChaseStep::Stuck => continue,
+        };
+        requests.send(MoveRequested { entity, dir });
+    }
+}
+
+fn play_aggro_sound(
+    settings: Res<HostileSettings>,
+    asset_server: Res<AssetServer>,
+    mut gained: EventReader<AggroGained>,
+    npcs: Query<&GlobalTransform>,
+    mut sounds: SpatialAudio,
+) {
+    for event in gained.read() {
+        if settings.aggro_sound.is_empty() {
+            continue;
+        }
+        let Ok(npc) = npcs.get(event.npc) else {
+            continue;
+        };
+        sounds.play_spatial_sfx(
+            npc.translation().truncate(),
+            asset_server.load(settings.aggro_sound.clone()),
+            1.,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+    use crate::collision::MoveBlocked;
+    use crate::combat::BumpAttack;
+    use crate::health::Health;
+    use crate::movement::{self, MoveResolved};
+
+    #[test]
+    fn chasing_goes_around_walls() {
+        // a wall at x = 2 from y = 0 to 2, the way around is over its top
+        let wall = |pos: TilePos| pos.x == 2 && pos.y <= 2;
+        let open = |pos: TilePos| pos.x < 6 && pos.y < 6 && !wall(pos);
+        let npc = TilePos::new(4, 1);
+        let player = TilePos::new(0, 1);
+
+        assert_eq!(
+            chase_step(npc, player, DiagonalRule::Always, open),
+            ChaseStep::Step(IVec2::NEG_X)
+        );
+        // nothing closer than where it stands, up against the wall
+        assert_eq!(
+            chase_step(TilePos::new(3, 1), player, DiagonalRule::Always, open),
+            ChaseStep::Stuck
+        );
+        assert_eq!(
+            chase_step(TilePos::new(3, 3), player, DiagonalRule::Always, open),
+            ChaseStep::Step(IVec2::NEG_X)
+        );
+        assert_eq!(
+            chase_step(TilePos::new(5, 5), player, DiagonalRule::Always, open),
+            ChaseStep::Step(IVec2::new(-1, -1))
+        );
+        assert_eq!(
+            chase_step(TilePos::new(5, 5), player, DiagonalRule::Disabled, open),
+            ChaseStep::Step(IVec2::NEG_X)
+        );
+    }
+
+    #[test]
+    fn attacks_only_across_allowed_steps() {
+        let open = |pos: TilePos| pos != TilePos::new(1, 0);
+        let npc = TilePos::new(0, 0);
+        let player = TilePos::new(1, 1);
+
+        assert_eq!(
+            chase_step(npc, player, DiagonalRule::Always, open),
+            ChaseStep::Attack(IVec2::ONE)
+        );
+        // past the corner of (1, 0)
+        assert_eq!(
+            chase_step(npc, player, DiagonalRule::NeverThroughCorners, open),
+            ChaseStep::Step(IVec2::Y)
+        );
+        assert_eq!(
+            chase_step(npc, player, DiagonalRule::Disabled, open),
+            ChaseStep::Step(IVec2::Y)
+        );
+    }
+
+    #[test]
+    fn walls_hide_the_player() {
+        let wall = |pos: TilePos| pos == TilePos::new(2, 1);
+        assert!(sees_player(
+            TilePos::new(4, 1),
+            TilePos::new(1, 1),
+            5,
+            |_| false
+        ));
+        assert!(!sees_player(
+            TilePos::new(4, 1),
+            TilePos::new(1, 1),
+            5,
+            wall
+        ));
+        assert!(!sees_player(
+            TilePos::new(4, 1),
+            TilePos::new(1, 1),
+            2,
+            |_| false
+        ));
+    }
+
+    fn map_frame() -> MapFrame {
+        MapFrame {
+            transform: Transform::IDENTITY,
+            grid_size: TilemapGridSize { x: 10., y: 10. },
+            map_type: TilemapType::Square,
+            map_size: TilemapSize { x: 8, y: 3 },
+        }
+    }
+
+    fn spawn_at(app: &mut App, tile: TilePos, bundle: impl Bundle) -> Entity {
+        let pos = coords::tile_to_world_center(&map_frame(), &tile).unwrap();
+        app.world
+            .spawn((Transform::from_translation(pos.extend(2.)), bundle))
+            .id()
+    }
+
+    fn tile_of(app: &App, entity: Entity) -> Option<TilePos> {
+        let transform = app.world.get::<Transform>(entity).unwrap();
+        coords::world_to_tile(&map_frame(), transform.translation.truncate())
+    }
+
+    fn sent<E: Event + Clone>(app: &App) -> Vec<E> {
+        let events = app.world.resource::<Events<E>>();
+        events.get_reader().read(events).cloned().collect()
+    }
+
+    /// Updates of 0.2 seconds, so a turn of 0.4 seconds every other update
+    fn take_turn(app: &mut App) {
+        app.update();
+        app.update();
+    }
+
+    #[test]
+    fn hostile_creature_reaches_and_attacks_the_player() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                200,
+            )))
+            .insert_resource(map_frame())
+            .insert_resource(CollisionMap::new(TilemapSize { x: 8, y: 3 }))
+            .init_resource::<MovementSettings>()
+            .init_resource::<HostileSettings>()
+            .add_event::<MoveRequested>()
+            .add_event::<MoveResolved>()
+            .add_event::<MoveBlocked>()
+            .add_event::<BumpAttack>()
+            .add_event::<AggroGained>()
+            .add_event::<AggroLost>()
+            .add_systems(
+                Update,
+                (
+                    take_hostile_turns,
+                    movement::tick_step_cooldowns,
+                    movement::resolve_moves,
+                    movement::apply_moves,
+                )
+                    .chain(),
+            );
+        let player = spawn_at(&mut app, TilePos::new(1, 1), (MainPlayer, Health::new(10)));
+        let npc = spawn_at(
+            &mut app,
+            TilePos::new(4, 1),
+            (Npc, Hostile { aggro_radius: 4 }),
+        );
+        // the first update has no time delta
+        app.update();
+
+        take_turn(&mut app);
+        assert_eq!(sent::<AggroGained>(&app), [AggroGained { npc }]);
+        assert_eq!(tile_of(&app, npc), Some(TilePos::new(3, 1)));
+        take_turn(&mut app);
+        assert_eq!(tile_of(&app, npc), Some(TilePos::new(2, 1)));
+        take_turn(&mut app);
+        assert_eq!(
+            sent::<BumpAttack>(&app),
+            [BumpAttack {
+                attacker: npc,
+                target: player,
+                dir: IVec2::NEG_X,
+            }]
+        );
+        assert_eq!(tile_of(&app, npc), Some(TilePos::new(2, 1)));
+        assert_eq!(tile_of(&app, player), Some(TilePos::new(1, 1)));
+
+        // out of its radius, it gives up
+        let far = coords::tile_to_world_center(&map_frame(), &TilePos::new(7, 1)).unwrap();
+        app.world.get_mut::<Transform>(player).unwrap().translation = far.extend(2.);
+        take_turn(&mut app);
+        assert_eq!(sent::<AggroLost>(&app), [AggroLost { npc }]);
+        assert!(app.world.get::<Aggroed>(npc).is_none());
+    }
+}
@@ -0,0 +1,301 @@
+//! Which mouse button does what, so the buttons can be rebound and swapped for left hands.
+//!
+//! The systems using the mouse look their buttons up in the `MouseInputMap` rather than naming
+//! them. The camera's grab buttons follow it: every bound button is told apart as a click or a
+//! drag, only the `Pan` button pans. Select and Pan can share a button because a press that moves
+//! less than the camera's drag threshold is a click; two click actions on one button can't.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::accessibility::Preferences;
+use crate::camera::PanCam;
+use crate::game::GameSet;
+
+/// Plugin that holds the `MouseInputMap` and hands its buttons to the camera
+#[derive(Default)]
+pub struct InputMapPlugin;
+
+impl Plugin for InputMapPlugin {
+    fn build(&self, app: &mut App) {
+        let mouse_map = app.world.resource::<Preferences>().mouse;
+        app.insert_resource(mouse_map)
+            .register_type::<MouseInputMap>()
+            .add_systems(Update, apply_mouse_bindings.in_set(GameSet::Input));
+    }
+}
+
+/// What a mouse button can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MouseAction {
+    /// Pick the tile or the entity clicked
+    Select,
+    /// Drag the camera
+    Pan,
+    ContextMenu,
+    /// Walk to the tile clicked
+    PathMove,
+}
+
+impl MouseAction {
+    pub const ALL: [MouseAction; 4] = [
+        MouseAction::Select,
+        MouseAction::Pan,
+        MouseAction::ContextMenu,
+        MouseAction::PathMove,
+    ];
+
+    /// Localization key of the action's name
+    pub fn key(self) -> &'static str {
+        match self {
+            MouseAction::Select => "mouse.select",
+            MouseAction::Pan => "mouse.pan",
+            MouseAction::ContextMenu => "mouse.context_menu",
+            MouseAction::PathMove => "mouse.path_move",
+        }
+    }
+
+    /// Whether the action is a click, rather than a drag
+    fn is_click(self) -> bool {
+        self != MouseAction::Pan
+    }
+}
+
+/// The button of each `MouseAction`
+#[derive(Resource, Reflect, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[reflect(Resource)]
+#[serde(default)]
+pub struct MouseInputMap {
+    pub select: MouseButton,
+    pub pan: MouseButton,
+    pub context_menu: MouseButton,
+    pub path_move: MouseButton,
+}
+
+impl Default for MouseInputMap {
+    fn default() -> Self {
+        Self {
+            select: MouseButton::Left,
+            pan: MouseButton::Left,
+            context_menu: MouseButton::Middle,
+            path_move: MouseButton::Right,
+        }
+    }
+}
+
+impl MouseInputMap {
+    pub fn button(&self, action: MouseAction) -> MouseButton {
+        match action {
+            MouseAction::Select => self.select,
+            MouseAction::Pan => self.pan,
+            MouseAction::ContextMenu => self.context_menu,
+            MouseAction::PathMove => self.path_move,
+        }
+    }
+
+    pub fn button_mut(&mut self, action: MouseAction) -> &mut MouseButton {
+        match action {
+            MouseAction::Select => &mut self.select,
+            MouseAction::Pan => &mut self.pan,
+            MouseAction::ContextMenu => &mut self.context_menu,
+            MouseAction::PathMove => &mut self.path_move,
+        }
+    }
+
+    /// Whether `button` is the one of `action`
+    pub fn is(&self, action: MouseAction, button: MouseButton) -> bool {
+        self.button(action) == button
+    }
+
+    /// The map with the primary and secondary buttons swapped
+    pub fn swapped(&self) -> Self {
+        let swap = |button| match button {
+            MouseButton::Left => MouseButton::Right,
+            MouseButton::Right => MouseButton::Left,
+            other => other,
+        };
+        Self {
+            select: swap(self.select),
+            pan: swap(self.pan),
+            context_menu: swap(self.context_menu),
+            path_move: swap(self.path_move),
+        }
+    }
+
+    /// The bound buttons, each once, in the order of `MouseAction::ALL`
+    pub fn buttons(&self) -> Vec<MouseButton> {
+        let mut buttons = Vec::new();
+        for action in MouseAction::ALL {
+            let button = self.button(action);
+            if !buttons.contains(&button) {
+                buttons.push(button);
+            }
+        }
+        buttons
+    }
+
+    /// Pairs of actions on the same button that can't be told apart. A click and `Pan` can,
+    /// with a `drag_threshold` above zero.
+    pub fn conflicts(&self, drag_threshold: f32) -> Vec<(MouseAction, MouseAction)> {
+        let mut conflicts = Vec::new();
+        for (i, a) in MouseAction::ALL.iter().enumerate() {
+            for b in &MouseAction::ALL[i + 1..] {
+                let told_apart = a.is_click() != b.is_click() && drag_threshold > 0.;
+                if self.button(*a) == self.button(*b) && !told_apart {
+                    conflicts.push((*a, *b));
+                }
+            }
+        }
+        conflicts
+    }
+}
+
+/// Localization key of the name of `button`
+pub fn button_key(button: MouseButton) -> &'static str {
+    match button {
+        MouseButton::Left => "mouse.left",
+        MouseButton::Right => "mouse.right",
+        MouseButton::Middle => "mouse.middle",
+        MouseButton::Other(_) => "mouse.other",
+    }
+}
+
+/// Give the camera the bound buttons when the map changes or a camera is spawned
+pub fn apply_mouse_bindings(map: Res<MouseInputMap>, mut cameras: Query<&mut PanCam>) {
+    for mut cam in &mut cameras {
+        if !map.is_changed() && !cam.is_added() {
+            continue;
+        }
+        cam.grab_buttons = map.buttons();
+        cam.pan_buttons = vec![map.pan];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::input::mouse::MouseMotion;
+    use bevy::math::vec2;
+    use bevy::render::camera::CameraProjection;
+    use bevy::window::PrimaryWindow;
+    use bevy_ecs_tilemap::prelude::*;
+
+    use super::*;
+    use crate::camera::{self, Panning, WorldClick};
+    use crate::coords::MapFrame;
+    use crate::cursor::{CameraCursor, CursorWorldCache};
+    use crate::ui::{self, SelectedTile};
+
+    #[test]
+    fn swapping_and_conflicts() {
+        let map = MouseInputMap::default();
+        assert!(map.conflicts(4.).is_empty());
+        assert_eq!(map.conflicts(0.), [(MouseAction::Select, MouseAction::Pan)]);
+
+        let swapped = map.swapped();
+        assert_eq!(swapped.select, MouseButton::Right);
+        assert_eq!(swapped.path_move, MouseButton::Left);
+        assert_eq!(swapped.context_menu, MouseButton::Middle);
+        assert_eq!(swapped.swapped(), map);
+
+        let clash = MouseInputMap {
+            path_move: MouseButton::Left,
+            ..map
+        };
+        assert_eq!(
+            clash.conflicts(4.),
+            [(MouseAction::Select, MouseAction::PathMove)]
+        );
+    }
+
+    /// Selection and panning through the swapped buttons
+    #[test]
+    fn remapped_buttons_select_and_pan() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(MouseInputMap::default().swapped())
+            .insert_resource(MapFrame {
+                transform: Transform::IDENTITY,
+                grid_size: TilemapGridSize { x: 24., y: 24. },
+                map_type: TilemapType::Square,
+                map_size: TilemapSize { x: 5, y: 5 },
+            })
+            .init_resource::<Input<MouseButton>>()
+            .init_resource::<Input<KeyCode>>()
+            .init_resource::<CursorWorldCache>()
+            .init_resource::<Panning>()
+            .init_resource::<SelectedTile>()
+            .add_event::<MouseMotion>()
+            .add_event::<WorldClick>()
+            .add_systems(
+                Update,
+                (
+                    apply_mouse_bindings,
+                    camera::camera_movement,
+                    ui::select_tile,
+                )
+                    .chain(),
+            );
+        let mut window = Window {
+            resolution: (1920., 1080.).into(),
+            ..default()
+        };
+        let start = vec2(960., 540.);
+        window.set_cursor_position(Some(start));
+        let window = app.world.spawn((window, PrimaryWindow)).id();
+        let mut projection = Camera2dBundle::default().projection;
+        projection.update(1920., 1080.);
+        let camera = app
+            .world
+            .spawn((
+                PanCam::default(),
+                camera::DragState::default(),
+                Transform::default(),
+                projection,
+            ))
+            .id();
+        app.world.resource_mut::<CursorWorldCache>().insert(
+            camera,
+            CameraCursor {
+                screen_pos: start,
+                world_pos: vec2(24., 24.),
+            },
+        );
+        app.update();
+
+        let gesture = |app: &mut App, button, moved: f32| {
+            app.world.resource_mut::<Input<MouseButton>>().press(button);
+            app.update();
+            app.world.resource_mut::<Input<MouseButton>>().clear();
+            app.world
+                .get_mut::<Window>(window)
+                .unwrap()
+                .set_cursor_position(Some(start + Vec2::X * moved));
+            app.update();
+            let mut buttons = app.world.resource_mut::<Input<MouseButton>>();
+            buttons.clear();
+            buttons.release(button);
+            app.update();
+            app.world.resource_mut::<Input<MouseButton>>().clear();
+            app.world
+                .get_mut::<Window>(window)
+                .unwrap()
+                .set_cursor_position(Some(start));
+            app.update();
+            app.world.get::<Transform>(camera).unwrap().translation.x
+        };
+
+        // the left button moves the player now, it neither pans nor selects
+        assert_eq!(gesture(&mut app, MouseButton::Left, 30.), 0.);
+        assert_eq!(app.world.resource::<SelectedTile>().0, None);
+
+        // the right button selects with a click
+        assert_eq!(gesture(&mut app, MouseButton::Right, 0.), 0.);
+        assert_eq!(
+            app.world.resource::<SelectedTile>().0,
+            Some(TilePos { x: 1, y: 1 })
+        );
+
+        // and pans with a drag
+        assert_eq!(gesture(&mut app, MouseButton::Right, 30.), -30.);
+    }
+}
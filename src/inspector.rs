@@ -0,0 +1,54 @@
+//! Lets the resource inspector live in its own OS window, leaving the game viewport unobstructed.
+
+use bevy::prelude::*;
+
+use crate::game::GameSet;
+
+/// Plugin that opens and closes the detached inspector window to match `InspectorSettings`
+#[derive(Default)]
+pub struct InspectorWindowPlugin;
+
+impl Plugin for InspectorWindowPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InspectorSettings>()
+            .add_systems(Update, sync_inspector_window.in_set(GameSet::RenderPrep));
+    }
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct InspectorSettings {
+    /// When true the inspector is shown in its own window instead of over the game
+    pub detached: bool,
+}
+
+/// Marks the window the inspector is detached into
+#[derive(Component)]
+pub struct InspectorWindow;
+
+fn sync_inspector_window(
+    mut commands: Commands,
+    mut settings: ResMut<InspectorSettings>,
+    windows: Query<Entity, With<InspectorWindow>>,
+    mut closed: RemovedComponents<InspectorWindow>,
+) {
+    // closing the detached window docks the inspector again instead of losing it
+    if closed.read().count() > 0 && windows.is_empty() && settings.detached {
+        settings.detached = false;
+        return;
+    }
+
+    match (settings.detached, windows.get_single()) {
+        (true, Err(_)) => {
+            commands.spawn((
+                Window {
+                    title: "Inspector".to_string(),
+                    resolution: (420., 720.).into(),
+                    ..default()
+                },
+                InspectorWindow,
+            ));
+        }
+        (false, Ok(window)) => commands.entity(window).despawn(),
+        _ => {}
+    }
+}
@@ -0,0 +1,77 @@
+use bevy::prelude::*;
+
+use crate::coords::{self, MapFrame};
+use crate::game::GameSet;
+use crate::player::MainPlayer;
+use crate::state::level_ready;
+
+/// Keys that make the player interact with whatever is next to them
+pub const INTERACT_KEYS: [KeyCode; 2] = [KeyCode::Return, KeyCode::Space];
+
+/// Plugin that turns the interact key into `Interact` events
+#[derive(Default)]
+pub struct InteractPlugin;
+
+impl Plugin for InteractPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Interact>()
+            .add_systems(
+                Update,
+                send_interact
+                    .in_set(GameSet::Input)
+                    .run_if(level_ready)
+                    .run_if(crate::debug_step::gameplay_running)
+                    .run_if(crate::console::console_closed),
+            );
+    }
+}
+
+/// Marks entities the player can interact with from an adjacent tile
+#[derive(Component, Default)]
+pub struct Interactable;
+
+/// Sent when `actor` interacts with `target`
+#[derive(Event, Debug, Clone, Copy)]
+pub struct Interact {
+    pub actor: Entity,
+    pub target: Entity,
+}
+
+fn send_interact(
+    keys: Res<Input<KeyCode>>,
+    player_q: Query<(Entity, &Transform), With<MainPlayer>>,
+    targets: Query<(Entity, &Transform), With<Interactable>>,
+    map_frame: Res<MapFrame>,
+    mut interact: EventWriter<Interact>,
+) {
+    if !keys.any_just_pressed(INTERACT_KEYS) {
+        return;
+    }
+    let Ok((player, player_transform)) = player_q.get_single() else {
+        return;
+    };
+    let player_pos = player_transform.translation.truncate();
+    let Some(player_tile) = coords::world_to_tile(&map_frame, player_pos) else {
+        return;
+    };
+
+    // interact with the closest target on this or an adjacent tile
+    let target = targets
+        .iter()
+        .filter(|(_, transform)| {
+            coords::world_to_tile(&map_frame, transform.translation.truncate())
+                .is_some_and(|tile| coords::tile_distance(&player_tile, &tile) <= 1)
+        })
+        .min_by(|(_, a), (_, b)| {
+            let a = a.translation.truncate().distance_squared(player_pos);
+            let b = b.translation.truncate().distance_squared(player_pos);
+            a.total_cmp(&b)
+        });
+
+    if let Some((target, _)) = target {
+        interact.send(Interact {
+            actor: player,
+            target,
+        });
+    }
+}
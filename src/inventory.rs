@@ -0,0 +1,467 @@
+use std::collections::{BTreeMap, HashMap};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{EguiContext, EguiUserTextures};
+use bevy_window::PrimaryWindow;
+use serde::{Deserialize, Serialize};
+
+use crate::coords::{self, MapFrame};
+use crate::game::{GameSet, LevelLifecycleSet};
+use crate::game_ui::{self, UiAssets, UiRoot};
+use crate::helpers;
+use crate::localization::tr;
+use crate::map::GameInfoAlt;
+use crate::player::MainPlayer;
+use crate::sprite_atlas::CurrentCreatureAtlas;
+use crate::state::{level_ready, AppState};
+use crate::toast::Toasts;
+
+/// Key that shows and hides the inventory panel
+pub const INVENTORY_KEY: KeyCode = KeyCode::I;
+
+/// Map property that makes the inventory be emptied when the level is left
+pub const LEVEL_LOCAL_ITEMS_PROPERTY: &str = "items_level_local";
+
+/// Size of an item icon in the sprite panel, in screen pixels
+const ICON_SIZE: Vec2 = Vec2::splat(48.);
+const ICON_SPACING: f32 = 6.;
+const PANEL_PADDING: f32 = 20.;
+const PANEL_COLUMNS: usize = 5;
+
+/// Plugin that picks up items the player walks onto and shows the inventory panel
+#[derive(Default)]
+pub struct InventoryPlugin;
+
+impl Plugin for InventoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Inventory>()
+            .init_resource::<ItemIcons>()
+            .init_resource::<InventoryPanel>()
+            .init_resource::<InventoryStyle>()
+            .register_type::<InventoryStyle>()
+            .add_event::<ItemPickedUp>()
+            .add_systems(
+                Update,
+                (pick_up_items, announce_pickups)
+                    .chain()
+                    .in_set(GameSet::Gameplay)
+                    .run_if(level_ready)
+                    .run_if(crate::debug_step::gameplay_running),
+            )
+            .add_systems(
+                Update,
+                (
+                    toggle_inventory_panel,
+                    inventory_ui.run_if(resource_equals(InventoryStyle::Egui)),
+                    inventory_sprite_panel,
+                )
+                    .chain()
+                    .in_set(GameSet::RenderPrep)
+                    .run_if(level_ready),
+            )
+            .add_systems(
+                OnExit(AppState::Level),
+                clear_level_local_items.in_set(LevelLifecycleSet),
+            );
+    }
+}
+
+/// An item lying on the map, spawned from a Tiled object of type "item"
+#[derive(Component, Debug, Clone)]
+pub struct Item {
+    pub id: String,
+}
+
+/// Sent when `picker` picks up an item with the given id
+#[derive(Event, Debug, Clone)]
+pub struct ItemPickedUp {
+    pub picker: Entity,
+    pub id: String,
+}
+
+/// The items the player carries, as a count per item id
+#[derive(Resource, Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Inventory {
+    items: BTreeMap<String, u32>,
+}
+
+impl Inventory {
+    pub fn add(&mut self, id: impl Into<String>) {
+        *self.items.entry(id.into()).or_default() += 1;
+    }
+
+    /// Remove one item with the given id, returns false if there was none
+    pub fn remove(&mut self, id: &str) -> bool {
+        let Some(count) = self.items.get_mut(id) else {
+            return false;
+        };
+        *count -= 1;
+        if *count == 0 {
+            self.items.remove(id);
+        }
+        true
+    }
+
+    pub fn count(&self, id: &str) -> u32 {
+        self.items.get(id).copied().unwrap_or(0)
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.count(id) > 0
+    }
+
+    /// Item ids and their counts, sorted by id
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.items.iter().map(|(id, count)| (id.as_str(), *count))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+}
+
+/// Atlas index used to draw each item id, recorded when items are spawned
+#[derive(Resource, Default)]
+pub struct ItemIcons(pub HashMap<String, usize>);
+
+/// Whether the inventory panel is shown
+#[derive(Resource, Default)]
+pub struct InventoryPanel(pub bool);
+
+/// How the inventory panel is drawn
+#[derive(Resource, Reflect, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[reflect(Resource)]
+pub enum InventoryStyle {
+    /// An egui window
+    #[default]
+    Egui,
+    /// A 9-slice sprite panel with the item icons
+    Sprites,
+}
+
+/// Marks the node holding the sprite inventory panel
+#[derive(Component)]
+struct InventorySpritePanel;
+
+fn pick_up_items(
+    mut commands: Commands,
+    player_q: Query<(Entity, &Transform), With<MainPlayer>>,
+    items: Query<(Entity, &Item, &Transform)>,
+    map_frame: Res<MapFrame>,
+    mut inventory: ResMut<Inventory>,
+    mut picked_up: EventWriter<ItemPickedUp>,
+) {
+    let Ok((player, player_transform)) = player_q.get_single() else {
+        return;
+    };
+    let Some(player_tile) =
+        coords::world_to_tile(&map_frame, player_transform.translation.truncate())
+    else {
+        return;
+    };
+
+    for (entity, item, transform) in items.iter() {
+        if coords::world_to_tile(&map_frame, transform.translation.truncate()) != Some(player_tile)
+        {
+            continue;
+        }
+        commands.entity(entity).despawn_recursive();
+        inventory.add(item.id.clone());
+        picked_up.send(ItemPickedUp {
+            picker: player,
+            id: item.id.clone(),
+        });
+    }
+}
+
+fn announce_pickups(mut picked_up: EventReader<ItemPickedUp>, mut toasts: Toasts) {
+    for pickup in picked_up.read() {
+        toasts.info(tr!("inventory.picked_up", item = pickup.id));
+    }
+}
+
+fn toggle_inventory_panel(keys: Res<Input<KeyCode>>, mut panel: ResMut<InventoryPanel>) {
+    if keys.just_pressed(INVENTORY_KEY) {
+        panel.0 = !panel.0;
+    }
+}
+
+fn inventory_ui(
+    mut panel: ResMut<InventoryPanel>,
+    inventory: Res<Inventory>,
+    icons: Res<ItemIcons>,
+    creature_atlas: Res<CurrentCreatureAtlas>,
+    atlases: Res<Assets<TextureAtlas>>,
+    mut egui_textures: ResMut<EguiUserTextures>,
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+) {
+    if !panel.0 {
+        return;
+    }
+    let Some(mut ctx) = contexts.iter_mut().next() else {
+        return;
+    };
+
+    let atlas = atlases.get(&creature_atlas.0);
+    let texture_id = atlas.map(|atlas| {
+        egui_textures
+            .image_id(&atlas.texture)
+            .unwrap_or_else(|| egui_textures.add_image(atlas.texture.clone()))
+    });
+
+    egui::Window::new(tr!("inventory.title"))
+        .id(egui::Id::new("inventory"))
+        .open(&mut panel.0)
+        .show(ctx.get_mut(), |ui| {
+            if inventory.is_empty() {
+                ui.label(tr!("inventory.empty"));
+            }
+            egui::Grid::new("inventory").show(ui, |ui| {
+                for (id, count) in inventory.iter() {
+                    let icon = atlas.zip(texture_id).and_then(|(atlas, texture_id)| {
+                        let rect = atlas.textures.get(*icons.0.get(id)?)?;
+                        let uv = egui::Rect::from_min_max(
+                            egui::pos2(rect.min.x / atlas.size.x, rect.min.y / atlas.size.y),
+                            egui::pos2(rect.max.x / atlas.size.x, rect.max.y / atlas.size.y),
+                        );
+                        Some(
+                            egui::Image::new(egui::load::SizedTexture::new(
+                                texture_id,
+                                [rect.width(), rect.height()],
+                            ))
+                            .uv(uv),
+                        )
+                    });
+                    match icon {
+                        Some(icon) => ui.add(icon),
+                        None => ui.label(""),
+                    };
+                    ui.label(id);
+                    ui.label(format!("x{count}"));
+                    ui.end_row();
+                }
+            });
+        });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn inventory_sprite_panel(
+    mut commands: Commands,
+    panel: Res<InventoryPanel>,
+    style: Res<InventoryStyle>,
+    inventory: Res<Inventory>,
+    icons: Res<ItemIcons>,
+    ui_assets: Res<UiAssets>,
+    roots: Query<Entity, With<UiRoot>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    shown: Query<Entity, With<InventorySpritePanel>>,
+) {
+    let wanted = panel.0 && *style == InventoryStyle::Sprites;
+    let changed = panel.is_changed() || style.is_changed() || inventory.is_changed();
+    // the UI root may only show up after the panel was opened
+    if !changed && shown.is_empty() != wanted {
+        return;
+    }
+    for entity in &shown {
+        commands.entity(entity).despawn_recursive();
+    }
+    if !wanted {
+        return;
+    }
+    let (Ok(root), Ok(window)) = (roots.get_single(), windows.get_single()) else {
+        return;
+    };
+
+    let count = inventory.iter().count();
+    let columns = count.clamp(1, PANEL_COLUMNS);
+    let rows = count.div_ceil(PANEL_COLUMNS).max(1);
+    let size = game_ui::icon_panel_size(columns, rows, PANEL_PADDING, ICON_SIZE, ICON_SPACING);
+    // along the right edge of the screen
+    let center = Vec2::new(window.width() / 2. - PANEL_PADDING - size.x / 2., 0.);
+    let rect = Rect::from_center_size(center, size);
+    let centers = game_ui::layout_icons(rect, PANEL_PADDING, ICON_SIZE, ICON_SPACING, count);
+
+    commands.entity(root).with_children(|ui| {
+        ui.spawn((
+            SpatialBundle::default(),
+            InventorySpritePanel,
+            Name::new("inventory panel"),
+        ))
+        .with_children(|node| {
+            game_ui::spawn_nine_slice_panel(node, rect, &ui_assets.panel_style());
+            if count == 0 {
+                node.spawn(Text2dBundle {
+                    text: Text::from_section(tr!("inventory.empty"), TextStyle::default()),
+                    transform: Transform::from_translation(rect.center().extend(0.1)),
+                    ..default()
+                });
+            }
+            for ((id, item_count), center) in inventory.iter().zip(centers) {
+                node.spawn(SpriteSheetBundle {
+                    texture_atlas: ui_assets.icons.clone(),
+                    sprite: TextureAtlasSprite {
+                        index: icons.0.get(id).copied().unwrap_or(0),
+                        custom_size: Some(ICON_SIZE),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(center.extend(0.1)),
+                    ..default()
+                });
+                if item_count > 1 {
+                    node.spawn(Text2dBundle {
+                        text: Text::from_section(
+                            format!("x{item_count}"),
+                            TextStyle {
+                                font_size: 16.,
+                                ..default()
+                            },
+                        ),
+                        text_anchor: bevy::sprite::Anchor::BottomRight,
+                        transform: Transform::from_translation(
+                            (center + Vec2::new(ICON_SIZE.x, -ICON_SIZE.y) / 2.).extend(0.2),
+                        ),
+                        ..default()
+                    });
+                }
+            }
+        });
+    });
+}
+
+/// Empty the inventory when leaving a map whose items only belong to that level
+fn clear_level_local_items(
+    mut inventory: ResMut<Inventory>,
+    game_info: Res<GameInfoAlt>,
+    tile_maps: Res<Assets<helpers::tiled::TiledMap>>,
+) {
+    let Some(map) = tile_maps.get(&game_info.tile_map) else {
+        return;
+    };
+    if matches!(
+        map.map.properties.get(LEVEL_LOCAL_ITEMS_PROPERTY),
+        Some(tiled::PropertyValue::BoolValue(true))
+    ) {
+        inventory.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy_ecs_tilemap::prelude::*;
+
+    use super::*;
+    use crate::layers::LayerZConfig;
+    use crate::map::spawn_map_object;
+    use crate::toast::Toast;
+
+    const MAP_PATH: &str = "assets/maps/TMX/map_test_items.tmx";
+
+    /// An app with the fixture map's objects spawned, as the level does
+    fn test_app() -> App {
+        let map = crate::helpers::tiled::load_test_map(MAP_PATH).map;
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<Inventory>()
+            .init_resource::<ItemIcons>()
+            .init_resource::<Assets<TextureAtlas>>()
+            .init_resource::<LayerZConfig>()
+            .add_event::<ItemPickedUp>()
+            .add_event::<Toast>()
+            .insert_resource(MapFrame::from_tiled_map(&map, Transform::IDENTITY))
+            .add_systems(Update, (pick_up_items, announce_pickups).chain());
+        app.world.run_system_once(
+            move |mut commands: Commands,
+                  atlases: Res<Assets<TextureAtlas>>,
+                  map_frame: Res<MapFrame>,
+                  z_config: Res<LayerZConfig>,
+                  mut item_icons: ResMut<ItemIcons>| {
+                for layer in crate::helpers::tiled::all_layers(&map) {
+                    let Some(objects) = layer.as_object_layer() else {
+                        continue;
+                    };
+                    for object in objects.objects() {
+                        spawn_map_object(
+                            &mut commands,
+                            &Handle::default(),
+                            &atlases,
+                            None,
+                            &map_frame,
+                            &z_config,
+                            &mut item_icons,
+                            &object,
+                            None,
+                        );
+                    }
+                }
+            },
+        );
+        app
+    }
+
+    fn tile_center(app: &App, x: u32, y: u32) -> Vec3 {
+        let frame = app.world.resource::<MapFrame>();
+        coords::tile_to_world_center(frame, &TilePos::new(x, y))
+            .unwrap()
+            .extend(2.)
+    }
+
+    #[test]
+    fn walking_onto_item_picks_it_up() {
+        let mut app = test_app();
+        let item = app
+            .world
+            .query_filtered::<Entity, With<Item>>()
+            .single(&app.world);
+        assert_eq!(app.world.resource::<ItemIcons>().0["key_red"], 7);
+        let player = app
+            .world
+            .spawn((
+                MainPlayer,
+                Transform::from_translation(tile_center(&app, 1, 1)),
+            ))
+            .id();
+
+        app.update();
+        assert!(app.world.resource::<Inventory>().is_empty());
+
+        // the item sits at Tiled pixel (60, 36), on the tile at (2, 1) from the bottom left
+        app.world.get_mut::<Transform>(player).unwrap().translation = tile_center(&app, 2, 1);
+        app.update();
+
+        let inventory = app.world.resource::<Inventory>();
+        assert_eq!(inventory.count("key_red"), 1);
+        assert!(app.world.get_entity(item).is_none());
+
+        let events = app.world.resource::<Events<ItemPickedUp>>();
+        let picked: Vec<_> = events.get_reader().read(events).cloned().collect();
+        assert_eq!(picked.len(), 1);
+        assert_eq!(picked[0].id, "key_red");
+        assert_eq!(picked[0].picker, player);
+
+        let toasts = app.world.resource::<Events<Toast>>();
+        assert_eq!(toasts.get_reader().read(toasts).count(), 1);
+    }
+
+    #[test]
+    fn inventory_counts_duplicates() {
+        let mut inventory = Inventory::default();
+        inventory.add("coin");
+        inventory.add("coin");
+        inventory.add("key");
+        assert_eq!(
+            inventory.iter().collect::<Vec<_>>(),
+            [("coin", 2), ("key", 1)]
+        );
+
+        assert!(inventory.remove("coin"));
+        assert!(inventory.remove("coin"));
+        assert!(!inventory.remove("coin"));
+        assert!(!inventory.contains("coin"));
+        assert_eq!(inventory.count("key"), 1);
+    }
+}
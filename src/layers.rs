@@ -0,0 +1,204 @@
+//! Draw order of the tile layers and creatures.
+//!
+//! The z budget, back to front:
+//! - 0 to 1.4: tile layers, `base + layer_index * spacing`
+//! - 1.4: region labels
+//! - 1.5: items
+//! - 1.8: targeting highlights
+//! - 1.9: hover and selection markers
+//! - 2 to 3: creatures, the lower on screen the closer to the front
+//! - 3 to 5: tile layers drawn over the creatures, like tree canopies
+//! - 4.9: ambient butterflies and birds
+//! - 5: fog of war
+//! - 5.5: lights
+//! - 6: weather
+//! - 6.4: health bars
+//! - 6.5: floating damage numbers
+//! - over everything: the portal fade, a child of the camera
+//!
+//! Properties read from the tile layers:
+//! - `above_entities`: draw the layer over the creatures
+
+use bevy::prelude::*;
+
+use crate::coords::{self, MapFrame};
+use crate::game::GameSet;
+use crate::helpers::tiled::TiledLayer;
+use crate::map::Npc;
+use crate::player::MainPlayer;
+use crate::sprite_scale;
+use crate::state::level_ready;
+
+/// Name of the layer property that lifts a tile layer above the creatures
+pub const ABOVE_ENTITIES_PROPERTY: &str = "above_entities";
+
+/// Plugin that y-sorts the creatures and moves the tile layers when `LayerZConfig` changes
+#[derive(Default)]
+pub struct LayerZPlugin;
+
+impl Plugin for LayerZPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LayerZConfig>()
+            .add_systems(
+                Update,
+                update_layer_z
+                    .in_set(GameSet::RenderPrep)
+                    .run_if(resource_changed::<LayerZConfig>()),
+            )
+            .add_systems(
+                PostUpdate,
+                sort_creatures
+                    .before(bevy::transform::TransformSystem::TransformPropagate)
+                    .run_if(level_ready),
+            );
+    }
+}
+
+/// Where tile layers and creatures go on the z axis
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct LayerZConfig {
+    /// z of the first tile layer
+    pub base: f32,
+    /// z between two consecutive tile layers
+    pub spacing: f32,
+    /// z of the creature furthest back, at the top of the map
+    pub creature_min: f32,
+    /// z of the creature furthest forward, at the bottom of the map
+    pub creature_max: f32,
+    /// Added instead of `base` for layers with the `above_entities` property
+    pub foreground_base: f32,
+}
+
+impl Default for LayerZConfig {
+    fn default() -> Self {
+        Self {
+            base: 0.,
+            spacing: 0.1,
+            creature_min: 2.,
+            creature_max: 3.,
+            foreground_base: 3.,
+        }
+    }
+}
+
+impl LayerZConfig {
+    /// z of the tile layer at `layer_index` in the Tiled map
+    pub fn layer_z(&self, layer_index: usize, above_entities: bool) -> f32 {
+        let base = if above_entities {
+            self.foreground_base
+        } else {
+            self.base
+        };
+        base + layer_index as f32 * self.spacing
+    }
+
+    /// z of a creature standing at the world position `pos`
+    pub fn creature_z(&self, frame: &MapFrame, pos: Vec2) -> f32 {
+        let rect = coords::map_world_rect(frame, &GlobalTransform::from(frame.transform));
+        let height = rect.height();
+        let from_bottom = if height > 0. {
+            ((pos.y - rect.min.y) / height).clamp(0., 1.)
+        } else {
+            0.
+        };
+        self.creature_max - from_bottom * (self.creature_max - self.creature_min)
+    }
+}
+
+/// Whether a Tiled layer has the `above_entities` property set
+pub fn above_entities(layer: &tiled::Layer) -> bool {
+    matches!(
+        layer.properties.get(ABOVE_ENTITIES_PROPERTY),
+        Some(tiled::PropertyValue::BoolValue(true))
+    )
+}
+
+fn update_layer_z(config: Res<LayerZConfig>, mut layers: Query<(&TiledLayer, &mut Transform)>) {
+    for (layer, mut transform) in &mut layers {
+        transform.translation.z = config.layer_z(layer.layer_index, layer.above_entities);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn sort_creatures(
+    config: Res<LayerZConfig>,
+    map_frame: Res<MapFrame>,
+    mut creatures: Query<&mut Transform, Or<(With<MainPlayer>, With<Npc>)>>,
+) {
+    for mut transform in &mut creatures {
+        // sorted by their feet, on the bottom edge of their tile
+        let feet = sprite_scale::feet_position(&map_frame, transform.translation.truncate());
+        let z = config.creature_z(&map_frame, feet);
+        // only touch the transform when the z moves, so it isn't flagged changed every frame
+        if transform.translation.z != z {
+            transform.translation.z = z;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use bevy_ecs_tilemap::prelude::*;
+
+    use super::*;
+    use crate::helpers::tiled::parse_tmx;
+
+    const MAP_PATH: &str = "assets/maps/TMX/map_test_canopy.tmx";
+
+    #[test]
+    fn canopy_is_drawn_over_the_creatures() {
+        let source = std::fs::read(MAP_PATH).unwrap();
+        let map = parse_tmx(&source, Path::new(MAP_PATH)).unwrap();
+        let frame = MapFrame::from_tiled_map(&map, Transform::IDENTITY);
+        let config = LayerZConfig::default();
+
+        let mut layer_z = std::collections::HashMap::new();
+        let mut creature_z = Vec::new();
+        for (layer_index, layer) in map.layers().enumerate() {
+            match layer.layer_type() {
+                tiled::LayerType::Tiles(_) => {
+                    let z = config.layer_z(layer_index, above_entities(&layer));
+                    layer_z.insert(layer.name.clone(), z);
+                }
+                tiled::LayerType::Objects(objects) => {
+                    for object in objects.objects() {
+                        let pos = coords::tiled_px_to_world(&frame, Vec2::new(object.x, object.y))
+                            .unwrap();
+                        creature_z.push((object.name.clone(), config.creature_z(&frame, pos)));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        assert!(layer_z["ground"] < layer_z["details"]);
+        // the canopy comes before the creatures in the file but is drawn over them
+        for (_, z) in &creature_z {
+            assert!(layer_z["details"] < *z);
+            assert!(*z < layer_z["canopy"]);
+        }
+        assert!(layer_z["canopy"] < layer_z["roof"]);
+
+        // the creature lower on the map is in front
+        let z_of = |name: &str| creature_z.iter().find(|(n, _)| n == name).unwrap().1;
+        assert!(z_of("north") < z_of("south"));
+    }
+
+    #[test]
+    fn creature_band_spans_the_map() {
+        let frame = MapFrame {
+            transform: Transform::IDENTITY,
+            grid_size: TilemapGridSize { x: 10., y: 10. },
+            map_type: TilemapType::Square,
+            map_size: TilemapSize { x: 4, y: 4 },
+        };
+        let config = LayerZConfig::default();
+        // tiles span -5..35
+        assert_eq!(config.creature_z(&frame, Vec2::new(0., -5.)), 3.);
+        assert_eq!(config.creature_z(&frame, Vec2::new(0., 15.)), 2.5);
+        assert_eq!(config.creature_z(&frame, Vec2::new(0., 35.)), 2.);
+        assert_eq!(config.creature_z(&frame, Vec2::new(0., 100.)), 2.);
+    }
+}
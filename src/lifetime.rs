@@ -0,0 +1,118 @@
+//! Temporary entities that remove themselves: after a `Lifetime`, or when the app leaves the
+//! state they belong to (`ExpireOnStateExit`), even if the system that spawned them stops
+//! looking after them.
+
+use bevy::prelude::*;
+
+use crate::game::GameSet;
+use crate::state::AppState;
+
+/// Plugin that despawns the entities whose `Lifetime` is over or whose state is left
+#[derive(Default)]
+pub struct LifetimePlugin;
+
+impl Plugin for LifetimePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, expire_lifetimes.in_set(GameSet::RenderPrep));
+        for state in AppState::ALL {
+            app.add_systems(OnExit(state), expire_on_state_exit(state));
+        }
+    }
+}
+
+/// Despawns the entity, with its children, when the timer finishes
+#[derive(Component, Debug, Clone, Deref, DerefMut)]
+pub struct Lifetime(pub Timer);
+
+impl Lifetime {
+    pub fn from_seconds(secs: f32) -> Self {
+        Self(Timer::from_seconds(secs, TimerMode::Once))
+    }
+}
+
+/// Despawns the entity, with its children, when the app leaves the state
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExpireOnStateExit(pub AppState);
+
+pub fn expire_lifetimes(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut lifetimes: Query<(Entity, &mut Lifetime)>,
+) {
+    for (entity, mut lifetime) in &mut lifetimes {
+        lifetime.tick(time.delta());
+        if lifetime.finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// System despawning the entities marked to expire when `state` is left
+pub fn expire_on_state_exit(
+    state: AppState,
+) -> impl FnMut(Commands, Query<(Entity, &ExpireOnStateExit)>) {
+    move |mut commands, marked| {
+        for (entity, expire) in &marked {
+            if expire.0 == state {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+
+    #[test]
+    fn entities_go_when_their_lifetime_is_over() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                100,
+            )))
+            .add_systems(Update, expire_lifetimes);
+        let short = app.world.spawn(Lifetime::from_seconds(0.15)).id();
+        let child = app.world.spawn_empty().id();
+        app.world.entity_mut(short).add_child(child);
+        let long = app.world.spawn(Lifetime::from_seconds(10.)).id();
+
+        // the first update has no time delta
+        app.update();
+        app.update();
+        assert!(app.world.get_entity(short).is_some());
+
+        app.update();
+        assert!(app.world.get_entity(short).is_none());
+        assert!(app.world.get_entity(child).is_none());
+        assert!(app.world.get_entity(long).is_some());
+    }
+
+    #[test]
+    fn leaving_the_level_cleans_up_its_entities() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugins(LifetimePlugin)
+            .add_state::<AppState>();
+        app.world
+            .resource_mut::<NextState<AppState>>()
+            .set(AppState::Level);
+        app.update();
+        let level_effect = app.world.spawn(ExpireOnStateExit(AppState::Level)).id();
+        let game_over_effect = app.world.spawn(ExpireOnStateExit(AppState::GameOver)).id();
+        let unmarked = app.world.spawn_empty().id();
+
+        app.world
+            .resource_mut::<NextState<AppState>>()
+            .set(AppState::GameOver);
+        app.update();
+
+        assert!(app.world.get_entity(level_effect).is_none());
+        assert!(app.world.get_entity(game_over_effect).is_some());
+        assert!(app.world.get_entity(unmarked).is_some());
+    }
+}
@@ -0,0 +1,287 @@
+//! Light sources: Tiled objects of type "light" glow with an additive radial gradient.
+//!
+//! Properties read from the light objects:
+//! - `radius`: radius of the glow in Tiled pixels, defaults to two tiles
+//! - `color`: color of the glow, a Tiled color or a `#rrggbb` string, defaults to warm white
+//!
+//! The glow gets stronger as `Darkness` rises, so lights stand out at night.
+
+use bevy::prelude::*;
+use bevy::render::mesh::MeshVertexBufferLayout;
+use bevy::render::render_resource::{
+    AsBindGroup, BlendComponent, BlendFactor, BlendOperation, BlendState, Extent3d,
+    RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError, TextureDimension,
+    TextureFormat,
+};
+use bevy::sprite::{Material2d, Material2dKey, Material2dPlugin, MaterialMesh2dBundle};
+
+use crate::coords::{self, MapFrame};
+use crate::game::{GameSet, LevelLifecycleSet};
+use crate::helpers;
+use crate::map::GameInfoAlt;
+use crate::state::{level_ready, AppState};
+
+/// Above the fog of war, below the weather
+const LIGHT_Z: f32 = 5.5;
+/// Width and height of the generated gradient texture
+const GLOW_TEXTURE_SIZE: u32 = 64;
+const DEFAULT_LIGHT_COLOR: Color = Color::rgb(1., 0.8, 0.5);
+
+/// Plugin that spawns the light objects of the map and scales their glow with the darkness
+#[derive(Default)]
+pub struct LightPlugin;
+
+impl Plugin for LightPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(Material2dPlugin::<GlowMaterial>::default())
+            .init_resource::<Darkness>()
+            .add_systems(Startup, create_glow_texture)
+            .add_systems(
+                OnEnter(AppState::Level),
+                spawn_lights.in_set(LevelLifecycleSet),
+            )
+            .add_systems(
+                OnExit(AppState::Level),
+                despawn_lights.in_set(LevelLifecycleSet),
+            )
+            .add_systems(
+                Update,
+                update_light_strength
+                    .in_set(GameSet::RenderPrep)
+                    .run_if(level_ready),
+            );
+    }
+}
+
+/// How dark it is, from 0 in full daylight to 1 at night
+///
+/// Meant to be driven by a day/night cycle, nothing changes it yet.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct Darkness(pub f32);
+
+impl Default for Darkness {
+    fn default() -> Self {
+        Self(1.)
+    }
+}
+
+/// Tinted texture drawn with additive blending
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct GlowMaterial {
+    #[uniform(0)]
+    pub color: Color,
+    #[texture(1)]
+    #[sampler(2)]
+    pub texture: Handle<Image>,
+}
+
+impl Material2d for GlowMaterial {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/glow.wgsl".into()
+    }
+
+    fn specialize(
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayout,
+        _key: Material2dKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let additive = BlendState {
+            color: BlendComponent {
+                src_factor: BlendFactor::SrcAlpha,
+                dst_factor: BlendFactor::One,
+                operation: BlendOperation::Add,
+            },
+            alpha: BlendComponent::OVER,
+        };
+        if let Some(fragment) = &mut descriptor.fragment {
+            for target in fragment.targets.iter_mut().flatten() {
+                target.blend = Some(additive);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The radial gradient every light uses
+#[derive(Resource)]
+struct GlowTexture(Handle<Image>);
+
+/// A light spawned from the map, `color` is its full-strength color
+#[derive(Component, Debug)]
+pub struct Light {
+    pub color: Color,
+}
+
+/// Glow strength at `distance` from the center, 0 at the center and 1 at the edge
+pub fn glow_falloff(distance: f32) -> f32 {
+    let falloff = (1. - distance).clamp(0., 1.);
+    falloff * falloff
+}
+
+/// Radius in Tiled pixels and color of a light object
+pub fn light_from_properties(properties: &tiled::Properties, default_radius: f32) -> (f32, Color) {
+    let radius = match properties.get("radius") {
+        Some(tiled::PropertyValue::FloatValue(radius)) if *radius > 0. => *radius,
+        Some(tiled::PropertyValue::IntValue(radius)) if *radius > 0 => *radius as f32,
+        _ => default_radius,
+    };
+    let color = match properties.get("color") {
+        Some(tiled::PropertyValue::ColorValue(color)) => {
+            Color::rgba_u8(color.red, color.green, color.blue, color.alpha)
+        }
+        Some(tiled::PropertyValue::StringValue(hex)) => {
+            Color::hex(hex).unwrap_or(DEFAULT_LIGHT_COLOR)
+        }
+        _ => DEFAULT_LIGHT_COLOR,
+    };
+    (radius, color)
+}
+
+fn create_glow_texture(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let size = GLOW_TEXTURE_SIZE;
+    let half = size as f32 / 2.;
+    let mut data = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let offset = Vec2::new(x as f32 + 0.5 - half, y as f32 + 0.5 - half);
+            let alpha = glow_falloff(offset.length() / half);
+            data.extend_from_slice(&[255, 255, 255, (alpha * 255.).round() as u8]);
+        }
+    }
+    let image = Image::new(
+        Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    commands.insert_resource(GlowTexture(images.add(image)));
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_lights(
+    mut commands: Commands,
+    game_info: Res<GameInfoAlt>,
+    tile_maps: Res<Assets<helpers::tiled::TiledMap>>,
+    map_frame: Res<MapFrame>,
+    glow_texture: Res<GlowTexture>,
+    darkness: Res<Darkness>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<GlowMaterial>>,
+) {
+    let Some(map) = tile_maps.get(&game_info.tile_map) else {
+        return;
+    };
+    let quad = meshes.add(shape::Quad::new(Vec2::ONE).into());
+    let default_radius = map_frame.grid_size.x * 2.;
+
+    for layer in helpers::tiled::all_layers(&map.map) {
+        let tiled::LayerType::Objects(object_layer) = layer.layer_type() else {
+            continue;
+        };
+        for object in object_layer.objects() {
+            if !object.visible || !object.user_type.eq_ignore_ascii_case("light") {
+                continue;
+            }
+            let center = match object.shape {
+                tiled::ObjectShape::Rect { width, height } => {
+                    Vec2::new(object.x + width / 2., object.y + height / 2.)
+                }
+                _ => Vec2::new(object.x, object.y),
+            };
+            let Some(pos) = coords::tiled_px_to_world(&map_frame, center) else {
+                warn!("light {} is outside the map", object.name);
+                continue;
+            };
+            let (radius, color) = light_from_properties(&object.properties, default_radius);
+            // map units are Tiled pixels scaled by the map transform
+            let diameter = radius * 2. * map_frame.transform.scale.truncate();
+            commands.spawn((
+                MaterialMesh2dBundle {
+                    mesh: quad.clone().into(),
+                    material: materials.add(GlowMaterial {
+                        color: color.with_a(color.a() * darkness.0),
+                        texture: glow_texture.0.clone(),
+                    }),
+                    transform: Transform::from_translation(pos.extend(LIGHT_Z))
+                        .with_scale(diameter.extend(1.)),
+                    ..default()
+                },
+                Light { color },
+                Name::new(format!("light {}", object.name)),
+            ));
+        }
+    }
+}
+
+fn despawn_lights(mut commands: Commands, lights: Query<Entity, With<Light>>) {
+    for entity in &lights {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn update_light_strength(
+    darkness: Res<Darkness>,
+    lights: Query<(&Light, &Handle<GlowMaterial>)>,
+    mut materials: ResMut<Assets<GlowMaterial>>,
+) {
+    if !darkness.is_changed() {
+        return;
+    }
+    let strength = darkness.0.clamp(0., 1.);
+    for (light, handle) in &lights {
+        if let Some(material) = materials.get_mut(handle) {
+            material.color = light.color.with_a(light.color.a() * strength);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glow_fades_to_the_edge() {
+        assert_eq!(glow_falloff(0.), 1.);
+        assert_eq!(glow_falloff(0.5), 0.25);
+        assert_eq!(glow_falloff(1.), 0.);
+        assert_eq!(glow_falloff(1.5), 0.);
+    }
+
+    #[test]
+    fn light_properties() {
+        let mut properties = tiled::Properties::new();
+        assert_eq!(
+            light_from_properties(&properties, 48.),
+            (48., DEFAULT_LIGHT_COLOR)
+        );
+
+        properties.insert("radius".to_string(), tiled::PropertyValue::IntValue(60));
+        properties.insert(
+            "color".to_string(),
+            tiled::PropertyValue::StringValue("#ff8000".to_string()),
+        );
+        assert_eq!(
+            light_from_properties(&properties, 48.),
+            (60., Color::rgb_u8(255, 128, 0))
+        );
+
+        properties.insert("radius".to_string(), tiled::PropertyValue::FloatValue(-2.));
+        properties.insert(
+            "color".to_string(),
+            tiled::PropertyValue::ColorValue(tiled::Color {
+                red: 10,
+                green: 20,
+                blue: 30,
+                alpha: 128,
+            }),
+        );
+        assert_eq!(
+            light_from_properties(&properties, 48.),
+            (48., Color::rgba_u8(10, 20, 30, 128))
+        );
+    }
+}
@@ -0,0 +1,294 @@
+//! Translations of the strings the player reads, one RON table per language in `LANG_DIR`.
+//!
+//! A table maps keys to strings, where `{name}` is replaced by the argument `name`. The UI gets
+//! its strings with `tr!("key", name = value)`, which reads the active `Localization`. A key
+//! missing from the chosen language falls back to `FALLBACK_LANGUAGE` with a warning, logged once
+//! per key. The language is one of the preferences, picked in the settings window.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Mutex, RwLock};
+
+use bevy::prelude::*;
+
+use crate::accessibility::Preferences;
+use crate::game::GameSet;
+
+/// Folder of the language tables, `<language>.ron` each
+pub const LANG_DIR: &str = "assets/lang";
+/// Language of the strings missing from the chosen one
+pub const FALLBACK_LANGUAGE: &str = "en";
+/// Prefix of texts from the map that are localization keys rather than text
+const KEY_PREFIX: char = '@';
+
+/// The localization `tr!` reads, installed from the resource whenever it changes
+static ACTIVE: RwLock<Option<Localization>> = RwLock::new(None);
+/// Keys already warned about, so a missing string in a window drawn every frame warns once
+static WARNED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// The string of `key` in the active language, with the `name = value` arguments substituted
+macro_rules! tr {
+    ($key:expr $(, $name:ident = $value:expr)* $(,)?) => {
+        $crate::localization::translate($key, &[$((stringify!($name), $value.to_string())),*])
+    };
+}
+pub(crate) use tr;
+
+/// Plugin that loads the language of the preferences and keeps `tr!` on the current one
+#[derive(Default)]
+pub struct LocalizationPlugin;
+
+impl Plugin for LocalizationPlugin {
+    fn build(&self, app: &mut App) {
+        let language = app.world.resource::<Preferences>().language.clone();
+        let localization = Localization::load(Path::new(LANG_DIR), &language);
+        install(localization.clone());
+        app.insert_resource(localization).add_systems(
+            Update,
+            install_localization
+                .in_set(GameSet::Input)
+                .run_if(resource_changed::<Localization>()),
+        );
+    }
+}
+
+#[derive(Resource, Debug, Clone, Default, PartialEq)]
+pub struct Localization {
+    language: String,
+    strings: HashMap<String, String>,
+    fallback: HashMap<String, String>,
+}
+
+impl Localization {
+    /// The tables of `language` and of the fallback language in `dir`, a missing table is empty
+    pub fn load(dir: &Path, language: &str) -> Self {
+        let fallback = read_table(dir, FALLBACK_LANGUAGE);
+        let strings = if language == FALLBACK_LANGUAGE {
+            fallback.clone()
+        } else {
+            read_table(dir, language)
+        };
+        Self {
+            language: language.to_string(),
+            strings,
+            fallback,
+        }
+    }
+
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    /// The languages with a table in `dir`, with their names, sorted by code
+    pub fn languages(dir: &Path) -> Vec<(String, String)> {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            warn!("can't list the languages in {dir:?}");
+            return Vec::new();
+        };
+        let mut languages: Vec<_> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != "ron" {
+                    return None;
+                }
+                let code = path.file_stem()?.to_string_lossy().into_owned();
+                let name = read_table(dir, &code)
+                    .remove("language.name")
+                    .unwrap_or_else(|| code.clone());
+                Some((code, name))
+            })
+            .collect();
+        languages.sort();
+        languages
+    }
+
+    /// The string of `key` with `args` substituted, from the fallback language when this one
+    /// doesn't have it, and the key itself when neither has it
+    pub fn translate(&self, key: &str, args: &[(&str, String)]) -> String {
+        let template = match self.strings.get(key) {
+            Some(template) => template,
+            None => {
+                warn_missing(key, &self.language);
+                self.fallback.get(key).map_or(key, String::as_str)
+            }
+        };
+        substitute(template, args)
+    }
+}
+
+fn read_table(dir: &Path, language: &str) -> HashMap<String, String> {
+    let path = dir.join(format!("{language}.ron"));
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) => {
+            warn!("can't read the language table {path:?}: {err}");
+            return HashMap::new();
+        }
+    };
+    ron::from_str(&text).unwrap_or_else(|err| {
+        warn!("can't parse the language table {path:?}: {err}");
+        HashMap::new()
+    })
+}
+
+fn warn_missing(key: &str, language: &str) {
+    let Ok(mut warned) = WARNED.lock() else {
+        return;
+    };
+    if !warned.iter().any(|warned| warned == key) {
+        warn!("no {language} string for {key:?}, using {FALLBACK_LANGUAGE}");
+        warned.push(key.to_string());
+    }
+}
+
+/// `template` with each `{name}` replaced by the value of `name` in `args`, unknown names are kept
+pub fn substitute(template: &str, args: &[(&str, String)]) -> String {
+    let mut text = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        text.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let value = after.find('}').and_then(|close| {
+            let name = &after[..close];
+            let (_, value) = args.iter().find(|(arg, _)| *arg == name)?;
+            Some((value, close))
+        });
+        match value {
+            Some((value, close)) => {
+                text.push_str(value);
+                rest = &after[close + 1..];
+            }
+            None => {
+                text.push('{');
+                rest = after;
+            }
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+/// Make `localization` the one `tr!` reads
+pub fn install(localization: Localization) {
+    if let Ok(mut active) = ACTIVE.write() {
+        *active = Some(localization);
+    }
+}
+
+/// What `tr!` expands to. Before a localization is installed, as in tests, the fallback language
+/// is loaded from `LANG_DIR`.
+pub fn translate(key: &str, args: &[(&str, String)]) -> String {
+    if let Ok(active) = ACTIVE.read() {
+        if let Some(localization) = active.as_ref() {
+            return localization.translate(key, args);
+        }
+    }
+    let localization = Localization::load(Path::new(LANG_DIR), FALLBACK_LANGUAGE);
+    let text = localization.translate(key, args);
+    if let Ok(mut active) = ACTIVE.write() {
+        active.get_or_insert(localization);
+    }
+    text
+}
+
+/// A text from the map: `@key` is looked up with `tr!`, anything else is shown as it is
+pub fn localized(text: &str) -> Cow<'_, str> {
+    match text.strip_prefix(KEY_PREFIX) {
+        Some(key) => Cow::Owned(tr!(key)),
+        None => Cow::Borrowed(text),
+    }
+}
+
+fn install_localization(localization: Res<Localization>) {
+    install(localization.clone());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lang_dir() -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("bevy_test_localization_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("en.ron"),
+            r#"{
+                "language.name": "English",
+                "saves.saved": "Saved {name}",
+                "age.minutes": "{count} min ago",
+            }"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("fr.ron"),
+            r#"{
+                "language.name": "Français",
+                "saves.saved": "{name} sauvegardé",
+            }"#,
+        )
+        .unwrap();
+        dir
+    }
+
+    #[test]
+    fn strings_are_substituted_and_fall_back_to_english() {
+        let dir = lang_dir();
+        let args = [("name", "slot 1".to_string()), ("count", 3.to_string())];
+
+        let english = Localization::load(&dir, "en");
+        assert_eq!(english.translate("saves.saved", &args), "Saved slot 1");
+        assert_eq!(english.translate("age.minutes", &args), "3 min ago");
+
+        let french = Localization::load(&dir, "fr");
+        assert_eq!(french.language(), "fr");
+        assert_eq!(french.translate("saves.saved", &args), "slot 1 sauvegardé");
+        // missing in French
+        assert_eq!(french.translate("age.minutes", &args), "3 min ago");
+        // missing everywhere
+        assert_eq!(french.translate("nowhere", &args), "nowhere");
+
+        // an unknown language is all fallback
+        let klingon = Localization::load(&dir, "tlh");
+        assert_eq!(klingon.translate("saves.saved", &args), "Saved slot 1");
+
+        assert_eq!(
+            Localization::languages(&dir),
+            [
+                ("en".to_string(), "English".to_string()),
+                ("fr".to_string(), "Français".to_string())
+            ]
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn substitution_keeps_unknown_placeholders() {
+        let args = [("name", "Bob".to_string())];
+        assert_eq!(substitute("hi {name}, {name}!", &args), "hi Bob, Bob!");
+        assert_eq!(substitute("{other} {name", &args), "{other} {name");
+        assert_eq!(substitute("{}", &args), "{}");
+    }
+
+    #[test]
+    fn every_language_has_the_english_keys() {
+        let dir = Path::new(LANG_DIR);
+        let english = read_table(dir, FALLBACK_LANGUAGE);
+        assert!(english.contains_key("saves.saved"));
+        for (code, _) in Localization::languages(dir) {
+            let table = read_table(dir, &code);
+            let missing: Vec<_> = english
+                .keys()
+                .filter(|key| !table.contains_key(*key))
+                .collect();
+            assert!(missing.is_empty(), "{code} misses {missing:?}");
+        }
+    }
+
+    #[test]
+    fn map_texts_with_the_prefix_are_keys() {
+        assert_eq!(localized("Hello there"), "Hello there");
+        assert_eq!(localized("@saves.title"), "Saves");
+    }
+}
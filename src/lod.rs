@@ -0,0 +1,585 @@
+//! Far view of the tile layers for when the camera is zoomed far out.
+//!
+//! Every tile layer gets a "far" image with one pixel per tile, the average color of the tile's
+//! texture, built once its tiles and tileset image are there. Past `LodSettings::far_scale` the
+//! tilemaps are hidden and a quad per layer shows its far image stretched over the map instead.
+//! The swap back happens a bit below the threshold, so a zoom resting on it doesn't flicker.
+//!
+//! `lod bench [frames]`, typed while zoomed out past the threshold, measures what the far view
+//! saves: it times `frames` frames with the far view off, then as many with it on, and prints both
+//! frame times in the console.
+
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::utils::HashMap;
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::camera::MainCamera;
+use crate::console::{ConsoleCommand, DebugConsole};
+use crate::game::GameSet;
+use crate::helpers::tiled::TiledLayer;
+use crate::perf::timed;
+use crate::state::level_ready;
+
+/// Plugin that builds the far images of the tile layers and swaps them in when zoomed out
+#[derive(Default)]
+pub struct LodPlugin;
+
+impl Plugin for LodPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LodSettings>()
+            .register_type::<LodSettings>()
+            .init_resource::<LodState>()
+            .init_resource::<LodBench>()
+            .add_systems(Update, lod_command.in_set(GameSet::Input))
+            .add_systems(
+                Update,
+                (
+                    timed!(build_far_layers),
+                    apply_deferred,
+                    run_lod_bench,
+                    timed!(swap_lod),
+                )
+                    .chain()
+                    .in_set(GameSet::RenderPrep)
+                    .run_if(level_ready),
+            );
+    }
+}
+
+/// Frames `lod bench` times in each view without a count
+const BENCH_FRAMES: usize = 300;
+/// Frames left untimed after each switch, for the frame times to settle
+const BENCH_WARMUP: usize = 30;
+
+/// When the far view replaces the tilemaps
+#[derive(Resource, Reflect, Debug, Clone, PartialEq)]
+#[reflect(Resource)]
+pub struct LodSettings {
+    pub enabled: bool,
+    /// Camera scale past which the far view is shown
+    pub far_scale: f32,
+    /// Share of `far_scale` the zoom has to go past the threshold to swap either way
+    pub hysteresis: f32,
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            far_scale: 8.,
+            hysteresis: 0.1,
+        }
+    }
+}
+
+impl LodSettings {
+    /// Whether to show the far view at camera `scale`, `far` being whether it's shown now
+    pub fn far_at(&self, far: bool, scale: f32) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let margin = self.far_scale * self.hysteresis.max(0.);
+        if far {
+            scale > self.far_scale - margin
+        } else {
+            scale > self.far_scale + margin
+        }
+    }
+}
+
+/// Whether the far view is shown
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LodState {
+    pub far: bool,
+}
+
+/// The `lod bench` running, if any
+#[derive(Resource, Default, Debug)]
+pub struct LodBench {
+    run: Option<BenchRun>,
+}
+
+/// Frame times of a `lod bench`, first with the far view off, then on
+#[derive(Debug, Clone, PartialEq)]
+struct BenchRun {
+    /// Frames timed in each view
+    frames: usize,
+    /// Frames seen so far, the untimed ones included
+    seen: usize,
+    /// Frame times in milliseconds with the far view off
+    off: Vec<f32>,
+    /// Frame times in milliseconds with the far view on
+    on: Vec<f32>,
+    /// `LodSettings::enabled` before the bench, put back after it
+    was_enabled: bool,
+}
+
+impl BenchRun {
+    fn new(frames: usize, was_enabled: bool) -> Self {
+        Self {
+            frames,
+            seen: 0,
+            off: Vec::with_capacity(frames),
+            on: Vec::with_capacity(frames),
+            was_enabled,
+        }
+    }
+
+    /// Whether the far view is allowed for the next frame
+    fn lod_enabled(&self) -> bool {
+        self.seen >= BENCH_WARMUP + self.frames
+    }
+
+    /// Record the time of a frame, true once both views are timed
+    fn record(&mut self, frame_ms: f32) -> bool {
+        let phase_len = BENCH_WARMUP + self.frames;
+        if self.seen % phase_len >= BENCH_WARMUP {
+            if self.seen < phase_len {
+                self.off.push(frame_ms);
+            } else {
+                self.on.push(frame_ms);
+            }
+        }
+        self.seen += 1;
+        self.seen >= 2 * phase_len
+    }
+
+    /// The console lines reporting the bench
+    fn report(&self) -> Vec<String> {
+        let off = FrameStats::of(&self.off);
+        let on = FrameStats::of(&self.on);
+        let saved = off.mean_ms - on.mean_ms;
+        vec![
+            format!("tile view: {off}"),
+            format!("far view: {on}"),
+            format!(
+                "the far view saves {saved:.2} ms per frame ({:.0}%)",
+                100. * saved / off.mean_ms.max(f32::EPSILON)
+            ),
+        ]
+    }
+}
+
+/// Mean and 95th percentile of frame times
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FrameStats {
+    mean_ms: f32,
+    p95_ms: f32,
+}
+
+impl FrameStats {
+    fn of(frames_ms: &[f32]) -> Self {
+        if frames_ms.is_empty() {
+            return Self {
+                mean_ms: 0.,
+                p95_ms: 0.,
+            };
+        }
+        let mut sorted = frames_ms.to_vec();
+        sorted.sort_by(f32::total_cmp);
+        let p95 = (sorted.len() * 95).div_ceil(100).max(1) - 1;
+        Self {
+            mean_ms: sorted.iter().sum::<f32>() / sorted.len() as f32,
+            p95_ms: sorted[p95],
+        }
+    }
+}
+
+impl std::fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{:.2} ms per frame, 95% under {:.2} ms",
+            self.mean_ms, self.p95_ms
+        )
+    }
+}
+
+/// The far quad of a tile layer
+#[derive(Component, Debug)]
+struct FarView {
+    quad: Entity,
+}
+
+/// A quad showing the far image of `layer`
+#[derive(Component, Debug)]
+struct FarQuad {
+    layer: Entity,
+}
+
+/// Average color of the `rect` pixels of an RGBA8 `image`, weighted by alpha so the transparent
+/// pixels around a sprite don't darken it. `None` for other formats or a rect outside the image.
+pub fn average_color(image: &Image, rect: URect) -> Option<[u8; 4]> {
+    if !matches!(
+        image.texture_descriptor.format,
+        TextureFormat::Rgba8UnormSrgb | TextureFormat::Rgba8Unorm
+    ) {
+        return None;
+    }
+    let width = image.texture_descriptor.size.width;
+    let height = image.texture_descriptor.size.height;
+    if rect.max.x > width || rect.max.y > height || rect.is_empty() {
+        return None;
+    }
+    let mut rgb = [0u64; 3];
+    let mut alpha = 0u64;
+    for y in rect.min.y..rect.max.y {
+        for x in rect.min.x..rect.max.x {
+            let at = ((y * width + x) * 4) as usize;
+            let pixel = image.data.get(at..at + 4)?;
+            let a = pixel[3] as u64;
+            for (sum, channel) in rgb.iter_mut().zip(pixel) {
+                *sum += *channel as u64 * a;
+            }
+            alpha += a;
+        }
+    }
+    if alpha == 0 {
+        return Some([0; 4]);
+    }
+    let count = rect.size().x as u64 * rect.size().y as u64;
+    Some([
+        (rgb[0] / alpha) as u8,
+        (rgb[1] / alpha) as u8,
+        (rgb[2] / alpha) as u8,
+        (alpha / count) as u8,
+    ])
+}
+
+/// Pixel rect of tile `index` in a tileset image `image_width` wide
+fn tile_rect(index: u32, image_width: u32, tile_size: UVec2, spacing: UVec2) -> URect {
+    let columns = ((image_width + spacing.x) / (tile_size.x + spacing.x)).max(1);
+    let min = UVec2::new(index % columns, index / columns) * (tile_size + spacing);
+    URect::from_corners(min, min + tile_size)
+}
+
+/// RGBA8 pixels of a far image, one per tile and the first row at the top of the map
+pub fn far_pixels(
+    size: TilemapSize,
+    tiles: impl IntoIterator<Item = (TilePos, [u8; 4])>,
+) -> Vec<u8> {
+    let mut data = vec![0; size.count() * 4];
+    for (pos, color) in tiles {
+        if !pos.within_map_bounds(&size) {
+            continue;
+        }
+        let row = size.y - 1 - pos.y;
+        let at = ((row * size.x + pos.x) * 4) as usize;
+        data[at..at + 4].copy_from_slice(&color);
+    }
+    data
+}
+
+#[allow(clippy::type_complexity)]
+fn build_far_layers(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    layers: Query<
+        (
+            Entity,
+            &TileStorage,
+            &TilemapTexture,
+            &TilemapTileSize,
+            &TilemapSpacing,
+            &TilemapGridSize,
+            &TilemapType,
+            &Transform,
+            Option<&FarView>,
+        ),
+        (
+            With<TiledLayer>,
+            Or<(Without<FarView>, Changed<TileStorage>)>,
+        ),
+    >,
+    tiles: Query<(&TilePos, &TileTextureIndex, &TileVisible)>,
+    quads: Query<(Entity, &FarQuad)>,
+) {
+    // the quads of the layers despawned with their level
+    for (quad, far_quad) in &quads {
+        if commands.get_entity(far_quad.layer).is_none() {
+            commands.entity(quad).despawn();
+        }
+    }
+
+    for (layer, storage, texture, tile_size, spacing, grid_size, map_type, transform, far_view) in
+        &layers
+    {
+        let tileset = match texture {
+            TilemapTexture::Single(tileset) => tileset,
+            // only without the tilemap's `atlas` feature
+            #[allow(unreachable_patterns)]
+            _ => continue,
+        };
+        // the tiles are spawned with the layer, the image may still be loading: try again later
+        let Some(tileset) = images.get(tileset) else {
+            continue;
+        };
+        let mut tile_colors = HashMap::default();
+        let tile_size_px = UVec2::new(tile_size.x as u32, tile_size.y as u32);
+        let spacing_px = UVec2::new(spacing.x as u32, spacing.y as u32);
+        let colors: Vec<_> = storage
+            .iter()
+            .flatten()
+            .filter_map(|tile| {
+                let (pos, index, visible) = tiles.get(*tile).ok()?;
+                if !visible.0 {
+                    return None;
+                }
+                let color = *tile_colors.entry(index.0).or_insert_with(|| {
+                    let rect = tile_rect(index.0, tileset.width(), tile_size_px, spacing_px);
+                    average_color(tileset, rect).unwrap_or([0; 4])
+                });
+                Some((*pos, color))
+            })
+            .collect();
+
+        let size = storage.size;
+        let image = images.add(Image::new(
+            Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            far_pixels(size, colors),
+            TextureFormat::Rgba8UnormSrgb,
+        ));
+        let first = TilePos::new(0, 0).center_in_world(grid_size, map_type);
+        let last = TilePos::new(size.x - 1, size.y - 1).center_in_world(grid_size, map_type);
+        let grid = Vec2::new(grid_size.x, grid_size.y);
+        let center = transform.transform_point(((first + last) / 2.).extend(0.));
+
+        if let Some(far_view) = far_view {
+            commands.entity(far_view.quad).despawn();
+        }
+        let quad = commands
+            .spawn((
+                SpriteBundle {
+                    texture: image,
+                    sprite: Sprite {
+                        custom_size: Some((last - first + grid) * transform.scale.truncate()),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(center),
+                    visibility: Visibility::Hidden,
+                    ..default()
+                },
+                FarQuad { layer },
+                Name::new("far layer"),
+            ))
+            .id();
+        commands.entity(layer).insert(FarView { quad });
+    }
+}
+
+fn swap_lod(
+    settings: Res<LodSettings>,
+    mut state: ResMut<LodState>,
+    cameras: Query<&OrthographicProjection, With<MainCamera>>,
+    mut layers: Query<&mut Visibility, (With<FarView>, Without<FarQuad>)>,
+    mut quads: Query<&mut Visibility, With<FarQuad>>,
+) {
+    let Ok(projection) = cameras.get_single() else {
+        return;
+    };
+    let far = settings.far_at(state.far, projection.scale);
+    if far != state.far {
+        info!(
+            "switching to the {} view at scale {:.1}",
+            if far { "far" } else { "tile" },
+            projection.scale
+        );
+        state.far = far;
+    }
+
+    let (layer_visibility, quad_visibility) = if far {
+        (Visibility::Hidden, Visibility::Inherited)
+    } else {
+        (Visibility::Inherited, Visibility::Hidden)
+    };
+    for mut visibility in &mut layers {
+        visibility.set_if_neq(layer_visibility);
+    }
+    for mut visibility in &mut quads {
+        visibility.set_if_neq(quad_visibility);
+    }
+}
+
+/// `lod bench [frames]` starts a bench, when zoomed out far enough for the far view
+fn lod_command(
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<DebugConsole>,
+    mut bench: ResMut<LodBench>,
+    settings: Res<LodSettings>,
+    cameras: Query<&OrthographicProjection, With<MainCamera>>,
+    layers: Query<&TileStorage, With<TiledLayer>>,
+) {
+    for command in console_commands.read() {
+        if command.name != "lod" {
+            continue;
+        }
+        let args: Vec<_> = command.args.iter().map(String::as_str).collect();
+        let frames = match args.as_slice() {
+            ["bench"] => BENCH_FRAMES,
+            ["bench", frames] => match frames.parse() {
+                Ok(frames) if frames > 0 => frames,
+                _ => {
+                    console.print("frames must be a positive number");
+                    continue;
+                }
+            },
+            _ => {
+                console.print("usage: lod bench [frames]");
+                continue;
+            }
+        };
+        if bench.run.is_some() {
+            console.print("a bench is already running");
+            continue;
+        }
+        let enabled = LodSettings {
+            enabled: true,
+            ..settings.clone()
+        };
+        let Ok(projection) = cameras.get_single() else {
+            continue;
+        };
+        if !enabled.far_at(false, projection.scale) {
+            console.print(format!(
+                "zoom out past scale {:.1} first, the camera is at {:.1}",
+                settings.far_scale * (1. + settings.hysteresis.max(0.)),
+                projection.scale
+            ));
+            continue;
+        }
+        let tiles: usize = layers.iter().map(|storage| storage.size.count()).sum();
+        console.print(format!(
+            "timing {frames} frames in each view, over {tiles} tiles in {} layers",
+            layers.iter().len()
+        ));
+        bench.run = Some(BenchRun::new(frames, settings.enabled));
+    }
+}
+
+/// Time the frames of the running bench, switching the far view off and on for it
+fn run_lod_bench(
+    time: Res<Time<Real>>,
+    mut bench: ResMut<LodBench>,
+    mut settings: ResMut<LodSettings>,
+    mut console: ResMut<DebugConsole>,
+) {
+    let Some(run) = &mut bench.run else {
+        return;
+    };
+    // the warmup also skips the first frames, timed before the view switched
+    if run.record(time.delta_seconds() * 1000.) {
+        for line in run.report() {
+            console.print(line);
+        }
+        settings.enabled = run.was_enabled;
+        bench.run = None;
+        return;
+    }
+    settings.enabled = run.lod_enabled();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swapping_is_hysteretic() {
+        let settings = LodSettings {
+            enabled: true,
+            far_scale: 8.,
+            hysteresis: 0.1,
+        };
+        assert!(!settings.far_at(false, 8.5));
+        assert!(settings.far_at(false, 8.9));
+        // back under the threshold, but not far enough to swap back
+        assert!(settings.far_at(true, 7.5));
+        assert!(!settings.far_at(true, 7.1));
+
+        let disabled = LodSettings {
+            enabled: false,
+            ..settings
+        };
+        assert!(!disabled.far_at(true, 20.));
+    }
+
+    fn tileset() -> Image {
+        // two 2x2 tiles side by side with 1 pixel of spacing: red, then half transparent blue
+        let mut data = Vec::new();
+        for _ in 0..2 {
+            data.extend_from_slice(&[255, 0, 0, 255, 255, 0, 0, 255, 0, 0, 0, 0]);
+            data.extend_from_slice(&[0, 0, 255, 255, 0, 0, 255, 0]);
+        }
+        Image::new(
+            Extent3d {
+                width: 5,
+                height: 2,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba8UnormSrgb,
+        )
+    }
+
+    #[test]
+    fn tiles_average_their_opaque_pixels() {
+        let image = tileset();
+        let rect = |index| tile_rect(index, 5, UVec2::splat(2), UVec2::ONE);
+        assert_eq!(rect(1), URect::new(3, 0, 5, 2));
+        assert_eq!(average_color(&image, rect(0)), Some([255, 0, 0, 255]));
+        assert_eq!(average_color(&image, rect(1)), Some([0, 0, 255, 127]));
+        assert_eq!(average_color(&image, URect::new(4, 0, 6, 2)), None);
+    }
+
+    #[test]
+    fn far_pixels_put_the_top_row_first() {
+        let size = TilemapSize { x: 2, y: 2 };
+        let red = [255, 0, 0, 255];
+        let data = far_pixels(size, [(TilePos::new(1, 1), red), (TilePos::new(5, 0), red)]);
+        assert_eq!(data.len(), 16);
+        assert_eq!(&data[4..8], &red);
+        assert!(data[8..].iter().all(|channel| *channel == 0));
+    }
+
+    #[test]
+    fn bench_times_each_view_after_a_warmup() {
+        let mut run = BenchRun::new(2, true);
+        let mut enabled = Vec::new();
+        let mut frame = 0.;
+        loop {
+            enabled.push(run.lod_enabled());
+            frame += 1.;
+            if run.record(frame) {
+                break;
+            }
+        }
+        assert_eq!(enabled.len(), 2 * (BENCH_WARMUP + 2));
+        assert_eq!(enabled.iter().filter(|on| **on).count(), BENCH_WARMUP + 2);
+        let warmup = BENCH_WARMUP as f32;
+        assert_eq!(run.off, vec![warmup + 1., warmup + 2.]);
+        assert_eq!(run.on, vec![2. * warmup + 3., 2. * warmup + 4.]);
+    }
+
+    #[test]
+    fn frame_stats_take_the_mean_and_the_95th_percentile() {
+        let frames: Vec<_> = (1..=100).map(|ms| ms as f32).collect();
+        let stats = FrameStats::of(&frames);
+        assert_eq!(stats.mean_ms, 50.5);
+        assert_eq!(stats.p95_ms, 95.);
+        assert_eq!(FrameStats::of(&[4.]).p95_ms, 4.);
+
+        let mut run = BenchRun::new(1, true);
+        run.off = vec![20.];
+        run.on = vec![5.];
+        assert_eq!(
+            run.report()[2],
+            "the far view saves 15.00 ms per frame (75%)"
+        );
+    }
+}
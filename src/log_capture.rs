@@ -0,0 +1,127 @@
+//! Keeps the last log lines in memory so they can go into bug reports.
+//!
+//! The `LogPlugin` of this bevy version can't take extra layers, so `main` disables it and sets up
+//! the same logging with `install_subscriber`, the capture layer added. The lines it captures are
+//! read through the `CapturedLog` resource.
+
+use std::collections::VecDeque;
+use std::fmt::{self, Write};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use bevy::log::LogPlugin;
+use bevy::prelude::*;
+use bevy::utils::tracing::field::{Field, Visit};
+use bevy::utils::tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+/// Log lines kept, the oldest are dropped first
+pub const CAPTURED_LINES: usize = 20;
+
+/// Where the layer installed by `install_capture_layer` writes
+static CAPTURED: OnceLock<CapturedLog> = OnceLock::new();
+
+/// Plugin that makes the captured log lines available as the `CapturedLog` resource
+#[derive(Default)]
+pub struct LogCapturePlugin;
+
+impl Plugin for LogCapturePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(captured_log());
+    }
+}
+
+/// The last `CAPTURED_LINES` log lines, shared with the tracing layer writing them
+#[derive(Resource, Clone, Default, Debug)]
+pub struct CapturedLog(Arc<Mutex<VecDeque<String>>>);
+
+impl CapturedLog {
+    pub fn push(&self, line: String) {
+        let Ok(mut lines) = self.0.lock() else {
+            return;
+        };
+        if lines.len() == CAPTURED_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    /// The captured lines, oldest first
+    pub fn lines(&self) -> Vec<String> {
+        self.0
+            .lock()
+            .map(|lines| lines.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// The log shared by the capture layer and `LogCapturePlugin`
+pub fn captured_log() -> CapturedLog {
+    CAPTURED.get_or_init(CapturedLog::default).clone()
+}
+
+/// Install the global subscriber `LogPlugin` would with `settings`, plus the capture layer
+pub fn install_subscriber(settings: &LogPlugin) {
+    let default_filter = format!("{},{}", settings.level, settings.filter);
+    let filter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new(&default_filter))
+        .unwrap();
+    let installed = Registry::default()
+        .with(filter)
+        .with(tracing_subscriber::fmt::Layer::default().with_writer(std::io::stderr))
+        .with(CaptureLayer(captured_log()))
+        .try_init();
+    if let Err(err) = installed {
+        warn!("Could not set the global tracing subscriber: {err}");
+    }
+}
+
+/// Tracing layer writing every event as one line into a `CapturedLog`
+pub struct CaptureLayer(pub CapturedLog);
+
+impl<S: Subscriber> Layer<S> for CaptureLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut line = format!("{} {}:", metadata.level(), metadata.target());
+        event.record(&mut LineVisitor(&mut line));
+        self.0.push(line);
+    }
+}
+
+/// Appends the fields of an event, the message without its name
+struct LineVisitor<'a>(&'a mut String);
+
+impl Visit for LineVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        let _ = if field.name() == "message" {
+            write!(self.0, " {value:?}")
+        } else {
+            write!(self.0, " {}={value:?}", field.name())
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::tracing::subscriber;
+
+    use super::*;
+
+    #[test]
+    fn keeps_the_last_lines() {
+        let log = CapturedLog::default();
+        let subscriber = Registry::default().with(CaptureLayer(log.clone()));
+        subscriber::with_default(subscriber, || {
+            for i in 0..CAPTURED_LINES + 5 {
+                info!(tile = i, "moved");
+            }
+        });
+
+        let lines = log.lines();
+        assert_eq!(lines.len(), CAPTURED_LINES);
+        assert!(lines[0].starts_with("INFO "));
+        assert!(lines[0].ends_with(": moved tile=5"));
+        assert!(lines[CAPTURED_LINES - 1].ends_with(&format!("tile={}", CAPTURED_LINES + 4)));
+    }
+}
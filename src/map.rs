@@ -0,0 +1,683 @@
+//! Loading the Tiled map and spawning what it contains.
+//!
+//! Object types spawned:
+//! - `spawn`: the player
+//! - `npc`: a creature, with the dialogue from its properties
+//! - `item`: an item to pick up, see `spawn_item`
+//! - `group_spawn`: `count` creatures in a `formation` around the object, see `groups`
+//!
+//! Properties read from the objects:
+//! - `requires_flag`, `forbids_flag`, `min_difficulty`: only spawn the object under these
+//!   conditions, see `spawn_conditions`
+//! - `item_id`: id of an item, the object name when missing
+//! - `template`: entity template of a creature, whose fields are defaults for the properties
+//!   below, see `templates`
+//! - `atlas_index`: sprite of an item, or still sprite of a creature
+//! - `health`, `max_health`: hit points of a creature, see `health`
+//! - `creature_row`: row of the creature atlas holding the animation of a creature, 0 when
+//!   missing or out of range
+//! - `aggro_radius`: tiles within which an npc chases the player, see `hostile`
+//!
+//! After a trip through a portal to this map, the player spawns on the portal named by the
+//! `PendingArrival` instead of the spawn object, see `map_switch`.
+//!
+//! Properties read from the map:
+//! - `persistent_flags`: comma separated game flags kept when the player respawns
+//! - `streaming`: spawn the tiles in chunks around the camera, see `helpers::chunks`
+//! - `creature_sheet`: image the creatures and items of the map are cut from instead of the
+//!   default creature atlas, see `sprite_atlas::CreatureSheet`
+
+use bevy::prelude::*;
+use bevy_asset_loader::asset_collection::AssetCollection;
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::animation::{self, AnimationClip, AnimationFrame, AnimationTimer};
+use crate::coords::{self, MapFrame};
+use crate::dialogue::Dialogue;
+use crate::flags::GameFlags;
+use crate::game::{GameSet, LevelLifecycleSet};
+use crate::health::Health;
+use crate::health_bar::HealthBarStyle;
+use crate::helpers;
+use crate::hostile::Hostile;
+use crate::interact::Interactable;
+use crate::inventory::{Item, ItemIcons};
+use crate::layers::LayerZConfig;
+use crate::localization::tr;
+use crate::map_switch::PendingArrival;
+use crate::player::{MainPlayer, SpawnPoint};
+use crate::replication::Replicated;
+use crate::spawn_conditions::{self, DeferredSpawns, Difficulty};
+use crate::sprite_atlas::CurrentCreatureAtlas;
+use crate::sprite_scale::{self, SpriteScaleMode, SpriteScaleOverride};
+use crate::state::{AppState, LevelReady};
+use crate::templates::{self, EntityTemplates};
+use crate::toast::Toasts;
+use crate::world_label::{self, WorldAnchoredLabel};
+
+/// Plugin that loads the Tiled map, builds its collision and spawns the level from its objects
+#[derive(Default)]
+pub struct MapPlugin;
+
+impl Plugin for MapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            TilemapPlugin,
+            helpers::tiled::TiledMapPlugin,
+            crate::collision::CollisionPlugin,
+            crate::layers::LayerZPlugin,
+        ));
+        add_level_systems(app);
+    }
+}
+
+/// Add the systems setting up, spawning and tearing down the level of the current map, also
+/// used by tests needing a whole level
+///
+/// The level's `Update` systems run on `level_ready` rather than `in_state(AppState::Level)`:
+/// the frame entering the level spawns it with commands and the tilemaps once their map entity
+/// shows up, so the level is only whole from the next frame on.
+pub fn add_level_systems(app: &mut App) {
+    app.init_resource::<LevelReady>()
+        .add_systems(OnExit(AppState::Loading), setup_map_frame)
+        .add_systems(OnExit(AppState::SwitchingMap), setup_map_frame)
+        .add_systems(
+            OnEnter(AppState::Level),
+            spawn_level.in_set(LevelLifecycleSet),
+        )
+        .add_systems(
+            OnExit(AppState::Level),
+            despawn_level.in_set(LevelLifecycleSet),
+        )
+        .add_systems(
+            Update,
+            mark_level_ready
+                .in_set(GameSet::RenderPrep)
+                .after(helpers::tiled::process_loaded_maps)
+                .run_if(in_state(AppState::Level)),
+        )
+        .add_systems(OnExit(AppState::Level), clear_level_ready);
+}
+
+/// The level's spawns are applied at the end of the frame, let its systems run from the next one
+fn mark_level_ready(mut ready: ResMut<LevelReady>) {
+    if !ready.0 {
+        ready.0 = true;
+    }
+}
+
+fn clear_level_ready(mut ready: ResMut<LevelReady>) {
+    ready.0 = false;
+}
+
+/// A creature spawned from a Tiled object of type "npc"
+#[derive(Component)]
+pub struct Npc;
+
+#[derive(AssetCollection, Resource)]
+pub struct GameInfoAlt {
+    /// The creature sprites, set from one of the two keys below by `sprite_atlas`
+    pub creature_atlas: Handle<TextureAtlas>,
+    #[asset(key = "atlas.creatures", optional)]
+    pub creature_sheet: Option<Handle<TextureAtlas>>,
+    #[asset(key = "images.creatures_folder", optional, collection(typed))]
+    pub creature_folder: Option<Vec<Handle<Image>>>,
+    #[asset(key = "map.main")]
+    pub tile_map: Handle<helpers::tiled::TiledMap>,
+    #[asset(key = "templates")]
+    pub templates: Handle<EntityTemplates>,
+}
+
+impl GameInfoAlt {
+    /// Asset path of the current map, with forward slashes, as the saves hold it
+    pub fn map_path(&self) -> Option<String> {
+        let path = self.tile_map.path()?.path();
+        Some(path.to_string_lossy().replace('\\', "/"))
+    }
+}
+
+/// Capture where the map will live in the world before any of the level spawns run
+fn setup_map_frame(
+    mut commands: Commands,
+    game_info: Res<GameInfoAlt>,
+    tile_maps: Res<Assets<helpers::tiled::TiledMap>>,
+    mut toasts: Toasts,
+) {
+    let Some(map) = tile_maps.get(&game_info.tile_map) else {
+        warn!("can't find tile map for map frame setup!");
+        toasts.error(tr!("toast.map_failed"));
+        return;
+    };
+    let transform =
+        Transform::from_scale(Vec3::splat(1.0)).with_translation(Vec3::new(0.0, 0.0, 0.1));
+    commands.insert_resource(MapFrame::from_tiled_map(&map.map, transform));
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_level(
+    mut commands: Commands,
+    game_info: Res<GameInfoAlt>,
+    creature_atlas: Res<CurrentCreatureAtlas>,
+    tile_maps: Res<Assets<helpers::tiled::TiledMap>>,
+    atlases: Res<Assets<TextureAtlas>>,
+    entity_templates: Res<Assets<EntityTemplates>>,
+    map_frame: Res<MapFrame>,
+    z_config: Res<LayerZConfig>,
+    mut flags: ResMut<GameFlags>,
+    difficulty: Res<Difficulty>,
+    mut deferred: ResMut<DeferredSpawns>,
+    mut item_icons: ResMut<ItemIcons>,
+    arrival: Option<Res<PendingArrival>>,
+    mut toasts: Toasts,
+    mut state: ResMut<NextState<AppState>>,
+) {
+    info!("spawn_level");
+    let arrival = arrival.map(|arrival| arrival.portal_name.clone());
+    commands.remove_resource::<PendingArrival>();
+    // the conditions are checked afresh every time the level spawns
+    deferred.0.clear();
+
+    commands.spawn(helpers::tiled::TiledMapBundle {
+        tiled_map: game_info.tile_map.clone(),
+        streaming: tile_maps.get(&game_info.tile_map).map_or_else(default, |map| {
+            helpers::tiled::MapStreaming::from_properties(&map.map.properties)
+        }),
+        transform: map_frame.transform,
+        ..Default::default()
+    });
+
+    // let mut _camera_pos = Vec2::default();
+    // let mut map_size = Vec2::default();
+
+    // spawn characters
+    if let Some(map) = tile_maps.get(&game_info.tile_map) {
+        // map_size = Vec2::new(
+        //     ((map.map.width - 1) * map.map.tile_width) as f32,
+        //     ((map.map.height - 1) * map.map.tile_height) as f32,
+        // );
+        if let Some(tiled::PropertyValue::StringValue(keys)) =
+            map.map.properties.get("persistent_flags")
+        {
+            for key in keys.split(',').map(str::trim).filter(|key| !key.is_empty()) {
+                flags.mark_persistent(key);
+            }
+        }
+
+        let arrival_pos = arrival.and_then(|name| {
+            let pos = portal_center(&map.map, &map_frame, &name);
+            if pos.is_none() {
+                warn!("there is no portal {name} to arrive on, using the spawn object");
+                toasts.warn(tr!("toast.portal_missing", name = name));
+            }
+            pos
+        });
+
+        info!("spawn objects");
+        let tile_layers = helpers::tiled::all_layers(&map.map)
+            .filter_map(|layer| match layer.layer_type() {
+                tiled::LayerType::Objects(layer) => Some(layer),
+                _ => None,
+            });
+
+        for layer in tile_layers {
+            //my_renderer.render(layer);
+            for object in layer.objects() {
+                let spawned = ["item", "spawn", "npc"]
+                    .iter()
+                    .any(|kind| object.user_type.eq_ignore_ascii_case(kind));
+                if !object.visible || !spawned {
+                    continue;
+                }
+                if !spawn_conditions::conditions_met(&object.properties, &flags, *difficulty) {
+                    info!(
+                        "skipping {} until its spawn conditions are met",
+                        object.name
+                    );
+                    deferred.0.push(object.id());
+                    continue;
+                }
+                spawn_map_object(
+                    &mut commands,
+                    &creature_atlas.0,
+                    &atlases,
+                    entity_templates.get(&game_info.templates),
+                    &map_frame,
+                    &z_config,
+                    &mut item_icons,
+                    &object,
+                    arrival_pos,
+                );
+            }
+        }
+    }
+
+    state.set(AppState::Level);
+}
+
+/// Remove the map, its layers and the objects spawned from it, before the level of another map
+fn despawn_level(
+    mut commands: Commands,
+    maps: Query<Entity, With<Handle<helpers::tiled::TiledMap>>>,
+    layers: Query<(Entity, &TileStorage), With<helpers::tiled::TiledLayer>>,
+    // the nested groups go with the outer ones
+    group_layers: Query<Entity, (With<helpers::tiled::TiledGroupLayer>, Without<Parent>)>,
+    objects: Query<Entity, With<helpers::tiled::TiledObject>>,
+) {
+    for (layer, storage) in &layers {
+        for tile in storage.iter().flatten() {
+            commands.entity(*tile).despawn_recursive();
+        }
+        commands.entity(layer).despawn_recursive();
+    }
+    for entity in maps.iter().chain(&group_layers).chain(&objects) {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// The world position of the center of the tile of the portal object named `name`
+fn portal_center(map: &tiled::Map, map_frame: &MapFrame, name: &str) -> Option<Vec2> {
+    let portal = helpers::tiled::all_layers(map)
+        .filter_map(|layer| match layer.layer_type() {
+            tiled::LayerType::Objects(layer) => Some(layer),
+            _ => None,
+        })
+        .flat_map(|layer| layer.objects().collect::<Vec<_>>())
+        .find(|object| object.user_type.eq_ignore_ascii_case("portal") && object.name == name)?;
+    let tile = coords::object_tile(map_frame, &portal)?;
+    coords::tile_to_world_center(map_frame, &tile)
+}
+
+/// Spawn the creature or item of a Tiled object where the map puts it, the player at `arrival`
+/// when there's one
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_map_object(
+    commands: &mut Commands,
+    creature_atlas: &Handle<TextureAtlas>,
+    atlases: &Assets<TextureAtlas>,
+    entity_templates: Option<&EntityTemplates>,
+    map_frame: &MapFrame,
+    z_config: &LayerZConfig,
+    item_icons: &mut ItemIcons,
+    object: &tiled::Object,
+    arrival: Option<Vec2>,
+) -> Option<Entity> {
+    if object.user_type.eq_ignore_ascii_case("item") {
+        return spawn_item(
+            commands,
+            creature_atlas,
+            atlases,
+            map_frame,
+            object,
+            item_icons,
+        );
+    }
+    info!("spawning {}\n", object.name);
+    let is_player = object.user_type.eq_ignore_ascii_case("spawn");
+    let pos = match arrival {
+        Some(arrival) if is_player => arrival,
+        _ => {
+            let Some(pos) = coords::tiled_px_to_world(map_frame, Vec2::new(object.x, object.y))
+            else {
+                warn!("spawn object {} is outside the map", object.name);
+                return None;
+            };
+            pos
+        }
+    };
+    let feet = sprite_scale::feet_position(map_frame, pos);
+    let z = z_config.creature_z(map_frame, feet);
+    Some(spawn_creature(
+        commands,
+        creature_atlas,
+        atlases,
+        entity_templates,
+        object,
+        pos.extend(z),
+        0,
+    ))
+}
+
+/// Spawn the creature of a Tiled object of type "spawn" or "npc" at `translation` with the
+/// sprites of `creature_atlas`, showing the frame `animation_frame` of its animation
+pub fn spawn_creature(
+    commands: &mut Commands,
+    creature_atlas: &Handle<TextureAtlas>,
+    atlases: &Assets<TextureAtlas>,
+    entity_templates: Option<&EntityTemplates>,
+    object: &tiled::Object,
+    translation: Vec3,
+    animation_frame: i32,
+) -> Entity {
+    let properties = templates::object_properties(entity_templates, object);
+    let atlas = atlases.get(creature_atlas);
+    let clip = creature_clip(&object.name, &properties, atlas);
+    let frame = animation_frame.rem_euclid(clip.frames.len() as i32);
+    let mut creature = commands.spawn((
+        SpriteSheetBundle {
+            texture_atlas: creature_atlas.clone(),
+            sprite: animation::spawn_sprite(&object.name, clip.frames[frame as usize], atlas),
+            transform: Transform::from_translation(translation),
+            ..default()
+        },
+        AnimationFrame(frame),
+        clip,
+        AnimationTimer(Timer::from_seconds(0.2, TimerMode::Repeating)),
+        Name::new(object.name.clone()),
+        helpers::tiled::TiledObject { id: object.id() },
+        Replicated,
+    ));
+    if object.user_type.eq_ignore_ascii_case("spawn") {
+        creature.insert((MainPlayer, SpawnPoint(translation)));
+    } else {
+        creature.insert((
+            Npc,
+            Interactable,
+            WorldAnchoredLabel::new(
+                world_label::NAMEPLATE_OFFSET,
+                world_label::NAMEPLATE_MAX_SCALE,
+            ),
+        ));
+        if let Some(dialogue) = Dialogue::from_properties(&properties) {
+            creature.insert(dialogue);
+        }
+        if let Some(hostile) = Hostile::from_properties(&properties) {
+            creature.insert(hostile);
+        }
+    }
+    if let Some(health) = Health::from_properties(&properties) {
+        creature.insert((health, HealthBarStyle::from_properties(&properties)));
+    }
+    if let Some(mode) = SpriteScaleMode::from_properties(&object.name, &properties) {
+        creature.insert(SpriteScaleOverride(mode));
+    }
+    creature.id()
+}
+
+/// The animation of a creature, from the atlas row in its `creature_row` property, or its still
+/// sprite from its `atlas_index` property
+fn creature_clip(
+    name: &str,
+    properties: &tiled::Properties,
+    atlas: Option<&TextureAtlas>,
+) -> AnimationClip {
+    if let Some(tiled::PropertyValue::IntValue(index)) = properties.get("atlas_index") {
+        match atlas {
+            Some(atlas) if *index >= 0 && (*index as usize) < atlas.len() => {
+                return AnimationClip::new(vec![*index as usize]);
+            }
+            _ => warn!("creature {name} has atlas_index {index}, outside the atlas"),
+        }
+    }
+    let (columns, rows) = atlas.and_then(animation::atlas_grid).unwrap_or((0, 0));
+    let row = match properties.get("creature_row") {
+        Some(tiled::PropertyValue::IntValue(row)) if *row >= 0 && (*row as usize) < rows => {
+            *row as usize
+        }
+        Some(row) => {
+            warn!(
+                "creature {name} has creature_row {row:?}, the atlas has {rows} rows, using row 0"
+            );
+            0
+        }
+        None => 0,
+    };
+    AnimationClip::creature_row(row, columns)
+}
+
+/// Spawn an item object, its id comes from the `item_id` property (or the object name) and its
+/// sprite from the `atlas_index` property
+pub fn spawn_item(
+    commands: &mut Commands,
+    creature_atlas: &Handle<TextureAtlas>,
+    atlases: &Assets<TextureAtlas>,
+    map_frame: &MapFrame,
+    object: &tiled::Object,
+    item_icons: &mut ItemIcons,
+) -> Option<Entity> {
+    let id = match object.properties.get("item_id") {
+        Some(tiled::PropertyValue::StringValue(id)) if !id.is_empty() => id.clone(),
+        _ => object.name.clone(),
+    };
+    let atlas_index = match object.properties.get("atlas_index") {
+        Some(tiled::PropertyValue::IntValue(index)) if *index >= 0 => *index as usize,
+        _ => {
+            warn!("item {} has no atlas_index property", object.name);
+            0
+        }
+    };
+    let Some(pos) = coords::tiled_px_to_world(map_frame, Vec2::new(object.x, object.y)) else {
+        warn!("item object {} is outside the map", object.name);
+        return None;
+    };
+
+    info!("spawning item {}", id);
+    item_icons.0.insert(id.clone(), atlas_index);
+    let item = commands.spawn((
+        SpriteSheetBundle {
+            texture_atlas: creature_atlas.clone(),
+            sprite: animation::spawn_sprite(&object.name, atlas_index, atlases.get(creature_atlas)),
+            transform: Transform::from_translation(pos.extend(1.5)),
+            ..default()
+        },
+        Item { id },
+        Name::new(object.name.clone()),
+        helpers::tiled::TiledObject { id: object.id() },
+        Replicated,
+    ));
+    Some(item.id())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use bevy::ecs::system::{CommandQueue, RunSystemOnce};
+
+    use super::*;
+    use crate::helpers::tiled::parse_tmx;
+    use crate::state::level_ready;
+
+    /// Spawn every object of the map at `path` as a creature where it's placed, with their names
+    fn spawn_creatures(
+        path: &str,
+        entity_templates: Option<&EntityTemplates>,
+    ) -> (World, Vec<(String, Entity)>) {
+        let source = std::fs::read(path).unwrap();
+        let map = parse_tmx(&source, Path::new(path)).unwrap();
+        let mut atlases = Assets::<TextureAtlas>::default();
+        let creature_atlas = atlases.add(TextureAtlas::from_grid(
+            Handle::default(),
+            Vec2::splat(24.),
+            20,
+            27,
+            None,
+            None,
+        ));
+
+        let mut world = World::new();
+        let mut queue = CommandQueue::default();
+        let mut creatures = Vec::new();
+        {
+            let mut commands = Commands::new(&mut queue, &world);
+            for layer in map.layers() {
+                let tiled::LayerType::Objects(objects) = layer.layer_type() else {
+                    continue;
+                };
+                for object in objects.objects() {
+                    let entity = spawn_creature(
+                        &mut commands,
+                        &creature_atlas,
+                        &atlases,
+                        entity_templates,
+                        &object,
+                        Vec3::new(object.x, -object.y, 0.),
+                        0,
+                    );
+                    creatures.push((object.name.clone(), entity));
+                }
+            }
+        }
+        queue.apply(&mut world);
+        (world, creatures)
+    }
+
+    #[test]
+    fn creature_rows_pick_the_animation() {
+        let (world, creatures) =
+            spawn_creatures("assets/maps/TMX/map_test_creature_rows.tmx", None);
+
+        let spawned: Vec<_> = creatures
+            .iter()
+            .map(|(name, entity)| {
+                let sprite = world.get::<TextureAtlasSprite>(*entity).unwrap();
+                let clip = world.get::<AnimationClip>(*entity).unwrap();
+                (name.as_str(), sprite.index, clip.frames.clone())
+            })
+            .collect();
+        assert_eq!(
+            spawned,
+            [
+                ("villager", 0, vec![0, 1]),
+                ("bat", 60, vec![60, 61]),
+                // out of range, back to row 0
+                ("ghost", 0, vec![0, 1]),
+            ]
+        );
+    }
+
+    #[test]
+    fn templates_fill_in_the_object_properties() {
+        let entity_templates: EntityTemplates = ron::from_str(
+            r#"#![enable(implicit_some)]
+            {
+                "bat": (creature_row: 3, health: 4),
+                "statue": (atlas_index: 45),
+            }"#,
+        )
+        .unwrap();
+        let (world, creatures) = spawn_creatures(
+            "assets/maps/TMX/map_test_templates.tmx",
+            Some(&entity_templates),
+        );
+
+        let spawned: Vec<_> = creatures
+            .iter()
+            .map(|(name, entity)| {
+                let clip = world.get::<AnimationClip>(*entity).unwrap();
+                let health = world.get::<Health>(*entity).copied();
+                (name.as_str(), clip.frames.clone(), health)
+            })
+            .collect();
+        assert_eq!(
+            spawned,
+            [
+                ("bat", vec![60, 61], Some(Health::new(4))),
+                // its own health overrides the template's
+                ("old bat", vec![60, 61], Some(Health { current: 2, max: 9 })),
+                ("statue", vec![45], None),
+                // unknown template, spawned from its own properties
+                ("stranger", vec![0, 1], None),
+            ]
+        );
+    }
+
+    #[test]
+    fn creatures_start_their_animations_at_their_own_phase() {
+        let phases = |seed| {
+            let (mut world, creatures) =
+                spawn_creatures("assets/maps/TMX/map_test_creature_rows.tmx", None);
+            world.insert_resource(animation::AnimationSeed(seed));
+            world.run_system_once(animation::offset_animation_phases);
+            creatures
+                .iter()
+                .map(|(_, entity)| world.get::<AnimationTimer>(*entity).unwrap().elapsed())
+                .collect::<Vec<_>>()
+        };
+        let first = phases(7);
+        assert_ne!(first[0], first[1]);
+        // the same on another run with the seed
+        assert_eq!(phases(7), first);
+    }
+
+    /// The level's systems as seen by a system needing the camera and the tilemap of the map
+    #[derive(Resource, Default)]
+    struct LevelSeen {
+        runs: u32,
+        partial: u32,
+    }
+
+    fn spawn_camera(mut commands: Commands) {
+        commands.spawn(crate::camera::MainCamera);
+    }
+
+    /// Spawn the map's tilemap once the map entity shows up, like `process_loaded_maps`
+    fn spawn_tilemap(
+        mut commands: Commands,
+        maps: Query<(), Added<helpers::tiled::TiledLayersStorage>>,
+    ) {
+        if !maps.is_empty() {
+            commands.spawn(TileStorage::empty(TilemapSize { x: 1, y: 1 }));
+        }
+    }
+
+    fn look_at_level(
+        cameras: Query<(), With<crate::camera::MainCamera>>,
+        tilemaps: Query<(), With<TileStorage>>,
+        mut seen: ResMut<LevelSeen>,
+    ) {
+        // the panic the level's systems would hit
+        cameras.single();
+        seen.runs += 1;
+        if tilemaps.is_empty() {
+            seen.partial += 1;
+        }
+    }
+
+    #[test]
+    fn level_systems_wait_for_the_level_to_be_spawned() {
+        let mut app = App::new();
+        crate::game::configure_game_sets(&mut app);
+        app.add_plugins(MinimalPlugins)
+            .add_state::<AppState>()
+            .init_resource::<LevelReady>()
+            .init_resource::<LevelSeen>()
+            .add_systems(
+                OnEnter(AppState::Level),
+                (spawn_camera, |mut commands: Commands| {
+                    commands.spawn(helpers::tiled::TiledMapBundle::default());
+                }),
+            )
+            .add_systems(
+                Update,
+                (
+                    look_at_level.in_set(GameSet::Cursor).run_if(level_ready),
+                    spawn_tilemap.in_set(GameSet::RenderPrep),
+                    mark_level_ready
+                        .in_set(GameSet::RenderPrep)
+                        .after(spawn_tilemap)
+                        .run_if(in_state(AppState::Level)),
+                ),
+            )
+            .add_systems(OnExit(AppState::Level), clear_level_ready);
+        app.update();
+
+        // the transition frame
+        app.world
+            .resource_mut::<NextState<AppState>>()
+            .set(AppState::Level);
+        app.update();
+        assert_eq!(app.world.resource::<LevelSeen>().runs, 0);
+        assert_eq!(*app.world.resource::<LevelReady>(), LevelReady(true));
+
+        app.update();
+        let seen = app.world.resource::<LevelSeen>();
+        assert_eq!((seen.runs, seen.partial), (1, 0));
+
+        app.world
+            .resource_mut::<NextState<AppState>>()
+            .set(AppState::GameOver);
+        app.update();
+        assert_eq!(*app.world.resource::<LevelReady>(), LevelReady(false));
+        assert_eq!(app.world.resource::<LevelSeen>().runs, 1);
+    }
+}
@@ -0,0 +1,289 @@
+//! Switching to another map: `LoadMap` tears the level down, loads the map and spawns the level
+//! again from it.
+//!
+//! The new map loads in `AppState::SwitchingMap`, so leaving `AppState::Level` runs the level
+//! teardowns and entering it again runs the level spawns, as for the first map. A map that fails
+//! to load is reported and the current map is spawned again.
+
+use bevy::asset::LoadState;
+use bevy::prelude::*;
+
+use crate::game::GameSet;
+use crate::helpers::tiled::TiledMap;
+use crate::localization::tr;
+use crate::map::GameInfoAlt;
+use crate::state::{level_ready, AppState};
+use crate::toast::Toasts;
+
+/// Plugin that switches maps on `LoadMap`
+#[derive(Default)]
+pub struct MapSwitchPlugin;
+
+impl Plugin for MapSwitchPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<LoadMap>()
+            .add_systems(
+                Update,
+                start_map_switch
+                    .in_set(GameSet::Gameplay)
+                    .run_if(level_ready),
+            )
+            .add_systems(
+                Update,
+                finish_map_switch
+                    .in_set(GameSet::Gameplay)
+                    .run_if(in_state(AppState::SwitchingMap)),
+            );
+    }
+}
+
+/// Leave the level for the map at `path`, relative to the assets folder
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct LoadMap {
+    pub path: String,
+}
+
+/// Where the player arrives in the next map: the portal object named `portal_name` instead of
+/// the spawn object, consumed when the level spawns
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct PendingArrival {
+    pub portal_name: String,
+}
+
+/// The map being loaded while in `AppState::SwitchingMap`
+#[derive(Resource, Debug)]
+pub struct MapSwitch(pub Handle<TiledMap>);
+
+fn start_map_switch(
+    mut commands: Commands,
+    mut requests: EventReader<LoadMap>,
+    asset_server: Res<AssetServer>,
+    mut state: ResMut<NextState<AppState>>,
+) {
+    let Some(request) = requests.read().last() else {
+        return;
+    };
+    info!("switching to map {}", request.path);
+    commands.insert_resource(MapSwitch(asset_server.load(&request.path)));
+    state.set(AppState::SwitchingMap);
+}
+
+/// Go back to the level once the next map is loaded, or failed to
+fn finish_map_switch(
+    mut commands: Commands,
+    switch: Option<Res<MapSwitch>>,
+    asset_server: Res<AssetServer>,
+    tile_maps: Res<Assets<TiledMap>>,
+    mut game_info: ResMut<GameInfoAlt>,
+    mut state: ResMut<NextState<AppState>>,
+    mut toasts: Toasts,
+) {
+    let Some(switch) = switch else {
+        warn!("switching maps without a map to switch to, going back to the level");
+        state.set(AppState::Level);
+        return;
+    };
+    if tile_maps.contains(&switch.0) {
+        game_info.tile_map = switch.0.clone();
+    } else if asset_server.get_load_state(&switch.0) == Some(LoadState::Failed) {
+        error!(
+            "can't load map {:?}, staying on the current map",
+            switch.0.path()
+        );
+        toasts.error(tr!("toast.map_failed"));
+        commands.remove_resource::<PendingArrival>();
+    } else {
+        return;
+    }
+    commands.remove_resource::<MapSwitch>();
+    state.set(AppState::Level);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy_ecs_tilemap::prelude::*;
+
+    use super::*;
+    use crate::coords::{self, MapFrame};
+    use crate::debug_step::DebugStep;
+    use crate::flags::GameFlags;
+    use crate::game::{configure_game_sets, KeepLevel};
+    use crate::helpers::tiled::TiledLoader;
+    use crate::inventory::ItemIcons;
+    use crate::layers::LayerZConfig;
+    use crate::map;
+    use crate::player::MainPlayer;
+    use crate::portals::PortalPlugin;
+    use crate::spawn_conditions::{DeferredSpawns, Difficulty};
+    use crate::sprite_atlas::{CurrentCreatureAtlas, SpriteAtlasPlugin};
+    use crate::state::LevelReady;
+    use crate::templates::EntityTemplates;
+    use crate::toast::Toast;
+    use crate::tween::TweenFinished;
+
+    /// The portal of map A leading to the "arrival" portal of map B
+    const PORTAL_A: TilePos = TilePos { x: 2, y: 4 };
+    /// The "arrival" portal of map B
+    const PORTAL_B: TilePos = TilePos { x: 4, y: 1 };
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .add_state::<AppState>()
+            .init_resource::<KeepLevel>()
+            .init_resource::<GameFlags>()
+            .init_resource::<ItemIcons>()
+            .init_resource::<LayerZConfig>()
+            .init_resource::<DebugStep>()
+            .init_resource::<Difficulty>()
+            .init_resource::<DeferredSpawns>()
+            .init_asset::<TiledMap>()
+            .init_asset::<Image>()
+            .init_asset::<TextureAtlas>()
+            .init_asset::<EntityTemplates>()
+            .register_asset_loader(TiledLoader)
+            .add_event::<Toast>()
+            .add_event::<TweenFinished>()
+            .add_plugins((MapSwitchPlugin, PortalPlugin, SpriteAtlasPlugin));
+        configure_game_sets(&mut app);
+        map::add_level_systems(&mut app);
+
+        let tile_map = app
+            .world
+            .resource::<AssetServer>()
+            .load("maps/TMX/map_test_portal_a.tmx");
+        let creature_sheet =
+            app.world
+                .resource_mut::<Assets<TextureAtlas>>()
+                .add(TextureAtlas::from_grid(
+                    Handle::default(),
+                    Vec2::splat(24.),
+                    20,
+                    27,
+                    None,
+                    None,
+                ));
+        app.world.insert_resource(GameInfoAlt {
+            creature_atlas: Handle::default(),
+            creature_sheet: Some(creature_sheet),
+            creature_folder: None,
+            tile_map,
+            templates: Handle::default(),
+        });
+        app
+    }
+
+    /// Update until `done`, the assets load on other threads
+    fn update_until(app: &mut App, done: impl Fn(&mut App) -> bool) {
+        for _ in 0..500 {
+            if done(app) {
+                return;
+            }
+            app.update();
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        panic!("gave up waiting");
+    }
+
+    fn is_in(app: &mut App, state: AppState) -> bool {
+        *app.world.resource::<State<AppState>>().get() == state
+    }
+
+    fn player_tile(app: &mut App) -> Option<TilePos> {
+        let map_frame = app.world.resource::<MapFrame>().clone();
+        let mut players = app.world.query_filtered::<&Transform, With<MainPlayer>>();
+        let pos = players.single(&app.world).translation.truncate();
+        coords::world_to_tile(&map_frame, pos)
+    }
+
+    /// Load the first map and enter its level, until the level's systems have seen the player
+    fn start_level(app: &mut App) {
+        update_until(app, |app| {
+            let game_info = app.world.resource::<GameInfoAlt>();
+            app.world
+                .resource::<Assets<TiledMap>>()
+                .contains(&game_info.tile_map)
+        });
+        app.world
+            .resource_mut::<NextState<AppState>>()
+            .set(AppState::Level);
+        update_until(app, |app| {
+            *app.world.resource::<LevelReady>() == LevelReady(true)
+        });
+        app.update();
+    }
+
+    fn player_atlas(app: &mut App) -> Handle<TextureAtlas> {
+        let mut players = app
+            .world
+            .query_filtered::<&Handle<TextureAtlas>, With<MainPlayer>>();
+        players.single(&app.world).clone()
+    }
+
+    fn switch_to(app: &mut App, path: &str) {
+        app.world.send_event(LoadMap {
+            path: path.to_string(),
+        });
+        update_until(app, |app| is_in(app, AppState::SwitchingMap));
+        update_until(app, |app| is_in(app, AppState::Level));
+        app.update();
+    }
+
+    #[test]
+    fn portals_carry_the_player_to_the_named_portal_of_another_map() {
+        let mut app = test_app();
+        start_level(&mut app);
+        assert_eq!(player_tile(&mut app), Some(TilePos::new(0, 4)));
+
+        let map_frame = app.world.resource::<MapFrame>().clone();
+        let portal = coords::tile_to_world_center(&map_frame, &PORTAL_A).unwrap();
+        app.world.run_system_once(
+            move |mut players: Query<&mut Transform, With<MainPlayer>>| {
+                let mut transform = players.single_mut();
+                transform.translation.x = portal.x;
+                transform.translation.y = portal.y;
+            },
+        );
+        update_until(&mut app, |app| is_in(app, AppState::SwitchingMap));
+        update_until(&mut app, |app| is_in(app, AppState::Level));
+        app.update();
+
+        assert_eq!(player_tile(&mut app), Some(PORTAL_B));
+        let mut players = app.world.query_filtered::<(), With<MainPlayer>>();
+        assert_eq!(players.iter(&app.world).count(), 1);
+        assert!(app.world.get_resource::<PendingArrival>().is_none());
+
+        // standing on the arrival portal, which leads back, doesn't leave map B
+        for _ in 0..5 {
+            app.update();
+        }
+        assert!(is_in(&mut app, AppState::Level));
+        assert_eq!(player_tile(&mut app), Some(PORTAL_B));
+    }
+
+    #[test]
+    fn creatures_are_spawned_with_the_sheet_of_their_map() {
+        let mut app = test_app();
+        start_level(&mut app);
+        let default = app.world.resource::<GameInfoAlt>().creature_atlas.clone();
+        assert_eq!(player_atlas(&mut app), default);
+
+        switch_to(&mut app, "maps/TMX/map_test_portal_b.tmx");
+        let sheet = app.world.resource::<CurrentCreatureAtlas>().0.clone();
+        assert_ne!(sheet, default);
+        assert_eq!(player_atlas(&mut app), sheet);
+        let atlases = app.world.resource::<Assets<TextureAtlas>>();
+        let texture = &atlases.get(&sheet).unwrap().texture;
+        assert_eq!(
+            texture.path().unwrap().path(),
+            std::path::Path::new("sprites/oryx_16bit_fantasy_creatures.png")
+        );
+
+        switch_to(&mut app, "maps/TMX/map_test_portal_a.tmx");
+        assert_eq!(player_atlas(&mut app), default);
+        // coming back reuses the atlas cut the first time
+        switch_to(&mut app, "maps/TMX/map_test_portal_b.tmx");
+        assert_eq!(player_atlas(&mut app), sheet);
+    }
+}
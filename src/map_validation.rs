@@ -0,0 +1,471 @@
+//! Checks a map right after it loads, so a broken map says what's wrong instead of failing in
+//! confusing ways once played.
+//!
+//! `validate_map` looks for:
+//! - an object of type "spawn", where the player starts
+//! - tilesets without an image the game can use, and tileset images that fail to load
+//! - layers named like the ones the game reads ("collision", "regions") but typo'd or of the
+//!   wrong kind; both layers are optional, so a map without them is fine
+//! - object types that nothing spawns or reads, see `OBJECT_TYPES`
+//! - properties the game reads that have another type than it expects
+//!
+//! The issues are logged as warnings, toasted and listed in the inspector's "Map problems"
+//! section. Started with `--strict-maps`, any issue is an error and the app exits.
+
+use std::collections::BTreeMap;
+
+use bevy::app::AppExit;
+use bevy::asset::LoadState;
+use bevy::prelude::*;
+use thiserror::Error;
+
+use crate::collision::COLLISION_LAYER_NAME;
+use crate::game::GameSet;
+use crate::helpers::tiled::{all_layers, TiledMap};
+use crate::inventory::LEVEL_LOCAL_ITEMS_PROPERTY;
+use crate::layers::ABOVE_ENTITIES_PROPERTY;
+use crate::regions::REGIONS_LAYER_NAME;
+use crate::toast::Toasts;
+
+/// Command line flag turning map issues into a load failure
+pub const STRICT_MAPS_FLAG: &str = "--strict-maps";
+
+/// Object types something spawns or reads: map, doors, triggers, lights and the editor's regions
+pub const OBJECT_TYPES: &[&str] = &[
+    "spawn",
+    "npc",
+    "item",
+    "door",
+    "trigger",
+    "light",
+    "portal",
+    "region",
+    "camera_poi",
+];
+
+/// Properties of the map and the types they're read as
+const MAP_PROPERTIES: &[(&str, &[&str])] = &[
+    ("music", &["string"]),
+    ("persistent_flags", &["string"]),
+    (LEVEL_LOCAL_ITEMS_PROPERTY, &["bool"]),
+    ("creature_sheet", &["string"]),
+    ("creature_tile_size", &["int"]),
+    ("creature_columns", &["int"]),
+    ("creature_rows", &["int"]),
+];
+
+/// Properties of layers and the types they're read as
+const LAYER_PROPERTIES: &[(&str, &[&str])] = &[(ABOVE_ENTITIES_PROPERTY, &["bool"])];
+
+/// Properties of tileset tiles and the types they're read as
+const TILE_PROPERTIES: &[(&str, &[&str])] =
+    &[("movement_cost", &["int", "float"]), ("blocked", &["bool"])];
+
+/// Properties of objects and the types they're read as
+const OBJECT_PROPERTIES: &[(&str, &[&str])] = &[
+    ("health", &["int"]),
+    ("max_health", &["int"]),
+    ("creature_row", &["int"]),
+    ("template", &["string"]),
+    ("link", &["string"]),
+    ("target_map", &["string"]),
+    ("target_portal", &["string"]),
+    ("dialogue", &["string"]),
+    ("requires_flag", &["string"]),
+    ("forbids_flag", &["string"]),
+    ("min_difficulty", &["string"]),
+    ("key_id", &["string"]),
+    ("keep_key", &["bool"]),
+    ("flag", &["string"]),
+    ("open_tile", &["int"]),
+    ("item_id", &["string"]),
+    ("atlas_index", &["int"]),
+    ("actions", &["string"]),
+    ("once", &["bool"]),
+    ("radius", &["int", "float"]),
+    ("color", &["color", "string"]),
+    ("ambience", &["string"]),
+    ("ambient", &["string"]),
+];
+
+/// Plugin that validates every map when it loads and reports what's wrong with it
+#[derive(Default)]
+pub struct MapValidationPlugin;
+
+impl Plugin for MapValidationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MapProblems>()
+            .insert_resource(StrictMaps(
+                std::env::args().any(|arg| arg == STRICT_MAPS_FLAG),
+            ))
+            .add_systems(
+                Update,
+                (validate_loaded_maps, check_tileset_images)
+                    .chain()
+                    .in_set(GameSet::RenderPrep),
+            );
+    }
+}
+
+/// Something wrong with a map
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum MapIssue {
+    #[error("no object of type \"spawn\", the player has nowhere to start")]
+    NoSpawn,
+    #[error("tileset \"{tileset}\" has no image the game can use")]
+    TilesetWithoutImage { tileset: String },
+    #[error("tileset \"{tileset}\" image {path} failed to load")]
+    TilesetImageFailed { tileset: String, path: String },
+    #[error("layer \"{found}\" looks like a typo of \"{expected}\"")]
+    LayerNameTypo {
+        found: String,
+        expected: &'static str,
+    },
+    #[error("layer \"{layer}\" should be {expected}")]
+    WrongLayerKind {
+        layer: String,
+        expected: &'static str,
+    },
+    #[error("{object} has the type \"{user_type}\", which nothing spawns")]
+    UnknownObjectType { object: String, user_type: String },
+    #[error("{owner}: property \"{property}\" is {found}, expected {expected}")]
+    WrongPropertyType {
+        owner: String,
+        property: String,
+        expected: String,
+        found: &'static str,
+    },
+}
+
+/// The issues found in each loaded map, by asset path
+#[derive(Resource, Default, Debug)]
+pub struct MapProblems(pub BTreeMap<String, Vec<MapIssue>>);
+
+/// Whether map issues are a load failure, set by `--strict-maps`
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StrictMaps(pub bool);
+
+/// Everything wrong with `map` that can be told without loading its images
+pub fn validate_map(map: &TiledMap) -> Vec<MapIssue> {
+    let mut issues = Vec::new();
+
+    for (tileset_index, tileset) in map.map.tilesets().iter().enumerate() {
+        let has_image = map
+            .tilemap_textures
+            .get(&tileset_index)
+            .is_some_and(|texture| !texture.image_handles().is_empty());
+        if !has_image {
+            issues.push(MapIssue::TilesetWithoutImage {
+                tileset: tileset.name.clone(),
+            });
+        }
+        for (tile_id, tile) in tileset.tiles() {
+            let owner = format!("tile {tile_id} of tileset \"{}\"", tileset.name);
+            check_properties(&owner, &tile.properties, TILE_PROPERTIES, &mut issues);
+        }
+    }
+
+    check_properties("map", &map.map.properties, MAP_PROPERTIES, &mut issues);
+
+    let mut has_spawn = false;
+    for layer in all_layers(&map.map) {
+        check_layer_name(&layer, &mut issues);
+        let owner = format!("layer \"{}\"", layer.name);
+        check_properties(&owner, &layer.properties, LAYER_PROPERTIES, &mut issues);
+
+        let tiled::LayerType::Objects(objects) = layer.layer_type() else {
+            continue;
+        };
+        for object in objects.objects() {
+            let description = format!("object \"{}\" (id {})", object.name, object.id());
+            let user_type = object.user_type.as_str();
+            if user_type.eq_ignore_ascii_case("spawn") {
+                has_spawn = true;
+            }
+            let known = OBJECT_TYPES
+                .iter()
+                .any(|known| user_type.eq_ignore_ascii_case(known));
+            if !user_type.is_empty() && !known {
+                issues.push(MapIssue::UnknownObjectType {
+                    object: description.clone(),
+                    user_type: user_type.to_string(),
+                });
+            }
+            check_properties(
+                &description,
+                &object.properties,
+                OBJECT_PROPERTIES,
+                &mut issues,
+            );
+        }
+    }
+    if !has_spawn {
+        issues.insert(0, MapIssue::NoSpawn);
+    }
+
+    issues
+}
+
+/// Catch layers meant to be the collision or regions layer but not read as one
+fn check_layer_name(layer: &tiled::Layer, issues: &mut Vec<MapIssue>) {
+    let expected_layers = [
+        (COLLISION_LAYER_NAME, "a tile layer"),
+        (REGIONS_LAYER_NAME, "an object layer"),
+    ];
+    for (expected, kind) in expected_layers {
+        if layer.name.eq_ignore_ascii_case(expected) {
+            let right_kind = match layer.layer_type() {
+                tiled::LayerType::Tiles(_) => expected == COLLISION_LAYER_NAME,
+                tiled::LayerType::Objects(_) => expected == REGIONS_LAYER_NAME,
+                _ => false,
+            };
+            if !right_kind {
+                issues.push(MapIssue::WrongLayerKind {
+                    layer: layer.name.clone(),
+                    expected: kind,
+                });
+            }
+        } else if edit_distance(&layer.name.to_ascii_lowercase(), expected) <= 2 {
+            issues.push(MapIssue::LayerNameTypo {
+                found: layer.name.clone(),
+                expected,
+            });
+        }
+    }
+}
+
+fn check_properties(
+    owner: &str,
+    properties: &tiled::Properties,
+    expected_types: &[(&str, &[&str])],
+    issues: &mut Vec<MapIssue>,
+) {
+    for (property, expected) in expected_types {
+        let Some(value) = properties.get(*property) else {
+            continue;
+        };
+        let found = property_type(value);
+        if !expected.contains(&found) {
+            issues.push(MapIssue::WrongPropertyType {
+                owner: owner.to_string(),
+                property: property.to_string(),
+                expected: expected.join(" or "),
+                found,
+            });
+        }
+    }
+}
+
+/// The type of a property as Tiled names it
+fn property_type(value: &tiled::PropertyValue) -> &'static str {
+    match value {
+        tiled::PropertyValue::BoolValue(_) => "bool",
+        tiled::PropertyValue::FloatValue(_) => "float",
+        tiled::PropertyValue::IntValue(_) => "int",
+        tiled::PropertyValue::ColorValue(_) => "color",
+        tiled::PropertyValue::StringValue(_) => "string",
+        tiled::PropertyValue::FileValue(_) => "file",
+        tiled::PropertyValue::ObjectValue(_) => "object",
+    }
+}
+
+/// Number of characters to insert, remove or replace to turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let replace = previous[j] + usize::from(a_char != *b_char);
+            current.push(replace.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+fn map_name(asset_server: &AssetServer, id: AssetId<TiledMap>) -> String {
+    asset_server
+        .get_path(id)
+        .map(|path| path.to_string())
+        .unwrap_or_else(|| format!("{id:?}"))
+}
+
+/// Log and toast the issues of a map, in strict mode failing the load
+fn report(
+    map: &str,
+    issues: &[MapIssue],
+    strict: StrictMaps,
+    toasts: &mut Toasts,
+    exit: &mut EventWriter<AppExit>,
+) {
+    if issues.is_empty() {
+        return;
+    }
+    for issue in issues {
+        if strict.0 {
+            error!("{map}: {issue}");
+        } else {
+            warn!("{map}: {issue}");
+        }
+    }
+    if strict.0 {
+        error!("{map} has problems and {STRICT_MAPS_FLAG} is set, exiting");
+        toasts.error(format!("{map} failed validation"));
+        exit.send(AppExit);
+    } else {
+        toasts.warn(format!(
+            "{map} has {} problem(s), see \"Map problems\" in the inspector",
+            issues.len()
+        ));
+    }
+}
+
+fn validate_loaded_maps(
+    mut events: EventReader<AssetEvent<TiledMap>>,
+    maps: Res<Assets<TiledMap>>,
+    asset_server: Res<AssetServer>,
+    strict: Res<StrictMaps>,
+    mut problems: ResMut<MapProblems>,
+    mut toasts: Toasts,
+    mut exit: EventWriter<AppExit>,
+) {
+    for event in events.read() {
+        let (AssetEvent::Added { id } | AssetEvent::Modified { id }) = event else {
+            continue;
+        };
+        let Some(map) = maps.get(*id) else {
+            continue;
+        };
+        let name = map_name(&asset_server, *id);
+        let issues = validate_map(map);
+        report(&name, &issues, *strict, &mut toasts, &mut exit);
+        problems.0.insert(name, issues);
+    }
+}
+
+/// Report the tileset images that failed to load, which `validate_map` can't see
+fn check_tileset_images(
+    maps: Res<Assets<TiledMap>>,
+    asset_server: Res<AssetServer>,
+    strict: Res<StrictMaps>,
+    mut problems: ResMut<MapProblems>,
+    mut toasts: Toasts,
+    mut exit: EventWriter<AppExit>,
+) {
+    for (id, map) in maps.iter() {
+        let name = map_name(&asset_server, id);
+        for (tileset_index, tileset) in map.map.tilesets().iter().enumerate() {
+            let Some(texture) = map.tilemap_textures.get(&tileset_index) else {
+                continue;
+            };
+            for handle in texture.image_handles() {
+                if asset_server.get_load_state(handle) != Some(LoadState::Failed) {
+                    continue;
+                }
+                let issue = MapIssue::TilesetImageFailed {
+                    tileset: tileset.name.clone(),
+                    path: asset_server
+                        .get_path(handle)
+                        .map(|path| path.to_string())
+                        .unwrap_or_default(),
+                };
+                let issues = problems.0.entry(name.clone()).or_default();
+                if issues.contains(&issue) {
+                    continue;
+                }
+                report(
+                    &name,
+                    std::slice::from_ref(&issue),
+                    *strict,
+                    &mut toasts,
+                    &mut exit,
+                );
+                issues.push(issue);
+            }
+        }
+    }
+}
+
+/// The "Map problems" section of the inspector
+pub fn map_problems_ui(world: &World, ui: &mut egui::Ui) {
+    let problems = world.resource::<MapProblems>();
+    let count: usize = problems.0.values().map(Vec::len).sum();
+    egui::CollapsingHeader::new(format!("Map problems ({count})"))
+        .default_open(count > 0)
+        .show(ui, |ui| {
+            if count == 0 {
+                ui.label("none");
+            }
+            for (map, issues) in &problems.0 {
+                for issue in issues {
+                    ui.label(format!("{map}: {issue}"));
+                }
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::tiled::load_test_map;
+
+    #[test]
+    fn a_sound_map_has_no_issues() {
+        let map = load_test_map("assets/maps/TMX/map_test_collision.tmx");
+
+        assert_eq!(validate_map(&map), []);
+    }
+
+    #[test]
+    fn broken_map_issues_are_all_found() {
+        let map = load_test_map("assets/maps/TMX/map_test_broken.tmx");
+
+        assert_eq!(
+            validate_map(&map),
+            [
+                MapIssue::NoSpawn,
+                MapIssue::TilesetWithoutImage {
+                    tileset: "loose_tiles".to_string(),
+                },
+                MapIssue::WrongPropertyType {
+                    owner: "tile 0 of tileset \"loose_tiles\"".to_string(),
+                    property: "blocked".to_string(),
+                    expected: "bool".to_string(),
+                    found: "string",
+                },
+                MapIssue::WrongPropertyType {
+                    owner: "map".to_string(),
+                    property: "music".to_string(),
+                    expected: "string".to_string(),
+                    found: "int",
+                },
+                MapIssue::WrongLayerKind {
+                    layer: "collision".to_string(),
+                    expected: "a tile layer",
+                },
+                MapIssue::UnknownObjectType {
+                    object: "object \"chest\" (id 1)".to_string(),
+                    user_type: "chst".to_string(),
+                },
+                MapIssue::WrongPropertyType {
+                    owner: "object \"guard\" (id 2)".to_string(),
+                    property: "health".to_string(),
+                    expected: "int".to_string(),
+                    found: "string",
+                },
+                MapIssue::LayerNameTypo {
+                    found: "Region".to_string(),
+                    expected: REGIONS_LAYER_NAME,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn typos_are_a_few_edits_away() {
+        assert_eq!(edit_distance("region", "regions"), 1);
+        assert_eq!(edit_distance("colision", "collision"), 1);
+        assert_eq!(edit_distance("decor", "regions"), 5);
+    }
+}
@@ -0,0 +1,338 @@
+//! The markers drawn over the hovered and the selected tiles, styled by `MarkerStyle`.
+//!
+//! A marker either fills its tile or draws four brackets in its corners, and pulses in alpha and
+//! scale unless the motion preferences reduce motion. The marker sprites are pooled: a frame with fewer marked tiles hides the spare ones
+//! instead of despawning them.
+
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::accessibility::MotionPreferences;
+use crate::coords::{self, MapFrame};
+use crate::game::GameSet;
+use crate::lifetime::ExpireOnStateExit;
+use crate::palette::{OverlayPalette, OverlayRole};
+use crate::state::{level_ready, AppState};
+use crate::ui::{HoveredTile, SelectedTile};
+
+/// Over the targeting highlights, under the creatures
+const MARKER_Z: f32 = 1.9;
+/// Side of a corner bracket, in tiles
+const BRACKET_FRACTION: f32 = 0.25;
+/// How much a marker grows and shrinks at full pulse amplitude
+const PULSE_SCALE: f32 = 0.08;
+
+/// Plugin that draws and animates the tile markers
+#[derive(Default)]
+pub struct TileMarkerPlugin;
+
+impl Plugin for TileMarkerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MarkerStyle>()
+            .register_type::<MarkerStyle>()
+            .init_resource::<MarkerPool>()
+            .add_systems(
+                Update,
+                draw_tile_markers
+                    .in_set(GameSet::RenderPrep)
+                    .run_if(level_ready),
+            );
+    }
+}
+
+/// How the tile markers look
+#[derive(Resource, Reflect, Debug, Clone, PartialEq)]
+#[reflect(Resource)]
+pub struct MarkerStyle {
+    /// Opacity of the hover marker, its color comes from the `OverlayPalette`
+    pub hover_alpha: f32,
+    /// Opacity of the selection marker, its color comes from the `OverlayPalette`
+    pub selection_alpha: f32,
+    /// Pulses per second, 0 for a steady marker
+    pub pulse_speed: f32,
+    /// From 0 for no pulse to 1 for a marker fading out completely at the bottom of the pulse
+    pub pulse_amplitude: f32,
+    pub shape: MarkerShape,
+}
+
+impl Default for MarkerStyle {
+    fn default() -> Self {
+        Self {
+            hover_alpha: 0.35,
+            selection_alpha: 0.6,
+            pulse_speed: 1.2,
+            pulse_amplitude: 0.5,
+            shape: MarkerShape::FullTile,
+        }
+    }
+}
+
+#[derive(Reflect, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MarkerShape {
+    /// A quad over the whole tile
+    #[default]
+    FullTile,
+    /// Four small squares in the corners of the tile
+    CornerBrackets,
+}
+
+impl MarkerStyle {
+    /// The alpha factor and scale of a marker `secs` into its pulse
+    pub fn pulse(&self, secs: f32) -> (f32, f32) {
+        let phase = secs * self.pulse_speed * TAU;
+        let amplitude = self.pulse_amplitude.clamp(0., 1.);
+        let alpha = 1. - amplitude * (0.5 - 0.5 * phase.cos());
+        let scale = 1. + amplitude * PULSE_SCALE * phase.sin();
+        (alpha, scale)
+    }
+}
+
+/// Offsets from the tile center of the four corner brackets of a marker on a `tile` sized tile,
+/// and their size
+pub fn bracket_layout(tile: Vec2) -> ([Vec2; 4], Vec2) {
+    let size = tile * BRACKET_FRACTION;
+    let offset = (tile - size) / 2.;
+    let corners = [
+        Vec2::new(-offset.x, -offset.y),
+        Vec2::new(offset.x, -offset.y),
+        Vec2::new(-offset.x, offset.y),
+        Vec2::new(offset.x, offset.y),
+    ];
+    (corners, size)
+}
+
+/// The quad of a marker, with its four brackets as children
+#[derive(Component)]
+struct TileMarker;
+
+#[derive(Component)]
+struct MarkerBracket;
+
+/// Marker sprites reused from frame to frame
+#[derive(Resource, Default)]
+struct MarkerPool {
+    entities: Vec<Entity>,
+}
+
+/// The tiles to mark this frame with their colors, the selection over the hover
+fn marked_tiles(
+    style: &MarkerStyle,
+    palette: &OverlayPalette,
+    hovered: &HoveredTile,
+    selected: &SelectedTile,
+) -> Vec<(TilePos, Color)> {
+    let selection_color = palette.color_with_alpha(OverlayRole::Selected, style.selection_alpha);
+    let hover_color = palette.color_with_alpha(OverlayRole::Hover, style.hover_alpha);
+    let mut tiles: Vec<_> = selected
+        .0
+        .iter()
+        .map(|pos| (*pos, selection_color))
+        .collect();
+    if let Some(hovered) = hovered.0 {
+        if !tiles.iter().any(|(pos, _)| *pos == hovered) {
+            tiles.push((hovered, hover_color));
+        }
+    }
+    tiles
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn draw_tile_markers(
+    mut commands: Commands,
+    style: Res<MarkerStyle>,
+    palette: Res<OverlayPalette>,
+    preferences: Res<MotionPreferences>,
+    time: Res<Time>,
+    hovered: Res<HoveredTile>,
+    selected: Res<SelectedTile>,
+    map_frame: Res<MapFrame>,
+    mut pool: ResMut<MarkerPool>,
+    mut markers: Query<
+        (&mut Transform, &mut Sprite, &mut Visibility, &Children),
+        (With<TileMarker>, Without<MarkerBracket>),
+    >,
+    mut brackets: Query<(&mut Transform, &mut Sprite), With<MarkerBracket>>,
+) {
+    // forget the markers despawned with the level they were drawn on
+    pool.entities.retain(|entity| markers.contains(*entity));
+
+    let tile_size = Vec2::new(map_frame.grid_size.x, map_frame.grid_size.y);
+    let (corners, bracket_size) = bracket_layout(tile_size);
+    let (alpha, scale) = if preferences.reduce_motion {
+        (1., 1.)
+    } else {
+        style.pulse(time.elapsed_seconds())
+    };
+    let brackets_shown = style.shape == MarkerShape::CornerBrackets;
+    let tiles = marked_tiles(&style, &palette, &hovered, &selected);
+    let mut used = 0;
+    for (pos, color) in tiles {
+        let Some(center) = coords::tile_to_world_center(&map_frame, &pos) else {
+            continue;
+        };
+        let color = color.with_a(color.a() * alpha);
+        let transform = Transform::from_translation(center.extend(MARKER_Z))
+            .with_scale(Vec3::new(scale, scale, 1.));
+        let quad_color = if brackets_shown { Color::NONE } else { color };
+        let bracket_color = if brackets_shown { color } else { Color::NONE };
+
+        let Some(entity) = pool.entities.get(used).copied() else {
+            // more marked tiles than ever before, grow the pool
+            let entity = commands
+                .spawn((
+                    SpriteBundle {
+                        sprite: Sprite {
+                            color: quad_color,
+                            custom_size: Some(tile_size),
+                            ..default()
+                        },
+                        transform,
+                        ..default()
+                    },
+                    TileMarker,
+                    ExpireOnStateExit(AppState::Level),
+                    Name::new("tile marker"),
+                ))
+                .with_children(|marker| {
+                    for corner in corners {
+                        marker.spawn((
+                            SpriteBundle {
+                                sprite: Sprite {
+                                    color: bracket_color,
+                                    custom_size: Some(bracket_size),
+                                    ..default()
+                                },
+                                transform: Transform::from_translation(corner.extend(0.)),
+                                ..default()
+                            },
+                            MarkerBracket,
+                        ));
+                    }
+                })
+                .id();
+            pool.entities.push(entity);
+            used += 1;
+            continue;
+        };
+        if let Ok((mut marker_transform, mut sprite, mut visibility, children)) =
+            markers.get_mut(entity)
+        {
+            *marker_transform = transform;
+            sprite.color = quad_color;
+            sprite.custom_size = Some(tile_size);
+            *visibility = Visibility::Inherited;
+            for (child, corner) in children.iter().zip(corners) {
+                if let Ok((mut bracket_transform, mut bracket)) = brackets.get_mut(*child) {
+                    bracket_transform.translation = corner.extend(0.);
+                    bracket.color = bracket_color;
+                    bracket.custom_size = Some(bracket_size);
+                }
+            }
+        }
+        used += 1;
+    }
+
+    for entity in &pool.entities[used.min(pool.entities.len())..] {
+        if let Ok((_, _, mut visibility, _)) = markers.get_mut(*entity) {
+            *visibility = Visibility::Hidden;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<MarkerStyle>()
+            .init_resource::<MarkerPool>()
+            .init_resource::<MotionPreferences>()
+            .init_resource::<OverlayPalette>()
+            .init_resource::<HoveredTile>()
+            .init_resource::<SelectedTile>()
+            .insert_resource(MapFrame {
+                transform: Transform::IDENTITY,
+                grid_size: TilemapGridSize { x: 10., y: 10. },
+                map_type: TilemapType::Square,
+                map_size: TilemapSize { x: 8, y: 8 },
+            })
+            .add_systems(Update, draw_tile_markers);
+        app
+    }
+
+    /// The centers of the shown markers
+    fn shown_markers(app: &mut App) -> Vec<Vec2> {
+        let mut markers = app
+            .world
+            .query_filtered::<(&Transform, &Visibility), With<TileMarker>>();
+        markers
+            .iter(&app.world)
+            .filter(|(_, visibility)| **visibility != Visibility::Hidden)
+            .map(|(transform, _)| transform.translation.truncate())
+            .collect()
+    }
+
+    fn marker_count(app: &mut App) -> usize {
+        let mut markers = app.world.query_filtered::<(), With<TileMarker>>();
+        markers.iter(&app.world).count()
+    }
+
+    #[test]
+    fn pulse_starts_steady_and_swings() {
+        let style = MarkerStyle {
+            pulse_speed: 1.,
+            pulse_amplitude: 0.5,
+            ..default()
+        };
+        assert_eq!(style.pulse(0.), (1., 1.));
+        let (alpha, scale) = style.pulse(0.25);
+        assert!((alpha - 0.75).abs() < 1e-5);
+        assert!((scale - (1. + 0.5 * PULSE_SCALE)).abs() < 1e-5);
+        let (alpha, _) = style.pulse(0.5);
+        assert!((alpha - 0.5).abs() < 1e-5);
+
+        let steady = MarkerStyle {
+            pulse_speed: 0.,
+            ..style
+        };
+        assert_eq!(steady.pulse(0.3), (1., 1.));
+    }
+
+    #[test]
+    fn brackets_sit_in_the_corners() {
+        let (corners, size) = bracket_layout(Vec2::new(24., 16.));
+        assert_eq!(size, Vec2::new(6., 4.));
+        assert_eq!(corners[0], Vec2::new(-9., -6.));
+        assert_eq!(corners[3], Vec2::new(9., 6.));
+    }
+
+    #[test]
+    fn markers_are_reused_as_the_tiles_change() {
+        let mut app = test_app();
+        app.world.resource_mut::<SelectedTile>().0 = Some(TilePos::new(1, 1));
+        app.world.resource_mut::<HoveredTile>().0 = Some(TilePos::new(4, 2));
+        app.update();
+        assert_eq!(
+            shown_markers(&mut app),
+            [Vec2::new(10., 10.), Vec2::new(40., 20.)]
+        );
+
+        app.world.resource_mut::<HoveredTile>().0 = Some(TilePos::new(6, 6));
+        app.update();
+        assert_eq!(marker_count(&mut app), 2);
+        assert_eq!(
+            shown_markers(&mut app),
+            [Vec2::new(10., 10.), Vec2::new(60., 60.)]
+        );
+
+        // hovering the selected tile leaves one marker
+        app.world.resource_mut::<HoveredTile>().0 = Some(TilePos::new(1, 1));
+        app.update();
+        assert_eq!(marker_count(&mut app), 2);
+        assert_eq!(shown_markers(&mut app), [Vec2::new(10., 10.)]);
+    }
+}
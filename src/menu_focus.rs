@@ -0,0 +1,353 @@
+//! Keyboard and gamepad navigation of the menu screens.
+//!
+//! A screen declares its focusable widgets while it draws them, in navigation order, by passing
+//! their responses to `MenuFocus::item`, between `MenuFocus::begin` and `MenuFocus::end`. The `FocusModel` of the screen shown last moves along
+//! them with the arrow keys, the d-pad or the left stick, skipping the disabled ones and wrapping
+//! around. Enter or the A button activates the focused widget, Escape or the B button backs out of
+//! the screen. Hovering a widget with the mouse focuses it too, so the pointer and the keys never
+//! disagree about what is highlighted.
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::EguiContext;
+use bevy_window::PrimaryWindow;
+
+use crate::game::GameSet;
+
+/// How far the left stick has to be pushed to move the focus
+const STICK_THRESHOLD: f32 = 0.5;
+
+/// Plugin that turns the navigation input into moves of the `MenuFocus`
+#[derive(Default)]
+pub struct MenuFocusPlugin;
+
+impl Plugin for MenuFocusPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MenuFocus>()
+            .add_systems(Update, read_menu_input.in_set(GameSet::Input));
+    }
+}
+
+/// A widget that can take the focus
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FocusItem {
+    pub id: String,
+    pub enabled: bool,
+}
+
+/// The focusable widgets of a screen and the one focused
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FocusModel {
+    items: Vec<FocusItem>,
+    focused: Option<usize>,
+}
+
+impl FocusModel {
+    /// Replace the widgets, keeping the focus on the same id when it's still there and enabled,
+    /// or on the first enabled widget
+    pub fn set_items(&mut self, items: Vec<FocusItem>) {
+        let focused_id = self.focused_id().map(str::to_string);
+        self.items = items;
+        self.focused = focused_id
+            .and_then(|id| {
+                self.items
+                    .iter()
+                    .position(|item| item.enabled && item.id == id)
+            })
+            .or_else(|| self.items.iter().position(|item| item.enabled));
+    }
+
+    pub fn focused_id(&self) -> Option<&str> {
+        self.focused
+            .and_then(|index| self.items.get(index))
+            .map(|item| item.id.as_str())
+    }
+
+    /// Move the focus `step` enabled widgets forward, or backward when negative, wrapping around
+    pub fn move_focus(&mut self, step: i32) {
+        let len = self.items.len() as i32;
+        if len == 0 || step == 0 {
+            return;
+        }
+        let mut index = self
+            .focused
+            .map_or(if step > 0 { -1 } else { 0 }, |i| i as i32);
+        for _ in 0..step.abs() {
+            // at most one lap looking for an enabled widget
+            let next = (1..=len)
+                .map(|offset| (index + offset * step.signum()).rem_euclid(len))
+                .find(|&i| self.items[i as usize].enabled);
+            match next {
+                Some(next) => index = next,
+                None => return,
+            }
+        }
+        self.focused = Some(index as usize);
+    }
+
+    /// Focus the widget `id`, unless it's missing or disabled
+    pub fn focus(&mut self, id: &str) -> bool {
+        let Some(index) = self
+            .items
+            .iter()
+            .position(|item| item.enabled && item.id == id)
+        else {
+            return false;
+        };
+        self.focused = Some(index);
+        true
+    }
+}
+
+/// What the navigation input asks of the menu shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MenuNav {
+    #[default]
+    None,
+    /// Move the focus by this many widgets
+    Move(i32),
+    /// Change the value of the focused widget, a slider or a choice, down or up
+    Adjust(i32),
+    Activate,
+    Back,
+}
+
+/// The focus of the menu screen shown. A screen opened over another one is navigated once the
+/// other one closes.
+#[derive(Resource, Debug, Default)]
+pub struct MenuFocus {
+    screen: Option<&'static str>,
+    model: FocusModel,
+    /// Navigation read this frame, applied by the screen when it's drawn
+    nav: MenuNav,
+    /// Widgets declared by the screen drawing this frame
+    building: Vec<FocusItem>,
+    drawn: bool,
+    /// The screen drawing is shown over another one, which keeps the focus
+    passive: bool,
+}
+
+impl MenuFocus {
+    /// Whether a menu screen was drawn last frame, and takes the navigation input
+    pub fn is_open(&self) -> bool {
+        self.screen.is_some()
+    }
+
+    /// Start drawing `screen`, applying the navigation of this frame. Whether the screen should
+    /// close, backed out of.
+    pub fn begin(&mut self, screen: &'static str) -> bool {
+        self.passive = self.screen.is_some_and(|shown| shown != screen);
+        if self.passive {
+            return false;
+        }
+        if self.screen.is_none() {
+            self.screen = Some(screen);
+            self.model = FocusModel::default();
+        }
+        self.drawn = true;
+        self.building.clear();
+        if let MenuNav::Move(step) = self.nav {
+            self.model.move_focus(step);
+        }
+        self.nav == MenuNav::Back
+    }
+
+    /// Declare the widget `id` drawn as `response`, highlighted when focused. Whether it was
+    /// activated with Enter or A, a click is left to the widget's own response.
+    pub fn item(
+        &mut self,
+        ui: &egui::Ui,
+        id: impl Into<String>,
+        response: &egui::Response,
+    ) -> bool {
+        if self.passive {
+            return false;
+        }
+        let id = id.into();
+        let enabled = response.enabled;
+        if enabled && response.hovered() && self.model.focused_id() != Some(&id) {
+            self.model.set_items(self.building.clone());
+            self.model.focus(&id);
+        }
+        let focused = self.model.focused_id() == Some(&id);
+        self.building.push(FocusItem { id, enabled });
+        if focused {
+            let stroke = egui::Stroke::new(2., ui.visuals().selection.stroke.color);
+            ui.painter()
+                .rect_stroke(response.rect.expand(3.), 3., stroke);
+        }
+        focused && enabled && self.nav == MenuNav::Activate
+    }
+
+    /// How the focused widget `id` is asked to change its value this frame
+    pub fn adjust(&self, id: &str) -> i32 {
+        match self.nav {
+            MenuNav::Adjust(step) if !self.passive && self.model.focused_id() == Some(id) => step,
+            _ => 0,
+        }
+    }
+
+    /// Done drawing the screen, its widgets are navigated from the next frame
+    pub fn end(&mut self) {
+        if std::mem::take(&mut self.passive) {
+            return;
+        }
+        let items = std::mem::take(&mut self.building);
+        self.model.set_items(items);
+    }
+}
+
+/// The option `step` places after `current` in `options`, wrapping around, for choices changed
+/// with left and right. `current` when it isn't one of the options.
+pub fn cycle<T: Clone + PartialEq>(options: &[T], current: &T, step: i32) -> T {
+    match options.iter().position(|option| option == current) {
+        Some(index) if step != 0 => {
+            let index = (index as i32 + step).rem_euclid(options.len() as i32);
+            options[index as usize].clone()
+        }
+        _ => current.clone(),
+    }
+}
+
+/// No menu screen takes the navigation input, as a run condition
+pub fn menu_closed(focus: Res<MenuFocus>) -> bool {
+    !focus.is_open()
+}
+
+/// The navigation asked by the keys, the d-pad and the stick this frame
+fn read_menu_input(
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    gamepads: Res<Gamepads>,
+    mut focus: ResMut<MenuFocus>,
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+    mut stick_held: Local<bool>,
+) {
+    // a screen not drawn since the last frame was closed
+    if !focus.drawn {
+        focus.screen = None;
+    }
+    focus.drawn = false;
+    focus.nav = MenuNav::None;
+    if !focus.is_open() {
+        return;
+    }
+
+    let typing = contexts
+        .iter_mut()
+        .next()
+        .is_some_and(|mut ctx| ctx.get_mut().wants_keyboard_input());
+    let pressed = |key: KeyCode, button: GamepadButtonType| {
+        (!typing && keys.just_pressed(key))
+            || gamepads
+                .iter()
+                .any(|gamepad| buttons.just_pressed(GamepadButton::new(gamepad, button)))
+    };
+    let mut stick = Vec2::ZERO;
+    for gamepad in gamepads.iter() {
+        let axis = |axis_type| axes.get(GamepadAxis::new(gamepad, axis_type)).unwrap_or(0.);
+        stick += Vec2::new(
+            axis(GamepadAxisType::LeftStickX),
+            axis(GamepadAxisType::LeftStickY),
+        );
+    }
+    let stick_pushed = stick.length() > STICK_THRESHOLD;
+    let stick_nav = stick_pushed && !*stick_held;
+    *stick_held = stick_pushed;
+
+    focus.nav = if pressed(KeyCode::Up, GamepadButtonType::DPadUp)
+        || (stick_nav && stick.y > stick.x.abs())
+    {
+        MenuNav::Move(-1)
+    } else if pressed(KeyCode::Down, GamepadButtonType::DPadDown)
+        || (stick_nav && -stick.y > stick.x.abs())
+    {
+        MenuNav::Move(1)
+    } else if pressed(KeyCode::Left, GamepadButtonType::DPadLeft) || (stick_nav && stick.x < 0.) {
+        MenuNav::Adjust(-1)
+    } else if pressed(KeyCode::Right, GamepadButtonType::DPadRight) || (stick_nav && stick.x > 0.) {
+        MenuNav::Adjust(1)
+    } else if pressed(KeyCode::Return, GamepadButtonType::South) {
+        MenuNav::Activate
+    } else if pressed(KeyCode::Escape, GamepadButtonType::East) {
+        MenuNav::Back
+    } else {
+        MenuNav::None
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model(enabled: &[bool]) -> FocusModel {
+        let mut model = FocusModel::default();
+        model.set_items(
+            enabled
+                .iter()
+                .enumerate()
+                .map(|(i, &enabled)| FocusItem {
+                    id: format!("item {i}"),
+                    enabled,
+                })
+                .collect(),
+        );
+        model
+    }
+
+    #[test]
+    fn focus_starts_on_the_first_enabled_item_and_wraps() {
+        let mut focus = model(&[false, true, true, true]);
+        assert_eq!(focus.focused_id(), Some("item 1"));
+        focus.move_focus(1);
+        focus.move_focus(1);
+        assert_eq!(focus.focused_id(), Some("item 3"));
+        focus.move_focus(1);
+        assert_eq!(focus.focused_id(), Some("item 1"));
+        focus.move_focus(-1);
+        assert_eq!(focus.focused_id(), Some("item 3"));
+        focus.move_focus(2);
+        assert_eq!(focus.focused_id(), Some("item 2"));
+    }
+
+    #[test]
+    fn disabled_items_are_skipped() {
+        let mut focus = model(&[true, false, false, true]);
+        focus.move_focus(1);
+        assert_eq!(focus.focused_id(), Some("item 3"));
+        focus.move_focus(-1);
+        assert_eq!(focus.focused_id(), Some("item 0"));
+        assert!(!focus.focus("item 2"));
+        assert_eq!(focus.focused_id(), Some("item 0"));
+
+        let mut nothing = model(&[false, false]);
+        assert_eq!(nothing.focused_id(), None);
+        nothing.move_focus(1);
+        assert_eq!(nothing.focused_id(), None);
+        assert_eq!(model(&[]).focused_id(), None);
+    }
+
+    #[test]
+    fn choices_cycle_both_ways() {
+        let options = ["a", "b", "c"];
+        assert_eq!(cycle(&options, &"a", -1), "c");
+        assert_eq!(cycle(&options, &"c", 1), "a");
+        assert_eq!(cycle(&options, &"b", 0), "b");
+        assert_eq!(cycle(&options, &"z", 1), "z");
+    }
+
+    #[test]
+    fn focus_follows_its_item_when_the_items_change() {
+        let mut focus = model(&[true, true, true]);
+        assert!(focus.focus("item 2"));
+        let mut items = focus.items.clone();
+        items.remove(0);
+        focus.set_items(items.clone());
+        assert_eq!(focus.focused_id(), Some("item 2"));
+
+        // the focused item got disabled
+        items[1].enabled = false;
+        focus.set_items(items);
+        assert_eq!(focus.focused_id(), Some("item 1"));
+    }
+}
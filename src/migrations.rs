@@ -0,0 +1,155 @@
+//! Bringing save files written by older versions of the game up to the current `SaveGame` format.
+//!
+//! Every save holds the `version` of the format it was written in, the saves from before it was
+//! added are version 0. The file is first read as a `ron::Value`, `migrate` runs it through the
+//! steps from its version up to `SAVE_VERSION`, and only then is it read as a `SaveGame`. A save
+//! newer than `SAVE_VERSION` is refused rather than read wrong. Changing the format means bumping
+//! `SAVE_VERSION`, adding the step to `MIGRATIONS` and a save of the old format to the fixtures
+//! in `tests/fixtures/saves`.
+//!
+//! `ron::Value` has no enums, so the saves hold none: an enum goes through it as its contents
+//! alone and can't be read back.
+
+use ron::value::{Map, Number};
+use ron::Value;
+use thiserror::Error;
+
+/// Version of the save format written by this build
+pub const SAVE_VERSION: u32 = 3;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum MigrationError {
+    #[error(
+        "the save is from a newer version of the game (format {found}, this one reads up to {})",
+        SAVE_VERSION
+    )]
+    TooNew { found: u32 },
+    #[error("malformed save: {0}")]
+    Malformed(&'static str),
+}
+
+type Migration = fn(Map) -> Result<Map, MigrationError>;
+
+/// The step from each version to the next, the one at index N reading version N
+const MIGRATIONS: [Migration; SAVE_VERSION as usize] =
+    [migrate_v0_to_v1, migrate_v1_to_v2, migrate_v2_to_v3];
+
+fn key(name: &str) -> Value {
+    Value::String(name.to_string())
+}
+
+/// Version of the format of `save`, 0 when it has none
+pub fn save_version(save: &Map) -> Result<u32, MigrationError> {
+    match save.iter().find(|(name, _)| **name == key("version")) {
+        None => Ok(0),
+        Some((_, Value::Number(Number::Integer(version)))) => {
+            u32::try_from(*version).map_err(|_| MigrationError::Malformed("negative version"))
+        }
+        Some(_) => Err(MigrationError::Malformed("the version isn't a number")),
+    }
+}
+
+/// Bring `save` from its version up to `SAVE_VERSION`
+pub fn migrate(save: Value) -> Result<Value, MigrationError> {
+    let Value::Map(mut save) = save else {
+        return Err(MigrationError::Malformed("not a struct"));
+    };
+    let version = save_version(&save)?;
+    if version > SAVE_VERSION {
+        return Err(MigrationError::TooNew { found: version });
+    }
+    for step in &MIGRATIONS[version as usize..] {
+        save = step(save)?;
+    }
+    Ok(Value::Map(save))
+}
+
+/// Replace the field `name` of `map` with `edit` of it, when it's there
+fn edit_field(
+    map: &mut Map,
+    name: &str,
+    edit: impl FnOnce(Value) -> Result<Value, MigrationError>,
+) -> Result<(), MigrationError> {
+    if let Some(value) = map.remove(&key(name)) {
+        map.insert(key(name), edit(value)?);
+    }
+    Ok(())
+}
+
+/// Version 1 added the `version`, and saves the flag values plain, `true` rather than
+/// `Bool(true)`, since the variant is lost going through `ron::Value`
+fn migrate_v0_to_v1(mut save: Map) -> Result<Map, MigrationError> {
+    edit_field(&mut save, "flags", |flags| {
+        let Value::Map(mut flags) = flags else {
+            return Err(MigrationError::Malformed("the flags aren't a struct"));
+        };
+        edit_field(&mut flags, "values", |values| {
+            let Value::Map(values) = values else {
+                return Err(MigrationError::Malformed("the flag values aren't a map"));
+            };
+            let mut plain = Map::new();
+            for (name, value) in values.iter() {
+                // `Bool(true)` reads as the tuple `(true)`
+                let value = match value {
+                    Value::Seq(contents) if contents.len() == 1 => contents[0].clone(),
+                    value => value.clone(),
+                };
+                plain.insert(name.clone(), value);
+            }
+            Ok(Value::Map(plain))
+        })?;
+        Ok(Value::Map(flags))
+    })?;
+    save.insert(key("version"), Value::Number(Number::Integer(1)));
+    Ok(save)
+}
+
+/// Version 2 added the explored tiles of the maps, none explored in older saves
+fn migrate_v1_to_v2(mut save: Map) -> Result<Map, MigrationError> {
+    save.insert(key("exploration"), Value::Map(Map::new()));
+    save.insert(key("version"), Value::Number(Number::Integer(2)));
+    Ok(save)
+}
+
+/// Version 3 added the difficulty, older saves were played at the normal one
+fn migrate_v2_to_v3(mut save: Map) -> Result<Map, MigrationError> {
+    save.insert(key("difficulty"), Value::String("normal".to_string()));
+    save.insert(key("version"), Value::Number(Number::Integer(3)));
+    Ok(save)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_flag_values_are_unwrapped() {
+        let save: Value =
+            ron::from_str(r#"(flags: (values: {"door": Bool(true), "coins": Int(3)}))"#).unwrap();
+        let migrated = migrate(save).unwrap();
+        let expected: Value = ron::from_str(
+            r#"(
+                flags: (values: {"door": true, "coins": 3}),
+                exploration: {},
+                difficulty: "normal",
+                version: 3,
+            )"#,
+        )
+        .unwrap();
+        assert_eq!(migrated, expected);
+        // already current, left alone
+        assert_eq!(migrate(expected.clone()).unwrap(), expected);
+    }
+
+    #[test]
+    fn newer_and_broken_saves_are_refused() {
+        let newer: Value = ron::from_str("(version: 99)").unwrap();
+        assert_eq!(migrate(newer), Err(MigrationError::TooNew { found: 99 }));
+        let broken: Value = ron::from_str("(version: \"one\")").unwrap();
+        assert!(matches!(migrate(broken), Err(MigrationError::Malformed(_))));
+        assert!(matches!(
+            migrate(Value::Bool(true)),
+            Err(MigrationError::Malformed(_))
+        ));
+    }
+}
@@ -0,0 +1,441 @@
+//! A minimap in the corner of the window, one pixel per tile: black where the fog hides the map,
+//! dimmed where the tile was seen before and bright where it's in sight, walls in a warmer tone
+//! than floors, with a dot on the player.
+//!
+//! The image is painted whole when the level spawns, from the explored tiles the fog restored, so
+//! it agrees with the fog from the first frame. After that only the pixels of the tiles in
+//! `FogChanged` events, and of the tiles changed through `TileWriter`, are rewritten.
+
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy_ecs_tilemap::prelude::*;
+use bevy_inspector_egui::bevy_egui::{EguiContext, EguiUserTextures};
+use bevy_window::PrimaryWindow;
+
+use crate::collision::CollisionMap;
+use crate::coords::{self, MapFrame};
+use crate::fog::{self, ExplorationMap, FogChanged, FogState};
+use crate::game::{GameSet, LevelLifecycleSet};
+use crate::player::MainPlayer;
+use crate::state::{level_ready, AppState};
+use crate::tile_writer::TileChanged;
+
+/// Key that shows and hides the minimap
+pub const MINIMAP_KEY: KeyCode = KeyCode::M;
+/// Longest side of the minimap on screen
+const MAX_SIDE: f32 = 180.;
+/// Most screen pixels a tile takes on the minimap
+const MAX_TILE_SIDE: f32 = 4.;
+const UNEXPLORED_COLOR: [u8; 4] = [0, 0, 0, 255];
+const EXPLORED_COLOR: [u8; 4] = [90, 90, 80, 255];
+const VISIBLE_COLOR: [u8; 4] = [200, 200, 180, 255];
+const EXPLORED_WALL_COLOR: [u8; 4] = [120, 95, 70, 255];
+const VISIBLE_WALL_COLOR: [u8; 4] = [230, 170, 110, 255];
+const PLAYER_COLOR: egui::Color32 = egui::Color32::from_rgb(240, 80, 60);
+
+/// Plugin that keeps the minimap image in step with the fog and draws it
+#[derive(Default)]
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Minimap>()
+            .add_systems(
+                OnEnter(AppState::Level),
+                spawn_minimap
+                    .in_set(LevelLifecycleSet)
+                    .after(crate::fog::spawn_fog),
+            )
+            .add_systems(
+                OnExit(AppState::Level),
+                despawn_minimap.in_set(LevelLifecycleSet),
+            )
+            .add_systems(Update, toggle_minimap.in_set(GameSet::Input))
+            .add_systems(
+                Update,
+                (
+                    update_minimap_image.after(crate::fog::update_exploration),
+                    show_minimap,
+                )
+                    .chain()
+                    .in_set(GameSet::RenderPrep)
+                    .run_if(level_ready),
+            )
+            .add_systems(
+                PostUpdate,
+                repaint_changed_tiles
+                    .after(crate::doors::block_closed_doors)
+                    .run_if(level_ready),
+            );
+    }
+}
+
+/// Whether the minimap shows, and its image
+#[derive(Resource, Debug)]
+pub struct Minimap {
+    pub open: bool,
+    /// One pixel per tile of the current map, while the level runs
+    image: Option<Handle<Image>>,
+}
+
+impl Default for Minimap {
+    fn default() -> Self {
+        Self {
+            open: true,
+            image: None,
+        }
+    }
+}
+
+fn tile_color(state: FogState, wall: bool) -> [u8; 4] {
+    match (state, wall) {
+        (FogState::Unexplored, _) => UNEXPLORED_COLOR,
+        (FogState::Explored, false) => EXPLORED_COLOR,
+        (FogState::Explored, true) => EXPLORED_WALL_COLOR,
+        (FogState::Visible, false) => VISIBLE_COLOR,
+        (FogState::Visible, true) => VISIBLE_WALL_COLOR,
+    }
+}
+
+/// Rewrite the pixels of `tiles` in the minimap image
+fn paint_tiles<'a>(
+    image: &mut Image,
+    exploration: &ExplorationMap,
+    collision_map: &CollisionMap,
+    tiles: impl IntoIterator<Item = &'a TilePos>,
+) {
+    let size = exploration.size();
+    for pos in tiles {
+        if !pos.within_map_bounds(&size) {
+            continue;
+        }
+        let pixel = fog::pixel_index(size, pos);
+        let color = tile_color(exploration.state(pos), collision_map.is_blocked(pos));
+        image.data[pixel..pixel + 4].copy_from_slice(&color);
+    }
+}
+
+/// The minimap image of the whole map
+fn minimap_image(exploration: &ExplorationMap, collision_map: &CollisionMap) -> Image {
+    let size = exploration.size();
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &UNEXPLORED_COLOR,
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    let tiles: Vec<_> = fog::all_tiles(size).collect();
+    paint_tiles(&mut image, exploration, collision_map, &tiles);
+    image
+}
+
+fn spawn_minimap(
+    exploration: Res<ExplorationMap>,
+    collision_map: Res<CollisionMap>,
+    mut minimap: ResMut<Minimap>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    minimap.image = Some(images.add(minimap_image(&exploration, &collision_map)));
+}
+
+fn despawn_minimap(
+    mut minimap: ResMut<Minimap>,
+    mut images: ResMut<Assets<Image>>,
+    textures: Option<ResMut<EguiUserTextures>>,
+) {
+    let Some(image) = minimap.image.take() else {
+        return;
+    };
+    if let Some(mut textures) = textures {
+        textures.remove_image(&image);
+    }
+    images.remove(&image);
+}
+
+fn toggle_minimap(keys: Res<Input<KeyCode>>, mut minimap: ResMut<Minimap>) {
+    if keys.just_pressed(MINIMAP_KEY) {
+        minimap.open = !minimap.open;
+    }
+}
+
+fn update_minimap_image(
+    mut fog_changed: EventReader<FogChanged>,
+    exploration: Res<ExplorationMap>,
+    collision_map: Res<CollisionMap>,
+    minimap: Res<Minimap>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let Some(image) = minimap
+        .image
+        .as_ref()
+        .and_then(|image| images.get_mut(image))
+    else {
+        fog_changed.clear();
+        return;
+    };
+    for changed in fog_changed.read() {
+        paint_tiles(image, &exploration, &collision_map, &changed.tiles);
+    }
+}
+
+/// Repaint the tiles changed through `TileWriter`, they may have turned into walls or stopped
+/// being ones
+fn repaint_changed_tiles(
+    mut tile_changes: EventReader<TileChanged>,
+    exploration: Res<ExplorationMap>,
+    collision_map: Res<CollisionMap>,
+    minimap: Res<Minimap>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let Some(image) = minimap
+        .image
+        .as_ref()
+        .and_then(|image| images.get_mut(image))
+    else {
+        tile_changes.clear();
+        return;
+    };
+    let tiles: Vec<_> = tile_changes.read().map(|change| change.pos).collect();
+    paint_tiles(image, &exploration, &collision_map, &tiles);
+}
+
+fn show_minimap(
+    minimap: Res<Minimap>,
+    exploration: Res<ExplorationMap>,
+    map_frame: Res<MapFrame>,
+    players: Query<&Transform, With<MainPlayer>>,
+    mut textures: ResMut<EguiUserTextures>,
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+) {
+    if !minimap.open {
+        return;
+    }
+    let Some(image) = minimap.image.as_ref() else {
+        return;
+    };
+    let Some(mut ctx) = contexts.iter_mut().next() else {
+        return;
+    };
+    let texture = textures
+        .image_id(image)
+        .unwrap_or_else(|| textures.add_image(image.clone()));
+    let size = exploration.size();
+    let tile_side = (MAX_SIDE / size.x.max(size.y).max(1) as f32).min(MAX_TILE_SIDE);
+    let player_tile = players
+        .get_single()
+        .ok()
+        .and_then(|transform| coords::world_to_tile(&map_frame, transform.translation.truncate()));
+
+    egui::Area::new(egui::Id::new("minimap"))
+        .anchor(egui::Align2::LEFT_TOP, egui::vec2(6., 6.))
+        .interactable(false)
+        .show(ctx.get_mut(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                let response = ui.add(egui::Image::new(egui::load::SizedTexture::new(
+                    texture,
+                    [size.x as f32 * tile_side, size.y as f32 * tile_side],
+                )));
+                if let Some(tile) = player_tile {
+                    // image rows go down, tile rows go up
+                    let offset = egui::vec2(
+                        (tile.x as f32 + 0.5) * tile_side,
+                        (size.y as f32 - tile.y as f32 - 0.5) * tile_side,
+                    );
+                    ui.painter().circle_filled(
+                        response.rect.min + offset,
+                        (tile_side / 2.).max(1.5),
+                        PLAYER_COLOR,
+                    );
+                }
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::collision::CollisionMap;
+    use crate::flags::GameFlags;
+    use crate::fog::DiscoveredAreas;
+    use crate::inventory::Inventory;
+    use crate::map::GameInfoAlt;
+    use crate::migrations::SAVE_VERSION;
+    use crate::save_slots::{self, SaveGame, SaveMeta};
+    use crate::spawn_conditions::Difficulty;
+    use crate::tile_writer::TileState;
+
+    const MAP: &str = "maps/TMX/map_test_1.tmx";
+    const SIZE: TilemapSize = TilemapSize { x: 60, y: 8 };
+
+    fn test_app(discovered: DiscoveredAreas) -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<Image>()
+            .init_asset::<crate::helpers::tiled::TiledMap>()
+            .init_resource::<ExplorationMap>()
+            .init_resource::<Minimap>()
+            .insert_resource(discovered)
+            .insert_resource(CollisionMap::new(SIZE))
+            .insert_resource(MapFrame {
+                transform: Transform::default(),
+                grid_size: TilemapGridSize { x: 24., y: 24. },
+                map_type: TilemapType::Square,
+                map_size: SIZE,
+            })
+            .add_event::<FogChanged>()
+            .add_event::<TileChanged>()
+            .add_systems(
+                Update,
+                (
+                    fog::update_exploration,
+                    fog::update_fog_image,
+                    update_minimap_image,
+                    repaint_changed_tiles,
+                )
+                    .chain(),
+            );
+        let tile_map = app.world.resource::<AssetServer>().load(MAP);
+        app.world.insert_resource(GameInfoAlt {
+            creature_atlas: Handle::default(),
+            creature_sheet: None,
+            creature_folder: None,
+            tile_map,
+            templates: Handle::default(),
+        });
+        app.world.run_system_once(fog::spawn_fog);
+        app.world.run_system_once(spawn_minimap);
+        app
+    }
+
+    fn move_player(app: &mut App, tile: TilePos) {
+        let map_frame = app.world.resource::<MapFrame>().clone();
+        let pos = coords::tile_to_world_center(&map_frame, &tile).unwrap();
+        let mut players = app.world.query_filtered::<Entity, With<MainPlayer>>();
+        let transform = Transform::from_translation(pos.extend(0.));
+        match players.get_single(&app.world) {
+            Ok(player) => {
+                app.world.entity_mut(player).insert(transform);
+            }
+            Err(_) => {
+                app.world.spawn((MainPlayer, transform));
+            }
+        }
+        app.update();
+    }
+
+    fn minimap_pixels(app: &App) -> Vec<u8> {
+        let image = app.world.resource::<Minimap>().image.clone().unwrap();
+        app.world
+            .resource::<Assets<Image>>()
+            .get(&image)
+            .unwrap()
+            .data
+            .clone()
+    }
+
+    #[test]
+    fn explored_tiles_survive_a_save_and_load() {
+        let mut app = test_app(DiscoveredAreas::default());
+        move_player(&mut app, TilePos::new(2, 4));
+        move_player(&mut app, TilePos::new(20, 4));
+        let explored = app.world.resource::<ExplorationMap>().explored_tiles();
+        let pixels = minimap_pixels(&app);
+        // only the changed pixels are painted, yet it's the whole map's image
+        assert_eq!(
+            pixels,
+            minimap_image(
+                app.world.resource::<ExplorationMap>(),
+                app.world.resource::<CollisionMap>()
+            )
+            .data
+        );
+        assert_ne!(
+            pixels,
+            minimap_image(&ExplorationMap::new(SIZE), &CollisionMap::new(SIZE)).data
+        );
+
+        let dir = std::env::temp_dir().join(format!("bevy_test_minimap_{}", std::process::id()));
+        let save = SaveGame {
+            version: SAVE_VERSION,
+            meta: SaveMeta {
+                saved_at: 0,
+                map_name: "map_test_1".to_string(),
+                playtime: 0.,
+            },
+            map: MAP.to_string(),
+            player_tile: Some((20, 4)),
+            player_health: None,
+            flags: GameFlags::default(),
+            inventory: Inventory::default(),
+            exploration: app
+                .world
+                .resource::<DiscoveredAreas>()
+                .with_current(MAP, app.world.resource::<ExplorationMap>()),
+            difficulty: Difficulty::Normal,
+        };
+        save_slots::write_slot(&dir, "explored", &save).unwrap();
+        let loaded = save_slots::read_slot(&dir, "explored").unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut reloaded = test_app(loaded.exploration);
+        // painted before the first frame, nothing explored shows black
+        let restored = reloaded.world.resource::<ExplorationMap>();
+        assert_eq!(restored.explored_tiles(), explored);
+        let first_frame = minimap_pixels(&reloaded);
+        for pos in fog::all_tiles(SIZE).filter(|pos| restored.is_explored(pos)) {
+            let pixel = fog::pixel_index(SIZE, &pos);
+            assert_eq!(first_frame[pixel..pixel + 4], EXPLORED_COLOR);
+        }
+
+        move_player(&mut reloaded, TilePos::new(20, 4));
+        assert_eq!(
+            reloaded.world.resource::<ExplorationMap>().explored_tiles(),
+            explored
+        );
+        assert_eq!(minimap_pixels(&reloaded), pixels);
+    }
+
+    #[test]
+    fn changed_tiles_repaint_as_walls() {
+        let mut app = test_app(DiscoveredAreas::default());
+        move_player(&mut app, TilePos::new(2, 4));
+        let wall = TilePos::new(3, 4);
+        let pixel = fog::pixel_index(SIZE, &wall);
+        assert_eq!(minimap_pixels(&app)[pixel..pixel + 4], VISIBLE_COLOR);
+
+        app.world
+            .resource_mut::<CollisionMap>()
+            .set_blocked(&wall, true);
+        app.world.send_event(TileChanged {
+            layer: "collision".to_string(),
+            pos: wall,
+            old: None,
+            new: Some(TileState {
+                texture: 4,
+                color: Color::WHITE,
+            }),
+        });
+        app.update();
+        assert_eq!(minimap_pixels(&app)[pixel..pixel + 4], VISIBLE_WALL_COLOR);
+    }
+
+    #[test]
+    fn explored_tiles_of_another_size_are_dropped() {
+        let mut exploration = ExplorationMap::new(TilemapSize { x: 3, y: 2 });
+        exploration.update_visible(&mut [TilePos::new(2, 1)].into());
+        let saved = exploration.explored_tiles();
+        assert_eq!(saved.bits, [1 << 5]);
+
+        let restored = ExplorationMap::restored(TilemapSize { x: 3, y: 2 }, Some(&saved));
+        assert!(restored.is_explored(&TilePos::new(2, 1)));
+        assert_eq!(restored.state(&TilePos::new(2, 1)), FogState::Explored);
+        let resized = ExplorationMap::restored(TilemapSize { x: 2, y: 3 }, Some(&saved));
+        assert!(!resized.is_explored(&TilePos::new(1, 2)));
+    }
+}
@@ -0,0 +1,381 @@
+//! Moves on the tile grid.
+//!
+//! A mover sends `MoveRequested`, `resolve_moves` checks it against the map bounds, the collision
+//! map and the other creatures and answers with either `MoveResolved` or `MoveBlocked`, or
+//! `BumpAttack` when the player walks into a creature with health, or a `Hostile` creature into the
+//! player, then `apply_moves` carries out the resolved moves. Systems that care about creatures changing tile
+//! read `MoveResolved` rather than watching transforms.
+//!
+//! A resolved move takes its tile at once, so the other requests of the frame for it are
+//! blocked. The requests of a frame are resolved the player's first, then the creatures' by
+//! entity index, for two movers heading to the same tile to always end the same way.
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::collision::{CollisionMap, MoveBlocked};
+use crate::combat::BumpAttack;
+use crate::coords::{self, MapFrame};
+use crate::game::GameSet;
+use crate::health::Health;
+use crate::hostile::Hostile;
+use crate::map::Npc;
+use crate::pathfinding::DiagonalRule;
+use crate::player::MainPlayer;
+use crate::state::level_ready;
+
+/// Seconds a step onto a tile of movement cost 1 takes, costlier tiles take proportionally longer
+pub const STEP_SECS: f32 = 0.12;
+
+/// Plugin that resolves and carries out the moves requested with `MoveRequested`
+#[derive(Default)]
+pub struct MovementPlugin;
+
+impl Plugin for MovementPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MovementSettings>()
+            .register_type::<MovementSettings>()
+            .add_event::<MoveRequested>()
+            .add_event::<MoveResolved>()
+            .add_systems(
+                Update,
+                (tick_step_cooldowns, resolve_moves, apply_moves)
+                    .chain()
+                    .in_set(GameSet::Movement)
+                    .run_if(level_ready)
+                    .run_if(crate::debug_step::gameplay_running),
+            );
+    }
+}
+
+/// How creatures move on the grid
+#[derive(Resource, Reflect, Debug, Clone, PartialEq, Default)]
+#[reflect(Resource)]
+pub struct MovementSettings {
+    /// Which diagonal steps are allowed, planned paths should follow the same rule
+    pub diagonal: DiagonalRule,
+}
+
+/// Sent to ask for `entity` to step by `dir` tiles
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MoveRequested {
+    pub entity: Entity,
+    pub dir: IVec2,
+}
+
+/// Sent when the move of `entity` from `from` to `to` was allowed
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MoveResolved {
+    pub entity: Entity,
+    pub from: TilePos,
+    pub to: TilePos,
+}
+
+/// Direction the creature last stepped or attacked in
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Facing(pub IVec2);
+
+/// The entity moves freely instead of stepping on the grid, `apply_moves` leaves it alone
+///
+/// Its mover still sends `MoveResolved` when it crosses into another tile.
+#[derive(Component, Debug, Default)]
+pub struct FreeMovement;
+
+/// Seconds left before the entity finished walking onto its tile and can move again
+#[derive(Component, Debug, Default)]
+pub struct StepCooldown(pub f32);
+
+impl StepCooldown {
+    pub fn is_walking(&self) -> bool {
+        self.0 > 0.
+    }
+}
+
+pub fn tick_step_cooldowns(time: Res<Time>, mut cooldowns: Query<&mut StepCooldown>) {
+    for mut cooldown in &mut cooldowns {
+        if cooldown.is_walking() {
+            cooldown.0 -= time.delta_seconds();
+        }
+    }
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn resolve_moves(
+    mut requests: EventReader<MoveRequested>,
+    settings: Res<MovementSettings>,
+    movers: Query<(&Transform, Has<MainPlayer>, Has<Hostile>)>,
+    creatures: Query<
+        (Entity, &Transform, Has<Health>, Has<MainPlayer>),
+        Or<(With<MainPlayer>, With<Npc>)>,
+    >,
+    map_frame: Res<MapFrame>,
+    collision_map: Res<CollisionMap>,
+    mut resolved: EventWriter<MoveResolved>,
+    mut blocked: EventWriter<MoveBlocked>,
+    mut attacks: EventWriter<BumpAttack>,
+) {
+    if requests.is_empty() {
+        return;
+    }
+    // the creature on each taken tile, whether it can be attacked and whether it's the player
+    let mut occupied: HashMap<TilePos, (Entity, bool, bool)> = creatures
+        .iter()
+        .filter_map(|(entity, transform, has_health, is_player)| {
+            let tile = coords::world_to_tile(&map_frame, transform.translation.truncate())?;
+            Some((tile, (entity, has_health, is_player)))
+        })
+        .collect();
+
+    // the player first then the creatures by index, for the same winner whatever the order the
+    // requests were sent in
+    let mut requests: Vec<MoveRequested> = requests.read().copied().collect();
+    requests.sort_by_key(|request| {
+        let is_player = movers
+            .get(request.entity)
+            .is_ok_and(|(_, is_player, _)| is_player);
+        (!is_player, request.entity.index())
+    });
+    for request in &requests {
+        let Ok((transform, is_player, is_hostile)) = movers.get(request.entity) else {
+            continue;
+        };
+        let Some(from) = coords::world_to_tile(&map_frame, transform.translation.truncate()) else {
+            continue;
+        };
+        let target = IVec2::new(from.x as i32, from.y as i32) + request.dir;
+        let (Ok(x), Ok(y)) = (u32::try_from(target.x), u32::try_from(target.y)) else {
+            continue;
+        };
+        let to = TilePos::new(x, y);
+        let open = collision_map.movement_cost(&to).is_some()
+            && settings.diagonal.allows(&collision_map, &from, &to);
+        match occupied.get(&to) {
+            // the player walking into a creature attacks it instead, as does a hostile creature
+            // walking into the player
+            Some(&(target, true, target_is_player))
+                if open && (is_player || (is_hostile && target_is_player)) =>
+            {
+                attacks.send(BumpAttack {
+                    attacker: request.entity,
+                    target,
+                    dir: request.dir,
+                });
+            }
+            None if open => {
+                let mover = occupied
+                    .remove(&from)
+                    .unwrap_or((request.entity, false, is_player));
+                occupied.insert(to, mover);
+                resolved.send(MoveResolved {
+                    entity: request.entity,
+                    from,
+                    to,
+                });
+            }
+            _ => {
+                blocked.send(MoveBlocked {
+                    entity: request.entity,
+                    tile: to,
+                });
+            }
+        }
+    }
+}
+
+/// Put the movers on the center of their new tile, they can't move again until they walked there
+pub fn apply_moves(
+    mut commands: Commands,
+    mut resolved: EventReader<MoveResolved>,
+    mut movers: Query<&mut Transform, Without<FreeMovement>>,
+    map_frame: Res<MapFrame>,
+    collision_map: Res<CollisionMap>,
+) {
+    for event in resolved.read() {
+        let Ok(mut transform) = movers.get_mut(event.entity) else {
+            continue;
+        };
+        let Some(pos) = coords::tile_to_world_center(&map_frame, &event.to) else {
+            continue;
+        };
+        transform.translation = pos.extend(transform.translation.z);
+        let cost = collision_map.movement_cost(&event.to).unwrap_or(1);
+        let dir = IVec2::new(event.to.x as i32, event.to.y as i32)
+            - IVec2::new(event.from.x as i32, event.from.y as i32);
+        commands
+            .entity(event.entity)
+            .insert((StepCooldown(STEP_SECS * cost as f32), Facing(dir)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map_frame() -> MapFrame {
+        MapFrame {
+            transform: Transform::IDENTITY,
+            grid_size: TilemapGridSize { x: 10., y: 10. },
+            map_type: TilemapType::Square,
+            map_size: TilemapSize { x: 4, y: 4 },
+        }
+    }
+
+    fn test_app() -> App {
+        let mut collision_map = CollisionMap::new(TilemapSize { x: 4, y: 4 });
+        collision_map.set_blocked(&TilePos::new(2, 1), true);
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<MovementSettings>()
+            .insert_resource(map_frame())
+            .insert_resource(collision_map)
+            .add_event::<MoveRequested>()
+            .add_event::<MoveResolved>()
+            .add_event::<MoveBlocked>()
+            .add_event::<BumpAttack>()
+            .add_systems(Update, (resolve_moves, apply_moves).chain());
+        app
+    }
+
+    fn spawn_at(app: &mut App, tile: TilePos, marker: impl Bundle) -> Entity {
+        let pos = coords::tile_to_world_center(&map_frame(), &tile).unwrap();
+        app.world
+            .spawn((Transform::from_translation(pos.extend(2.)), marker))
+            .id()
+    }
+
+    fn request(app: &mut App, entity: Entity, dir: IVec2) {
+        app.world.send_event(MoveRequested { entity, dir });
+        app.update();
+    }
+
+    fn sent<E: Event + Clone>(app: &App) -> Vec<E> {
+        let events = app.world.resource::<Events<E>>();
+        events.get_reader().read(events).cloned().collect()
+    }
+
+    #[test]
+    fn blocked_move_leaves_the_transform_alone() {
+        let mut app = test_app();
+        let player = spawn_at(&mut app, TilePos::new(1, 1), MainPlayer);
+        let before = *app.world.get::<Transform>(player).unwrap();
+
+        request(&mut app, player, IVec2::X);
+
+        assert_eq!(*app.world.get::<Transform>(player).unwrap(), before);
+        let blocked = sent::<MoveBlocked>(&app);
+        assert_eq!(blocked.len(), 1);
+        assert_eq!(blocked[0].entity, player);
+        assert_eq!(blocked[0].tile, TilePos::new(2, 1));
+        assert!(sent::<MoveResolved>(&app).is_empty());
+        assert!(app.world.get::<StepCooldown>(player).is_none());
+    }
+
+    #[test]
+    fn resolved_move_lands_on_the_tile_center() {
+        let mut app = test_app();
+        let player = spawn_at(&mut app, TilePos::new(1, 1), MainPlayer);
+
+        request(&mut app, player, IVec2::new(1, 1));
+
+        assert_eq!(
+            sent::<MoveResolved>(&app),
+            vec![MoveResolved {
+                entity: player,
+                from: TilePos::new(1, 1),
+                to: TilePos::new(2, 2),
+            }]
+        );
+        let transform = app.world.get::<Transform>(player).unwrap();
+        assert_eq!(transform.translation, Vec3::new(20., 20., 2.));
+        assert!(app.world.get::<StepCooldown>(player).unwrap().is_walking());
+    }
+
+    #[test]
+    fn creatures_block_their_tile() {
+        let mut app = test_app();
+        let player = spawn_at(&mut app, TilePos::new(1, 1), MainPlayer);
+        spawn_at(&mut app, TilePos::new(1, 2), Npc);
+
+        request(&mut app, player, IVec2::Y);
+        assert_eq!(sent::<MoveBlocked>(&app)[0].tile, TilePos::new(1, 2));
+
+        // off the map
+        request(&mut app, player, IVec2::new(0, -2));
+        assert!(sent::<MoveResolved>(&app).is_empty());
+    }
+
+    #[test]
+    fn the_player_wins_a_tile_contested_on_the_same_frame() {
+        let mut app = test_app();
+        let bat = spawn_at(&mut app, TilePos::new(0, 2), Npc);
+        let rat = spawn_at(&mut app, TilePos::new(2, 2), Npc);
+        let player = spawn_at(&mut app, TilePos::new(1, 3), MainPlayer);
+
+        // the creatures ask first, the player still gets there
+        app.world.send_event(MoveRequested {
+            entity: rat,
+            dir: IVec2::NEG_X,
+        });
+        app.world.send_event(MoveRequested {
+            entity: bat,
+            dir: IVec2::X,
+        });
+        request(&mut app, player, IVec2::NEG_Y);
+
+        assert_eq!(
+            sent::<MoveResolved>(&app),
+            vec![MoveResolved {
+                entity: player,
+                from: TilePos::new(1, 3),
+                to: TilePos::new(1, 2),
+            }]
+        );
+        let blocked: Vec<Entity> = sent::<MoveBlocked>(&app).iter().map(|b| b.entity).collect();
+        assert_eq!(blocked, [bat, rat]);
+        let tile_of = |app: &App, entity| {
+            let transform = app.world.get::<Transform>(entity).unwrap();
+            coords::world_to_tile(&map_frame(), transform.translation.truncate())
+        };
+        assert_eq!(tile_of(&app, player), Some(TilePos::new(1, 2)));
+        assert_eq!(tile_of(&app, bat), Some(TilePos::new(0, 2)));
+        assert_eq!(tile_of(&app, rat), Some(TilePos::new(2, 2)));
+
+        // without the player, the creature spawned first wins
+        app.world.send_event(MoveRequested {
+            entity: rat,
+            dir: IVec2::new(-1, 1),
+        });
+        request(&mut app, bat, IVec2::new(1, 1));
+        assert_eq!(tile_of(&app, bat), Some(TilePos::new(1, 3)));
+        assert_eq!(tile_of(&app, rat), Some(TilePos::new(2, 2)));
+    }
+
+    #[test]
+    fn diagonal_rule_decides_steps_past_the_pillar() {
+        // (2, 1) is the pillar, (1, 1) to (2, 2) passes its corner and (1, 1) to (0, 2) doesn't
+        let past_pillar = IVec2::new(1, 1);
+        let open = IVec2::new(-1, 1);
+        let cases = [
+            (DiagonalRule::Always, true, true),
+            (DiagonalRule::NeverThroughCorners, false, true),
+            (DiagonalRule::Disabled, false, false),
+        ];
+        for (rule, past_pillar_allowed, open_allowed) in cases {
+            for (dir, allowed) in [(past_pillar, past_pillar_allowed), (open, open_allowed)] {
+                let mut app = test_app();
+                app.insert_resource(MovementSettings { diagonal: rule });
+                let player = spawn_at(&mut app, TilePos::new(1, 1), MainPlayer);
+
+                request(&mut app, player, dir);
+
+                assert_eq!(
+                    sent::<MoveResolved>(&app).len(),
+                    usize::from(allowed),
+                    "{rule:?} {dir}"
+                );
+                assert_eq!(sent::<MoveBlocked>(&app).len(), usize::from(!allowed));
+            }
+        }
+    }
+}
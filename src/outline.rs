@@ -0,0 +1,290 @@
+//! Outlines around the hovered and the selected creatures.
+//!
+//! Picking marks the creature on the hovered tile `Hovered` and the one on the selected tile
+//! `Selected`. An outlined creature gets four copies of its sprite as children, tinted and moved
+//! a pixel out on each side just behind it, so they follow its movement, frame, flip and y-sort
+//! and only show around its edges. The copies stay once spawned and are hidden while the creature
+//! isn't outlined.
+
+use bevy::prelude::*;
+
+use crate::coords::{self, MapFrame};
+use crate::game::GameSet;
+use crate::map::Npc;
+use crate::palette::{OverlayPalette, OverlayRole};
+use crate::player::MainPlayer;
+use crate::state::level_ready;
+use crate::ui::{HoveredTile, SelectedTile};
+
+/// How far the copies stick out, one pixel of the creature sheet
+const OUTLINE_WIDTH: f32 = 1.;
+/// Local z of the copies, behind the creature and in front of whatever is behind it
+const OUTLINE_Z: f32 = -0.001;
+
+/// Plugin that picks the hovered and selected creatures and outlines them
+#[derive(Default)]
+pub struct OutlinePlugin;
+
+impl Plugin for OutlinePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (pick_creatures, apply_deferred, draw_outlines)
+                .chain()
+                .in_set(GameSet::RenderPrep)
+                .run_if(level_ready),
+        );
+    }
+}
+
+/// The creature is on the hovered tile
+#[derive(Component, Debug)]
+pub struct Hovered;
+
+/// The creature is on the selected tile
+#[derive(Component, Debug)]
+pub struct Selected;
+
+/// The sprite copies drawing the outline of a creature
+#[derive(Component, Debug)]
+struct Outline {
+    sprites: [Entity; 4],
+}
+
+#[derive(Component)]
+struct OutlineSprite;
+
+type Creatures = Or<(With<Npc>, With<MainPlayer>)>;
+
+/// Mark the creatures on the hovered and selected tiles, and unmark the others
+#[allow(clippy::type_complexity)]
+fn pick_creatures(
+    mut commands: Commands,
+    hovered_tile: Res<HoveredTile>,
+    selected_tile: Res<SelectedTile>,
+    map_frame: Res<MapFrame>,
+    creatures: Query<(Entity, &Transform, Has<Hovered>, Has<Selected>), Creatures>,
+) {
+    for (entity, transform, hovered, selected) in &creatures {
+        let tile = coords::world_to_tile(&map_frame, transform.translation.truncate());
+        let on = |picked: Option<_>| tile.is_some() && tile == picked;
+        match (hovered, on(hovered_tile.0)) {
+            (false, true) => {
+                commands.entity(entity).insert(Hovered);
+            }
+            (true, false) => {
+                commands.entity(entity).remove::<Hovered>();
+            }
+            _ => {}
+        }
+        match (selected, on(selected_tile.0)) {
+            (false, true) => {
+                commands.entity(entity).insert(Selected);
+            }
+            (true, false) => {
+                commands.entity(entity).remove::<Selected>();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Offsets of the four copies, one on each side
+fn outline_offsets() -> [Vec3; 4] {
+    [
+        Vec3::new(-OUTLINE_WIDTH, 0., OUTLINE_Z),
+        Vec3::new(OUTLINE_WIDTH, 0., OUTLINE_Z),
+        Vec3::new(0., -OUTLINE_WIDTH, OUTLINE_Z),
+        Vec3::new(0., OUTLINE_WIDTH, OUTLINE_Z),
+    ]
+}
+
+#[allow(clippy::type_complexity)]
+fn draw_outlines(
+    mut commands: Commands,
+    creatures: Query<
+        (
+            Entity,
+            &TextureAtlasSprite,
+            &Handle<TextureAtlas>,
+            Has<Hovered>,
+            Has<Selected>,
+            Option<&Outline>,
+        ),
+        (Creatures, Without<OutlineSprite>),
+    >,
+    mut outline_sprites: Query<(&mut TextureAtlasSprite, &mut Visibility), With<OutlineSprite>>,
+    palette: Res<OverlayPalette>,
+) {
+    for (entity, sprite, atlas, hovered, selected, outline) in &creatures {
+        let color = match (hovered, selected) {
+            (_, true) => Some(palette.color(OverlayRole::Selected)),
+            (true, false) => Some(palette.color(OverlayRole::Hover)),
+            (false, false) => None,
+        };
+        let Some(outline) = outline else {
+            let Some(color) = color else {
+                continue;
+            };
+            let sprites = outline_offsets().map(|offset| {
+                commands
+                    .spawn((
+                        SpriteSheetBundle {
+                            texture_atlas: atlas.clone(),
+                            sprite: TextureAtlasSprite {
+                                color,
+                                ..sprite.clone()
+                            },
+                            transform: Transform::from_translation(offset),
+                            ..default()
+                        },
+                        OutlineSprite,
+                        Name::new("outline"),
+                    ))
+                    .set_parent(entity)
+                    .id()
+            });
+            commands.entity(entity).insert(Outline { sprites });
+            continue;
+        };
+
+        for copy in outline.sprites {
+            let Ok((mut copy_sprite, mut visibility)) = outline_sprites.get_mut(copy) else {
+                continue;
+            };
+            let Some(color) = color else {
+                visibility.set_if_neq(Visibility::Hidden);
+                continue;
+            };
+            visibility.set_if_neq(Visibility::Inherited);
+            if copy_sprite.index != sprite.index
+                || copy_sprite.flip_x != sprite.flip_x
+                || copy_sprite.flip_y != sprite.flip_y
+                || copy_sprite.color != color
+                || copy_sprite.custom_size != sprite.custom_size
+                || copy_sprite.anchor.as_vec() != sprite.anchor.as_vec()
+            {
+                copy_sprite.index = sprite.index;
+                copy_sprite.flip_x = sprite.flip_x;
+                copy_sprite.flip_y = sprite.flip_y;
+                copy_sprite.color = color;
+                copy_sprite.custom_size = sprite.custom_size;
+                copy_sprite.anchor = bevy::sprite::Anchor::Custom(sprite.anchor.as_vec());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs_tilemap::prelude::*;
+
+    use super::*;
+
+    fn test_app() -> (App, Entity) {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<HoveredTile>()
+            .init_resource::<SelectedTile>()
+            .init_resource::<OverlayPalette>()
+            .insert_resource(MapFrame {
+                transform: Transform::IDENTITY,
+                grid_size: TilemapGridSize { x: 24., y: 24. },
+                map_type: TilemapType::Square,
+                map_size: TilemapSize { x: 8, y: 8 },
+            })
+            .add_systems(
+                Update,
+                (
+                    pick_creatures,
+                    apply_deferred,
+                    draw_outlines,
+                    apply_deferred,
+                )
+                    .chain(),
+            );
+        let mut sprite = TextureAtlasSprite::new(41);
+        sprite.flip_x = true;
+        let bat = app
+            .world
+            .spawn((
+                Npc,
+                sprite,
+                Handle::<TextureAtlas>::default(),
+                Transform::from_xyz(48., 24., 2.5),
+            ))
+            .id();
+        (app, bat)
+    }
+
+    /// The sprite and visibility of the outline copies of `creature`
+    fn copies(app: &App, creature: Entity) -> Vec<(TextureAtlasSprite, Visibility)> {
+        let Some(outline) = app.world.get::<Outline>(creature) else {
+            return Vec::new();
+        };
+        outline
+            .sprites
+            .iter()
+            .map(|copy| {
+                let sprite = app.world.get::<TextureAtlasSprite>(*copy).unwrap();
+                let visibility = app.world.get::<Visibility>(*copy).unwrap();
+                (sprite.clone(), *visibility)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn outlines_follow_hover_and_selection() {
+        let (mut app, bat) = test_app();
+        let palette = OverlayPalette::default();
+        app.update();
+        assert!(copies(&app, bat).is_empty());
+
+        app.world.resource_mut::<HoveredTile>().0 = Some(TilePos::new(2, 1));
+        app.update();
+        assert!(app.world.get::<Hovered>(bat).is_some());
+        let hovered = copies(&app, bat);
+        assert_eq!(hovered.len(), 4);
+        for (sprite, _) in &hovered {
+            assert_eq!((sprite.index, sprite.flip_x), (41, true));
+            assert_eq!(sprite.color, palette.color(OverlayRole::Hover));
+        }
+        let outline = app.world.get::<Outline>(bat).unwrap();
+        for copy in outline.sprites {
+            assert_eq!(app.world.get::<Parent>(copy).unwrap().get(), bat);
+            assert!(app.world.get::<Transform>(copy).unwrap().translation.z < 0.);
+        }
+
+        // selection wins, and the copies follow the animation
+        app.world.resource_mut::<SelectedTile>().0 = Some(TilePos::new(2, 1));
+        app.world.get_mut::<TextureAtlasSprite>(bat).unwrap().index = 42;
+        app.update();
+        for (sprite, visibility) in copies(&app, bat) {
+            assert_eq!(
+                (sprite.index, sprite.color),
+                (42, palette.color(OverlayRole::Selected))
+            );
+            assert_eq!(visibility, Visibility::Inherited);
+        }
+
+        // a new palette restyles the outlines already drawn
+        let high_contrast = OverlayPalette {
+            kind: crate::palette::PaletteKind::HighContrast,
+        };
+        *app.world.resource_mut::<OverlayPalette>() = high_contrast;
+        app.update();
+        for (sprite, _) in copies(&app, bat) {
+            assert_eq!(sprite.color, high_contrast.color(OverlayRole::Selected));
+        }
+
+        // unpicked, the copies are kept hidden
+        app.world.resource_mut::<HoveredTile>().0 = None;
+        app.world.resource_mut::<SelectedTile>().0 = None;
+        app.update();
+        assert!(app.world.get::<Hovered>(bat).is_none());
+        assert!(app.world.get::<Selected>(bat).is_none());
+        let hidden = copies(&app, bat);
+        assert_eq!(hidden.len(), 4);
+        assert!(hidden.iter().all(|(_, v)| *v == Visibility::Hidden));
+    }
+}
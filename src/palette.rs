@@ -0,0 +1,201 @@
+//! The colors of the overlays and markers, by role, from one of the built-in palettes.
+//!
+//! The overlay systems ask the `OverlayPalette` for the color of a role instead of using color
+//! constants of their own, so switching to a colorblind-friendly palette restyles all of them.
+//! The overlays drawn once rather than every frame re-tint themselves on `PaletteChanged`.
+
+use bevy::prelude::*;
+
+use crate::game::GameSet;
+
+/// Plugin that holds the `OverlayPalette` and reports its changes
+#[derive(Default)]
+pub struct PalettePlugin;
+
+impl Plugin for PalettePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<OverlayPalette>()
+            .register_type::<OverlayPalette>()
+            .add_event::<PaletteChanged>()
+            .add_systems(Update, announce_palette_change.in_set(GameSet::Input));
+    }
+}
+
+/// What an overlay color shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlayRole {
+    Hover,
+    Selected,
+    Blocked,
+    Path,
+    Danger,
+}
+
+#[derive(Reflect, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PaletteKind {
+    #[default]
+    Default,
+    /// Okabe-Ito colors, told apart with red-green color blindness
+    DeuteranopiaSafe,
+    /// Saturated colors that stand out on any tile
+    HighContrast,
+}
+
+impl PaletteKind {
+    pub const ALL: [PaletteKind; 3] = [
+        PaletteKind::Default,
+        PaletteKind::DeuteranopiaSafe,
+        PaletteKind::HighContrast,
+    ];
+
+    /// Localization key of the palette's name
+    pub fn key(self) -> &'static str {
+        match self {
+            PaletteKind::Default => "palette.default",
+            PaletteKind::DeuteranopiaSafe => "palette.deuteranopia_safe",
+            PaletteKind::HighContrast => "palette.high_contrast",
+        }
+    }
+}
+
+/// The palette the overlays take their colors from, opaque: each overlay applies its own alpha
+#[derive(Resource, Reflect, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[reflect(Resource)]
+pub struct OverlayPalette {
+    pub kind: PaletteKind,
+}
+
+impl OverlayPalette {
+    pub fn color(&self, role: OverlayRole) -> Color {
+        use OverlayRole::*;
+        match (self.kind, role) {
+            (_, Hover) => Color::WHITE,
+            (PaletteKind::Default, Selected) => Color::rgb(1., 0.85, 0.2),
+            (PaletteKind::Default, Blocked) => Color::rgb(1., 0.2, 0.2),
+            (PaletteKind::Default, Path) => Color::rgb(0.3, 0.8, 1.),
+            (PaletteKind::Default, Danger) => Color::rgb(1., 0.35, 0.2),
+            (PaletteKind::DeuteranopiaSafe, Selected) => Color::rgb_u8(0xf0, 0xe4, 0x42),
+            (PaletteKind::DeuteranopiaSafe, Blocked) => Color::rgb_u8(0x00, 0x72, 0xb2),
+            (PaletteKind::DeuteranopiaSafe, Path) => Color::rgb_u8(0xcc, 0x79, 0xa7),
+            (PaletteKind::DeuteranopiaSafe, Danger) => Color::rgb_u8(0xd5, 0x5e, 0x00),
+            (PaletteKind::HighContrast, Selected) => Color::rgb(1., 1., 0.),
+            (PaletteKind::HighContrast, Blocked) => Color::rgb(1., 0., 1.),
+            (PaletteKind::HighContrast, Path) => Color::rgb(0., 1., 1.),
+            (PaletteKind::HighContrast, Danger) => Color::rgb(1., 0., 0.),
+        }
+    }
+
+    /// The color of `role` with the alpha `alpha`
+    pub fn color_with_alpha(&self, role: OverlayRole, alpha: f32) -> Color {
+        self.color(role).with_a(alpha)
+    }
+}
+
+/// `from` moved towards `to` by `t`, from 0 for `from` to 1 for `to`
+pub fn mix(from: Color, to: Color, t: f32) -> Color {
+    let [r0, g0, b0, a0] = from.as_rgba_f32();
+    let [r1, g1, b1, a1] = to.as_rgba_f32();
+    let t = t.clamp(0., 1.);
+    Color::rgba(
+        r0 + (r1 - r0) * t,
+        g0 + (g1 - g0) * t,
+        b0 + (b1 - b0) * t,
+        a0 + (a1 - a0) * t,
+    )
+}
+
+/// `color` darkened to `brightness`, from 0 for black to 1 for the color itself
+pub fn shade(color: Color, brightness: f32) -> Color {
+    let [r, g, b, a] = color.as_rgba_f32();
+    Color::rgba(r * brightness, g * brightness, b * brightness, a)
+}
+
+/// Sent when the `OverlayPalette` changes
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaletteChanged;
+
+fn announce_palette_change(palette: Res<OverlayPalette>, mut changes: EventWriter<PaletteChanged>) {
+    if palette.is_changed() && !palette.is_added() {
+        changes.send(PaletteChanged);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The sources of the overlay and marker systems, which take every color from the palette
+    const OVERLAY_SOURCES: [(&str, &str); 5] = [
+        ("collision.rs", include_str!("collision.rs")),
+        ("markers.rs", include_str!("markers.rs")),
+        ("outline.rs", include_str!("outline.rs")),
+        ("path_debug.rs", include_str!("path_debug.rs")),
+        ("targeting.rs", include_str!("targeting.rs")),
+    ];
+
+    #[test]
+    fn overlays_take_their_colors_from_the_palette() {
+        // white and no color are the untinted tile and the hidden sprite, not overlay colors
+        let allowed = ["Color::WHITE", "Color::NONE"];
+        for (file, source) in OVERLAY_SOURCES {
+            let code = source.split("#[cfg(test)]").next().unwrap();
+            for (number, line) in code.lines().enumerate() {
+                let mut rest = line;
+                while let Some(at) = rest.find("Color::") {
+                    rest = &rest[at..];
+                    assert!(
+                        allowed.iter().any(|color| rest.starts_with(color)),
+                        "{file}:{} builds a color instead of using the palette: {}",
+                        number + 1,
+                        line.trim()
+                    );
+                    rest = &rest["Color::".len()..];
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn palettes_keep_the_roles_apart() {
+        let roles = [
+            OverlayRole::Hover,
+            OverlayRole::Selected,
+            OverlayRole::Blocked,
+            OverlayRole::Path,
+            OverlayRole::Danger,
+        ];
+        for kind in PaletteKind::ALL {
+            let palette = OverlayPalette { kind };
+            for (i, a) in roles.iter().enumerate() {
+                assert_eq!(palette.color(*a).a(), 1.);
+                for b in &roles[i + 1..] {
+                    assert_ne!(palette.color(*a), palette.color(*b), "{kind:?} {a:?} {b:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn palette_edits_are_announced() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins).add_plugins(PalettePlugin);
+        app.update();
+        let changes = |app: &App| {
+            let events = app.world.resource::<Events<PaletteChanged>>();
+            events.get_reader().read(events).count()
+        };
+        assert_eq!(changes(&app), 0);
+
+        app.world.resource_mut::<OverlayPalette>().kind = PaletteKind::HighContrast;
+        app.update();
+        assert_eq!(changes(&app), 1);
+    }
+
+    #[test]
+    fn mixing_and_shading() {
+        let red = Color::rgb(1., 0., 0.);
+        assert_eq!(mix(Color::WHITE, red, 0.5), Color::rgb(1., 0.5, 0.5));
+        assert_eq!(mix(Color::WHITE, red, 2.), red);
+        assert_eq!(shade(red, 0.5), Color::rgb(0.5, 0., 0.));
+    }
+}
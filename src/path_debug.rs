@@ -0,0 +1,154 @@
+//! Debug gizmos showing the paths entities plan to walk, and the tiles that block movement along
+//! with the collision shapes Tiled gives their tiles.
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::collision::CollisionMap;
+use crate::coords::{self, MapFrame};
+use crate::game::GameSet;
+use crate::helpers::tiled::{TileColliders, WorldShape};
+use crate::palette::{shade, OverlayPalette, OverlayRole};
+use crate::pathfinding::MovePath;
+use crate::state::level_ready;
+
+/// Key that toggles drawing the planned `MovePath`s
+pub const PATHS_KEY: KeyCode = KeyCode::F3;
+/// Key that toggles outlining the blocked tiles and the tile collision shapes
+pub const COLLISION_KEY: KeyCode = KeyCode::F4;
+
+/// Plugin that draws the path and collision debug gizmos while they are toggled on
+#[derive(Default)]
+pub struct PathDebugPlugin;
+
+impl Plugin for PathDebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ShowPaths>()
+            .init_resource::<ShowCollision>()
+            .add_systems(
+                Update,
+                (
+                    toggle_debug_gizmos,
+                    draw_move_paths.run_if(resource_equals(ShowPaths(true))),
+                    draw_blocked_tiles.run_if(resource_equals(ShowCollision(true))),
+                )
+                    .chain()
+                    .in_set(GameSet::RenderPrep)
+                    .run_if(level_ready),
+            );
+    }
+}
+
+#[derive(Resource, Default, PartialEq, Eq)]
+pub struct ShowPaths(pub bool);
+
+#[derive(Resource, Default, PartialEq, Eq)]
+pub struct ShowCollision(pub bool);
+
+/// A stable shade of the palette's path color per entity, so overlapping paths can be told apart
+pub fn path_color(palette: &OverlayPalette, entity: Entity) -> Color {
+    // shades rather than hues, hues are what a colorblind palette avoids mixing
+    const SHADES: [f32; 3] = [1., 0.7, 0.45];
+    shade(
+        palette.color(OverlayRole::Path),
+        SHADES[entity.index() as usize % SHADES.len()],
+    )
+}
+
+/// World positions of the centers of the path tiles, tiles outside the map are skipped
+pub fn path_points(map_frame: &MapFrame, path: &MovePath) -> Vec<Vec2> {
+    path.tiles
+        .iter()
+        .filter_map(|tile| coords::tile_to_world_center(map_frame, tile))
+        .collect()
+}
+
+fn toggle_debug_gizmos(
+    keys: Res<Input<KeyCode>>,
+    mut show_paths: ResMut<ShowPaths>,
+    mut show_collision: ResMut<ShowCollision>,
+) {
+    if keys.just_pressed(PATHS_KEY) {
+        show_paths.0 = !show_paths.0;
+    }
+    if keys.just_pressed(COLLISION_KEY) {
+        show_collision.0 = !show_collision.0;
+    }
+}
+
+fn draw_move_paths(
+    mut gizmos: Gizmos,
+    map_frame: Res<MapFrame>,
+    palette: Res<OverlayPalette>,
+    paths: Query<(Entity, &MovePath)>,
+) {
+    let marker_radius = map_frame.grid_size.x.min(map_frame.grid_size.y) * 0.3;
+    for (entity, path) in &paths {
+        let color = path_color(&palette, entity);
+        let points = path_points(&map_frame, path);
+        gizmos.linestrip_2d(points.iter().copied(), color);
+        if let Some(destination) = points.last() {
+            gizmos.circle_2d(*destination, marker_radius, color);
+        }
+    }
+}
+
+/// Outline a tile collision shape
+fn draw_shape(gizmos: &mut Gizmos, shape: &WorldShape, color: Color) {
+    match shape {
+        WorldShape::Rect(rect) => gizmos.rect_2d(rect.center(), 0., rect.size(), color),
+        WorldShape::Polygon(points) => {
+            gizmos.linestrip_2d(points.iter().chain(points.first()).copied(), color);
+        }
+    }
+}
+
+fn draw_blocked_tiles(
+    mut gizmos: Gizmos,
+    map_frame: Res<MapFrame>,
+    palette: Res<OverlayPalette>,
+    collision_map: Res<CollisionMap>,
+    tile_colliders: Res<TileColliders>,
+) {
+    let size = collision_map.size();
+    let tile_size = Vec2::new(map_frame.grid_size.x, map_frame.grid_size.y);
+    let color = palette.color(OverlayRole::Blocked);
+    let shape_color = shade(color, 0.7);
+    for y in 0..size.y {
+        for x in 0..size.x {
+            let tile = TilePos::new(x, y);
+            for shape in tile_colliders.colliders_for_tile(&map_frame, tile) {
+                draw_shape(&mut gizmos, &shape, shape_color);
+            }
+            if !collision_map.is_blocked(&tile) {
+                continue;
+            }
+            if let Some(center) = coords::tile_to_world_center(&map_frame, &tile) {
+                gizmos.rect_2d(center, 0., tile_size, color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_points_are_tile_centers() {
+        let map_frame = MapFrame {
+            transform: Transform::from_xyz(100., 0., 0.),
+            grid_size: TilemapGridSize { x: 16., y: 16. },
+            map_type: TilemapType::Square,
+            map_size: TilemapSize { x: 4, y: 3 },
+        };
+        let path = MovePath {
+            tiles: vec![TilePos::new(0, 0), TilePos::new(1, 1), TilePos::new(9, 9)],
+        };
+        assert_eq!(
+            path_points(&map_frame, &path),
+            vec![Vec2::new(100., 0.), Vec2::new(116., 16.)]
+        );
+        assert_eq!(path.destination(), Some(TilePos::new(9, 9)));
+    }
+}
@@ -0,0 +1,296 @@
+//! Shortest paths over the `CollisionMap`, weighted by the movement cost of each tile.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::collision::CollisionMap;
+use crate::coords;
+
+/// Tiles an entity plans to walk through, in order, the last one being its destination
+#[derive(Component, Debug, Clone, Default)]
+pub struct MovePath {
+    pub tiles: Vec<TilePos>,
+}
+
+impl MovePath {
+    pub fn destination(&self) -> Option<TilePos> {
+        self.tiles.last().copied()
+    }
+}
+
+/// Which diagonal steps movers and planned paths may take
+#[derive(Reflect, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiagonalRule {
+    /// Any diagonal step onto an open tile
+    #[default]
+    Always,
+    /// Only diagonal steps whose two orthogonal neighbours are open, so corners can't be cut
+    NeverThroughCorners,
+    /// No diagonal steps at all
+    Disabled,
+}
+
+impl DiagonalRule {
+    /// Whether the rule allows the step from `from` to the neighbouring tile `to`
+    ///
+    /// Only looks at the tiles passed on the way, whether `to` itself is open is up to the caller.
+    pub fn allows(&self, map: &CollisionMap, from: &TilePos, to: &TilePos) -> bool {
+        self.allows_with(from, to, |tile| map.movement_cost(&tile).is_some())
+    }
+
+    /// Like `allows`, with `open` telling whether a tile can be walked on
+    pub fn allows_with(
+        &self,
+        from: &TilePos,
+        to: &TilePos,
+        open: impl Fn(TilePos) -> bool,
+    ) -> bool {
+        if from.x == to.x || from.y == to.y {
+            return true;
+        }
+        match self {
+            DiagonalRule::Always => true,
+            DiagonalRule::NeverThroughCorners => {
+                open(TilePos::new(to.x, from.y)) && open(TilePos::new(from.x, to.y))
+            }
+            DiagonalRule::Disabled => false,
+        }
+    }
+}
+
+/// The eight tiles around `pos` that are inside `size`
+pub fn neighbours(pos: &TilePos, size: &TilemapSize) -> impl Iterator<Item = TilePos> {
+    let (x, y) = (pos.x as i64, pos.y as i64);
+    let size = *size;
+    (-1..=1)
+        .flat_map(move |dy| (-1..=1).map(move |dx| (x + dx, y + dy)))
+        .filter(move |&(nx, ny)| {
+            (nx, ny) != (x, y) && nx >= 0 && ny >= 0 && nx < size.x as i64 && ny < size.y as i64
+        })
+        .map(|(nx, ny)| TilePos::new(nx as u32, ny as u32))
+}
+
+/// Cheapest path from `from` to `to`, both included, moving in eight directions as far as
+/// `diagonal` allows
+///
+/// Each step costs the movement cost of the tile it enters. Returns `None` when `to` can't be
+/// reached.
+pub fn find_path(
+    map: &CollisionMap,
+    from: TilePos,
+    to: TilePos,
+    diagonal: DiagonalRule,
+) -> Option<Vec<TilePos>> {
+    let size = map.size();
+    if !from.within_map_bounds(&size) || map.movement_cost(&to).is_none() {
+        return None;
+    }
+
+    // A* with the tile distance as heuristic, which never overestimates since costs are >= 1
+    let mut best_cost: HashMap<TilePos, u32> = HashMap::from([(from, 0)]);
+    let mut came_from: HashMap<TilePos, TilePos> = HashMap::new();
+    let mut open = BinaryHeap::from([Reverse((coords::tile_distance(&from, &to), 0, key(from)))]);
+
+    while let Some(Reverse((_, cost, current))) = open.pop() {
+        let current = TilePos::new(current.0, current.1);
+        if current == to {
+            let mut path = vec![to];
+            let mut pos = to;
+            while let Some(previous) = came_from.get(&pos) {
+                path.push(*previous);
+                pos = *previous;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        if best_cost.get(&current).is_some_and(|best| cost > *best) {
+            continue;
+        }
+
+        for next in neighbours(&current, &size) {
+            if !diagonal.allows(map, &current, &next) {
+                continue;
+            }
+            let Some(step) = map.movement_cost(&next) else {
+                continue;
+            };
+            let next_cost = cost + step;
+            if best_cost.get(&next).is_some_and(|best| next_cost >= *best) {
+                continue;
+            }
+            best_cost.insert(next, next_cost);
+            came_from.insert(next, current);
+            let estimate = next_cost + coords::tile_distance(&next, &to);
+            open.push(Reverse((estimate, next_cost, key(next))));
+        }
+    }
+    None
+}
+
+/// `TilePos` isn't `Ord`, so the open list stores its coordinates instead
+fn key(pos: TilePos) -> (u32, u32) {
+    (pos.x, pos.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a collision map from rows drawn top to bottom: `.` costs 1, digits cost that much
+    /// and `#` is blocked
+    fn grid(rows: &[&str]) -> CollisionMap {
+        let size = TilemapSize {
+            x: rows[0].len() as u32,
+            y: rows.len() as u32,
+        };
+        let mut map = CollisionMap::new(size);
+        for (row, line) in rows.iter().enumerate() {
+            let y = size.y - 1 - row as u32;
+            for (x, c) in line.chars().enumerate() {
+                let pos = TilePos::new(x as u32, y);
+                match c {
+                    '#' => map.set_blocked(&pos, true),
+                    '.' => {}
+                    c => map.set_movement_cost(&pos, c.to_digit(10).unwrap()),
+                }
+            }
+        }
+        map
+    }
+
+    fn path_cost(map: &CollisionMap, path: &[TilePos]) -> u32 {
+        path[1..]
+            .iter()
+            .map(|pos| map.movement_cost(pos).unwrap())
+            .sum()
+    }
+
+    #[test]
+    fn straight_path_on_open_ground() {
+        let map = grid(&["....."]);
+        let path = find_path(
+            &map,
+            TilePos::new(0, 0),
+            TilePos::new(4, 0),
+            DiagonalRule::Always,
+        )
+        .unwrap();
+        assert_eq!(path.len(), 5);
+        assert_eq!(path.first(), Some(&TilePos::new(0, 0)));
+        assert_eq!(path.last(), Some(&TilePos::new(4, 0)));
+    }
+
+    #[test]
+    fn path_routes_around_swamp() {
+        let map = grid(&[
+            ".....", //
+            ".999.", //
+            ".....",
+        ]);
+        let from = TilePos::new(0, 1);
+        let to = TilePos::new(4, 1);
+        let path = find_path(&map, from, to, DiagonalRule::Always).unwrap();
+        assert_eq!(path_cost(&map, &path), 4);
+        assert!(path
+            .iter()
+            .all(|pos| pos.y != 1 || pos.x == 0 || pos.x == 4));
+    }
+
+    #[test]
+    fn path_crosses_mud_when_there_is_no_detour() {
+        let map = grid(&[
+            "#####", //
+            ".2.3.", //
+            "#####",
+        ]);
+        let path = find_path(
+            &map,
+            TilePos::new(0, 1),
+            TilePos::new(4, 1),
+            DiagonalRule::Always,
+        )
+        .unwrap();
+        assert_eq!(path.len(), 5);
+        assert_eq!(path_cost(&map, &path), 7);
+    }
+
+    #[test]
+    fn no_path_to_blocked_or_walled_off_tile() {
+        let map = grid(&[
+            "..#..", //
+            "..#.#", //
+            "..#..",
+        ]);
+        assert_eq!(
+            find_path(
+                &map,
+                TilePos::new(0, 0),
+                TilePos::new(4, 1),
+                DiagonalRule::Always
+            ),
+            None
+        );
+        assert_eq!(
+            find_path(
+                &map,
+                TilePos::new(0, 0),
+                TilePos::new(3, 0),
+                DiagonalRule::Always
+            ),
+            None
+        );
+    }
+
+    /// Open 3x3 grid with a pillar just right of the bottom left corner
+    fn pillar_grid() -> CollisionMap {
+        grid(&[
+            "...", //
+            "...", //
+            ".#.",
+        ])
+    }
+
+    #[test]
+    fn corner_rules_on_a_pillar() {
+        let map = pillar_grid();
+        let (from, past_pillar) = (TilePos::new(0, 0), TilePos::new(1, 1));
+        let open = (TilePos::new(0, 1), TilePos::new(1, 2));
+
+        assert!(DiagonalRule::Always.allows(&map, &from, &past_pillar));
+        assert!(!DiagonalRule::NeverThroughCorners.allows(&map, &from, &past_pillar));
+        assert!(DiagonalRule::NeverThroughCorners.allows(&map, &open.0, &open.1));
+        assert!(!DiagonalRule::Disabled.allows(&map, &open.0, &open.1));
+        for rule in [
+            DiagonalRule::Always,
+            DiagonalRule::NeverThroughCorners,
+            DiagonalRule::Disabled,
+        ] {
+            assert!(rule.allows(&map, &from, &open.0));
+        }
+    }
+
+    #[test]
+    fn paths_follow_the_diagonal_rule() {
+        let map = pillar_grid();
+        let (from, to) = (TilePos::new(0, 0), TilePos::new(2, 2));
+        let path = |rule| find_path(&map, from, to, rule).unwrap();
+
+        // straight across the pillar's corner
+        assert_eq!(path(DiagonalRule::Always), [from, TilePos::new(1, 1), to]);
+        // one step up first, then diagonally
+        let around = path(DiagonalRule::NeverThroughCorners);
+        assert_eq!(around.len(), 4);
+        assert!(around
+            .windows(2)
+            .all(|step| DiagonalRule::NeverThroughCorners.allows(&map, &step[0], &step[1])));
+        // only orthogonal steps
+        let orthogonal = path(DiagonalRule::Disabled);
+        assert_eq!(orthogonal.len(), 5);
+        assert!(orthogonal
+            .windows(2)
+            .all(|step| step[0].x == step[1].x || step[0].y == step[1].y));
+    }
+}
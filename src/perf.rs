@@ -0,0 +1,335 @@
+//! Run times of our own hot systems, to see which one eats the frame.
+//!
+//! A system is measured by registering it as `timed!(system)`. With the `perf` feature the macro
+//! wraps it in an adapter timing each run, without the feature it's the system itself, so the
+//! measuring costs nothing in a normal build. The samples of a frame become a `Diagnostic` per
+//! system and a row of the `PerfHistory`, which keeps the last `HISTORY_FRAMES` frames. The
+//! overlay opened with `PERF_KEY` lists the most expensive systems, and `perf dump [path]` writes
+//! the history as CSV.
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use bevy::core::FrameCount;
+use bevy::diagnostic::{Diagnostic, DiagnosticId, Diagnostics, DiagnosticsStore};
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_inspector_egui::bevy_egui::EguiContext;
+use bevy_window::PrimaryWindow;
+
+use crate::auto_hide::{fade_visuals, overlay_alpha, AutoHide, Overlay};
+use crate::console::{ConsoleCommand, DebugConsole};
+use crate::game::GameSet;
+
+/// Key that opens and closes the perf overlay
+pub const PERF_KEY: KeyCode = KeyCode::F8;
+/// Frames kept in the `PerfHistory`
+const HISTORY_FRAMES: usize = 600;
+/// Frames the overlay averages over
+const AVERAGE_FRAMES: usize = 60;
+/// Systems listed in the overlay
+const TOP_SYSTEMS: usize = 8;
+/// File `perf dump` writes without a path
+const DEFAULT_DUMP_PATH: &str = "perf.csv";
+
+/// Run times measured since the last `collect_samples`, filled from any thread
+static SAMPLES: Mutex<Vec<(&'static str, Duration)>> = Mutex::new(Vec::new());
+
+/// Register `system` so its run time is measured when the `perf` feature is on
+macro_rules! timed {
+    ($system:expr) => {
+        $crate::perf::timed_system(stringify!($system), $system)
+    };
+}
+pub(crate) use timed;
+
+/// `system` measured under `name`, see `timed!`
+#[cfg(feature = "perf")]
+pub fn timed_system<M>(
+    name: &'static str,
+    system: impl IntoSystem<(), (), M>,
+) -> impl System<In = (), Out = ()> {
+    use bevy::ecs::system::AdapterSystem;
+
+    let system = IntoSystem::into_system(system);
+    let system_name = system.name();
+    // `stringify!` gives the path the system was registered with
+    let name = name.rsplit("::").next().unwrap_or(name).trim();
+    AdapterSystem::new(Timing { name }, system, system_name)
+}
+
+/// `system` as it is, nothing is measured without the `perf` feature
+#[cfg(not(feature = "perf"))]
+pub fn timed_system<M, S: IntoSystemConfigs<M>>(_name: &'static str, system: S) -> S {
+    system
+}
+
+/// Adapter timing the runs of the system it wraps
+#[cfg(feature = "perf")]
+struct Timing {
+    name: &'static str,
+}
+
+#[cfg(feature = "perf")]
+impl<S: System> bevy::ecs::system::Adapt<S> for Timing {
+    type In = S::In;
+    type Out = S::Out;
+
+    fn adapt(&mut self, input: S::In, run_system: impl FnOnce(S::In) -> S::Out) -> S::Out {
+        let start = std::time::Instant::now();
+        let out = run_system(input);
+        let elapsed = start.elapsed();
+        if let Ok(mut samples) = SAMPLES.lock() {
+            samples.push((self.name, elapsed));
+        }
+        out
+    }
+}
+
+/// Plugin that collects the run times of the `timed!` systems and shows them
+#[derive(Default)]
+pub struct PerfPlugin;
+
+impl Plugin for PerfPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PerfHistory>()
+            .init_resource::<PerfOverlay>()
+            .add_systems(
+                Update,
+                (toggle_perf_overlay, perf_command).in_set(GameSet::Input),
+            )
+            .add_systems(Update, show_perf_overlay.in_set(GameSet::RenderPrep))
+            .add_systems(Last, (register_diagnostics, collect_samples).chain());
+    }
+}
+
+/// Run times of the measured systems in a frame, in milliseconds
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PerfFrame {
+    pub frame: u32,
+    pub samples: Vec<(&'static str, f64)>,
+}
+
+/// The measurements of the last `HISTORY_FRAMES` frames
+#[derive(Resource, Debug, Default)]
+pub struct PerfHistory {
+    frames: VecDeque<PerfFrame>,
+}
+
+impl PerfHistory {
+    pub fn push(&mut self, frame: PerfFrame) {
+        if self.frames.len() == HISTORY_FRAMES {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(frame);
+    }
+
+    /// The `count` systems with the highest average time over the last `frames` frames, most
+    /// expensive first. Frames where a system didn't run count as zero.
+    pub fn top(&self, count: usize, frames: usize) -> Vec<(&'static str, f64)> {
+        let recent = self.frames.iter().rev().take(frames);
+        let frame_count = recent.len().max(1) as f64;
+        let mut totals: HashMap<&'static str, f64> = HashMap::default();
+        for frame in recent {
+            for (name, ms) in &frame.samples {
+                *totals.entry(name).or_default() += ms;
+            }
+        }
+        let mut top: Vec<_> = totals
+            .into_iter()
+            .map(|(name, total)| (name, total / frame_count))
+            .collect();
+        top.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+        top.truncate(count);
+        top
+    }
+
+    /// The history as CSV, a `frame,system,ms` row per measurement
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("frame,system,ms\n");
+        for frame in &self.frames {
+            for (name, ms) in &frame.samples {
+                let _ = writeln!(csv, "{},{name},{ms:.4}", frame.frame);
+            }
+        }
+        csv
+    }
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct PerfOverlay {
+    pub open: bool,
+}
+
+/// Diagnostic of the system measured as `name`
+fn diagnostic_id(name: &str) -> DiagnosticId {
+    // FNV-1a, stable from run to run unlike the std hasher
+    let hash = name.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    DiagnosticId::from_u128(0x5045_5246_0000_0000_0000_0000_0000_0000 | hash as u128)
+}
+
+/// Add the diagnostics of the systems measured for the first time
+fn register_diagnostics(mut store: ResMut<DiagnosticsStore>) {
+    let Ok(samples) = SAMPLES.lock() else {
+        return;
+    };
+    for (name, _) in samples.iter() {
+        let id = diagnostic_id(name);
+        if store.get(id).is_none() {
+            store.add(Diagnostic::new(id, *name, AVERAGE_FRAMES).with_suffix("ms"));
+        }
+    }
+}
+
+/// Move the samples of this frame into the diagnostics and the `PerfHistory`
+fn collect_samples(
+    frame: Res<FrameCount>,
+    mut history: ResMut<PerfHistory>,
+    mut diagnostics: Diagnostics,
+) {
+    let samples = match SAMPLES.lock() {
+        Ok(mut samples) => std::mem::take(&mut *samples),
+        Err(_) => return,
+    };
+    if samples.is_empty() {
+        return;
+    }
+    // a system running more than once in the frame counts once, with its total time
+    let mut totals: Vec<(&'static str, f64)> = Vec::new();
+    for (name, elapsed) in samples {
+        let ms = elapsed.as_secs_f64() * 1000.;
+        match totals
+            .iter_mut()
+            .find(|(total_name, _)| *total_name == name)
+        {
+            Some((_, total)) => *total += ms,
+            None => totals.push((name, ms)),
+        }
+    }
+    for (name, ms) in &totals {
+        diagnostics.add_measurement(diagnostic_id(name), || *ms);
+    }
+    history.push(PerfFrame {
+        frame: frame.0,
+        samples: totals,
+    });
+}
+
+fn toggle_perf_overlay(keys: Res<Input<KeyCode>>, mut overlay: ResMut<PerfOverlay>) {
+    if keys.just_pressed(PERF_KEY) {
+        overlay.open = !overlay.open;
+    }
+}
+
+/// `perf dump [path]` writes the `PerfHistory` as CSV
+fn perf_command(
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<DebugConsole>,
+    history: Res<PerfHistory>,
+) {
+    for command in console_commands.read() {
+        if command.name != "perf" {
+            continue;
+        }
+        if command.args.first().map(String::as_str) != Some("dump") {
+            console.print("usage: perf dump [path]");
+            continue;
+        }
+        let path = command
+            .args
+            .get(1)
+            .map_or(DEFAULT_DUMP_PATH, String::as_str);
+        if cfg!(not(feature = "perf")) {
+            console.print("built without the perf feature, nothing is measured");
+        }
+        match std::fs::write(Path::new(path), history.to_csv()) {
+            Ok(()) => console.print(format!("wrote {} frames to {path}", history.frames.len())),
+            Err(err) => console.print(format!("can't write {path}: {err}")),
+        }
+    }
+}
+
+fn show_perf_overlay(
+    overlay: Res<PerfOverlay>,
+    history: Res<PerfHistory>,
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+    overlays: Query<&AutoHide>,
+) {
+    let alpha = overlay_alpha(&overlays, Overlay::Perf);
+    if !overlay.open || alpha <= 0. {
+        return;
+    }
+    let Some(mut ctx) = contexts.iter_mut().next() else {
+        return;
+    };
+    egui::Area::new(egui::Id::new("perf_overlay"))
+        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-6., 6.))
+        .interactable(false)
+        .show(ctx.get_mut(), |ui| {
+            fade_visuals(ui.visuals_mut(), alpha);
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.label(format!("Slowest systems, last {AVERAGE_FRAMES} frames"));
+                if cfg!(not(feature = "perf")) {
+                    ui.weak("built without the perf feature");
+                }
+                egui::Grid::new("perf_systems").show(ui, |ui| {
+                    for (name, ms) in history.top(TOP_SYSTEMS, AVERAGE_FRAMES) {
+                        ui.monospace(name);
+                        ui.monospace(format!("{ms:.3} ms"));
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(frame: u32, samples: &[(&'static str, f64)]) -> PerfFrame {
+        PerfFrame {
+            frame,
+            samples: samples.to_vec(),
+        }
+    }
+
+    #[test]
+    fn top_systems_average_over_the_recent_frames() {
+        let mut history = PerfHistory::default();
+        history.push(frame(0, &[("old", 100.)]));
+        history.push(frame(1, &[("animate_sprite", 1.), ("swap_lod", 0.5)]));
+        history.push(frame(2, &[("animate_sprite", 3.)]));
+
+        assert_eq!(
+            history.top(5, 2),
+            [("animate_sprite", 2.), ("swap_lod", 0.25)]
+        );
+        assert_eq!(history.top(1, 3), [("old", 100. / 3.)]);
+    }
+
+    #[test]
+    fn the_history_keeps_the_last_frames_and_dumps_them() {
+        let mut history = PerfHistory::default();
+        for i in 0..HISTORY_FRAMES as u32 + 2 {
+            history.push(frame(i, &[("swap_lod", 0.5)]));
+        }
+        assert_eq!(history.frames.len(), HISTORY_FRAMES);
+        let csv = history.to_csv();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("frame,system,ms"));
+        assert_eq!(lines.next(), Some("2,swap_lod,0.5000"));
+        assert_eq!(lines.count(), HISTORY_FRAMES - 1);
+    }
+
+    #[test]
+    fn diagnostic_ids_are_stable_and_distinct() {
+        assert_eq!(diagnostic_id("swap_lod"), diagnostic_id("swap_lod"));
+        assert_ne!(diagnostic_id("swap_lod"), diagnostic_id("animate_sprite"));
+    }
+}
@@ -0,0 +1,330 @@
+//! Physics with bevy_rapier, behind the `physics` feature.
+//!
+//! The blocked tiles of the `CollisionMap` become static colliders, one per `TileStrip`, and the
+//! tiles it lets through keep the finer shapes of their `TileColliders`, rebuilt whenever either
+//! changes. The player gets a kinematic character controller and
+//! `FreeMovement`: the movement keys push it continuously instead of stepping it tile by tile,
+//! and crossing into another tile still sends `MoveResolved`. The other creatures keep stepping
+//! on the grid. Every rapier type stays in this module.
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::collision::{CollisionMap, TileStrip};
+use crate::coords::{self, MapFrame};
+use crate::game::GameSet;
+use crate::helpers::tiled::{TileColliders, WorldShape};
+use crate::movement::{Facing, FreeMovement, MoveResolved, STEP_SECS};
+use crate::player::{shortcut_held, MainPlayer, MOVE_KEYS};
+use crate::state::level_ready;
+
+/// Pixels in a rapier meter, one tile of the oryx sheets
+const PIXELS_PER_METER: f32 = 24.;
+/// Radius of the player's collider, in tiles, a bit under half so it fits through a doorway
+const PLAYER_RADIUS: f32 = 0.4;
+
+/// Plugin that runs rapier and moves the player with it
+#[derive(Default)]
+pub struct PhysicsPlugin;
+
+impl Plugin for PhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(
+            PIXELS_PER_METER,
+        ))
+        .add_systems(Startup, disable_gravity)
+        .add_systems(
+            Update,
+            (
+                (
+                    spawn_wall_colliders.run_if(
+                        resource_changed::<CollisionMap>()
+                            .or_else(resource_changed::<TileColliders>()),
+                    ),
+                    add_player_controller,
+                    free_player_input.run_if(crate::menu_focus::menu_closed),
+                )
+                    .in_set(GameSet::Movement),
+                track_player_tile.in_set(GameSet::Gameplay),
+            )
+                .run_if(level_ready)
+                .run_if(crate::debug_step::gameplay_running),
+        );
+    }
+}
+
+/// A static collider over a strip of blocked tiles, or a collision shape of a tile
+#[derive(Component)]
+struct WallCollider;
+
+/// The tile the freely moving player was last on
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+struct LastTile(TilePos);
+
+/// Seen from above, nothing falls
+fn disable_gravity(mut config: ResMut<RapierConfiguration>) {
+    config.gravity = Vec2::ZERO;
+}
+
+/// Center and half size of `strip` in world space
+fn strip_bounds(map_frame: &MapFrame, strip: &TileStrip) -> Option<(Vec2, Vec2)> {
+    let first = coords::tile_to_world_center(map_frame, &TilePos::new(strip.x, strip.y))?;
+    let last = TilePos::new(strip.x + strip.len - 1, strip.y);
+    let last = coords::tile_to_world_center(map_frame, &last)?;
+    let tile = Vec2::new(map_frame.grid_size.x, map_frame.grid_size.y)
+        * map_frame.transform.scale.truncate();
+    Some(((first + last) / 2., (last - first).abs() / 2. + tile / 2.))
+}
+
+/// A collider for `shape` and where it goes
+fn shape_collider(shape: &WorldShape) -> Option<(Collider, Vec2)> {
+    match shape {
+        WorldShape::Rect(rect) => {
+            let half_size = rect.half_size();
+            Some((Collider::cuboid(half_size.x, half_size.y), rect.center()))
+        }
+        // the points are already in world space
+        WorldShape::Polygon(points) => Some((Collider::convex_hull(points)?, Vec2::ZERO)),
+    }
+}
+
+fn spawn_wall_colliders(
+    mut commands: Commands,
+    collision_map: Res<CollisionMap>,
+    tile_colliders: Res<TileColliders>,
+    map_frame: Option<Res<MapFrame>>,
+    walls: Query<Entity, With<WallCollider>>,
+) {
+    let Some(map_frame) = map_frame else {
+        return;
+    };
+    for entity in &walls {
+        commands.entity(entity).despawn();
+    }
+    let strips = collision_map.blocked_strips();
+    debug!("spawning {} wall colliders", strips.len());
+    for strip in strips {
+        let Some((center, half_size)) = strip_bounds(&map_frame, &strip) else {
+            continue;
+        };
+        commands.spawn((
+            RigidBody::Fixed,
+            Collider::cuboid(half_size.x, half_size.y),
+            TransformBundle::from_transform(Transform::from_translation(center.extend(0.))),
+            WallCollider,
+            Name::new("wall collider"),
+        ));
+    }
+
+    let size = collision_map.size();
+    for y in 0..size.y {
+        for x in 0..size.x {
+            let tile = TilePos::new(x, y);
+            if collision_map.is_blocked(&tile) {
+                continue;
+            }
+            for shape in tile_colliders.colliders_for_tile(&map_frame, tile) {
+                let Some((collider, center)) = shape_collider(&shape) else {
+                    continue;
+                };
+                commands.spawn((
+                    RigidBody::Fixed,
+                    collider,
+                    TransformBundle::from_transform(Transform::from_translation(center.extend(0.))),
+                    WallCollider,
+                    Name::new("tile collider"),
+                ));
+            }
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn add_player_controller(
+    mut commands: Commands,
+    map_frame: Res<MapFrame>,
+    players: Query<(Entity, &Transform), (With<MainPlayer>, Without<FreeMovement>)>,
+) {
+    for (entity, transform) in &players {
+        let Some(tile) = coords::world_to_tile(&map_frame, transform.translation.truncate()) else {
+            continue;
+        };
+        let radius = map_frame.grid_size.x * PLAYER_RADIUS;
+        commands.entity(entity).insert((
+            RigidBody::KinematicPositionBased,
+            Collider::ball(radius),
+            KinematicCharacterController::default(),
+            FreeMovement,
+            LastTile(tile),
+        ));
+    }
+}
+
+/// Direction held on the movement keys, not normalized
+fn held_direction(input: &Input<KeyCode>) -> Vec2 {
+    MOVE_KEYS
+        .iter()
+        .filter(|(_, keys)| input.any_pressed(keys.iter().copied()))
+        .fold(Vec2::ZERO, |sum, (dir, _)| sum + dir.as_vec2())
+}
+
+/// Move the player at the speed of a step per `STEP_SECS`, with the same keys as on the grid
+fn free_player_input(
+    input: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    map_frame: Res<MapFrame>,
+    mut players: Query<&mut KinematicCharacterController, With<MainPlayer>>,
+) {
+    let dir = if shortcut_held(&input) {
+        Vec2::ZERO
+    } else {
+        held_direction(&input).normalize_or_zero()
+    };
+    let speed = map_frame.grid_size.x / STEP_SECS;
+    for mut controller in &mut players {
+        controller.translation = (dir != Vec2::ZERO).then(|| dir * speed * time.delta_seconds());
+    }
+}
+
+/// Send `MoveResolved` when the player crosses into another tile, for the grid systems
+fn track_player_tile(
+    mut commands: Commands,
+    map_frame: Res<MapFrame>,
+    mut players: Query<(Entity, &Transform, &mut LastTile), With<MainPlayer>>,
+    mut resolved: EventWriter<MoveResolved>,
+) {
+    for (entity, transform, mut last) in &mut players {
+        let Some(tile) = coords::world_to_tile(&map_frame, transform.translation.truncate()) else {
+            continue;
+        };
+        if tile == last.0 {
+            continue;
+        }
+        let dir =
+            IVec2::new(tile.x as i32, tile.y as i32) - IVec2::new(last.0.x as i32, last.0.y as i32);
+        commands
+            .entity(entity)
+            .insert(Facing(dir.clamp(IVec2::NEG_ONE, IVec2::ONE)));
+        resolved.send(MoveResolved {
+            entity,
+            from: last.0,
+            to: tile,
+        });
+        last.0 = tile;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+    use crate::debug_step::DebugStep;
+    use crate::game::configure_game_sets;
+    use crate::menu_focus::MenuFocus;
+    use crate::state::{AppState, LevelReady};
+
+    #[test]
+    fn the_player_cannot_walk_through_a_wall() {
+        let mut app = App::new();
+        let mut collision_map = CollisionMap::new(TilemapSize { x: 6, y: 3 });
+        for y in 0..3 {
+            collision_map.set_blocked(&TilePos::new(4, y), true);
+        }
+        app.add_plugins((MinimalPlugins, TransformPlugin, HierarchyPlugin))
+            .add_state::<AppState>()
+            .init_resource::<DebugStep>()
+            .init_resource::<MenuFocus>()
+            .init_resource::<Input<KeyCode>>()
+            .insert_resource(LevelReady(true))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                16,
+            )))
+            .insert_resource(collision_map)
+            .init_resource::<TileColliders>()
+            .insert_resource(MapFrame {
+                transform: Transform::IDENTITY,
+                grid_size: TilemapGridSize { x: 24., y: 24. },
+                map_type: TilemapType::Square,
+                map_size: TilemapSize { x: 6, y: 3 },
+            })
+            .add_event::<MoveResolved>()
+            .add_plugins(PhysicsPlugin);
+        configure_game_sets(&mut app);
+        app.world
+            .resource_mut::<NextState<AppState>>()
+            .set(AppState::Level);
+        let player = app
+            .world
+            .spawn((
+                MainPlayer,
+                TransformBundle::from_transform(Transform::from_xyz(24., 24., 2.)),
+            ))
+            .id();
+        app.update();
+        assert!(app.world.get::<FreeMovement>(player).is_some());
+
+        app.world.resource_mut::<Input<KeyCode>>().press(KeyCode::D);
+        for _ in 0..120 {
+            app.update();
+        }
+
+        // the wall's left edge is at 84, the player's collider is 9.6 wide on each side
+        let x = app.world.get::<Transform>(player).unwrap().translation.x;
+        assert!(x > 60., "the player didn't move, at {x}");
+        assert!(x < 84. - 9., "the player went through the wall, at {x}");
+        assert_eq!(app.world.get::<Facing>(player).unwrap().0, IVec2::X);
+    }
+
+    #[test]
+    fn tile_shapes_become_colliders_off_the_blocked_tiles() {
+        let map =
+            crate::helpers::tiled::load_test_map("assets/maps/TMX/map_test_tile_colliders.tmx");
+        let tile_colliders = TileColliders::from_map(&map.map);
+        let map_frame = MapFrame {
+            transform: Transform::IDENTITY,
+            grid_size: TilemapGridSize { x: 24., y: 24. },
+            map_type: TilemapType::Square,
+            map_size: TilemapSize { x: 3, y: 2 },
+        };
+        let shapes_on = |tile| tile_colliders.colliders_for_tile(&map_frame, tile).count();
+        let all_shapes: usize = (0..2)
+            .flat_map(|y| (0..3).map(move |x| TilePos::new(x, y)))
+            .map(shapes_on)
+            .sum();
+        let on_blocked = shapes_on(TilePos::new(1, 0));
+        assert!(on_blocked > 0);
+
+        let mut collision_map = CollisionMap::new(map_frame.map_size);
+        collision_map.set_blocked(&TilePos::new(1, 0), true);
+        let mut app = App::new();
+        app.insert_resource(collision_map)
+            .insert_resource(tile_colliders)
+            .insert_resource(map_frame);
+        app.world.run_system_once(spawn_wall_colliders);
+
+        let mut colliders = app.world.query::<(&Name, &Collider)>();
+        let names: Vec<_> = colliders
+            .iter(&app.world)
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(
+            names
+                .iter()
+                .filter(|name| **name == "tile collider")
+                .count(),
+            all_shapes - on_blocked
+        );
+        assert_eq!(
+            names
+                .iter()
+                .filter(|name| **name == "wall collider")
+                .count(),
+            1
+        );
+    }
+}
@@ -0,0 +1,172 @@
+//! The player character and its movement on the tile grid.
+
+use bevy::prelude::*;
+
+use crate::accessibility::{KeyRepeat, MotionPreferences, MoveInputMode};
+use crate::game::GameSet;
+use crate::movement::{FreeMovement, MoveRequested, StepCooldown};
+use crate::state::level_ready;
+
+/// Step directions and the keys moving the player that way, numpad, QWEADZXC and arrows
+pub const MOVE_KEYS: [(IVec2, &[KeyCode]); 8] = [
+    (IVec2::new(-1, -1), &[KeyCode::Numpad1, KeyCode::Z]),
+    (
+        IVec2::new(0, -1),
+        &[KeyCode::Numpad2, KeyCode::X, KeyCode::Down],
+    ),
+    (IVec2::new(1, -1), &[KeyCode::Numpad3, KeyCode::C]),
+    (
+        IVec2::new(-1, 0),
+        &[KeyCode::Numpad4, KeyCode::A, KeyCode::Left],
+    ),
+    (
+        IVec2::new(1, 0),
+        &[KeyCode::Numpad6, KeyCode::D, KeyCode::Right],
+    ),
+    (IVec2::new(-1, 1), &[KeyCode::Numpad7, KeyCode::Q]),
+    (
+        IVec2::new(0, 1),
+        &[KeyCode::Numpad8, KeyCode::W, KeyCode::Up],
+    ),
+    (IVec2::new(1, 1), &[KeyCode::Numpad9, KeyCode::E]),
+];
+
+/// Plugin that turns the movement keys into `MoveRequested` events for the player
+#[derive(Default)]
+pub struct PlayerPlugin;
+
+impl Plugin for PlayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            player_input
+                .in_set(GameSet::Input)
+                .run_if(level_ready)
+                .run_if(crate::debug_step::gameplay_running)
+                .run_if(crate::console::console_closed)
+                .run_if(crate::menu_focus::menu_closed)
+                .run_if(not(crate::editor::editing))
+                .run_if(not(crate::portals::in_transit)),
+        );
+    }
+}
+
+#[derive(Component)]
+pub struct MainPlayer;
+
+/// Where the player was spawned from the map, respawning puts it back there
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct SpawnPoint(pub Vec3);
+
+#[allow(clippy::type_complexity)]
+fn player_input(
+    input: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    preferences: Res<MotionPreferences>,
+    query: Query<(Entity, Option<&StepCooldown>), (With<MainPlayer>, Without<FreeMovement>)>,
+    mut requests: EventWriter<MoveRequested>,
+    mut repeat: Local<KeyRepeat>,
+) {
+    if shortcut_held(&input) {
+        return;
+    }
+    let interval = preferences.move_repeat_interval();
+    let pressed = move_direction(|keys| input.any_just_pressed(keys.iter().copied()));
+    let move_input = if pressed != IVec2::ZERO {
+        repeat.restart(interval);
+        pressed
+    } else if preferences.move_input == MoveInputMode::Hold {
+        let held = move_direction(|keys| input.any_pressed(keys.iter().copied()));
+        let walking = query
+            .iter()
+            .any(|(_, cooldown)| cooldown.is_some_and(StepCooldown::is_walking));
+        if held != IVec2::ZERO && repeat.tick(time.delta_seconds(), interval, walking) {
+            held
+        } else {
+            IVec2::ZERO
+        }
+    } else {
+        IVec2::ZERO
+    };
+
+    if move_input.cmpeq(IVec2::ZERO).all() {
+        return;
+    }
+
+    for (entity, cooldown) in &query {
+        // still walking onto the last tile
+        if cooldown.is_some_and(StepCooldown::is_walking) {
+            continue;
+        }
+        requests.send(MoveRequested {
+            entity,
+            dir: move_input,
+        });
+    }
+}
+
+/// Whether Ctrl or Alt is held, the move keys are then part of a shortcut like Ctrl+Shift+C
+pub fn shortcut_held(input: &Input<KeyCode>) -> bool {
+    input.any_pressed([
+        KeyCode::ControlLeft,
+        KeyCode::ControlRight,
+        KeyCode::AltLeft,
+        KeyCode::AltRight,
+    ])
+}
+
+/// The step direction of the `MOVE_KEYS` for which `active` holds, combining the axes
+fn move_direction(active: impl Fn(&[KeyCode]) -> bool) -> IVec2 {
+    let mut p = IVec2::ZERO;
+    for (dir, keys) in MOVE_KEYS {
+        if !active(keys) {
+            continue;
+        }
+        if dir.x != 0 {
+            p.x = dir.x;
+        }
+        if dir.y != 0 {
+            p.y = dir.y;
+        }
+    }
+    p
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug_info::COPY_KEY;
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<Input<KeyCode>>()
+            .init_resource::<MotionPreferences>()
+            .add_event::<MoveRequested>()
+            .add_systems(Update, player_input);
+        app.world.spawn(MainPlayer);
+        app
+    }
+
+    fn requests(app: &App) -> Vec<IVec2> {
+        let events = app.world.resource::<Events<MoveRequested>>();
+        events.get_reader().read(events).map(|e| e.dir).collect()
+    }
+
+    #[test]
+    fn copy_chord_does_not_move_the_player() {
+        let mut app = test_app();
+        let mut keys = app.world.resource_mut::<Input<KeyCode>>();
+        keys.press(KeyCode::ControlLeft);
+        keys.press(KeyCode::ShiftLeft);
+        keys.press(COPY_KEY);
+        app.update();
+        assert!(requests(&app).is_empty());
+
+        let mut keys = app.world.resource_mut::<Input<KeyCode>>();
+        keys.reset_all();
+        keys.press(COPY_KEY);
+        app.update();
+        assert_eq!(requests(&app), [IVec2::new(1, -1)]);
+    }
+}
@@ -0,0 +1,446 @@
+//! Portals, placed in Tiled as objects of type "portal" and linked in pairs, or leading to
+//! another map.
+//!
+//! Portals read these properties:
+//!
+//! * `link` the id shared by the two portals of a pair
+//! * `target_map` the map the portal leads to, relative to the assets folder
+//! * `target_portal` the name of the portal object the player arrives on in `target_map`, the
+//!   spawn object when missing
+//!
+//! Stepping onto a linked portal fades the screen to black, moves the player to the other portal
+//! of the pair and fades back in. Stepping onto a portal with a `target_map` switches to that map,
+//! see `map_switch`. The portal the player arrives or spawns on stays inactive until the player
+//! has left its tile once, so arriving doesn't send the player straight back.
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::camera::{self, MainCamera, PanCam};
+use crate::coords::{self, MapFrame};
+use crate::game::{GameSet, LevelLifecycleSet};
+use crate::helpers;
+use crate::lifetime::ExpireOnStateExit;
+use crate::map::GameInfoAlt;
+use crate::map_switch::{LoadMap, PendingArrival};
+use crate::player::MainPlayer;
+use crate::state::{level_ready, AppState};
+use crate::tween::{Ease, Tween, TweenFinished, TweenValue};
+
+/// Seconds the screen takes to fade out, and again to fade back in
+pub const FADE_SECS: f32 = 0.3;
+/// Side of the fade overlay in world units, enough to cover the view at any zoom
+const OVERLAY_SIZE: f32 = 100_000.;
+
+/// Plugin that spawns the portals of the map and carries the player through them
+#[derive(Default)]
+pub struct PortalPlugin;
+
+impl Plugin for PortalPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PortalWatch>()
+            .add_systems(
+                OnEnter(AppState::Level),
+                spawn_portals.in_set(LevelLifecycleSet),
+            )
+            .add_systems(
+                OnExit(AppState::Level),
+                despawn_portals.in_set(LevelLifecycleSet),
+            )
+            .add_systems(
+                Update,
+                (
+                    enter_portals.run_if(crate::debug_step::gameplay_running),
+                    finish_fades,
+                )
+                    .chain()
+                    .in_set(GameSet::Gameplay)
+                    .run_if(level_ready),
+            );
+    }
+}
+
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub struct Portal {
+    pub tile: TilePos,
+    pub link: Option<String>,
+    /// Tile of the other portal of the pair, `None` while unlinked
+    pub destination: Option<TilePos>,
+    /// The other map the portal leads to, used over the link
+    pub target: Option<PortalTarget>,
+}
+
+/// Where a portal to another map leads
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortalTarget {
+    pub map: String,
+    /// Name of the portal object arrived on, the spawn object when `None`
+    pub portal: Option<String>,
+}
+
+/// The tile the player was last seen on, and the portal it arrived on if it hasn't left it yet
+#[derive(Resource, Default, Debug)]
+pub struct PortalWatch {
+    last_tile: Option<TilePos>,
+    arrival: Option<TilePos>,
+    /// Whether the player was seen since the portals spawned
+    settled: bool,
+}
+
+/// The fullscreen overlay of a trip through a portal, fading out then back in
+#[derive(Component, Debug)]
+pub struct PortalFade {
+    player: Entity,
+    destination: TilePos,
+    /// Whether the player was moved and the overlay is fading back in
+    arrived: bool,
+}
+
+/// Run condition, true while the player is on its way through a portal
+pub fn in_transit(fades: Query<(), With<PortalFade>>) -> bool {
+    !fades.is_empty()
+}
+
+/// Give each pair of portals sharing a `link` the other's tile as destination
+///
+/// Links used by a single portal or by more than two are reported and left unlinked.
+fn link_portals(portals: &mut [Portal]) {
+    let mut by_link: HashMap<String, Vec<usize>> = HashMap::default();
+    for (index, portal) in portals.iter().enumerate() {
+        if let Some(link) = &portal.link {
+            by_link.entry(link.clone()).or_default().push(index);
+        }
+    }
+    for (link, indices) in by_link {
+        match indices[..] {
+            [a, b] => {
+                portals[a].destination = Some(portals[b].tile);
+                portals[b].destination = Some(portals[a].tile);
+            }
+            [_] => warn!("portal link {link} has no other end"),
+            _ => warn!(
+                "portal link {link} is used by {} portals, only pairs are linked",
+                indices.len()
+            ),
+        }
+    }
+}
+
+fn spawn_portals(
+    mut commands: Commands,
+    game_info: Res<GameInfoAlt>,
+    tile_maps: Res<Assets<helpers::tiled::TiledMap>>,
+    map_frame: Res<MapFrame>,
+    mut watch: ResMut<PortalWatch>,
+) {
+    let Some(map) = tile_maps.get(&game_info.tile_map) else {
+        return;
+    };
+    *watch = PortalWatch::default();
+
+    let mut portals = Vec::new();
+    let mut names = Vec::new();
+    for layer in helpers::tiled::all_layers(&map.map) {
+        let tiled::LayerType::Objects(object_layer) = layer.layer_type() else {
+            continue;
+        };
+        for object in object_layer.objects() {
+            if !object.user_type.eq_ignore_ascii_case("portal") {
+                continue;
+            }
+            let string = |name| match object.properties.get(name) {
+                Some(tiled::PropertyValue::StringValue(value)) if !value.is_empty() => {
+                    Some(value.clone())
+                }
+                _ => None,
+            };
+            let link = string("link");
+            let target = string("target_map").map(|map| PortalTarget {
+                map,
+                portal: string("target_portal"),
+            });
+            if link.is_none() && target.is_none() {
+                warn!(
+                    "portal {} has neither a link nor a target_map property, skipping it",
+                    object.name
+                );
+                continue;
+            }
+            let Some(tile) = coords::object_tile(&map_frame, &object) else {
+                warn!("portal {} is outside the map, skipping it", object.name);
+                continue;
+            };
+            portals.push(Portal {
+                tile,
+                link,
+                destination: None,
+                target,
+            });
+            names.push(Name::new(object.name.clone()));
+        }
+    }
+
+    link_portals(&mut portals);
+    commands.spawn_batch(portals.into_iter().zip(names));
+}
+
+fn despawn_portals(mut commands: Commands, portals: Query<Entity, With<Portal>>) {
+    for entity in &portals {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Start a trip when the player steps onto a linked portal, or switch maps when the portal
+/// leads to another map
+#[allow(clippy::too_many_arguments)]
+fn enter_portals(
+    mut commands: Commands,
+    mut watch: ResMut<PortalWatch>,
+    player_q: Query<(Entity, &Transform), With<MainPlayer>>,
+    portals: Query<&Portal>,
+    fades: Query<(), With<PortalFade>>,
+    cameras: Query<Entity, With<MainCamera>>,
+    map_frame: Res<MapFrame>,
+    mut load_map: EventWriter<LoadMap>,
+) {
+    let Ok((player, transform)) = player_q.get_single() else {
+        return;
+    };
+    let tile = coords::world_to_tile(&map_frame, transform.translation.truncate());
+    if tile == watch.last_tile {
+        return;
+    }
+    watch.last_tile = tile;
+    if !watch.settled {
+        // the tile the player spawned on counts as the one it arrived on
+        watch.settled = true;
+        watch.arrival = tile;
+    }
+    if watch.arrival != tile {
+        // left the portal it arrived on, which works again
+        watch.arrival = None;
+    }
+    if !fades.is_empty() || watch.arrival.is_some() {
+        return;
+    }
+    let Some(portal) = portals.iter().find(|portal| Some(portal.tile) == tile) else {
+        return;
+    };
+    if let Some(target) = &portal.target {
+        match &target.portal {
+            Some(name) => commands.insert_resource(PendingArrival {
+                portal_name: name.clone(),
+            }),
+            None => commands.remove_resource::<PendingArrival>(),
+        }
+        load_map.send(LoadMap {
+            path: target.map.clone(),
+        });
+        return;
+    }
+    let Some(destination) = portal.destination else {
+        return;
+    };
+
+    let mut fade = commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgba(0., 0., 0., 0.),
+                custom_size: Some(Vec2::splat(OVERLAY_SIZE)),
+                ..default()
+            },
+            // just below the camera, in front of everything else
+            transform: Transform::from_xyz(0., 0., -0.1),
+            ..default()
+        },
+        Tween::new(
+            TweenValue::Alpha { from: 0., to: 1. },
+            Ease::Linear,
+            FADE_SECS,
+        ),
+        PortalFade {
+            player,
+            destination,
+            arrived: false,
+        },
+        ExpireOnStateExit(AppState::Level),
+        Name::new("portal fade"),
+    ));
+    if let Ok(camera) = cameras.get_single() {
+        fade.set_parent(camera);
+    }
+}
+
+/// Move the player once the screen is black, and remove the overlay once it faded back in
+fn finish_fades(
+    mut commands: Commands,
+    mut finished: EventReader<TweenFinished>,
+    mut fades: Query<&mut PortalFade>,
+    mut players: Query<&mut Transform, (With<MainPlayer>, Without<MainCamera>)>,
+    mut cameras: Query<(&PanCam, &OrthographicProjection, &mut Transform), With<MainCamera>>,
+    map_frame: Res<MapFrame>,
+    mut watch: ResMut<PortalWatch>,
+) {
+    for event in finished.read() {
+        let Ok(mut fade) = fades.get_mut(event.entity) else {
+            continue;
+        };
+        if fade.arrived {
+            commands.entity(event.entity).despawn_recursive();
+            continue;
+        }
+        fade.arrived = true;
+        commands.entity(event.entity).insert(Tween::new(
+            TweenValue::Alpha { from: 1., to: 0. },
+            Ease::Linear,
+            FADE_SECS,
+        ));
+
+        let Some(pos) = coords::tile_to_world_center(&map_frame, &fade.destination) else {
+            continue;
+        };
+        if let Ok(mut transform) = players.get_mut(fade.player) {
+            transform.translation.x = pos.x;
+            transform.translation.y = pos.y;
+        }
+        watch.arrival = Some(fade.destination);
+        for (cam, proj, mut transform) in &mut cameras {
+            camera::snap_camera_to(pos, proj, &mut transform, cam);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+    use crate::tween::run_tweens;
+
+    const PORTAL_A: TilePos = TilePos { x: 1, y: 0 };
+    const PORTAL_B: TilePos = TilePos { x: 3, y: 3 };
+
+    fn map_frame() -> MapFrame {
+        MapFrame {
+            transform: Transform::IDENTITY,
+            grid_size: TilemapGridSize { x: 10., y: 10. },
+            map_type: TilemapType::Square,
+            map_size: TilemapSize { x: 4, y: 4 },
+        }
+    }
+
+    fn portal(tile: TilePos, link: &str) -> Portal {
+        Portal {
+            tile,
+            link: Some(link.to_string()),
+            destination: None,
+            target: None,
+        }
+    }
+
+    fn test_app() -> (App, Entity) {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                100,
+            )))
+            .insert_resource(map_frame())
+            .init_resource::<PortalWatch>()
+            .add_event::<TweenFinished>()
+            .add_event::<LoadMap>()
+            .add_systems(
+                Update,
+                (enter_portals, apply_deferred, finish_fades, run_tweens).chain(),
+            );
+        let mut portals = [portal(PORTAL_A, "cave"), portal(PORTAL_B, "cave")];
+        link_portals(&mut portals);
+        app.world.spawn_batch(portals);
+        app.world.spawn((
+            MainCamera,
+            PanCam::default(),
+            OrthographicProjection::default(),
+            Transform::default(),
+        ));
+        let player = app
+            .world
+            .spawn((MainPlayer, Transform::from_xyz(5., 5., 2.)))
+            .id();
+        // the first update has no time delta
+        app.update();
+        (app, player)
+    }
+
+    fn step_onto(app: &mut App, player: Entity, tile: TilePos) {
+        let pos = coords::tile_to_world_center(&map_frame(), &tile).unwrap();
+        let mut transform = app.world.get_mut::<Transform>(player).unwrap();
+        transform.translation = pos.extend(2.);
+        app.update();
+    }
+
+    fn player_tile(app: &App, player: Entity) -> Option<TilePos> {
+        let pos = app.world.get::<Transform>(player).unwrap().translation;
+        coords::world_to_tile(&map_frame(), pos.truncate())
+    }
+
+    fn fading(app: &mut App) -> bool {
+        let mut fades = app.world.query::<&PortalFade>();
+        fades.iter(&app.world).next().is_some()
+    }
+
+    /// Run the frames of a whole trip, fading out and back in
+    fn finish_trip(app: &mut App) {
+        for _ in 0..10 {
+            app.update();
+        }
+        assert!(!fading(app));
+    }
+
+    #[test]
+    fn links_pair_up_portals() {
+        let mut portals = [
+            portal(PORTAL_A, "cave"),
+            portal(TilePos::new(0, 3), "lonely"),
+            portal(PORTAL_B, "cave"),
+            portal(TilePos::new(2, 2), "crowded"),
+            portal(TilePos::new(2, 3), "crowded"),
+            portal(TilePos::new(3, 2), "crowded"),
+        ];
+        link_portals(&mut portals);
+
+        let destinations = portals.map(|portal| portal.destination);
+        assert_eq!(
+            destinations,
+            [Some(PORTAL_B), None, Some(PORTAL_A), None, None, None]
+        );
+    }
+
+    #[test]
+    fn arriving_doesnt_bounce_back_until_the_tile_is_left() {
+        let (mut app, player) = test_app();
+
+        step_onto(&mut app, player, PORTAL_A);
+        assert!(fading(&mut app));
+        finish_trip(&mut app);
+        assert_eq!(player_tile(&app, player), Some(PORTAL_B));
+        let center = coords::tile_to_world_center(&map_frame(), &PORTAL_B).unwrap();
+        let mut cameras = app.world.query_filtered::<&Transform, With<MainCamera>>();
+        assert_eq!(cameras.single(&app.world).translation.truncate(), center);
+
+        // standing on the arrival portal does nothing
+        for _ in 0..5 {
+            app.update();
+        }
+        assert!(!fading(&mut app));
+        assert_eq!(player_tile(&app, player), Some(PORTAL_B));
+
+        // leaving it and coming back does
+        step_onto(&mut app, player, TilePos::new(3, 2));
+        step_onto(&mut app, player, PORTAL_B);
+        assert!(fading(&mut app));
+        finish_trip(&mut app);
+        assert_eq!(player_tile(&app, player), Some(PORTAL_A));
+    }
+}
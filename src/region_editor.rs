@@ -0,0 +1,709 @@
+//! Editor tools for the trigger zones, named regions and camera points of interest of the map.
+//!
+//! With "Edit regions" checked in the editor window, dragging over the map draws a rectangle
+//! snapped to the tiles, and a form then names it and picks its kind: a `trigger` with its
+//! actions, a `region` whose name is drawn over the map, or a `camera_poi`. A click selects the
+//! smallest region under the cursor, dragging the handles on the edges of the selected one
+//! resizes it and Delete removes it. The handles keep their size on screen whatever the zoom.
+//!
+//! Every change is an `EditRegion`, so it goes through the editor's undo history, and the export
+//! writes the regions back to the map file. A deleted region is only marked `deleted` and hidden,
+//! so undoing brings the same entity back. Camera points of interest have no behaviour in the game
+//! yet, they are only edited and exported.
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use bevy_inspector_egui::bevy_egui::EguiContext;
+use bevy_window::PrimaryWindow;
+
+use crate::camera::{CameraMode, CurrentCameraView, EguiWantsFocus, MainCamera};
+use crate::coords::{self, MapFrame};
+use crate::cursor::CursorWorldCache;
+use crate::editor::{apply_edits, editing, editing_regions, EditRegion, EditorState};
+use crate::game::{GameSet, LevelLifecycleSet};
+use crate::helpers;
+use crate::input_map::MouseInputMap;
+use crate::map::GameInfoAlt;
+use crate::palette::{OverlayPalette, OverlayRole};
+use crate::regions::{region_label, LabelSettings, RegionLabel, REGIONS_LAYER_NAME};
+use crate::state::{level_ready, AppState};
+use crate::triggers::{parse_actions, TriggerActions, TriggerZone};
+use crate::ui_scale::UiScaling;
+
+/// Key that deletes the selected region
+pub const DELETE_REGION_KEY: KeyCode = KeyCode::Delete;
+/// Half the size of the resize handles, in screen pixels
+const HANDLE_RADIUS_PX: f32 = 6.;
+const REGION_ALPHA: f32 = 0.6;
+
+/// Plugin that draws, resizes and deletes the regions of the map in editor mode
+#[derive(Default)]
+pub struct RegionEditorPlugin;
+
+impl Plugin for RegionEditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RegionTool>()
+            .add_systems(
+                OnEnter(AppState::Level),
+                spawn_camera_pois.in_set(LevelLifecycleSet),
+            )
+            .add_systems(
+                OnExit(AppState::Level),
+                despawn_regions.in_set(LevelLifecycleSet),
+            )
+            .add_systems(
+                Update,
+                region_pointer
+                    .in_set(GameSet::Cursor)
+                    .run_if(level_ready)
+                    .run_if(editing_regions),
+            )
+            .add_systems(
+                Update,
+                sync_regions
+                    .after(apply_edits)
+                    .in_set(GameSet::Gameplay)
+                    .run_if(level_ready),
+            )
+            .add_systems(
+                Update,
+                (draw_regions, region_ui)
+                    .in_set(GameSet::RenderPrep)
+                    .run_if(level_ready)
+                    .run_if(editing),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    Trigger,
+    Region,
+    CameraPoi,
+}
+
+impl RegionKind {
+    pub const ALL: [Self; 3] = [Self::Trigger, Self::Region, Self::CameraPoi];
+
+    /// The Tiled object type of the regions of this kind
+    pub fn object_type(self) -> &'static str {
+        match self {
+            Self::Trigger => "trigger",
+            Self::Region => "region",
+            Self::CameraPoi => "camera_poi",
+        }
+    }
+
+    /// The Tiled object layer new regions of this kind are added to
+    pub fn layer_name(self) -> &'static str {
+        match self {
+            Self::Trigger => "triggers",
+            Self::Region => REGIONS_LAYER_NAME,
+            Self::CameraPoi => "camera_pois",
+        }
+    }
+}
+
+/// A rectangle of the map edited by the region tools, loaded from a Tiled object or drawn
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct MapRegion {
+    pub name: String,
+    pub kind: RegionKind,
+    /// Area covered in world coordinates
+    pub rect: Rect,
+    /// Id of the Tiled object it was loaded from, `None` when drawn in the editor
+    pub object_id: Option<u32>,
+    /// The `actions` script of a trigger
+    pub actions: String,
+    /// Deleted in the editor, kept so the deletion can be undone
+    pub deleted: bool,
+}
+
+/// An edge of a region, grabbed by its handle to resize it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Left,
+    Right,
+    Bottom,
+    Top,
+}
+
+impl Edge {
+    const ALL: [Self; 4] = [Self::Left, Self::Right, Self::Bottom, Self::Top];
+
+    /// The middle of this edge of `rect`, where its handle is
+    fn handle_pos(self, rect: Rect) -> Vec2 {
+        let center = rect.center();
+        match self {
+            Self::Left => Vec2::new(rect.min.x, center.y),
+            Self::Right => Vec2::new(rect.max.x, center.y),
+            Self::Bottom => Vec2::new(center.x, rect.min.y),
+            Self::Top => Vec2::new(center.x, rect.max.y),
+        }
+    }
+}
+
+/// The edge of `rect` whose handle is under `cursor` at camera `scale`, the nearest one when
+/// several are
+pub fn handle_at(rect: Rect, cursor: Vec2, scale: f32) -> Option<Edge> {
+    let reach = HANDLE_RADIUS_PX * scale;
+    Edge::ALL
+        .into_iter()
+        .map(|edge| (edge, (cursor - edge.handle_pos(rect)).abs()))
+        .filter(|(_, offset)| offset.max_element() <= reach)
+        .min_by(|(_, a), (_, b)| a.length_squared().total_cmp(&b.length_squared()))
+        .map(|(edge, _)| edge)
+}
+
+/// The world rectangle of `tile`
+fn tile_rect(map_frame: &MapFrame, tile: &TilePos) -> Option<Rect> {
+    let size = Vec2::new(map_frame.grid_size.x, map_frame.grid_size.y);
+    coords::tile_to_world_center(map_frame, tile).map(|center| Rect::from_center_size(center, size))
+}
+
+/// `rect` with `edge` dragged to the tile `cursor_tile`, keeping at least one tile
+pub fn resize(rect: Rect, edge: Edge, cursor_tile: Rect) -> Rect {
+    let tile = cursor_tile.size();
+    let mut resized = rect;
+    match edge {
+        Edge::Left => resized.min.x = cursor_tile.min.x.min(rect.max.x - tile.x),
+        Edge::Right => resized.max.x = cursor_tile.max.x.max(rect.min.x + tile.x),
+        Edge::Bottom => resized.min.y = cursor_tile.min.y.min(rect.max.y - tile.y),
+        Edge::Top => resized.max.y = cursor_tile.max.y.max(rect.min.y + tile.y),
+    }
+    resized
+}
+
+#[derive(Debug, Clone, Copy)]
+enum DragKind {
+    /// Drawing a new region from the tile `from`, pressed at `pressed_at`
+    Draw { from: Rect, pressed_at: Vec2 },
+    Resize {
+        entity: Entity,
+        edge: Edge,
+        original: Rect,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RegionDrag {
+    kind: DragKind,
+    /// The rectangle the region would have if dropped now
+    rect: Rect,
+    /// The camera mode to go back to, the camera is frozen while dragging
+    camera_mode: CameraMode,
+}
+
+/// The form naming a drawn region before it's created
+#[derive(Debug, Clone)]
+struct RegionForm {
+    rect: Rect,
+    name: String,
+    kind: RegionKind,
+    actions: String,
+    error: Option<String>,
+}
+
+#[derive(Resource, Debug, Default)]
+struct RegionTool {
+    selected: Option<Entity>,
+    drag: Option<RegionDrag>,
+    form: Option<RegionForm>,
+}
+
+fn spawn_camera_pois(
+    mut commands: Commands,
+    game_info: Res<GameInfoAlt>,
+    tile_maps: Res<Assets<helpers::tiled::TiledMap>>,
+    map_frame: Res<MapFrame>,
+) {
+    let Some(map) = tile_maps.get(&game_info.tile_map) else {
+        return;
+    };
+    let object_layers =
+        helpers::tiled::all_layers(&map.map).filter_map(|layer| match layer.layer_type() {
+            tiled::LayerType::Objects(layer) => Some(layer),
+            _ => None,
+        });
+    for layer in object_layers {
+        for object in layer.objects() {
+            let kind = RegionKind::CameraPoi;
+            if !object.user_type.eq_ignore_ascii_case(kind.object_type()) {
+                continue;
+            }
+            let Some(rect) = coords::object_rect(&map_frame, &object) else {
+                warn!(
+                    "camera point {} is not a rectangle inside the map",
+                    object.name
+                );
+                continue;
+            };
+            commands.spawn((
+                MapRegion {
+                    name: object.name.clone(),
+                    kind,
+                    rect,
+                    object_id: Some(object.id()),
+                    actions: String::new(),
+                    deleted: false,
+                },
+                Name::new(format!("camera point {}", object.name)),
+            ));
+        }
+    }
+}
+
+/// Despawn the regions the triggers and region labels don't despawn with their own entities
+#[allow(clippy::type_complexity)]
+fn despawn_regions(
+    mut commands: Commands,
+    mut tool: ResMut<RegionTool>,
+    regions: Query<Entity, (With<MapRegion>, Without<TriggerZone>, Without<RegionLabel>)>,
+) {
+    for entity in &regions {
+        commands.entity(entity).despawn_recursive();
+    }
+    *tool = RegionTool::default();
+}
+
+#[allow(clippy::too_many_arguments)]
+fn region_pointer(
+    buttons: Res<Input<MouseButton>>,
+    mouse_map: Res<MouseInputMap>,
+    egui_focus: Res<EguiWantsFocus>,
+    cursor_cache: Res<CursorWorldCache>,
+    cameras: Query<Entity, With<MainCamera>>,
+    view: Res<CurrentCameraView>,
+    map_frame: Res<MapFrame>,
+    mut camera_mode: ResMut<CameraMode>,
+    mut tool: ResMut<RegionTool>,
+    regions: Query<(Entity, &MapRegion)>,
+    mut edits: EventWriter<EditRegion>,
+) {
+    let cursor = cameras
+        .get_single()
+        .ok()
+        .and_then(|camera| cursor_cache.get(camera))
+        .map(|cursor| cursor.world_pos);
+    let cursor_tile = cursor
+        .and_then(|pos| coords::world_to_tile(&map_frame, pos))
+        .and_then(|tile| tile_rect(&map_frame, &tile));
+
+    let Some(mut drag) = tool.drag else {
+        if tool.form.is_some() || **egui_focus || !buttons.just_pressed(mouse_map.select) {
+            return;
+        }
+        let (Some(cursor), Some(tile)) = (cursor, cursor_tile) else {
+            return;
+        };
+        let grabbed = tool
+            .selected
+            .and_then(|entity| regions.get(entity).ok())
+            .filter(|(_, region)| !region.deleted)
+            .and_then(|(entity, region)| {
+                let edge = handle_at(region.rect, cursor, view.scale)?;
+                Some((entity, edge, region.rect))
+            });
+        let (kind, rect) = match grabbed {
+            Some((entity, edge, original)) => (
+                DragKind::Resize {
+                    entity,
+                    edge,
+                    original,
+                },
+                original,
+            ),
+            None => (
+                DragKind::Draw {
+                    from: tile,
+                    pressed_at: cursor,
+                },
+                tile,
+            ),
+        };
+        tool.drag = Some(RegionDrag {
+            kind,
+            rect,
+            camera_mode: *camera_mode,
+        });
+        *camera_mode = CameraMode::Frozen;
+        return;
+    };
+
+    if let Some(tile) = cursor_tile {
+        drag.rect = match drag.kind {
+            DragKind::Draw { from, .. } => from.union(tile),
+            DragKind::Resize { edge, original, .. } => resize(original, edge, tile),
+        };
+    }
+    if buttons.pressed(mouse_map.select) {
+        tool.drag = Some(drag);
+        return;
+    }
+
+    // dropped
+    tool.drag = None;
+    *camera_mode = drag.camera_mode;
+    match drag.kind {
+        DragKind::Draw { from, pressed_at } if drag.rect == from => {
+            // a click rather than a drag: select the smallest region under it
+            tool.selected = regions
+                .iter()
+                .filter(|(_, region)| !region.deleted && region.rect.contains(pressed_at))
+                .min_by(|(_, a), (_, b)| {
+                    let area = |rect: Rect| rect.width() * rect.height();
+                    area(a.rect).total_cmp(&area(b.rect))
+                })
+                .map(|(entity, _)| entity);
+        }
+        DragKind::Draw { .. } => {
+            tool.form = Some(RegionForm {
+                rect: drag.rect,
+                name: String::new(),
+                kind: RegionKind::Trigger,
+                actions: String::new(),
+                error: None,
+            });
+        }
+        DragKind::Resize {
+            entity, original, ..
+        } => {
+            let Ok((_, region)) = regions.get(entity) else {
+                return;
+            };
+            if drag.rect != original {
+                edits.send(EditRegion {
+                    entity,
+                    region: MapRegion {
+                        rect: drag.rect,
+                        ..region.clone()
+                    },
+                });
+            }
+        }
+    }
+}
+
+/// Bring the trigger zones and region labels in line with their edited `MapRegion`
+#[allow(clippy::type_complexity)]
+fn sync_regions(
+    mut commands: Commands,
+    view: Res<CurrentCameraView>,
+    settings: Res<LabelSettings>,
+    ui_scale: Res<UiScaling>,
+    mut regions: Query<
+        (
+            Entity,
+            &MapRegion,
+            Option<&mut TriggerZone>,
+            Option<&mut Transform>,
+            Option<&mut Visibility>,
+        ),
+        Changed<MapRegion>,
+    >,
+) {
+    for (entity, region, zone, transform, visibility) in &mut regions {
+        match region.kind {
+            RegionKind::Trigger => match (zone, region.deleted) {
+                (Some(_), true) => {
+                    commands.entity(entity).remove::<TriggerZone>();
+                }
+                (Some(mut zone), false) => zone.rect = region.rect,
+                (None, false) => {
+                    commands
+                        .entity(entity)
+                        .insert(TriggerZone::new(region.rect));
+                }
+                (None, true) => {}
+            },
+            RegionKind::Region => match (transform, visibility) {
+                (Some(mut transform), Some(mut visibility)) => {
+                    let center = region.rect.center();
+                    transform.translation.x = center.x;
+                    transform.translation.y = center.y;
+                    *visibility = if region.deleted {
+                        Visibility::Hidden
+                    } else {
+                        Visibility::Inherited
+                    };
+                }
+                _ if region.deleted => {}
+                _ => {
+                    commands.entity(entity).insert(region_label(
+                        &region.name,
+                        region.rect.center(),
+                        settings.alpha(view.scale),
+                        &settings,
+                        &ui_scale,
+                    ));
+                }
+            },
+            RegionKind::CameraPoi => {}
+        }
+    }
+}
+
+fn draw_regions(
+    mut gizmos: Gizmos,
+    state: Res<EditorState>,
+    tool: Res<RegionTool>,
+    view: Res<CurrentCameraView>,
+    palette: Res<OverlayPalette>,
+    regions: Query<(Entity, &MapRegion)>,
+) {
+    for (entity, region) in &regions {
+        if region.deleted {
+            continue;
+        }
+        let selected = tool.selected == Some(entity);
+        let rect = match tool.drag {
+            Some(RegionDrag {
+                kind: DragKind::Resize {
+                    entity: resized, ..
+                },
+                rect,
+                ..
+            }) if resized == entity => rect,
+            _ => region.rect,
+        };
+        let role = if selected {
+            OverlayRole::Selected
+        } else {
+            OverlayRole::Hover
+        };
+        let color = palette.color_with_alpha(role, REGION_ALPHA);
+        gizmos.rect_2d(rect.center(), 0., rect.size(), color);
+        if selected && state.regions {
+            let handle_size = Vec2::splat(2. * HANDLE_RADIUS_PX * view.scale);
+            for edge in Edge::ALL {
+                gizmos.rect_2d(edge.handle_pos(rect), 0., handle_size, color);
+            }
+        }
+    }
+
+    let drawn = match (&tool.drag, &tool.form) {
+        (
+            Some(RegionDrag {
+                kind: DragKind::Draw { .. },
+                rect,
+                ..
+            }),
+            _,
+        ) => Some(*rect),
+        (_, Some(form)) => Some(form.rect),
+        _ => None,
+    };
+    if let Some(rect) = drawn {
+        let color = palette.color(OverlayRole::Path);
+        gizmos.rect_2d(rect.center(), 0., rect.size(), color);
+    }
+}
+
+fn region_ui(
+    mut commands: Commands,
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+    keys: Res<Input<KeyCode>>,
+    mut tool: ResMut<RegionTool>,
+    regions: Query<&MapRegion>,
+    mut edits: EventWriter<EditRegion>,
+) {
+    let Some(mut ctx) = contexts.iter_mut().next() else {
+        return;
+    };
+    let ctx = ctx.get_mut();
+    let selected = tool
+        .selected
+        .and_then(|entity| Some((entity, regions.get(entity).ok()?)))
+        .filter(|(_, region)| !region.deleted);
+    if tool.form.is_none() && selected.is_none() {
+        return;
+    }
+
+    let mut delete =
+        selected.is_some() && keys.just_pressed(DELETE_REGION_KEY) && !ctx.wants_keyboard_input();
+    let mut created = None;
+    let mut cancelled = false;
+    egui::Window::new("Region")
+        .default_pos(egui::pos2(8., 220.))
+        .resizable(false)
+        .show(ctx, |ui| {
+            if let Some(form) = &mut tool.form {
+                ui.horizontal(|ui| {
+                    ui.label("Name");
+                    ui.text_edit_singleline(&mut form.name);
+                });
+                egui::ComboBox::from_label("Type")
+                    .selected_text(form.kind.object_type())
+                    .show_ui(ui, |ui| {
+                        for kind in RegionKind::ALL {
+                            ui.selectable_value(&mut form.kind, kind, kind.object_type());
+                        }
+                    });
+                if form.kind == RegionKind::Trigger {
+                    ui.horizontal(|ui| {
+                        ui.label("Actions");
+                        ui.text_edit_singleline(&mut form.actions);
+                    });
+                }
+                if let Some(error) = &form.error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Create").clicked() {
+                        created = Some(form.clone());
+                    }
+                    cancelled = ui.button("Cancel").clicked();
+                });
+            } else if let Some((_, region)) = selected {
+                ui.label(format!("{} \"{}\"", region.kind.object_type(), region.name));
+                if region.kind == RegionKind::Trigger && !region.actions.is_empty() {
+                    ui.label(&region.actions);
+                }
+                let size = region.rect.size();
+                ui.label(format!("{} x {} px", size.x, size.y));
+                delete |= ui.button("Delete").clicked();
+            }
+        });
+
+    if cancelled {
+        tool.form = None;
+    }
+    if let Some(form) = created {
+        match create_region(&mut commands, &form) {
+            Ok((entity, region)) => {
+                edits.send(EditRegion { entity, region });
+                tool.form = None;
+                tool.selected = Some(entity);
+            }
+            Err(error) => {
+                if let Some(form) = &mut tool.form {
+                    form.error = Some(error);
+                }
+            }
+        }
+    }
+    if let (true, Some((entity, region))) = (delete, selected) {
+        edits.send(EditRegion {
+            entity,
+            region: MapRegion {
+                deleted: true,
+                ..region.clone()
+            },
+        });
+        tool.selected = None;
+    }
+}
+
+/// Spawn the region of `form` deleted, with the region undeleting it, so its creation is an edit
+/// that can be undone like the others
+fn create_region(
+    commands: &mut Commands,
+    form: &RegionForm,
+) -> Result<(Entity, MapRegion), String> {
+    let name = form.name.trim();
+    if name.is_empty() {
+        return Err("The region needs a name".to_string());
+    }
+    let actions = match form.kind {
+        RegionKind::Trigger => Some(parse_actions(&form.actions).map_err(|err| err.to_string())?),
+        _ => None,
+    };
+    let region = MapRegion {
+        name: name.to_string(),
+        kind: form.kind,
+        rect: form.rect,
+        object_id: None,
+        actions: match actions {
+            Some(_) => form.actions.trim().to_string(),
+            None => String::new(),
+        },
+        deleted: false,
+    };
+    let entity_name = match form.kind {
+        RegionKind::Trigger => name.to_string(),
+        RegionKind::Region => format!("region {name}"),
+        RegionKind::CameraPoi => format!("camera point {name}"),
+    };
+    let mut entity = commands.spawn((
+        MapRegion {
+            deleted: true,
+            ..region.clone()
+        },
+        Name::new(entity_name),
+    ));
+    if let Some(actions) = actions {
+        entity.insert(TriggerActions::new(actions));
+    }
+    Ok((entity.id(), region))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handles_keep_their_screen_size_at_any_zoom() {
+        let rect = Rect::new(0., 0., 96., 48.);
+        // 4 world units off the right handle
+        let near_right = Vec2::new(100., 24.);
+        assert_eq!(handle_at(rect, near_right, 1.), Some(Edge::Right));
+        // zoomed in 4 times, the handle covers 1.5 world units
+        assert_eq!(handle_at(rect, near_right, 0.25), None);
+        assert_eq!(
+            handle_at(rect, Vec2::new(97., 24.), 0.25),
+            Some(Edge::Right)
+        );
+        // zoomed out, the handles cover more of the world
+        assert_eq!(handle_at(rect, Vec2::new(48., 60.), 1.), None);
+        assert_eq!(handle_at(rect, Vec2::new(48., 60.), 2.), Some(Edge::Top));
+        assert_eq!(handle_at(rect, Vec2::new(48., 24.), 1.), None);
+
+        // on a small region, the nearest handle wins
+        let small = Rect::new(0., 0., 8., 8.);
+        assert_eq!(handle_at(small, Vec2::new(1., 4.), 1.), Some(Edge::Left));
+        assert_eq!(handle_at(small, Vec2::new(4., 7.), 1.), Some(Edge::Top));
+    }
+
+    #[test]
+    fn resizing_follows_the_cursor_tile_and_keeps_a_tile() {
+        let rect = Rect::new(0., 0., 48., 32.);
+        let tile_at = |x: f32, y: f32| Rect::new(x, y, x + 16., y + 16.);
+        assert_eq!(
+            resize(rect, Edge::Right, tile_at(64., 16.)),
+            Rect::new(0., 0., 80., 32.)
+        );
+        assert_eq!(
+            resize(rect, Edge::Left, tile_at(16., 0.)),
+            Rect::new(16., 0., 48., 32.)
+        );
+        assert_eq!(
+            resize(rect, Edge::Bottom, tile_at(0., -32.)),
+            Rect::new(0., -32., 48., 32.)
+        );
+        // dragged past the other edge, one tile is left
+        assert_eq!(
+            resize(rect, Edge::Top, tile_at(0., -64.)),
+            Rect::new(0., 0., 48., 16.)
+        );
+        assert_eq!(
+            resize(rect, Edge::Left, tile_at(96., 0.)),
+            Rect::new(32., 0., 48., 32.)
+        );
+    }
+
+    #[test]
+    fn drawn_rectangles_snap_to_the_tiles() {
+        let map_frame = MapFrame {
+            transform: Transform::from_xyz(8., 8., 0.),
+            grid_size: TilemapGridSize { x: 16., y: 16. },
+            map_type: TilemapType::Square,
+            map_size: TilemapSize { x: 8, y: 8 },
+        };
+        let from = tile_rect(&map_frame, &TilePos::new(1, 1)).unwrap();
+        let to = tile_rect(&map_frame, &TilePos::new(3, 2)).unwrap();
+        assert_eq!(from, Rect::new(16., 16., 32., 32.));
+        assert_eq!(from.union(to), Rect::new(16., 16., 64., 48.));
+        assert_eq!(to.union(from), from.union(to));
+        assert!(tile_rect(&map_frame, &TilePos::new(8, 0)).is_none());
+    }
+}
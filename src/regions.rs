@@ -0,0 +1,205 @@
+//! Names of map regions drawn over the map, from the rectangle objects of the "regions" layer.
+//!
+//! The labels fade out when the camera zooms in, so they don't clutter close-up play. Regions can
+//! also be drawn in editor mode, see `region_editor`.
+
+use bevy::prelude::*;
+
+use crate::camera::CameraViewChanged;
+use crate::coords::{self, MapFrame};
+use crate::game::{GameSet, LevelLifecycleSet};
+use crate::helpers;
+use crate::map::GameInfoAlt;
+use crate::region_editor::{MapRegion, RegionKind};
+use crate::state::{level_ready, AppState};
+use crate::ui_scale::UiScaling;
+
+/// Name of the Tiled object layer holding the region rectangles
+pub const REGIONS_LAYER_NAME: &str = "regions";
+
+/// Above the tiles, below items and creatures
+const LABEL_Z: f32 = 1.4;
+
+/// Plugin that spawns the region labels and fades them with the camera zoom
+#[derive(Default)]
+pub struct RegionLabelPlugin;
+
+impl Plugin for RegionLabelPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LabelSettings>()
+            .add_systems(
+                OnEnter(AppState::Level),
+                spawn_region_labels.in_set(LevelLifecycleSet),
+            )
+            .add_systems(
+                OnExit(AppState::Level),
+                despawn_region_labels.in_set(LevelLifecycleSet),
+            )
+            .add_systems(
+                Update,
+                fade_region_labels
+                    .in_set(GameSet::RenderPrep)
+                    .run_if(level_ready),
+            );
+    }
+}
+
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct LabelSettings {
+    pub font_size: f32,
+    pub color: Color,
+    /// Labels are fully shown at camera scales above this
+    pub fade_scale: f32,
+    /// Labels are hidden at camera scales below `fade_scale - fade_range`
+    pub fade_range: f32,
+}
+
+impl Default for LabelSettings {
+    fn default() -> Self {
+        Self {
+            font_size: 28.,
+            color: Color::rgb(1., 0.95, 0.8),
+            fade_scale: 0.75,
+            fade_range: 0.25,
+        }
+    }
+}
+
+impl LabelSettings {
+    /// Label opacity at the camera scale `scale`
+    pub fn alpha(&self, scale: f32) -> f32 {
+        if self.fade_range <= 0. {
+            return if scale >= self.fade_scale { 1. } else { 0. };
+        }
+        ((scale - (self.fade_scale - self.fade_range)) / self.fade_range).clamp(0., 1.)
+    }
+}
+
+/// Marks the text of a region label
+#[derive(Component, Debug)]
+pub struct RegionLabel;
+
+fn spawn_region_labels(
+    mut commands: Commands,
+    game_info: Res<GameInfoAlt>,
+    tile_maps: Res<Assets<helpers::tiled::TiledMap>>,
+    map_frame: Res<MapFrame>,
+    settings: Res<LabelSettings>,
+    ui_scale: Res<UiScaling>,
+) {
+    let Some(map) = tile_maps.get(&game_info.tile_map) else {
+        return;
+    };
+    let regions = helpers::tiled::all_layers(&map.map)
+        .filter(|layer| layer.name.eq_ignore_ascii_case(REGIONS_LAYER_NAME))
+        .filter_map(|layer| match layer.layer_type() {
+            tiled::LayerType::Objects(layer) => Some(layer),
+            _ => None,
+        });
+
+    for layer in regions {
+        for object in layer.objects() {
+            if !object.visible || object.name.is_empty() {
+                continue;
+            }
+            let Some(rect) = coords::object_rect(&map_frame, &object) else {
+                warn!("region {} is not a rectangle inside the map", object.name);
+                continue;
+            };
+            commands.spawn((
+                region_label(&object.name, rect.center(), 1., &settings, &ui_scale),
+                MapRegion {
+                    name: object.name.clone(),
+                    kind: RegionKind::Region,
+                    rect,
+                    object_id: Some(object.id()),
+                    actions: String::new(),
+                    deleted: false,
+                },
+                Name::new(format!("region {}", object.name)),
+            ));
+        }
+    }
+}
+
+/// The text of the label of the region `name` centered on `pos`, drawn with the opacity `alpha`
+pub fn region_label(
+    name: &str,
+    pos: Vec2,
+    alpha: f32,
+    settings: &LabelSettings,
+    ui_scale: &UiScaling,
+) -> impl Bundle {
+    (
+        Text2dBundle {
+            text: Text::from_section(
+                name.to_string(),
+                TextStyle {
+                    font_size: settings.font_size * ui_scale.scale(),
+                    color: settings.color.with_a(settings.color.a() * alpha),
+                    ..default()
+                },
+            )
+            .with_alignment(TextAlignment::Center),
+            transform: Transform::from_translation(pos.extend(LABEL_Z)),
+            ..default()
+        },
+        RegionLabel,
+    )
+}
+
+fn despawn_region_labels(mut commands: Commands, labels: Query<Entity, With<RegionLabel>>) {
+    for entity in &labels {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn fade_region_labels(
+    mut view_changed: EventReader<CameraViewChanged>,
+    settings: Res<LabelSettings>,
+    ui_scale: Res<UiScaling>,
+    mut labels: Query<&mut Text, With<RegionLabel>>,
+    mut last_scale: Local<Option<f32>>,
+) {
+    if let Some(view) = view_changed.read().last() {
+        *last_scale = Some(view.scale);
+    } else if !settings.is_changed() && !ui_scale.is_changed() {
+        return;
+    }
+    let Some(scale) = *last_scale else {
+        return;
+    };
+    let alpha = settings.alpha(scale);
+    for mut text in &mut labels {
+        for section in &mut text.sections {
+            section.style.font_size = settings.font_size * ui_scale.scale();
+            section.style.color = settings.color.with_a(settings.color.a() * alpha);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_fade_when_zooming_in() {
+        let settings = LabelSettings {
+            fade_scale: 1.,
+            fade_range: 0.5,
+            ..default()
+        };
+        assert_eq!(settings.alpha(2.), 1.);
+        assert_eq!(settings.alpha(1.), 1.);
+        assert_eq!(settings.alpha(0.75), 0.5);
+        assert_eq!(settings.alpha(0.5), 0.);
+        assert_eq!(settings.alpha(0.25), 0.);
+
+        let sharp = LabelSettings {
+            fade_range: 0.,
+            ..settings
+        };
+        assert_eq!(sharp.alpha(0.99), 0.);
+        assert_eq!(sharp.alpha(1.), 1.);
+    }
+}
@@ -0,0 +1,694 @@
+//! Snapshots of the dynamic state of the level, the layer a co-op mode sends over the network.
+//!
+//! The entities marked `Replicated` (the player, the NPCs, the items and the doors) get a `NetId`
+//! that is the same on every machine running the map: the Tiled object they come from, with the
+//! members of a group numbered in the order of their tiles, or the tile of a door. A snapshot
+//! holds the authoritative state of each of them: its tile, facing, health and animation frame.
+//! The rest is derived and left out: the z and the sprite follow the tile and the frame, the
+//! health bar the health, and whether a door is open its game flag.
+//!
+//! `apply_snapshot` brings the world to a snapshot, updating the entities found in both,
+//! despawning the ones the snapshot doesn't have and spawning the missing ones from their Tiled
+//! object, through the entity templates like the level does. Dormant creatures aren't in the
+//! world, so they aren't in its snapshots either. `SnapshotDelta` holds what changed between two
+//! snapshots, so only that has to be sent. There is no transport yet: the `snapshot` console
+//! command keeps one snapshot to compare the world with and bring it back to, sending the way back
+//! as a delta the way a remote copy would get it.
+
+use bevy::ecs::event::ManualEventReader;
+use bevy::ecs::system::RunSystemOnce;
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use bevy_ecs_tilemap::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::animation::{
+    set_sprite_index_checked, AnimationClip, AnimationFrame, SpriteIndexWarnings,
+};
+use crate::console::{ConsoleCommand, DebugConsole};
+use crate::coords::{self, MapFrame};
+use crate::doors::{self, Door};
+use crate::game::GameSet;
+use crate::health::Health;
+use crate::helpers;
+use crate::helpers::tiled::TiledObject;
+use crate::inventory::ItemIcons;
+use crate::layers::LayerZConfig;
+use crate::map::GameInfoAlt;
+use crate::movement::Facing;
+use crate::sprite_atlas::CurrentCreatureAtlas;
+use crate::state::level_ready;
+use crate::templates::EntityTemplates;
+
+/// Plugin that gives the replicated entities their `NetId` and handles the `snapshot` command
+#[derive(Default)]
+pub struct ReplicationPlugin;
+
+impl Plugin for ReplicationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<KeptSnapshot>().add_systems(
+            Update,
+            (
+                assign_net_ids.in_set(GameSet::Input),
+                snapshot_command.in_set(GameSet::Gameplay),
+            )
+                .run_if(level_ready),
+        );
+    }
+}
+
+/// The snapshot kept by the `snapshot` console command
+#[derive(Resource, Default, Debug)]
+struct KeptSnapshot(Option<WorldSnapshot>);
+
+/// Marks the entities whose state goes into the snapshots
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct Replicated;
+
+/// Id of a replicated entity, the same on every machine running the same map
+#[derive(
+    Component, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord,
+)]
+pub enum NetId {
+    /// Spawned from the Tiled object `object`, `member` numbers the creatures of a group
+    Object { object: u32, member: u32 },
+    /// The door on the tile `(x, y)`
+    Tile { x: u32, y: u32 },
+}
+
+/// The authoritative state of a replicated entity
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EntitySnapshot {
+    pub id: NetId,
+    pub tile: Option<(u32, u32)>,
+    pub facing: Option<(i32, i32)>,
+    /// Current and max hit points
+    pub health: Option<(i32, i32)>,
+    pub animation_frame: Option<i32>,
+}
+
+/// The replicated entities of a world, sorted by id
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct WorldSnapshot {
+    pub entities: Vec<EntitySnapshot>,
+}
+
+/// What changed from one snapshot to another
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SnapshotDelta {
+    /// Entities new or changed, in full
+    pub changed: Vec<EntitySnapshot>,
+    pub removed: Vec<NetId>,
+}
+
+impl SnapshotDelta {
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl WorldSnapshot {
+    pub fn get(&self, id: NetId) -> Option<&EntitySnapshot> {
+        self.entities
+            .binary_search_by_key(&id, |entity| entity.id)
+            .ok()
+            .map(|index| &self.entities[index])
+    }
+
+    /// The changes turning `base` into this snapshot
+    pub fn delta_from(&self, base: &WorldSnapshot) -> SnapshotDelta {
+        SnapshotDelta {
+            changed: self
+                .entities
+                .iter()
+                .filter(|entity| base.get(entity.id) != Some(*entity))
+                .cloned()
+                .collect(),
+            removed: base
+                .entities
+                .iter()
+                .map(|entity| entity.id)
+                .filter(|id| self.get(*id).is_none())
+                .collect(),
+        }
+    }
+
+    /// Apply the changes of `delta` to this snapshot
+    pub fn apply_delta(&mut self, delta: &SnapshotDelta) {
+        self.entities
+            .retain(|entity| !delta.removed.contains(&entity.id));
+        for changed in &delta.changed {
+            match self
+                .entities
+                .binary_search_by_key(&changed.id, |entity| entity.id)
+            {
+                Ok(index) => self.entities[index] = changed.clone(),
+                Err(index) => self.entities.insert(index, changed.clone()),
+            }
+        }
+    }
+}
+
+/// Give the new replicated entities their id
+#[allow(clippy::type_complexity)]
+fn assign_net_ids(
+    mut commands: Commands,
+    map_frame: Res<MapFrame>,
+    new: Query<
+        (
+            Entity,
+            Option<&TiledObject>,
+            Option<&Door>,
+            Option<&Transform>,
+        ),
+        (With<Replicated>, Without<NetId>),
+    >,
+    ids: Query<&NetId>,
+) {
+    if new.is_empty() {
+        return;
+    }
+    let mut taken: HashSet<NetId> = ids.iter().copied().collect();
+    let mut new: Vec<_> = new
+        .iter()
+        .map(|(entity, object, door, transform)| {
+            let tile = transform
+                .and_then(|transform| {
+                    coords::world_to_tile(&map_frame, transform.translation.truncate())
+                })
+                .map(|tile| (tile.y, tile.x));
+            (
+                object.map(|object| object.id),
+                tile,
+                door.map(|door| door.tile),
+                entity,
+            )
+        })
+        .collect();
+    // the members of a group share their object, they're numbered in the order of their tiles
+    new.sort_by_key(|(object, tile, _, _)| (*object, *tile));
+    for (object, _, door, entity) in new {
+        let id = match (object, door) {
+            (_, Some(tile)) => NetId::Tile {
+                x: tile.x,
+                y: tile.y,
+            },
+            (Some(object), None) => {
+                let mut member = 0;
+                while taken.contains(&NetId::Object { object, member }) {
+                    member += 1;
+                }
+                NetId::Object { object, member }
+            }
+            (None, None) => {
+                warn!("replicated entity {entity:?} comes from no object or door, it has no id");
+                continue;
+            }
+        };
+        taken.insert(id);
+        commands.entity(entity).insert(id);
+    }
+}
+
+/// The replicated state of `world`
+pub fn world_snapshot(world: &mut World) -> WorldSnapshot {
+    let map_frame = world.get_resource::<MapFrame>().cloned();
+    let mut replicated = world.query_filtered::<(
+        &NetId,
+        Option<&Transform>,
+        Option<&Door>,
+        Option<&Facing>,
+        Option<&Health>,
+        Option<&AnimationFrame>,
+    ), With<Replicated>>();
+    let mut entities: Vec<_> = replicated
+        .iter(world)
+        .map(|(id, transform, door, facing, health, frame)| {
+            let tile = door.map(|door| door.tile).or_else(|| {
+                let pos = transform?.translation.truncate();
+                coords::world_to_tile(map_frame.as_ref()?, pos)
+            });
+            EntitySnapshot {
+                id: *id,
+                tile: tile.map(|tile| (tile.x, tile.y)),
+                facing: facing.map(|facing| (facing.0.x, facing.0.y)),
+                health: health.map(|health| (health.current, health.max)),
+                animation_frame: frame.map(|frame| frame.0),
+            }
+        })
+        .collect();
+    entities.sort_by_key(|entity| entity.id);
+    WorldSnapshot { entities }
+}
+
+/// Bring the replicated entities of `world` to `snapshot`, spawning and despawning as needed
+pub fn apply_snapshot(world: &mut World, snapshot: &WorldSnapshot) {
+    let mut replicated = world.query_filtered::<(Entity, &NetId), With<Replicated>>();
+    let existing: HashMap<NetId, Entity> = replicated
+        .iter(world)
+        .map(|(entity, id)| (*id, entity))
+        .collect();
+    for (id, entity) in &existing {
+        if snapshot.get(*id).is_none() {
+            world.entity_mut(*entity).despawn_recursive();
+        }
+    }
+    let missing: Vec<_> = snapshot
+        .entities
+        .iter()
+        .filter(|entity| !existing.contains_key(&entity.id))
+        .cloned()
+        .collect();
+    if !missing.is_empty() {
+        if world.contains_resource::<GameInfoAlt>()
+            && world.contains_resource::<MapFrame>()
+            && world.contains_resource::<CurrentCreatureAtlas>()
+        {
+            world.run_system_once_with(missing, spawn_replicated);
+        } else {
+            warn!("no level to spawn {} replicated entities in", missing.len());
+        }
+    }
+
+    let map_frame = world.get_resource::<MapFrame>().cloned();
+    let atlas_lens: HashMap<AssetId<TextureAtlas>, usize> = world
+        .get_resource::<Assets<TextureAtlas>>()
+        .map(|atlases| {
+            atlases
+                .iter()
+                .map(|(id, atlas)| (id, atlas.len()))
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut states = world.query::<(
+        Entity,
+        &NetId,
+        Option<&mut Transform>,
+        Option<&mut Facing>,
+        Option<&mut Health>,
+        Option<(&mut AnimationFrame, &AnimationClip, &mut TextureAtlasSprite)>,
+        Option<&Handle<TextureAtlas>>,
+    )>();
+    let mut new_facings = Vec::new();
+    let mut clamped = Vec::new();
+    for (entity, id, transform, facing, health, animation, atlas) in states.iter_mut(world) {
+        let Some(state) = snapshot.get(*id) else {
+            continue;
+        };
+        if let (Some(mut transform), Some((x, y)), Some(frame)) =
+            (transform, state.tile, map_frame.as_ref())
+        {
+            // a creature already on the tile stays where it is on it, mid step or not
+            let tile = TilePos::new(x, y);
+            let current = coords::world_to_tile(frame, transform.translation.truncate());
+            if current != Some(tile) {
+                if let Some(pos) = coords::tile_to_world_center(frame, &tile) {
+                    transform.translation = pos.extend(transform.translation.z);
+                }
+            }
+        }
+        if let Some((x, y)) = state.facing {
+            match facing {
+                Some(mut facing) => {
+                    facing.set_if_neq(Facing(IVec2::new(x, y)));
+                }
+                None => new_facings.push((entity, Facing(IVec2::new(x, y)))),
+            }
+        }
+        if let (Some(mut health), Some((current, max))) = (health, state.health) {
+            health.set_if_neq(Health { current, max });
+        }
+        if let (Some((mut frame, clip, mut sprite)), Some(index)) =
+            (animation, state.animation_frame)
+        {
+            if frame.0 != index && !clip.frames.is_empty() {
+                frame.0 = index.rem_euclid(clip.frames.len() as i32);
+                let index = clip.frames[frame.0 as usize];
+                match atlas.and_then(|atlas| atlas_lens.get(&atlas.id())) {
+                    Some(&atlas_len) => {
+                        if set_sprite_index_checked(&mut sprite, index, atlas_len) {
+                            clamped.push((entity, index, atlas_len));
+                        }
+                    }
+                    None => sprite.index = index,
+                }
+            }
+        }
+    }
+    for (entity, facing) in new_facings {
+        world.entity_mut(entity).insert(facing);
+    }
+    if let Some(mut warnings) = world.get_resource_mut::<SpriteIndexWarnings>() {
+        for (entity, index, atlas_len) in clamped {
+            warnings.warn(entity, index, atlas_len);
+        }
+    }
+}
+
+/// `snapshot` keeps a snapshot of the world, `snapshot delta` tells what changed since and
+/// `snapshot restore` brings the world back to it
+fn snapshot_command(world: &mut World, mut reader: Local<ManualEventReader<ConsoleCommand>>) {
+    let commands: Vec<_> = reader
+        .read(world.resource::<Events<ConsoleCommand>>())
+        .filter(|command| command.name == "snapshot")
+        .cloned()
+        .collect();
+    for command in commands {
+        let kept = world.resource::<KeptSnapshot>().0.clone();
+        let line = match (command.args.first().map(String::as_str), kept) {
+            (None, _) => {
+                let snapshot = world_snapshot(world);
+                let line = format!("kept a snapshot of {} entities", snapshot.entities.len());
+                world.resource_mut::<KeptSnapshot>().0 = Some(snapshot);
+                line
+            }
+            (Some("delta" | "restore"), None) => "no snapshot kept".to_string(),
+            (Some("delta"), Some(kept)) => {
+                let delta = world_snapshot(world).delta_from(&kept);
+                if delta.is_empty() {
+                    "nothing changed since the snapshot".to_string()
+                } else {
+                    format!(
+                        "{} entities changed, {} removed since the snapshot",
+                        delta.changed.len(),
+                        delta.removed.len()
+                    )
+                }
+            }
+            (Some("restore"), Some(kept)) => {
+                let mut restored = world_snapshot(world);
+                let delta = kept.delta_from(&restored);
+                restored.apply_delta(&delta);
+                apply_snapshot(world, &restored);
+                format!(
+                    "restored {} entities, {} changed and {} removed",
+                    restored.entities.len(),
+                    delta.changed.len(),
+                    delta.removed.len()
+                )
+            }
+            _ => "usage: snapshot [delta|restore]".to_string(),
+        };
+        world.resource_mut::<DebugConsole>().print(line);
+    }
+}
+
+/// Spawn the replicated entities of `missing` from their Tiled object or door tile
+#[allow(clippy::too_many_arguments)]
+fn spawn_replicated(
+    In(missing): In<Vec<EntitySnapshot>>,
+    mut commands: Commands,
+    game_info: Res<GameInfoAlt>,
+    map_frame: Res<MapFrame>,
+    tile_maps: Res<Assets<helpers::tiled::TiledMap>>,
+    creature_atlas: Res<CurrentCreatureAtlas>,
+    atlases: Res<Assets<TextureAtlas>>,
+    entity_templates: Res<Assets<EntityTemplates>>,
+    z_config: Res<LayerZConfig>,
+    mut item_icons: ResMut<ItemIcons>,
+) {
+    let Some(map) = tile_maps.get(&game_info.tile_map) else {
+        warn!("the map isn't loaded, can't spawn the replicated entities");
+        return;
+    };
+    for state in missing {
+        let tile = state.tile.map(|(x, y)| TilePos::new(x, y));
+        let spawned = match state.id {
+            NetId::Object { object, .. } => {
+                let Some(object) = helpers::tiled::object_by_id(&map.map, object) else {
+                    warn!("replicated {:?} has no object in the map", state.id);
+                    continue;
+                };
+                let user_type = object.user_type.to_ascii_lowercase();
+                match user_type.as_str() {
+                    "item" => crate::map::spawn_item(
+                        &mut commands,
+                        &creature_atlas.0,
+                        &atlases,
+                        &map_frame,
+                        &object,
+                        &mut item_icons,
+                    ),
+                    "spawn" | "npc" | "group_spawn" => {
+                        let pos = tile
+                            .and_then(|tile| coords::tile_to_world_center(&map_frame, &tile))
+                            .or_else(|| {
+                                let tile = coords::object_tile(&map_frame, &object)?;
+                                coords::tile_to_world_center(&map_frame, &tile)
+                            });
+                        pos.map(|pos| {
+                            crate::map::spawn_creature(
+                                &mut commands,
+                                &creature_atlas.0,
+                                &atlases,
+                                entity_templates.get(&game_info.templates),
+                                &object,
+                                pos.extend(z_config.creature_z(&map_frame, pos)),
+                                state.animation_frame.unwrap_or(0),
+                            )
+                        })
+                    }
+                    _ => None,
+                }
+            }
+            NetId::Tile { x, y } => {
+                let tile = TilePos::new(x, y);
+                doors::door_at(&map.map, &map_frame, tile).map(|door| {
+                    commands
+                        .spawn((door, Name::new(format!("door {x},{y}")), Replicated))
+                        .id()
+                })
+            }
+        };
+        match spawned {
+            Some(entity) => {
+                commands.entity(entity).insert(state.id);
+            }
+            None => warn!("can't spawn replicated {:?}", state.id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map_frame() -> MapFrame {
+        MapFrame {
+            transform: Transform::IDENTITY,
+            grid_size: TilemapGridSize { x: 10., y: 10. },
+            map_type: TilemapType::Square,
+            map_size: TilemapSize { x: 8, y: 8 },
+        }
+    }
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(map_frame())
+            .add_systems(Update, assign_net_ids);
+        app
+    }
+
+    fn spawn_creature(app: &mut App, object: u32, tile: TilePos) -> Entity {
+        let pos = coords::tile_to_world_center(&map_frame(), &tile).unwrap();
+        app.world
+            .spawn((
+                Replicated,
+                TiledObject { id: object },
+                Transform::from_translation(pos.extend(2.)),
+                Health::new(10),
+                AnimationFrame(0),
+                AnimationClip::new(vec![4, 5]),
+                TextureAtlasSprite::new(4),
+            ))
+            .id()
+    }
+
+    fn id(app: &App, entity: Entity) -> Option<NetId> {
+        app.world.get::<NetId>(entity).copied()
+    }
+
+    #[test]
+    fn ids_come_from_the_objects_and_door_tiles() {
+        let mut app = test_app();
+        // a group, spawned out of the order of its tiles
+        let second = spawn_creature(&mut app, 7, TilePos::new(3, 2));
+        let first = spawn_creature(&mut app, 7, TilePos::new(2, 2));
+        let player = spawn_creature(&mut app, 1, TilePos::new(0, 0));
+        let door = app
+            .world
+            .spawn((Replicated, Door::new(TilePos::new(5, 6), "key")))
+            .id();
+        app.update();
+        assert_eq!(
+            id(&app, first),
+            Some(NetId::Object {
+                object: 7,
+                member: 0
+            })
+        );
+        assert_eq!(
+            id(&app, second),
+            Some(NetId::Object {
+                object: 7,
+                member: 1
+            })
+        );
+        assert_eq!(
+            id(&app, player),
+            Some(NetId::Object {
+                object: 1,
+                member: 0
+            })
+        );
+        assert_eq!(id(&app, door), Some(NetId::Tile { x: 5, y: 6 }));
+
+        // a creature of the group coming back takes the free number
+        app.world.despawn(first);
+        let back = spawn_creature(&mut app, 7, TilePos::new(4, 4));
+        app.update();
+        assert_eq!(
+            id(&app, back),
+            Some(NetId::Object {
+                object: 7,
+                member: 0
+            })
+        );
+    }
+
+    #[test]
+    fn applying_a_snapshot_of_the_world_changes_nothing() {
+        let mut app = test_app();
+        let bat = spawn_creature(&mut app, 2, TilePos::new(1, 1));
+        spawn_creature(&mut app, 3, TilePos::new(4, 1));
+        app.world.entity_mut(bat).insert(Facing(IVec2::NEG_X));
+        app.update();
+        let snapshot = world_snapshot(&mut app.world);
+        assert_eq!(snapshot.entities.len(), 2);
+        assert_eq!(snapshot.entities[0].tile, Some((1, 1)));
+        assert_eq!(snapshot.entities[0].facing, Some((-1, 0)));
+
+        // nothing is touched, so nothing is marked changed
+        app.world.clear_trackers();
+        apply_snapshot(&mut app.world, &snapshot);
+        assert_eq!(world_snapshot(&mut app.world), snapshot);
+        let mut changed = app.world.query_filtered::<(), Or<(
+            Changed<Transform>,
+            Changed<Health>,
+            Changed<AnimationFrame>,
+            Changed<Facing>,
+        )>>();
+        assert_eq!(changed.iter(&app.world).count(), 0);
+        apply_snapshot(&mut app.world, &snapshot);
+        assert_eq!(world_snapshot(&mut app.world), snapshot);
+    }
+
+    #[test]
+    fn applying_a_snapshot_moves_updates_and_despawns() {
+        let mut app = test_app();
+        let bat = spawn_creature(&mut app, 2, TilePos::new(1, 1));
+        let slime = spawn_creature(&mut app, 3, TilePos::new(4, 1));
+        app.update();
+        let mut snapshot = world_snapshot(&mut app.world);
+        snapshot.entities[0] = EntitySnapshot {
+            tile: Some((2, 5)),
+            facing: Some((0, 1)),
+            health: Some((3, 10)),
+            animation_frame: Some(1),
+            ..snapshot.entities[0].clone()
+        };
+        snapshot.entities.pop();
+
+        apply_snapshot(&mut app.world, &snapshot);
+        assert!(app.world.get_entity(slime).is_none());
+        let transform = app.world.get::<Transform>(bat).unwrap();
+        assert_eq!(
+            coords::world_to_tile(&map_frame(), transform.translation.truncate()),
+            Some(TilePos::new(2, 5))
+        );
+        assert_eq!(transform.translation.z, 2.);
+        assert_eq!(app.world.get::<Facing>(bat), Some(&Facing(IVec2::Y)));
+        assert_eq!(
+            app.world.get::<Health>(bat),
+            Some(&Health {
+                current: 3,
+                max: 10
+            })
+        );
+        assert_eq!(app.world.get::<TextureAtlasSprite>(bat).unwrap().index, 5);
+        assert_eq!(world_snapshot(&mut app.world), snapshot);
+    }
+
+    #[test]
+    fn restoring_the_kept_snapshot_goes_through_a_delta() {
+        let mut app = test_app();
+        app.init_resource::<KeptSnapshot>()
+            .init_resource::<DebugConsole>()
+            .add_event::<ConsoleCommand>()
+            .add_systems(Update, snapshot_command.after(assign_net_ids));
+        let bat = spawn_creature(&mut app, 2, TilePos::new(1, 1));
+        let slime = spawn_creature(&mut app, 3, TilePos::new(4, 1));
+        app.update();
+        let run = |app: &mut App, line: &str| {
+            app.world.send_event(ConsoleCommand::parse(line).unwrap());
+            app.update();
+        };
+
+        run(&mut app, "snapshot");
+        let kept = app.world.resource::<KeptSnapshot>().0.clone().unwrap();
+        app.world.get_mut::<Health>(bat).unwrap().current = 4;
+        app.world.get_mut::<Transform>(slime).unwrap().translation.x += 10.;
+        assert_ne!(world_snapshot(&mut app.world), kept);
+
+        run(&mut app, "snapshot restore");
+        assert_eq!(world_snapshot(&mut app.world), kept);
+    }
+
+    fn entity(object: u32, tile: (u32, u32), health: i32) -> EntitySnapshot {
+        EntitySnapshot {
+            id: NetId::Object { object, member: 0 },
+            tile: Some(tile),
+            facing: None,
+            health: Some((health, 10)),
+            animation_frame: Some(0),
+        }
+    }
+
+    #[test]
+    fn deltas_roundtrip() {
+        let base = WorldSnapshot {
+            entities: vec![
+                entity(1, (0, 0), 10),
+                entity(2, (3, 3), 10),
+                entity(4, (5, 1), 4),
+            ],
+        };
+        let next = WorldSnapshot {
+            entities: vec![
+                entity(1, (0, 1), 10),
+                entity(3, (2, 2), 10),
+                entity(4, (5, 1), 4),
+            ],
+        };
+        let delta = next.delta_from(&base);
+        assert_eq!(
+            delta.changed,
+            vec![entity(1, (0, 1), 10), entity(3, (2, 2), 10)]
+        );
+        assert_eq!(
+            delta.removed,
+            vec![NetId::Object {
+                object: 2,
+                member: 0
+            }]
+        );
+        let mut patched = base.clone();
+        patched.apply_delta(&delta);
+        assert_eq!(patched, next);
+
+        assert!(next.delta_from(&next).is_empty());
+        let text = ron::to_string(&delta).unwrap();
+        assert_eq!(ron::from_str::<SnapshotDelta>(&text).unwrap(), delta);
+    }
+}
@@ -0,0 +1,231 @@
+//! Starting on machines where the renderer can't: headless boxes and broken drivers.
+//!
+//! The app is built by `run` under `catch_unwind`, since the renderer panics when it finds no
+//! adapter or can't make the window's surface. When it does, the game starts again in a new
+//! process with `SAFE_MODE_FLAG` and the conservative `StartupSettings::safe`, one step down
+//! `StartupSettings::degraded`: a new process because winit can't make a second event loop in
+//! this one. When safe mode fails too, it prints what it tried, the errors and the adapters it
+//! can see, and exits with a failure code instead of a panic.
+
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::process::{Command, ExitCode};
+
+use bevy::prelude::*;
+use bevy::render::settings::{Backends, RenderCreation, WgpuSettings};
+use bevy::render::RenderPlugin;
+use bevy::window::{PresentMode, WindowMode, WindowResolution};
+
+/// Command line flag starting with the `StartupSettings::safe` settings
+pub const SAFE_MODE_FLAG: &str = "--safe-mode";
+/// Variable the error of the first attempt is handed to the safe mode process in
+const FIRST_ERROR_VAR: &str = "BEVY_TEST_STARTUP_ERROR";
+/// Size of the window in safe mode
+const SAFE_WINDOW_SIZE: Vec2 = Vec2::new(1280., 720.);
+
+/// How the window and the renderer are set up
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StartupSettings {
+    pub safe_mode: bool,
+    /// Backends the renderer may pick from, wgpu's own choice when `None`
+    pub backends: Option<Backends>,
+    pub present_mode: PresentMode,
+    /// Size of the window, forced windowed, the default window when `None`
+    pub window_size: Option<Vec2>,
+}
+
+impl StartupSettings {
+    /// The settings least likely to fail: GL rather than Vulkan, no vsync and a small window
+    pub fn safe() -> Self {
+        Self {
+            safe_mode: true,
+            backends: Some(Backends::GL),
+            present_mode: PresentMode::AutoNoVsync,
+            window_size: Some(SAFE_WINDOW_SIZE),
+        }
+    }
+
+    /// The settings asked for on the command line
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Self {
+        if args.any(|arg| arg == SAFE_MODE_FLAG) {
+            Self::safe()
+        } else {
+            Self::default()
+        }
+    }
+
+    /// The settings to retry with when these fail, `None` once there's nothing left to drop
+    pub fn degraded(&self) -> Option<Self> {
+        (!self.safe_mode).then(Self::safe)
+    }
+
+    pub fn window(&self) -> Window {
+        let mut window = Window {
+            present_mode: self.present_mode,
+            ..default()
+        };
+        if let Some(size) = self.window_size {
+            window.mode = WindowMode::Windowed;
+            window.resolution = WindowResolution::new(size.x, size.y);
+        }
+        window
+    }
+
+    pub fn render_plugin(&self) -> RenderPlugin {
+        let mut wgpu_settings = WgpuSettings::default();
+        if let Some(backends) = self.backends {
+            wgpu_settings.backends = Some(backends);
+        }
+        RenderPlugin {
+            render_creation: RenderCreation::Automatic(wgpu_settings),
+        }
+    }
+
+    /// What these settings are, for the diagnostic
+    fn describe(&self) -> String {
+        let backends = self.backends.map_or_else(
+            || "any backend".to_string(),
+            |backends| format!("{backends:?}"),
+        );
+        let window = self.window_size.map_or_else(
+            || "default window".to_string(),
+            |size| format!("{}x{} windowed", size.x, size.y),
+        );
+        let mode = if self.safe_mode {
+            "safe mode"
+        } else {
+            "default"
+        };
+        format!("{mode} ({backends}, {:?}, {window})", self.present_mode)
+    }
+}
+
+/// Build the app with `build` and run it, falling back on safe mode when it can't be built
+pub fn run(settings: StartupSettings, build: fn(&StartupSettings) -> App) -> ExitCode {
+    // the panic is reported below, not as a raw panic message
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let built = panic::catch_unwind(AssertUnwindSafe(|| build(&settings)));
+    panic::set_hook(hook);
+
+    let error = match built {
+        Ok(mut app) => {
+            app.run();
+            return ExitCode::SUCCESS;
+        }
+        Err(payload) => panic_message(payload.as_ref()),
+    };
+    let mut attempts = Vec::new();
+    if let Ok(first) = std::env::var(FIRST_ERROR_VAR) {
+        attempts.push((StartupSettings::default(), first));
+    }
+    attempts.push((settings.clone(), error.clone()));
+
+    if settings.degraded().is_some() {
+        eprintln!("can't start: {error}\nretrying with {SAFE_MODE_FLAG}");
+        if let Some(code) = restart_in_safe_mode(&error) {
+            return code;
+        }
+    }
+    eprintln!("{}", diagnostic(&attempts, &adapters()));
+    ExitCode::FAILURE
+}
+
+/// Start this executable again with `SAFE_MODE_FLAG`, its exit code once it's done
+fn restart_in_safe_mode(error: &str) -> Option<ExitCode> {
+    let exe = std::env::current_exe().ok()?;
+    let status = Command::new(exe)
+        .args(std::env::args().skip(1))
+        .arg(SAFE_MODE_FLAG)
+        .env(FIRST_ERROR_VAR, error)
+        .status()
+        .map_err(|err| eprintln!("can't restart in safe mode: {err}"))
+        .ok()?;
+    Some(match status.code() {
+        Some(0) => ExitCode::SUCCESS,
+        Some(code) => ExitCode::from(u8::try_from(code).unwrap_or(1)),
+        None => ExitCode::FAILURE,
+    })
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown error".to_string()
+    }
+}
+
+/// The graphics adapters wgpu can see on any backend
+fn adapters() -> Vec<String> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::all(),
+        ..default()
+    });
+    instance
+        .enumerate_adapters(wgpu::Backends::all())
+        .map(|adapter| {
+            let info = adapter.get_info();
+            format!(
+                "{} ({:?}, {:?}, driver {} {})",
+                info.name, info.backend, info.device_type, info.driver, info.driver_info
+            )
+        })
+        .collect()
+}
+
+/// What to tell the player when every attempt failed
+fn diagnostic(attempts: &[(StartupSettings, String)], adapters: &[String]) -> String {
+    let mut text = String::from("The game couldn't start its window and renderer.\n\nTried:\n");
+    for (settings, error) in attempts {
+        text += &format!("  - {}: {error}\n", settings.describe());
+    }
+    text += "\nGraphics adapters found:\n";
+    if adapters.is_empty() {
+        text += "  none\n";
+    }
+    for adapter in adapters {
+        text += &format!("  - {adapter}\n");
+    }
+    text += "\nUpdating the graphics drivers may help. Set WGPU_BACKEND to pick a backend.";
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_settings_degrade_once_then_give_up() {
+        let default = StartupSettings::from_args(["game".to_string()].into_iter());
+        assert_eq!(default, StartupSettings::default());
+
+        let safe = default.degraded().unwrap();
+        assert!(safe.safe_mode);
+        assert_eq!(safe.backends, Some(Backends::GL));
+        assert_eq!(safe.present_mode, PresentMode::AutoNoVsync);
+        let window = safe.window();
+        assert_eq!(window.mode, WindowMode::Windowed);
+        assert_eq!(window.resolution.width(), 1280.);
+        assert_eq!(window.resolution.height(), 720.);
+        assert_eq!(safe.degraded(), None);
+
+        let args = ["game", SAFE_MODE_FLAG].map(String::from);
+        assert_eq!(StartupSettings::from_args(args.into_iter()), safe);
+    }
+
+    #[test]
+    fn the_diagnostic_lists_the_attempts_and_adapters() {
+        let attempts = [
+            (StartupSettings::default(), "no adapter".to_string()),
+            (StartupSettings::safe(), "no surface".to_string()),
+        ];
+        let text = diagnostic(&attempts, &[]);
+        assert!(text.contains("default (any backend"));
+        assert!(text.contains("no adapter"));
+        assert!(text.contains("AutoNoVsync, 1280x720 windowed): no surface"));
+        assert!(text.contains("none"));
+    }
+}
@@ -0,0 +1,808 @@
+//! Named save slots: the saves window lists, writes, loads and deletes them.
+//!
+//! A slot is a `<name>.ron` file in `SAVE_DIR` holding a `SaveGame`, with a `<name>.png`
+//! thumbnail next to it, a downscaled screenshot taken when saving. Slots that fail to read are
+//! listed as unreadable, so they can still be deleted. Overwriting or deleting a slot asks first.
+//!
+//! Loading switches to the saved map with `LoadMap`, even when it's the current one, so the level
+//! spawns again with the saved flags. The flags, the inventory, the explored tiles and the
+//! difficulty are restored
+//! once the level is torn down, the player's tile and health once the new level has spawned.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+use bevy::render::texture::{CompressedImageFormats, ImageSampler, ImageType};
+use bevy::render::view::screenshot::ScreenshotManager;
+use bevy_ecs_tilemap::prelude::*;
+use bevy_inspector_egui::bevy_egui::{EguiContext, EguiUserTextures};
+use bevy_window::PrimaryWindow;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::coords::{self, MapFrame};
+use crate::flags::GameFlags;
+use crate::fog::{DiscoveredAreas, ExplorationMap};
+use crate::game::{GameSet, LevelLifecycleSet};
+use crate::health::Health;
+use crate::inventory::Inventory;
+use crate::localization::tr;
+use crate::map::GameInfoAlt;
+use crate::map_switch::LoadMap;
+use crate::menu_focus::MenuFocus;
+use crate::migrations::{self, MigrationError, SAVE_VERSION};
+use crate::player::MainPlayer;
+use crate::spawn_conditions::Difficulty;
+use crate::state::{level_ready, AppState};
+use crate::toast::Toasts;
+
+/// Key that opens and closes the saves window
+pub const SAVES_KEY: KeyCode = KeyCode::F5;
+/// Folder of the save slots, relative to the working directory
+const SAVE_DIR: &str = "saves";
+/// Size the thumbnails are scaled down to fit in
+const THUMBNAIL_SIZE: UVec2 = UVec2::new(160, 90);
+
+/// Plugin that keeps the playtime and runs the saves window
+#[derive(Default)]
+pub struct SaveSlotsPlugin;
+
+impl Plugin for SaveSlotsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Playtime>()
+            .init_resource::<SaveMenu>()
+            .add_event::<SlotRequest>()
+            .add_systems(Update, toggle_save_menu.in_set(GameSet::Input))
+            .add_systems(
+                Update,
+                (
+                    tick_playtime.run_if(crate::debug_step::gameplay_running),
+                    handle_slot_requests,
+                    place_loaded_player,
+                )
+                    .in_set(GameSet::Gameplay)
+                    .run_if(level_ready),
+            )
+            .add_systems(
+                Update,
+                (refresh_slots, show_save_menu)
+                    .chain()
+                    .in_set(GameSet::RenderPrep),
+            )
+            .add_systems(
+                OnExit(AppState::Level),
+                restore_loaded_state.after(LevelLifecycleSet),
+            );
+    }
+}
+
+/// Seconds played, counted while the level runs
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq)]
+pub struct Playtime(pub f32);
+
+/// What a slot shows before it's loaded
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SaveMeta {
+    /// Seconds since the Unix epoch
+    pub saved_at: u64,
+    pub map_name: String,
+    pub playtime: f32,
+}
+
+/// Everything a save slot holds
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SaveGame {
+    /// Version of the format, older saves are brought up to date by `migrations`
+    pub version: u32,
+    pub meta: SaveMeta,
+    /// Asset path of the map
+    pub map: String,
+    pub player_tile: Option<(u32, u32)>,
+    /// Current and max hit points of the player
+    pub player_health: Option<(i32, i32)>,
+    pub flags: GameFlags,
+    pub inventory: Inventory,
+    /// Explored tiles of every map visited, by map asset path
+    pub exploration: DiscoveredAreas,
+    pub difficulty: Difficulty,
+}
+
+#[derive(Debug, Error)]
+pub enum SaveError {
+    #[error("could not read or write the slot: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("unreadable save: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("can't update the save: {0}")]
+    Migration(#[from] MigrationError),
+    #[error("unreadable save: {0}")]
+    Format(ron::Error),
+    #[error("could not write the save: {0}")]
+    Serialize(#[from] ron::Error),
+}
+
+/// A slot of the save folder
+#[derive(Debug)]
+pub struct SlotInfo {
+    pub name: String,
+    /// The slot's metadata, or why it can't be read
+    pub meta: Result<SaveMeta, String>,
+    /// Egui texture of the thumbnail, with the image keeping it alive
+    thumbnail: Option<(Handle<Image>, egui::TextureId)>,
+}
+
+/// The slot name turned into a file stem, `None` when nothing is left of it
+pub fn slot_file_name(name: &str) -> Option<String> {
+    let name: String = name
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '-' | '_' | ' ') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    (!name.is_empty()).then_some(name)
+}
+
+fn slot_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{name}.ron"))
+}
+
+fn thumbnail_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{name}.png"))
+}
+
+pub fn write_slot(dir: &Path, name: &str, save: &SaveGame) -> Result<PathBuf, SaveError> {
+    std::fs::create_dir_all(dir)?;
+    let path = slot_path(dir, name);
+    let text = ron::ser::to_string_pretty(save, ron::ser::PrettyConfig::default())?;
+    std::fs::write(&path, text)?;
+    Ok(path)
+}
+
+/// Read the save of a slot, migrated to the current format
+pub fn read_slot(dir: &Path, name: &str) -> Result<SaveGame, SaveError> {
+    let text = std::fs::read_to_string(slot_path(dir, name))?;
+    let save: ron::Value = ron::from_str(&text)?;
+    migrations::migrate(save)?
+        .into_rust()
+        .map_err(SaveError::Format)
+}
+
+/// Remove the slot and its thumbnail
+pub fn delete_slot(dir: &Path, name: &str) -> Result<(), SaveError> {
+    std::fs::remove_file(slot_path(dir, name))?;
+    let thumbnail = thumbnail_path(dir, name);
+    if thumbnail.exists() {
+        std::fs::remove_file(thumbnail)?;
+    }
+    Ok(())
+}
+
+/// The names and metadata of the slots in `dir`, the newest first and the unreadable ones last
+pub fn list_slots(dir: &Path) -> Vec<(String, Result<SaveMeta, String>)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut slots: Vec<_> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "ron" {
+                return None;
+            }
+            let name = path.file_stem()?.to_string_lossy().into_owned();
+            let meta = read_slot(dir, &name)
+                .map(|save| save.meta)
+                .map_err(|err| err.to_string());
+            Some((name, meta))
+        })
+        .collect();
+    slots.sort_by(|(a_name, a), (b_name, b)| {
+        let saved_at = |meta: &Result<SaveMeta, String>| meta.as_ref().ok().map(|m| m.saved_at);
+        saved_at(b).cmp(&saved_at(a)).then(a_name.cmp(b_name))
+    });
+    slots
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+/// How long ago `then` was at `now`, both in seconds since the epoch
+pub fn age_text(now: u64, then: u64) -> String {
+    let secs = now.saturating_sub(then);
+    match secs {
+        0..=59 => tr!("age.just_now"),
+        60..=3599 => tr!("age.minutes", count = secs / 60),
+        3600..=86_399 => tr!("age.hours", count = secs / 3600),
+        _ => tr!("age.days", count = secs / 86_400),
+    }
+}
+
+fn playtime_text(playtime: f32) -> String {
+    let minutes = (playtime.max(0.) / 60.) as u32;
+    format!("{}:{:02}", minutes / 60, minutes % 60)
+}
+
+/// What the saves window asks the game to do
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub enum SlotRequest {
+    Save(String),
+    Load(String),
+    Delete(String),
+}
+
+/// A request waiting for the player to confirm it
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Confirm {
+    Overwrite(String),
+    Delete(String),
+}
+
+/// The saves window
+#[derive(Resource, Default)]
+pub struct SaveMenu {
+    pub open: bool,
+    slots: Vec<SlotInfo>,
+    /// Name typed for a new slot
+    new_name: String,
+    confirm: Option<Confirm>,
+    /// The list is read again on the next frame
+    stale: bool,
+    /// Set from the render thread once a thumbnail is written
+    thumbnail_written: Arc<AtomicBool>,
+}
+
+fn toggle_save_menu(keys: Res<Input<KeyCode>>, mut menu: ResMut<SaveMenu>) {
+    if keys.just_pressed(SAVES_KEY) {
+        menu.open = !menu.open;
+        menu.stale = menu.open;
+        menu.confirm = None;
+    }
+}
+
+fn tick_playtime(time: Res<Time>, mut playtime: ResMut<Playtime>) {
+    playtime.0 += time.delta_seconds();
+}
+
+/// Load the slot list and the thumbnails again when they changed
+fn refresh_slots(
+    mut menu: ResMut<SaveMenu>,
+    mut images: ResMut<Assets<Image>>,
+    mut textures: ResMut<EguiUserTextures>,
+) {
+    let thumbnail_written = menu.thumbnail_written.swap(false, Ordering::Relaxed);
+    if !menu.open || !(menu.stale || thumbnail_written) {
+        return;
+    }
+    menu.stale = false;
+    for slot in menu.slots.drain(..) {
+        if let Some((image, _)) = slot.thumbnail {
+            textures.remove_image(&image);
+        }
+    }
+    let dir = Path::new(SAVE_DIR);
+    menu.slots = list_slots(dir)
+        .into_iter()
+        .map(|(name, meta)| {
+            let thumbnail = std::fs::read(thumbnail_path(dir, &name))
+                .ok()
+                .and_then(|bytes| {
+                    Image::from_buffer(
+                        &bytes,
+                        ImageType::Extension("png"),
+                        CompressedImageFormats::NONE,
+                        true,
+                        ImageSampler::Default,
+                    )
+                    .ok()
+                })
+                .map(|image| {
+                    let image = images.add(image);
+                    let texture = textures.add_image(image.clone());
+                    (image, texture)
+                });
+            SlotInfo {
+                name,
+                meta,
+                thumbnail,
+            }
+        })
+        .collect();
+}
+
+fn show_save_menu(
+    mut menu: ResMut<SaveMenu>,
+    mut focus: ResMut<MenuFocus>,
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+    mut requests: EventWriter<SlotRequest>,
+) {
+    if !menu.open {
+        return;
+    }
+    let Some(mut ctx) = contexts.iter_mut().next() else {
+        return;
+    };
+
+    let menu = &mut *menu;
+    let now = now();
+    let mut open = true;
+    if focus.begin("saves") {
+        // backing out of a question goes back to the list
+        match menu.confirm {
+            Some(_) => menu.confirm = None,
+            None => open = false,
+        }
+    }
+    let mut picked = None;
+    egui::Window::new(tr!("saves.title"))
+        .id(egui::Id::new("saves"))
+        .open(&mut open)
+        .default_width(360.)
+        .show(ctx.get_mut(), |ui| {
+            if let Some(confirm) = menu.confirm.clone() {
+                let question = match &confirm {
+                    Confirm::Overwrite(name) => tr!("saves.confirm_overwrite", name = name),
+                    Confirm::Delete(name) => tr!("saves.confirm_delete", name = name),
+                };
+                ui.label(question);
+                ui.horizontal(|ui| {
+                    let yes = ui.button(tr!("saves.yes"));
+                    if focus.item(ui, "yes", &yes) || yes.clicked() {
+                        picked = Some(match confirm {
+                            Confirm::Overwrite(name) => SlotRequest::Save(name),
+                            Confirm::Delete(name) => SlotRequest::Delete(name),
+                        });
+                    }
+                    let no = ui.button(tr!("saves.no"));
+                    if focus.item(ui, "no", &no) || no.clicked() {
+                        menu.confirm = None;
+                    }
+                });
+                return;
+            }
+
+            ui.horizontal(|ui| {
+                let name_edit = ui.text_edit_singleline(&mut menu.new_name);
+                if focus.item(ui, "new_name", &name_edit) {
+                    name_edit.request_focus();
+                }
+                let name = slot_file_name(&menu.new_name);
+                let save = ui.add_enabled(name.is_some(), egui::Button::new(tr!("saves.save")));
+                if focus.item(ui, "save", &save) || save.clicked() {
+                    if let Some(name) = name {
+                        if menu.slots.iter().any(|slot| slot.name == name) {
+                            menu.confirm = Some(Confirm::Overwrite(name));
+                        } else {
+                            picked = Some(SlotRequest::Save(name));
+                        }
+                    }
+                }
+            });
+            ui.separator();
+            if menu.slots.is_empty() {
+                ui.weak(tr!("saves.empty"));
+            }
+            egui::ScrollArea::vertical()
+                .max_height(400.)
+                .show(ui, |ui| {
+                    for slot in &menu.slots {
+                        ui.horizontal(|ui| {
+                            match slot.thumbnail {
+                                Some((_, texture)) => {
+                                    ui.image((texture, egui::vec2(80., 45.)));
+                                }
+                                None => {
+                                    ui.add_space(80.);
+                                }
+                            }
+                            ui.vertical(|ui| {
+                                ui.strong(&slot.name);
+                                match &slot.meta {
+                                    Ok(meta) => {
+                                        ui.label(tr!(
+                                            "saves.summary",
+                                            map = meta.map_name,
+                                            playtime = playtime_text(meta.playtime),
+                                            age = age_text(now, meta.saved_at)
+                                        ));
+                                    }
+                                    Err(err) => {
+                                        ui.colored_label(
+                                            ui.visuals().error_fg_color,
+                                            tr!("saves.unreadable"),
+                                        )
+                                        .on_hover_text(err);
+                                    }
+                                }
+                                ui.horizontal(|ui| {
+                                    let load = ui.add_enabled(
+                                        slot.meta.is_ok(),
+                                        egui::Button::new(tr!("saves.load")),
+                                    );
+                                    let id = format!("{}/load", slot.name);
+                                    if focus.item(ui, id, &load) || load.clicked() {
+                                        picked = Some(SlotRequest::Load(slot.name.clone()));
+                                    }
+                                    if slot.meta.is_ok() {
+                                        let overwrite = ui.button(tr!("saves.overwrite"));
+                                        let id = format!("{}/overwrite", slot.name);
+                                        if focus.item(ui, id, &overwrite) || overwrite.clicked() {
+                                            menu.confirm =
+                                                Some(Confirm::Overwrite(slot.name.clone()));
+                                        }
+                                    }
+                                    let delete = ui.button(tr!("saves.delete"));
+                                    let id = format!("{}/delete", slot.name);
+                                    if focus.item(ui, id, &delete) || delete.clicked() {
+                                        menu.confirm = Some(Confirm::Delete(slot.name.clone()));
+                                    }
+                                });
+                            });
+                        });
+                    }
+                });
+        });
+    focus.end();
+    if let Some(request) = picked {
+        if matches!(request, SlotRequest::Load(_)) {
+            open = false;
+        }
+        menu.confirm = None;
+        requests.send(request);
+    }
+    menu.open = open;
+}
+
+/// The save being loaded, until the player stands on its tile again
+#[derive(Resource, Debug)]
+struct PendingLoad {
+    save: SaveGame,
+    /// Whether the level was torn down, so the next player spawned is the loaded one
+    left_level: bool,
+}
+
+/// The save of the game as it is now
+#[allow(clippy::too_many_arguments)]
+fn snapshot(
+    game_info: &GameInfoAlt,
+    map_frame: &MapFrame,
+    player: Option<(&Transform, Option<&Health>)>,
+    flags: &GameFlags,
+    inventory: &Inventory,
+    playtime: f32,
+    discovered: &DiscoveredAreas,
+    exploration: &ExplorationMap,
+    difficulty: Difficulty,
+) -> Option<SaveGame> {
+    let map = game_info.map_path()?;
+    let map_name = Path::new(&map)
+        .file_stem()
+        .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+    let player_tile = player
+        .and_then(|(transform, _)| {
+            coords::world_to_tile(map_frame, transform.translation.truncate())
+        })
+        .map(|tile| (tile.x, tile.y));
+    let player_health = player
+        .and_then(|(_, health)| health)
+        .map(|health| (health.current, health.max));
+    let exploration = discovered.with_current(&map, exploration);
+    Some(SaveGame {
+        version: SAVE_VERSION,
+        meta: SaveMeta {
+            saved_at: now(),
+            map_name,
+            playtime,
+        },
+        map,
+        player_tile,
+        player_health,
+        flags: flags.clone(),
+        inventory: inventory.clone(),
+        exploration,
+        difficulty,
+    })
+}
+
+/// Write a downscaled screenshot of the window to `path` once it's rendered
+fn capture_thumbnail(
+    screenshots: &mut ScreenshotManager,
+    window: Entity,
+    path: PathBuf,
+    written: Arc<AtomicBool>,
+) {
+    let requested = screenshots.take_screenshot(window, move |image| {
+        let thumbnail = match image.try_into_dynamic() {
+            Ok(image) => image
+                .thumbnail(THUMBNAIL_SIZE.x, THUMBNAIL_SIZE.y)
+                .to_rgba8(),
+            Err(err) => {
+                warn!("can't convert the screenshot for {path:?}: {err}");
+                return;
+            }
+        };
+        match thumbnail.save(&path) {
+            Ok(()) => written.store(true, Ordering::Relaxed),
+            Err(err) => warn!("can't write the thumbnail {path:?}: {err}"),
+        }
+    });
+    if requested.is_err() {
+        warn!("a screenshot is already being taken, the save has no thumbnail");
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_slot_requests(
+    mut commands: Commands,
+    mut requests: EventReader<SlotRequest>,
+    game_info: Res<GameInfoAlt>,
+    map_frame: Res<MapFrame>,
+    players: Query<(&Transform, Option<&Health>), With<MainPlayer>>,
+    (flags, inventory, playtime): (Res<GameFlags>, Res<Inventory>, Res<Playtime>),
+    (discovered, exploration, difficulty): (
+        Res<DiscoveredAreas>,
+        Res<ExplorationMap>,
+        Res<Difficulty>,
+    ),
+    mut screenshots: ResMut<ScreenshotManager>,
+    windows: Query<Entity, With<PrimaryWindow>>,
+    mut menu: ResMut<SaveMenu>,
+    mut load_map: EventWriter<LoadMap>,
+    mut toasts: Toasts,
+) {
+    let dir = Path::new(SAVE_DIR);
+    for request in requests.read() {
+        match request {
+            SlotRequest::Save(name) => {
+                let Some(save) = snapshot(
+                    &game_info,
+                    &map_frame,
+                    players.get_single().ok(),
+                    &flags,
+                    &inventory,
+                    playtime.0,
+                    &discovered,
+                    &exploration,
+                    *difficulty,
+                ) else {
+                    toasts.error(tr!("saves.not_from_file"));
+                    continue;
+                };
+                match write_slot(dir, name, &save) {
+                    Ok(path) => {
+                        info!("saved to {path:?}");
+                        toasts.info(tr!("saves.saved", name = name));
+                        if let Ok(window) = windows.get_single() {
+                            capture_thumbnail(
+                                &mut screenshots,
+                                window,
+                                thumbnail_path(dir, name),
+                                menu.thumbnail_written.clone(),
+                            );
+                        }
+                    }
+                    Err(err) => toasts.error(tr!("saves.save_failed", name = name, error = err)),
+                }
+            }
+            SlotRequest::Load(name) => match read_slot(dir, name) {
+                Ok(save) => {
+                    info!("loading {name}, on map {}", save.map);
+                    load_map.send(LoadMap {
+                        path: save.map.clone(),
+                    });
+                    commands.insert_resource(PendingLoad {
+                        save,
+                        left_level: false,
+                    });
+                }
+                Err(err) => toasts.error(tr!("saves.load_failed", name = name, error = err)),
+            },
+            SlotRequest::Delete(name) => match delete_slot(dir, name) {
+                Ok(()) => toasts.info(tr!("saves.deleted", name = name)),
+                Err(err) => toasts.error(tr!("saves.delete_failed", name = name, error = err)),
+            },
+        }
+        menu.stale = true;
+    }
+}
+
+/// Put back the saved flags, inventory, explored tiles and difficulty before the saved map's
+/// level spawns
+fn restore_loaded_state(
+    pending: Option<ResMut<PendingLoad>>,
+    mut flags: ResMut<GameFlags>,
+    mut inventory: ResMut<Inventory>,
+    mut playtime: ResMut<Playtime>,
+    mut discovered: ResMut<DiscoveredAreas>,
+    mut difficulty: ResMut<Difficulty>,
+) {
+    let Some(mut pending) = pending else {
+        return;
+    };
+    *flags = pending.save.flags.clone();
+    *inventory = pending.save.inventory.clone();
+    *discovered = pending.save.exploration.clone();
+    *difficulty = pending.save.difficulty;
+    playtime.0 = pending.save.meta.playtime;
+    pending.left_level = true;
+}
+
+/// Move the player of the loaded level to its saved tile, with its saved health
+fn place_loaded_player(
+    mut commands: Commands,
+    pending: Option<Res<PendingLoad>>,
+    map_frame: Res<MapFrame>,
+    mut players: Query<(&mut Transform, Option<&mut Health>), With<MainPlayer>>,
+) {
+    let Some(pending) = pending.filter(|pending| pending.left_level) else {
+        return;
+    };
+    let Ok((mut transform, health)) = players.get_single_mut() else {
+        return;
+    };
+    let save = &pending.save;
+    let pos = save
+        .player_tile
+        .and_then(|(x, y)| coords::tile_to_world_center(&map_frame, &TilePos::new(x, y)));
+    if let Some(pos) = pos {
+        transform.translation = pos.extend(transform.translation.z);
+    }
+    if let (Some(mut health), Some((current, max))) = (health, save.player_health) {
+        health.current = current;
+        health.max = max;
+    }
+    commands.remove_resource::<PendingLoad>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flags::FlagValue;
+
+    /// An empty folder of its own for a test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("bevy_test_saves_{}_{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn save(saved_at: u64) -> SaveGame {
+        let mut flags = GameFlags::default();
+        flags.set("door_open".to_string(), FlagValue::Bool(true));
+        let mut inventory = Inventory::default();
+        inventory.add("key");
+        let mut exploration = ExplorationMap::new(TilemapSize { x: 40, y: 2 });
+        exploration.update_visible(&mut [TilePos::new(0, 0), TilePos::new(35, 1)].into());
+        SaveGame {
+            version: SAVE_VERSION,
+            meta: SaveMeta {
+                saved_at,
+                map_name: "map_test_1".to_string(),
+                playtime: 754.,
+            },
+            map: "maps/TMX/map_test_1.tmx".to_string(),
+            player_tile: Some((3, 4)),
+            player_health: Some((7, 10)),
+            flags,
+            inventory,
+            exploration: DiscoveredAreas::default()
+                .with_current("maps/TMX/map_test_1.tmx", &exploration),
+            difficulty: Difficulty::Hard,
+        }
+    }
+
+    #[test]
+    fn slots_round_trip() {
+        let dir = test_dir("round_trip");
+        let saved = save(1000);
+        write_slot(&dir, "first", &saved).unwrap();
+        write_slot(&dir, "second", &save(2000)).unwrap();
+
+        let loaded = read_slot(&dir, "first").unwrap();
+        assert_eq!(loaded.map, saved.map);
+        assert_eq!(loaded.player_tile, saved.player_tile);
+        assert_eq!(loaded.player_health, saved.player_health);
+        assert_eq!(loaded.difficulty, saved.difficulty);
+        assert_eq!(loaded.flags.get_bool("door_open"), Some(true));
+        assert_eq!(loaded.inventory, saved.inventory);
+        assert_eq!(loaded.exploration, saved.exploration);
+        let names: Vec<_> = list_slots(&dir).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["second", "first"]);
+
+        std::fs::write(thumbnail_path(&dir, "first"), b"png").unwrap();
+        delete_slot(&dir, "first").unwrap();
+        assert!(!thumbnail_path(&dir, "first").exists());
+        assert_eq!(list_slots(&dir).len(), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn corrupt_slots_are_listed_as_unreadable() {
+        let dir = test_dir("corrupt");
+        write_slot(&dir, "good", &save(1000)).unwrap();
+        std::fs::write(slot_path(&dir, "broken"), "(meta: (saved_at: ").unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a save").unwrap();
+
+        let slots = list_slots(&dir);
+        assert_eq!(slots.len(), 2);
+        assert_eq!(slots[0].0, "good");
+        assert!(slots[0].1.is_ok());
+        assert_eq!(slots[1].0, "broken");
+        assert!(slots[1].1.is_err());
+        assert!(read_slot(&dir, "broken").is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn every_old_save_format_still_loads() {
+        let dir = Path::new("tests/fixtures/saves");
+        let slots = list_slots(dir);
+        let names: Vec<_> = slots.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names.len(), SAVE_VERSION as usize + 1, "{names:?}");
+        for (name, meta) in &slots {
+            assert!(meta.is_ok(), "{name}: {meta:?}");
+            let save = read_slot(dir, name).unwrap();
+            assert_eq!(save.version, SAVE_VERSION);
+            assert_eq!(save.map, "maps/TMX/map_test_1.tmx", "{name}");
+            assert_eq!(save.player_tile, Some((3, 4)), "{name}");
+            assert_eq!(save.flags.get_bool("door_open"), Some(true), "{name}");
+            assert_eq!(save.flags.get("coins"), Some(&FlagValue::Int(3)), "{name}");
+            assert_eq!(
+                save.flags.get("met"),
+                Some(&FlagValue::String("Bob".to_string())),
+                "{name}"
+            );
+            assert!(save.inventory.contains("key"), "{name}");
+            // the explored tiles came with version 2
+            assert_eq!(
+                save.exploration.0.is_empty(),
+                matches!(name.as_str(), "v0" | "v1"),
+                "{name}"
+            );
+            // and the difficulty with version 3
+            let difficulty = match name.as_str() {
+                "v0" | "v1" | "v2" => Difficulty::Normal,
+                _ => Difficulty::Hard,
+            };
+            assert_eq!(save.difficulty, difficulty, "{name}");
+        }
+    }
+
+    #[test]
+    fn saves_from_newer_versions_are_refused() {
+        let dir = test_dir("newer");
+        let mut newer = save(1000);
+        newer.version = SAVE_VERSION + 1;
+        write_slot(&dir, "future", &newer).unwrap();
+        let err = read_slot(&dir, "future").unwrap_err();
+        assert!(matches!(
+            err,
+            SaveError::Migration(MigrationError::TooNew { .. })
+        ));
+        assert!(err.to_string().contains("newer version of the game"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn slot_names_and_ages() {
+        assert_eq!(
+            slot_file_name("  before boss "),
+            Some("before boss".to_string())
+        );
+        assert_eq!(slot_file_name("../up"), Some("___up".to_string()));
+        assert_eq!(slot_file_name("   "), None);
+        assert_eq!(age_text(1000, 990), "just now");
+        assert_eq!(age_text(10_000, 10_000 - 7200), "2 h ago");
+        assert_eq!(playtime_text(754.), "0:12");
+    }
+}
@@ -0,0 +1,301 @@
+//! Cycling the selection through the creatures near the player with Tab and Shift+Tab.
+//!
+//! The creatures within `CYCLE_RADIUS` tiles of the player, or of the camera center when there
+//! is no player, are taken nearest first, ties broken by entity so the order holds still while
+//! nothing moves. The picked creature is the `SelectedEntity` and the selected tile follows it;
+//! when it's out of view the camera eases over just far enough to show it. Selecting another tile
+//! with the mouse drops it, and so does the creature despawning, before its id can be reused.
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::camera::MainCamera;
+use crate::camera_rig::{CameraKeyframe, MoveCamera};
+use crate::console::console_closed;
+use crate::coords::{self, MapFrame};
+use crate::game::GameSet;
+use crate::map::Npc;
+use crate::menu_focus::menu_closed;
+use crate::player::MainPlayer;
+use crate::state::level_ready;
+use crate::tween::Ease;
+use crate::ui::{self, SelectedTile};
+
+/// Key that selects the next creature, or the previous one with Shift held
+pub const CYCLE_KEY: KeyCode = KeyCode::Tab;
+/// How far from the player the creatures are cycled through, in tiles
+const CYCLE_RADIUS: f32 = 10.;
+/// Seconds the camera takes to bring the selection into view
+const PAN_SECS: f32 = 0.3;
+
+/// Plugin that cycles the `SelectedEntity` and keeps the selected tile on it
+#[derive(Default)]
+pub struct SelectionCyclePlugin;
+
+impl Plugin for SelectionCyclePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SelectedEntity>()
+            .add_systems(
+                Update,
+                cycle_selection
+                    .in_set(GameSet::Input)
+                    .run_if(level_ready)
+                    .run_if(console_closed)
+                    .run_if(menu_closed),
+            )
+            .add_systems(
+                Update,
+                // clicks select their tile in `ui::select_tile`
+                follow_selected_entity
+                    .in_set(GameSet::Camera)
+                    .after(ui::select_tile)
+                    .run_if(level_ready),
+            );
+    }
+}
+
+/// The creature picked with Tab, if any
+#[derive(Resource, Default, Debug, PartialEq, Eq)]
+pub struct SelectedEntity(pub Option<Entity>);
+
+/// The `candidates` within `radius` of `origin`, nearest first and by entity when as near
+pub fn cycle_order(
+    origin: Vec2,
+    radius: f32,
+    candidates: impl IntoIterator<Item = (Entity, Vec2)>,
+) -> Vec<Entity> {
+    let mut near: Vec<(f32, Entity)> = candidates
+        .into_iter()
+        .map(|(entity, pos)| (pos.distance_squared(origin), entity))
+        .filter(|(distance, _)| *distance <= radius * radius)
+        .collect();
+    near.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+    near.into_iter().map(|(_, entity)| entity).collect()
+}
+
+/// The entity `step` places after `current` in `order`, wrapping around. The first one going
+/// forward, or the last one going back, when `current` isn't in the order.
+pub fn cycle_step(order: &[Entity], current: Option<Entity>, step: i32) -> Option<Entity> {
+    if order.is_empty() {
+        return None;
+    }
+    let len = order.len() as i32;
+    let index = match current.and_then(|current| order.iter().position(|e| *e == current)) {
+        Some(index) => (index as i32 + step).rem_euclid(len),
+        None if step >= 0 => 0,
+        None => len - 1,
+    };
+    Some(order[index as usize])
+}
+
+/// How far to move `view` so `pos` is at least `margin` inside it, zero when it is already
+pub fn pan_to_show(view: Rect, pos: Vec2, margin: f32) -> Vec2 {
+    let mut shift = Vec2::ZERO;
+    for axis in 0..2 {
+        let min = view.min[axis] + margin;
+        let max = view.max[axis] - margin;
+        shift[axis] = if min > max {
+            pos[axis] - view.center()[axis]
+        } else if pos[axis] < min {
+            pos[axis] - min
+        } else if pos[axis] > max {
+            pos[axis] - max
+        } else {
+            0.
+        };
+    }
+    shift
+}
+
+fn cycle_selection(
+    keys: Res<Input<KeyCode>>,
+    mut selected: ResMut<SelectedEntity>,
+    map_frame: Res<MapFrame>,
+    players: Query<&Transform, With<MainPlayer>>,
+    creatures: Query<(Entity, &Transform), With<Npc>>,
+    cameras: Query<(&Transform, &OrthographicProjection), With<MainCamera>>,
+    mut camera_moves: EventWriter<MoveCamera>,
+) {
+    if !keys.just_pressed(CYCLE_KEY) {
+        return;
+    }
+    let step = if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        -1
+    } else {
+        1
+    };
+    let camera = cameras.get_single().ok();
+    let origin = players
+        .get_single()
+        .ok()
+        .or(camera.map(|(transform, _)| transform))
+        .map(|transform| transform.translation.truncate());
+    let Some(origin) = origin else {
+        return;
+    };
+    let tile_size = map_frame.grid_size.x.max(map_frame.grid_size.y);
+    let order = cycle_order(
+        origin,
+        CYCLE_RADIUS * tile_size,
+        creatures
+            .iter()
+            .map(|(entity, transform)| (entity, transform.translation.truncate())),
+    );
+    selected.0 = cycle_step(&order, selected.0, step);
+
+    let target = selected.0.and_then(|entity| creatures.get(entity).ok());
+    let (Some((_, target)), Some((transform, projection))) = (target, camera) else {
+        return;
+    };
+    let center = transform.translation.truncate();
+    let view = Rect::from_corners(center + projection.area.min, center + projection.area.max);
+    let shift = pan_to_show(view, target.translation.truncate(), tile_size);
+    if shift != Vec2::ZERO {
+        camera_moves.send(MoveCamera {
+            keyframe: CameraKeyframe {
+                target_pos: center + shift,
+                target_scale: projection.scale,
+                duration: PAN_SECS,
+                easing: Ease::QuadOut,
+            },
+            skippable: true,
+        });
+    }
+}
+
+/// Keep the selected tile under the `SelectedEntity`, and drop it when it's gone or another tile
+/// was selected
+fn follow_selected_entity(
+    mut selected: ResMut<SelectedEntity>,
+    mut selected_tile: ResMut<SelectedTile>,
+    map_frame: Res<MapFrame>,
+    creatures: Query<&Transform, With<Npc>>,
+    // the entity followed and the tile given to it last frame
+    mut followed: Local<Option<(Entity, Option<TilePos>)>>,
+) {
+    let Some(entity) = selected.0 else {
+        *followed = None;
+        return;
+    };
+    let Ok(transform) = creatures.get(entity) else {
+        // despawned, its id may come back as another entity
+        if followed.is_some_and(|(_, tile)| tile == selected_tile.0) {
+            selected_tile.0 = None;
+        }
+        selected.0 = None;
+        *followed = None;
+        return;
+    };
+    let tile = coords::world_to_tile(&map_frame, transform.translation.truncate());
+    if let Some((followed_entity, followed_tile)) = *followed {
+        let clicked_away = selected_tile.0 != followed_tile && selected_tile.0 != tile;
+        if followed_entity == entity && clicked_away {
+            selected.0 = None;
+            *followed = None;
+            return;
+        }
+    }
+    if selected_tile.0 != tile {
+        selected_tile.0 = tile;
+    }
+    *followed = Some((entity, tile));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entities(world: &mut World, count: usize) -> Vec<Entity> {
+        (0..count).map(|_| world.spawn_empty().id()).collect()
+    }
+
+    #[test]
+    fn the_order_is_by_distance_then_entity() {
+        let mut world = World::new();
+        let e = entities(&mut world, 4);
+        let candidates = [
+            (e[0], Vec2::new(30., 0.)),
+            (e[1], Vec2::new(0., 10.)),
+            (e[2], Vec2::new(-10., 0.)),
+            (e[3], Vec2::new(500., 0.)),
+        ];
+        let order = cycle_order(Vec2::ZERO, 100., candidates);
+        assert_eq!(order, [e[1], e[2], e[0]]);
+        // the same every time while nothing moves
+        assert_eq!(cycle_order(Vec2::ZERO, 100., candidates), order);
+
+        assert_eq!(cycle_step(&order, None, 1), Some(e[1]));
+        assert_eq!(cycle_step(&order, None, -1), Some(e[0]));
+        assert_eq!(cycle_step(&order, Some(e[0]), 1), Some(e[1]));
+        assert_eq!(cycle_step(&order, Some(e[1]), -1), Some(e[0]));
+        assert_eq!(cycle_step(&order, Some(e[3]), 1), Some(e[1]));
+        assert_eq!(cycle_step(&[], Some(e[3]), 1), None);
+    }
+
+    #[test]
+    fn the_camera_only_moves_when_the_selection_is_out_of_view() {
+        let view = Rect::new(0., 0., 100., 50.);
+        assert_eq!(pan_to_show(view, Vec2::new(50., 25.), 10.), Vec2::ZERO);
+        assert_eq!(
+            pan_to_show(view, Vec2::new(130., -20.), 10.),
+            Vec2::new(40., -30.)
+        );
+    }
+
+    /// An app following the selected bat, on the tile (2, 3)
+    fn follow_app() -> (App, Entity) {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<SelectedEntity>()
+            .init_resource::<SelectedTile>()
+            .insert_resource(MapFrame {
+                transform: Transform::IDENTITY,
+                grid_size: TilemapGridSize { x: 10., y: 10. },
+                map_type: TilemapType::Square,
+                map_size: TilemapSize { x: 8, y: 8 },
+            })
+            .add_systems(Update, follow_selected_entity);
+        let bat = app
+            .world
+            .spawn((Npc, Transform::from_xyz(20., 30., 0.)))
+            .id();
+        app.world.resource_mut::<SelectedEntity>().0 = Some(bat);
+        app.update();
+        (app, bat)
+    }
+
+    #[test]
+    fn a_despawned_selection_is_dropped() {
+        let (mut app, bat) = follow_app();
+        assert_eq!(
+            app.world.resource::<SelectedTile>().0,
+            Some(TilePos::new(2, 3))
+        );
+
+        // the selected tile follows it
+        app.world.get_mut::<Transform>(bat).unwrap().translation.x = 40.;
+        app.update();
+        assert_eq!(
+            app.world.resource::<SelectedTile>().0,
+            Some(TilePos::new(4, 3))
+        );
+
+        app.world.despawn(bat);
+        app.update();
+        assert_eq!(app.world.resource::<SelectedEntity>().0, None);
+        assert_eq!(app.world.resource::<SelectedTile>().0, None);
+    }
+
+    #[test]
+    fn selecting_another_tile_drops_the_creature() {
+        let (mut app, _) = follow_app();
+
+        app.world.resource_mut::<SelectedTile>().0 = Some(TilePos::new(6, 6));
+        app.update();
+        assert_eq!(app.world.resource::<SelectedEntity>().0, None);
+        assert_eq!(
+            app.world.resource::<SelectedTile>().0,
+            Some(TilePos::new(6, 6))
+        );
+    }
+}
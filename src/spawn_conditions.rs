@@ -0,0 +1,318 @@
+//! Map objects spawned only under some conditions, and the `Difficulty` of the game.
+//!
+//! Properties read from the creature and item objects:
+//! - `requires_flag`: only spawn while the game flag is set, `!name` while it isn't
+//! - `forbids_flag`: only spawn while the game flag isn't set
+//! - `min_difficulty`: only spawn at this `Difficulty` or a harder one
+//!
+//! The conditions are checked when the level spawns. The objects whose conditions aren't met go
+//! to the `DeferredSpawns` of the level, checked again on every `FlagChanged`, so an object
+//! waiting for a flag spawns as soon as it's set. Entering a level starts over from its objects.
+//!
+//! The difficulty is picked in the settings window and kept in the saves.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::coords::MapFrame;
+use crate::flags::{FlagChanged, GameFlags};
+use crate::game::GameSet;
+use crate::helpers;
+use crate::inventory::ItemIcons;
+use crate::layers::LayerZConfig;
+use crate::map::{self, GameInfoAlt};
+use crate::sprite_atlas::CurrentCreatureAtlas;
+use crate::state::level_ready;
+use crate::templates::EntityTemplates;
+
+/// Name of the object property holding the lowest difficulty it spawns at
+pub const MIN_DIFFICULTY_PROPERTY: &str = "min_difficulty";
+
+/// Plugin that spawns the deferred objects of the level once their conditions are met
+#[derive(Default)]
+pub struct SpawnConditionsPlugin;
+
+impl Plugin for SpawnConditionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Difficulty>()
+            .register_type::<Difficulty>()
+            .init_resource::<DeferredSpawns>()
+            .add_systems(
+                Update,
+                spawn_deferred_objects
+                    .in_set(GameSet::Gameplay)
+                    .run_if(level_ready),
+            );
+    }
+}
+
+/// How hard the game is, from the easiest; saved by name, see `migrations`
+#[derive(
+    Resource,
+    Reflect,
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Default,
+)]
+#[reflect(Resource)]
+#[serde(into = "String", try_from = "String")]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+    /// Name of the difficulty in the `min_difficulty` property and in the saves
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|difficulty| difficulty.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// Localization key of the name shown in the settings
+    pub fn key(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "difficulty.easy",
+            Difficulty::Normal => "difficulty.normal",
+            Difficulty::Hard => "difficulty.hard",
+        }
+    }
+}
+
+impl From<Difficulty> for String {
+    fn from(difficulty: Difficulty) -> Self {
+        difficulty.name().to_string()
+    }
+}
+
+impl TryFrom<String> for Difficulty {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        Self::from_name(&name).ok_or_else(|| format!("unknown difficulty {name}"))
+    }
+}
+
+/// Ids of the objects of the level waiting for their conditions to spawn
+#[derive(Resource, Debug, Default, Clone, PartialEq, Eq)]
+pub struct DeferredSpawns(pub Vec<u32>);
+
+/// Whether the object with `properties` spawns with the `flags` at the `difficulty`
+pub fn conditions_met(
+    properties: &tiled::Properties,
+    flags: &GameFlags,
+    difficulty: Difficulty,
+) -> bool {
+    let min_difficulty = match properties.get(MIN_DIFFICULTY_PROPERTY) {
+        Some(tiled::PropertyValue::StringValue(name)) if !name.is_empty() => {
+            Difficulty::from_name(name).or_else(|| {
+                warn!("unknown {MIN_DIFFICULTY_PROPERTY} {name}, spawning at any difficulty");
+                None
+            })
+        }
+        _ => None,
+    };
+    flags.object_condition_met(properties)
+        && min_difficulty.is_none_or(|min_difficulty| difficulty >= min_difficulty)
+}
+
+/// Spawn the deferred objects whose conditions the flags now meet
+#[allow(clippy::too_many_arguments)]
+fn spawn_deferred_objects(
+    mut commands: Commands,
+    mut changed: EventReader<FlagChanged>,
+    flags: Res<GameFlags>,
+    difficulty: Res<Difficulty>,
+    mut deferred: ResMut<DeferredSpawns>,
+    game_info: Res<GameInfoAlt>,
+    creature_atlas: Res<CurrentCreatureAtlas>,
+    tile_maps: Res<Assets<helpers::tiled::TiledMap>>,
+    atlases: Res<Assets<TextureAtlas>>,
+    entity_templates: Res<Assets<EntityTemplates>>,
+    map_frame: Res<MapFrame>,
+    z_config: Res<LayerZConfig>,
+    mut item_icons: ResMut<ItemIcons>,
+) {
+    if changed.read().count() == 0 || deferred.0.is_empty() {
+        return;
+    }
+    let Some(map) = tile_maps.get(&game_info.tile_map) else {
+        return;
+    };
+    deferred.0.retain(|id| {
+        let Some(object) = helpers::tiled::object_by_id(&map.map, *id) else {
+            return false;
+        };
+        if !conditions_met(&object.properties, &flags, *difficulty) {
+            return true;
+        }
+        info!("spawning {}, its spawn conditions are now met", object.name);
+        map::spawn_map_object(
+            &mut commands,
+            &creature_atlas.0,
+            &atlases,
+            entity_templates.get(&game_info.templates),
+            &map_frame,
+            &z_config,
+            &mut item_icons,
+            &object,
+            None,
+        );
+        false
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flags::FlagValue;
+    use crate::helpers::tiled::TiledObject;
+    use crate::map::Npc;
+
+    const MAP_PATH: &str = "assets/maps/TMX/map_test_spawn_conditions.tmx";
+
+    /// Names of the objects of the fixture map that spawn with `flags` at `difficulty`
+    fn spawned(flags: &GameFlags, difficulty: Difficulty) -> Vec<String> {
+        let map = helpers::tiled::load_test_map(MAP_PATH).map;
+        helpers::tiled::all_layers(&map)
+            .filter_map(|layer| match layer.layer_type() {
+                tiled::LayerType::Objects(layer) => Some(layer),
+                _ => None,
+            })
+            .flat_map(|layer| layer.objects().collect::<Vec<_>>())
+            .filter(|object| conditions_met(&object.properties, flags, difficulty))
+            .map(|object| object.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn conditions_decide_what_spawns() {
+        let mut flags = GameFlags::default();
+        assert_eq!(
+            spawned(&flags, Difficulty::Normal),
+            ["hero", "villager", "sleeping guard"]
+        );
+
+        flags.set_bool("alarm", true);
+        assert_eq!(
+            spawned(&flags, Difficulty::Normal),
+            ["hero", "villager", "alarm guard", "alarm key"]
+        );
+        assert_eq!(
+            spawned(&flags, Difficulty::Hard),
+            ["hero", "villager", "alarm guard", "alarm key", "troll"]
+        );
+        assert_eq!(
+            spawned(&flags, Difficulty::Easy),
+            spawned(&flags, Difficulty::Normal)
+        );
+    }
+
+    #[test]
+    fn difficulties_by_name() {
+        assert_eq!(Difficulty::from_name(" Hard"), Some(Difficulty::Hard));
+        assert_eq!(Difficulty::from_name("nightmare"), None);
+        assert!(Difficulty::Easy < Difficulty::Normal);
+        let saved = ron::to_string(&Difficulty::Hard).unwrap();
+        assert_eq!(saved, "\"hard\"");
+        assert_eq!(
+            ron::from_str::<Difficulty>(&saved).unwrap(),
+            Difficulty::Hard
+        );
+    }
+
+    #[test]
+    fn deferred_objects_spawn_when_their_flag_is_set() {
+        let mut app = App::new();
+        let map = helpers::tiled::load_test_map(MAP_PATH);
+        let map_frame = MapFrame::from_tiled_map(&map.map, Transform::IDENTITY);
+        let mut tile_maps = Assets::<helpers::tiled::TiledMap>::default();
+        let tile_map = tile_maps.add(map);
+        let mut atlases = Assets::<TextureAtlas>::default();
+        let creature_atlas = atlases.add(TextureAtlas::from_grid(
+            Handle::default(),
+            Vec2::splat(24.),
+            20,
+            27,
+            None,
+            None,
+        ));
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(GameInfoAlt {
+                creature_atlas: creature_atlas.clone(),
+                creature_sheet: None,
+                creature_folder: None,
+                tile_map,
+                templates: Handle::default(),
+            })
+            .insert_resource(CurrentCreatureAtlas(creature_atlas))
+            .insert_resource(tile_maps)
+            .insert_resource(atlases)
+            .insert_resource(map_frame)
+            .init_resource::<Assets<EntityTemplates>>()
+            .init_resource::<LayerZConfig>()
+            .init_resource::<ItemIcons>()
+            .init_resource::<GameFlags>()
+            .init_resource::<Difficulty>()
+            // left out when the level spawned without the alarm, at normal difficulty
+            .insert_resource(DeferredSpawns(vec![4, 5, 6]))
+            .add_event::<FlagChanged>()
+            .add_systems(Update, spawn_deferred_objects);
+        let spawned_ids = |app: &mut App| {
+            let mut ids: Vec<u32> = app
+                .world
+                .query::<&TiledObject>()
+                .iter(&app.world)
+                .map(|object| object.id)
+                .collect();
+            ids.sort();
+            ids
+        };
+
+        app.update();
+        assert!(spawned_ids(&mut app).is_empty());
+
+        app.world
+            .resource_mut::<GameFlags>()
+            .set_bool("alarm", true);
+        app.world.send_event(FlagChanged {
+            key: "alarm".to_string(),
+            old: None,
+            new: FlagValue::Bool(true),
+        });
+        app.update();
+
+        // the alarm guard and its key, the troll still waits for a harder game
+        assert_eq!(spawned_ids(&mut app), [4, 5]);
+        assert_eq!(app.world.resource::<DeferredSpawns>().0, [6]);
+        let guards = app
+            .world
+            .query_filtered::<&Name, With<Npc>>()
+            .iter(&app.world)
+            .count();
+        assert_eq!(guards, 1);
+        assert_eq!(
+            app.world.resource::<ItemIcons>().0.get("alarm key"),
+            Some(&7)
+        );
+    }
+}
@@ -0,0 +1,254 @@
+//! Creatures grow or fade in when they spawn, and shrink or fade out before they are removed.
+//!
+//! Both effects are tweens of the scale or the sprite alpha only: the y-sort owns the z of the
+//! translation and the animation owns the sprite index, so neither is touched here.
+
+use bevy::prelude::*;
+
+use crate::game::GameSet;
+use crate::lifetime::Lifetime;
+use crate::map::Npc;
+use crate::player::MainPlayer;
+use crate::tween::{Ease, Tween, TweenFinished, TweenValue};
+
+/// Plugin that plays the spawn and despawn effects of the creatures
+#[derive(Default)]
+pub struct SpawnEffectPlugin;
+
+impl Plugin for SpawnEffectPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SpawnEffectSettings>()
+            .register_type::<SpawnEffectSettings>()
+            .add_event::<DespawnAfterEffect>()
+            .add_systems(
+                Update,
+                (attach_spawn_effects, start_despawn_effects)
+                    .before(crate::tween::run_tweens)
+                    .in_set(GameSet::RenderPrep),
+            )
+            .add_systems(
+                Update,
+                finish_effects
+                    .after(crate::tween::run_tweens)
+                    .in_set(GameSet::RenderPrep),
+            );
+    }
+}
+
+/// How a creature appears and disappears
+#[derive(Reflect, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EffectStyle {
+    /// Grows from nothing with a slight overshoot, and shrinks back on the way out
+    #[default]
+    Scale,
+    /// Fades the sprite in and out
+    Fade,
+}
+
+#[derive(Resource, Reflect, Debug, Clone, PartialEq)]
+#[reflect(Resource)]
+pub struct SpawnEffectSettings {
+    pub style: EffectStyle,
+    /// Seconds a spawn or despawn effect lasts
+    pub secs: f32,
+}
+
+impl Default for SpawnEffectSettings {
+    fn default() -> Self {
+        Self {
+            style: EffectStyle::Scale,
+            secs: 0.3,
+        }
+    }
+}
+
+/// The creature is playing its spawn effect
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpawnEffect(pub EffectStyle);
+
+/// The creature is playing its despawn effect, its `Lifetime` removes it when it is over
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DespawnEffect(pub EffectStyle);
+
+/// Send to despawn `entity` once its despawn effect played
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DespawnAfterEffect {
+    pub entity: Entity,
+}
+
+impl EffectStyle {
+    fn spawn_tween(&self, secs: f32) -> Tween {
+        match self {
+            EffectStyle::Scale => Tween::new(
+                TweenValue::Scale {
+                    from: Vec3::ZERO,
+                    to: Vec3::ONE,
+                },
+                Ease::BackOut,
+                secs,
+            ),
+            EffectStyle::Fade => {
+                Tween::new(TweenValue::Alpha { from: 0., to: 1. }, Ease::QuadOut, secs)
+            }
+        }
+    }
+
+    fn despawn_tween(&self, secs: f32) -> Tween {
+        match self {
+            EffectStyle::Scale => Tween::new(
+                TweenValue::Scale {
+                    from: Vec3::ONE,
+                    to: Vec3::ZERO,
+                },
+                Ease::QuadIn,
+                secs,
+            ),
+            EffectStyle::Fade => {
+                Tween::new(TweenValue::Alpha { from: 1., to: 0. }, Ease::QuadIn, secs)
+            }
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn attach_spawn_effects(
+    mut commands: Commands,
+    settings: Res<SpawnEffectSettings>,
+    mut spawned: Query<
+        (Entity, &mut Transform, Option<&mut TextureAtlasSprite>),
+        Or<(Added<MainPlayer>, Added<Npc>)>,
+    >,
+) {
+    for (entity, mut transform, mut sprite) in &mut spawned {
+        let tween = settings.style.spawn_tween(settings.secs);
+        // start from the first value right away so the creature doesn't show for a frame
+        tween.apply(
+            &mut transform,
+            sprite.as_deref_mut().map(|sprite| &mut sprite.color),
+        );
+        commands
+            .entity(entity)
+            .insert((SpawnEffect(settings.style), tween));
+    }
+}
+
+fn start_despawn_effects(
+    mut commands: Commands,
+    settings: Res<SpawnEffectSettings>,
+    mut requests: EventReader<DespawnAfterEffect>,
+    effects: Query<(), With<DespawnEffect>>,
+) {
+    for request in requests.read() {
+        let Some(mut entity) = commands.get_entity(request.entity) else {
+            continue;
+        };
+        if effects.contains(request.entity) {
+            continue;
+        }
+        // replaces a spawn effect still playing
+        entity.remove::<SpawnEffect>().insert((
+            DespawnEffect(settings.style),
+            settings.style.despawn_tween(settings.secs),
+            Lifetime::from_seconds(settings.secs),
+        ));
+    }
+}
+
+fn finish_effects(
+    mut commands: Commands,
+    mut finished: EventReader<TweenFinished>,
+    spawning: Query<(), With<SpawnEffect>>,
+) {
+    for event in finished.read() {
+        if spawning.contains(event.entity) {
+            commands.entity(event.entity).remove::<SpawnEffect>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+
+    fn test_app(style: EffectStyle) -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                100,
+            )))
+            .insert_resource(SpawnEffectSettings { style, secs: 0.3 })
+            .add_event::<TweenFinished>()
+            .add_event::<DespawnAfterEffect>()
+            .add_systems(
+                Update,
+                (
+                    (attach_spawn_effects, start_despawn_effects),
+                    apply_deferred,
+                    crate::tween::run_tweens,
+                    apply_deferred,
+                    finish_effects,
+                    crate::lifetime::expire_lifetimes,
+                )
+                    .chain(),
+            );
+        app
+    }
+
+    fn spawn_npc(app: &mut App) -> Entity {
+        app.world
+            .spawn((
+                Transform::from_xyz(5., 5., 2.5),
+                TextureAtlasSprite::new(22),
+                Npc,
+            ))
+            .id()
+    }
+
+    #[test]
+    fn creatures_grow_in_with_an_overshoot() {
+        let mut app = test_app(EffectStyle::Scale);
+        let npc = spawn_npc(&mut app);
+
+        app.update();
+        assert_eq!(app.world.get::<Transform>(npc).unwrap().scale, Vec3::ZERO);
+        assert!(app.world.get::<SpawnEffect>(npc).is_some());
+
+        let mut largest = 0f32;
+        for _ in 0..4 {
+            app.update();
+            largest = largest.max(app.world.get::<Transform>(npc).unwrap().scale.x);
+        }
+        assert!(largest > 1.);
+        let transform = app.world.get::<Transform>(npc).unwrap();
+        assert_eq!(transform.scale, Vec3::ONE);
+        assert_eq!(transform.translation, Vec3::new(5., 5., 2.5));
+        assert!(app.world.get::<SpawnEffect>(npc).is_none());
+    }
+
+    #[test]
+    fn despawn_waits_for_the_fade_out() {
+        let mut app = test_app(EffectStyle::Fade);
+        let npc = spawn_npc(&mut app);
+        for _ in 0..5 {
+            app.update();
+        }
+        let sprite = app.world.get::<TextureAtlasSprite>(npc).unwrap();
+        assert_eq!(sprite.color.a(), 1.);
+        assert_eq!(sprite.index, 22);
+
+        app.world.send_event(DespawnAfterEffect { entity: npc });
+        app.update();
+        app.update();
+        let alpha = app.world.get::<TextureAtlasSprite>(npc).unwrap().color.a();
+        assert!(0. < alpha && alpha < 1.);
+
+        for _ in 0..3 {
+            app.update();
+        }
+        assert!(app.world.get_entity(npc).is_none());
+    }
+}
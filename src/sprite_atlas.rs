@@ -0,0 +1,430 @@
+//! The creature atlas, either a pre-packed sheet or packed at load time from a folder of PNGs.
+//!
+//! `main.assets.ron` picks the mode by the key it has:
+//! - `atlas.creatures`: a `TextureAtlas` cut from one sheet
+//! - `images.creatures_folder`: a `Folder` of PNGs, packed into one atlas when the loading state
+//!   ends, with a `SpriteCatalog` to find a sprite's index by its file stem
+//!
+//! Animations by `creature_row` need the grid of a pre-packed sheet, the `atlas_index` of a
+//! sprite of a packed folder is the one the catalog lists for it.
+//!
+//! A map can use a sheet of its own instead, named by its `creature_sheet` property, see
+//! `CreatureSheet`. The creatures of the level are spawned with the `CurrentCreatureAtlas`, and
+//! the catalog and the templates are checked against it again whenever it changes. The sprites
+//! the map's creatures and items show are checked against it when the level spawns.
+
+use bevy::prelude::*;
+use bevy::sprite::TextureAtlasBuilderError;
+use bevy::utils::HashMap;
+
+use crate::animation;
+use crate::game::{GameSet, LevelLifecycleSet};
+use crate::helpers::tiled::{all_layers, TiledMap};
+use crate::localization::tr;
+use crate::map::GameInfoAlt;
+use crate::state::AppState;
+use crate::templates::{self, EntityTemplates};
+use crate::toast::Toasts;
+
+/// Pixels left between the packed sprites, so nearest filtering doesn't bleed a neighbour in
+const PADDING: u32 = 2;
+
+/// Plugin that sets `GameInfoAlt::creature_atlas` once the creature sprites are loaded, and the
+/// `CurrentCreatureAtlas` of each map
+#[derive(Default)]
+pub struct SpriteAtlasPlugin;
+
+impl Plugin for SpriteAtlasPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SpriteCatalog>()
+            .init_resource::<CurrentCreatureAtlas>()
+            .init_resource::<MapCreatureAtlases>()
+            .add_systems(
+                OnExit(AppState::Loading),
+                (build_creature_atlas, select_creature_atlas).chain(),
+            )
+            .add_systems(OnExit(AppState::SwitchingMap), select_creature_atlas)
+            .add_systems(
+                OnEnter(AppState::Level),
+                check_map_sprites.after(LevelLifecycleSet),
+            )
+            .add_systems(
+                Update,
+                check_catalog
+                    .in_set(GameSet::RenderPrep)
+                    .run_if(resource_changed::<CurrentCreatureAtlas>()),
+            );
+    }
+}
+
+/// The creature atlas of the current map, the one its creatures and items are spawned with
+#[derive(Resource, Default, Debug, Clone, PartialEq)]
+pub struct CurrentCreatureAtlas(pub Handle<TextureAtlas>);
+
+/// The atlases cut from the maps' creature sheets by image path, kept for when a map comes back
+#[derive(Resource, Default, Debug)]
+pub struct MapCreatureAtlases(HashMap<String, Handle<TextureAtlas>>);
+
+/// A creature sheet of a map's own, read from the map properties:
+/// - `creature_sheet`: path of the image in the assets folder
+/// - `creature_tile_size`: size of its square cells in pixels
+/// - `creature_columns`, `creature_rows`: how many cells it has across and down
+///
+/// The grid properties left out are those of the default creature atlas.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreatureSheet {
+    pub path: String,
+    pub tile_size: Vec2,
+    pub columns: usize,
+    pub rows: usize,
+}
+
+impl CreatureSheet {
+    /// The sheet of a map with the `properties`, `None` when it uses the `default` atlas
+    pub fn from_properties(
+        properties: &tiled::Properties,
+        default: Option<&TextureAtlas>,
+    ) -> Option<Self> {
+        let Some(tiled::PropertyValue::StringValue(path)) = properties.get("creature_sheet") else {
+            return None;
+        };
+        let int = |name| match properties.get(name) {
+            Some(tiled::PropertyValue::IntValue(value)) if *value > 0 => Some(*value as usize),
+            _ => None,
+        };
+        let default_cell = default
+            .and_then(|atlas| atlas.textures.first())
+            .map(Rect::size);
+        let default_grid = default.and_then(animation::atlas_grid);
+        let tile_size = int("creature_tile_size")
+            .map(|size| Vec2::splat(size as f32))
+            .or(default_cell);
+        let columns = int("creature_columns").or(default_grid.map(|(columns, _)| columns));
+        let rows = int("creature_rows").or(default_grid.map(|(_, rows)| rows));
+        match (tile_size, columns, rows) {
+            (Some(tile_size), Some(columns), Some(rows)) => Some(Self {
+                path: path.clone(),
+                tile_size,
+                columns,
+                rows,
+            }),
+            _ => {
+                warn!(
+                    "creature sheet {path} needs creature_tile_size, creature_columns and \
+                     creature_rows when the default atlas has no grid, using the default atlas"
+                );
+                None
+            }
+        }
+    }
+}
+
+/// Index in the creature atlas of each sprite packed from a folder, by file stem
+#[derive(Resource, Default, Debug, Clone, PartialEq)]
+pub struct SpriteCatalog(pub HashMap<String, usize>);
+
+/// Pack the `(name, image)` sprites into one atlas, with `PADDING` between them
+pub fn pack_sprites(
+    images: &mut Assets<Image>,
+    sprites: &[(String, Handle<Image>)],
+) -> Result<(TextureAtlas, SpriteCatalog), TextureAtlasBuilderError> {
+    let mut builder = TextureAtlasBuilder::default().padding(UVec2::splat(PADDING));
+    for (name, handle) in sprites {
+        match images.get(handle) {
+            Some(image) => builder.add_texture(handle.id(), image),
+            None => warn!("sprite {name} isn't loaded, leaving it out of the atlas"),
+        }
+    }
+    let atlas = builder.finish(images)?;
+    let catalog = sprites
+        .iter()
+        .filter_map(|(name, handle)| Some((name.clone(), atlas.get_texture_index(handle)?)))
+        .collect();
+    Ok((atlas, SpriteCatalog(catalog)))
+}
+
+fn build_creature_atlas(
+    mut game_info: ResMut<GameInfoAlt>,
+    mut images: ResMut<Assets<Image>>,
+    mut atlases: ResMut<Assets<TextureAtlas>>,
+    mut catalog: ResMut<SpriteCatalog>,
+    mut toasts: Toasts,
+) {
+    if let Some(sheet) = game_info.creature_sheet.clone() {
+        game_info.creature_atlas = sheet;
+        return;
+    }
+    let Some(folder) = &game_info.creature_folder else {
+        error!("main.assets.ron has neither atlas.creatures nor images.creatures_folder");
+        toasts.error(tr!("toast.sprites_missing"));
+        return;
+    };
+
+    let sprites: Vec<_> = folder
+        .iter()
+        .filter_map(|handle| {
+            let path = handle.path()?.path();
+            let is_png = path
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("png"));
+            let stem = path.file_stem()?.to_string_lossy().into_owned();
+            is_png.then(|| (stem, handle.clone()))
+        })
+        .collect();
+    match pack_sprites(&mut images, &sprites) {
+        Ok((atlas, packed)) => {
+            info!("packed {} creature sprites", packed.0.len());
+            game_info.creature_atlas = atlases.add(atlas);
+            *catalog = packed;
+        }
+        Err(err) => {
+            error!("can't pack the creature sprites: {err:?}");
+            toasts.error(tr!("toast.sprites_failed"));
+        }
+    }
+}
+
+/// Make the sheet of the current map the `CurrentCreatureAtlas`, or the default atlas when it
+/// has none
+pub fn select_creature_atlas(
+    game_info: Res<GameInfoAlt>,
+    tile_maps: Res<Assets<TiledMap>>,
+    asset_server: Res<AssetServer>,
+    mut atlases: ResMut<Assets<TextureAtlas>>,
+    mut map_atlases: ResMut<MapCreatureAtlases>,
+    mut current: ResMut<CurrentCreatureAtlas>,
+) {
+    let sheet = tile_maps.get(&game_info.tile_map).and_then(|map| {
+        CreatureSheet::from_properties(&map.map.properties, atlases.get(&game_info.creature_atlas))
+    });
+    let atlas = match sheet {
+        Some(sheet) => map_atlases
+            .0
+            .entry(sheet.path.clone())
+            .or_insert_with(|| {
+                info!("using the creature sheet {}", sheet.path);
+                atlases.add(TextureAtlas::from_grid(
+                    asset_server.load(&sheet.path),
+                    sheet.tile_size,
+                    sheet.columns,
+                    sheet.rows,
+                    None,
+                    None,
+                ))
+            })
+            .clone(),
+        None => game_info.creature_atlas.clone(),
+    };
+    if current.0 != atlas {
+        current.0 = atlas;
+    }
+}
+
+/// The sprites of the catalog the `atlas` has no room for
+pub fn missing_sprites(catalog: &SpriteCatalog, atlas: &TextureAtlas) -> Vec<String> {
+    let mut missing: Vec<String> = catalog
+        .0
+        .iter()
+        .filter(|(_, index)| **index >= atlas.len())
+        .map(|(name, _)| name.clone())
+        .collect();
+    missing.sort();
+    missing
+}
+
+/// Warn about the sprites of the catalog that aren't in the new atlas
+fn check_catalog(
+    current: Res<CurrentCreatureAtlas>,
+    catalog: Res<SpriteCatalog>,
+    atlases: Res<Assets<TextureAtlas>>,
+    mut toasts: Toasts,
+) {
+    let Some(atlas) = atlases.get(&current.0) else {
+        return;
+    };
+    let missing = missing_sprites(&catalog, atlas);
+    for name in &missing {
+        warn!("sprite {name} is outside the creature atlas of this map");
+    }
+    if !missing.is_empty() {
+        toasts.warn(tr!("toast.sprites_out_of_atlas", count = missing.len()));
+    }
+}
+
+/// The sprites the creatures and items of `map` show that `atlas` doesn't have
+pub fn map_sprite_problems(
+    map: &tiled::Map,
+    entity_templates: Option<&EntityTemplates>,
+    atlas: &TextureAtlas,
+) -> Vec<String> {
+    let len = atlas.len();
+    let mut problems = Vec::new();
+    let objects = all_layers(map).filter_map(|layer| match layer.layer_type() {
+        tiled::LayerType::Objects(layer) => Some(layer),
+        _ => None,
+    });
+    for object in objects.flat_map(|layer| layer.objects().collect::<Vec<_>>()) {
+        // items don't use templates, see `map::spawn_item`
+        let (kind, properties) = match object.user_type.to_ascii_lowercase().as_str() {
+            "item" => ("item", object.properties.clone()),
+            "spawn" | "npc" | "group_spawn" => (
+                "creature",
+                templates::object_properties(entity_templates, &object),
+            ),
+            _ => continue,
+        };
+        if let Some(tiled::PropertyValue::IntValue(index)) = properties.get("atlas_index") {
+            if *index >= 0 && *index as usize >= len {
+                problems.push(format!(
+                    "{kind} {} has atlas_index {index}, the atlas has {len} sprites",
+                    object.name
+                ));
+            }
+        }
+    }
+    problems
+}
+
+/// Report the sprites of the map's objects outside the creature atlas, they show its last sprite
+fn check_map_sprites(
+    game_info: Res<GameInfoAlt>,
+    current: Res<CurrentCreatureAtlas>,
+    tile_maps: Res<Assets<TiledMap>>,
+    atlases: Res<Assets<TextureAtlas>>,
+    entity_templates: Res<Assets<EntityTemplates>>,
+    mut toasts: Toasts,
+) {
+    let (Some(map), Some(atlas)) = (tile_maps.get(&game_info.tile_map), atlases.get(&current.0))
+    else {
+        return;
+    };
+    let problems = map_sprite_problems(&map.map, entity_templates.get(&game_info.templates), atlas);
+    for problem in &problems {
+        warn!("{problem}");
+    }
+    if !problems.is_empty() {
+        toasts.warn(tr!("toast.sprites_out_of_atlas", count = problems.len()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+
+    use super::*;
+
+    fn sprite(images: &mut Assets<Image>, size: u32, color: [u8; 4]) -> Handle<Image> {
+        images.add(Image::new_fill(
+            Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &color,
+            TextureFormat::Rgba8UnormSrgb,
+        ))
+    }
+
+    #[test]
+    fn packed_sprites_are_found_by_name() {
+        let mut images = Assets::<Image>::default();
+        let sprites = vec![
+            ("bat".to_string(), sprite(&mut images, 24, [200; 4])),
+            ("slime".to_string(), sprite(&mut images, 16, [100; 4])),
+        ];
+
+        let (atlas, catalog) = pack_sprites(&mut images, &sprites).unwrap();
+
+        assert_eq!(atlas.len(), 2);
+        let bat = atlas.textures[catalog.0["bat"]];
+        let slime = atlas.textures[catalog.0["slime"]];
+        assert_eq!(bat.size(), Vec2::splat(24.));
+        assert_eq!(slime.size(), Vec2::splat(16.));
+        assert!(bat.intersect(slime).is_empty());
+        assert!(!catalog.0.contains_key("ghost"));
+    }
+
+    fn properties(values: &[(&str, tiled::PropertyValue)]) -> tiled::Properties {
+        values
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn map_sheets_default_to_the_grid_of_the_default_atlas() {
+        let default =
+            TextureAtlas::from_grid(Handle::default(), Vec2::splat(24.), 20, 27, None, None);
+        let sheet = tiled::PropertyValue::StringValue("sprites/cave.png".to_string());
+        assert_eq!(
+            CreatureSheet::from_properties(&properties(&[]), Some(&default)),
+            None
+        );
+        assert_eq!(
+            CreatureSheet::from_properties(
+                &properties(&[
+                    ("creature_sheet", sheet.clone()),
+                    ("creature_rows", tiled::PropertyValue::IntValue(4)),
+                ]),
+                Some(&default)
+            ),
+            Some(CreatureSheet {
+                path: "sprites/cave.png".to_string(),
+                tile_size: Vec2::splat(24.),
+                columns: 20,
+                rows: 4,
+            })
+        );
+        // no grid to fall back on
+        assert_eq!(
+            CreatureSheet::from_properties(&properties(&[("creature_sheet", sheet)]), None),
+            None
+        );
+
+        let small = TextureAtlas::from_grid(Handle::default(), Vec2::splat(24.), 2, 2, None, None);
+        let catalog = SpriteCatalog(HashMap::from_iter([
+            ("bat".to_string(), 1),
+            ("troll".to_string(), 9),
+            ("ghost".to_string(), 4),
+        ]));
+        assert_eq!(missing_sprites(&catalog, &small), ["ghost", "troll"]);
+    }
+
+    #[test]
+    fn packed_sprites_keep_their_distance() {
+        let mut images = Assets::<Image>::default();
+        let sprites: Vec<_> = (0..4)
+            .map(|i| (format!("sprite {i}"), sprite(&mut images, 8, [255; 4])))
+            .collect();
+
+        let (atlas, _) = pack_sprites(&mut images, &sprites).unwrap();
+
+        for (i, a) in atlas.textures.iter().enumerate() {
+            for b in &atlas.textures[i + 1..] {
+                let grown = Rect::from_center_size(a.center(), a.size() + PADDING as f32 * 2.);
+                assert!(
+                    grown.intersect(*b).is_empty(),
+                    "{a:?} and {b:?} are closer than the padding"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn map_sprites_past_the_atlas_are_reported() {
+        let map = crate::helpers::tiled::load_test_map("assets/maps/TMX/map_test_templates.tmx");
+        let entity_templates: EntityTemplates = ron::from_str(
+            r#"#![enable(implicit_some)] {"bat": (creature_row: 3), "statue": (atlas_index: 45)}"#,
+        )
+        .unwrap();
+        let small = TextureAtlas::from_grid(Handle::default(), Vec2::splat(16.), 2, 1, None, None);
+        assert_eq!(
+            map_sprite_problems(&map.map, Some(&entity_templates), &small),
+            ["creature statue has atlas_index 45, the atlas has 2 sprites"]
+        );
+        let default =
+            TextureAtlas::from_grid(Handle::default(), Vec2::splat(24.), 20, 27, None, None);
+        assert!(map_sprite_problems(&map.map, Some(&entity_templates), &default).is_empty());
+    }
+}
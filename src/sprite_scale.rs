@@ -0,0 +1,296 @@
+//! Fitting the creature sprites to the tiles of maps whose tiles aren't the size of the creature
+//! atlas cells.
+//!
+//! `SpriteScaleMode` picks how: `Native` keeps the cell size, `FitTileHeight` and `FitTileWidth`
+//! scale the sprite, keeping its aspect, to the tile's height or width. The resource is the mode
+//! of every creature, a creature's `sprite_scale_mode` property, on its object or its template,
+//! overrides it. Whatever the size, the sprite is anchored with its feet on the bottom edge of
+//! its tile, and the y-sort sorts the creatures by their feet.
+//!
+//! The size goes in the sprite's `custom_size` rather than in the transform scale, which the
+//! spawn and despawn effects tween.
+
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use serde::Deserialize;
+
+use crate::coords::MapFrame;
+use crate::game::GameSet;
+use crate::map::Npc;
+use crate::player::MainPlayer;
+use crate::state::level_ready;
+
+/// Name of the creature property overriding the scale mode
+pub const SCALE_MODE_PROPERTY: &str = "sprite_scale_mode";
+
+/// Plugin that sizes and anchors the creature sprites for the map's tiles
+#[derive(Default)]
+pub struct SpriteScalePlugin;
+
+impl Plugin for SpriteScalePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SpriteScaleMode>()
+            .register_type::<SpriteScaleMode>()
+            .add_systems(
+                Update,
+                fit_creature_sprites
+                    .in_set(GameSet::RenderPrep)
+                    .run_if(level_ready),
+            );
+    }
+}
+
+/// How a creature sprite is sized against the map's tiles
+#[derive(Resource, Reflect, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[reflect(Resource)]
+pub enum SpriteScaleMode {
+    /// The size of its atlas cell
+    #[default]
+    Native,
+    /// As high as a tile
+    FitTileHeight,
+    /// As wide as a tile
+    FitTileWidth,
+}
+
+/// The creature's own scale mode, from its `sprite_scale_mode` property
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpriteScaleOverride(pub SpriteScaleMode);
+
+/// How a creature sprite is drawn on its tile
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpriteFit {
+    /// Drawn size of the sprite
+    pub size: Vec2,
+    /// Point of the sprite at the creature's translation, the tile center
+    pub anchor: Vec2,
+}
+
+impl SpriteScaleMode {
+    /// Name of the mode in the `sprite_scale_mode` property
+    pub fn name(self) -> &'static str {
+        match self {
+            SpriteScaleMode::Native => "native",
+            SpriteScaleMode::FitTileHeight => "fit_tile_height",
+            SpriteScaleMode::FitTileWidth => "fit_tile_width",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            SpriteScaleMode::Native,
+            SpriteScaleMode::FitTileHeight,
+            SpriteScaleMode::FitTileWidth,
+        ]
+        .into_iter()
+        .find(|mode| mode.name().eq_ignore_ascii_case(name))
+    }
+
+    /// The mode in the `sprite_scale_mode` property, if it's set to one
+    pub fn from_properties(name: &str, properties: &tiled::Properties) -> Option<Self> {
+        let tiled::PropertyValue::StringValue(value) = properties.get(SCALE_MODE_PROPERTY)? else {
+            return None;
+        };
+        let mode = Self::from_name(value);
+        if mode.is_none() {
+            warn!("{name} has the unknown {SCALE_MODE_PROPERTY} {value}, using the default");
+        }
+        mode
+    }
+
+    /// Size and anchor of a sprite cut from a `cell` of the atlas, on a `tile` of the map
+    pub fn fit(self, cell: Vec2, tile: Vec2) -> SpriteFit {
+        let scale = match self {
+            SpriteScaleMode::Native => 1.,
+            SpriteScaleMode::FitTileHeight if cell.y > 0. => tile.y / cell.y,
+            SpriteScaleMode::FitTileWidth if cell.x > 0. => tile.x / cell.x,
+            _ => 1.,
+        };
+        let size = cell * scale;
+        // the bottom edge half a tile below the center
+        let anchor_y = if size.y > 0. {
+            tile.y / (2. * size.y) - 0.5
+        } else {
+            0.
+        };
+        SpriteFit {
+            size,
+            anchor: Vec2::new(0., anchor_y),
+        }
+    }
+}
+
+/// Where the feet of a creature at the world position `pos` are, on the bottom edge of its tile
+pub fn feet_position(map_frame: &MapFrame, pos: Vec2) -> Vec2 {
+    pos - Vec2::new(0., map_frame.grid_size.y / 2.)
+}
+
+/// Size and anchor the creatures spawned or given another atlas since the last run, or all of
+/// them when the mode or the map changes
+#[allow(clippy::type_complexity)]
+fn fit_creature_sprites(
+    mode: Res<SpriteScaleMode>,
+    map_frame: Res<MapFrame>,
+    atlases: Res<Assets<TextureAtlas>>,
+    mut creatures: Query<
+        (
+            Ref<Handle<TextureAtlas>>,
+            &mut TextureAtlasSprite,
+            Option<Ref<SpriteScaleOverride>>,
+        ),
+        Or<(With<MainPlayer>, With<Npc>)>,
+    >,
+) {
+    let refit_all = mode.is_changed() || map_frame.is_changed();
+    let tile = Vec2::new(map_frame.grid_size.x, map_frame.grid_size.y);
+    for (atlas, mut sprite, scale_override) in &mut creatures {
+        let override_changed = scale_override
+            .as_ref()
+            .is_some_and(|scale_override| scale_override.is_changed());
+        if !refit_all && !atlas.is_changed() && !override_changed {
+            continue;
+        }
+        let Some(cell) = atlases
+            .get(&*atlas)
+            .and_then(|atlas| atlas.textures.get(sprite.index))
+        else {
+            continue;
+        };
+        let mode = scale_override.map_or(*mode, |scale_override| scale_override.0);
+        let fit = mode.fit(cell.size(), tile);
+        if sprite.custom_size != Some(fit.size) || sprite.anchor.as_vec() != fit.anchor {
+            sprite.custom_size = Some(fit.size);
+            sprite.anchor = Anchor::Custom(fit.anchor);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs_tilemap::prelude::*;
+
+    use super::*;
+
+    fn test_app(tile_side: f32, cell_side: f32) -> (App, Handle<TextureAtlas>) {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<Assets<TextureAtlas>>()
+            .init_resource::<SpriteScaleMode>()
+            .insert_resource(MapFrame {
+                transform: Transform::IDENTITY,
+                grid_size: TilemapGridSize {
+                    x: tile_side,
+                    y: tile_side,
+                },
+                map_type: TilemapType::Square,
+                map_size: TilemapSize { x: 4, y: 4 },
+            })
+            .add_systems(Update, fit_creature_sprites);
+        let atlas = app
+            .world
+            .resource_mut::<Assets<TextureAtlas>>()
+            .add(TextureAtlas::from_grid(
+                Handle::default(),
+                Vec2::splat(cell_side),
+                4,
+                4,
+                None,
+                None,
+            ));
+        (app, atlas)
+    }
+
+    fn spawn_npc(app: &mut App, atlas: &Handle<TextureAtlas>) -> Entity {
+        app.world
+            .spawn((
+                SpriteSheetBundle {
+                    texture_atlas: atlas.clone(),
+                    sprite: TextureAtlasSprite::new(5),
+                    transform: Transform::from_xyz(0., 0., 2.),
+                    ..default()
+                },
+                Npc,
+            ))
+            .id()
+    }
+
+    /// Drawn size of the sprite, and the y of its bottom edge
+    fn drawn(app: &App, npc: Entity) -> (Vec2, f32) {
+        let sprite = app.world.get::<TextureAtlasSprite>(npc).unwrap();
+        let size = sprite.custom_size.unwrap();
+        let y = app.world.get::<Transform>(npc).unwrap().translation.y;
+        (size, y - (sprite.anchor.as_vec().y + 0.5) * size.y)
+    }
+
+    #[test]
+    fn small_sprites_on_big_tiles() {
+        let (mut app, atlas) = test_app(32., 16.);
+        let npc = spawn_npc(&mut app, &atlas);
+        app.update();
+        // native: half the tile high, standing on its bottom edge
+        assert_eq!(drawn(&app, npc), (Vec2::splat(16.), -16.));
+        assert_eq!(
+            app.world
+                .get::<TextureAtlasSprite>(npc)
+                .unwrap()
+                .anchor
+                .as_vec(),
+            Vec2::new(0., 0.5)
+        );
+
+        *app.world.resource_mut::<SpriteScaleMode>() = SpriteScaleMode::FitTileHeight;
+        app.update();
+        assert_eq!(drawn(&app, npc), (Vec2::splat(32.), -16.));
+        assert_eq!(
+            app.world
+                .get::<TextureAtlasSprite>(npc)
+                .unwrap()
+                .anchor
+                .as_vec(),
+            Vec2::ZERO
+        );
+    }
+
+    #[test]
+    fn big_sprites_on_small_tiles() {
+        let (mut app, atlas) = test_app(16., 32.);
+        let npc = spawn_npc(&mut app, &atlas);
+        let giant = spawn_npc(&mut app, &atlas);
+        app.world
+            .entity_mut(giant)
+            .insert(SpriteScaleOverride(SpriteScaleMode::Native));
+        *app.world.resource_mut::<SpriteScaleMode>() = SpriteScaleMode::FitTileWidth;
+        app.update();
+
+        assert_eq!(drawn(&app, npc), (Vec2::splat(16.), -8.));
+        // twice the tile high, feet on the tile's bottom edge all the same
+        assert_eq!(drawn(&app, giant), (Vec2::splat(32.), -8.));
+        assert_eq!(
+            app.world
+                .get::<TextureAtlasSprite>(giant)
+                .unwrap()
+                .anchor
+                .as_vec(),
+            Vec2::new(0., -0.25)
+        );
+        let map_frame = app.world.resource::<MapFrame>();
+        assert_eq!(feet_position(map_frame, Vec2::ZERO), Vec2::new(0., -8.));
+    }
+
+    #[test]
+    fn modes_by_name() {
+        let properties = tiled::Properties::from([(
+            SCALE_MODE_PROPERTY.to_string(),
+            tiled::PropertyValue::StringValue("Fit_Tile_Height".to_string()),
+        )]);
+        assert_eq!(
+            SpriteScaleMode::from_properties("troll", &properties),
+            Some(SpriteScaleMode::FitTileHeight)
+        );
+        assert_eq!(SpriteScaleMode::from_name("stretch"), None);
+        assert_eq!(
+            SpriteScaleMode::from_properties("troll", &tiled::Properties::new()),
+            None
+        );
+    }
+}
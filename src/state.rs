@@ -5,4 +5,28 @@ pub enum AppState {
     #[default]
     Loading,
     Level,
+    /// The player died, the level stays spawned behind the game over screen
+    GameOver,
+    /// The level was torn down and the next map is loading, see `map_switch`
+    SwitchingMap,
+}
+
+impl AppState {
+    /// Every state, for adding systems to each of them
+    pub const ALL: [AppState; 4] = [
+        AppState::Loading,
+        AppState::Level,
+        AppState::GameOver,
+        AppState::SwitchingMap,
+    ];
+}
+
+/// Whether the level's spawns have all been applied, set a frame after entering
+/// `AppState::Level` and cleared when leaving it, see `map::add_level_systems`
+#[derive(Resource, Default, Debug, PartialEq, Eq)]
+pub struct LevelReady(pub bool);
+
+/// In `AppState::Level` with the level spawned, as a run condition for the level's systems
+pub fn level_ready(state: Res<State<AppState>>, ready: Option<Res<LevelReady>>) -> bool {
+    *state.get() == AppState::Level && ready.is_some_and(|ready| ready.0)
 }
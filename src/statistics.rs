@@ -0,0 +1,373 @@
+//! Counters of what the player did, for this session and for all the sessions before.
+//!
+//! The counting systems listen to the events the gameplay already sends. The lifetime counters are
+//! read from `STATS_FILE` at startup and the session is added into them whenever they're written
+//! back: when a save slot is written and when the app exits. Crossing one of the `MILESTONES`
+//! sends `MilestoneReached`, announced with a toast.
+
+use std::path::Path;
+
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::camera::CameraViewChanged;
+use crate::combat::BumpAttack;
+use crate::console::{ConsoleCommand, DebugConsole};
+use crate::game::GameSet;
+use crate::interact::Interact;
+use crate::inventory::ItemPickedUp;
+use crate::localization::tr;
+use crate::map::GameInfoAlt;
+use crate::movement::MoveResolved;
+use crate::player::MainPlayer;
+use crate::save_slots::SlotRequest;
+use crate::state::{level_ready, AppState};
+use crate::toast::Toasts;
+
+/// File of the lifetime counters, relative to the working directory
+const STATS_FILE: &str = "statistics.ron";
+
+/// Counters whose lifetime total announces a milestone at these values
+const MILESTONES: &[(Stat, u64)] = &[
+    (Stat::TilesWalked, 1000),
+    (Stat::TilesWalked, 10_000),
+    (Stat::TurnsTaken, 10_000),
+    (Stat::ItemsPickedUp, 100),
+    (Stat::MapsVisited, 10),
+];
+
+/// Plugin that counts the `Statistics` and announces the milestones
+#[derive(Default)]
+pub struct StatisticsPlugin;
+
+impl Plugin for StatisticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Statistics::load(Path::new(STATS_FILE)))
+            .register_type::<Statistics>()
+            .register_type::<StatCounters>()
+            .add_event::<MilestoneReached>()
+            .add_systems(OnEnter(AppState::Level), count_map_visit)
+            .add_systems(Update, stats_command.in_set(GameSet::Input))
+            .add_systems(
+                Update,
+                (
+                    count_playtime.run_if(crate::debug_step::gameplay_running),
+                    count_player_actions,
+                    count_zoom,
+                    announce_milestones,
+                )
+                    .chain()
+                    .in_set(GameSet::Gameplay)
+                    .run_if(level_ready),
+            )
+            .add_systems(Last, persist_statistics);
+    }
+}
+
+/// A counter of `StatCounters`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stat {
+    TilesWalked,
+    TurnsTaken,
+    ItemsPickedUp,
+    MapsVisited,
+}
+
+impl Stat {
+    /// Localization key of the counter's name
+    pub fn key(self) -> &'static str {
+        match self {
+            Stat::TilesWalked => "stat.tiles_walked",
+            Stat::TurnsTaken => "stat.turns_taken",
+            Stat::ItemsPickedUp => "stat.items_picked_up",
+            Stat::MapsVisited => "stat.maps_visited",
+        }
+    }
+}
+
+#[derive(Reflect, Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct StatCounters {
+    /// Seconds spent in the level, not counting the debug pause
+    pub playtime: f32,
+    pub tiles_walked: u64,
+    /// Moves, attacks and interactions of the player
+    pub turns_taken: u64,
+    pub items_picked_up: u64,
+    /// Asset paths of the maps entered, sorted
+    pub maps_visited: Vec<String>,
+    /// Largest camera scale used
+    pub max_zoom_out: f32,
+}
+
+impl StatCounters {
+    pub fn get(&self, stat: Stat) -> u64 {
+        match stat {
+            Stat::TilesWalked => self.tiles_walked,
+            Stat::TurnsTaken => self.turns_taken,
+            Stat::ItemsPickedUp => self.items_picked_up,
+            Stat::MapsVisited => self.maps_visited.len() as u64,
+        }
+    }
+
+    pub fn visit_map(&mut self, path: &str) {
+        if let Err(at) = self
+            .maps_visited
+            .binary_search_by(|map| map.as_str().cmp(path))
+        {
+            self.maps_visited.insert(at, path.to_string());
+        }
+    }
+
+    /// These counters with `other` added in: sums, the union of the maps and the larger zoom
+    pub fn merged(&self, other: &StatCounters) -> StatCounters {
+        let mut merged = StatCounters {
+            playtime: self.playtime + other.playtime,
+            tiles_walked: self.tiles_walked + other.tiles_walked,
+            turns_taken: self.turns_taken + other.turns_taken,
+            items_picked_up: self.items_picked_up + other.items_picked_up,
+            maps_visited: self.maps_visited.clone(),
+            max_zoom_out: self.max_zoom_out.max(other.max_zoom_out),
+        };
+        for map in &other.maps_visited {
+            merged.visit_map(map);
+        }
+        merged
+    }
+}
+
+#[derive(Resource, Reflect, Debug, Clone, Default, PartialEq)]
+#[reflect(Resource)]
+pub struct Statistics {
+    pub session: StatCounters,
+    /// The sessions before this one
+    pub previous: StatCounters,
+}
+
+impl Statistics {
+    /// The counters of every session, this one included
+    pub fn lifetime(&self) -> StatCounters {
+        self.previous.merged(&self.session)
+    }
+
+    /// Start a session on the lifetime counters saved at `path`, none when it can't be read
+    pub fn load(path: &Path) -> Self {
+        let previous = match std::fs::read_to_string(path) {
+            Ok(text) => ron::from_str(&text).unwrap_or_else(|err| {
+                warn!("can't read the statistics in {path:?}, starting over: {err}");
+                StatCounters::default()
+            }),
+            Err(_) => StatCounters::default(),
+        };
+        Self {
+            session: StatCounters::default(),
+            previous,
+        }
+    }
+
+    /// Write the lifetime counters to `path`
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let text = ron::ser::to_string_pretty(&self.lifetime(), ron::ser::PrettyConfig::default())
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        std::fs::write(path, text)
+    }
+}
+
+/// Sent when a lifetime counter reaches one of the `MILESTONES`
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MilestoneReached {
+    pub stat: Stat,
+    pub value: u64,
+}
+
+/// The milestones passed going from the counters `before` to `after`
+pub fn crossed_milestones(before: &StatCounters, after: &StatCounters) -> Vec<MilestoneReached> {
+    MILESTONES
+        .iter()
+        .filter(|(stat, value)| before.get(*stat) < *value && after.get(*stat) >= *value)
+        .map(|&(stat, value)| MilestoneReached { stat, value })
+        .collect()
+}
+
+fn count_playtime(time: Res<Time>, mut stats: ResMut<Statistics>) {
+    stats.session.playtime += time.delta_seconds();
+}
+
+fn count_map_visit(game_info: Option<Res<GameInfoAlt>>, mut stats: ResMut<Statistics>) {
+    let Some(path) = game_info.and_then(|game_info| game_info.tile_map.path().cloned()) else {
+        return;
+    };
+    stats
+        .session
+        .visit_map(&path.path().to_string_lossy().replace('\\', "/"));
+}
+
+fn count_player_actions(
+    mut moves: EventReader<MoveResolved>,
+    mut attacks: EventReader<BumpAttack>,
+    mut interactions: EventReader<Interact>,
+    mut pickups: EventReader<ItemPickedUp>,
+    players: Query<(), With<MainPlayer>>,
+    mut stats: ResMut<Statistics>,
+) {
+    let moved = moves
+        .read()
+        .filter(|event| players.contains(event.entity))
+        .count() as u64;
+    let attacked = attacks
+        .read()
+        .filter(|event| players.contains(event.attacker))
+        .count() as u64;
+    let interacted = interactions
+        .read()
+        .filter(|event| players.contains(event.actor))
+        .count() as u64;
+    let picked_up = pickups
+        .read()
+        .filter(|event| players.contains(event.picker))
+        .count() as u64;
+    if moved + attacked + interacted + picked_up == 0 {
+        return;
+    }
+    let session = &mut stats.session;
+    session.tiles_walked += moved;
+    session.turns_taken += moved + attacked + interacted;
+    session.items_picked_up += picked_up;
+}
+
+fn count_zoom(mut views: EventReader<CameraViewChanged>, mut stats: ResMut<Statistics>) {
+    for view in views.read() {
+        if view.scale > stats.session.max_zoom_out {
+            stats.session.max_zoom_out = view.scale;
+        }
+    }
+}
+
+/// Send `MilestoneReached` for the milestones crossed since the last frame
+fn announce_milestones(
+    stats: Res<Statistics>,
+    mut last: Local<Option<StatCounters>>,
+    mut milestones: EventWriter<MilestoneReached>,
+    mut toasts: Toasts,
+) {
+    if !stats.is_changed() && last.is_some() {
+        return;
+    }
+    let lifetime = stats.lifetime();
+    if let Some(before) = last.as_ref() {
+        for milestone in crossed_milestones(before, &lifetime) {
+            toasts.info(tr!(
+                "stat.milestone",
+                value = milestone.value,
+                stat = tr!(milestone.stat.key())
+            ));
+            milestones.send(milestone);
+        }
+    }
+    *last = Some(lifetime);
+}
+
+/// Write the lifetime counters when a slot is saved and when the app exits
+fn persist_statistics(
+    stats: Res<Statistics>,
+    mut slot_requests: EventReader<SlotRequest>,
+    mut exits: EventReader<AppExit>,
+) {
+    let saved = slot_requests
+        .read()
+        .any(|request| matches!(request, SlotRequest::Save(_)));
+    let exiting = exits.read().count() > 0;
+    if saved || exiting {
+        if let Err(err) = stats.save(Path::new(STATS_FILE)) {
+            warn!("can't write the statistics to {STATS_FILE}: {err}");
+        }
+    }
+}
+
+/// `stats` prints the session and lifetime counters
+fn stats_command(
+    mut console_commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<DebugConsole>,
+    stats: Res<Statistics>,
+) {
+    for command in console_commands.read() {
+        if command.name != "stats" {
+            continue;
+        }
+        let lifetime = stats.lifetime();
+        for (label, counters) in [("session", &stats.session), ("lifetime", &lifetime)] {
+            console.print(format!(
+                "{label}: {:.0} s played, {} tiles walked, {} turns, {} items, {} maps, zoom {:.1}",
+                counters.playtime,
+                counters.tiles_walked,
+                counters.turns_taken,
+                counters.items_picked_up,
+                counters.maps_visited.len(),
+                counters.max_zoom_out
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counters(tiles_walked: u64, maps: &[&str]) -> StatCounters {
+        let mut counters = StatCounters {
+            playtime: 10.,
+            tiles_walked,
+            turns_taken: tiles_walked,
+            max_zoom_out: 2.,
+            ..default()
+        };
+        for map in maps {
+            counters.visit_map(map);
+        }
+        counters
+    }
+
+    #[test]
+    fn the_session_is_added_into_the_lifetime_counters_on_load() {
+        let path =
+            std::env::temp_dir().join(format!("bevy_test_statistics_{}.ron", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(Statistics::load(&path), Statistics::default());
+
+        let mut first = Statistics::load(&path);
+        first.session = counters(990, &["a.tmx", "b.tmx"]);
+        first.save(&path).unwrap();
+
+        let mut second = Statistics::load(&path);
+        assert_eq!(second.previous, first.session);
+        second.session = counters(20, &["b.tmx", "c.tmx"]);
+        second.session.max_zoom_out = 6.;
+        second.save(&path).unwrap();
+
+        let lifetime = Statistics::load(&path).previous;
+        assert_eq!(lifetime.tiles_walked, 1010);
+        assert_eq!(lifetime.playtime, 20.);
+        assert_eq!(lifetime.maps_visited, ["a.tmx", "b.tmx", "c.tmx"]);
+        assert_eq!(lifetime.max_zoom_out, 6.);
+        assert_eq!(
+            crossed_milestones(&first.session, &lifetime),
+            [MilestoneReached {
+                stat: Stat::TilesWalked,
+                value: 1000
+            }]
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn unreadable_statistics_start_over() {
+        let path = std::env::temp_dir().join(format!(
+            "bevy_test_statistics_bad_{}.ron",
+            std::process::id()
+        ));
+        std::fs::write(&path, "(tiles_walked: ").unwrap();
+        assert_eq!(Statistics::load(&path).previous, StatCounters::default());
+        let _ = std::fs::remove_file(&path);
+    }
+}
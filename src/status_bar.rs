@@ -0,0 +1,213 @@
+//! A thin bar along the bottom of the window with the current map, the app state, the player's
+//! tile and the hovered tile.
+//!
+//! The bar itself isn't interactable, so egui lets the pointer through to the camera over it.
+//! Only the map name on its left takes clicks: it opens the map menu, which lists the maps on disk
+//! and switches to the one picked with `LoadMap`.
+
+use std::path::Path;
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use bevy_inspector_egui::bevy_egui::EguiContext;
+use bevy_window::PrimaryWindow;
+
+use crate::auto_hide::{fade_visuals, overlay_alpha, AutoHide, Overlay};
+use crate::coords::{self, MapFrame};
+use crate::game::GameSet;
+use crate::localization::tr;
+use crate::map::GameInfoAlt;
+use crate::map_switch::LoadMap;
+use crate::player::MainPlayer;
+use crate::state::AppState;
+use crate::ui::HoveredTile;
+
+/// Folder the map menu lists, relative to the assets folder
+const MAPS_FOLDER: &str = "maps/TMX";
+/// Space between the map name and the rest of the bar
+const GAP: f32 = 12.;
+
+/// Plugin that draws the status bar and the map menu
+#[derive(Default)]
+pub struct StatusBarPlugin;
+
+impl Plugin for StatusBarPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MapMenu>()
+            .add_systems(Update, show_status_bar.in_set(GameSet::RenderPrep));
+    }
+}
+
+/// The menu of maps to switch to, opened from the status bar
+#[derive(Resource, Default, Debug)]
+pub struct MapMenu {
+    pub open: bool,
+    /// Asset paths of the maps, listed when the menu opens
+    maps: Vec<String>,
+}
+
+impl MapMenu {
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        if self.open {
+            self.maps = list_maps(&Path::new("assets").join(MAPS_FOLDER));
+        }
+    }
+}
+
+/// Asset paths of the `.tmx` files in `folder`, sorted
+fn list_maps(folder: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(folder) else {
+        warn!("can't list the maps in {folder:?}");
+        return Vec::new();
+    };
+    let mut maps: Vec<_> = entries
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            name.ends_with(".tmx")
+                .then(|| format!("{MAPS_FOLDER}/{name}"))
+        })
+        .collect();
+    maps.sort();
+    maps
+}
+
+/// Name of the map at asset path `path`, its file stem
+fn map_name(path: &Path) -> String {
+    path.file_stem().map_or_else(
+        || "?".to_string(),
+        |stem| stem.to_string_lossy().into_owned(),
+    )
+}
+
+fn tile_label(tile: Option<TilePos>) -> String {
+    tile.map_or_else(
+        || "-".to_string(),
+        |tile| format!("({}, {})", tile.x, tile.y),
+    )
+}
+
+/// The bar's text after the map name
+fn status_text(state: AppState, player: Option<TilePos>, hovered: Option<TilePos>) -> String {
+    tr!(
+        "status.text",
+        state = format!("{state:?}"),
+        player = tile_label(player),
+        hovered = tile_label(hovered)
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn show_status_bar(
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+    state: Res<State<AppState>>,
+    game_info: Option<Res<GameInfoAlt>>,
+    hovered: Res<HoveredTile>,
+    map_frame: Option<Res<MapFrame>>,
+    players: Query<&Transform, With<MainPlayer>>,
+    mut menu: ResMut<MapMenu>,
+    mut load_map: EventWriter<LoadMap>,
+    overlays: Query<&AutoHide>,
+) {
+    let alpha = overlay_alpha(&overlays, Overlay::StatusBar);
+    if alpha <= 0. {
+        return;
+    }
+    let Some(mut ctx) = contexts.iter_mut().next() else {
+        return;
+    };
+    let ctx = ctx.get_mut();
+
+    let name = game_info
+        .as_ref()
+        .and_then(|game_info| game_info.tile_map.path())
+        .map_or_else(|| tr!("status.no_map"), |path| map_name(path.path()));
+    let player = map_frame.as_ref().and_then(|map_frame| {
+        let transform = players.get_single().ok()?;
+        coords::world_to_tile(map_frame, transform.translation.truncate())
+    });
+
+    // the map name is its own area, the only part of the bar taking the pointer
+    let button = egui::Area::new(egui::Id::new("status_bar_map"))
+        .order(egui::Order::Foreground)
+        .anchor(egui::Align2::LEFT_BOTTOM, egui::vec2(6., -3.))
+        .show(ctx, |ui| {
+            fade_visuals(ui.visuals_mut(), alpha);
+            ui.small_button(&name)
+        })
+        .inner;
+    if button.clicked() {
+        menu.toggle();
+    }
+
+    egui::Area::new(egui::Id::new("status_bar"))
+        .anchor(egui::Align2::LEFT_BOTTOM, egui::Vec2::ZERO)
+        .interactable(false)
+        .show(ctx, |ui| {
+            fade_visuals(ui.visuals_mut(), alpha);
+            egui::Frame::none()
+                .fill(ui.visuals().extreme_bg_color)
+                .inner_margin(egui::Margin::symmetric(6., 3.))
+                .show(ui, |ui| {
+                    ui.set_min_width(ui.ctx().screen_rect().width() - 12.);
+                    ui.horizontal(|ui| {
+                        // leave room for the map name drawn over the bar
+                        ui.add_space(button.rect.width() + GAP);
+                        ui.label(status_text(**state, player, hovered.0));
+                    });
+                });
+        });
+
+    if !menu.open {
+        return;
+    }
+    let mut open = true;
+    let mut picked = None;
+    egui::Window::new(tr!("status.maps"))
+        .id(egui::Id::new("maps"))
+        .open(&mut open)
+        .anchor(egui::Align2::LEFT_BOTTOM, egui::vec2(6., -28.))
+        .resizable(false)
+        .collapsible(false)
+        .show(ctx, |ui| {
+            if menu.maps.is_empty() {
+                ui.label(tr!("status.no_maps", folder = MAPS_FOLDER));
+            }
+            for path in &menu.maps {
+                if ui.button(map_name(Path::new(path))).clicked() {
+                    picked = Some(path.clone());
+                }
+            }
+        });
+    if let Some(path) = picked {
+        if *state.get() == AppState::Level {
+            load_map.send(LoadMap { path });
+        } else {
+            warn!("can't switch maps in state {:?}", state.get());
+        }
+        open = false;
+    }
+    menu.open = open;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_text_shows_the_state_and_tiles() {
+        assert_eq!(
+            status_text(AppState::Level, Some(TilePos::new(3, 4)), None),
+            "Level    player (3, 4)    hovered -"
+        );
+        assert_eq!(map_name(Path::new("maps/TMX/map_test_1.tmx")), "map_test_1");
+    }
+
+    #[test]
+    fn the_map_menu_lists_the_tmx_files() {
+        let maps = list_maps(&Path::new("assets").join(MAPS_FOLDER));
+        assert!(maps.contains(&"maps/TMX/map_test_1.tmx".to_string()));
+        assert!(maps.iter().all(|path| path.ends_with(".tmx")));
+        assert!(maps.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+}
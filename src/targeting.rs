@@ -0,0 +1,317 @@
+//! Targeting mode for ranged abilities: while the targeting key is held, the tiles on the line
+//! from the player to the hovered tile are highlighted, and releasing the key confirms the target.
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::collision::CollisionMap;
+use crate::coords::{self, MapFrame};
+use crate::game::GameSet;
+use crate::lifetime::ExpireOnStateExit;
+use crate::palette::{OverlayPalette, OverlayRole, PaletteChanged};
+use crate::player::MainPlayer;
+use crate::state::{level_ready, AppState};
+use crate::ui::HoveredTile;
+
+/// Key held to aim
+pub const TARGETING_KEY: KeyCode = KeyCode::F;
+
+/// Opacity of the highlights, their color comes from the `OverlayPalette`
+const HIGHLIGHT_ALPHA: f32 = 0.45;
+/// Between the map layers and the creatures
+const HIGHLIGHT_Z: f32 = 1.8;
+
+/// Plugin that previews and confirms targeting lines
+#[derive(Default)]
+pub struct TargetingPlugin;
+
+impl Plugin for TargetingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TargetingPreview>()
+            .init_resource::<HighlightPool>()
+            .add_event::<TargetConfirmed>()
+            .add_systems(
+                Update,
+                update_targeting
+                    .in_set(GameSet::Gameplay)
+                    .run_if(level_ready),
+            )
+            .add_systems(
+                Update,
+                (draw_tile_highlights, retint_highlights)
+                    .in_set(GameSet::RenderPrep)
+                    .run_if(level_ready),
+            );
+    }
+}
+
+/// Sent when the targeting key is released, `path` starts next to the player and ends at `end`
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct TargetConfirmed {
+    pub path: Vec<TilePos>,
+    pub end: TilePos,
+}
+
+/// The line currently previewed, empty when not aiming
+#[derive(Resource, Default)]
+pub struct TargetingPreview {
+    pub path: Vec<TilePos>,
+}
+
+#[derive(Component)]
+struct TileHighlight;
+
+/// Sprites used to highlight tiles, reused from frame to frame
+#[derive(Resource, Default)]
+struct HighlightPool {
+    entities: Vec<Entity>,
+}
+
+/// Tiles on the line from `from` to `to`, both included, using Bresenham's algorithm
+pub fn tile_line(from: TilePos, to: TilePos) -> Vec<TilePos> {
+    let (mut x, mut y) = (from.x as i64, from.y as i64);
+    let (to_x, to_y) = (to.x as i64, to.y as i64);
+    let dx = (to_x - x).abs();
+    let dy = -(to_y - y).abs();
+    let step_x = if x < to_x { 1 } else { -1 };
+    let step_y = if y < to_y { 1 } else { -1 };
+    let mut error = dx + dy;
+
+    let mut line = Vec::with_capacity(dx.max(-dy) as usize + 1);
+    loop {
+        line.push(TilePos::new(x as u32, y as u32));
+        if x == to_x && y == to_y {
+            return line;
+        }
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+/// The targeting path from `from` to `to`: the line without its start, cut before the first
+/// blocked tile
+pub fn targeting_path(
+    from: TilePos,
+    to: TilePos,
+    is_blocked: impl Fn(&TilePos) -> bool,
+) -> Vec<TilePos> {
+    tile_line(from, to)
+        .into_iter()
+        .skip(1)
+        .take_while(|pos| !is_blocked(pos))
+        .collect()
+}
+
+fn update_targeting(
+    keys: Res<Input<KeyCode>>,
+    hovered_tile: Res<HoveredTile>,
+    player_q: Query<&Transform, With<MainPlayer>>,
+    map_frame: Res<MapFrame>,
+    collision_map: Res<CollisionMap>,
+    mut preview: ResMut<TargetingPreview>,
+    mut confirmed: EventWriter<TargetConfirmed>,
+) {
+    if keys.just_released(TARGETING_KEY) {
+        let path = std::mem::take(&mut preview.path);
+        if let Some(end) = path.last().copied() {
+            confirmed.send(TargetConfirmed { path, end });
+        }
+        return;
+    }
+    if !keys.pressed(TARGETING_KEY) {
+        return;
+    }
+
+    let player_tile = player_q
+        .get_single()
+        .ok()
+        .and_then(|transform| coords::world_to_tile(&map_frame, transform.translation.truncate()));
+    // keep the last preview while the cursor is off the map
+    let (Some(player_tile), Some(target)) = (player_tile, hovered_tile.0) else {
+        return;
+    };
+    let path = targeting_path(player_tile, target, |pos| collision_map.is_blocked(pos));
+    if preview.path != path {
+        preview.path = path;
+    }
+}
+
+fn draw_tile_highlights(
+    mut commands: Commands,
+    preview: Res<TargetingPreview>,
+    map_frame: Res<MapFrame>,
+    palette: Res<OverlayPalette>,
+    mut pool: ResMut<HighlightPool>,
+    mut highlights: Query<(&mut Transform, &mut Visibility), With<TileHighlight>>,
+) {
+    if !preview.is_changed() {
+        return;
+    }
+
+    // forget the highlights despawned with the level they were drawn on
+    pool.entities.retain(|entity| highlights.contains(*entity));
+    let centers = preview
+        .path
+        .iter()
+        .filter_map(|pos| coords::tile_to_world_center(&map_frame, pos));
+    let mut used = 0;
+    for center in centers {
+        let translation = center.extend(HIGHLIGHT_Z);
+        match pool.entities.get(used).copied() {
+            Some(entity) => {
+                if let Ok((mut transform, mut visibility)) = highlights.get_mut(entity) {
+                    transform.translation = translation;
+                    *visibility = Visibility::Visible;
+                }
+            }
+            // the line is longer than ever before, grow the pool
+            None => {
+                let entity = commands
+                    .spawn((
+                        SpriteBundle {
+                            sprite: Sprite {
+                                color: palette
+                                    .color_with_alpha(OverlayRole::Danger, HIGHLIGHT_ALPHA),
+                                custom_size: Some(Vec2::new(
+                                    map_frame.grid_size.x,
+                                    map_frame.grid_size.y,
+                                )),
+                                ..default()
+                            },
+                            transform: Transform::from_translation(translation),
+                            ..default()
+                        },
+                        TileHighlight,
+                        ExpireOnStateExit(AppState::Level),
+                        Name::new("tile highlight"),
+                    ))
+                    .id();
+                pool.entities.push(entity);
+            }
+        }
+        used += 1;
+    }
+
+    for entity in &pool.entities[used.min(pool.entities.len())..] {
+        if let Ok((_, mut visibility)) = highlights.get_mut(*entity) {
+            *visibility = Visibility::Hidden;
+        }
+    }
+}
+
+/// Give the highlights already spawned the color of the new palette
+fn retint_highlights(
+    palette: Res<OverlayPalette>,
+    mut palette_changes: EventReader<PaletteChanged>,
+    mut highlights: Query<&mut Sprite, With<TileHighlight>>,
+) {
+    if palette_changes.read().count() == 0 {
+        return;
+    }
+    let color = palette.color_with_alpha(OverlayRole::Danger, HIGHLIGHT_ALPHA);
+    for mut sprite in &mut highlights {
+        sprite.color = color;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tiles(coords: &[(u32, u32)]) -> Vec<TilePos> {
+        coords.iter().map(|&(x, y)| TilePos::new(x, y)).collect()
+    }
+
+    #[test]
+    fn line_to_same_tile() {
+        assert_eq!(
+            tile_line(TilePos::new(2, 2), TilePos::new(2, 2)),
+            tiles(&[(2, 2)])
+        );
+    }
+
+    #[test]
+    fn straight_and_diagonal_lines() {
+        assert_eq!(
+            tile_line(TilePos::new(0, 1), TilePos::new(3, 1)),
+            tiles(&[(0, 1), (1, 1), (2, 1), (3, 1)])
+        );
+        assert_eq!(
+            tile_line(TilePos::new(1, 3), TilePos::new(1, 0)),
+            tiles(&[(1, 3), (1, 2), (1, 1), (1, 0)])
+        );
+        assert_eq!(
+            tile_line(TilePos::new(3, 0), TilePos::new(0, 3)),
+            tiles(&[(3, 0), (2, 1), (1, 2), (0, 3)])
+        );
+    }
+
+    #[test]
+    fn shallow_line_is_connected() {
+        let line = tile_line(TilePos::new(0, 0), TilePos::new(5, 2));
+        assert_eq!(
+            line,
+            tiles(&[(0, 0), (1, 0), (2, 1), (3, 1), (4, 2), (5, 2)])
+        );
+        for pair in line.windows(2) {
+            assert_eq!(coords::tile_distance(&pair[0], &pair[1]), 1);
+        }
+    }
+
+    #[test]
+    fn line_is_symmetric_in_length() {
+        for (from, to) in [((0, 0), (7, 3)), ((6, 1), (0, 4)), ((2, 9), (4, 0))] {
+            let from = TilePos::new(from.0, from.1);
+            let to = TilePos::new(to.0, to.1);
+            assert_eq!(tile_line(from, to).len(), tile_line(to, from).len());
+        }
+    }
+
+    #[test]
+    fn path_stops_before_first_blocked_tile() {
+        let wall = TilePos::new(3, 1);
+        let path = targeting_path(TilePos::new(0, 1), TilePos::new(5, 1), |pos| *pos == wall);
+        assert_eq!(path, tiles(&[(1, 1), (2, 1)]));
+
+        let open = targeting_path(TilePos::new(0, 1), TilePos::new(2, 1), |_| false);
+        assert_eq!(open, tiles(&[(1, 1), (2, 1)]));
+    }
+
+    #[test]
+    fn palette_changes_retint_the_spawned_highlights() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<OverlayPalette>()
+            .add_event::<PaletteChanged>()
+            .add_systems(Update, retint_highlights);
+        let highlight = app
+            .world
+            .spawn((
+                Sprite {
+                    color: OverlayPalette::default()
+                        .color_with_alpha(OverlayRole::Danger, HIGHLIGHT_ALPHA),
+                    ..default()
+                },
+                TileHighlight,
+            ))
+            .id();
+
+        let palette = OverlayPalette {
+            kind: crate::palette::PaletteKind::DeuteranopiaSafe,
+        };
+        app.insert_resource(palette);
+        app.world.send_event(PaletteChanged);
+        app.update();
+        assert_eq!(
+            app.world.get::<Sprite>(highlight).unwrap().color,
+            palette.color_with_alpha(OverlayRole::Danger, HIGHLIGHT_ALPHA)
+        );
+    }
+}
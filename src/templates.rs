@@ -0,0 +1,246 @@
+//! Entity templates: named creature specs in `*.templates.ron` files, so creatures can be tuned
+//! without touching the code or every object of the map.
+//!
+//! A creature object names its template in its `template` property. The template fields act as
+//! default properties of the object, the object's own properties override them. The files enable
+//! `implicit_some` so that the fields are written without `Some(..)`.
+//!
+//! ```ron
+//! #![enable(implicit_some)]
+//! {
+//!     "bat": (creature_row: 3, health: 4),
+//!     "guard": (creature_row: 7, health: 20, dialogue: "Halt!"),
+//!     "troll": (creature_row: 9, health: 60, health_bar_width: 30, health_bar_offset: 24),
+//!     "giant": (creature_row: 9, sprite_scale_mode: Native),
+//! }
+//! ```
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_common_assets::ron::RonAssetPlugin;
+use serde::Deserialize;
+
+use crate::animation;
+use crate::game::GameSet;
+use crate::hostile::AGGRO_RADIUS_PROPERTY;
+use crate::map::GameInfoAlt;
+use crate::sprite_atlas::CurrentCreatureAtlas;
+use crate::sprite_scale::{SpriteScaleMode, SCALE_MODE_PROPERTY};
+use crate::toast::Toasts;
+
+/// Plugin that loads the `*.templates.ron` files and checks them against the creature atlas
+#[derive(Default)]
+pub struct TemplatePlugin;
+
+impl Plugin for TemplatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(RonAssetPlugin::<EntityTemplates>::new(&["templates.ron"]))
+            .add_systems(Update, check_templates.in_set(GameSet::RenderPrep));
+    }
+}
+
+/// The templates of a file by name
+#[derive(Asset, TypePath, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(transparent)]
+pub struct EntityTemplates(pub HashMap<String, EntityTemplate>);
+
+/// Default properties of the objects using the template, fields left out are not set
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct EntityTemplate {
+    /// Row of the creature atlas holding the animation
+    pub creature_row: Option<u32>,
+    /// Index in the creature atlas of a still sprite, replaces the animation
+    pub atlas_index: Option<u32>,
+    pub health: Option<i32>,
+    pub max_health: Option<i32>,
+    /// Pages separated by `|`, like the `dialogue` property
+    pub dialogue: Option<String>,
+    /// Width of the health bar, for creatures bigger than a tile
+    pub health_bar_width: Option<f32>,
+    /// Height of the health bar over the creature's center
+    pub health_bar_offset: Option<f32>,
+    /// How the sprite is sized against the map's tiles, see `sprite_scale`
+    pub sprite_scale_mode: Option<SpriteScaleMode>,
+    /// Tiles within which the creature chases the player, see `hostile`
+    pub aggro_radius: Option<u32>,
+}
+
+impl EntityTemplate {
+    /// The template as Tiled properties
+    pub fn properties(&self) -> tiled::Properties {
+        let ints = [
+            ("creature_row", self.creature_row.map(|row| row as i32)),
+            ("atlas_index", self.atlas_index.map(|index| index as i32)),
+            ("health", self.health),
+            ("max_health", self.max_health),
+            (
+                AGGRO_RADIUS_PROPERTY,
+                self.aggro_radius.map(|radius| radius as i32),
+            ),
+        ];
+        let mut properties: tiled::Properties = ints
+            .into_iter()
+            .filter_map(|(name, value)| {
+                Some((name.to_string(), tiled::PropertyValue::IntValue(value?)))
+            })
+            .collect();
+        let floats = [
+            ("health_bar_width", self.health_bar_width),
+            ("health_bar_offset", self.health_bar_offset),
+        ];
+        properties.extend(floats.into_iter().filter_map(|(name, value)| {
+            Some((name.to_string(), tiled::PropertyValue::FloatValue(value?)))
+        }));
+        if let Some(dialogue) = &self.dialogue {
+            properties.insert(
+                "dialogue".to_string(),
+                tiled::PropertyValue::StringValue(dialogue.clone()),
+            );
+        }
+        if let Some(mode) = self.sprite_scale_mode {
+            properties.insert(
+                SCALE_MODE_PROPERTY.to_string(),
+                tiled::PropertyValue::StringValue(mode.name().to_string()),
+            );
+        }
+        properties
+    }
+}
+
+impl EntityTemplates {
+    /// What's wrong with the templates when drawn from `atlas`
+    pub fn problems(&self, atlas: &TextureAtlas) -> Vec<String> {
+        let (_, rows) = animation::atlas_grid(atlas).unwrap_or((0, 0));
+        let mut problems = Vec::new();
+        let mut names: Vec<_> = self.0.keys().collect();
+        names.sort();
+        for name in names {
+            let template = &self.0[name];
+            if let Some(row) = template.creature_row {
+                if row as usize >= rows {
+                    problems.push(format!(
+                        "template {name} has creature_row {row}, the atlas has {rows} rows"
+                    ));
+                }
+            }
+            if let Some(index) = template.atlas_index {
+                if index as usize >= atlas.len() {
+                    problems.push(format!(
+                        "template {name} has atlas_index {index}, the atlas has {} sprites",
+                        atlas.len()
+                    ));
+                }
+            }
+        }
+        problems
+    }
+}
+
+/// The properties of `object`: those of its template, overridden by its own
+///
+/// Unknown templates are reported and ignored, as are templates while none are loaded.
+pub fn object_properties(
+    templates: Option<&EntityTemplates>,
+    object: &tiled::Object,
+) -> tiled::Properties {
+    let Some(tiled::PropertyValue::StringValue(name)) = object.properties.get("template") else {
+        return object.properties.clone();
+    };
+    let Some(template) = templates.and_then(|templates| templates.0.get(name)) else {
+        warn!("{} uses template {name}, which doesn't exist", object.name);
+        return object.properties.clone();
+    };
+    let mut properties = template.properties();
+    properties.extend(object.properties.clone());
+    properties
+}
+
+/// Check the templates once the creature atlas is there, and again when the file or the atlas
+/// of the map changes
+fn check_templates(
+    mut events: EventReader<AssetEvent<EntityTemplates>>,
+    game_info: Option<Res<GameInfoAlt>>,
+    creature_atlas: Option<Res<CurrentCreatureAtlas>>,
+    templates: Res<Assets<EntityTemplates>>,
+    atlases: Res<Assets<TextureAtlas>>,
+    mut toasts: Toasts,
+) {
+    let changed = events.read().any(|event| {
+        matches!(
+            event,
+            AssetEvent::Added { .. } | AssetEvent::Modified { .. }
+        )
+    });
+    let (Some(game_info), Some(creature_atlas)) = (game_info, creature_atlas) else {
+        return;
+    };
+    if !changed && !creature_atlas.is_changed() {
+        return;
+    }
+    let Some(atlas) = atlases.get(&creature_atlas.0) else {
+        return;
+    };
+    let Some(templates) = templates.get(&game_info.templates) else {
+        return;
+    };
+    let problems = templates.problems(atlas);
+    for problem in &problems {
+        warn!("{problem}");
+    }
+    if !problems.is_empty() {
+        toasts.warn(format!(
+            "The entity templates have {} problem(s), see the log",
+            problems.len()
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEMPLATES: &str = r#"#![enable(implicit_some)]
+    {
+        "bat": (creature_row: 3, health: 4, aggro_radius: 5),
+        "statue": (atlas_index: 45),
+        "giant": (creature_row: 9, sprite_scale_mode: FitTileHeight),
+        "ghost": (creature_row: 99, atlas_index: 9999),
+    }"#;
+
+    #[test]
+    fn templates_become_properties() {
+        let templates: EntityTemplates = ron::from_str(TEMPLATES).unwrap();
+
+        assert_eq!(
+            templates.0["bat"].properties(),
+            tiled::Properties::from([
+                (
+                    "creature_row".to_string(),
+                    tiled::PropertyValue::IntValue(3)
+                ),
+                ("health".to_string(), tiled::PropertyValue::IntValue(4)),
+                ("aggro_radius".to_string(), tiled::PropertyValue::IntValue(5)),
+            ])
+        );
+        assert_eq!(
+            SpriteScaleMode::from_properties("giant", &templates.0["giant"].properties()),
+            Some(SpriteScaleMode::FitTileHeight)
+        );
+    }
+
+    #[test]
+    fn sprites_outside_the_atlas_are_problems() {
+        let templates: EntityTemplates = ron::from_str(TEMPLATES).unwrap();
+        let atlas =
+            TextureAtlas::from_grid(Handle::default(), Vec2::splat(24.), 20, 27, None, None);
+
+        assert_eq!(
+            templates.problems(&atlas),
+            [
+                "template ghost has creature_row 99, the atlas has 27 rows",
+                "template ghost has atlas_index 9999, the atlas has 540 sprites",
+            ]
+        );
+    }
+}
@@ -0,0 +1,711 @@
+//! Terrain brush: picks the tile for each painted cell from the tileset's Wang sets, so borders
+//! between terrains line up the way Tiled's terrain tool would draw them.
+//!
+//! Cells are labelled with a Wang color, 0 meaning unlabelled. A cell's edges and corners take
+//! its own color while every cell sharing them has the same color (unlabelled cells and the map
+//! edge count as the same), otherwise they take the color of the differing neighbour. The tile
+//! whose Wang id matches the most of those slots is used.
+//!
+//! With "Paint terrain" checked in the editor, the select button paints the color picked in the
+//! "Terrain" window on the picked layer, and shift erases. Dragging paints every tile crossed, the
+//! hovered tile is tinted, and the tiles go through `TileWriter` so the collision map follows.
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_ecs_tilemap::prelude::*;
+use bevy_inspector_egui::bevy_egui::EguiContext;
+use bevy_window::PrimaryWindow;
+
+use crate::camera::{CameraMode, EguiWantsFocus, MainCamera};
+use crate::coords::{self, MapFrame};
+use crate::cursor::CursorWorldCache;
+use crate::editor::editing_terrain;
+use crate::game::GameSet;
+use crate::helpers;
+use crate::helpers::tiled::TiledLayer;
+use crate::input_map::MouseInputMap;
+use crate::map::GameInfoAlt;
+use crate::pathfinding;
+use crate::state::{level_ready, AppState};
+use crate::tile_writer::{TileWriteError, TileWriter};
+
+/// Tint of the hovered tile while painting
+const PREVIEW_TINT: Color = Color::rgb(1., 0.8, 0.5);
+
+/// Plugin that loads the `WangSets` of the current map
+#[derive(Default)]
+pub struct TerrainPlugin;
+
+impl Plugin for TerrainPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WangSets>()
+            .init_resource::<TerrainBrush>()
+            .init_resource::<TerrainPainter>()
+            .add_systems(OnExit(AppState::Loading), load_wang_sets)
+            .add_systems(OnExit(AppState::SwitchingMap), load_wang_sets)
+            .add_systems(
+                Update,
+                paint_terrain
+                    .in_set(GameSet::Cursor)
+                    .run_if(level_ready)
+                    .run_if(editing_terrain),
+            )
+            .add_systems(
+                Update,
+                stop_painting
+                    .in_set(GameSet::Cursor)
+                    .run_if(level_ready)
+                    .run_if(not(editing_terrain)),
+            )
+            .add_systems(
+                Update,
+                terrain_ui
+                    .in_set(GameSet::RenderPrep)
+                    .run_if(level_ready)
+                    .run_if(editing_terrain),
+            );
+    }
+}
+
+// index of each slot in a Wang id, clockwise from the top as in Tiled
+const TOP: usize = 0;
+const TOP_RIGHT: usize = 1;
+const RIGHT: usize = 2;
+const BOTTOM_RIGHT: usize = 3;
+const BOTTOM: usize = 4;
+const BOTTOM_LEFT: usize = 5;
+const LEFT: usize = 6;
+const TOP_LEFT: usize = 7;
+
+/// Tile offset of the neighbour across each slot, y pointing up
+const SLOT_OFFSETS: [(i64, i64); 8] = [
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WangKind {
+    Corner,
+    Edge,
+    Mixed,
+}
+
+impl WangKind {
+    /// Whether the slot is used by sets of this kind
+    fn uses_slot(self, slot: usize) -> bool {
+        let corner = slot % 2 == 1;
+        match self {
+            WangKind::Corner => corner,
+            WangKind::Edge => !corner,
+            WangKind::Mixed => true,
+        }
+    }
+}
+
+/// A Wang set of one tileset: the colors of each slot of its tiles
+#[derive(Debug, Clone)]
+pub struct WangSet {
+    pub name: String,
+    /// Index of the tileset in the map
+    pub tileset: usize,
+    pub kind: WangKind,
+    /// Tile ids and their Wang ids, sorted by tile id
+    pub tiles: Vec<(u32, [u8; 8])>,
+}
+
+impl WangSet {
+    /// The tile whose Wang id matches the most slots of `wang_id`, the lowest id on ties
+    ///
+    /// Slots the set's kind doesn't use are ignored. `None` when the set has no tiles.
+    pub fn best_tile(&self, wang_id: &[u8; 8]) -> Option<u32> {
+        let mut best: Option<(usize, u32)> = None;
+        for (tile, tile_wang_id) in &self.tiles {
+            let score = (0..8)
+                .filter(|slot| self.kind.uses_slot(*slot) && tile_wang_id[*slot] == wang_id[*slot])
+                .count();
+            if best.is_none_or(|(best_score, _)| score > best_score) {
+                best = Some((score, *tile));
+            }
+        }
+        best.map(|(_, tile)| tile)
+    }
+}
+
+/// The Wang sets of every tileset of the current map
+#[derive(Resource, Default, Debug)]
+pub struct WangSets(pub Vec<WangSet>);
+
+/// Terrain painted by the editor, `None` until one is picked
+#[derive(Resource, Default, Debug)]
+pub struct TerrainBrush(pub Option<TerrainBrushSelection>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TerrainBrushSelection {
+    /// Index into `WangSets`
+    pub set: usize,
+    pub color: u8,
+    /// Tiled name of the painted layer
+    pub layer: String,
+}
+
+/// Wang color label of every cell of a layer
+#[derive(Debug, Clone)]
+pub struct TerrainGrid {
+    size: TilemapSize,
+    labels: Vec<u8>,
+}
+
+impl TerrainGrid {
+    pub fn new(size: TilemapSize) -> Self {
+        Self {
+            size,
+            labels: vec![0; size.count()],
+        }
+    }
+
+    /// Label of `pos`, 0 when unlabelled or outside the grid
+    pub fn label(&self, pos: &TilePos) -> u8 {
+        if !pos.within_map_bounds(&self.size) {
+            return 0;
+        }
+        self.labels[pos.to_index(&self.size)]
+    }
+
+    pub fn set_label(&mut self, pos: &TilePos, color: u8) {
+        if pos.within_map_bounds(&self.size) {
+            let index = pos.to_index(&self.size);
+            self.labels[index] = color;
+        }
+    }
+
+    /// Label of the cell at `offset` from `pos`, 0 outside the grid
+    fn label_at(&self, pos: &TilePos, (dx, dy): (i64, i64)) -> u8 {
+        let (x, y) = (pos.x as i64 + dx, pos.y as i64 + dy);
+        if x < 0 || y < 0 {
+            return 0;
+        }
+        self.label(&TilePos::new(x as u32, y as u32))
+    }
+
+    /// Wang id a labelled cell needs to fit its neighbours
+    pub fn wang_id(&self, pos: &TilePos) -> [u8; 8] {
+        let own = self.label(pos);
+        let differs = |slot: usize| {
+            let label = self.label_at(pos, SLOT_OFFSETS[slot]);
+            (label != 0 && label != own).then_some(label)
+        };
+        let mut wang_id = [own; 8];
+        for slot in [TOP, RIGHT, BOTTOM, LEFT] {
+            if let Some(label) = differs(slot) {
+                wang_id[slot] = label;
+            }
+        }
+        // corners are shared with the diagonal cell and the two edge cells next to it
+        for slot in [TOP_RIGHT, BOTTOM_RIGHT, BOTTOM_LEFT, TOP_LEFT] {
+            let sharing = [slot, (slot + 7) % 8, (slot + 1) % 8];
+            if let Some(label) = sharing.into_iter().find_map(differs) {
+                wang_id[slot] = label;
+            }
+        }
+        wang_id
+    }
+
+    /// Paint `pos` with `color` and pick new tiles for it and its labelled neighbours
+    ///
+    /// Returns the tiles to set, cells whose best tile can't be found are left out.
+    pub fn paint(&mut self, set: &WangSet, pos: TilePos, color: u8) -> Vec<(TilePos, u32)> {
+        self.set_label(&pos, color);
+        std::iter::once(pos)
+            .chain(pathfinding::neighbours(&pos, &self.size))
+            .filter(|cell| self.label(cell) != 0)
+            .filter_map(|cell| set.best_tile(&self.wang_id(&cell)).map(|tile| (cell, tile)))
+            .collect()
+    }
+}
+
+/// A drag of the select button, painting every tile it crosses
+#[derive(Debug, Clone, Copy)]
+struct Stroke {
+    /// The camera mode to go back to, the camera is frozen while painting
+    camera_mode: CameraMode,
+    last: Option<TilePos>,
+}
+
+/// The hovered tile tinted by the painter
+#[derive(Debug, Clone)]
+struct Preview {
+    layer: String,
+    pos: TilePos,
+    /// The color to give it back
+    color: Color,
+}
+
+#[derive(Resource, Debug, Default)]
+struct TerrainPainter {
+    /// Labels painted so far, by layer and Wang set
+    grids: HashMap<(String, usize), TerrainGrid>,
+    stroke: Option<Stroke>,
+    preview: Option<Preview>,
+}
+
+impl TerrainPainter {
+    fn clear_preview(&mut self, writer: &mut TileWriter) {
+        if let Some(preview) = self.preview.take() {
+            // the tile may have been erased since
+            let _ = writer.tint_tile(&preview.layer, preview.pos, preview.color);
+        }
+    }
+}
+
+fn load_wang_sets(
+    mut commands: Commands,
+    game_info: Res<GameInfoAlt>,
+    tile_maps: Res<Assets<helpers::tiled::TiledMap>>,
+) {
+    commands.insert_resource(TerrainBrush::default());
+    commands.insert_resource(TerrainPainter::default());
+    let Some(map) = tile_maps.get(&game_info.tile_map) else {
+        warn!("can't find tile map for terrain setup!");
+        return;
+    };
+    commands.insert_resource(helpers::tiled::wang_sets(&map.map));
+}
+
+/// Paint `pos` of `layer` with `color`, 0 erasing it, and write the tiles picked for it and its
+/// neighbours
+fn paint_cell(
+    writer: &mut TileWriter,
+    grid: &mut TerrainGrid,
+    set: &WangSet,
+    layer: &str,
+    pos: TilePos,
+    color: u8,
+) -> Result<(), TileWriteError> {
+    for (cell, tile) in grid.paint(set, pos, color) {
+        writer.set_tile(layer, cell, tile)?;
+    }
+    if color == 0 {
+        writer.remove_tile(layer, pos)?;
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn paint_terrain(
+    buttons: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    mouse_map: Res<MouseInputMap>,
+    egui_focus: Res<EguiWantsFocus>,
+    cursor_cache: Res<CursorWorldCache>,
+    cameras: Query<Entity, With<MainCamera>>,
+    map_frame: Res<MapFrame>,
+    brush: Res<TerrainBrush>,
+    wang_sets: Res<WangSets>,
+    mut camera_mode: ResMut<CameraMode>,
+    mut painter: ResMut<TerrainPainter>,
+    mut writer: TileWriter,
+) {
+    let painter = &mut *painter;
+    let hovered = cameras
+        .get_single()
+        .ok()
+        .and_then(|camera| cursor_cache.get(camera))
+        .and_then(|cursor| coords::world_to_tile(&map_frame, cursor.world_pos));
+    let brush = brush
+        .0
+        .as_ref()
+        .filter(|selection| !selection.layer.is_empty())
+        .and_then(|selection| Some((selection, wang_sets.0.get(selection.set)?)));
+
+    if brush.is_none() || buttons.just_released(mouse_map.select) {
+        if let Some(stroke) = painter.stroke.take() {
+            *camera_mode = stroke.camera_mode;
+        }
+    }
+    let Some((selection, set)) = brush else {
+        painter.clear_preview(&mut writer);
+        return;
+    };
+
+    let previewed = painter
+        .preview
+        .as_ref()
+        .map(|preview| (preview.layer.as_str(), preview.pos));
+    if previewed != hovered.map(|pos| (selection.layer.as_str(), pos)) {
+        painter.clear_preview(&mut writer);
+    }
+    if let (None, Some(pos)) = (&painter.preview, hovered) {
+        // empty cells have nothing to tint
+        if let Ok(Some(tile)) = writer.tile(&selection.layer, pos) {
+            if writer
+                .tint_tile(&selection.layer, pos, PREVIEW_TINT)
+                .is_ok()
+            {
+                painter.preview = Some(Preview {
+                    layer: selection.layer.clone(),
+                    pos,
+                    color: tile.color,
+                });
+            }
+        }
+    }
+
+    if painter.stroke.is_none() && buttons.just_pressed(mouse_map.select) && !**egui_focus {
+        painter.stroke = Some(Stroke {
+            camera_mode: *camera_mode,
+            last: None,
+        });
+        *camera_mode = CameraMode::Frozen;
+    }
+    let Some(stroke) = &mut painter.stroke else {
+        return;
+    };
+    let Some(pos) = hovered.filter(|pos| stroke.last != Some(*pos)) else {
+        return;
+    };
+    stroke.last = Some(pos);
+    let color = if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        0
+    } else {
+        selection.color
+    };
+    let grid = painter
+        .grids
+        .entry((selection.layer.clone(), selection.set))
+        .or_insert_with(|| TerrainGrid::new(map_frame.map_size));
+    if let Err(error) = paint_cell(&mut writer, grid, set, &selection.layer, pos, color) {
+        warn!("can't paint terrain: {error}");
+    }
+}
+
+/// Untint the hovered tile and give the camera back when the painter is turned off
+fn stop_painting(
+    mut camera_mode: ResMut<CameraMode>,
+    mut painter: ResMut<TerrainPainter>,
+    mut writer: TileWriter,
+) {
+    if painter.preview.is_some() {
+        painter.clear_preview(&mut writer);
+    }
+    if let Some(stroke) = painter.stroke.take() {
+        *camera_mode = stroke.camera_mode;
+    }
+}
+
+fn terrain_ui(
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+    wang_sets: Res<WangSets>,
+    layers: Query<(&Name, &TiledLayer)>,
+    mut brush: ResMut<TerrainBrush>,
+) {
+    let Some(mut ctx) = contexts.iter_mut().next() else {
+        return;
+    };
+    egui::Window::new("Terrain")
+        .default_pos(egui::pos2(8., 360.))
+        .resizable(false)
+        .show(ctx.get_mut(), |ui| {
+            if wang_sets.0.is_empty() {
+                ui.label("The tilesets of this map have no Wang sets");
+                return;
+            }
+            let selection = brush.0.get_or_insert_with(|| TerrainBrushSelection {
+                set: 0,
+                color: 1,
+                layer: String::new(),
+            });
+            egui::ComboBox::from_label("Set")
+                .selected_text(&wang_sets.0[selection.set].name)
+                .show_ui(ui, |ui| {
+                    for (index, set) in wang_sets.0.iter().enumerate() {
+                        ui.selectable_value(&mut selection.set, index, &set.name);
+                    }
+                });
+            let set = &wang_sets.0[selection.set];
+            let colors = set
+                .tiles
+                .iter()
+                .flat_map(|(_, wang_id)| wang_id.iter().copied())
+                .max()
+                .unwrap_or(1)
+                .max(1);
+            ui.horizontal(|ui| {
+                ui.label("Color");
+                ui.add(egui::DragValue::new(&mut selection.color).clamp_range(1..=colors));
+            });
+
+            // new tiles go to the first tileset of a layer, it has to be the set's
+            let mut first_tilesets = HashMap::<&str, usize>::new();
+            for (name, layer) in &layers {
+                let first = first_tilesets
+                    .entry(name.as_str())
+                    .or_insert(layer.tileset_index);
+                *first = (*first).min(layer.tileset_index);
+            }
+            let mut names: Vec<_> = first_tilesets
+                .into_iter()
+                .filter(|(_, tileset)| *tileset == set.tileset)
+                .map(|(name, _)| name)
+                .collect();
+            names.sort();
+            if !names.contains(&selection.layer.as_str()) {
+                selection.layer = names
+                    .first()
+                    .map(|name| name.to_string())
+                    .unwrap_or_default();
+            }
+            if names.is_empty() {
+                ui.label("No tile layer uses the tileset of this set");
+                return;
+            }
+            egui::ComboBox::from_label("Layer")
+                .selected_text(&selection.layer)
+                .show_ui(ui, |ui| {
+                    for name in names {
+                        ui.selectable_value(&mut selection.layer, name.to_string(), name);
+                    }
+                });
+            ui.label("Shift erases");
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::helpers::tiled::{load_test_map, process_loaded_maps, TiledMap, TiledMapBundle};
+    use crate::layers::LayerZConfig;
+    use crate::tile_writer::TileWriterPlugin;
+    use crate::toast::Toast;
+
+    /// Grid from rows drawn top to bottom, digits are labels and `.` is unlabelled
+    fn grid(rows: &[&str]) -> TerrainGrid {
+        let size = TilemapSize {
+            x: rows[0].len() as u32,
+            y: rows.len() as u32,
+        };
+        let mut grid = TerrainGrid::new(size);
+        for (row, line) in rows.iter().enumerate() {
+            let y = size.y - 1 - row as u32;
+            for (x, c) in line.chars().enumerate() {
+                grid.set_label(
+                    &TilePos::new(x as u32, y),
+                    c.to_digit(10).unwrap_or(0) as u8,
+                );
+            }
+        }
+        grid
+    }
+
+    /// Every combination of colors 1 and 2 on the used slots, tile ids counting up
+    fn full_set(kind: WangKind) -> WangSet {
+        let mut tiles = Vec::new();
+        for bits in 0..256u32 {
+            let wang_id: [u8; 8] = std::array::from_fn(|slot| {
+                if !kind.uses_slot(slot) {
+                    0
+                } else if bits & (1 << slot) != 0 {
+                    2
+                } else {
+                    1
+                }
+            });
+            if !tiles.iter().any(|(_, existing)| *existing == wang_id) {
+                tiles.push((tiles.len() as u32, wang_id));
+            }
+        }
+        WangSet {
+            name: "test".to_string(),
+            tileset: 0,
+            kind,
+            tiles,
+        }
+    }
+
+    fn wang_id_at(grid: &TerrainGrid, x: u32, y: u32) -> [u8; 8] {
+        grid.wang_id(&TilePos::new(x, y))
+    }
+
+    #[test]
+    fn surrounded_cell_is_uniform() {
+        let grid = grid(&["111", "111", "111"]);
+        assert_eq!(wang_id_at(&grid, 1, 1), [1; 8]);
+    }
+
+    #[test]
+    fn isolated_cell_takes_the_surrounding_color() {
+        let grid = grid(&["111", "121", "111"]);
+        assert_eq!(wang_id_at(&grid, 1, 1), [1; 8]);
+        // the ring around it only borders the isolated cell on one slot or one corner
+        assert_eq!(wang_id_at(&grid, 1, 2), [1, 1, 1, 2, 2, 2, 1, 1]);
+        assert_eq!(wang_id_at(&grid, 0, 0), [1, 2, 1, 1, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn isolated_cell_in_unlabelled_ground_keeps_its_color() {
+        let grid = grid(&["...", ".2.", "..."]);
+        assert_eq!(wang_id_at(&grid, 1, 1), [2; 8]);
+    }
+
+    #[test]
+    fn straight_edge_between_terrains() {
+        let grid = grid(&["222", "222", "111", "111"]);
+        // the row below the border sees color 2 above it
+        assert_eq!(wang_id_at(&grid, 1, 1), [2, 2, 1, 1, 1, 1, 1, 2]);
+        // and the row above sees color 1 below it
+        assert_eq!(wang_id_at(&grid, 1, 2), [2, 2, 2, 1, 1, 1, 2, 2]);
+        // two rows away nothing changes
+        assert_eq!(wang_id_at(&grid, 1, 0), [1; 8]);
+    }
+
+    #[test]
+    fn outer_and_inner_corners() {
+        let grid = grid(&["222", "211", "211"]);
+        // the inner corner cell only touches color 2 diagonally and along two edges
+        assert_eq!(wang_id_at(&grid, 1, 1), [2, 2, 1, 1, 1, 2, 2, 2]);
+        // the bottom row borders color 2 along its left edge
+        assert_eq!(wang_id_at(&grid, 1, 0), [1, 1, 1, 1, 1, 2, 2, 2]);
+        // the corner of the 2 region only touches color 1 diagonally
+        assert_eq!(wang_id_at(&grid, 0, 2), [2, 2, 2, 1, 2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn map_edges_count_as_the_same_terrain() {
+        let grid = grid(&["12", "11"]);
+        assert_eq!(wang_id_at(&grid, 0, 0), [1, 2, 1, 1, 1, 1, 1, 1]);
+        assert_eq!(wang_id_at(&grid, 1, 1), [2, 2, 2, 1, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn best_tile_ignores_unused_slots() {
+        let corner = full_set(WangKind::Corner);
+        assert_eq!(corner.tiles.len(), 16);
+        let wang_id = [9, 2, 9, 1, 9, 1, 9, 1];
+        let tile = corner.best_tile(&wang_id).unwrap();
+        let (_, chosen) = corner.tiles.iter().find(|(id, _)| *id == tile).unwrap();
+        assert_eq!(*chosen, [0, 2, 0, 1, 0, 1, 0, 1]);
+
+        let edge = full_set(WangKind::Edge);
+        let tile = edge.best_tile(&[2, 9, 1, 9, 1, 9, 2, 9]).unwrap();
+        let (_, chosen) = edge.tiles.iter().find(|(id, _)| *id == tile).unwrap();
+        assert_eq!(*chosen, [2, 0, 1, 0, 1, 0, 2, 0]);
+    }
+
+    #[test]
+    fn best_tile_falls_back_to_closest_match() {
+        let set = WangSet {
+            name: "partial".to_string(),
+            tileset: 0,
+            kind: WangKind::Mixed,
+            tiles: vec![(4, [1; 8]), (7, [2; 8]), (9, [2, 2, 2, 2, 1, 1, 1, 1])],
+        };
+        assert_eq!(set.best_tile(&[2, 2, 2, 1, 1, 1, 1, 1]), Some(9));
+        assert_eq!(set.best_tile(&[3; 8]), Some(4));
+        let empty = WangSet {
+            tiles: Vec::new(),
+            ..set
+        };
+        assert_eq!(empty.best_tile(&[1; 8]), None);
+    }
+
+    #[test]
+    fn painting_updates_the_cell_and_its_neighbours() {
+        let set = full_set(WangKind::Mixed);
+        let mut grid = grid(&["111", "111", "111"]);
+        let mut changes = grid.paint(&set, TilePos::new(1, 1), 2);
+        assert_eq!(changes.len(), 9);
+        changes.sort_by_key(|(pos, _)| (pos.y, pos.x));
+
+        let tile_for = |wang_id: [u8; 8]| set.best_tile(&wang_id).unwrap();
+        assert_eq!(changes[4], (TilePos::new(1, 1), tile_for([1; 8])));
+        assert_eq!(
+            changes[7],
+            (TilePos::new(1, 2), tile_for([1, 1, 1, 2, 2, 2, 1, 1]))
+        );
+        assert_eq!(
+            changes[0],
+            (TilePos::new(0, 0), tile_for([1, 2, 1, 1, 1, 1, 1, 1]))
+        );
+    }
+
+    #[test]
+    fn painting_in_a_corner_skips_cells_outside_and_unlabelled() {
+        let set = full_set(WangKind::Corner);
+        let mut grid = grid(&["...", "1..", "11."]);
+        let changes = grid.paint(&set, TilePos::new(0, 0), 2);
+        let mut cells: Vec<_> = changes.iter().map(|(pos, _)| (pos.x, pos.y)).collect();
+        cells.sort();
+        assert_eq!(cells, vec![(0, 0), (0, 1), (1, 0)]);
+    }
+
+    fn map_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugins(TileWriterPlugin)
+            .init_resource::<Assets<TiledMap>>()
+            .init_resource::<LayerZConfig>()
+            .add_event::<AssetEvent<TiledMap>>()
+            .add_event::<Toast>()
+            .add_systems(Update, process_loaded_maps);
+        let map = load_test_map("assets/maps/TMX/map_test_collision.tmx");
+        let tiled_map = app.world.resource_mut::<Assets<TiledMap>>().add(map);
+        app.world.spawn(TiledMapBundle {
+            tiled_map,
+            ..default()
+        });
+        app.update();
+        app
+    }
+
+    /// Paint through `paint_cell` on the ground layer, returning the grid painted
+    fn paint_ground(app: &mut App, grid: &TerrainGrid, pos: TilePos, color: u8) -> TerrainGrid {
+        let grid = grid.clone();
+        app.world.run_system_once(move |mut writer: TileWriter| {
+            let mut grid = grid.clone();
+            let set = full_set(WangKind::Mixed);
+            paint_cell(&mut writer, &mut grid, &set, "ground", pos, color).unwrap();
+            grid
+        })
+    }
+
+    fn ground_texture(app: &mut App, pos: TilePos) -> Option<u32> {
+        let mut layers = app.world.query::<(&Name, &TileStorage)>();
+        let tile = layers
+            .iter(&app.world)
+            .find(|(name, _)| name.as_str() == "ground")
+            .and_then(|(_, storage)| storage.get(&pos))?;
+        Some(app.world.get::<TileTextureIndex>(tile)?.0)
+    }
+
+    #[test]
+    fn painter_writes_the_picked_tiles_and_erases() {
+        let mut app = map_app();
+        let set = full_set(WangKind::Mixed);
+        let (left, right) = (TilePos::new(2, 2), TilePos::new(3, 2));
+        let grid = TerrainGrid::new(TilemapSize { x: 6, y: 5 });
+        let grid = paint_ground(&mut app, &grid, left, 2);
+        let grid = paint_ground(&mut app, &grid, right, 1);
+
+        let tile_for = |wang_id: [u8; 8]| set.best_tile(&wang_id);
+        assert_eq!(
+            ground_texture(&mut app, left),
+            tile_for([2, 1, 1, 1, 2, 2, 2, 2])
+        );
+        assert_eq!(
+            ground_texture(&mut app, right),
+            tile_for([1, 1, 1, 1, 1, 2, 2, 2])
+        );
+
+        // erasing empties the cell and fits the neighbour back to the unlabelled ground
+        paint_ground(&mut app, &grid, right, 0);
+        assert_eq!(ground_texture(&mut app, right), None);
+        assert_eq!(ground_texture(&mut app, left), tile_for([2; 8]));
+    }
+}
@@ -0,0 +1,388 @@
+//! Changing tiles at runtime: `TileWriter` sets, removes and tints the tiles of a tile layer,
+//! found by its Tiled name, and sends a `TileChanged` event for each change so whatever is built
+//! from the tiles can follow.
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use thiserror::Error;
+
+use crate::helpers::tiled::TiledLayer;
+
+/// Plugin that adds the `TileChanged` event written by `TileWriter`
+#[derive(Default)]
+pub struct TileWriterPlugin;
+
+impl Plugin for TileWriterPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<TileChanged>();
+    }
+}
+
+/// What a tile looks like
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileState {
+    pub texture: u32,
+    pub color: Color,
+}
+
+/// A tile was set, removed or tinted through `TileWriter`
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct TileChanged {
+    /// Tiled name of the layer
+    pub layer: String,
+    pub pos: TilePos,
+    /// `None` when the cell was empty
+    pub old: Option<TileState>,
+    /// `None` when the tile was removed
+    pub new: Option<TileState>,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum TileWriteError {
+    #[error("there is no tile layer named {0}")]
+    UnknownLayer(String),
+    #[error("{pos:?} is outside of layer {layer}")]
+    OutOfBounds { layer: String, pos: TilePos },
+    #[error("there is no tile at {pos:?} on layer {layer}")]
+    NoTile { layer: String, pos: TilePos },
+}
+
+/// Sets, removes and tints the tiles of the tile layers
+///
+/// A Tiled layer is drawn by one tilemap per tileset it uses. A tile is changed in the tilemap
+/// already holding it, a new tile goes to the tilemap of the layer's first tileset, and texture
+/// indices are those of that tileset.
+#[derive(SystemParam)]
+pub struct TileWriter<'w, 's> {
+    commands: Commands<'w, 's>,
+    layers: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static Name,
+            &'static TiledLayer,
+            &'static mut TileStorage,
+            &'static Transform,
+        ),
+    >,
+    tiles: Query<'w, 's, (&'static mut TileTextureIndex, &'static mut TileColor)>,
+    changed: EventWriter<'w, TileChanged>,
+}
+
+impl TileWriter<'_, '_> {
+    /// What the tile at `pos` looks like, `None` when the cell is empty
+    pub fn tile(&self, layer: &str, pos: TilePos) -> Result<Option<TileState>, TileWriteError> {
+        let (_, tile) = self.find_cell(layer, pos)?;
+        Ok(tile
+            .and_then(|tile| self.tiles.get(tile).ok())
+            .map(|(texture, color)| state(texture, color)))
+    }
+
+    /// The layer drawing `pos` on top, the one with the highest z having a tile there, and the
+    /// tile
+    pub fn topmost_tile(&self, pos: TilePos) -> Option<(String, TileState)> {
+        self.layers
+            .iter()
+            .filter_map(|(_, name, _, storage, transform)| {
+                let (texture, color) = self.tiles.get(storage.checked_get(&pos)?).ok()?;
+                Some((transform.translation.z, name, state(texture, color)))
+            })
+            .max_by(|(a, ..), (b, ..)| a.total_cmp(b))
+            .map(|(_, name, tile)| (name.to_string(), tile))
+    }
+
+    /// Show `texture` at `pos`, spawning the tile if the cell is empty
+    pub fn set_tile(
+        &mut self,
+        layer: &str,
+        pos: TilePos,
+        texture: u32,
+    ) -> Result<(), TileWriteError> {
+        let (tilemap, tile) = self.find_cell(layer, pos)?;
+        let (old, new) = match tile.and_then(|tile| self.tiles.get_mut(tile).ok()) {
+            Some((mut texture_index, color)) => {
+                let old = state(&texture_index, &color);
+                texture_index.0 = texture;
+                (Some(old), state(&texture_index, &color))
+            }
+            None => {
+                // tiles spawned earlier in the same system are only commands so far, they are
+                // replaced like empty cells
+                if let Some(tile) = tile {
+                    self.commands.entity(tile).despawn_recursive();
+                }
+                let tile = self
+                    .commands
+                    .spawn(TileBundle {
+                        position: pos,
+                        tilemap_id: TilemapId(tilemap),
+                        texture_index: TileTextureIndex(texture),
+                        ..default()
+                    })
+                    .id();
+                self.storage(tilemap).set(&pos, tile);
+                let color = TileColor::default();
+                (None, state(&TileTextureIndex(texture), &color))
+            }
+        };
+        self.send(layer, pos, old, Some(new));
+        Ok(())
+    }
+
+    /// Empty the cell at `pos`, doing nothing if it is empty already
+    pub fn remove_tile(&mut self, layer: &str, pos: TilePos) -> Result<(), TileWriteError> {
+        let (tilemap, tile) = self.find_cell(layer, pos)?;
+        let Some(tile) = tile else {
+            return Ok(());
+        };
+        let old = self
+            .tiles
+            .get(tile)
+            .ok()
+            .map(|(texture, color)| state(texture, color));
+        self.commands.entity(tile).despawn_recursive();
+        self.storage(tilemap).remove(&pos);
+        self.send(layer, pos, old, None);
+        Ok(())
+    }
+
+    /// Tint the tile at `pos` with `color`
+    pub fn tint_tile(
+        &mut self,
+        layer: &str,
+        pos: TilePos,
+        color: Color,
+    ) -> Result<(), TileWriteError> {
+        let (_, tile) = self.find_cell(layer, pos)?;
+        let no_tile = || TileWriteError::NoTile {
+            layer: layer.to_string(),
+            pos,
+        };
+        let tile = tile.ok_or_else(no_tile)?;
+        let (texture, mut tile_color) = self.tiles.get_mut(tile).map_err(|_| no_tile())?;
+        let old = state(&texture, &tile_color);
+        tile_color.0 = color;
+        let new = state(&texture, &tile_color);
+        self.send(layer, pos, Some(old), Some(new));
+        Ok(())
+    }
+
+    /// The tilemap of `layer` where the tile at `pos` is or goes, and the tile if there is one
+    fn find_cell(
+        &self,
+        layer: &str,
+        pos: TilePos,
+    ) -> Result<(Entity, Option<Entity>), TileWriteError> {
+        let mut tilemaps: Vec<_> = self
+            .layers
+            .iter()
+            .filter(|(_, name, _, _, _)| name.as_str() == layer)
+            .collect();
+        tilemaps.sort_by_key(|(_, _, tiled_layer, _, _)| tiled_layer.tileset_index);
+        let Some((first, _, _, storage, _)) = tilemaps.first() else {
+            return Err(TileWriteError::UnknownLayer(layer.to_string()));
+        };
+        if !pos.within_map_bounds(&storage.size) {
+            return Err(TileWriteError::OutOfBounds {
+                layer: layer.to_string(),
+                pos,
+            });
+        }
+        let cell = tilemaps
+            .iter()
+            .find_map(|(tilemap, _, _, storage, _)| Some((*tilemap, Some(storage.get(&pos)?))))
+            .unwrap_or((*first, None));
+        Ok(cell)
+    }
+
+    fn storage(&mut self, tilemap: Entity) -> Mut<'_, TileStorage> {
+        self.layers
+            .get_mut(tilemap)
+            .map(|(_, _, _, storage, _)| storage)
+            .expect("find_cell only returns tilemaps with a storage")
+    }
+
+    fn send(&mut self, layer: &str, pos: TilePos, old: Option<TileState>, new: Option<TileState>) {
+        self.changed.send(TileChanged {
+            layer: layer.to_string(),
+            pos,
+            old,
+            new,
+        });
+    }
+}
+
+fn state(texture: &TileTextureIndex, color: &TileColor) -> TileState {
+    TileState {
+        texture: texture.0,
+        color: color.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::helpers::tiled::{load_test_map, process_loaded_maps, TiledMap, TiledMapBundle};
+    use crate::layers::LayerZConfig;
+    use crate::toast::Toast;
+
+    /// The collision layer of the fixture has an empty cell at the top left
+    const EMPTY_CELL: TilePos = TilePos { x: 0, y: 4 };
+    /// and texture 4 at the top right
+    const FILLED_CELL: TilePos = TilePos { x: 5, y: 4 };
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugins(TileWriterPlugin)
+            .init_resource::<Assets<TiledMap>>()
+            .init_resource::<LayerZConfig>()
+            .add_event::<AssetEvent<TiledMap>>()
+            .add_event::<Toast>()
+            .add_systems(Update, process_loaded_maps);
+        let map = load_test_map("assets/maps/TMX/map_test_collision.tmx");
+        let tiled_map = app.world.resource_mut::<Assets<TiledMap>>().add(map);
+        app.world.spawn(TiledMapBundle {
+            tiled_map,
+            ..default()
+        });
+        app.update();
+        app
+    }
+
+    fn write(
+        app: &mut App,
+        edit: impl Fn(&mut TileWriter) -> Result<(), TileWriteError> + Send + Sync + 'static,
+    ) -> Result<(), TileWriteError> {
+        app.world
+            .run_system_once(move |mut writer: TileWriter| edit(&mut writer))
+    }
+
+    /// The tile entity at `pos` on the collision layer, with its texture
+    fn collision_tile(app: &mut App, pos: TilePos) -> Option<(Entity, u32)> {
+        let mut layers = app.world.query::<(&Name, &TileStorage)>();
+        let tile = layers
+            .iter(&app.world)
+            .find(|(name, _)| name.as_str() == "collision")
+            .and_then(|(_, storage)| storage.get(&pos))?;
+        let texture = app.world.get::<TileTextureIndex>(tile)?;
+        Some((tile, texture.0))
+    }
+
+    fn changes(app: &App) -> Vec<TileChanged> {
+        let events = app.world.resource::<Events<TileChanged>>();
+        events.get_reader().read(events).cloned().collect()
+    }
+
+    fn tile(texture: u32) -> Option<TileState> {
+        Some(TileState {
+            texture,
+            color: Color::WHITE,
+        })
+    }
+
+    #[test]
+    fn set_remove_and_set_again() {
+        let mut app = test_app();
+        assert_eq!(collision_tile(&mut app, EMPTY_CELL), None);
+
+        write(&mut app, |writer| {
+            writer.set_tile("collision", EMPTY_CELL, 7)
+        })
+        .unwrap();
+        let (spawned, texture) = collision_tile(&mut app, EMPTY_CELL).unwrap();
+        assert_eq!(texture, 7);
+
+        write(&mut app, |writer| {
+            writer.remove_tile("collision", EMPTY_CELL)
+        })
+        .unwrap();
+        assert_eq!(collision_tile(&mut app, EMPTY_CELL), None);
+        assert!(app.world.get_entity(spawned).is_none());
+
+        write(&mut app, |writer| {
+            writer.set_tile("collision", EMPTY_CELL, 8)
+        })
+        .unwrap();
+        assert_eq!(
+            collision_tile(&mut app, EMPTY_CELL).map(|(_, t)| t),
+            Some(8)
+        );
+
+        let change = |old, new| TileChanged {
+            layer: "collision".to_string(),
+            pos: EMPTY_CELL,
+            old,
+            new,
+        };
+        assert_eq!(
+            changes(&app),
+            [
+                change(None, tile(7)),
+                change(tile(7), None),
+                change(None, tile(8)),
+            ]
+        );
+    }
+
+    #[test]
+    fn existing_tiles_change_in_place() {
+        let mut app = test_app();
+        let (original, texture) = collision_tile(&mut app, FILLED_CELL).unwrap();
+        assert_eq!(texture, 4);
+
+        write(&mut app, |writer| {
+            writer.set_tile("collision", FILLED_CELL, 9)
+        })
+        .unwrap();
+        write(&mut app, |writer| {
+            writer.tint_tile("collision", FILLED_CELL, Color::RED)
+        })
+        .unwrap();
+
+        assert_eq!(collision_tile(&mut app, FILLED_CELL), Some((original, 9)));
+        assert_eq!(app.world.get::<TileColor>(original).unwrap().0, Color::RED);
+        let tinted = TileState {
+            texture: 9,
+            color: Color::RED,
+        };
+        let states: Vec<_> = changes(&app).into_iter().map(|c| (c.old, c.new)).collect();
+        assert_eq!(states, [(tile(4), tile(9)), (tile(9), Some(tinted))]);
+    }
+
+    #[test]
+    fn bad_layers_and_positions_are_errors() {
+        let mut app = test_app();
+
+        assert_eq!(
+            write(&mut app, |writer| {
+                writer.set_tile("colision", EMPTY_CELL, 1)
+            }),
+            Err(TileWriteError::UnknownLayer("colision".to_string()))
+        );
+        let outside = TilePos { x: 6, y: 0 };
+        assert_eq!(
+            write(&mut app, move |writer| {
+                writer.remove_tile("collision", outside)
+            }),
+            Err(TileWriteError::OutOfBounds {
+                layer: "collision".to_string(),
+                pos: outside,
+            })
+        );
+        assert_eq!(
+            write(&mut app, |writer| {
+                writer.tint_tile("collision", EMPTY_CELL, Color::RED)
+            }),
+            Err(TileWriteError::NoTile {
+                layer: "collision".to_string(),
+                pos: EMPTY_CELL,
+            })
+        );
+        assert!(changes(&app).is_empty());
+    }
+}
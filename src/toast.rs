@@ -0,0 +1,253 @@
+//! Short-lived notifications stacked in the bottom-right corner of the screen.
+//!
+//! Send them with the `Toasts` system param, e.g. `toasts.info("Map reloaded")`, or as plain
+//! `Toast` events.
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::EguiContext;
+use bevy_window::PrimaryWindow;
+
+use crate::auto_hide::{overlay_alpha, AutoHide, Overlay};
+use crate::game::GameSet;
+
+/// How long a toast stays up unless told otherwise
+pub const DEFAULT_TOAST_SECS: f32 = 3.;
+/// Errors stay up longer, they usually need reading twice
+pub const ERROR_TOAST_SECS: f32 = 6.;
+/// Most toasts shown at once, the oldest are hidden first
+const MAX_VISIBLE_TOASTS: usize = 5;
+/// Toasts fade out during their last seconds
+const FADE_SECS: f32 = 0.5;
+
+/// Plugin that queues `Toast` events and draws them, in every app state
+#[derive(Default)]
+pub struct ToastPlugin;
+
+impl Plugin for ToastPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Toast>()
+            .init_resource::<ToastQueue>()
+            .add_systems(
+                Update,
+                (queue_toasts, show_toasts)
+                    .chain()
+                    .in_set(GameSet::RenderPrep),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToastLevel {
+    Info,
+    Warn,
+    Error,
+}
+
+impl ToastLevel {
+    fn color(self) -> egui::Color32 {
+        match self {
+            ToastLevel::Info => egui::Color32::from_gray(230),
+            ToastLevel::Warn => egui::Color32::from_rgb(255, 200, 80),
+            ToastLevel::Error => egui::Color32::from_rgb(255, 100, 90),
+        }
+    }
+}
+
+/// A message shown for `duration` seconds
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct Toast {
+    pub text: String,
+    pub duration: f32,
+    pub level: ToastLevel,
+}
+
+impl Toast {
+    pub fn new(level: ToastLevel, text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            duration: DEFAULT_TOAST_SECS,
+            level,
+        }
+    }
+
+    pub fn info(text: impl Into<String>) -> Self {
+        Self::new(ToastLevel::Info, text)
+    }
+
+    pub fn warn(text: impl Into<String>) -> Self {
+        Self::new(ToastLevel::Warn, text)
+    }
+
+    pub fn error(text: impl Into<String>) -> Self {
+        Self::new(ToastLevel::Error, text).with_duration(ERROR_TOAST_SECS)
+    }
+
+    pub fn with_duration(mut self, duration: f32) -> Self {
+        self.duration = duration;
+        self
+    }
+}
+
+/// System param to send toasts from any system
+#[derive(SystemParam)]
+pub struct Toasts<'w> {
+    writer: EventWriter<'w, Toast>,
+}
+
+impl Toasts<'_> {
+    pub fn send(&mut self, toast: Toast) {
+        self.writer.send(toast);
+    }
+
+    pub fn info(&mut self, text: impl Into<String>) {
+        self.send(Toast::info(text));
+    }
+
+    pub fn warn(&mut self, text: impl Into<String>) {
+        self.send(Toast::warn(text));
+    }
+
+    pub fn error(&mut self, text: impl Into<String>) {
+        self.send(Toast::error(text));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ActiveToast {
+    toast: Toast,
+    remaining: f32,
+    /// How many identical toasts were collapsed into this one
+    count: u32,
+}
+
+/// Toasts currently on screen, oldest first
+#[derive(Resource, Default, Debug)]
+pub struct ToastQueue {
+    active: Vec<ActiveToast>,
+}
+
+impl ToastQueue {
+    /// Add a toast, an identical one still on screen is counted and restarted instead
+    pub fn push(&mut self, toast: Toast) {
+        let duplicate = self
+            .active
+            .iter_mut()
+            .find(|active| active.toast.text == toast.text && active.toast.level == toast.level);
+        match duplicate {
+            Some(active) => {
+                active.count += 1;
+                active.remaining = toast.duration;
+                active.toast.duration = toast.duration;
+            }
+            None => self.active.push(ActiveToast {
+                remaining: toast.duration,
+                toast,
+                count: 1,
+            }),
+        }
+    }
+
+    /// Count down the toasts and drop the expired ones
+    pub fn tick(&mut self, delta_secs: f32) {
+        for active in &mut self.active {
+            active.remaining -= delta_secs;
+        }
+        self.active.retain(|active| active.remaining > 0.);
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.active.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.active.is_empty()
+    }
+}
+
+fn queue_toasts(time: Res<Time>, mut toasts: EventReader<Toast>, mut queue: ResMut<ToastQueue>) {
+    if queue.is_empty() && toasts.is_empty() {
+        return;
+    }
+    queue.tick(time.delta_seconds());
+    for toast in toasts.read() {
+        queue.push(toast.clone());
+    }
+}
+
+fn show_toasts(
+    queue: Res<ToastQueue>,
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+    overlays: Query<&AutoHide>,
+) {
+    let alpha = overlay_alpha(&overlays, Overlay::Toasts);
+    if queue.is_empty() || alpha <= 0. {
+        return;
+    }
+    let Some(mut ctx) = contexts.iter_mut().next() else {
+        return;
+    };
+
+    let first_visible = queue.active.len().saturating_sub(MAX_VISIBLE_TOASTS);
+    egui::Area::new(egui::Id::new("toasts"))
+        .order(egui::Order::Foreground)
+        .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-12., -12.))
+        .interactable(false)
+        .show(ctx.get_mut(), |ui| {
+            for active in &queue.active[first_visible..] {
+                let opacity = (active.remaining / FADE_SECS).clamp(0., 1.) * alpha;
+                let text = if active.count > 1 {
+                    format!("{} x{}", active.toast.text, active.count)
+                } else {
+                    active.toast.text.clone()
+                };
+                egui::Frame::popup(ui.style())
+                    .fill(ui.style().visuals.window_fill.gamma_multiply(opacity))
+                    .stroke(egui::Stroke::NONE)
+                    .show(ui, |ui| {
+                        ui.label(
+                            egui::RichText::new(text)
+                                .color(active.toast.level.color().gamma_multiply(opacity)),
+                        );
+                    });
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicates_collapse_into_a_counter() {
+        let mut queue = ToastQueue::default();
+        queue.push(Toast::info("Picked up coin"));
+        queue.tick(2.);
+        queue.push(Toast::info("Picked up coin"));
+        queue.push(Toast::warn("Picked up coin"));
+
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.active[0].count, 2);
+        // the duplicate restarted the countdown
+        assert_eq!(queue.active[0].remaining, DEFAULT_TOAST_SECS);
+        assert_eq!(queue.active[1].count, 1);
+    }
+
+    #[test]
+    fn toasts_expire() {
+        let mut queue = ToastQueue::default();
+        queue.push(Toast::info("short").with_duration(1.));
+        queue.push(Toast::error("long"));
+
+        queue.tick(0.5);
+        assert_eq!(queue.len(), 2);
+        queue.tick(0.5);
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.active[0].toast.text, "long");
+        queue.tick(DEFAULT_TOAST_SECS);
+        assert_eq!(queue.len(), 1);
+        queue.tick(ERROR_TOAST_SECS - DEFAULT_TOAST_SECS);
+        assert!(queue.is_empty());
+    }
+}
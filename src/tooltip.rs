@@ -0,0 +1,184 @@
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use bevy_inspector_egui::bevy_egui::EguiContext;
+use bevy_window::PrimaryWindow;
+
+use crate::camera::MainCamera;
+use crate::coords::{self, MapFrame};
+use crate::cursor::CursorWorldCache;
+use crate::game::GameSet;
+use crate::helpers;
+use crate::helpers::tiled::TileColliders;
+use crate::map::GameInfoAlt;
+use crate::state::level_ready;
+use crate::ui::HoveredTile;
+
+/// How long the cursor has to rest on a tile before the tooltip shows up
+const HOVER_DELAY_SECS: f32 = 0.5;
+
+/// Plugin that shows an egui tooltip describing the hovered tile
+#[derive(Default)]
+pub struct TileTooltipPlugin;
+
+impl Plugin for TileTooltipPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TileTooltip>().add_systems(
+            Update,
+            (update_tile_tooltip, show_tile_tooltip)
+                .chain()
+                .in_set(GameSet::RenderPrep)
+                .run_if(level_ready),
+        );
+    }
+}
+
+/// Everything listed in the tooltip for a single tile
+#[derive(Clone, Default)]
+pub struct TooltipContents {
+    pub tile_pos: TilePos,
+    pub layers: Vec<String>,
+    pub properties: Vec<String>,
+    pub entities: Vec<String>,
+}
+
+/// State of the hover tooltip
+#[derive(Resource, Default)]
+pub struct TileTooltip {
+    /// Cursor position (in window coordinates) the cursor has been resting at
+    rest_pos: Option<Vec2>,
+    /// Seconds the cursor has been resting at `rest_pos`
+    rest_secs: f32,
+    /// When true the tooltip stays put while the cursor moves (Alt is held)
+    pinned: bool,
+    /// Where to draw the tooltip and what to put in it, `None` when hidden
+    shown: Option<(Vec2, TooltipContents)>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_tile_tooltip(
+    time: Res<Time>,
+    keys: Res<Input<KeyCode>>,
+    hovered_tile: Res<HoveredTile>,
+    mut tooltip: ResMut<TileTooltip>,
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+    cursor_cache: Res<CursorWorldCache>,
+    q_camera: Query<Entity, With<MainCamera>>,
+    game_info: Res<GameInfoAlt>,
+    tile_maps: Res<Assets<helpers::tiled::TiledMap>>,
+    tile_colliders: Res<TileColliders>,
+    map_frame: Option<Res<MapFrame>>,
+    named_q: Query<(&Name, &GlobalTransform)>,
+) {
+    tooltip.pinned = keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]);
+    if tooltip.pinned && tooltip.shown.is_some() {
+        return;
+    }
+
+    let egui_wants_pointer = contexts
+        .iter_mut()
+        .next()
+        .map(|mut ctx| ctx.get_mut().wants_pointer_input())
+        .unwrap_or(false);
+    let cursor = q_camera
+        .get_single()
+        .ok()
+        .and_then(|camera| cursor_cache.get(camera))
+        .map(|cursor| cursor.screen_pos);
+
+    let (Some(cursor), Some(tile_pos), false) = (cursor, hovered_tile.0, egui_wants_pointer) else {
+        tooltip.rest_pos = None;
+        tooltip.shown = None;
+        return;
+    };
+
+    // any cursor motion hides the tooltip and restarts the hover delay
+    if tooltip.rest_pos != Some(cursor) {
+        tooltip.rest_pos = Some(cursor);
+        tooltip.rest_secs = 0.;
+        tooltip.shown = None;
+        return;
+    }
+
+    tooltip.rest_secs += time.delta_seconds();
+    if tooltip.rest_secs < HOVER_DELAY_SECS || tooltip.shown.is_some() {
+        return;
+    }
+
+    let mut contents = TooltipContents {
+        tile_pos,
+        ..default()
+    };
+
+    if let Some(map) = tile_maps.get(&game_info.tile_map) {
+        let with_colliders: Vec<_> = tile_colliders.layers_at(tile_pos).collect();
+        for info in helpers::tiled::tiles_at(&map.map, &tile_pos) {
+            let collider = if with_colliders.contains(&info.layer_index) {
+                ", collider"
+            } else {
+                ""
+            };
+            contents.layers.push(format!(
+                "{} ({}): {}{collider}",
+                info.layer_name, info.layer_index, info.texture_index
+            ));
+            for (key, value) in info.properties.iter() {
+                contents.properties.push(format!("{key} = {value:?}"));
+            }
+        }
+    }
+
+    // entities whose position falls within the hovered tile
+    if let Some(map_frame) = map_frame {
+        for (name, transform) in named_q.iter() {
+            if coords::world_to_tile(&map_frame, transform.translation().truncate())
+                == Some(tile_pos)
+            {
+                contents.entities.push(name.as_str().to_string());
+            }
+        }
+    }
+
+    tooltip.shown = Some((cursor, contents));
+}
+
+fn show_tile_tooltip(
+    tooltip: Res<TileTooltip>,
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+) {
+    let Some((pos, contents)) = &tooltip.shown else {
+        return;
+    };
+    let Some(mut ctx) = contexts.iter_mut().next() else {
+        return;
+    };
+
+    egui::Area::new(egui::Id::new("tile_tooltip"))
+        .order(egui::Order::Tooltip)
+        .fixed_pos(egui::pos2(pos.x + 16., pos.y + 16.))
+        .interactable(false)
+        .show(ctx.get_mut(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.label(format!(
+                    "Tile ({}, {}){}",
+                    contents.tile_pos.x,
+                    contents.tile_pos.y,
+                    if tooltip.pinned { " [pinned]" } else { "" }
+                ));
+                for layer in &contents.layers {
+                    ui.label(layer);
+                }
+                if !contents.properties.is_empty() {
+                    ui.separator();
+                    for property in &contents.properties {
+                        ui.label(property);
+                    }
+                }
+                if !contents.entities.is_empty() {
+                    ui.separator();
+                    for entity in &contents.entities {
+                        ui.label(entity);
+                    }
+                }
+            });
+        });
+}
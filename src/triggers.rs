@@ -0,0 +1,602 @@
+//! Trigger zones placed in Tiled as objects of type "trigger".
+//!
+//! A trigger carries a tiny script in its `actions` property, a `;` separated list of:
+//!
+//! * `message:<text>` shows `<text>` in a popup
+//! * `teleport:<x>,<y>` moves the player to the tile `(x, y)`, in Tiled tile coordinates
+//! * `flag:<name>` or just `<name>` sets the game flag `<name>` to true, `flag:<name>=<value>`
+//!   sets it to a bool, int or string value
+//! * `sound:<path>` plays the audio asset at `<path>`
+//! * `camera:<x>,<y>,<scale>,<secs>` eases the camera to the tile `(x, y)` and the zoom `<scale>`
+//!   in `<secs>` seconds, a trailing `,skippable` lets the player cut it short, see `camera_rig`
+//!
+//! The actions run every time the player enters the zone, or only the first time when the
+//! object's `once` property is true. A `requires_flag` property (`name` or `!name`) makes the
+//! trigger only fire while the condition on the game flags is met. Triggers can also be drawn in
+//! editor mode, see `region_editor`.
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use bevy_inspector_egui::bevy_egui::EguiContext;
+use bevy_window::PrimaryWindow;
+use thiserror::Error;
+
+use crate::audio::SpatialAudio;
+use crate::camera_rig::{CameraKeyframe, MoveCamera};
+use crate::collision::CollisionMap;
+use crate::coords::{self, MapFrame};
+use crate::flags::{FlagValue, GameFlags};
+use crate::game::{GameSet, LevelLifecycleSet};
+use crate::helpers;
+use crate::map::GameInfoAlt;
+use crate::player::MainPlayer;
+use crate::region_editor::{MapRegion, RegionKind};
+use crate::state::{level_ready, AppState};
+use crate::toast::Toasts;
+use crate::tween::Ease;
+
+/// Plugin that spawns trigger zones from the map and runs their actions
+#[derive(Default)]
+pub struct TriggerPlugin;
+
+impl Plugin for TriggerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ZoneEntered>()
+            .init_resource::<TriggerMessages>()
+            .add_systems(
+                OnEnter(AppState::Level),
+                spawn_trigger_zones.in_set(LevelLifecycleSet),
+            )
+            .add_systems(
+                OnExit(AppState::Level),
+                despawn_trigger_zones.in_set(LevelLifecycleSet),
+            )
+            .add_systems(
+                Update,
+                (
+                    detect_zone_entries.run_if(crate::debug_step::gameplay_running),
+                    run_trigger_actions,
+                )
+                    .chain()
+                    .in_set(GameSet::Gameplay)
+                    .run_if(level_ready),
+            )
+            .add_systems(
+                Update,
+                show_trigger_messages
+                    .in_set(GameSet::RenderPrep)
+                    .run_if(level_ready),
+            );
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TriggerAction {
+    ShowMessage(String),
+    /// Target tile in Tiled tile coordinates (y down)
+    TeleportPlayer {
+        x: u32,
+        y: u32,
+    },
+    SetFlag {
+        key: String,
+        value: FlagValue,
+    },
+    PlaySound(String),
+    /// Target tile in Tiled tile coordinates (y down)
+    MoveCamera {
+        x: u32,
+        y: u32,
+        scale: f32,
+        secs: f32,
+        skippable: bool,
+    },
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum TriggerParseError {
+    #[error("unknown action `{0}`")]
+    UnknownAction(String),
+    #[error("missing argument in `{0}`")]
+    MissingArgument(String),
+    #[error("bad teleport target in `{0}`, expected `teleport:<x>,<y>`")]
+    BadTeleportTarget(String),
+    #[error("bad camera move in `{0}`, expected `camera:<x>,<y>,<scale>,<secs>[,skippable]`")]
+    BadCameraMove(String),
+}
+
+/// Parse an `actions` script into its list of actions
+pub fn parse_actions(script: &str) -> Result<Vec<TriggerAction>, TriggerParseError> {
+    script
+        .split(';')
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .map(parse_action)
+        .collect()
+}
+
+fn parse_action(token: &str) -> Result<TriggerAction, TriggerParseError> {
+    let Some((name, argument)) = token.split_once(':') else {
+        // a bare word sets the flag of the same name
+        return if token.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            Ok(TriggerAction::SetFlag {
+                key: token.to_string(),
+                value: FlagValue::Bool(true),
+            })
+        } else {
+            Err(TriggerParseError::UnknownAction(token.to_string()))
+        };
+    };
+
+    let argument = argument.trim();
+    if argument.is_empty() {
+        return Err(TriggerParseError::MissingArgument(token.to_string()));
+    }
+
+    match name.trim() {
+        "message" => Ok(TriggerAction::ShowMessage(argument.to_string())),
+        "teleport" => {
+            let bad_target = || TriggerParseError::BadTeleportTarget(token.to_string());
+            let (x, y) = argument.split_once(',').ok_or_else(bad_target)?;
+            let x = x.trim().parse().map_err(|_| bad_target())?;
+            let y = y.trim().parse().map_err(|_| bad_target())?;
+            Ok(TriggerAction::TeleportPlayer { x, y })
+        }
+        "flag" => {
+            let (key, value) = match argument.split_once('=') {
+                Some((key, value)) => (key.trim(), parse_flag_value(value.trim())),
+                None => (argument, FlagValue::Bool(true)),
+            };
+            Ok(TriggerAction::SetFlag {
+                key: key.to_string(),
+                value,
+            })
+        }
+        "sound" => Ok(TriggerAction::PlaySound(argument.to_string())),
+        "camera" => {
+            let bad_move = || TriggerParseError::BadCameraMove(token.to_string());
+            let parts: Vec<_> = argument.split(',').map(str::trim).collect();
+            let (numbers, skippable) = match parts.as_slice() {
+                [numbers @ .., "skippable"] => (numbers, true),
+                numbers => (numbers, false),
+            };
+            let [x, y, scale, secs] = numbers else {
+                return Err(bad_move());
+            };
+            Ok(TriggerAction::MoveCamera {
+                x: x.parse().map_err(|_| bad_move())?,
+                y: y.parse().map_err(|_| bad_move())?,
+                scale: scale.parse().map_err(|_| bad_move())?,
+                secs: secs.parse().map_err(|_| bad_move())?,
+                skippable,
+            })
+        }
+        _ => Err(TriggerParseError::UnknownAction(token.to_string())),
+    }
+}
+
+fn parse_flag_value(value: &str) -> FlagValue {
+    if let Ok(value) = value.parse::<bool>() {
+        FlagValue::Bool(value)
+    } else if let Ok(value) = value.parse::<i32>() {
+        FlagValue::Int(value)
+    } else {
+        FlagValue::String(value.to_string())
+    }
+}
+
+/// An area of the map that notices the player entering it
+#[derive(Component, Debug)]
+pub struct TriggerZone {
+    /// Area covered in world coordinates
+    pub rect: Rect,
+    /// Whether the player was inside the zone last frame
+    occupied: bool,
+}
+
+impl TriggerZone {
+    pub fn new(rect: Rect) -> Self {
+        Self {
+            rect,
+            occupied: false,
+        }
+    }
+
+    /// Whether `pos` is inside the zone, counting the min edges but not the max ones so that a
+    /// zone drawn along tile edges doesn't also cover the tiles next to it
+    pub fn contains(&self, pos: Vec2) -> bool {
+        pos.cmpge(self.rect.min).all() && pos.cmplt(self.rect.max).all()
+    }
+}
+
+/// The actions run when the player enters the `TriggerZone` on the same entity
+#[derive(Component, Debug)]
+pub struct TriggerActions {
+    pub actions: Vec<TriggerAction>,
+    /// Only run the actions the first time the zone is entered
+    pub once: bool,
+    /// Flag requirement (`name` or `!name`) that has to be met for the actions to run
+    pub requires_flag: Option<String>,
+    fired: bool,
+}
+
+impl TriggerActions {
+    /// `actions` run every time the zone is entered
+    pub fn new(actions: Vec<TriggerAction>) -> Self {
+        Self {
+            actions,
+            once: false,
+            requires_flag: None,
+            fired: false,
+        }
+    }
+}
+
+/// Sent when `entity` enters the `TriggerZone` on `zone`
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ZoneEntered {
+    pub zone: Entity,
+    pub entity: Entity,
+}
+
+/// Messages from triggers waiting to be shown, oldest first
+#[derive(Resource, Default)]
+pub struct TriggerMessages(pub VecDeque<String>);
+
+fn despawn_trigger_zones(mut commands: Commands, zones: Query<Entity, With<TriggerZone>>) {
+    for entity in &zones {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn spawn_trigger_zones(
+    mut commands: Commands,
+    game_info: Res<GameInfoAlt>,
+    tile_maps: Res<Assets<helpers::tiled::TiledMap>>,
+    map_frame: Res<MapFrame>,
+    mut toasts: Toasts,
+) {
+    let Some(map) = tile_maps.get(&game_info.tile_map) else {
+        return;
+    };
+
+    let object_layers =
+        helpers::tiled::all_layers(&map.map).filter_map(|layer| match layer.layer_type() {
+            tiled::LayerType::Objects(layer) => Some(layer),
+            _ => None,
+        });
+
+    for layer in object_layers {
+        for object in layer.objects() {
+            if !object.user_type.eq_ignore_ascii_case("trigger") {
+                continue;
+            }
+
+            if !matches!(object.shape, tiled::ObjectShape::Rect { .. }) {
+                warn!("trigger {} is not a rectangle, skipping it", object.name);
+                continue;
+            }
+            let Some(rect) = coords::object_rect(&map_frame, &object) else {
+                warn!("trigger {} is outside the map, skipping it", object.name);
+                continue;
+            };
+
+            let script = match object.properties.get("actions") {
+                Some(tiled::PropertyValue::StringValue(script)) => script.clone(),
+                _ => {
+                    warn!("trigger {} has no actions", object.name);
+                    String::new()
+                }
+            };
+            let actions = match parse_actions(&script) {
+                Ok(actions) => actions,
+                Err(err) => {
+                    error!("trigger {} has bad actions: {err}", object.name);
+                    toasts.error(format!("Trigger {} has bad actions: {err}", object.name));
+                    continue;
+                }
+            };
+            let once = matches!(
+                object.properties.get("once"),
+                Some(tiled::PropertyValue::BoolValue(true))
+            );
+            let requires_flag = match object.properties.get("requires_flag") {
+                Some(tiled::PropertyValue::StringValue(requirement)) => Some(requirement.clone()),
+                _ => None,
+            };
+
+            commands.spawn((
+                TriggerZone::new(rect),
+                TriggerActions {
+                    once,
+                    requires_flag,
+                    ..TriggerActions::new(actions)
+                },
+                MapRegion {
+                    name: object.name.clone(),
+                    kind: RegionKind::Trigger,
+                    rect,
+                    object_id: Some(object.id()),
+                    actions: script,
+                    deleted: false,
+                },
+                Name::new(object.name.clone()),
+            ));
+        }
+    }
+}
+
+fn detect_zone_entries(
+    mut zones: Query<(Entity, &mut TriggerZone)>,
+    player_q: Query<(Entity, &Transform), With<MainPlayer>>,
+    mut entered: EventWriter<ZoneEntered>,
+) {
+    let Ok((player, player_transform)) = player_q.get_single() else {
+        return;
+    };
+    let player_pos = player_transform.translation.truncate();
+
+    for (zone_entity, mut zone) in &mut zones {
+        let inside = zone.contains(player_pos);
+        if inside && !zone.occupied {
+            entered.send(ZoneEntered {
+                zone: zone_entity,
+                entity: player,
+            });
+        }
+        zone.occupied = inside;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_trigger_actions(
+    mut entered: EventReader<ZoneEntered>,
+    mut zones: Query<(&mut TriggerActions, &TriggerZone, &Name)>,
+    mut player_q: Query<&mut Transform, With<MainPlayer>>,
+    mut flags: ResMut<GameFlags>,
+    mut messages: ResMut<TriggerMessages>,
+    collision_map: Res<CollisionMap>,
+    map_frame: Res<MapFrame>,
+    asset_server: Res<AssetServer>,
+    mut sounds: SpatialAudio,
+    mut camera_moves: EventWriter<MoveCamera>,
+) {
+    for event in entered.read() {
+        let Ok((mut trigger, zone, name)) = zones.get_mut(event.zone) else {
+            continue;
+        };
+        if trigger.once && trigger.fired {
+            continue;
+        }
+        if let Some(requirement) = &trigger.requires_flag {
+            if !flags.requirement_met(requirement) {
+                continue;
+            }
+        }
+        trigger.fired = true;
+
+        for action in &trigger.actions {
+            match action {
+                TriggerAction::ShowMessage(text) => messages.0.push_back(text.clone()),
+                TriggerAction::TeleportPlayer { x, y } => {
+                    let map_size = map_frame.map_size;
+                    let target = (*y < map_size.y).then(|| TilePos {
+                        x: *x,
+                        y: map_size.y - 1 - *y,
+                    });
+                    let Some(target) = target.filter(|pos| !collision_map.is_blocked(pos)) else {
+                        warn!("trigger {name} can't teleport to blocked tile ({x}, {y})");
+                        continue;
+                    };
+                    let Some(world_pos) = coords::tile_to_world_center(&map_frame, &target) else {
+                        continue;
+                    };
+                    if let Ok(mut transform) = player_q.get_mut(event.entity) {
+                        transform.translation.x = world_pos.x;
+                        transform.translation.y = world_pos.y;
+                    }
+                }
+                TriggerAction::SetFlag { key, value } => flags.set(key.clone(), value.clone()),
+                TriggerAction::PlaySound(path) => {
+                    if !path.is_empty() {
+                        let sound = asset_server.load(path.clone());
+                        sounds.play_spatial_sfx(zone.rect.center(), sound, 1.);
+                    }
+                }
+                TriggerAction::MoveCamera {
+                    x,
+                    y,
+                    scale,
+                    secs,
+                    skippable,
+                } => {
+                    let target = (*y < map_frame.map_size.y)
+                        .then(|| TilePos::new(*x, map_frame.map_size.y - 1 - *y))
+                        .and_then(|tile| coords::tile_to_world_center(&map_frame, &tile));
+                    let Some(target_pos) = target else {
+                        warn!("trigger {name} can't move the camera outside the map ({x}, {y})");
+                        continue;
+                    };
+                    camera_moves.send(MoveCamera {
+                        keyframe: CameraKeyframe {
+                            target_pos,
+                            target_scale: *scale,
+                            duration: *secs,
+                            easing: Ease::QuadOut,
+                        },
+                        skippable: *skippable,
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn show_trigger_messages(
+    mut messages: ResMut<TriggerMessages>,
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+) {
+    let Some(text) = messages.0.front() else {
+        return;
+    };
+    let Some(mut ctx) = contexts.iter_mut().next() else {
+        return;
+    };
+
+    let mut dismissed = false;
+    egui::Window::new("Message")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0., 0.))
+        .show(ctx.get_mut(), |ui| {
+            ui.label(text);
+            dismissed = ui.button("OK").clicked();
+        });
+    if dismissed {
+        messages.0.pop_front();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_example_script() {
+        assert_eq!(
+            parse_actions("message:Hello;teleport:10,12;give_key"),
+            Ok(vec![
+                TriggerAction::ShowMessage("Hello".to_string()),
+                TriggerAction::TeleportPlayer { x: 10, y: 12 },
+                TriggerAction::SetFlag {
+                    key: "give_key".to_string(),
+                    value: FlagValue::Bool(true),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn parses_all_actions_with_whitespace() {
+        assert_eq!(
+            parse_actions(" flag: door_open ; sound:sounds/bell.ogg;; message: Hi there "),
+            Ok(vec![
+                TriggerAction::SetFlag {
+                    key: "door_open".to_string(),
+                    value: FlagValue::Bool(true),
+                },
+                TriggerAction::PlaySound("sounds/bell.ogg".to_string()),
+                TriggerAction::ShowMessage("Hi there".to_string()),
+            ])
+        );
+        assert_eq!(parse_actions(""), Ok(vec![]));
+    }
+
+    #[test]
+    fn parses_camera_moves() {
+        assert_eq!(
+            parse_actions("camera:3, 4, 0.5, 2; camera:0,0,1,1.5,skippable"),
+            Ok(vec![
+                TriggerAction::MoveCamera {
+                    x: 3,
+                    y: 4,
+                    scale: 0.5,
+                    secs: 2.,
+                    skippable: false,
+                },
+                TriggerAction::MoveCamera {
+                    x: 0,
+                    y: 0,
+                    scale: 1.,
+                    secs: 1.5,
+                    skippable: true,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn parses_flag_values() {
+        assert_eq!(
+            parse_actions("flag:a=false;flag:b = 3;flag:c=open"),
+            Ok(vec![
+                TriggerAction::SetFlag {
+                    key: "a".to_string(),
+                    value: FlagValue::Bool(false),
+                },
+                TriggerAction::SetFlag {
+                    key: "b".to_string(),
+                    value: FlagValue::Int(3),
+                },
+                TriggerAction::SetFlag {
+                    key: "c".to_string(),
+                    value: FlagValue::String("open".to_string()),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn reports_the_bad_token() {
+        assert_eq!(
+            parse_actions("message:Hi;dance:twice"),
+            Err(TriggerParseError::UnknownAction("dance:twice".to_string()))
+        );
+        assert_eq!(
+            parse_actions("teleport:10"),
+            Err(TriggerParseError::BadTeleportTarget(
+                "teleport:10".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_actions("teleport:a,b"),
+            Err(TriggerParseError::BadTeleportTarget(
+                "teleport:a,b".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_actions("camera:3,4,2"),
+            Err(TriggerParseError::BadCameraMove("camera:3,4,2".to_string()))
+        );
+        assert_eq!(
+            parse_actions("message:"),
+            Err(TriggerParseError::MissingArgument("message:".to_string()))
+        );
+        assert_eq!(
+            parse_actions("give key"),
+            Err(TriggerParseError::UnknownAction("give key".to_string()))
+        );
+    }
+
+    #[test]
+    fn one_tile_zone_fires_on_one_tile() {
+        let map = crate::helpers::tiled::load_test_map("assets/maps/TMX/map_test_object_tiles.tmx");
+        let frame = MapFrame::from_tiled_map(&map.map, Transform::IDENTITY);
+        let object = map
+            .map
+            .layers()
+            .filter_map(|layer| layer.as_object_layer())
+            .flat_map(|layer| layer.objects())
+            .find(|object| object.name == "rect_object")
+            .unwrap();
+        let zone = TriggerZone::new(coords::object_rect(&frame, &object).unwrap());
+
+        let mut inside = vec![];
+        for x in 0..frame.map_size.x {
+            for y in 0..frame.map_size.y {
+                let tile_pos = TilePos::new(x, y);
+                let center = coords::tile_to_world_center(&frame, &tile_pos).unwrap();
+                if zone.contains(center) {
+                    inside.push(tile_pos);
+                }
+            }
+        }
+        assert_eq!(inside, vec![TilePos::new(2, 3)]);
+        // the zone's far edges belong to the next tiles over
+        assert!(zone.contains(zone.rect.min));
+        assert!(!zone.contains(zone.rect.max));
+        assert!(!zone.contains(Vec2::new(zone.rect.max.x, zone.rect.min.y)));
+    }
+}
@@ -0,0 +1,206 @@
+//! Small timed interpolations of a transform or sprite value, shared by every effect that
+//! animates an entity over a fixed duration.
+
+use bevy::prelude::*;
+
+use crate::game::GameSet;
+
+/// Plugin that advances every `Tween` and reports the finished ones
+#[derive(Default)]
+pub struct TweenPlugin;
+
+impl Plugin for TweenPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<TweenFinished>()
+            .add_systems(Update, run_tweens.in_set(GameSet::RenderPrep));
+    }
+}
+
+/// How the progress of a tween is shaped over its duration
+#[derive(Reflect, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Ease {
+    #[default]
+    Linear,
+    /// Starts slow and speeds up
+    QuadIn,
+    /// Starts fast and slows down
+    QuadOut,
+    /// Goes a bit past the end and settles back
+    BackOut,
+    /// Reaches the end halfway through and comes back to the start
+    ThereAndBack,
+}
+
+impl Ease {
+    /// Eased progress for the linear progress `t` in `0..=1`
+    pub fn sample(&self, t: f32) -> f32 {
+        let t = t.clamp(0., 1.);
+        match self {
+            Ease::Linear => t,
+            Ease::QuadIn => t * t,
+            Ease::QuadOut => 1. - (1. - t) * (1. - t),
+            Ease::BackOut => {
+                const OVERSHOOT: f32 = 1.70158;
+                let t = t - 1.;
+                1. + (OVERSHOOT + 1.) * t * t * t + OVERSHOOT * t * t
+            }
+            Ease::ThereAndBack => 4. * t * (1. - t),
+        }
+    }
+}
+
+/// The value a tween drives, and where it goes from and to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TweenValue {
+    Scale {
+        from: Vec3,
+        to: Vec3,
+    },
+    /// Alpha of the entity's sprite
+    Alpha {
+        from: f32,
+        to: f32,
+    },
+    /// x and y of the translation, z is left to the draw order systems
+    Position {
+        from: Vec2,
+        to: Vec2,
+    },
+}
+
+/// Drives `value` from its start to its end over the timer's duration
+///
+/// The component is removed and `TweenFinished` sent when the timer is done.
+#[derive(Component, Debug, Clone)]
+pub struct Tween {
+    pub value: TweenValue,
+    pub ease: Ease,
+    pub timer: Timer,
+}
+
+impl Tween {
+    pub fn new(value: TweenValue, ease: Ease, secs: f32) -> Self {
+        Self {
+            value,
+            ease,
+            timer: Timer::from_seconds(secs, TimerMode::Once),
+        }
+    }
+
+    /// Write the value at the current progress, `color` is the sprite color if there is a sprite
+    pub fn apply(&self, transform: &mut Transform, color: Option<&mut Color>) {
+        let t = self.ease.sample(self.timer.percent());
+        match self.value {
+            TweenValue::Scale { from, to } => transform.scale = from.lerp(to, t),
+            TweenValue::Alpha { from, to } => {
+                if let Some(color) = color {
+                    color.set_a(from + (to - from) * t);
+                }
+            }
+            TweenValue::Position { from, to } => {
+                let pos = from.lerp(to, t);
+                transform.translation.x = pos.x;
+                transform.translation.y = pos.y;
+            }
+        }
+    }
+}
+
+/// Sent when the `Tween` on `entity` reached its end
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TweenFinished {
+    pub entity: Entity,
+}
+
+#[allow(clippy::type_complexity)]
+pub fn run_tweens(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut tweens: Query<(
+        Entity,
+        &mut Tween,
+        &mut Transform,
+        Option<&mut TextureAtlasSprite>,
+        Option<&mut Sprite>,
+    )>,
+    mut finished: EventWriter<TweenFinished>,
+) {
+    for (entity, mut tween, mut transform, mut atlas_sprite, mut sprite) in &mut tweens {
+        tween.timer.tick(time.delta());
+        let color = match (atlas_sprite.as_deref_mut(), sprite.as_deref_mut()) {
+            (Some(atlas_sprite), _) => Some(&mut atlas_sprite.color),
+            (None, Some(sprite)) => Some(&mut sprite.color),
+            (None, None) => None,
+        };
+        tween.apply(&mut transform, color);
+        if tween.timer.finished() {
+            commands.entity(entity).remove::<Tween>();
+            finished.send(TweenFinished { entity });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+
+    #[test]
+    fn eases_start_at_zero_and_end_at_one() {
+        for ease in [Ease::Linear, Ease::QuadIn, Ease::QuadOut, Ease::BackOut] {
+            assert!(ease.sample(0.).abs() < 1e-6, "{ease:?}");
+            assert!((ease.sample(1.) - 1.).abs() < 1e-6, "{ease:?}");
+        }
+        // overshoots on the way
+        assert!(Ease::BackOut.sample(0.7) > 1.);
+        let there_and_back = [0., 0.5, 1.].map(|t| Ease::ThereAndBack.sample(t));
+        assert_eq!(there_and_back, [0., 1., 0.]);
+    }
+
+    #[test]
+    fn tween_runs_to_the_end_and_reports_it() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                100,
+            )))
+            .add_event::<TweenFinished>()
+            .add_systems(Update, run_tweens);
+        let entity = app
+            .world
+            .spawn((
+                Transform::from_xyz(0., 0., 2.5),
+                TextureAtlasSprite::default(),
+                Tween::new(
+                    TweenValue::Position {
+                        from: Vec2::ZERO,
+                        to: Vec2::new(10., 0.),
+                    },
+                    Ease::Linear,
+                    0.25,
+                ),
+            ))
+            .id();
+
+        // the first frame has no delta
+        app.update();
+        app.update();
+        let translation = app.world.get::<Transform>(entity).unwrap().translation;
+        assert!((translation.x - 4.).abs() < 1e-4);
+        assert_eq!(translation.z, 2.5);
+
+        app.update();
+        app.update();
+        assert_eq!(
+            app.world.get::<Transform>(entity).unwrap().translation,
+            Vec3::new(10., 0., 2.5)
+        );
+        assert!(app.world.get::<Tween>(entity).is_none());
+        let events = app.world.resource::<Events<TweenFinished>>();
+        let finished: Vec<_> = events.get_reader().read(events).copied().collect();
+        assert_eq!(finished, [TweenFinished { entity }]);
+    }
+}
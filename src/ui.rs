@@ -0,0 +1,270 @@
+//! The cursor-driven tile state, the resource inspector and the debug overlays.
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use bevy_inspector_egui::bevy_egui::{EguiContext, EguiPlugin};
+use bevy_inspector_egui::prelude::*;
+use bevy_window::PrimaryWindow;
+
+use crate::camera::{MainCamera, PanCamSystemSet, WorldClick};
+use crate::coords::{self, MapFrame};
+use crate::cursor::CursorWorldCache;
+use crate::game::GameSet;
+use crate::input_map::{MouseAction, MouseInputMap};
+use crate::inspector::{InspectorSettings, InspectorWindow};
+use crate::state::level_ready;
+use crate::{
+    accessibility, audio, camera_preset, combat, debug_info, export, inventory, lod,
+    map_validation, markers, movement, palette, spawn_effects, statistics, toast, ui_scale,
+    weather,
+};
+
+/// Plugin that tracks the hovered and selected tiles and draws the inspector and overlays
+#[derive(Default)]
+pub struct UiPlugin;
+
+impl Plugin for UiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            bevy_inspector_egui::DefaultInspectorConfigPlugin,
+            EguiPlugin,
+            crate::inspector::InspectorWindowPlugin,
+            crate::toast::ToastPlugin,
+            crate::tooltip::TileTooltipPlugin,
+            crate::path_debug::PathDebugPlugin,
+            crate::game_ui::GameUiPlugin,
+            crate::markers::TileMarkerPlugin,
+            crate::cursor_icon::CursorIconPlugin,
+            crate::outline::OutlinePlugin,
+            crate::status_bar::StatusBarPlugin,
+        ))
+        .init_resource::<Configuration>()
+        .init_resource::<WorldPosition>()
+        .init_resource::<HoveredTile>()
+        .init_resource::<SelectedTile>()
+        .add_systems(
+            Update,
+            update_mouse_position
+                .in_set(GameSet::Cursor)
+                .run_if(level_ready),
+        )
+        .add_systems(
+            Update,
+            // clicks come out of the camera's drag handling
+            select_tile
+                .in_set(GameSet::Camera)
+                .after(PanCamSystemSet)
+                .run_if(level_ready),
+        )
+        .add_systems(
+            Update,
+            inspector_ui.in_set(GameSet::RenderPrep).run_if(level_ready),
+        );
+    }
+}
+
+#[derive(Reflect, Resource, Default)]
+struct WorldPosition(Vec2);
+
+/// The tile currently under the cursor, if any
+#[derive(Resource, Default)]
+pub struct HoveredTile(pub Option<TilePos>);
+
+/// The tile last clicked with the `Select` button, if any
+#[derive(Resource, Default)]
+pub struct SelectedTile(pub Option<TilePos>);
+
+#[derive(Reflect, Resource, Default, InspectorOptions)]
+#[reflect(Resource, InspectorOptions)]
+struct Configuration {
+    name: String,
+    #[inspector(min = 0.0, max = 1.0)]
+    option: f32,
+    mouse_position: WorldPosition,
+    cursor_in_map_pos: Vec2,
+}
+
+fn update_mouse_position(
+    mut config: ResMut<Configuration>,
+    mut hovered_tile: ResMut<HoveredTile>,
+    cursor_cache: Res<CursorWorldCache>,
+    q_camera: Query<Entity, With<MainCamera>>,
+    map_frame: Option<Res<MapFrame>>,
+) {
+    // assuming there is exactly one main camera entity, so Query::single() is OK
+    let camera = q_camera.single();
+
+    // keep showing the last known position while the cursor is outside the window
+    if let Some(cursor) = cursor_cache.last_known(camera) {
+        config.mouse_position.0 = cursor.world_pos;
+    }
+
+    // Once we have a world position we can transform it into a possible tile position.
+    hovered_tile.0 = cursor_cache
+        .get(camera)
+        .zip(map_frame)
+        .and_then(|(cursor, map_frame)| coords::world_to_tile(&map_frame, cursor.world_pos));
+    if let Some(tile_pos) = hovered_tile.0 {
+        config.cursor_in_map_pos = Vec2::from(tile_pos);
+    }
+}
+
+pub fn select_tile(
+    mut clicks: EventReader<WorldClick>,
+    mouse_map: Res<MouseInputMap>,
+    mut selected_tile: ResMut<SelectedTile>,
+    map_frame: Option<Res<MapFrame>>,
+) {
+    let Some(map_frame) = map_frame else {
+        return;
+    };
+    for click in clicks.read() {
+        if mouse_map.is(MouseAction::Select, click.button) {
+            selected_tile.0 = coords::world_to_tile(&map_frame, click.world_pos);
+        }
+    }
+}
+
+/// Write the edited map next to the loaded one and report the result in a toast
+fn export_current_map(world: &mut World) {
+    let Some(path) = export::default_export_path(world) else {
+        world.send_event(toast::Toast::error("Export failed: no map file loaded"));
+        return;
+    };
+    let toast = match export::export_map(world, &path) {
+        Ok((layers, objects)) => toast::Toast::info(format!(
+            "Exported {} ({layers} layers edited, {objects} objects changed)",
+            path.display()
+        )),
+        Err(err) => toast::Toast::error(format!("Export failed: {err}")),
+    };
+    world.send_event(toast);
+}
+
+fn inspector_ui(world: &mut World) {
+    // draw into the detached window when there is one, the primary window otherwise
+    let detached_context = world
+        .query_filtered::<&mut EguiContext, With<InspectorWindow>>()
+        .get_single(world)
+        .ok()
+        .cloned();
+    let contents = |world: &mut World, ui: &mut egui::Ui| {
+        let detached = world.resource::<InspectorSettings>().detached;
+        if ui
+            .button(if detached { "Dock" } else { "Detach" })
+            .clicked()
+        {
+            world.resource_mut::<InspectorSettings>().detached = !detached;
+        }
+        if ui.button("Export map").clicked() {
+            export_current_map(world);
+        }
+        if ui.button("Copy debug info").clicked() {
+            world.send_event(debug_info::CopyDebugInfo);
+        }
+        map_validation::map_problems_ui(world, ui);
+        camera_preset::camera_preset_ui(world, ui);
+        egui::ScrollArea::both().show(ui, |ui| {
+            bevy_inspector_egui::bevy_inspector::ui_for_resource::<Configuration>(world, ui);
+            bevy_inspector_egui::bevy_inspector::ui_for_resource::<weather::Weather>(world, ui);
+            bevy_inspector_egui::bevy_inspector::ui_for_resource::<inventory::InventoryStyle>(
+                world, ui,
+            );
+            bevy_inspector_egui::bevy_inspector::ui_for_resource::<movement::MovementSettings>(
+                world, ui,
+            );
+            bevy_inspector_egui::bevy_inspector::ui_for_resource::<
+                spawn_effects::SpawnEffectSettings,
+            >(world, ui);
+            bevy_inspector_egui::bevy_inspector::ui_for_resource::<combat::CombatSettings>(
+                world, ui,
+            );
+            bevy_inspector_egui::bevy_inspector::ui_for_resource::<audio::AudioChannels>(world, ui);
+            bevy_inspector_egui::bevy_inspector::ui_for_resource::<markers::MarkerStyle>(world, ui);
+            bevy_inspector_egui::bevy_inspector::ui_for_resource::<lod::LodSettings>(world, ui);
+            bevy_inspector_egui::bevy_inspector::ui_for_resource::<statistics::Statistics>(
+                world, ui,
+            );
+            bevy_inspector_egui::bevy_inspector::ui_for_resource::<
+                accessibility::MotionPreferences,
+            >(world, ui);
+            bevy_inspector_egui::bevy_inspector::ui_for_resource::<palette::OverlayPalette>(
+                world, ui,
+            );
+            bevy_inspector_egui::bevy_inspector::ui_for_resource::<ui_scale::UiScaling>(world, ui);
+        });
+    };
+
+    match detached_context {
+        Some(mut egui_context) => {
+            egui::CentralPanel::default().show(egui_context.get_mut(), |ui| contents(world, ui));
+        }
+        None => {
+            let mut egui_context = world
+                .query_filtered::<&mut EguiContext, With<PrimaryWindow>>()
+                .single(world)
+                .clone();
+            egui::Window::new("Resource Inspector")
+                .show(egui_context.get_mut(), |ui| contents(world, ui));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cursor::CameraCursor;
+    use crate::game;
+
+    /// The hovered tile as the systems drawing the frame see it
+    #[derive(Resource, Default)]
+    struct DrawnHover(Option<TilePos>);
+
+    fn draw_hover(hovered: Res<HoveredTile>, mut drawn: ResMut<DrawnHover>) {
+        drawn.0 = hovered.0;
+    }
+
+    #[test]
+    fn hovered_tile_follows_the_cursor_on_the_same_frame() {
+        let mut app = App::new();
+        game::configure_game_sets(&mut app);
+        // added back to front, so only the set order puts them right
+        app.init_resource::<Configuration>()
+            .init_resource::<HoveredTile>()
+            .init_resource::<CursorWorldCache>()
+            .init_resource::<DrawnHover>()
+            .insert_resource(MapFrame {
+                transform: Transform::IDENTITY,
+                grid_size: TilemapGridSize { x: 10., y: 10. },
+                map_type: TilemapType::Square,
+                map_size: TilemapSize { x: 8, y: 8 },
+            })
+            .add_systems(
+                Update,
+                (
+                    draw_hover.in_set(GameSet::RenderPrep),
+                    update_mouse_position.in_set(GameSet::Cursor),
+                ),
+            );
+        let camera = app.world.spawn(MainCamera).id();
+
+        for (world_pos, tile) in [
+            (Vec2::new(10., 10.), TilePos::new(1, 1)),
+            (Vec2::new(44., 16.), TilePos::new(4, 2)),
+            (Vec2::new(74., -4.), TilePos::new(7, 0)),
+        ] {
+            // what the cursor cache computes in `PreUpdate`
+            app.world.resource_mut::<CursorWorldCache>().insert(
+                camera,
+                CameraCursor {
+                    screen_pos: Vec2::ZERO,
+                    world_pos,
+                },
+            );
+            app.update();
+
+            assert_eq!(app.world.resource::<HoveredTile>().0, Some(tile));
+            assert_eq!(app.world.resource::<DrawnHover>().0, Some(tile));
+        }
+    }
+}
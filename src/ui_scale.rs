@@ -0,0 +1,170 @@
+//! How big the interface is drawn, on top of the window's own scale factor.
+//!
+//! The scale starts from a guess made from the window, so a small handheld screen and a 4K
+//! monitor both get readable panels, and can be set by hand in the settings window. It applies to
+//! egui through `EguiSettings::scale_factor`, which also converts the pointer into points so
+//! egui's hit testing keeps working, to Bevy UI through `UiScale`, and to the text drawn in the
+//! world. It is kept small enough for the settings window to fit in the window, so a scale
+//! picked by mistake can always be undone.
+
+use bevy::prelude::*;
+use bevy::ui::UiScale;
+use bevy::window::WindowResized;
+use bevy_inspector_egui::bevy_egui::EguiSettings;
+use bevy_window::PrimaryWindow;
+
+use crate::accessibility::Preferences;
+use crate::game::GameSet;
+
+/// Smallest scale that can be set
+pub const MIN_UI_SCALE: f32 = 0.5;
+/// Largest scale that can be set, on a window big enough
+pub const MAX_UI_SCALE: f32 = 3.;
+/// Size of the settings window in points at scale 1, with some margin, which has to stay on the
+/// screen
+const SETTINGS_WINDOW_SIZE: Vec2 = Vec2::new(360., 440.);
+
+/// Plugin that guesses the `UiScaling` from the window and applies it
+#[derive(Default)]
+pub struct UiScalePlugin;
+
+impl Plugin for UiScalePlugin {
+    fn build(&self, app: &mut App) {
+        let scaling = UiScaling {
+            custom: app.world.resource::<Preferences>().ui_scale,
+            ..default()
+        };
+        app.insert_resource(scaling)
+            .register_type::<UiScaling>()
+            .add_systems(
+                Update,
+                (fit_ui_scale_to_window, apply_ui_scale)
+                    .chain()
+                    .in_set(GameSet::Input),
+            );
+    }
+}
+
+#[derive(Resource, Reflect, Debug, Clone, Copy, PartialEq)]
+#[reflect(Resource)]
+pub struct UiScaling {
+    /// Scale set in the settings, the guessed one when `None`
+    pub custom: Option<f32>,
+    /// Scale guessed from the window
+    pub auto: f32,
+    /// Largest scale keeping the settings window on the screen
+    pub max: f32,
+}
+
+impl Default for UiScaling {
+    fn default() -> Self {
+        Self {
+            custom: None,
+            auto: 1.,
+            max: MAX_UI_SCALE,
+        }
+    }
+}
+
+impl UiScaling {
+    /// The scale the interface is drawn at
+    pub fn scale(&self) -> f32 {
+        self.custom
+            .unwrap_or(self.auto)
+            .clamp(MIN_UI_SCALE, self.max.max(MIN_UI_SCALE))
+    }
+}
+
+/// The scale guessed for a window with `scale_factor` and `physical_height` pixels
+pub fn auto_ui_scale(scale_factor: f64, physical_height: u32) -> f32 {
+    if scale_factor > 1. {
+        // the system scales the window already
+        return 1.;
+    }
+    match physical_height {
+        0..=800 => 1.25,
+        2000.. => 2.,
+        _ => 1.,
+    }
+}
+
+/// The largest scale keeping the settings window inside a window of `logical_size`
+pub fn max_ui_scale(logical_size: Vec2) -> f32 {
+    let fit = logical_size / SETTINGS_WINDOW_SIZE;
+    fit.x.min(fit.y).min(MAX_UI_SCALE)
+}
+
+/// Guess the scale and its bound again when the window changes
+fn fit_ui_scale_to_window(
+    mut resized: EventReader<WindowResized>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut scaling: ResMut<UiScaling>,
+    mut fitted: Local<bool>,
+) {
+    let resized = resized.read().count() > 0;
+    if *fitted && !resized {
+        return;
+    }
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    *fitted = true;
+    let auto = auto_ui_scale(window.scale_factor(), window.physical_height());
+    let max = max_ui_scale(Vec2::new(window.width(), window.height()));
+    if scaling.auto != auto || scaling.max != max {
+        scaling.auto = auto;
+        scaling.max = max;
+    }
+}
+
+fn apply_ui_scale(
+    scaling: Res<UiScaling>,
+    mut egui_settings: ResMut<EguiSettings>,
+    mut ui_scale: ResMut<UiScale>,
+) {
+    if !scaling.is_changed() {
+        return;
+    }
+    let scale = scaling.scale() as f64;
+    egui_settings.scale_factor = scale;
+    ui_scale.0 = scale;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_guess_grows_on_small_and_huge_screens_only() {
+        // Steam Deck
+        assert_eq!(auto_ui_scale(1., 800), 1.25);
+        assert_eq!(auto_ui_scale(1., 1080), 1.);
+        // 4K at 100%, and at 200% where the system scales already
+        assert_eq!(auto_ui_scale(1., 2160), 2.);
+        assert_eq!(auto_ui_scale(2., 2160), 1.);
+    }
+
+    #[test]
+    fn the_scale_keeps_the_settings_window_on_the_screen() {
+        let max = max_ui_scale(Vec2::new(1280., 800.));
+        assert!(max * SETTINGS_WINDOW_SIZE.x <= 1280. && max * SETTINGS_WINDOW_SIZE.y <= 800.);
+        assert_eq!(max_ui_scale(Vec2::new(7680., 4320.)), MAX_UI_SCALE);
+
+        let scaling = UiScaling {
+            custom: Some(10.),
+            auto: 1.,
+            max,
+        };
+        assert_eq!(scaling.scale(), max);
+        let tiny = UiScaling {
+            custom: Some(0.1),
+            ..scaling
+        };
+        assert_eq!(tiny.scale(), MIN_UI_SCALE);
+        let auto = UiScaling {
+            custom: None,
+            ..scaling
+        };
+        assert_eq!(auto.scale(), 1.);
+    }
+}
@@ -0,0 +1,213 @@
+//! Rain and snow drawn over the map.
+//!
+//! There are no particles across the map: a pool of a few hundred sprites falls through the
+//! camera view and wraps around its edges, so the weather follows the camera for a fixed cost.
+
+use bevy::prelude::*;
+
+use crate::camera::CameraViewChanged;
+use crate::game::GameSet;
+use crate::state::level_ready;
+
+/// Above the fog of war
+const WEATHER_Z: f32 = 6.;
+/// Sprites in the pool at full intensity
+pub const MAX_WEATHER_SPRITES: usize = 500;
+
+/// Plugin that keeps the weather sprites falling through the camera view
+#[derive(Default)]
+pub struct WeatherPlugin;
+
+impl Plugin for WeatherPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Weather>()
+            .register_type::<Weather>()
+            .add_systems(
+                Update,
+                (resize_weather_pool, move_weather_sprites)
+                    .chain()
+                    .in_set(GameSet::RenderPrep)
+                    .run_if(level_ready)
+                    .run_if(crate::background::not_throttled),
+            );
+    }
+}
+
+/// Current weather, intensities go from 0 to 1
+#[derive(Resource, Reflect, Debug, Clone, Copy, PartialEq, Default)]
+#[reflect(Resource)]
+pub enum Weather {
+    #[default]
+    Clear,
+    Rain {
+        intensity: f32,
+    },
+    Snow {
+        intensity: f32,
+    },
+}
+
+impl Weather {
+    fn intensity(&self) -> f32 {
+        match self {
+            Weather::Clear => 0.,
+            Weather::Rain { intensity } | Weather::Snow { intensity } => intensity.clamp(0., 1.),
+        }
+    }
+
+    /// Number of sprites falling at once
+    pub fn pool_size(&self) -> usize {
+        (self.intensity() * MAX_WEATHER_SPRITES as f32).round() as usize
+    }
+
+    /// Fall speed in world units per second, at camera scale 1
+    fn fall_speed(&self) -> f32 {
+        match self {
+            Weather::Clear => 0.,
+            Weather::Rain { .. } => 350. + 250. * self.intensity(),
+            Weather::Snow { .. } => 40. + 40. * self.intensity(),
+        }
+    }
+
+    fn sprite(&self) -> Sprite {
+        let (color, size) = match self {
+            Weather::Snow { .. } => (Color::rgba(1., 1., 1., 0.8), Vec2::splat(2.5)),
+            _ => (Color::rgba(0.7, 0.8, 1., 0.5), Vec2::new(1., 9.)),
+        };
+        Sprite {
+            color,
+            custom_size: Some(size),
+            ..default()
+        }
+    }
+}
+
+/// A pooled rain streak or snow flake
+#[derive(Component, Debug)]
+struct WeatherSprite {
+    /// Position in the pool, also seeds the sprite's drift
+    index: usize,
+}
+
+/// Wrap `pos` into `rect`, so sprites leaving one edge come back at the other
+pub fn wrap_into(pos: Vec2, rect: Rect) -> Vec2 {
+    let size = rect.size();
+    if size.x <= 0. || size.y <= 0. {
+        return pos;
+    }
+    let offset = pos - rect.min;
+    rect.min + Vec2::new(offset.x.rem_euclid(size.x), offset.y.rem_euclid(size.y))
+}
+
+/// Spread of the pool's starting positions over the unit square, without clumping
+fn scatter(index: usize) -> Vec2 {
+    let i = index as f32;
+    Vec2::new((i * 0.618_034).fract(), (i * 0.754_878).fract())
+}
+
+fn resize_weather_pool(
+    mut commands: Commands,
+    weather: Res<Weather>,
+    mut sprites: Query<(&WeatherSprite, &mut Sprite, &mut Visibility)>,
+    mut view: Local<Option<Rect>>,
+    mut view_changed: EventReader<CameraViewChanged>,
+) {
+    if let Some(changed) = view_changed.read().last() {
+        *view = Some(changed.view);
+    }
+    if !weather.is_changed() {
+        return;
+    }
+    let wanted = weather.pool_size();
+    let sprite = weather.sprite();
+    let mut pooled = 0;
+    for (weather_sprite, mut current, mut visibility) in &mut sprites {
+        pooled += 1;
+        *visibility = if weather_sprite.index < wanted {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        *current = sprite.clone();
+    }
+
+    let view = view.unwrap_or(Rect::from_center_size(Vec2::ZERO, Vec2::splat(512.)));
+    for index in pooled..wanted {
+        let pos = view.min + scatter(index) * view.size();
+        commands.spawn((
+            SpriteBundle {
+                sprite: sprite.clone(),
+                transform: Transform::from_translation(pos.extend(WEATHER_Z)),
+                ..default()
+            },
+            WeatherSprite { index },
+        ));
+    }
+}
+
+fn move_weather_sprites(
+    time: Res<Time>,
+    weather: Res<Weather>,
+    mut view_changed: EventReader<CameraViewChanged>,
+    mut view: Local<Option<(Rect, f32)>>,
+    mut sprites: Query<(&WeatherSprite, &mut Transform, &Visibility)>,
+) {
+    if let Some(changed) = view_changed.read().last() {
+        *view = Some((changed.view, changed.scale));
+    }
+    let Some((view, scale)) = *view else {
+        return;
+    };
+    if *weather == Weather::Clear {
+        return;
+    }
+    // paused time stops the weather too
+    let delta = time.delta_seconds();
+    let elapsed = time.elapsed_seconds();
+    let fall = weather.fall_speed() * scale * delta;
+    let snowing = matches!(*weather, Weather::Snow { .. });
+
+    for (sprite, mut transform, visibility) in &mut sprites {
+        if *visibility == Visibility::Hidden {
+            continue;
+        }
+        let mut pos = transform.translation.truncate() - Vec2::new(0., fall);
+        if snowing {
+            // flakes sway with the wind, each with its own phase
+            let phase = scatter(sprite.index).x * std::f32::consts::TAU;
+            pos.x += (elapsed * 1.3 + phase).sin() * 20. * scale * delta;
+        }
+        transform.translation = wrap_into(pos, view).extend(WEATHER_Z);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sprites_wrap_around_the_view() {
+        let view = Rect::new(100., 50., 200., 150.);
+        assert_eq!(
+            wrap_into(Vec2::new(150., 100.), view),
+            Vec2::new(150., 100.)
+        );
+        assert_eq!(wrap_into(Vec2::new(150., 40.), view), Vec2::new(150., 140.));
+        assert_eq!(wrap_into(Vec2::new(230., 160.), view), Vec2::new(130., 60.));
+        // a camera that jumped far away still gets all the sprites in view
+        assert_eq!(
+            wrap_into(Vec2::new(-420., 100.), view),
+            Vec2::new(180., 100.)
+        );
+    }
+
+    #[test]
+    fn intensity_sets_the_pool_size() {
+        assert_eq!(Weather::Clear.pool_size(), 0);
+        assert_eq!(Weather::Rain { intensity: 0.5 }.pool_size(), 250);
+        assert_eq!(
+            Weather::Snow { intensity: 3. }.pool_size(),
+            MAX_WEATHER_SPRITES
+        );
+    }
+}
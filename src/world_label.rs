@@ -0,0 +1,286 @@
+//! Labels drawn over world entities, like the names floating above the creatures.
+//!
+//! An entity with a `WorldAnchoredLabel` and a `Name` gets its name drawn `offset` above it while
+//! the camera is zoomed in past `max_scale`, fading in and out as the zoom crosses it. Like the
+//! far view of `lod`, the label only goes away a bit past the threshold, so a zoom resting on it
+//! doesn't flicker. The labels are egui areas placed at `world_to_screen` of their owner every
+//! frame and skipped while it's out of view, so they go with their owner when it despawns. The
+//! dialogue bubbles are placed with the same projection.
+
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::EguiContext;
+use bevy_window::PrimaryWindow;
+
+use crate::accessibility::MotionPreferences;
+use crate::camera::MainCamera;
+use crate::game::GameSet;
+use crate::state::level_ready;
+
+/// Camera scale under which the creatures' names show
+pub const NAMEPLATE_MAX_SCALE: f32 = 1.;
+/// Where the creatures' names float, from their center in world units
+pub const NAMEPLATE_OFFSET: Vec2 = Vec2::new(0., 18.);
+/// Share of `max_scale` the zoom has to go past it for a label to show or hide
+const LABEL_HYSTERESIS: f32 = 0.1;
+/// Seconds a label takes to fade in or out
+const LABEL_FADE_SECS: f32 = 0.25;
+
+/// Plugin that fades the `WorldAnchoredLabel`s with the zoom and draws them over their owners
+#[derive(Default)]
+pub struct WorldLabelPlugin;
+
+impl Plugin for WorldLabelPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<WorldAnchoredLabel>().add_systems(
+            Update,
+            (fade_world_labels, show_world_labels)
+                .chain()
+                .in_set(GameSet::RenderPrep)
+                .run_if(level_ready),
+        );
+    }
+}
+
+/// Shows the entity's `Name` above it while the camera is zoomed in enough to read it
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq)]
+pub struct WorldAnchoredLabel {
+    /// Where the bottom of the label is, from the entity's center in world units
+    pub offset: Vec2,
+    /// Camera scale under which the label shows
+    pub max_scale: f32,
+    /// Whether the zoom last crossed into showing it
+    shown: bool,
+    /// Opacity the label is drawn with, from 0 to 1
+    alpha: f32,
+}
+
+impl WorldAnchoredLabel {
+    pub fn new(offset: Vec2, max_scale: f32) -> Self {
+        Self {
+            offset,
+            max_scale,
+            shown: false,
+            alpha: 0.,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn alpha(&self) -> f32 {
+        self.alpha
+    }
+
+    /// Whether to show the label at camera `scale`, `shown` being whether it shows now
+    pub fn shown_at(&self, shown: bool, scale: f32) -> bool {
+        let margin = self.max_scale * LABEL_HYSTERESIS;
+        if shown {
+            scale < self.max_scale + margin
+        } else {
+            scale < self.max_scale - margin
+        }
+    }
+}
+
+/// The world rectangle a 2D camera shows
+pub fn camera_view(transform: &GlobalTransform, projection: &OrthographicProjection) -> Rect {
+    let center = transform.translation().truncate();
+    Rect::from_corners(center + projection.area.min, center + projection.area.max)
+}
+
+/// Where `world_pos` is drawn in a window of `window_size` showing `view`, in logical pixels
+/// from its top left corner
+pub fn world_to_screen(view: Rect, window_size: Vec2, world_pos: Vec2) -> Vec2 {
+    let normalized = (world_pos - view.min) / view.size();
+    Vec2::new(normalized.x, 1. - normalized.y) * window_size
+}
+
+fn fade_world_labels(
+    time: Res<Time>,
+    motion: Res<MotionPreferences>,
+    cameras: Query<&OrthographicProjection, With<MainCamera>>,
+    mut labels: Query<&mut WorldAnchoredLabel>,
+) {
+    let Ok(projection) = cameras.get_single() else {
+        return;
+    };
+    for mut label in &mut labels {
+        let shown = label.shown_at(label.shown, projection.scale);
+        let target = if shown { 1. } else { 0. };
+        let alpha = if motion.reduce_motion {
+            target
+        } else {
+            let step = time.delta_seconds() / LABEL_FADE_SECS;
+            label.alpha + (target - label.alpha).clamp(-step, step)
+        };
+        // only touch the component when it changes
+        if label.shown != shown || label.alpha != alpha {
+            label.shown = shown;
+            label.alpha = alpha;
+        }
+    }
+}
+
+fn show_world_labels(
+    labels: Query<(
+        Entity,
+        &WorldAnchoredLabel,
+        &Name,
+        &GlobalTransform,
+        Option<&InheritedVisibility>,
+    )>,
+    cameras: Query<(&GlobalTransform, &OrthographicProjection), With<MainCamera>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut contexts: Query<&mut EguiContext, With<PrimaryWindow>>,
+) {
+    let (Ok((camera_transform, projection)), Ok(window), Ok(mut ctx)) = (
+        cameras.get_single(),
+        windows.get_single(),
+        contexts.get_single_mut(),
+    ) else {
+        return;
+    };
+    let view = camera_view(camera_transform, projection);
+    let window_size = Vec2::new(window.width(), window.height());
+    let screen = Rect::from_corners(Vec2::ZERO, window_size);
+    for (entity, label, name, transform, visibility) in &labels {
+        if label.alpha <= 0. || visibility.is_some_and(|visibility| !visibility.get()) {
+            continue;
+        }
+        let owner = transform.translation().truncate();
+        if !view.contains(owner) {
+            continue;
+        }
+        let pos = world_to_screen(view, window_size, owner + label.offset);
+        if !screen.contains(pos) {
+            continue;
+        }
+        let alpha = label.alpha;
+        egui::Area::new(egui::Id::new(("world_label", entity)))
+            .order(egui::Order::Background)
+            .pivot(egui::Align2::CENTER_BOTTOM)
+            .fixed_pos(egui::pos2(pos.x, pos.y))
+            .interactable(false)
+            .show(ctx.get_mut(), |ui| {
+                egui::Frame::none()
+                    .fill(egui::Color32::from_black_alpha((160. * alpha) as u8))
+                    .rounding(2.)
+                    .inner_margin(egui::Margin::symmetric(3., 1.))
+                    .show(ui, |ui| {
+                        ui.label(
+                            egui::RichText::new(name.as_str())
+                                .small()
+                                .color(egui::Color32::WHITE.gamma_multiply(alpha)),
+                        );
+                    });
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+
+    #[test]
+    fn world_positions_project_onto_the_window() {
+        let view = Rect::new(100., 0., 300., 100.);
+        let window = Vec2::new(800., 400.);
+        assert_eq!(
+            world_to_screen(view, window, Vec2::new(100., 100.)),
+            Vec2::ZERO
+        );
+        assert_eq!(
+            world_to_screen(view, window, Vec2::new(200., 50.)),
+            window / 2.
+        );
+        assert_eq!(
+            world_to_screen(view, window, Vec2::new(300., 0.)),
+            Vec2::new(800., 400.)
+        );
+        // off the view is off the window
+        assert_eq!(
+            world_to_screen(view, window, Vec2::new(0., 125.)),
+            Vec2::new(-400., -100.)
+        );
+
+        let projection = OrthographicProjection {
+            area: Rect::new(-100., -50., 100., 50.),
+            ..default()
+        };
+        let transform = GlobalTransform::from_xyz(200., 50., 999.9);
+        assert_eq!(camera_view(&transform, &projection), view);
+    }
+
+    #[test]
+    fn labels_show_and_hide_past_the_threshold() {
+        let label = WorldAnchoredLabel::new(Vec2::ZERO, 1.);
+        assert!(!label.shown_at(false, 0.95));
+        assert!(label.shown_at(false, 0.85));
+        // zoomed out past the threshold, but not far enough to hide
+        assert!(label.shown_at(true, 1.05));
+        assert!(!label.shown_at(true, 1.15));
+    }
+
+    fn fade_app(scale: f32) -> (App, Entity, Entity) {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                100,
+            )))
+            .init_resource::<MotionPreferences>()
+            .add_systems(Update, fade_world_labels);
+        let camera = app
+            .world
+            .spawn((MainCamera, OrthographicProjection { scale, ..default() }))
+            .id();
+        let label = app
+            .world
+            .spawn(WorldAnchoredLabel::new(NAMEPLATE_OFFSET, 1.))
+            .id();
+        // the first frame has no delta
+        app.update();
+        (app, camera, label)
+    }
+
+    fn alpha(app: &App, label: Entity) -> f32 {
+        app.world.get::<WorldAnchoredLabel>(label).unwrap().alpha()
+    }
+
+    #[test]
+    fn labels_fade_with_the_zoom() {
+        let (mut app, camera, label) = fade_app(0.5);
+        app.update();
+        assert!(alpha(&app, label) > 0. && alpha(&app, label) < 1.);
+        for _ in 0..3 {
+            app.update();
+        }
+        assert_eq!(alpha(&app, label), 1.);
+
+        // resting on the threshold keeps it
+        app.world
+            .get_mut::<OrthographicProjection>(camera)
+            .unwrap()
+            .scale = 1.05;
+        app.update();
+        assert_eq!(alpha(&app, label), 1.);
+
+        app.world
+            .get_mut::<OrthographicProjection>(camera)
+            .unwrap()
+            .scale = 2.;
+        for _ in 0..3 {
+            app.update();
+        }
+        assert_eq!(alpha(&app, label), 0.);
+    }
+
+    #[test]
+    fn reduced_motion_snaps_the_labels() {
+        let (mut app, _, label) = fade_app(0.5);
+        app.world.resource_mut::<MotionPreferences>().reduce_motion = true;
+        app.update();
+        assert_eq!(alpha(&app, label), 1.);
+    }
+}